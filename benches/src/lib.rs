@@ -5,10 +5,11 @@ extern crate wasmi;
 #[macro_use]
 extern crate assert_matches;
 extern crate wabt;
+extern crate parity_wasm;
 
 use std::error;
 use std::fs::File;
-use wasmi::{ImportsBuilder, Module, ModuleInstance, NopExternals, RuntimeValue};
+use wasmi::{ImportsBuilder, Module, ModuleInstance, NopExternals, RuntimeValue, StackRecycler};
 
 use test::Bencher;
 
@@ -208,6 +209,192 @@ r#"
 	});
 }
 
+// Straight-line arithmetic on locals is fused into superinstructions, so this
+// mostly measures their dispatch.
+#[bench]
+fn matmul(b: &mut Bencher) {
+	let wasm = wabt::wat2wasm(
+		r#"
+(module
+  (memory 1)
+  ;; Multiplies square matrices of size `n` with elements A[i][j] = i + j and
+  ;; B[i][j] = i - j, returning the sum of the elements of the product.
+  (func (export "matmul") (param $n i32) (result i32)
+	(local $i i32) (local $j i32) (local $k i32) (local $a i32) (local $b i32)
+	(local $acc i32) (local $sum i32)
+	(set_local $b (i32.shl (i32.mul (get_local $n) (get_local $n)) (i32.const 2)))
+	(block (loop
+	  (br_if 1 (i32.ge_s (get_local $i) (get_local $n)))
+	  (set_local $j (i32.const 0))
+	  (block (loop
+		(br_if 1 (i32.ge_s (get_local $j) (get_local $n)))
+		(set_local $a
+		  (i32.shl (i32.add (i32.mul (get_local $i) (get_local $n)) (get_local $j)) (i32.const 2)))
+		(i32.store (get_local $a) (i32.add (get_local $i) (get_local $j)))
+		(i32.store (i32.add (get_local $a) (get_local $b)) (i32.sub (get_local $i) (get_local $j)))
+		(set_local $j (i32.add (get_local $j) (i32.const 1)))
+		(br 0)
+	  ))
+	  (set_local $i (i32.add (get_local $i) (i32.const 1)))
+	  (br 0)
+	))
+	(set_local $i (i32.const 0))
+	(block (loop
+	  (br_if 1 (i32.ge_s (get_local $i) (get_local $n)))
+	  (set_local $j (i32.const 0))
+	  (block (loop
+		(br_if 1 (i32.ge_s (get_local $j) (get_local $n)))
+		(set_local $acc (i32.const 0))
+		(set_local $k (i32.const 0))
+		(block (loop
+		  (br_if 1 (i32.ge_s (get_local $k) (get_local $n)))
+		  (set_local $acc
+			(i32.add
+			  (get_local $acc)
+			  (i32.mul
+				(i32.load
+				  (i32.shl (i32.add (i32.mul (get_local $i) (get_local $n)) (get_local $k)) (i32.const 2)))
+				(i32.load
+				  (i32.add
+					(get_local $b)
+					(i32.shl (i32.add (i32.mul (get_local $k) (get_local $n)) (get_local $j)) (i32.const 2)))))))
+		  (set_local $k (i32.add (get_local $k) (i32.const 1)))
+		  (br 0)
+		))
+		(set_local $sum (i32.add (get_local $sum) (get_local $acc)))
+		(set_local $j (i32.add (get_local $j) (i32.const 1)))
+		(br 0)
+	  ))
+	  (set_local $i (i32.add (get_local $i) (i32.const 1)))
+	  (br 0)
+	))
+	(get_local $sum)
+  )
+)
+		"#
+	).unwrap();
+	let module = Module::from_buffer(&wasm).unwrap();
+
+	let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+		.expect("failed to instantiate wasm module")
+		.assert_no_start();
+
+	b.iter(|| {
+		let value = instance
+			.invoke_export("matmul", &[RuntimeValue::I32(16)], &mut NopExternals);
+		assert_matches!(value, Ok(Some(RuntimeValue::I32(87040))));
+	});
+}
+
+const FIB_RECURSIVE: &'static str = r#"
+(module
+  (func $fib (export "fib") (param i32) (result i32)
+	(if (result i32) (i32.lt_u (get_local 0) (i32.const 2))
+		(then (get_local 0))
+		(else
+			(i32.add
+				(call $fib (i32.sub (get_local 0) (i32.const 1)))
+				(call $fib (i32.sub (get_local 0) (i32.const 2)))
+			)
+		)
+	)
+  )
+)
+"#;
+
+// Frames (including locals) of nested calls are allocated on the value stack,
+// so a call-heavy function shouldn't allocate per call.
+#[bench]
+fn fib_recursive(b: &mut Bencher) {
+	let wasm = wabt::wat2wasm(FIB_RECURSIVE).unwrap();
+	let module = Module::from_buffer(&wasm).unwrap();
+
+	let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+		.expect("failed to instantiate wasm module")
+		.assert_no_start();
+
+	b.iter(|| {
+		let value = instance
+			.invoke_export("fib", &[RuntimeValue::I32(20)], &mut NopExternals);
+		assert_matches!(value, Ok(Some(RuntimeValue::I32(6765))));
+	});
+}
+
+// Same as above, but the stacks are reused between invocations.
+#[bench]
+fn fib_recursive_recycled(b: &mut Bencher) {
+	let wasm = wabt::wat2wasm(FIB_RECURSIVE).unwrap();
+	let module = Module::from_buffer(&wasm).unwrap();
+
+	let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+		.expect("failed to instantiate wasm module")
+		.assert_no_start();
+
+	let mut stack_recycler = StackRecycler::default();
+	b.iter(|| {
+		let value = instance.invoke_export_with_stack(
+			"fib",
+			&[RuntimeValue::I32(20)],
+			&mut NopExternals,
+			&mut stack_recycler,
+		);
+		assert_matches!(value, Ok(Some(RuntimeValue::I32(6765))));
+	});
+}
+
+// A call that does almost no work, so the invocation overhead
+// (including allocation of the stacks) dominates.
+const SHORT_CALL: &str = r#"
+(module
+  (func (export "add") (param i32 i32) (result i32)
+	get_local 0
+	get_local 1
+	i32.add
+  )
+)
+"#;
+
+#[bench]
+fn short_call(b: &mut Bencher) {
+	let wasm = wabt::wat2wasm(SHORT_CALL).unwrap();
+	let module = Module::from_buffer(&wasm).unwrap();
+
+	let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+		.expect("failed to instantiate wasm module")
+		.assert_no_start();
+
+	b.iter(|| {
+		let value = instance.invoke_export(
+			"add",
+			&[RuntimeValue::I32(1), RuntimeValue::I32(2)],
+			&mut NopExternals,
+		);
+		assert_matches!(value, Ok(Some(RuntimeValue::I32(3))));
+	});
+}
+
+// Same as above, but the stacks are reused between invocations.
+#[bench]
+fn short_call_recycled(b: &mut Bencher) {
+	let wasm = wabt::wat2wasm(SHORT_CALL).unwrap();
+	let module = Module::from_buffer(&wasm).unwrap();
+
+	let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+		.expect("failed to instantiate wasm module")
+		.assert_no_start();
+
+	let mut stack_recycler = StackRecycler::default();
+	b.iter(|| {
+		let value = instance.invoke_export_with_stack(
+			"add",
+			&[RuntimeValue::I32(1), RuntimeValue::I32(2)],
+			&mut NopExternals,
+			&mut stack_recycler,
+		);
+		assert_matches!(value, Ok(Some(RuntimeValue::I32(3))));
+	});
+}
+
 // This is used for testing overhead of a function call
 // is not too large.
 #[bench]
@@ -276,3 +463,110 @@ fn recursive_trap(b: &mut Bencher) {
 		assert_matches!(value, Err(_));
 	});
 }
+
+// Run a loop of `n` iterations in an invocation that yields every `interval` instructions,
+// to measure the overhead of cooperative scheduling.
+fn count_until_with_yield_interval(b: &mut Bencher, interval: Option<u64>) {
+	use wasmi::{FuncInstance, ResumableError};
+
+	let wasm = wabt::wat2wasm(
+		r#"
+(module
+  (func (export "count_until") (param i32) (result i32)
+	(local i32)
+	(loop
+	  (br_if 0
+		(i32.lt_u
+		  (tee_local 1 (i32.add (get_local 1) (i32.const 1)))
+		  (get_local 0))))
+	(get_local 1)
+  )
+)
+		"#
+	).unwrap();
+	let module = Module::from_buffer(&wasm).unwrap();
+
+	let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+		.expect("failed to instantiate wasm module")
+		.assert_no_start();
+	let func = instance.export_by_name("count_until").unwrap();
+	let func = func.as_func().unwrap();
+
+	b.iter(|| {
+		let mut invocation =
+			FuncInstance::invoke_resumable(func, vec![RuntimeValue::I32(100_000)]).unwrap();
+		invocation.set_yield_interval(interval);
+		let mut outcome = invocation.start_execution(&mut NopExternals);
+		while let Err(ResumableError::Yielded) = outcome {
+			outcome = invocation.resume_execution(None, &mut NopExternals);
+		}
+		assert_matches!(outcome, Ok(Some(RuntimeValue::I32(100_000))));
+	});
+}
+
+#[bench]
+fn count_until_no_yield(b: &mut Bencher) {
+	count_until_with_yield_interval(b, None);
+}
+
+#[bench]
+fn count_until_yield_every_100(b: &mut Bencher) {
+	count_until_with_yield_interval(b, Some(100));
+}
+
+#[bench]
+fn count_until_yield_every_10_000(b: &mut Bencher) {
+	count_until_with_yield_interval(b, Some(10_000));
+}
+
+#[bench]
+fn count_until_yield_every_1_000_000(b: &mut Bencher) {
+	count_until_with_yield_interval(b, Some(1_000_000));
+}
+
+// Sum the squares of `0..n` through a tiny helper, to measure the overhead of calls
+// that inlining removes.
+fn sum_of_squares(b: &mut Bencher, max_callee_size: Option<u32>) {
+	let wasm = wabt::wat2wasm(
+		r#"
+(module
+  (func $square (param i32) (result i32)
+	(i32.mul (get_local 0) (get_local 0)))
+  (func (export "sum_of_squares") (param i32) (result i32)
+	(local i32)
+	(block
+	  (loop
+		(br_if 1 (i32.eqz (get_local 0)))
+		(set_local 0 (i32.sub (get_local 0) (i32.const 1)))
+		(set_local 1 (i32.add (get_local 1) (call $square (get_local 0))))
+		(br 0)))
+	(get_local 1))
+)
+		"#
+	).unwrap();
+	let parity_module = parity_wasm::deserialize_buffer(&wasm).unwrap();
+	let module = match max_callee_size {
+		Some(size) => Module::from_parity_wasm_module_with_inlining(parity_module, size),
+		None => Module::from_parity_wasm_module(parity_module),
+	}.unwrap();
+
+	let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+		.expect("failed to instantiate wasm module")
+		.assert_no_start();
+
+	b.iter(|| {
+		let value = instance
+			.invoke_export("sum_of_squares", &[RuntimeValue::I32(10_000)], &mut NopExternals);
+		assert_matches!(value, Ok(Some(RuntimeValue::I32(_))));
+	});
+}
+
+#[bench]
+fn sum_of_squares_calls(b: &mut Bencher) {
+	sum_of_squares(b, None);
+}
+
+#[bench]
+fn sum_of_squares_inlined(b: &mut Bencher) {
+	sum_of_squares(b, Some(16));
+}
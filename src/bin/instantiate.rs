@@ -5,7 +5,6 @@ extern crate wasmi;
 
 use std::env::args;
 use std::fs::File;
-use wasmi::memory_units::*;
 use wasmi::{
     Error, FuncInstance, FuncRef, GlobalDescriptor, GlobalInstance, GlobalRef, ImportsBuilder,
     MemoryDescriptor, MemoryInstance, MemoryRef, Module, ModuleImportResolver, ModuleInstance,
@@ -43,11 +42,7 @@ impl ModuleImportResolver for ResolveAll {
         _field_name: &str,
         memory_type: &MemoryDescriptor,
     ) -> Result<MemoryRef, Error> {
-        Ok(MemoryInstance::alloc(
-            Pages(memory_type.initial() as usize),
-            memory_type.maximum().map(|m| Pages(m as usize)),
-        )
-        .unwrap())
+        Ok(MemoryInstance::alloc(memory_type.initial(), memory_type.maximum()).unwrap())
     }
 
     fn resolve_table(
@@ -0,0 +1,149 @@
+//! Running invocations on a thread with a stack of a bounded size.
+
+use crate::{Error, Trap, TrapKind};
+use core::{
+    cell::Cell,
+    hint,
+    sync::atomic::{AtomicBool, Ordering},
+};
+use std::{panic, thread};
+
+/// The part of a stack given to [`run_on_bounded_stack`] that is kept free for the
+/// native frames of an invocation of wasm code, in bytes.
+///
+/// Every invocation traps with [`TrapKind::NativeStackExhausted`] if less than this is
+/// left when it starts. The interpreter needs far less for itself, the rest is for the
+/// host functions the invocation calls, up to the point where they invoke wasm again.
+///
+/// [`run_on_bounded_stack`]: fn.run_on_bounded_stack.html
+/// [`TrapKind::NativeStackExhausted`]: enum.TrapKind.html#variant.NativeStackExhausted
+pub const NATIVE_STACK_RESERVE: usize = 256 * 1024;
+
+/// Whether `run_on_bounded_stack` was ever called, so that invocations on other threads
+/// don't need to look up `STACK_LIMIT` until then.
+static BOUNDED_STACKS_USED: AtomicBool = AtomicBool::new(false);
+
+std::thread_local! {
+    /// The lowest address of the stack that an invocation may start at, or zero if the
+    /// current thread wasn't started by `run_on_bounded_stack`.
+    static STACK_LIMIT: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Returns an address in the current frame, which is close to the stack pointer.
+#[inline(never)]
+fn stack_address() -> usize {
+    let marker = 0u8;
+    hint::black_box(&marker) as *const u8 as usize
+}
+
+/// Run `f` on a new thread with a stack of `stack_size` bytes, and wait for its result.
+///
+/// The interpreter keeps the values and frames of wasm code on stacks of its own, which
+/// are limited by [`StackRecycler::with_limits`], so calls between wasm functions don't
+/// consume the native stack. Host functions that invoke wasm code again do, though, and
+/// so does the host code they run. Deeply nested invocations of this kind, e.g. from
+/// untrusted modules recursing through an import, would eventually overflow the native
+/// stack, which aborts the process. On the stack of this function, every invocation
+/// checks first that at least [`NATIVE_STACK_RESERVE`] bytes are left, and traps with
+/// [`TrapKind::NativeStackExhausted`] otherwise, which unwinds the nested invocations
+/// like any other trap.
+///
+/// Instances can't be moved between threads, so `f` should load and instantiate the
+/// module itself, e.g. from a binary it borrows. A panic of `f` is resumed on the
+/// calling thread.
+///
+/// # Platform requirements
+///
+/// This requires threads with a configurable stack size, and a stack that grows
+/// downwards, which covers all the major platforms. The stack may be rounded up to the
+/// page size or to a minimum of the platform, which only leaves more room.
+///
+/// Host code that uses more than [`NATIVE_STACK_RESERVE`] bytes of stack itself before
+/// invoking wasm code again isn't covered, and can still overflow it.
+///
+/// # Overhead
+///
+/// Every call spawns a thread and maps its stack, which costs in the order of tens of
+/// microseconds, so run a whole workload with it rather than every single invocation.
+/// The check itself is a read of a thread local per invocation, which is only done once
+/// this function was called at least once in the process.
+///
+/// # Errors
+///
+/// Returns `Err` if the thread can't be spawned, e.g. because a stack of `stack_size`
+/// bytes can't be allocated.
+///
+/// # Examples
+///
+/// ```rust
+/// extern crate wabt;
+/// extern crate wasmi;
+///
+/// use wasmi::{ImportsBuilder, Module, ModuleInstance, NopExternals, RuntimeValue};
+///
+/// fn main() {
+///     let wasm = wabt::wat2wasm(
+///         r#"(module (func (export "answer") (result i32) (i32.const 42)))"#,
+///     )
+///     .unwrap();
+///
+///     let result = wasmi::run_on_bounded_stack(1024 * 1024, || {
+///         let module = Module::from_buffer(&wasm).unwrap();
+///         let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+///             .unwrap()
+///             .assert_no_start();
+///         instance
+///             .invoke_export("answer", &[], &mut NopExternals)
+///             .unwrap()
+///     });
+///     assert_eq!(result.unwrap(), Some(RuntimeValue::I32(42)));
+/// }
+/// ```
+///
+/// [`StackRecycler::with_limits`]: struct.StackRecycler.html#method.with_limits
+/// [`NATIVE_STACK_RESERVE`]: constant.NATIVE_STACK_RESERVE.html
+/// [`TrapKind::NativeStackExhausted`]: enum.TrapKind.html#variant.NativeStackExhausted
+pub fn run_on_bounded_stack<T, F>(stack_size: usize, f: F) -> Result<T, Error>
+where
+    T: Send,
+    F: FnOnce() -> T + Send,
+{
+    BOUNDED_STACKS_USED.store(true, Ordering::Relaxed);
+    thread::scope(|scope| {
+        let handle = thread::Builder::new()
+            .name("wasmi-bounded-stack".into())
+            .stack_size(stack_size)
+            .spawn_scoped(scope, move || {
+                // The frames below this one are small, so the stack starts right above.
+                let limit =
+                    stack_address().saturating_sub(stack_size.saturating_sub(NATIVE_STACK_RESERVE));
+                STACK_LIMIT.with(|stack_limit| stack_limit.set(limit));
+                f()
+            })
+            .map_err(|e| {
+                Error::Function(format!(
+                    "Failed to spawn a thread with a stack of {} bytes: {}",
+                    stack_size, e
+                ))
+            })?;
+        match handle.join() {
+            Ok(result) => Ok(result),
+            Err(payload) => panic::resume_unwind(payload),
+        }
+    })
+}
+
+/// Check that the current thread has enough stack left to start an invocation, if it
+/// was started by `run_on_bounded_stack`.
+pub(crate) fn check_native_stack() -> Result<(), Trap> {
+    // Spawning the thread synchronizes with it, so a thread started by
+    // `run_on_bounded_stack` always sees the flag set.
+    if !BOUNDED_STACKS_USED.load(Ordering::Relaxed) {
+        return Ok(());
+    }
+    let limit = STACK_LIMIT.with(Cell::get);
+    if stack_address() < limit {
+        return Err(TrapKind::NativeStackExhausted.into());
+    }
+    Ok(())
+}
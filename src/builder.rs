@@ -0,0 +1,154 @@
+use crate::types::{Signature, ValueType};
+use alloc::vec::Vec;
+use parity_wasm::{
+    builder,
+    elements::{self, Instruction, Instructions, Local},
+};
+
+/// Programmatic builder of small wasm modules.
+///
+/// This builder covers the shapes of modules that are commonly needed in tests:
+/// a set of functions with given bodies, an optional linear memory and exports.
+/// It is not meant to be a full assembler; use [`parity_wasm::builder`] directly if
+/// more control is required.
+///
+/// The built module can be fed into [`Module::from_parity_wasm_module`].
+///
+/// # Examples
+///
+/// ```rust
+/// extern crate parity_wasm;
+/// extern crate wasmi;
+///
+/// use parity_wasm::elements::Instruction;
+/// use wasmi::{ModuleBuilder, Signature, ValueType};
+///
+/// fn main() {
+///     let parity_module = ModuleBuilder::new()
+///         .with_memory(1, None)
+///         .with_func(
+///             &Signature::new(&[ValueType::I32][..], Some(ValueType::I32)),
+///             &[],
+///             vec![Instruction::GetLocal(0)],
+///         )
+///         .export_func("identity", 0)
+///         .export_memory("mem")
+///         .build();
+///
+///     let module = wasmi::Module::from_parity_wasm_module(parity_module)
+///         .expect("ModuleBuilder generated invalid module!");
+///
+///     // Instantiate `module`, etc...
+/// }
+/// ```
+///
+/// [`parity_wasm::builder`]: https://docs.rs/parity-wasm/*/parity_wasm/builder/index.html
+/// [`Module::from_parity_wasm_module`]: struct.Module.html#method.from_parity_wasm_module
+pub struct ModuleBuilder {
+    builder: builder::ModuleBuilder,
+}
+
+impl ModuleBuilder {
+    /// Create an empty `ModuleBuilder`.
+    pub fn new() -> ModuleBuilder {
+        ModuleBuilder {
+            builder: builder::module(),
+        }
+    }
+
+    /// Add a function with the given `signature`, declared `locals` and `body`.
+    ///
+    /// The `body` shouldn't contain the terminating `End` instruction, it is
+    /// appended automatically.
+    ///
+    /// Returns the index of the added function.
+    pub fn push_func(
+        &mut self,
+        signature: &Signature,
+        locals: &[ValueType],
+        body: Vec<Instruction>,
+    ) -> u32 {
+        let mut instructions = body;
+        instructions.push(Instruction::End);
+
+        let func = builder::function()
+            .signature()
+            .with_params(
+                signature
+                    .params()
+                    .iter()
+                    .map(|ty| ty.into_elements())
+                    .collect(),
+            )
+            .with_results(
+                signature
+                    .return_type()
+                    .map(ValueType::into_elements)
+                    .into_iter()
+                    .collect(),
+            )
+            .build()
+            .body()
+            .with_locals(
+                locals
+                    .iter()
+                    .map(|ty| Local::new(1, ty.into_elements()))
+                    .collect(),
+            )
+            .with_instructions(Instructions::new(instructions))
+            .build()
+            .build();
+
+        self.builder.push_function(func).body
+    }
+
+    /// Add a function, see [`push_func`] for details.
+    ///
+    /// [`push_func`]: #method.push_func
+    pub fn with_func(
+        mut self,
+        signature: &Signature,
+        locals: &[ValueType],
+        body: Vec<Instruction>,
+    ) -> Self {
+        self.push_func(signature, locals, body);
+        self
+    }
+
+    /// Add a linear memory with the given `initial` and `maximum` sizes (in pages).
+    pub fn with_memory(mut self, initial: u32, maximum: Option<u32>) -> Self {
+        let memory = builder::MemoryBuilder::new()
+            .with_min(initial)
+            .with_max(maximum)
+            .build();
+        self.builder.push_memory(memory);
+        self
+    }
+
+    /// Export the function with index `func_index` under the given `name`.
+    pub fn export_func(self, name: &str, func_index: u32) -> Self {
+        self.export(name, elements::Internal::Function(func_index))
+    }
+
+    /// Export the linear memory under the given `name`.
+    pub fn export_memory(self, name: &str) -> Self {
+        self.export(name, elements::Internal::Memory(0))
+    }
+
+    fn export(mut self, name: &str, internal: elements::Internal) -> Self {
+        self.builder
+            .push_export(elements::ExportEntry::new(name.into(), internal));
+        self
+    }
+
+    /// Finalize building and return the `parity_wasm`'s module.
+    pub fn build(self) -> elements::Module {
+        self.builder.build()
+    }
+}
+
+impl Default for ModuleBuilder {
+    fn default() -> Self {
+        ModuleBuilder::new()
+    }
+}
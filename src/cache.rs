@@ -0,0 +1,109 @@
+use crate::{fnv, Error, Module};
+use alloc::{collections::VecDeque, rc::Rc, vec::Vec};
+use core::cell::RefCell;
+
+/// Cache of compiled modules, consulted by [`Module::from_buffer_cached`].
+///
+/// Entries are keyed by the content hash of the wasm binary, see
+/// [`Module::content_hash`]. The hash isn't collision resistant, so the binary
+/// itself is passed along as well, and implementations must only return a module
+/// that was compiled from exactly the same `wasm` bytes. Otherwise a crafted binary
+/// could be served the compiled code of a different module.
+///
+/// [`Module::from_buffer_cached`]: struct.Module.html#method.from_buffer_cached
+/// [`Module::content_hash`]: struct.Module.html#method.content_hash
+pub trait CompilationCache {
+    /// Returns the module compiled from `wasm`, which hashes to `hash`, if it's cached.
+    fn get(&self, hash: u64, wasm: &[u8]) -> Option<Rc<Module>>;
+
+    /// Cache `module`, compiled from `wasm`, which hashes to `hash`.
+    fn put(&self, hash: u64, wasm: &[u8], module: Rc<Module>);
+}
+
+/// In-memory [`CompilationCache`] that keeps up to a fixed number of modules,
+/// evicting the least recently used one when full.
+///
+/// [`CompilationCache`]: trait.CompilationCache.html
+pub struct LruCompilationCache {
+    capacity: usize,
+    /// Cached entries, the most recently used one last.
+    entries: RefCell<VecDeque<CacheEntry>>,
+}
+
+struct CacheEntry {
+    hash: u64,
+    wasm: Vec<u8>,
+    module: Rc<Module>,
+}
+
+impl CacheEntry {
+    fn is_for(&self, hash: u64, wasm: &[u8]) -> bool {
+        self.hash == hash && self.wasm[..] == *wasm
+    }
+}
+
+impl LruCompilationCache {
+    /// Create an empty cache that keeps up to `capacity` modules.
+    pub fn new(capacity: usize) -> LruCompilationCache {
+        LruCompilationCache {
+            capacity,
+            entries: RefCell::new(VecDeque::new()),
+        }
+    }
+
+    /// Returns the number of cached modules.
+    pub fn len(&self) -> usize {
+        self.entries.borrow().len()
+    }
+
+    /// Returns `true` if no modules are cached.
+    pub fn is_empty(&self) -> bool {
+        self.entries.borrow().is_empty()
+    }
+}
+
+impl CompilationCache for LruCompilationCache {
+    fn get(&self, hash: u64, wasm: &[u8]) -> Option<Rc<Module>> {
+        let mut entries = self.entries.borrow_mut();
+        let position = entries.iter().position(|entry| entry.is_for(hash, wasm))?;
+        let entry = entries.remove(position)?;
+        let module = entry.module.clone();
+        entries.push_back(entry);
+        Some(module)
+    }
+
+    fn put(&self, hash: u64, wasm: &[u8], module: Rc<Module>) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.borrow_mut();
+        entries.retain(|entry| !entry.is_for(hash, wasm));
+        if entries.len() >= self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(CacheEntry {
+            hash,
+            wasm: wasm.to_vec(),
+            module,
+        });
+    }
+}
+
+/// Compute the content hash of a wasm binary, which is 64-bit FNV-1a.
+pub(crate) fn content_hash(wasm: &[u8]) -> u64 {
+    fnv::fnv1a(wasm)
+}
+
+/// Load `wasm` through `cache`, compiling and caching it on a miss.
+pub(crate) fn from_buffer_cached<C: CompilationCache + ?Sized>(
+    wasm: &[u8],
+    cache: &C,
+) -> Result<Rc<Module>, Error> {
+    let hash = content_hash(wasm);
+    if let Some(module) = cache.get(hash, wasm) {
+        return Ok(module);
+    }
+    let module = Rc::new(Module::from_buffer(wasm)?);
+    cache.put(hash, wasm, module.clone());
+    Ok(module)
+}
@@ -0,0 +1,146 @@
+use alloc::{collections::BTreeMap, vec::Vec};
+use parity_wasm::elements::{External, Instruction, Module, Section, Serialize};
+
+/// Normalize the cosmetic parts of `module` that toolchains emit differently, so that
+/// equivalent modules are equal after canonicalization.
+///
+/// This is opt-in and meant to run before compiling, e.g. with
+/// [`Module::from_parity_wasm_module`], so that equivalent modules produce the same
+/// compiled code and, once serialized, the same [`Module::content_hash`]. It:
+///
+/// - removes duplicate entries of the type section, keeping the first one, and updates
+///   the type indices of imports, functions and `call_indirect`s accordingly,
+/// - orders the sections by their id, with custom sections last, in their original
+///   order.
+///
+/// Types are compared structurally, which is what `call_indirect` checks anyway, so
+/// the behavior of the module doesn't change. A module that refers to a type that
+/// doesn't exist is invalid, and its type section is left as is, so that validation
+/// still reports the original index.
+///
+/// # Examples
+///
+/// ```rust
+/// extern crate parity_wasm;
+/// extern crate wabt;
+/// extern crate wasmi;
+///
+/// use parity_wasm::elements::{deserialize_buffer, serialize, Module};
+///
+/// fn main() {
+///     let parse = |wat: &str| -> Module {
+///         deserialize_buffer(&wabt::wat2wasm(wat).unwrap()).unwrap()
+///     };
+///     let deduplicated = parse("(module (type (func)) (func (type 0)) (func (type 0)))");
+///     let redundant = parse("(module (type (func)) (type (func)) (func (type 0)) (func (type 1)))");
+///
+///     let hash = |module: Module| {
+///         wasmi::Module::content_hash(serialize(wasmi::canonicalize(module)).unwrap())
+///     };
+///     assert_eq!(hash(deduplicated), hash(redundant));
+/// }
+/// ```
+///
+/// [`Module::from_parity_wasm_module`]: struct.Module.html#method.from_parity_wasm_module
+/// [`Module::content_hash`]: struct.Module.html#method.content_hash
+pub fn canonicalize(mut module: Module) -> Module {
+    deduplicate_types(&mut module);
+    module.sections_mut().sort_by_key(section_order);
+    module
+}
+
+/// The position of `section` in a canonical module. Sorting by it is stable, so custom
+/// sections keep their relative order.
+fn section_order(section: &Section) -> u8 {
+    match *section {
+        Section::Type(_) => 1,
+        Section::Import(_) => 2,
+        Section::Function(_) => 3,
+        Section::Table(_) => 4,
+        Section::Memory(_) => 5,
+        Section::Global(_) => 6,
+        Section::Export(_) => 7,
+        Section::Start(_) => 8,
+        Section::Element(_) => 9,
+        Section::DataCount(_) => 10,
+        Section::Code(_) => 11,
+        Section::Data(_) => 12,
+        // Sections of unknown ids make the module invalid, but are kept.
+        Section::Unparsed { .. } => 13,
+        Section::Custom(_) | Section::Name(_) | Section::Reloc(_) => 14,
+    }
+}
+
+fn deduplicate_types(module: &mut Module) {
+    let types = match module.type_section() {
+        Some(ts) => ts.types(),
+        None => return,
+    };
+
+    // The new index of each type, and the types that are kept.
+    let mut new_indices = Vec::with_capacity(types.len());
+    let mut unique = Vec::new();
+    let mut indices_by_encoding = BTreeMap::new();
+    for ty in types {
+        let mut encoding = Vec::new();
+        if ty.clone().serialize(&mut encoding).is_err() {
+            return;
+        }
+        let new_index = *indices_by_encoding.entry(encoding).or_insert_with(|| {
+            unique.push(ty.clone());
+            unique.len() as u32 - 1
+        });
+        new_indices.push(new_index);
+    }
+    if unique.len() == types.len() || !type_indices_in_bounds(module, types.len()) {
+        return;
+    }
+
+    let remap = |type_idx: &mut u32| *type_idx = new_indices[*type_idx as usize];
+    if let Some(is) = module.import_section_mut() {
+        for import in is.entries_mut() {
+            if let External::Function(type_idx) = import.external_mut() {
+                remap(type_idx);
+            }
+        }
+    }
+    if let Some(fs) = module.function_section_mut() {
+        for func in fs.entries_mut() {
+            remap(func.type_ref_mut());
+        }
+    }
+    if let Some(cs) = module.code_section_mut() {
+        for body in cs.bodies_mut() {
+            for instruction in body.code_mut().elements_mut() {
+                if let Instruction::CallIndirect(type_idx, _) = instruction {
+                    remap(type_idx);
+                }
+            }
+        }
+    }
+    *module
+        .type_section_mut()
+        .expect("checked above that the module has a type section")
+        .types_mut() = unique;
+}
+
+/// Returns whether every type index `module` refers to is less than `num_types`.
+fn type_indices_in_bounds(module: &Module, num_types: usize) -> bool {
+    let in_bounds = |type_idx: u32| (type_idx as usize) < num_types;
+    let imports = module.import_section().map_or(&[][..], |is| is.entries());
+    let funcs = module.function_section().map_or(&[][..], |fs| fs.entries());
+    let bodies = module.code_section().map_or(&[][..], |cs| cs.bodies());
+    imports.iter().all(|import| match *import.external() {
+        External::Function(type_idx) => in_bounds(type_idx),
+        _ => true,
+    }) && funcs.iter().all(|func| in_bounds(func.type_ref()))
+        && bodies.iter().all(|body| {
+            body.code()
+                .elements()
+                .iter()
+                .all(|instruction| match *instruction {
+                    Instruction::CallIndirect(type_idx, _) => in_bounds(type_idx),
+                    _ => true,
+                })
+        })
+}
@@ -0,0 +1,191 @@
+use crate::memory::MemoryRef;
+use crate::runner::RuntimeValueInternal;
+use crate::{Error, RuntimeValue, Trap, ValueType};
+use alloc::{collections::BTreeSet, vec::Vec};
+
+/// A set of breakpoints, each at an instruction of a function.
+///
+/// Functions are identified by their index in the function index space of a module,
+/// i.e. imported functions are counted. Instructions are identified by their index in
+/// the compiled function body, which can be mapped to and from offsets in the wasm
+/// binary with [`Module::source_offset`].
+///
+/// See [`FuncInstance::invoke_with_breakpoints`] for how breakpoints are hit.
+///
+/// [`Module::source_offset`]: struct.Module.html#method.source_offset
+/// [`FuncInstance::invoke_with_breakpoints`]: struct.FuncInstance.html#method.invoke_with_breakpoints
+#[derive(Clone, Debug, Default)]
+pub struct Breakpoints {
+    set: BTreeSet<(u32, u32)>,
+}
+
+impl Breakpoints {
+    /// Create an empty set of breakpoints.
+    pub fn new() -> Breakpoints {
+        Breakpoints::default()
+    }
+
+    /// Set a breakpoint at the instruction `pc` of the function `func_index`.
+    pub fn add(&mut self, func_index: u32, pc: u32) {
+        self.set.insert((func_index, pc));
+    }
+
+    /// Remove the breakpoint at the instruction `pc` of the function `func_index`.
+    ///
+    /// Returns `false` if there was no such breakpoint.
+    pub fn remove(&mut self, func_index: u32, pc: u32) -> bool {
+        self.set.remove(&(func_index, pc))
+    }
+
+    /// Returns `true` if there are no breakpoints.
+    pub fn is_empty(&self) -> bool {
+        self.set.is_empty()
+    }
+
+    /// Returns the instructions with a breakpoint in the function `func_index`, in
+    /// ascending order.
+    pub(crate) fn pcs(&self, func_index: u32) -> Vec<u32> {
+        self.set
+            .range((func_index, 0)..=(func_index, u32::MAX))
+            .map(|&(_, pc)| pc)
+            .collect()
+    }
+}
+
+/// Callback invoked whenever execution hits a breakpoint.
+///
+/// It is implemented for closures taking a [`DebugFrame`].
+///
+/// [`DebugFrame`]: struct.DebugFrame.html
+pub trait BreakpointHandler {
+    /// Called before the instruction with the breakpoint is executed.
+    ///
+    /// Returning `Ok` resumes the execution, returning `Err` aborts it with the
+    /// given trap.
+    fn on_breakpoint(&mut self, frame: &mut DebugFrame) -> Result<(), Trap>;
+}
+
+impl<F: FnMut(&mut DebugFrame) -> Result<(), Trap>> BreakpointHandler for F {
+    fn on_breakpoint(&mut self, frame: &mut DebugFrame) -> Result<(), Trap> {
+        self(frame)
+    }
+}
+
+/// Callbacks invoked whenever execution enters or leaves a wasm function.
+///
+/// Functions are identified by their index in the function index space of the module
+/// that defines them, which is not necessarily the module of the called function if
+/// functions of other instances are called through imports or tables. Host functions
+/// aren't reported.
+///
+/// Calls that were inlined by [`Module::from_parity_wasm_module_with_inlining`] don't
+/// enter a function at run time, so they aren't reported either, only the function
+/// they were inlined into is.
+///
+/// See [`FuncInstance::invoke_with_call_hook`].
+///
+/// [`Module::from_parity_wasm_module_with_inlining`]: struct.Module.html#method.from_parity_wasm_module_with_inlining
+/// [`FuncInstance::invoke_with_call_hook`]: struct.FuncInstance.html#method.invoke_with_call_hook
+pub trait CallHook {
+    /// Called before the function `func_index` executes its first instruction.
+    fn on_enter(&mut self, func_index: u32);
+
+    /// Called after the function `func_index` returned, or with `trapped` set, after
+    /// the execution trapped while the function was on the call stack.
+    ///
+    /// Functions exit in the reverse order they entered, also if the execution traps.
+    fn on_exit(&mut self, func_index: u32, trapped: bool);
+}
+
+/// The stack frame of a function that hit a breakpoint.
+///
+/// The frame consists of the locals of the function, which start with its
+/// parameters, followed by the operand stack.
+pub struct DebugFrame<'a> {
+    func_index: u32,
+    pc: u32,
+    local_types: Vec<ValueType>,
+    /// The locals followed by the operands.
+    values: &'a mut [RuntimeValueInternal],
+    memory: Option<&'a MemoryRef>,
+}
+
+impl<'a> DebugFrame<'a> {
+    pub(crate) fn new(
+        func_index: u32,
+        pc: u32,
+        local_types: Vec<ValueType>,
+        values: &'a mut [RuntimeValueInternal],
+        memory: Option<&'a MemoryRef>,
+    ) -> DebugFrame<'a> {
+        debug_assert!(local_types.len() <= values.len());
+        DebugFrame {
+            func_index,
+            pc,
+            local_types,
+            values,
+            memory,
+        }
+    }
+
+    /// Returns the index of the function, as given to [`Breakpoints::add`].
+    ///
+    /// [`Breakpoints::add`]: struct.Breakpoints.html#method.add
+    pub fn func_index(&self) -> u32 {
+        self.func_index
+    }
+
+    /// Returns the index of the instruction about to be executed.
+    pub fn pc(&self) -> u32 {
+        self.pc
+    }
+
+    /// Returns the number of locals, including the parameters.
+    pub fn local_count(&self) -> usize {
+        self.local_types.len()
+    }
+
+    /// Returns the value of the local `idx`, or `None` if there is no such local.
+    pub fn local(&self, idx: usize) -> Option<RuntimeValue> {
+        let value_type = *self.local_types.get(idx)?;
+        Some(self.values[idx].with_type(value_type))
+    }
+
+    /// Set the local `idx` to `value`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if there is no such local or if `value` is of another type.
+    pub fn set_local(&mut self, idx: usize, value: RuntimeValue) -> Result<(), Error> {
+        match self.local_types.get(idx) {
+            Some(&value_type) if value_type == value.value_type() => {
+                self.values[idx] = value.into();
+                Ok(())
+            }
+            Some(_) => Err(Error::Value(format!(
+                "Attempt to change the type of local {}",
+                idx
+            ))),
+            None => Err(Error::Value(format!("Local {} doesn't exist", idx))),
+        }
+    }
+
+    /// Returns the operand stack, bottom first.
+    ///
+    /// Operands aren't typed at runtime, so they are given as raw slots that can be
+    /// decoded with [`RuntimeValue::from_abi_slot`] once their type is known, e.g.
+    /// from the code of the function.
+    ///
+    /// [`RuntimeValue::from_abi_slot`]: enum.RuntimeValue.html#method.from_abi_slot
+    pub fn operands(&self) -> Vec<u64> {
+        self.values[self.local_types.len()..]
+            .iter()
+            .map(|value| value.0)
+            .collect()
+    }
+
+    /// Returns the default memory of the module of the function, if any.
+    pub fn memory(&self) -> Option<&MemoryRef> {
+        self.memory
+    }
+}
@@ -0,0 +1,17 @@
+//! 64-bit FNV-1a, the stable hash of the hashes and fingerprints wasmi exposes.
+
+const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// Hash `bytes`.
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    fnv1a_extend(OFFSET_BASIS, bytes)
+}
+
+/// Continue the hash `hash` of some bytes with `bytes`, so that hashing the concatenation
+/// of byte strings doesn't need to concatenate them.
+pub(crate) fn fnv1a_extend(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(PRIME)
+    })
+}
@@ -1,16 +1,19 @@
+use crate::debug::{BreakpointHandler, Breakpoints, CallHook};
 use crate::host::Externals;
 use crate::isa;
-use crate::module::ModuleInstance;
-use crate::runner::{check_function_args, Interpreter, InterpreterState, StackRecycler};
+use crate::module::{ModuleInstance, ModuleRef};
+use crate::runner::{
+    check_function_args, check_host_result, CallStats, Interpreter, InterpreterState, StackRecycler,
+};
 use crate::types::ValueType;
 use crate::value::RuntimeValue;
-use crate::{Signature, Trap};
+use crate::{Error, Signature, Trap, TrapKind};
 use alloc::{
     borrow::Cow,
     rc::{Rc, Weak},
     vec::Vec,
 };
-use core::fmt;
+use core::{cell::OnceCell, fmt};
 use parity_wasm::elements::Local;
 
 /// Reference to a function (See [`FuncInstance`] for details).
@@ -28,6 +31,62 @@ impl ::core::ops::Deref for FuncRef {
     }
 }
 
+impl FuncRef {
+    /// Whether `self` and `other` refer to the same function.
+    pub(crate) fn ptr_eq(&self, other: &FuncRef) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+
+    /// Bind a function allocated with [`FuncInstance::alloc_late`] to `target`.
+    ///
+    /// From then on, calling this function calls `target`, wherever it was imported.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if this function wasn't allocated with [`FuncInstance::alloc_late`],
+    /// if it is already bound, if `target` has another signature, or if `target` is
+    /// this function or is bound to it.
+    ///
+    /// [`FuncInstance::alloc_late`]: struct.FuncInstance.html#method.alloc_late
+    pub fn bind(&self, target: &FuncRef) -> Result<(), Error> {
+        let (signature, slot) = match self.0 .0 {
+            FuncInstanceInternal::Late {
+                ref signature,
+                ref target,
+            } => (signature, target),
+            _ => {
+                return Err(Error::Function(
+                    "Only functions allocated with alloc_late can be bound".into(),
+                ))
+            }
+        };
+        if target.signature() != signature {
+            return Err(Error::Function(format!(
+                "Function of signature {:?} can't be bound to a function of signature {:?}",
+                signature,
+                target.signature()
+            )));
+        }
+        // Bindings are never changed, so refusing to close a cycle here is enough for
+        // `as_internal` to always get to a function that isn't bound.
+        let mut func = target;
+        loop {
+            if func.ptr_eq(self) {
+                return Err(Error::Function("Function can't be bound to itself".into()));
+            }
+            match func.0 .0 {
+                FuncInstanceInternal::Late { ref target, .. } => match target.get() {
+                    Some(next) => func = next,
+                    None => break,
+                },
+                _ => break,
+            }
+        }
+        slot.set(target.clone())
+            .map_err(|_| Error::Function("Function is already bound".into()))
+    }
+}
+
 /// Runtime representation of a function.
 ///
 /// Functions are the unit of organization of code in WebAssembly. Each function takes a sequence of values
@@ -50,11 +109,21 @@ pub(crate) enum FuncInstanceInternal {
         signature: Rc<Signature>,
         module: Weak<ModuleInstance>,
         body: Rc<FuncBody>,
+        /// Index of the function in the function index space of `module`.
+        index: u32,
     },
     Host {
         signature: Signature,
         host_func_index: usize,
     },
+    /// A function allocated with `alloc_late` that isn't bound yet.
+    ///
+    /// Bound functions are never seen as this variant, `as_internal` returns the
+    /// variant of their target instead.
+    Late {
+        signature: Signature,
+        target: OnceCell<FuncRef>,
+    },
 }
 
 impl fmt::Debug for FuncInstance {
@@ -68,6 +137,9 @@ impl fmt::Debug for FuncInstance {
             FuncInstanceInternal::Host { ref signature, .. } => {
                 write!(f, "Host {{ signature={:?} }}", signature)
             }
+            FuncInstanceInternal::Late { ref signature, .. } => {
+                write!(f, "Late {{ signature={:?} }}", signature)
+            }
         }
     }
 }
@@ -89,6 +161,86 @@ impl FuncInstance {
         FuncRef(Rc::new(FuncInstance(func)))
     }
 
+    /// Allocate a function instance that is bound to its implementation later on.
+    ///
+    /// The function can be imported like any other, and is then bound to its target
+    /// with [`FuncRef::bind`]. This allows two instances to import functions from each
+    /// other, which otherwise can't be linked, because each instance would have to
+    /// exist before the other one is instantiated. They are linked in two phases
+    /// instead:
+    ///
+    /// 1. The first instance is instantiated with late functions for the imports
+    ///    from the second one, and without running its start function.
+    /// 2. The second instance is instantiated with the first one as resolver, and the
+    ///    late functions are bound to its exports. Then the start functions can be run.
+    ///
+    /// Calling the function before it is bound traps with [`TrapKind::UnboundFunction`].
+    ///
+    /// A bound function doesn't keep the instance of its target alive, just like
+    /// an import from another instance, so both instances have to be kept around.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// extern crate wabt;
+    /// extern crate wasmi;
+    ///
+    /// use wasmi::{
+    ///     ExternVal, FuncInstance, ImportsBuilder, Module, ModuleInstance, NopExternals,
+    ///     RuntimeValue, Signature, ValueType,
+    /// };
+    ///
+    /// fn main() {
+    ///     let load = |wat: &str| Module::from_buffer(wabt::wat2wasm(wat).unwrap()).unwrap();
+    ///     let a = load(
+    ///         r#"
+    ///         (module
+    ///             (import "b" "double" (func $double (param i32) (result i32)))
+    ///             (func (export "inc") (param i32) (result i32)
+    ///                 (i32.add (get_local 0) (i32.const 1)))
+    ///             (func (export "run") (param i32) (result i32)
+    ///                 (call $double (get_local 0))))
+    ///         "#,
+    ///     );
+    ///     let b = load(
+    ///         r#"
+    ///         (module
+    ///             (import "a" "inc" (func $inc (param i32) (result i32)))
+    ///             (func (export "double") (param i32) (result i32)
+    ///                 (i32.mul (call $inc (get_local 0)) (i32.const 2))))
+    ///         "#,
+    ///     );
+    ///
+    ///     // Phase one: `a` imports a late function standing in for `b.double`.
+    ///     let double = FuncInstance::alloc_late(Signature::new(
+    ///         &[ValueType::I32][..],
+    ///         Some(ValueType::I32),
+    ///     ));
+    ///     let imports =
+    ///         ImportsBuilder::new().with_extern("b", "double", ExternVal::Func(double.clone()));
+    ///     let a = ModuleInstance::new(&a, &imports).unwrap().assert_no_start();
+    ///
+    ///     // Phase two: `b` imports from `a`, and the late function is bound to `b`'s export.
+    ///     let imports = ImportsBuilder::new().with_resolver("a", &a);
+    ///     let b = ModuleInstance::new(&b, &imports).unwrap().assert_no_start();
+    ///     let target = b.export_by_name("double").unwrap();
+    ///     double.bind(target.as_func().unwrap()).unwrap();
+    ///
+    ///     let result = a.invoke_export("run", &[RuntimeValue::I32(4)], &mut NopExternals);
+    ///     assert_eq!(result.unwrap(), Some(RuntimeValue::I32(10)));
+    /// }
+    /// ```
+    ///
+    /// [`FuncRef::bind`]: struct.FuncRef.html#method.bind
+    /// [`TrapKind::UnboundFunction`]: enum.TrapKind.html#variant.UnboundFunction
+    pub fn alloc_late(signature: Signature) -> FuncRef {
+        let func = FuncInstanceInternal::Late {
+            signature,
+            target: OnceCell::new(),
+        };
+        FuncRef(Rc::new(FuncInstance(func)))
+    }
+
     /// Returns [signature] of this function instance.
     ///
     /// This function instance can only be called with matching signatures.
@@ -98,15 +250,31 @@ impl FuncInstance {
         match *self.as_internal() {
             FuncInstanceInternal::Internal { ref signature, .. } => signature,
             FuncInstanceInternal::Host { ref signature, .. } => signature,
+            FuncInstanceInternal::Late { ref signature, .. } => signature,
         }
     }
 
+    /// Returns `true` if this function is provided by the host environment
+    /// (i.e. was created with [`alloc_host`]) and `false` if it is defined by a wasm module.
+    ///
+    /// [`alloc_host`]: #method.alloc_host
+    pub fn is_host(&self) -> bool {
+        matches!(*self.as_internal(), FuncInstanceInternal::Host { .. })
+    }
+
     pub(crate) fn as_internal(&self) -> &FuncInstanceInternal {
-        &self.0
+        match self.0 {
+            FuncInstanceInternal::Late { ref target, .. } => match target.get() {
+                Some(target) => target.as_internal(),
+                None => &self.0,
+            },
+            ref func => func,
+        }
     }
 
     pub(crate) fn alloc_internal(
         module: Weak<ModuleInstance>,
+        index: u32,
         signature: Rc<Signature>,
         body: FuncBody,
     ) -> FuncRef {
@@ -114,6 +282,7 @@ impl FuncInstance {
             signature,
             module,
             body: Rc::new(body),
+            index,
         };
         FuncRef(Rc::new(FuncInstance(func)))
     }
@@ -121,7 +290,16 @@ impl FuncInstance {
     pub(crate) fn body(&self) -> Option<Rc<FuncBody>> {
         match *self.as_internal() {
             FuncInstanceInternal::Internal { ref body, .. } => Some(Rc::clone(body)),
-            FuncInstanceInternal::Host { .. } => None,
+            FuncInstanceInternal::Host { .. } | FuncInstanceInternal::Late { .. } => None,
+        }
+    }
+
+    /// Returns the index of this function in the function index space of the module
+    /// that defines it, or `None` if it isn't defined by a module.
+    pub(crate) fn index(&self) -> Option<u32> {
+        match *self.as_internal() {
+            FuncInstanceInternal::Internal { index, .. } => Some(index),
+            FuncInstanceInternal::Host { .. } | FuncInstanceInternal::Late { .. } => None,
         }
     }
 
@@ -130,10 +308,12 @@ impl FuncInstance {
     /// # Errors
     ///
     /// Returns `Err` if `args` types is not match function [`signature`] or
-    /// if [`Trap`] at execution time occured.
+    /// if [`Trap`] at execution time occured. A host function returning a value
+    /// of the wrong type traps with [`TrapKind::UnexpectedHostResult`].
     ///
     /// [`signature`]: #method.signature
     /// [`Trap`]: #enum.Trap.html
+    /// [`TrapKind::UnexpectedHostResult`]: enum.TrapKind.html#variant.UnexpectedHostResult
     pub fn invoke<E: Externals>(
         func: &FuncRef,
         args: &[RuntimeValue],
@@ -148,7 +328,11 @@ impl FuncInstance {
             FuncInstanceInternal::Host {
                 ref host_func_index,
                 ..
-            } => externals.invoke_index(*host_func_index, args.into()),
+            } => check_host_result(
+                func.signature().return_type(),
+                externals.invoke_index(*host_func_index, args.into())?,
+            ),
+            FuncInstanceInternal::Late { .. } => Err(TrapKind::UnboundFunction.into()),
         }
     }
 
@@ -176,7 +360,332 @@ impl FuncInstance {
             FuncInstanceInternal::Host {
                 ref host_func_index,
                 ..
-            } => externals.invoke_index(*host_func_index, args.into()),
+            } => check_host_result(
+                func.signature().return_type(),
+                externals.invoke_index(*host_func_index, args.into())?,
+            ),
+            FuncInstanceInternal::Late { .. } => Err(TrapKind::UnboundFunction.into()),
+        }
+    }
+
+    /// Invoke this function with metered execution.
+    ///
+    /// Each executed instruction consumes one unit of `fuel`, including the instructions
+    /// of functions called from this one. These are the compiled instructions, not the
+    /// wasm instructions they were compiled from, see [`CallStats::instructions`]. If
+    /// `fuel` runs out, execution traps with [`TrapKind::OutOfFuel`]. Host functions don't
+    /// consume any fuel.
+    ///
+    /// On return, successful or not, `fuel` holds the remaining amount.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`invoke`].
+    ///
+    /// [`CallStats::instructions`]: struct.CallStats.html#structfield.instructions
+    /// [`TrapKind::OutOfFuel`]: enum.TrapKind.html#variant.OutOfFuel
+    /// [`invoke`]: #method.invoke
+    pub fn invoke_with_fuel<E: Externals>(
+        func: &FuncRef,
+        args: &[RuntimeValue],
+        externals: &mut E,
+        fuel: &mut u64,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        check_function_args(func.signature(), args)?;
+        match *func.as_internal() {
+            FuncInstanceInternal::Internal { .. } => {
+                let mut interpreter = Interpreter::new(func, args, None)?;
+                interpreter.set_fuel(Some(*fuel));
+                let return_value = interpreter.start_execution(externals);
+                *fuel = interpreter
+                    .fuel()
+                    .expect("fuel was set before starting execution; qed");
+                return_value
+            }
+            FuncInstanceInternal::Host {
+                ref host_func_index,
+                ..
+            } => check_host_result(
+                func.signature().return_type(),
+                externals.invoke_index(*host_func_index, args.into())?,
+            ),
+            FuncInstanceInternal::Late { .. } => Err(TrapKind::UnboundFunction.into()),
+        }
+    }
+
+    /// Invoke this function, allowing the execution to call host functions at most
+    /// `limit` times.
+    ///
+    /// All calls of host functions count towards the limit, from this function and from
+    /// the functions called by it. Execution traps with
+    /// [`TrapKind::HostCallLimitExceeded`] instead of making one more call. Unlike fuel,
+    /// this guards against a module that spends the time of the host by calling an
+    /// expensive import in a loop, while running cheap code in between. The limit
+    /// applies to this invocation only, each invocation starts counting anew. Invoking
+    /// a host function directly counts as a single host call.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`invoke`].
+    ///
+    /// [`TrapKind::HostCallLimitExceeded`]: enum.TrapKind.html#variant.HostCallLimitExceeded
+    /// [`invoke`]: #method.invoke
+    pub fn invoke_with_host_call_limit<E: Externals>(
+        func: &FuncRef,
+        args: &[RuntimeValue],
+        externals: &mut E,
+        limit: u64,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        check_function_args(func.signature(), args)?;
+        match *func.as_internal() {
+            FuncInstanceInternal::Internal { .. } => {
+                let mut interpreter = Interpreter::new(func, args, None)?;
+                interpreter.set_host_call_limit(Some(limit));
+                interpreter.start_execution(externals)
+            }
+            FuncInstanceInternal::Host { .. } if limit == 0 => {
+                Err(TrapKind::HostCallLimitExceeded.into())
+            }
+            FuncInstanceInternal::Host {
+                ref host_func_index,
+                ..
+            } => check_host_result(
+                func.signature().return_type(),
+                externals.invoke_index(*host_func_index, args.into())?,
+            ),
+            FuncInstanceInternal::Late { .. } => Err(TrapKind::UnboundFunction.into()),
+        }
+    }
+
+    /// Invoke this function, collecting [`CallStats`] of the execution.
+    ///
+    /// The statistics cover the execution up to its end, successful or not. The
+    /// instructions are counted as with [`invoke_with_fuel`], so collecting them
+    /// makes the call about as slow as a metered one. Invoking a host function
+    /// directly counts as a single host call.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`invoke`].
+    ///
+    /// [`CallStats`]: struct.CallStats.html
+    /// [`invoke_with_fuel`]: #method.invoke_with_fuel
+    /// [`invoke`]: #method.invoke
+    pub fn invoke_with_stats<E: Externals>(
+        func: &FuncRef,
+        args: &[RuntimeValue],
+        externals: &mut E,
+    ) -> (Result<Option<RuntimeValue>, Trap>, CallStats) {
+        if let Err(trap) = check_function_args(func.signature(), args) {
+            return (Err(trap), CallStats::default());
+        }
+        match *func.as_internal() {
+            FuncInstanceInternal::Internal { .. } => {
+                let mut interpreter = match Interpreter::new(func, args, None) {
+                    Ok(interpreter) => interpreter,
+                    Err(trap) => return (Err(trap), CallStats::default()),
+                };
+                interpreter.collect_stats();
+                let return_value = interpreter.start_execution(externals);
+                let stats = interpreter
+                    .stats()
+                    .expect("stats were collected before starting execution; qed");
+                (return_value, stats)
+            }
+            FuncInstanceInternal::Host {
+                ref host_func_index,
+                ..
+            } => {
+                let stats = CallStats {
+                    host_calls: 1,
+                    ..CallStats::default()
+                };
+                let return_val = externals
+                    .invoke_index(*host_func_index, args.into())
+                    .and_then(|val| check_host_result(func.signature().return_type(), val));
+                (return_val, stats)
+            }
+            FuncInstanceInternal::Late { .. } => {
+                (Err(TrapKind::UnboundFunction.into()), CallStats::default())
+            }
+        }
+    }
+
+    /// Invoke this function, pausing the execution at `breakpoints`.
+    ///
+    /// Whenever execution is about to run an instruction with a breakpoint, `handler`
+    /// is called with the [frame][`DebugFrame`] of the function, which gives access to
+    /// its locals and operands. Execution resumes once the handler returns `Ok`.
+    ///
+    /// The breakpoints refer to the functions of the module instance that defines this
+    /// function. Functions of other instances, which can be called through imports or
+    /// tables, never hit a breakpoint.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`invoke`]. If `handler` returns `Err`, the execution is aborted with
+    /// that trap.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// extern crate wabt;
+    /// extern crate wasmi;
+    ///
+    /// use wasmi::{
+    ///     Breakpoints, DebugFrame, FuncInstance, ImportsBuilder, Module, ModuleInstance,
+    ///     NopExternals, RuntimeValue, Trap,
+    /// };
+    ///
+    /// fn main() {
+    ///     let wasm = wabt::wat2wasm(
+    ///         r#"
+    ///         (module
+    ///           (func (export "double") (param i32) (result i32)
+    ///             (i32.add (get_local 0) (get_local 0))))
+    ///         "#,
+    ///     )
+    ///     .unwrap();
+    ///     let module = Module::from_buffer(&wasm).unwrap();
+    ///     let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+    ///         .unwrap()
+    ///         .assert_no_start();
+    ///     let double = instance
+    ///         .export_by_name("double")
+    ///         .and_then(|export| export.as_func().cloned())
+    ///         .unwrap();
+    ///
+    ///     // Break at the first instruction of function 0 and change its argument.
+    ///     let mut breakpoints = Breakpoints::new();
+    ///     breakpoints.add(0, 0);
+    ///     let mut handler = |frame: &mut DebugFrame| -> Result<(), Trap> {
+    ///         assert_eq!(frame.local(0), Some(RuntimeValue::I32(2)));
+    ///         frame.set_local(0, RuntimeValue::I32(5)).unwrap();
+    ///         Ok(())
+    ///     };
+    ///     let result = FuncInstance::invoke_with_breakpoints(
+    ///         &double,
+    ///         &[RuntimeValue::I32(2)],
+    ///         &mut NopExternals,
+    ///         &breakpoints,
+    ///         &mut handler,
+    ///     );
+    ///     assert_eq!(result.unwrap(), Some(RuntimeValue::I32(10)));
+    /// }
+    /// ```
+    ///
+    /// [`DebugFrame`]: struct.DebugFrame.html
+    /// [`invoke`]: #method.invoke
+    pub fn invoke_with_breakpoints<E: Externals, H: BreakpointHandler>(
+        func: &FuncRef,
+        args: &[RuntimeValue],
+        externals: &mut E,
+        breakpoints: &Breakpoints,
+        handler: &mut H,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        check_function_args(func.signature(), args)?;
+        match *func.as_internal() {
+            FuncInstanceInternal::Internal { ref module, .. } => {
+                let module = ModuleRef(module.upgrade().expect("module deallocated"));
+                let mut interpreter = Interpreter::new(func, args, None)?;
+                interpreter.set_breakpoints(module, breakpoints.clone());
+                interpreter.start_execution_with_breakpoints(externals, handler)
+            }
+            FuncInstanceInternal::Host {
+                ref host_func_index,
+                ..
+            } => check_host_result(
+                func.signature().return_type(),
+                externals.invoke_index(*host_func_index, args.into())?,
+            ),
+            FuncInstanceInternal::Late { .. } => Err(TrapKind::UnboundFunction.into()),
+        }
+    }
+
+    /// Invoke this function, calling `hook` whenever a wasm function is entered or exited.
+    ///
+    /// This reports the calls of this function and of all wasm functions it calls,
+    /// which is enough to build a call tree or to measure the time spent in each
+    /// function, at a much lower cost than observing every instruction. If the
+    /// execution traps, all functions on the call stack are exited with `trapped` set,
+    /// innermost first. Invoking a host function directly doesn't report anything.
+    ///
+    /// Executions without a hook don't pay anything for this.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`invoke`].
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// extern crate wabt;
+    /// extern crate wasmi;
+    ///
+    /// use wasmi::{CallHook, FuncInstance, ImportsBuilder, Module, ModuleInstance, NopExternals};
+    ///
+    /// struct Depth {
+    ///     current: u32,
+    ///     max: u32,
+    /// }
+    ///
+    /// impl CallHook for Depth {
+    ///     fn on_enter(&mut self, _func_index: u32) {
+    ///         self.current += 1;
+    ///         self.max = self.max.max(self.current);
+    ///     }
+    ///
+    ///     fn on_exit(&mut self, _func_index: u32, _trapped: bool) {
+    ///         self.current -= 1;
+    ///     }
+    /// }
+    ///
+    /// fn main() {
+    ///     let wasm = wabt::wat2wasm(
+    ///         r#"
+    ///         (module
+    ///           (func $leaf)
+    ///           (func $middle (call $leaf))
+    ///           (func (export "main") (call $middle) (call $leaf)))
+    ///         "#,
+    ///     )
+    ///     .unwrap();
+    ///     let module = Module::from_buffer(&wasm).unwrap();
+    ///     let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+    ///         .unwrap()
+    ///         .assert_no_start();
+    ///     let main = instance
+    ///         .export_by_name("main")
+    ///         .and_then(|export| export.as_func().cloned())
+    ///         .unwrap();
+    ///
+    ///     let mut depth = Depth { current: 0, max: 0 };
+    ///     FuncInstance::invoke_with_call_hook(&main, &[], &mut NopExternals, &mut depth).unwrap();
+    ///     assert_eq!((depth.current, depth.max), (0, 3));
+    /// }
+    /// ```
+    ///
+    /// [`invoke`]: #method.invoke
+    pub fn invoke_with_call_hook<E: Externals, C: CallHook>(
+        func: &FuncRef,
+        args: &[RuntimeValue],
+        externals: &mut E,
+        hook: &mut C,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        check_function_args(func.signature(), args)?;
+        match *func.as_internal() {
+            FuncInstanceInternal::Internal { .. } => {
+                let mut interpreter = Interpreter::new(func, args, None)?;
+                interpreter.start_execution_with_call_hook(externals, hook)
+            }
+            FuncInstanceInternal::Host {
+                ref host_func_index,
+                ..
+            } => check_host_result(
+                func.signature().return_type(),
+                externals.invoke_index(*host_func_index, args.into())?,
+            ),
+            FuncInstanceInternal::Late { .. } => Err(TrapKind::UnboundFunction.into()),
         }
     }
 
@@ -214,9 +723,11 @@ impl FuncInstance {
                 kind: FuncInvocationKind::Host {
                     args,
                     host_func_index: *host_func_index,
+                    return_type: func.signature().return_type(),
                     finished: false,
                 },
             }),
+            FuncInstanceInternal::Late { .. } => Err(TrapKind::UnboundFunction.into()),
         }
     }
 }
@@ -228,7 +739,8 @@ pub enum ResumableError {
     Trap(Trap),
     /// The invocation is not resumable.
     ///
-    /// Invocations are only resumable if a host function is called, and the host function returns a trap of `Host` kind. For other cases, this error will be returned. This includes:
+    /// Invocations are only resumable if a host function is called, and the host function returns a trap of `Host` kind, or if
+    /// the invocation [yielded][`Yielded`]. For other cases, this error will be returned. This includes:
     /// - The invocation is directly a host function.
     /// - The invocation has not been started.
     /// - The invocation returns normally or returns any trap other than `Host` kind.
@@ -236,6 +748,7 @@ pub enum ResumableError {
     /// This error is returned by [`resume_execution`].
     ///
     /// [`resume_execution`]: struct.FuncInvocation.html#method.resume_execution
+    /// [`Yielded`]: #variant.Yielded
     NotResumable,
     /// The invocation has already been started.
     ///
@@ -243,6 +756,15 @@ pub enum ResumableError {
     ///
     /// [`start_execution`]: struct.FuncInvocation.html#method.start_execution
     AlreadyStarted,
+    /// The invocation paused after executing the number of instructions given to
+    /// [`set_yield_interval`].
+    ///
+    /// Execution continues where it paused with [`resume_execution`], passing `None`
+    /// as the return value.
+    ///
+    /// [`set_yield_interval`]: struct.FuncInvocation.html#method.set_yield_interval
+    /// [`resume_execution`]: struct.FuncInvocation.html#method.resume_execution
+    Yielded,
 }
 
 impl From<Trap> for ResumableError {
@@ -261,6 +783,7 @@ enum FuncInvocationKind<'args> {
     Host {
         args: Cow<'args, [RuntimeValue]>,
         host_func_index: usize,
+        return_type: Option<ValueType>,
         finished: bool,
     },
 }
@@ -285,6 +808,92 @@ impl<'args> FuncInvocation<'args> {
         }
     }
 
+    /// Yield control back to the caller every `instructions` executed wasm instructions,
+    /// or never if `None`, which is the default.
+    ///
+    /// This allows to run many invocations cooperatively: [`start_execution`] and
+    /// [`resume_execution`] return [`ResumableError::Yielded`] whenever the invocation
+    /// paused, and calling [`resume_execution`] with `None` continues it for another
+    /// `instructions` instructions. Execution pauses between two instructions, so
+    /// pausing has no effect on the wasm code. Time spent in host functions isn't
+    /// accounted for.
+    ///
+    /// Invocations of host functions never yield.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// extern crate wabt;
+    /// extern crate wasmi;
+    ///
+    /// use wasmi::{
+    ///     FuncInstance, ImportsBuilder, Module, ModuleInstance, NopExternals, ResumableError,
+    ///     RuntimeValue,
+    /// };
+    ///
+    /// fn main() {
+    ///     let wasm = wabt::wat2wasm(
+    ///         r#"
+    ///         (module
+    ///           (func (export "count") (param i32) (result i32)
+    ///             (loop
+    ///               (br_if 0 (tee_local 0 (i32.sub (get_local 0) (i32.const 1)))))
+    ///             (get_local 0)))
+    ///         "#,
+    ///     )
+    ///     .unwrap();
+    ///     let module = Module::from_buffer(&wasm).unwrap();
+    ///     let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+    ///         .unwrap()
+    ///         .assert_no_start();
+    ///     let count = instance.export_by_name("count").unwrap();
+    ///     let count = count.as_func().unwrap();
+    ///
+    ///     // Run two invocations round-robin, 100 instructions at a time.
+    ///     let mut tasks = vec![
+    ///         FuncInstance::invoke_resumable(count, vec![RuntimeValue::I32(1000)]).unwrap(),
+    ///         FuncInstance::invoke_resumable(count, vec![RuntimeValue::I32(10)]).unwrap(),
+    ///     ];
+    ///     let mut results = vec![None; tasks.len()];
+    ///     for task in &mut tasks {
+    ///         task.set_yield_interval(Some(100));
+    ///     }
+    ///     let mut started = false;
+    ///     while results.iter().any(Option::is_none) {
+    ///         for (task, result) in tasks.iter_mut().zip(&mut results) {
+    ///             if result.is_some() {
+    ///                 continue;
+    ///             }
+    ///             let outcome = if started {
+    ///                 task.resume_execution(None, &mut NopExternals)
+    ///             } else {
+    ///                 task.start_execution(&mut NopExternals)
+    ///             };
+    ///             match outcome {
+    ///                 Ok(value) => *result = Some(value),
+    ///                 Err(ResumableError::Yielded) => {}
+    ///                 Err(err) => panic!("unexpected error {:?}", err),
+    ///             }
+    ///         }
+    ///         started = true;
+    ///     }
+    ///     assert_eq!(results, [Some(Some(RuntimeValue::I32(0))); 2]);
+    /// }
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `instructions` is `Some(0)`.
+    ///
+    /// [`start_execution`]: #method.start_execution
+    /// [`resume_execution`]: #method.resume_execution
+    /// [`ResumableError::Yielded`]: enum.ResumableError.html#variant.Yielded
+    pub fn set_yield_interval(&mut self, instructions: Option<u64>) {
+        if let FuncInvocationKind::Internal(ref mut interpreter) = self.kind {
+            interpreter.set_yield_interval(instructions);
+        }
+    }
+
     /// Start the invocation execution.
     pub fn start_execution<'externals, E: Externals + 'externals>(
         &mut self,
@@ -295,18 +904,21 @@ impl<'args> FuncInvocation<'args> {
                 if interpreter.state() != &InterpreterState::Initialized {
                     return Err(ResumableError::AlreadyStarted);
                 }
-                Ok(interpreter.start_execution(externals)?)
+                let return_val = interpreter.start_execution(externals)?;
+                yielded_or(interpreter, return_val)
             }
             FuncInvocationKind::Host {
                 ref args,
                 ref mut finished,
                 ref host_func_index,
+                return_type,
             } => {
                 if *finished {
                     return Err(ResumableError::AlreadyStarted);
                 }
                 *finished = true;
-                Ok(externals.invoke_index(*host_func_index, args.as_ref().into())?)
+                let return_val = externals.invoke_index(*host_func_index, args.as_ref().into())?;
+                Ok(check_host_result(return_type, return_val)?)
             }
         }
     }
@@ -335,7 +947,8 @@ impl<'args> FuncInvocation<'args> {
         match &mut self.kind {
             FuncInvocationKind::Internal(interpreter) => {
                 if interpreter.state().is_resumable() {
-                    Ok(interpreter.resume_execution(return_val, externals)?)
+                    let return_val = interpreter.resume_execution(return_val, externals)?;
+                    yielded_or(interpreter, return_val)
                 } else {
                     Err(ResumableError::AlreadyStarted)
                 }
@@ -345,6 +958,18 @@ impl<'args> FuncInvocation<'args> {
     }
 }
 
+/// Returns `return_val` unless the execution of `interpreter` yielded.
+fn yielded_or(
+    interpreter: &Interpreter,
+    return_val: Option<RuntimeValue>,
+) -> Result<Option<RuntimeValue>, ResumableError> {
+    if interpreter.state() == &InterpreterState::Yielded {
+        Err(ResumableError::Yielded)
+    } else {
+        Ok(return_val)
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct FuncBody {
     pub locals: Vec<Local>,
@@ -1,8 +1,10 @@
+use crate::nan_preserving_float::{F32, F64};
 use crate::types::ValueType;
 use crate::value::RuntimeValue;
 use crate::Error;
-use alloc::rc::Rc;
+use alloc::{rc::Rc, vec::Vec};
 use core::cell::Cell;
+use core::convert::TryInto;
 use parity_wasm::elements::ValueType as EValueType;
 
 /// Reference to a global variable (See [`GlobalInstance`] for details).
@@ -29,6 +31,14 @@ impl ::core::ops::Deref for GlobalRef {
 /// Attempt to change value of immutable global or to change type of
 /// the value (e.g. assign [`I32`] value to a global that was created with [`I64`] type) will lead to an error.
 ///
+/// Globals are never accessed concurrently, so [`get`] and [`set`] don't need to be atomic:
+/// a [`GlobalRef`], like every other handle to the instances of wasmi, isn't `Send`, and
+/// wasmi executes code on a single thread. Shared globals aren't part of the threads
+/// proposal either, so modules can't declare them.
+///
+/// [`get`]: #method.get
+/// [`set`]: #method.set
+/// [`GlobalRef`]: struct.GlobalRef.html
 /// [`I32`]: enum.RuntimeValue.html#variant.I32
 /// [`I64`]: enum.RuntimeValue.html#variant.I64
 #[derive(Debug)]
@@ -88,4 +98,109 @@ impl GlobalInstance {
     pub(crate) fn elements_value_type(&self) -> EValueType {
         self.value_type().into_elements()
     }
+
+    /// Encode the type, mutability and value of this global variable.
+    ///
+    /// The encoding is the global type as in the wasm binary format, i.e. the value
+    /// type byte followed by `1` if the global is mutable and `0` otherwise, followed
+    /// by the value in little-endian byte order. Floats are encoded by their bits,
+    /// so NaN payloads are preserved. The encoding doesn't depend on the endianness
+    /// of the host and can be turned back into a global with [`decode`].
+    ///
+    /// [`decode`]: #method.decode
+    pub fn encode(&self) -> Vec<u8> {
+        let (type_byte, value) = match self.get() {
+            RuntimeValue::I32(v) => (0x7f, v.to_le_bytes().to_vec()),
+            RuntimeValue::I64(v) => (0x7e, v.to_le_bytes().to_vec()),
+            RuntimeValue::F32(v) => (0x7d, v.to_bits().to_le_bytes().to_vec()),
+            RuntimeValue::F64(v) => (0x7c, v.to_bits().to_le_bytes().to_vec()),
+        };
+        let mut bytes = Vec::with_capacity(2 + value.len());
+        bytes.push(type_byte);
+        bytes.push(self.mutable as u8);
+        bytes.extend_from_slice(&value);
+        bytes
+    }
+
+    /// Allocate a global variable from its encoding produced by [`encode`].
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if `bytes` isn't a valid encoding of a global variable.
+    ///
+    /// [`encode`]: #method.encode
+    pub fn decode(bytes: &[u8]) -> Result<GlobalRef, Error> {
+        let invalid = || Error::Global("Invalid encoding of a global variable".into());
+
+        let (header, value) = if bytes.len() >= 2 {
+            bytes.split_at(2)
+        } else {
+            return Err(invalid());
+        };
+        let mutable = match header[1] {
+            0 => false,
+            1 => true,
+            _ => return Err(invalid()),
+        };
+        let value = match header[0] {
+            0x7f => RuntimeValue::I32(i32::from_le_bytes(value.try_into().map_err(|_| invalid())?)),
+            0x7e => RuntimeValue::I64(i64::from_le_bytes(value.try_into().map_err(|_| invalid())?)),
+            0x7d => RuntimeValue::F32(F32::from_bits(u32::from_le_bytes(
+                value.try_into().map_err(|_| invalid())?,
+            ))),
+            0x7c => RuntimeValue::F64(F64::from_bits(u64::from_le_bytes(
+                value.try_into().map_err(|_| invalid())?,
+            ))),
+            _ => return Err(invalid()),
+        };
+        Ok(GlobalInstance::alloc(value, mutable))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GlobalInstance;
+    use crate::nan_preserving_float::{F32, F64};
+    use crate::RuntimeValue;
+
+    #[test]
+    fn encode_decode_roundtrip() {
+        let values = [
+            RuntimeValue::I32(-2),
+            RuntimeValue::I64(i64::MIN),
+            RuntimeValue::F32(F32::from_bits(0x7fa0_0001)),
+            RuntimeValue::F64(F64::from_bits(0xfff4_0000_0000_0001)),
+            RuntimeValue::F64((-0.0).into()),
+        ];
+        for &value in values.iter() {
+            for &mutable in &[false, true] {
+                let global = GlobalInstance::alloc(value, mutable);
+                let decoded = GlobalInstance::decode(&global.encode()).unwrap();
+                assert_eq!(decoded.is_mutable(), mutable);
+                // Compare the bits, NaNs aren't equal to themselves.
+                assert_eq!(decoded.encode(), global.encode());
+            }
+        }
+    }
+
+    #[test]
+    fn encoding_is_little_endian() {
+        let global = GlobalInstance::alloc(RuntimeValue::I32(0x0102_0304), true);
+        assert_eq!(global.encode(), [0x7f, 0x01, 0x04, 0x03, 0x02, 0x01]);
+    }
+
+    #[test]
+    fn decode_rejects_invalid_encodings() {
+        let invalid: &[&[u8]] = &[
+            &[],
+            &[0x7f],
+            &[0x7f, 0x00, 0x00],
+            &[0x7f, 0x02, 0x00, 0x00, 0x00, 0x00],
+            &[0x7b, 0x00, 0x00, 0x00, 0x00, 0x00],
+            &[0x7e, 0x00, 0x00, 0x00, 0x00, 0x00],
+        ];
+        for bytes in invalid {
+            assert!(GlobalInstance::decode(bytes).is_err());
+        }
+    }
 }
@@ -1,5 +1,9 @@
-use crate::value::{FromRuntimeValue, RuntimeValue};
-use crate::{Trap, TrapKind};
+use crate::func::{FuncInstance, FuncRef};
+use crate::nan_preserving_float::{F32, F64};
+use crate::types::{Signature, ValueType};
+use crate::value::{FromRuntimeValue, RuntimeValue, WordOrder};
+use crate::{Error, MemoryRef, Trap, TrapKind};
+use alloc::format;
 
 use downcast_rs::{impl_downcast, DowncastSync};
 
@@ -52,6 +56,39 @@ impl<'a> RuntimeArgs<'a> {
         Ok(self.0[idx])
     }
 
+    /// Extract an `u128` passed as two `i64` arguments, starting at index `idx`, in
+    /// the given `order`.
+    ///
+    /// See [`RuntimeValue::join_u128`] for details.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if either argument isn't an `i64` or there are not enough arguments.
+    ///
+    /// [`RuntimeValue::join_u128`]: enum.RuntimeValue.html#method.join_u128
+    pub fn nth_u128_checked(&self, idx: usize, order: WordOrder) -> Result<u128, Trap> {
+        let second = idx.checked_add(1).ok_or(TrapKind::UnexpectedSignature)?;
+        RuntimeValue::join_u128(
+            self.nth_value_checked(idx)?,
+            self.nth_value_checked(second)?,
+            order,
+        )
+    }
+
+    /// Extract an `i128` passed as two `i64` arguments, starting at index `idx`, in
+    /// the given `order`.
+    ///
+    /// See [`RuntimeValue::join_i128`] for details.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if either argument isn't an `i64` or there are not enough arguments.
+    ///
+    /// [`RuntimeValue::join_i128`]: enum.RuntimeValue.html#method.join_i128
+    pub fn nth_i128_checked(&self, idx: usize, order: WordOrder) -> Result<i128, Trap> {
+        self.nth_u128_checked(idx, order).map(|value| value as i128)
+    }
+
     /// Extract argument by index `idx`.
     ///
     /// # Panics
@@ -211,6 +248,32 @@ pub trait Externals {
         index: usize,
         args: RuntimeArgs,
     ) -> Result<Option<RuntimeValue>, Trap>;
+
+    /// Perform invoke of a host function by specified `index`, with access to the
+    /// linear memory of the caller.
+    ///
+    /// This is what the interpreter calls when wasm code calls a host function.
+    /// `memory` is the default memory of the module instance the calling function
+    /// belongs to, whether it's defined in that module or imported, or `None` if
+    /// the instance has no memory. It is also `None` when the host function is
+    /// invoked directly, e.g. with [`FuncInstance::invoke`].
+    ///
+    /// The default implementation ignores `memory` and forwards to [`invoke_index`].
+    /// Override it to read arguments from and write results to the caller's memory
+    /// without keeping a [`MemoryRef`] around.
+    ///
+    /// [`invoke_index`]: #tymethod.invoke_index
+    /// [`FuncInstance::invoke`]: struct.FuncInstance.html#method.invoke
+    /// [`MemoryRef`]: struct.MemoryRef.html
+    fn invoke_index_with_memory(
+        &mut self,
+        index: usize,
+        args: RuntimeArgs,
+        memory: Option<&MemoryRef>,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        let _ = memory;
+        self.invoke_index(index, args)
+    }
 }
 
 /// Implementation of [`Externals`] that just traps on [`invoke_index`].
@@ -225,15 +288,238 @@ impl Externals for NopExternals {
         _index: usize,
         _args: RuntimeArgs,
     ) -> Result<Option<RuntimeValue>, Trap> {
-        Err(TrapKind::Unreachable.into())
+        Err(TrapKind::Unreachable { func_index: None }.into())
+    }
+}
+
+/// A type of the parameters and results of host functions defined with [`host_module!`].
+///
+/// [`host_module!`]: macro.host_module.html
+pub trait HostValueType: FromRuntimeValue + Into<RuntimeValue> {
+    /// The wasm type values of this type are represented by.
+    const VALUE_TYPE: ValueType;
+}
+
+/// A type that host functions defined with [`host_module!`] can return.
+///
+/// Implemented for `()`, for all [`HostValueType`]s, and for `Result<T, Trap>` of those,
+/// so host functions can trap.
+///
+/// [`host_module!`]: macro.host_module.html
+/// [`HostValueType`]: trait.HostValueType.html
+pub trait HostFuncResult {
+    /// The result type of the signature of the host function.
+    const VALUE_TYPE: Option<ValueType>;
+
+    /// Convert the returned value into the result of [`Externals::invoke_index`].
+    ///
+    /// [`Externals::invoke_index`]: trait.Externals.html#tymethod.invoke_index
+    fn into_invoke_result(self) -> Result<Option<RuntimeValue>, Trap>;
+}
+
+macro_rules! impl_host_value_type {
+    ($($ty:ty => $value_type:ident),*) => {
+        $(
+            impl HostValueType for $ty {
+                const VALUE_TYPE: ValueType = ValueType::$value_type;
+            }
+
+            impl HostFuncResult for $ty {
+                const VALUE_TYPE: Option<ValueType> = Some(ValueType::$value_type);
+
+                fn into_invoke_result(self) -> Result<Option<RuntimeValue>, Trap> {
+                    Ok(Some(self.into()))
+                }
+            }
+        )*
+    };
+}
+
+impl_host_value_type!(i32 => I32, u32 => I32, i64 => I64, u64 => I64, F32 => F32, F64 => F64);
+
+impl HostFuncResult for () {
+    const VALUE_TYPE: Option<ValueType> = None;
+
+    fn into_invoke_result(self) -> Result<Option<RuntimeValue>, Trap> {
+        Ok(None)
+    }
+}
+
+impl<T: HostFuncResult> HostFuncResult for Result<T, Trap> {
+    const VALUE_TYPE: Option<ValueType> = T::VALUE_TYPE;
+
+    fn into_invoke_result(self) -> Result<Option<RuntimeValue>, Trap> {
+        self?.into_invoke_result()
     }
 }
 
+/// Resolve `field_name` to one of `funcs`, given by their names, parameters and results.
+///
+/// The host function is allocated with the index of its entry in `funcs`. Used by
+/// [`host_module!`].
+///
+/// [`host_module!`]: macro.host_module.html
+#[doc(hidden)]
+pub fn resolve_host_func(
+    field_name: &str,
+    signature: &Signature,
+    funcs: &[(&str, &[ValueType], Option<ValueType>)],
+) -> Result<FuncRef, Error> {
+    let (index, &(_, params, return_type)) = funcs
+        .iter()
+        .enumerate()
+        .find(|(_, &(name, ..))| name == field_name)
+        .ok_or_else(|| Error::Instantiation(format!("Export {} not found", field_name)))?;
+    let expected = Signature::new(params.to_vec(), return_type);
+    if *signature != expected {
+        return Err(Error::Instantiation(format!(
+            "Export {} has signature {:?}, but the import expects {:?}",
+            field_name, expected, signature
+        )));
+    }
+    Ok(FuncInstance::alloc_host(expected, index))
+}
+
+/// Define a host module from an impl block of native functions.
+///
+/// The functions are defined as methods of the type, and the type implements both
+/// [`ModuleImportResolver`] and [`Externals`] for them. Each function is exported by its
+/// name and with the signature given by its parameter and result types, which must be
+/// [`HostValueType`]s and a [`HostFuncResult`] respectively. So the names, indices and
+/// signatures of the resolver and the dispatch can't get out of sync.
+///
+/// Each function takes `&mut self`. Arguments are converted as with
+/// [`RuntimeArgs::nth_checked`]. A function returning `Result<T, Trap>` traps on `Err`.
+///
+/// # Examples
+///
+/// ```rust
+/// extern crate wabt;
+/// #[macro_use]
+/// extern crate wasmi;
+///
+/// use wasmi::{ImportsBuilder, ModuleInstance, Module, RuntimeValue, Trap, TrapKind};
+///
+/// struct Env {
+///     printed: Vec<u32>,
+/// }
+///
+/// host_module! {
+///     impl Env {
+///         fn add(&mut self, a: i32, b: i32) -> i32 {
+///             a.wrapping_add(b)
+///         }
+///
+///         fn print(&mut self, value: u32) {
+///             self.printed.push(value);
+///         }
+///
+///         fn div(&mut self, a: u32, b: u32) -> Result<u32, Trap> {
+///             a.checked_div(b).ok_or_else(|| TrapKind::DivisionByZero.into())
+///         }
+///     }
+/// }
+///
+/// fn main() {
+///     let wasm = wabt::wat2wasm(
+///         r#"
+///         (module
+///             (import "env" "add" (func $add (param i32 i32) (result i32)))
+///             (import "env" "print" (func $print (param i32)))
+///             (func (export "run") (param i32)
+///                 (call $print (call $add (local.get 0) (i32.const 1)))))
+///         "#,
+///     )
+///     .unwrap();
+///     let module = Module::from_buffer(wasm).unwrap();
+///
+///     let mut env = Env { printed: Vec::new() };
+///     let instance = ModuleInstance::new(&module, &ImportsBuilder::new().with_resolver("env", &env))
+///         .unwrap()
+///         .assert_no_start();
+///     instance
+///         .invoke_export("run", &[RuntimeValue::I32(41)], &mut env)
+///         .unwrap();
+///     assert_eq!(env.printed, [42]);
+/// }
+/// ```
+///
+/// [`ModuleImportResolver`]: trait.ModuleImportResolver.html
+/// [`Externals`]: trait.Externals.html
+/// [`HostValueType`]: trait.HostValueType.html
+/// [`HostFuncResult`]: trait.HostFuncResult.html
+/// [`RuntimeArgs::nth_checked`]: struct.RuntimeArgs.html#method.nth_checked
+#[macro_export]
+macro_rules! host_module {
+    (
+        impl $ty:ty {
+            $(
+                $(#[$attr:meta])*
+                $vis:vis fn $name:ident(&mut $self:ident $(, $arg:ident: $arg_ty:ty)* $(,)?)
+                    $(-> $ret:ty)? $body:block
+            )*
+        }
+    ) => {
+        impl $ty {
+            $(
+                $(#[$attr])*
+                $vis fn $name(&mut $self $(, $arg: $arg_ty)*) $(-> $ret)? $body
+            )*
+        }
+
+        impl $crate::ModuleImportResolver for $ty {
+            fn resolve_func(
+                &self,
+                field_name: &str,
+                signature: &$crate::Signature,
+            ) -> Result<$crate::FuncRef, $crate::Error> {
+                $crate::resolve_host_func(
+                    field_name,
+                    signature,
+                    &[$((
+                        stringify!($name),
+                        &[$(<$arg_ty as $crate::HostValueType>::VALUE_TYPE),*][..],
+                        <$crate::host_module!(@result $($ret)?) as $crate::HostFuncResult>::VALUE_TYPE,
+                    )),*],
+                )
+            }
+        }
+
+        impl $crate::Externals for $ty {
+            fn invoke_index(
+                &mut self,
+                index: usize,
+                args: $crate::RuntimeArgs,
+            ) -> Result<Option<$crate::RuntimeValue>, $crate::Trap> {
+                // The discriminants of the variants are the indices of the functions, and
+                // of their arguments respectively.
+                #[allow(non_camel_case_types, dead_code)]
+                enum Func {
+                    $($name),*
+                }
+                $(
+                    if index == Func::$name as usize {
+                        #[allow(non_camel_case_types, dead_code)]
+                        enum Arg {
+                            $($arg),*
+                        }
+                        $(let $arg: $arg_ty = args.nth_checked(Arg::$arg as usize)?;)*
+                        return $crate::HostFuncResult::into_invoke_result(self.$name($($arg),*));
+                    }
+                )*
+                Err($crate::TrapKind::Unreachable { func_index: None }.into())
+            }
+        }
+    };
+    (@result) => { () };
+    (@result $ret:ty) => { $ret };
+}
+
 #[cfg(test)]
 mod tests {
 
     use super::{HostError, RuntimeArgs};
-    use crate::value::RuntimeValue;
+    use crate::value::{RuntimeValue, WordOrder};
 
     #[test]
     fn i32_runtime_args() {
@@ -248,6 +534,29 @@ mod tests {
         assert!(args.nth_checked::<i32>(0).is_err());
     }
 
+    #[test]
+    fn i128_runtime_args() {
+        let value: i128 = -0x0123_4567_89ab_cdef_0000_0000_0000_0002;
+        for &order in &[WordOrder::HighFirst, WordOrder::LowFirst] {
+            let [first, second] = RuntimeValue::split_i128(value, order);
+            let values = [RuntimeValue::I32(7), first, second];
+            let args: RuntimeArgs = (&values[..]).into();
+            assert_eq!(args.nth_i128_checked(1, order).unwrap(), value);
+            assert_eq!(args.nth_u128_checked(1, order).unwrap(), value as u128);
+            // Not enough arguments, or not `i64`s.
+            assert!(args.nth_i128_checked(2, order).is_err());
+            assert!(args.nth_i128_checked(0, order).is_err());
+            assert!(args.nth_i128_checked(usize::MAX, order).is_err());
+        }
+
+        let [high, low] = RuntimeValue::split_i128(-2, WordOrder::HighFirst);
+        assert_eq!(high, RuntimeValue::I64(-1));
+        assert_eq!(low, RuntimeValue::I64(-2));
+        let [low, high] = RuntimeValue::split_u128(1 << 64, WordOrder::LowFirst);
+        assert_eq!(low, RuntimeValue::I64(0));
+        assert_eq!(high, RuntimeValue::I64(1));
+    }
+
     // Tests that `HostError` trait is object safe.
     fn _host_error_is_object_safe(_: &dyn HostError) {}
 }
@@ -1,7 +1,7 @@
 use crate::func::FuncRef;
 use crate::global::GlobalRef;
 use crate::memory::MemoryRef;
-use crate::module::ModuleRef;
+use crate::module::{ExternVal, ModuleRef};
 use crate::table::TableRef;
 use crate::types::{GlobalDescriptor, MemoryDescriptor, TableDescriptor};
 use crate::{Error, Signature};
@@ -96,10 +96,25 @@ pub trait ImportResolver {
 /// # }
 /// ```
 ///
+/// Single entities can also be registered directly by their module and field names,
+/// without implementing a resolver:
+///
+/// ```rust
+/// use wasmi::{ExternVal, ImportsBuilder, MemoryInstance};
+/// use wasmi::memory_units::Pages;
+///
+/// let memory = MemoryInstance::alloc(Pages(1), None).unwrap();
+/// let imports = ImportsBuilder::new()
+///     .with_extern("env", "memory", ExternVal::Memory(memory));
+/// ```
+///
+/// Such entities take precedence over a resolver registered under the same module name.
+///
 /// [`ImportResolver`]: trait.ImportResolver.html
 /// [`ModuleImportResolver`]: trait.ModuleImportResolver.html
 pub struct ImportsBuilder<'a> {
     modules: BTreeMap<String, &'a dyn ModuleImportResolver>,
+    externs: BTreeMap<String, BTreeMap<String, ExternVal>>,
 }
 
 impl<'a> Default for ImportsBuilder<'a> {
@@ -113,6 +128,7 @@ impl<'a> ImportsBuilder<'a> {
     pub fn new() -> ImportsBuilder<'a> {
         ImportsBuilder {
             modules: BTreeMap::new(),
+            externs: BTreeMap::new(),
         }
     }
 
@@ -137,8 +153,41 @@ impl<'a> ImportsBuilder<'a> {
         self.modules.insert(name.into(), resolver);
     }
 
-    fn resolver(&self, name: &str) -> Option<&dyn ModuleImportResolver> {
-        self.modules.get(name).cloned()
+    /// Register an entity by a module and a field name.
+    pub fn with_extern<M: Into<String>, F: Into<String>>(
+        mut self,
+        module_name: M,
+        field_name: F,
+        extern_val: ExternVal,
+    ) -> Self {
+        self.push_extern(module_name, field_name, extern_val);
+        self
+    }
+
+    /// Register an entity by a module and a field name.
+    ///
+    /// Mutable borrowed version.
+    pub fn push_extern<M: Into<String>, F: Into<String>>(
+        &mut self,
+        module_name: M,
+        field_name: F,
+        extern_val: ExternVal,
+    ) {
+        self.externs
+            .entry(module_name.into())
+            .or_default()
+            .insert(field_name.into(), extern_val);
+    }
+
+    fn resolver(&self, name: &str) -> Result<&dyn ModuleImportResolver, Error> {
+        self.modules
+            .get(name)
+            .cloned()
+            .ok_or_else(|| Error::Instantiation(format!("Module {} not found", name)))
+    }
+
+    fn extern_val(&self, module_name: &str, field_name: &str) -> Option<&ExternVal> {
+        self.externs.get(module_name)?.get(field_name)
     }
 }
 
@@ -149,8 +198,12 @@ impl<'a> ImportResolver for ImportsBuilder<'a> {
         field_name: &str,
         signature: &Signature,
     ) -> Result<FuncRef, Error> {
-        self.resolver(module_name)
-            .ok_or_else(|| Error::Instantiation(format!("Module {} not found", module_name)))?
+        if let Some(extern_val) = self.extern_val(module_name, field_name) {
+            return extern_val.as_func().cloned().ok_or_else(|| {
+                Error::Instantiation(format!("Export {} is not a function", field_name))
+            });
+        }
+        self.resolver(module_name)?
             .resolve_func(field_name, signature)
     }
 
@@ -160,8 +213,12 @@ impl<'a> ImportResolver for ImportsBuilder<'a> {
         field_name: &str,
         global_type: &GlobalDescriptor,
     ) -> Result<GlobalRef, Error> {
-        self.resolver(module_name)
-            .ok_or_else(|| Error::Instantiation(format!("Module {} not found", module_name)))?
+        if let Some(extern_val) = self.extern_val(module_name, field_name) {
+            return extern_val.as_global().cloned().ok_or_else(|| {
+                Error::Instantiation(format!("Export {} is not a global", field_name))
+            });
+        }
+        self.resolver(module_name)?
             .resolve_global(field_name, global_type)
     }
 
@@ -171,8 +228,12 @@ impl<'a> ImportResolver for ImportsBuilder<'a> {
         field_name: &str,
         memory_type: &MemoryDescriptor,
     ) -> Result<MemoryRef, Error> {
-        self.resolver(module_name)
-            .ok_or_else(|| Error::Instantiation(format!("Module {} not found", module_name)))?
+        if let Some(extern_val) = self.extern_val(module_name, field_name) {
+            return extern_val.as_memory().cloned().ok_or_else(|| {
+                Error::Instantiation(format!("Export {} is not a memory", field_name))
+            });
+        }
+        self.resolver(module_name)?
             .resolve_memory(field_name, memory_type)
     }
 
@@ -182,8 +243,12 @@ impl<'a> ImportResolver for ImportsBuilder<'a> {
         field_name: &str,
         table_type: &TableDescriptor,
     ) -> Result<TableRef, Error> {
-        self.resolver(module_name)
-            .ok_or_else(|| Error::Instantiation(format!("Module {} not found", module_name)))?
+        if let Some(extern_val) = self.extern_val(module_name, field_name) {
+            return extern_val.as_table().cloned().ok_or_else(|| {
+                Error::Instantiation(format!("Export {} is not a table", field_name))
+            });
+        }
+        self.resolver(module_name)?
             .resolve_table(field_name, table_type)
     }
 }
@@ -62,12 +62,30 @@
 //! - All control flow structures are flattened to plain gotos.
 //! - Implicit returns via reaching function scope `End` are replaced with an explicit `return` instruction.
 //! - Locals live on the value stack now.
-//! - Load/store instructions doesn't take `align` parameter.
+//! - Load/store and atomic instructions doesn't take `align` parameter.
 //! - *.const store value in straight encoding.
 //! - Reserved immediates are ignored for `call_indirect`, `current_memory`, `grow_memory`.
+//! - Common sequences of `i32` arithmetic on locals are fused into single instructions.
 //!
 
-use alloc::vec::Vec;
+use alloc::{collections::TryReserveError, vec::Vec};
+
+/// Version of the instruction encoding.
+///
+/// This must be bumped whenever the encoding or the semantics of the instructions
+/// change, so that instructions produced by an incompatible version of wasmi are
+/// never executed, e.g. when compiled modules are persisted between runs. It is also
+/// bumped when the compilation of a module changes the number of executed instructions,
+/// which is reported as [`CallStats::instructions`].
+///
+/// [`CallStats::instructions`]: struct.CallStats.html#structfield.instructions
+pub const ISA_VERSION: u32 = 4;
+
+/// Maximum number of instructions in the code of a function.
+///
+/// Instructions are addressed by `u32` positions, and `u32::MAX` is reserved as the
+/// destination of branches to labels that aren't resolved yet.
+pub(crate) const MAX_INSTRUCTIONS: u32 = u32::MAX - 1;
 
 /// Should we keep a value before "discarding" a stack frame?
 ///
@@ -169,7 +187,8 @@ pub enum Instruction<'a> {
     Return(DropKeep),
 
     Call(u32),
-    CallIndirect(u32),
+    /// Call through the table with the second index, using the signature with the first one.
+    CallIndirect(u32, u32),
 
     Drop,
     Select,
@@ -204,6 +223,13 @@ pub enum Instruction<'a> {
     CurrentMemory,
     GrowMemory,
 
+    #[cfg(feature = "atomics")]
+    AtomicNotify(u32),
+    #[cfg(feature = "atomics")]
+    I32AtomicWait(u32),
+    #[cfg(feature = "atomics")]
+    I64AtomicWait(u32),
+
     I32Const(i32),
     I64Const(i64),
     F32Const(u32),
@@ -339,6 +365,22 @@ pub enum Instruction<'a> {
     I64ReinterpretF64,
     F32ReinterpretI32,
     F64ReinterpretI64,
+
+    /// Push the sum of the locals at the specified depths.
+    ///
+    /// This and the following instructions are fused from sequences of the plain
+    /// ones, see `Compiler` for the patterns they replace. Depths are relative to
+    /// the stack pointer before the instruction is executed.
+    I32AddLocals(u32, u32),
+    /// Push the product of the locals at the specified depths.
+    I32MulLocals(u32, u32),
+    /// Push the sum of the local at the specified depth and a constant.
+    I32AddLocalConst(u32, i32),
+    /// Put the sum of the locals at the first two depths in at the third one.
+    I32AddLocalsToLocal(u32, u32, u32),
+    /// Put the sum of the local at the specified depth and a constant in at the
+    /// second depth.
+    I32AddLocalConstToLocal(u32, i32, u32),
 }
 
 /// The internally-stored instruction type. This differs from `Instruction` in that the `BrTable`
@@ -349,6 +391,10 @@ pub enum Instruction<'a> {
 ///
 /// When returning instructions we convert to `Instruction`, whose `BrTable` variant internally
 /// borrows the list of instructions and returns targets by reading it.
+///
+/// Every instruction takes 16 bytes. Branches don't determine that size: `I64Const`,
+/// `F64Const` and fused instructions with three immediates need as much, so encoding branch
+/// targets more compactly, e.g. as small relative offsets, wouldn't shrink the code at all.
 #[derive(Copy, Debug, Clone, PartialEq, Eq)]
 #[allow(clippy::upper_case_acronyms)]
 pub(crate) enum InstructionInternal {
@@ -358,14 +404,16 @@ pub(crate) enum InstructionInternal {
     Br(Target),
     BrIfEqz(Target),
     BrIfNez(Target),
-    BrTable { count: u32 },
+    BrTable {
+        count: u32,
+    },
     BrTableTarget(Target),
 
     Unreachable,
     Return(DropKeep),
 
     Call(u32),
-    CallIndirect(u32),
+    CallIndirect(u32, u32),
 
     Drop,
     Select,
@@ -400,6 +448,13 @@ pub(crate) enum InstructionInternal {
     CurrentMemory,
     GrowMemory,
 
+    #[cfg(feature = "atomics")]
+    AtomicNotify(u32),
+    #[cfg(feature = "atomics")]
+    I32AtomicWait(u32),
+    #[cfg(feature = "atomics")]
+    I64AtomicWait(u32),
+
     I32Const(i32),
     I64Const(i64),
     F32Const(u32),
@@ -535,18 +590,128 @@ pub(crate) enum InstructionInternal {
     I64ReinterpretF64,
     F32ReinterpretI32,
     F64ReinterpretI64,
+
+    I32AddLocals(u32, u32),
+    I32MulLocals(u32, u32),
+    I32AddLocalConst(u32, i32),
+    I32AddLocalsToLocal(u32, u32, u32),
+    I32AddLocalConstToLocal(u32, i32, u32),
+}
+
+/// Category of a compiled instruction, see [`Module::opcode_histogram`].
+///
+/// [`Module::opcode_histogram`]: ../struct.Module.html#method.opcode_histogram
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum OpcodeKind {
+    /// `local.get`, `local.set` and `local.tee`.
+    Local,
+    /// `global.get` and `global.set`.
+    Global,
+    /// Constants of any type.
+    Const,
+    /// Loads from the linear memory.
+    Load,
+    /// Stores to the linear memory.
+    Store,
+    /// `memory.size` and `memory.grow`.
+    Memory,
+    /// `memory.atomic.notify` and the atomic waits.
+    ///
+    /// These are only compiled with the `atomics` feature, so this is never counted
+    /// without it.
+    Atomic,
+    /// Integer and float comparisons, including the `eqz`s.
+    Compare,
+    /// Integer and float arithmetic, bitwise operations included.
+    Arithmetic,
+    /// Conversions and reinterpretations between the value types.
+    Conversion,
+    /// `drop` and `select`.
+    Parametric,
+    /// Unconditional, conditional and table branches, and `return`.
+    Branch,
+    /// Direct calls.
+    Call,
+    /// Indirect calls through a table.
+    CallIndirect,
+    /// `unreachable`.
+    Unreachable,
+    /// Superinstructions fusing local accesses with `i32` arithmetic.
+    Fused,
+}
+
+impl InstructionInternal {
+    /// Returns the category of this instruction, or `None` for the targets of a
+    /// `br_table`, which are part of the `br_table` itself.
+    pub(crate) fn opcode_kind(&self) -> Option<OpcodeKind> {
+        use self::InstructionInternal::*;
+
+        let kind = match *self {
+            GetLocal(_) | SetLocal(_) | TeeLocal(_) => OpcodeKind::Local,
+            GetGlobal(_) | SetGlobal(_) => OpcodeKind::Global,
+            I32Const(_) | I64Const(_) | F32Const(_) | F64Const(_) => OpcodeKind::Const,
+            I32Load(_) | I64Load(_) | F32Load(_) | F64Load(_) | I32Load8S(_) | I32Load8U(_)
+            | I32Load16S(_) | I32Load16U(_) | I64Load8S(_) | I64Load8U(_) | I64Load16S(_)
+            | I64Load16U(_) | I64Load32S(_) | I64Load32U(_) => OpcodeKind::Load,
+            I32Store(_) | I64Store(_) | F32Store(_) | F64Store(_) | I32Store8(_)
+            | I32Store16(_) | I64Store8(_) | I64Store16(_) | I64Store32(_) => OpcodeKind::Store,
+            CurrentMemory | GrowMemory => OpcodeKind::Memory,
+            #[cfg(feature = "atomics")]
+            AtomicNotify(_) | I32AtomicWait(_) | I64AtomicWait(_) => OpcodeKind::Atomic,
+            I32Eqz | I32Eq | I32Ne | I32LtS | I32LtU | I32GtS | I32GtU | I32LeS | I32LeU
+            | I32GeS | I32GeU | I64Eqz | I64Eq | I64Ne | I64LtS | I64LtU | I64GtS | I64GtU
+            | I64LeS | I64LeU | I64GeS | I64GeU | F32Eq | F32Ne | F32Lt | F32Gt | F32Le | F32Ge
+            | F64Eq | F64Ne | F64Lt | F64Gt | F64Le | F64Ge => OpcodeKind::Compare,
+            I32Clz | I32Ctz | I32Popcnt | I32Add | I32Sub | I32Mul | I32DivS | I32DivU
+            | I32RemS | I32RemU | I32And | I32Or | I32Xor | I32Shl | I32ShrS | I32ShrU
+            | I32Rotl | I32Rotr | I64Clz | I64Ctz | I64Popcnt | I64Add | I64Sub | I64Mul
+            | I64DivS | I64DivU | I64RemS | I64RemU | I64And | I64Or | I64Xor | I64Shl
+            | I64ShrS | I64ShrU | I64Rotl | I64Rotr | F32Abs | F32Neg | F32Ceil | F32Floor
+            | F32Trunc | F32Nearest | F32Sqrt | F32Add | F32Sub | F32Mul | F32Div | F32Min
+            | F32Max | F32Copysign | F64Abs | F64Neg | F64Ceil | F64Floor | F64Trunc
+            | F64Nearest | F64Sqrt | F64Add | F64Sub | F64Mul | F64Div | F64Min | F64Max
+            | F64Copysign => OpcodeKind::Arithmetic,
+            I32WrapI64 | I32TruncSF32 | I32TruncUF32 | I32TruncSF64 | I32TruncUF64
+            | I64ExtendSI32 | I64ExtendUI32 | I64TruncSF32 | I64TruncUF32 | I64TruncSF64
+            | I64TruncUF64 | F32ConvertSI32 | F32ConvertUI32 | F32ConvertSI64 | F32ConvertUI64
+            | F32DemoteF64 | F64ConvertSI32 | F64ConvertUI32 | F64ConvertSI64 | F64ConvertUI64
+            | F64PromoteF32 | I32ReinterpretF32 | I64ReinterpretF64 | F32ReinterpretI32
+            | F64ReinterpretI64 => OpcodeKind::Conversion,
+            Drop | Select => OpcodeKind::Parametric,
+            Br(_) | BrIfEqz(_) | BrIfNez(_) | BrTable { .. } | Return(_) => OpcodeKind::Branch,
+            BrTableTarget(_) => return None,
+            Call(_) => OpcodeKind::Call,
+            CallIndirect(..) => OpcodeKind::CallIndirect,
+            Unreachable => OpcodeKind::Unreachable,
+            I32AddLocals(..)
+            | I32MulLocals(..)
+            | I32AddLocalConst(..)
+            | I32AddLocalsToLocal(..)
+            | I32AddLocalConstToLocal(..) => OpcodeKind::Fused,
+        };
+        Some(kind)
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Instructions {
     vec: Vec<InstructionInternal>,
+    /// Upper bound of the number of values the function keeps on the value stack,
+    /// including its locals.
+    max_stack_height: u32,
 }
 
 impl Instructions {
-    pub fn with_capacity(capacity: usize) -> Self {
-        Instructions {
-            vec: Vec::with_capacity(capacity),
-        }
+    /// Create an empty instruction buffer with room for `capacity` instructions.
+    ///
+    /// Unlike `Vec::with_capacity`, allocation failure is reported instead of aborting.
+    pub fn try_with_capacity(capacity: usize) -> Result<Self, TryReserveError> {
+        let mut vec = Vec::new();
+        vec.try_reserve_exact(capacity)?;
+        Ok(Instructions {
+            vec,
+            max_stack_height: 0,
+        })
     }
 
     pub fn current_pc(&self) -> u32 {
@@ -557,6 +722,29 @@ impl Instructions {
         self.vec.push(instruction);
     }
 
+    /// Returns the number of bytes the instructions take on the heap.
+    pub(crate) fn heap_size(&self) -> usize {
+        self.vec.len() * core::mem::size_of::<InstructionInternal>()
+    }
+
+    pub fn max_stack_height(&self) -> u32 {
+        self.max_stack_height
+    }
+
+    pub(crate) fn set_max_stack_height(&mut self, max_stack_height: u32) {
+        self.max_stack_height = max_stack_height;
+    }
+
+    /// Returns the instructions starting at `pc`.
+    pub(crate) fn tail(&self, pc: u32) -> &[InstructionInternal] {
+        &self.vec[pc as usize..]
+    }
+
+    /// Remove the instructions starting at `pc`.
+    pub(crate) fn truncate(&mut self, pc: u32) {
+        self.vec.truncate(pc as usize);
+    }
+
     pub fn patch_relocation(&mut self, reloc: Reloc, dst_pc: u32) {
         match reloc {
             Reloc::Br { pc } => match self.vec[pc as usize] {
@@ -621,7 +809,7 @@ impl<'a> Iterator for InstructionIter<'a> {
             InstructionInternal::Return(x) => Instruction::Return(x),
 
             InstructionInternal::Call(x) => Instruction::Call(x),
-            InstructionInternal::CallIndirect(x) => Instruction::CallIndirect(x),
+            InstructionInternal::CallIndirect(x, table) => Instruction::CallIndirect(x, table),
 
             InstructionInternal::Drop => Instruction::Drop,
             InstructionInternal::Select => Instruction::Select,
@@ -656,6 +844,13 @@ impl<'a> Iterator for InstructionIter<'a> {
             InstructionInternal::CurrentMemory => Instruction::CurrentMemory,
             InstructionInternal::GrowMemory => Instruction::GrowMemory,
 
+            #[cfg(feature = "atomics")]
+            InstructionInternal::AtomicNotify(x) => Instruction::AtomicNotify(x),
+            #[cfg(feature = "atomics")]
+            InstructionInternal::I32AtomicWait(x) => Instruction::I32AtomicWait(x),
+            #[cfg(feature = "atomics")]
+            InstructionInternal::I64AtomicWait(x) => Instruction::I64AtomicWait(x),
+
             InstructionInternal::I32Const(x) => Instruction::I32Const(x),
             InstructionInternal::I64Const(x) => Instruction::I64Const(x),
             InstructionInternal::F32Const(x) => Instruction::F32Const(x),
@@ -791,6 +986,16 @@ impl<'a> Iterator for InstructionIter<'a> {
             InstructionInternal::I64ReinterpretF64 => Instruction::I64ReinterpretF64,
             InstructionInternal::F32ReinterpretI32 => Instruction::F32ReinterpretI32,
             InstructionInternal::F64ReinterpretI64 => Instruction::F64ReinterpretI64,
+
+            InstructionInternal::I32AddLocals(x, y) => Instruction::I32AddLocals(x, y),
+            InstructionInternal::I32MulLocals(x, y) => Instruction::I32MulLocals(x, y),
+            InstructionInternal::I32AddLocalConst(x, c) => Instruction::I32AddLocalConst(x, c),
+            InstructionInternal::I32AddLocalsToLocal(x, y, z) => {
+                Instruction::I32AddLocalsToLocal(x, y, z)
+            }
+            InstructionInternal::I32AddLocalConstToLocal(x, c, z) => {
+                Instruction::I32AddLocalConstToLocal(x, c, z)
+            }
         };
 
         self.position += 1;
@@ -798,3 +1003,53 @@ impl<'a> Iterator for InstructionIter<'a> {
         Some(out)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::ISA_VERSION;
+    use alloc::string::String;
+
+    /// Returns the source of the item starting with `header`, without comments and whitespace.
+    fn item_source(source: &str, header: &str) -> String {
+        let start = source.find(header).expect("item not found");
+        let end = start + source[start..].find("\n}\n").expect("item end not found");
+        source[start..end]
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.starts_with("//"))
+            .collect()
+    }
+
+    #[test]
+    fn instruction_size() {
+        use super::{InstructionInternal, Target};
+        use core::mem::size_of;
+
+        // The size of the code is dominated by the widest instructions, see the docs of
+        // `InstructionInternal`.
+        assert_eq!(size_of::<InstructionInternal>(), 16);
+        assert!(size_of::<Target>() < 16);
+    }
+
+    /// If this test fails, the encoding of the instructions has changed.
+    ///
+    /// Bump `ISA_VERSION` and update the pinned fingerprint.
+    #[test]
+    fn isa_version_tracks_encoding() {
+        let source = include_str!("isa.rs");
+        let encoding = [
+            "pub enum Keep",
+            "pub struct DropKeep",
+            "pub struct Target",
+            "pub(crate) enum InstructionInternal",
+        ]
+        .iter()
+        .map(|header| item_source(source, header))
+        .collect::<String>();
+
+        assert_eq!(
+            (ISA_VERSION, crate::fnv::fnv1a(encoding.as_bytes())),
+            (4, 12980704745747729302)
+        );
+    }
+}
@@ -0,0 +1,178 @@
+use crate::nan_preserving_float::{F32, F64};
+use crate::{Error, ImportManifest, RuntimeValue, ValueType};
+use serde_json::{Number, Value};
+
+impl RuntimeValue {
+    /// Convert a JSON number to a value of type `value_type`.
+    ///
+    /// Integers are accepted in the range of both the signed and the unsigned
+    /// interpretation of the wasm type, e.g. `-1` and `4294967295` are both the
+    /// `I32` with all bits set. Numbers with a fraction or an exponent aren't
+    /// accepted for integer types, even if they are whole.
+    ///
+    /// Floats accept any JSON number, which is rounded to the nearest representable
+    /// value. Rounding to `F32` loses precision, e.g. `0.1` becomes
+    /// `0.100000001490116...`. Numbers beyond the range of the type are rejected
+    /// rather than rounded to infinity.
+    ///
+    /// Available with the `json` feature.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if `json` isn't a number or doesn't fit `value_type`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use serde_json::json;
+    /// use wasmi::{RuntimeValue, ValueType};
+    ///
+    /// assert_eq!(
+    ///     RuntimeValue::from_json(&json!(-1), ValueType::I32).unwrap(),
+    ///     RuntimeValue::I32(-1)
+    /// );
+    /// assert!(RuntimeValue::from_json(&json!(1.5), ValueType::I64).is_err());
+    /// assert!(RuntimeValue::from_json(&json!("1"), ValueType::I32).is_err());
+    /// ```
+    pub fn from_json(json: &Value, value_type: ValueType) -> Result<RuntimeValue, Error> {
+        let number = match json {
+            Value::Number(number) => number,
+            _ => {
+                return Err(Error::Value(format!(
+                    "Expected a number for {:?}, got {}",
+                    value_type, json
+                )))
+            }
+        };
+        let out_of_range =
+            || Error::Value(format!("{} is out of range of {:?}", number, value_type));
+        let not_integer = || {
+            Error::Value(format!(
+                "Expected an integer for {:?}, got {}",
+                value_type, number
+            ))
+        };
+        let value = match value_type {
+            ValueType::I32 => {
+                let value = integer(number).ok_or_else(not_integer)?;
+                if value < i128::from(i32::MIN) || value > i128::from(u32::MAX) {
+                    return Err(out_of_range());
+                }
+                RuntimeValue::I32(value as i32)
+            }
+            ValueType::I64 => {
+                let value = integer(number).ok_or_else(not_integer)?;
+                if value < i128::from(i64::MIN) || value > i128::from(u64::MAX) {
+                    return Err(out_of_range());
+                }
+                RuntimeValue::I64(value as i64)
+            }
+            ValueType::F32 => {
+                let value = float(number).ok_or_else(out_of_range)?;
+                let rounded = value as f32;
+                if rounded.is_infinite() {
+                    return Err(out_of_range());
+                }
+                RuntimeValue::F32(F32::from(rounded))
+            }
+            ValueType::F64 => RuntimeValue::F64(F64::from(float(number).ok_or_else(out_of_range)?)),
+        };
+        Ok(value)
+    }
+
+    /// Convert this value to a JSON number.
+    ///
+    /// Integers are converted with their signed interpretation. `I64` values are
+    /// represented exactly by `serde_json`, but consumers that parse JSON numbers as
+    /// doubles, e.g. JavaScript, lose precision beyond ±2<sup>53</sup>.
+    ///
+    /// Floats are converted exactly, `F32` by widening to `F64`. JSON has no
+    /// representation for NaN and infinities, so they are converted to `null`.
+    ///
+    /// Available with the `json` feature.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use serde_json::json;
+    /// use wasmi::RuntimeValue;
+    ///
+    /// assert_eq!(RuntimeValue::I32(-1).to_json(), json!(-1));
+    /// assert_eq!(RuntimeValue::F64(2.5f64.into()).to_json(), json!(2.5));
+    /// assert_eq!(RuntimeValue::F32(f32::NAN.into()).to_json(), json!(null));
+    /// ```
+    pub fn to_json(&self) -> Value {
+        match *self {
+            RuntimeValue::I32(value) => Value::from(value),
+            RuntimeValue::I64(value) => Value::from(value),
+            RuntimeValue::F32(value) => Value::from(f64::from(value.to_float())),
+            RuntimeValue::F64(value) => Value::from(value.to_float()),
+        }
+    }
+}
+
+impl ImportManifest {
+    /// Parse a manifest from JSON.
+    ///
+    /// The manifest is an object with a member per module name, each an object that maps
+    /// the allowed field names to their capability ids.
+    ///
+    /// Available with the `json` feature.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if `json` isn't valid JSON or doesn't have this shape.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use wasmi::ImportManifest;
+    ///
+    /// let manifest = ImportManifest::from_json(
+    ///     r#"{ "env": { "print": "console.log", "now": "clock.monotonic" } }"#,
+    /// )
+    /// .unwrap();
+    /// assert_eq!(manifest.capability("env", "now"), Some("clock.monotonic"));
+    /// assert_eq!(manifest.capability("env", "exit"), None);
+    /// ```
+    pub fn from_json(json: &str) -> Result<ImportManifest, Error> {
+        let invalid = |what: &str| Error::Value(format!("Invalid import manifest: {}", what));
+        let value: Value = serde_json::from_str(json).map_err(|e| invalid(&e.to_string()))?;
+        let modules = value
+            .as_object()
+            .ok_or_else(|| invalid("expected an object of modules"))?;
+
+        let mut manifest = ImportManifest::new();
+        for (module_name, fields) in modules {
+            let fields = fields.as_object().ok_or_else(|| {
+                invalid(&format!(
+                    "expected an object of fields for module {}",
+                    module_name
+                ))
+            })?;
+            for (field_name, capability) in fields {
+                let capability = capability.as_str().ok_or_else(|| {
+                    invalid(&format!(
+                        "expected a capability id for {}.{}",
+                        module_name, field_name
+                    ))
+                })?;
+                manifest.push_import(module_name.as_str(), field_name.as_str(), capability);
+            }
+        }
+        Ok(manifest)
+    }
+}
+
+/// Returns the value of `number` if it is written as an integer.
+fn integer(number: &Number) -> Option<i128> {
+    number
+        .as_i64()
+        .map(i128::from)
+        .or_else(|| number.as_u64().map(i128::from))
+}
+
+/// Returns the value of `number`, rounded to the nearest `f64`.
+fn float(number: &Number) -> Option<f64> {
+    number.as_f64().filter(|value| value.is_finite())
+}
@@ -116,6 +116,8 @@ extern crate wabt;
 
 use alloc::{
     boxed::Box,
+    collections::BTreeMap,
+    rc::Rc,
     string::{String, ToString},
     vec::Vec,
 };
@@ -153,6 +155,219 @@ impl Trap {
     pub fn into_kind(self) -> TrapKind {
         self.kind
     }
+
+    /// Returns the cause of this trap.
+    ///
+    /// See [`TrapCode`] for bucketing traps by their cause.
+    ///
+    /// [`TrapCode`]: enum.TrapCode.html
+    pub fn code(&self) -> TrapCode {
+        self.kind.code()
+    }
+
+    /// Create new trap that requests termination of the execution with the given `exit_code`.
+    ///
+    /// This is intended to be returned from `proc_exit`-style host functions.
+    /// See [`TrapKind::Exit`] for details.
+    ///
+    /// [`TrapKind::Exit`]: enum.TrapKind.html#variant.Exit
+    pub fn exit(exit_code: i32) -> Trap {
+        Trap::new(TrapKind::Exit(exit_code))
+    }
+
+    /// Create new trap raised by the host with a machine-readable `code` attached to
+    /// `error`.
+    ///
+    /// The code can be read back with [`host_code`], without downcasting `error`.
+    /// See [`TrapKind::HostWithCode`] for details.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use wasmi::{HostError, Trap};
+    /// use std::fmt;
+    ///
+    /// const RETRY: u32 = 1;
+    ///
+    /// #[derive(Debug)]
+    /// struct Busy;
+    ///
+    /// impl fmt::Display for Busy {
+    ///     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    ///         write!(f, "resource is busy")
+    ///     }
+    /// }
+    ///
+    /// impl HostError for Busy {}
+    ///
+    /// let trap = Trap::host_with_code(RETRY, Busy);
+    /// assert_eq!(trap.host_code(), Some(RETRY));
+    /// assert!(trap.as_host_error().unwrap().downcast_ref::<Busy>().is_some());
+    /// ```
+    ///
+    /// [`host_code`]: #method.host_code
+    /// [`TrapKind::HostWithCode`]: enum.TrapKind.html#variant.HostWithCode
+    pub fn host_with_code<E: host::HostError>(code: u32, error: E) -> Trap {
+        Trap::new(TrapKind::HostWithCode {
+            code,
+            error: Box::new(error),
+        })
+    }
+
+    /// Returns the code attached by the host if this trap was created with
+    /// [`host_with_code`].
+    ///
+    /// [`host_with_code`]: #method.host_with_code
+    pub fn host_code(&self) -> Option<u32> {
+        match self.kind {
+            TrapKind::HostWithCode { code, .. } => Some(code),
+            _ => None,
+        }
+    }
+
+    /// Unwrap the result of a checked arithmetic operation, trapping with
+    /// [`TrapKind::IntegerOverflow`] if it overflowed.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use wasmi::{Trap, TrapKind};
+    ///
+    /// fn add(a: i32, b: i32) -> Result<i32, Trap> {
+    ///     Trap::check_overflow(a.checked_add(b))
+    /// }
+    ///
+    /// assert_eq!(add(1, 2).unwrap(), 3);
+    /// match add(i32::MAX, 1).unwrap_err().kind() {
+    ///     TrapKind::IntegerOverflow => {}
+    ///     _ => panic!("expected overflow"),
+    /// }
+    /// ```
+    ///
+    /// [`TrapKind::IntegerOverflow`]: enum.TrapKind.html#variant.IntegerOverflow
+    pub fn check_overflow<T>(value: Option<T>) -> Result<T, Trap> {
+        value.ok_or_else(|| Trap::new(TrapKind::IntegerOverflow))
+    }
+
+    /// Returns the exit code if this trap was raised by a request to terminate the execution.
+    pub fn exit_code(&self) -> Option<i32> {
+        match self.kind {
+            TrapKind::Exit(exit_code) => Some(exit_code),
+            _ => None,
+        }
+    }
+
+    /// Returns the index of the function that executed `unreachable` if wasm code
+    /// trapped this way, see [`TrapKind::Unreachable`].
+    ///
+    /// This distinguishes a deliberate abort of the wasm code from genuine runtime
+    /// errors such as out of bounds memory accesses. The recommended pattern is for
+    /// the host function that the wasm code calls before aborting (e.g. one reporting
+    /// a panic message) to record the reason in the [`Externals`], and for the embedder
+    /// to pick it up once the invocation returns with this trap:
+    ///
+    /// ```rust
+    /// extern crate wabt;
+    /// extern crate wasmi;
+    ///
+    /// use wasmi::{Error, ImportsBuilder, Module, ModuleInstance, NopExternals};
+    ///
+    /// fn main() {
+    ///     let wasm = wabt::wat2wasm(
+    ///         r#"
+    ///         (module
+    ///           (func $abort unreachable)
+    ///           (func (export "run") (call $abort))
+    ///         )
+    ///         "#,
+    ///     )
+    ///     .unwrap();
+    ///     let module = Module::from_buffer(&wasm).unwrap();
+    ///     let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+    ///         .unwrap()
+    ///         .assert_no_start();
+    ///
+    ///     match instance.invoke_export("run", &[], &mut NopExternals) {
+    ///         Err(Error::Trap(trap)) => match trap.unreachable_func_index() {
+    ///             // Controlled abort in `$abort`, report the recorded reason.
+    ///             Some(func_index) => assert_eq!(func_index, 0),
+    ///             // Genuine runtime error.
+    ///             None => panic!("unexpected trap: {}", trap),
+    ///         },
+    ///         result => panic!("expected a trap, got {:?}", result),
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// [`TrapKind::Unreachable`]: enum.TrapKind.html#variant.Unreachable
+    /// [`Externals`]: trait.Externals.html
+    pub fn unreachable_func_index(&self) -> Option<u32> {
+        match self.kind {
+            TrapKind::Unreachable { func_index } => func_index,
+            _ => None,
+        }
+    }
+
+    /// Returns a reference to a [`HostError`] if this trap was raised by the host,
+    /// with or without a code.
+    ///
+    /// [`HostError`]: trait.HostError.html
+    pub fn as_host_error(&self) -> Option<&dyn host::HostError> {
+        match self.kind {
+            TrapKind::Host(ref host_err) => Some(&**host_err),
+            TrapKind::HostWithCode { ref error, .. } => Some(&**error),
+            _ => None,
+        }
+    }
+
+    /// Returns [`HostError`] if this trap was raised by the host, otherwise returns
+    /// the original trap.
+    ///
+    /// The code of a trap created with [`host_with_code`] is dropped, read it with
+    /// [`host_code`] first if needed.
+    ///
+    /// Together with [`into_kind`] this allows to convert a trap into an error type
+    /// of the embedder without losing any information:
+    ///
+    /// ```rust
+    /// use wasmi::{HostError, Trap, TrapKind};
+    ///
+    /// #[derive(Debug)]
+    /// enum AppError {
+    ///     Host(Box<dyn HostError>),
+    ///     Exit(i32),
+    ///     Wasm(TrapKind),
+    /// }
+    ///
+    /// impl From<Trap> for AppError {
+    ///     fn from(trap: Trap) -> AppError {
+    ///         match trap.try_into_host_error() {
+    ///             Ok(host_err) => AppError::Host(host_err),
+    ///             Err(trap) => match trap.into_kind() {
+    ///                 TrapKind::Exit(exit_code) => AppError::Exit(exit_code),
+    ///                 kind => AppError::Wasm(kind),
+    ///             },
+    ///         }
+    ///     }
+    /// }
+    ///
+    /// match AppError::from(Trap::exit(3)) {
+    ///     AppError::Exit(3) => {}
+    ///     err => panic!("unexpected error {:?}", err),
+    /// }
+    /// ```
+    ///
+    /// [`HostError`]: trait.HostError.html
+    /// [`into_kind`]: #method.into_kind
+    /// [`host_with_code`]: #method.host_with_code
+    /// [`host_code`]: #method.host_code
+    pub fn try_into_host_error(self) -> Result<Box<dyn host::HostError>, Self> {
+        match self.kind {
+            TrapKind::Host(host_err) => Ok(host_err),
+            TrapKind::HostWithCode { error, .. } => Ok(error),
+            kind => Err(Trap::new(kind)),
+        }
+    }
 }
 
 impl fmt::Display for Trap {
@@ -172,21 +387,69 @@ impl error::Error for Trap {
 ///
 /// See [`Trap`] for details.
 ///
+/// Each limit on the resources of an execution traps with a kind of its own, so the
+/// limit that was hit can be told apart:
+///
+/// - the value stack: [`StackOverflow`],
+/// - the depth of the call stack: [`CallDepthExceeded`],
+/// - the native stack of [`run_on_bounded_stack`]: [`NativeStackExhausted`],
+/// - fuel: [`OutOfFuel`],
+/// - the number of host calls: [`HostCallLimitExceeded`],
+/// - allocating memory: [`MemoryAllocationFailed`], and [`MemoryGrowFailed`] for a
+///   memory that can't grow because of its maximum. Both only for memories that trap
+///   on a failed `grow_memory`.
+///
 /// [`Trap`]: struct.Trap.html
+/// [`StackOverflow`]: #variant.StackOverflow
+/// [`CallDepthExceeded`]: #variant.CallDepthExceeded
+/// [`run_on_bounded_stack`]: fn.run_on_bounded_stack.html
+/// [`NativeStackExhausted`]: #variant.NativeStackExhausted
+/// [`OutOfFuel`]: #variant.OutOfFuel
+/// [`HostCallLimitExceeded`]: #variant.HostCallLimitExceeded
+/// [`MemoryAllocationFailed`]: #variant.MemoryAllocationFailed
+/// [`MemoryGrowFailed`]: #variant.MemoryGrowFailed
 #[derive(Debug)]
 pub enum TrapKind {
     /// Wasm code executed `unreachable` opcode.
     ///
     /// `unreachable` is a special opcode which always traps upon execution.
     /// This opcode have a similar purpose as `ud2` in x86.
-    Unreachable,
+    ///
+    /// Toolchains commonly lower aborts to `unreachable`, e.g. a Rust panic executes it
+    /// after calling the panic handler, so this trap usually means that the wasm code
+    /// gave up deliberately rather than that it went wrong. See [`Trap::unreachable_func_index`]
+    /// for handling it as a controlled abort.
+    ///
+    /// [`Trap::unreachable_func_index`]: struct.Trap.html#method.unreachable_func_index
+    Unreachable {
+        /// Index of the function that executed `unreachable`, in the function index
+        /// space of its module, i.e. imported functions are counted.
+        ///
+        /// `None` if the trap wasn't raised by wasm code, but by the host (e.g. by
+        /// [`NopExternals`] when asked to call a function).
+        ///
+        /// [`NopExternals`]: struct.NopExternals.html
+        func_index: Option<u32>,
+    },
 
     /// Attempt to load or store at the address which
     /// lies outside of bounds of the memory.
     ///
     /// Since addresses are interpreted as unsigned integers, out of bounds access
     /// can't happen with negative addresses (i.e. they will always wrap).
-    MemoryAccessOutOfBounds,
+    ///
+    /// Like [`Error::MemoryOutOfBounds`] for accesses by the host, it records what
+    /// was accessed.
+    ///
+    /// [`Error::MemoryOutOfBounds`]: enum.Error.html#variant.MemoryOutOfBounds
+    MemoryAccessOutOfBounds {
+        /// Effective address of the access, i.e. the address operand plus the static
+        /// offset of the instruction. It is 64-bit since the sum can exceed the 32-bit
+        /// address space.
+        address: u64,
+        /// Number of bytes accessed.
+        len: usize,
+    },
 
     /// Attempt to access table element at index which
     /// lies outside of bounds.
@@ -203,6 +466,62 @@ pub enum TrapKind {
     /// This typically can happen when `call_indirect` is executed.
     ElemUninitialized,
 
+    /// Attempt to grow a memory failed.
+    ///
+    /// By default a failed `grow_memory` returns `-1`, as the spec requires. This trap
+    /// is only raised for memories that opted into it with
+    /// [`MemoryInstance::set_trap_on_grow_failure`], if the memory can't grow by the
+    /// requested number of pages. If it could, but the host couldn't allocate them,
+    /// [`MemoryAllocationFailed`] is raised instead.
+    ///
+    /// [`MemoryInstance::set_trap_on_grow_failure`]: struct.MemoryInstance.html#method.set_trap_on_grow_failure
+    /// [`MemoryAllocationFailed`]: #variant.MemoryAllocationFailed
+    MemoryGrowFailed,
+
+    /// Attempt to grow a memory failed, because the host couldn't allocate its buffer.
+    ///
+    /// Like [`MemoryGrowFailed`], this is only raised for memories that opted into it
+    /// with [`MemoryInstance::set_trap_on_grow_failure`]. Nothing is wrong with the
+    /// module, the host is out of memory.
+    ///
+    /// [`MemoryGrowFailed`]: #variant.MemoryGrowFailed
+    /// [`MemoryInstance::set_trap_on_grow_failure`]: struct.MemoryInstance.html#method.set_trap_on_grow_failure
+    MemoryAllocationFailed,
+
+    /// Metered execution ran out of fuel.
+    ///
+    /// See [`FuncInstance::invoke_with_fuel`].
+    ///
+    /// [`FuncInstance::invoke_with_fuel`]: struct.FuncInstance.html#method.invoke_with_fuel
+    OutOfFuel,
+
+    /// Execution with a limit on host calls was about to call a host function more
+    /// often than allowed.
+    ///
+    /// See [`FuncInstance::invoke_with_host_call_limit`].
+    ///
+    /// [`FuncInstance::invoke_with_host_call_limit`]: struct.FuncInstance.html#method.invoke_with_host_call_limit
+    HostCallLimitExceeded,
+
+    /// Atomic memory access at an address that is not naturally aligned.
+    ///
+    /// Only raised by the atomic instructions, available with the `atomics` feature.
+    UnalignedAtomic,
+
+    /// `i32.atomic.wait` or `i64.atomic.wait` would block forever.
+    ///
+    /// wasmi is single-threaded, so no other thread could ever notify the waiter.
+    /// Waits with a timeout return "timed-out" (`2`) right away instead of trapping.
+    AtomicWaitDeadlock,
+
+    /// `i32.atomic.wait` or `i64.atomic.wait` was executed on a memory that isn't
+    /// shared.
+    ///
+    /// See [`MemoryInstance::alloc_shared`].
+    ///
+    /// [`MemoryInstance::alloc_shared`]: struct.MemoryInstance.html#method.alloc_shared
+    AtomicWaitOnUnsharedMemory,
+
     /// Attempt to divide by zero.
     ///
     /// This trap typically can happen if `div` or `rem` is executed with
@@ -218,40 +537,278 @@ pub enum TrapKind {
     /// - trying to truncate NaNs, infinity, or value for which the result is out of range into an integer.
     InvalidConversionToInt,
 
-    /// Stack overflow.
+    /// Result of an integer arithmetic operation isn't representable.
+    ///
+    /// Wasm arithmetic instructions wrap around instead, so this trap is never
+    /// raised by wasm code itself. It is meant for host functions that provide
+    /// checked arithmetic, so their failures are reported as traps rather than as
+    /// opaque [`Host`] errors. See [`Trap::check_overflow`].
+    ///
+    /// [`Host`]: #variant.Host
+    /// [`Trap::check_overflow`]: struct.Trap.html#method.check_overflow
+    IntegerOverflow,
+
+    /// Stack overflow, i.e. the values of the executing functions exceed the limit of
+    /// the value stack.
     ///
     /// This is likely caused by some infinite or very deep recursion.
     /// Extensive inlining might also be the cause of stack overflow.
+    ///
+    /// Recursion that exceeds the limit on the number of nested calls before filling the
+    /// value stack traps with [`CallDepthExceeded`] instead.
+    ///
+    /// See [`StackRecycler::with_limits`].
+    ///
+    /// [`CallDepthExceeded`]: #variant.CallDepthExceeded
+    /// [`StackRecycler::with_limits`]: struct.StackRecycler.html#method.with_limits
     StackOverflow,
 
+    /// A call would exceed the limit on the number of nested calls.
+    ///
+    /// Like [`StackOverflow`], this is likely caused by some infinite or very deep
+    /// recursion.
+    ///
+    /// See [`StackRecycler::with_limits`].
+    ///
+    /// [`StackOverflow`]: #variant.StackOverflow
+    /// [`StackRecycler::with_limits`]: struct.StackRecycler.html#method.with_limits
+    CallDepthExceeded,
+
+    /// An invocation started with less than [`NATIVE_STACK_RESERVE`] bytes left on the
+    /// native stack of [`run_on_bounded_stack`].
+    ///
+    /// This is caused by deeply nested invocations, of host functions that invoke wasm
+    /// code again, which would overflow the native stack otherwise.
+    ///
+    /// [`NATIVE_STACK_RESERVE`]: constant.NATIVE_STACK_RESERVE.html
+    /// [`run_on_bounded_stack`]: fn.run_on_bounded_stack.html
+    NativeStackExhausted,
+
     /// Attempt to invoke a function with mismatching signature.
     ///
     /// This can happen if [`FuncInstance`] was invoked
     /// with mismatching [signature][`Signature`].
     ///
-    /// This can always happen with indirect calls. `call_indirect` instruction always
-    /// specifies the expected signature of function. If `call_indirect` is executed
-    /// with index that points on function with signature different that is
-    /// expected by this `call_indirect`, this trap is raised.
+    /// Mismatches of indirect calls are reported as [`IndirectCallTypeMismatch`].
     ///
     /// [`Signature`]: struct.Signature.html
+    /// [`IndirectCallTypeMismatch`]: #variant.IndirectCallTypeMismatch
     UnexpectedSignature,
 
+    /// `call_indirect` found a function with another signature than the one it expects.
+    ///
+    /// `call_indirect` instruction always specifies the expected signature of function.
+    /// If it is executed with index that points on function with a different signature,
+    /// this trap is raised. Unlike [`ElemUninitialized`], the table element is set, so
+    /// this usually means that the wrong index was computed or that the table was filled
+    /// with the wrong function.
+    ///
+    /// [`ElemUninitialized`]: #variant.ElemUninitialized
+    IndirectCallTypeMismatch,
+
+    /// A host function returned a value whose type doesn't match the result
+    /// type of its [signature][`Signature`].
+    ///
+    /// This indicates a bug in the implementation of [`Externals`]. The value is
+    /// rejected before it reaches the value stack, whether the host function was
+    /// called from wasm code or invoked directly.
+    ///
+    /// [`Signature`]: struct.Signature.html
+    /// [`Externals`]: trait.Externals.html
+    UnexpectedHostResult {
+        /// Result type declared by the signature of the host function.
+        expected: Option<ValueType>,
+        /// Type of the value returned by the host function.
+        actual: Option<ValueType>,
+    },
+
+    /// A host function was passed an `i32` that isn't a valid encoding of its argument,
+    /// e.g. an unknown enum discriminant or unknown bitflags.
+    ///
+    /// See [`RuntimeValue::as_enum_checked`] and [`RuntimeValue::as_flags_checked`].
+    ///
+    /// [`RuntimeValue::as_enum_checked`]: enum.RuntimeValue.html#method.as_enum_checked
+    /// [`RuntimeValue::as_flags_checked`]: enum.RuntimeValue.html#method.as_flags_checked
+    InvalidArgument {
+        /// The rejected value.
+        value: i32,
+    },
+
     /// Error specified by the host.
     ///
     /// Typically returned from an implementation of [`Externals`].
     ///
     /// [`Externals`]: trait.Externals.html
     Host(Box<dyn host::HostError>),
+
+    /// Error specified by the host, with a machine-readable code.
+    ///
+    /// Created with [`Trap::host_with_code`]. The code lets the embedder tell host
+    /// errors apart, e.g. to decide whether to retry, without downcasting the error.
+    /// Apart from that, it is handled like [`Host`], in particular it leaves the
+    /// invocation resumable.
+    ///
+    /// [`Trap::host_with_code`]: struct.Trap.html#method.host_with_code
+    /// [`Host`]: #variant.Host
+    HostWithCode {
+        /// Code given by the host.
+        code: u32,
+        /// The error of the host.
+        error: Box<dyn host::HostError>,
+    },
+
+    /// Termination of the execution requested by the host with the given exit code.
+    ///
+    /// Typically returned from an implementation of [`Externals`] that models a
+    /// `proc_exit`-style function. This doesn't indicate an error in the wasm code,
+    /// the execution is just stopped. Unlike [`Host`] traps, an invocation that
+    /// was stopped this way can't be resumed.
+    ///
+    /// [`Externals`]: trait.Externals.html
+    /// [`Host`]: #variant.Host
+    Exit(i32),
+
+    /// Call of a stub standing in for an import that couldn't be resolved.
+    ///
+    /// Raised by the stubs of a [`StubResolver`] configured with [`StubBehavior::Trap`].
+    ///
+    /// [`StubResolver`]: struct.StubResolver.html
+    /// [`StubBehavior::Trap`]: enum.StubBehavior.html#variant.Trap
+    UnresolvedImport {
+        /// Field name of the import.
+        field_name: String,
+    },
+
+    /// Call of a function allocated with [`FuncInstance::alloc_late`] before it was
+    /// bound to its target.
+    ///
+    /// [`FuncInstance::alloc_late`]: struct.FuncInstance.html#method.alloc_late
+    UnboundFunction,
 }
 
 impl TrapKind {
     /// Whether this trap is specified by the host.
     pub fn is_host(&self) -> bool {
-        matches!(self, TrapKind::Host(_))
+        matches!(self, TrapKind::Host(_) | TrapKind::HostWithCode { .. })
+    }
+
+    /// Returns the cause of this trap, without the details attached to it.
+    pub fn code(&self) -> TrapCode {
+        match self {
+            TrapKind::Unreachable { .. } => TrapCode::Unreachable,
+            TrapKind::MemoryAccessOutOfBounds { .. } => TrapCode::MemoryAccessOutOfBounds,
+            TrapKind::TableAccessOutOfBounds => TrapCode::TableAccessOutOfBounds,
+            TrapKind::ElemUninitialized => TrapCode::ElemUninitialized,
+            TrapKind::MemoryGrowFailed => TrapCode::MemoryGrowFailed,
+            TrapKind::MemoryAllocationFailed => TrapCode::MemoryAllocationFailed,
+            TrapKind::OutOfFuel => TrapCode::OutOfFuel,
+            TrapKind::HostCallLimitExceeded => TrapCode::HostCallLimitExceeded,
+            TrapKind::UnalignedAtomic => TrapCode::UnalignedAtomic,
+            TrapKind::AtomicWaitDeadlock => TrapCode::AtomicWaitDeadlock,
+            TrapKind::AtomicWaitOnUnsharedMemory => TrapCode::AtomicWaitOnUnsharedMemory,
+            TrapKind::DivisionByZero => TrapCode::DivisionByZero,
+            TrapKind::InvalidConversionToInt => TrapCode::InvalidConversionToInt,
+            TrapKind::IntegerOverflow => TrapCode::IntegerOverflow,
+            TrapKind::StackOverflow => TrapCode::StackOverflow,
+            TrapKind::CallDepthExceeded => TrapCode::CallDepthExceeded,
+            TrapKind::NativeStackExhausted => TrapCode::NativeStackExhausted,
+            TrapKind::UnexpectedSignature => TrapCode::UnexpectedSignature,
+            TrapKind::IndirectCallTypeMismatch => TrapCode::IndirectCallTypeMismatch,
+            TrapKind::UnexpectedHostResult { .. } => TrapCode::UnexpectedHostResult,
+            TrapKind::InvalidArgument { .. } => TrapCode::InvalidArgument,
+            TrapKind::Host(_) | TrapKind::HostWithCode { .. } => TrapCode::Host,
+            TrapKind::Exit(_) => TrapCode::Exit,
+            TrapKind::UnresolvedImport { .. } => TrapCode::UnresolvedImport,
+            TrapKind::UnboundFunction => TrapCode::UnboundFunction,
+        }
     }
 }
 
+/// Cause of a trap, i.e. a [`TrapKind`] without the details attached to it.
+///
+/// Unlike [`TrapKind`], which can hold errors of the host, `TrapCode` is `Copy`, `Eq`,
+/// `Ord` and `Hash`, so it can be used as a key to count traps by their cause.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::collections::BTreeMap;
+/// use wasmi::{Trap, TrapCode, TrapKind};
+///
+/// let traps = vec![
+///     Trap::new(TrapKind::DivisionByZero),
+///     Trap::exit(1),
+///     Trap::new(TrapKind::DivisionByZero),
+/// ];
+///
+/// let mut counts = BTreeMap::new();
+/// for trap in &traps {
+///     *counts.entry(trap.code()).or_insert(0) += 1;
+/// }
+/// assert_eq!(counts[&TrapCode::DivisionByZero], 2);
+/// assert_eq!(counts[&TrapCode::Exit], 1);
+/// ```
+///
+/// [`TrapKind`]: enum.TrapKind.html
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum TrapCode {
+    /// See [`TrapKind::Unreachable`](enum.TrapKind.html#variant.Unreachable).
+    Unreachable,
+    /// See [`TrapKind::MemoryAccessOutOfBounds`](enum.TrapKind.html#variant.MemoryAccessOutOfBounds).
+    MemoryAccessOutOfBounds,
+    /// See [`TrapKind::TableAccessOutOfBounds`](enum.TrapKind.html#variant.TableAccessOutOfBounds).
+    TableAccessOutOfBounds,
+    /// See [`TrapKind::ElemUninitialized`](enum.TrapKind.html#variant.ElemUninitialized).
+    ElemUninitialized,
+    /// See [`TrapKind::MemoryGrowFailed`](enum.TrapKind.html#variant.MemoryGrowFailed).
+    MemoryGrowFailed,
+    /// See [`TrapKind::MemoryAllocationFailed`](enum.TrapKind.html#variant.MemoryAllocationFailed).
+    MemoryAllocationFailed,
+    /// See [`TrapKind::OutOfFuel`](enum.TrapKind.html#variant.OutOfFuel).
+    OutOfFuel,
+    /// See [`TrapKind::HostCallLimitExceeded`](enum.TrapKind.html#variant.HostCallLimitExceeded).
+    HostCallLimitExceeded,
+    /// See [`TrapKind::UnalignedAtomic`](enum.TrapKind.html#variant.UnalignedAtomic).
+    UnalignedAtomic,
+    /// See [`TrapKind::AtomicWaitDeadlock`](enum.TrapKind.html#variant.AtomicWaitDeadlock).
+    AtomicWaitDeadlock,
+    /// See [`TrapKind::AtomicWaitOnUnsharedMemory`](enum.TrapKind.html#variant.AtomicWaitOnUnsharedMemory).
+    AtomicWaitOnUnsharedMemory,
+    /// See [`TrapKind::DivisionByZero`](enum.TrapKind.html#variant.DivisionByZero).
+    DivisionByZero,
+    /// See [`TrapKind::InvalidConversionToInt`](enum.TrapKind.html#variant.InvalidConversionToInt).
+    InvalidConversionToInt,
+    /// See [`TrapKind::IntegerOverflow`](enum.TrapKind.html#variant.IntegerOverflow).
+    IntegerOverflow,
+    /// See [`TrapKind::StackOverflow`](enum.TrapKind.html#variant.StackOverflow).
+    StackOverflow,
+    /// See [`TrapKind::CallDepthExceeded`](enum.TrapKind.html#variant.CallDepthExceeded).
+    CallDepthExceeded,
+    /// See [`TrapKind::NativeStackExhausted`](enum.TrapKind.html#variant.NativeStackExhausted).
+    NativeStackExhausted,
+    /// See [`TrapKind::UnexpectedSignature`](enum.TrapKind.html#variant.UnexpectedSignature).
+    UnexpectedSignature,
+    /// See [`TrapKind::IndirectCallTypeMismatch`](enum.TrapKind.html#variant.IndirectCallTypeMismatch).
+    IndirectCallTypeMismatch,
+    /// See [`TrapKind::UnexpectedHostResult`](enum.TrapKind.html#variant.UnexpectedHostResult).
+    UnexpectedHostResult,
+    /// See [`TrapKind::InvalidArgument`](enum.TrapKind.html#variant.InvalidArgument).
+    InvalidArgument,
+    /// See [`TrapKind::Host`](enum.TrapKind.html#variant.Host).
+    ///
+    /// Also the code of [`TrapKind::HostWithCode`](enum.TrapKind.html#variant.HostWithCode),
+    /// whose code given by the host is returned by [`Trap::host_code`].
+    ///
+    /// [`Trap::host_code`]: struct.Trap.html#method.host_code
+    Host,
+    /// See [`TrapKind::Exit`](enum.TrapKind.html#variant.Exit).
+    Exit,
+    /// See [`TrapKind::UnresolvedImport`](enum.TrapKind.html#variant.UnresolvedImport).
+    UnresolvedImport,
+    /// See [`TrapKind::UnboundFunction`](enum.TrapKind.html#variant.UnboundFunction).
+    UnboundFunction,
+}
+
 /// Internal interpreter error.
 #[derive(Debug)]
 pub enum Error {
@@ -266,6 +823,39 @@ pub enum Error {
     Table(String),
     /// Memory-level error.
     Memory(String),
+    /// Access of a memory region that is out of bounds.
+    MemoryOutOfBounds {
+        /// Offset of the accessed region.
+        offset: usize,
+        /// Length of the accessed region in bytes.
+        len: usize,
+        /// Size of the memory in bytes at the time of the access.
+        memory_size: usize,
+    },
+    /// The buffer of a memory couldn't be allocated, i.e. the host is out of memory.
+    ///
+    /// Nothing is wrong with the module, so the embedder may retry, e.g. after freeing
+    /// memory, or reject the module gracefully.
+    MemoryAllocationFailed {
+        /// Size of the memory that was requested.
+        requested_pages: memory_units::Pages,
+    },
+    /// An active segment doesn't fit the table or memory it initializes.
+    ///
+    /// Instantiation checks all segments before initializing any table or memory, so
+    /// nothing was written when this error is returned.
+    SegmentDoesNotFit {
+        /// Kind of the segment.
+        kind: SegmentKind,
+        /// Index of the segment in its section.
+        index: usize,
+        /// Offset at which the segment starts.
+        offset: u32,
+        /// Length of the segment, in table elements or bytes.
+        len: usize,
+        /// Size of the table or memory, in elements or bytes.
+        size: usize,
+    },
     /// Global-level error.
     Global(String),
     /// Value-level error.
@@ -291,6 +881,9 @@ impl Error {
             Error::Trap(Trap {
                 kind: TrapKind::Host(host_err),
             }) => Some(&**host_err),
+            Error::Trap(Trap {
+                kind: TrapKind::HostWithCode { error, .. },
+            }) => Some(&**error),
             _ => None,
         }
     }
@@ -309,6 +902,9 @@ impl Error {
             Error::Trap(Trap {
                 kind: TrapKind::Host(host_err),
             }) => Some(host_err),
+            Error::Trap(Trap {
+                kind: TrapKind::HostWithCode { error, .. },
+            }) => Some(error),
             _ => None,
         }
     }
@@ -327,6 +923,9 @@ impl Error {
             Error::Trap(Trap {
                 kind: TrapKind::Host(host_err),
             }) => Ok(host_err),
+            Error::Trap(Trap {
+                kind: TrapKind::HostWithCode { error, .. },
+            }) => Ok(error),
             other => Err(other),
         }
     }
@@ -341,6 +940,31 @@ impl Into<String> for Error {
             Error::Function(s) => s,
             Error::Table(s) => s,
             Error::Memory(s) => s,
+            Error::MemoryOutOfBounds {
+                offset,
+                len,
+                memory_size,
+            } => format!(
+                "tried offset {} len {}, memory is {} bytes",
+                offset, len, memory_size
+            ),
+            Error::MemoryAllocationFailed { requested_pages } => {
+                format!("failed to allocate {} pages", requested_pages.0)
+            }
+            Error::SegmentDoesNotFit {
+                kind,
+                index,
+                offset,
+                len,
+                size,
+            } => format!(
+                "{} segment {} of length {} at offset {} does not fit, size is {}",
+                kind.name(),
+                index,
+                len,
+                offset,
+                size
+            ),
             Error::Global(s) => s,
             Error::Value(s) => s,
             Error::Trap(s) => format!("trap: {:?}", s),
@@ -357,6 +981,33 @@ impl fmt::Display for Error {
             Error::Function(ref s) => write!(f, "Function: {}", s),
             Error::Table(ref s) => write!(f, "Table: {}", s),
             Error::Memory(ref s) => write!(f, "Memory: {}", s),
+            Error::MemoryOutOfBounds {
+                offset,
+                len,
+                memory_size,
+            } => write!(
+                f,
+                "Memory: tried offset {} len {}, memory is {} bytes",
+                offset, len, memory_size
+            ),
+            Error::MemoryAllocationFailed { requested_pages } => {
+                write!(f, "Memory: failed to allocate {} pages", requested_pages.0)
+            }
+            Error::SegmentDoesNotFit {
+                kind,
+                index,
+                offset,
+                len,
+                size,
+            } => write!(
+                f,
+                "Instantiation: {} segment {} of length {} at offset {} does not fit, size is {}",
+                kind.name(),
+                index,
+                len,
+                offset,
+                size
+            ),
             Error::Global(ref s) => write!(f, "Global: {}", s),
             Error::Value(ref s) => write!(f, "Value: {}", s),
             Error::Trap(ref s) => write!(f, "Trap: {:?}", s),
@@ -374,6 +1025,9 @@ impl error::Error for Error {
             Error::Function(ref s) => s,
             Error::Table(ref s) => s,
             Error::Memory(ref s) => s,
+            Error::MemoryOutOfBounds { .. } => "Memory access out of bounds",
+            Error::MemoryAllocationFailed { .. } => "Memory allocation failed",
+            Error::SegmentDoesNotFit { .. } => "Segment does not fit",
             Error::Global(ref s) => s,
             Error::Value(ref s) => s,
             Error::Trap(_) => "Trap",
@@ -400,6 +1054,63 @@ where
     }
 }
 
+/// Convert an error that occurred while deserializing a wasm binary.
+///
+/// Binaries using a proposal that isn't supported, e.g. reference types or SIMD, fail
+/// to deserialize with a generic error about an unknown opcode or type. Those errors
+/// are reported as such, naming the proposal and the offending opcode or type.
+fn deserialization_error(e: parity_wasm::elements::Error) -> ValidationError {
+    use parity_wasm::elements::Error::*;
+
+    const REFERENCE_TYPES: &str = "reference types";
+    // The `simd` feature of parity-wasm can't be used to support SIMD instead: it decodes an
+    // early draft of the proposal, whose opcodes differ from the standard ones, e.g. 0x79
+    // instead of 0xae for `i32x4.add`, so it would misinterpret the modules of any toolchain.
+    const SIMD: &str = "SIMD";
+
+    // Types are encoded as negative `i8`s, e.g. `externref` and `funcref` as 0x6f and
+    // 0x70, i.e. -17 and -16.
+    let unsupported_type = |ty: i8| match ty {
+        -17 => Some((REFERENCE_TYPES, "externref")),
+        -16 => Some((REFERENCE_TYPES, "funcref")),
+        -5 => Some((SIMD, "v128")),
+        _ => None,
+    };
+    let unsupported = match e {
+        UnknownValueType(ty) | UnknownTableElementType(ty) => unsupported_type(ty),
+        UnknownOpcode(0xd0) => Some((REFERENCE_TYPES, "ref.null")),
+        UnknownOpcode(0xd1) => Some((REFERENCE_TYPES, "ref.is_null")),
+        UnknownOpcode(0xd2) => Some((REFERENCE_TYPES, "ref.func")),
+        UnknownOpcode(0x25) => Some((REFERENCE_TYPES, "table.get")),
+        UnknownOpcode(0x26) => Some((REFERENCE_TYPES, "table.set")),
+        UnknownOpcode(0x1c) => Some((REFERENCE_TYPES, "typed select")),
+        UnknownOpcode(0xfd) => Some((SIMD, "v128 instructions")),
+        UnknownOpcode(0xc0) => Some(("sign-extension operators", "i32.extend8_s")),
+        UnknownOpcode(0xc1) => Some(("sign-extension operators", "i32.extend16_s")),
+        UnknownOpcode(0xc2) => Some(("sign-extension operators", "i64.extend8_s")),
+        UnknownOpcode(0xc3) => Some(("sign-extension operators", "i64.extend16_s")),
+        UnknownOpcode(0xc4) => Some(("sign-extension operators", "i64.extend32_s")),
+        UnknownOpcode(0xfc) => Some((
+            "bulk memory and non-trapping float-to-int conversions",
+            "0xfc prefixed instructions",
+        )),
+        UnknownOpcode(0xfe) => Some(("threads", "atomic instructions")),
+        UnknownOpcode(0x12) => Some(("tail calls", "return_call")),
+        UnknownOpcode(0x13) => Some(("tail calls", "return_call_indirect")),
+        Other("Enable the multi_value feature to deserialize more than one function result") => {
+            Some(("multi-value", "functions with more than one result"))
+        }
+        _ => None,
+    };
+    match unsupported {
+        Some((proposal, what)) => ValidationError::new(
+            ValidationErrorKind::UnsupportedFeature,
+            format!("{} not supported: module uses `{}`", proposal, what),
+        ),
+        None => ValidationError::new(ValidationErrorKind::MalformedSection, e.to_string()),
+    }
+}
+
 impl From<Trap> for Error {
     fn from(e: Trap) -> Error {
         Error::Trap(e)
@@ -412,39 +1123,97 @@ impl From<TrapKind> for Trap {
     }
 }
 
+impl From<Trap> for TrapKind {
+    fn from(e: Trap) -> TrapKind {
+        e.into_kind()
+    }
+}
+
 impl From<validation::Error> for Error {
     fn from(e: validation::Error) -> Error {
         Error::Validation(e.to_string())
     }
 }
 
+#[cfg(feature = "std")]
+mod bounded_stack;
+mod builder;
+mod cache;
+mod canonical;
+mod debug;
+mod fnv;
 mod func;
 mod global;
 mod host;
 mod imports;
 mod isa;
+#[cfg(feature = "json")]
+mod json;
+mod manifest;
 mod memory;
 mod module;
+mod names;
 pub mod nan_preserving_float;
 mod prepare;
+mod producers;
 mod runner;
+#[cfg(feature = "soft_float")]
+mod soft_float;
+mod store;
+mod stub;
 mod table;
+mod tracing;
 mod types;
+mod validator;
 mod value;
+#[cfg(feature = "wat")]
+mod wat;
 
 #[cfg(test)]
 mod tests;
 
+#[cfg(feature = "std")]
+pub use self::bounded_stack::{run_on_bounded_stack, NATIVE_STACK_RESERVE};
+pub use self::builder::ModuleBuilder;
+pub use self::cache::{CompilationCache, LruCompilationCache};
+pub use self::canonical::canonicalize;
+pub use self::debug::{BreakpointHandler, Breakpoints, CallHook, DebugFrame};
 pub use self::func::{FuncInstance, FuncInvocation, FuncRef, ResumableError};
 pub use self::global::{GlobalInstance, GlobalRef};
-pub use self::host::{Externals, HostError, NopExternals, RuntimeArgs};
+#[doc(hidden)]
+pub use self::host::resolve_host_func;
+pub use self::host::{
+    Externals, HostError, HostFuncResult, HostValueType, NopExternals, RuntimeArgs,
+};
 pub use self::imports::{ImportResolver, ImportsBuilder, ModuleImportResolver};
-pub use self::memory::{MemoryInstance, MemoryRef, LINEAR_MEMORY_PAGE_SIZE};
-pub use self::module::{ExternVal, ModuleInstance, ModuleRef, NotStartedModuleRef};
-pub use self::runner::{StackRecycler, DEFAULT_CALL_STACK_LIMIT, DEFAULT_VALUE_STACK_LIMIT};
+pub use self::isa::{OpcodeKind, ISA_VERSION};
+pub use self::manifest::{ImportManifest, ManifestResolver};
+pub use self::memory::{
+    MemoryAllocator, MemoryInstance, MemoryRef, MemoryWrite, Region, LINEAR_MEMORY_PAGE_SIZE,
+};
+pub use self::module::{ExternVal, ModuleInstance, ModuleRef, NotStartedModuleRef, PreparedCall};
+pub use self::prepare::{
+    Callees, CompileLimits, CompileReport, Diagnostic, FeatureSet, ModuleStats, ValidationSummary,
+    LARGE_LOCALS_THRESHOLD,
+};
+pub use self::producers::{ProducerValue, Producers};
+pub use self::runner::{
+    CallStats, StackRecycler, DEFAULT_CALL_STACK_LIMIT, DEFAULT_VALUE_STACK_LIMIT,
+};
+pub use self::store::{FuncHandle, GlobalHandle, InstanceHandle, MemoryHandle, Store, TableHandle};
+pub use self::stub::{StubBehavior, StubExternals, StubResolver};
 pub use self::table::{TableInstance, TableRef};
-pub use self::types::{GlobalDescriptor, MemoryDescriptor, Signature, TableDescriptor, ValueType};
-pub use self::value::{Error as ValueError, FromRuntimeValue, LittleEndianConvert, RuntimeValue};
+pub use self::tracing::{HostCall, HostCallInterceptor, TracingExternals, TracingResolver};
+pub use self::types::{
+    ExportKind, GlobalDescriptor, MemoryDescriptor, SegmentKind, Signature, TableDescriptor,
+    ValueType,
+};
+pub use self::validator::Validator;
+pub use self::value::{
+    Coercion, Error as ValueError, FromRuntimeValue, I32Enum, IntoRuntimeValues,
+    LittleEndianConvert, RuntimeValue, WordOrder,
+};
+pub use validation::{Error as ValidationError, ErrorKind as ValidationErrorKind};
 
 /// WebAssembly-specific sizes and units.
 pub mod memory_units {
@@ -453,9 +1222,11 @@ pub mod memory_units {
 }
 
 /// Deserialized module prepared for instantiation.
+///
+/// All function bodies are validated and compiled when the module is created, and the
+/// compiled code is shared by all its instances, so no call pays for compilation.
 pub struct Module {
-    code_map: Vec<isa::Instructions>,
-    module: parity_wasm::elements::Module,
+    compiled: prepare::CompiledModule,
 }
 
 impl Module {
@@ -492,79 +1263,962 @@ impl Module {
     /// }
     /// ```
     pub fn from_parity_wasm_module(module: parity_wasm::elements::Module) -> Result<Module, Error> {
-        let prepare::CompiledModule { code_map, module } = prepare::compile_module(module)?;
-
-        Ok(Module { code_map, module })
+        Ok(Module::from_compiled(prepare::compile_module(module)?))
     }
 
-    /// Fail if the module contains any floating-point operations
+    /// Create `Module` from `parity_wasm::elements::Module`, recording the source
+    /// offset of each compiled instruction.
+    ///
+    /// This is the same as [`from_parity_wasm_module`], except that the recorded
+    /// offsets are available through [`source_offset`]. They take memory proportional
+    /// to the size of the compiled code, so they are only recorded on request.
     ///
     /// # Errors
     ///
     /// Returns `Err` if provided `Module` is not valid.
     ///
-    /// # Examples
+    /// [`from_parity_wasm_module`]: #method.from_parity_wasm_module
+    /// [`source_offset`]: #method.source_offset
+    pub fn from_parity_wasm_module_with_source_offsets(
+        module: parity_wasm::elements::Module,
+    ) -> Result<Module, Error> {
+        Ok(Module::from_compiled(
+            prepare::compile_module_with_source_offsets(module)?,
+        ))
+    }
+
+    /// Create `Module` from `parity_wasm::elements::Module`, rejecting it if the
+    /// control flow of any function exceeds `limits`.
     ///
-    /// ```rust
-    /// # extern crate wasmi;
-    /// # extern crate wabt;
+    /// The limits are checked before any function is compiled, which protects the
+    /// compiler from pathological inputs and bounds the depth of the control stack.
     ///
-    /// let wasm_binary: Vec<u8> =
-    ///     wabt::wat2wasm(
-    ///         r#"
-    ///         (module
-    ///          (func $add (param $lhs i32) (param $rhs i32) (result i32)
-    ///                get_local $lhs
-    ///                get_local $rhs
-    ///                i32.add))
-    ///         "#,
-    ///     )
-    ///     .expect("failed to parse wat");
+    /// # Errors
     ///
-    /// // Load wasm binary and prepare it for instantiation.
-    /// let module = wasmi::Module::from_buffer(&wasm_binary).expect("Parsing failed");
-    /// assert!(module.deny_floating_point().is_ok());
+    /// Returns `Err` if provided `Module` is not valid or exceeds `limits`.
     ///
-    /// let wasm_binary: Vec<u8> =
-    ///     wabt::wat2wasm(
-    ///         r#"
-    ///         (module
-    ///          (func $add (param $lhs f32) (param $rhs f32) (result f32)
-    ///                get_local $lhs
-    ///                get_local $rhs
-    ///                f32.add))
-    ///         "#,
-    ///     )
-    ///     .expect("failed to parse wat");
+    /// # Examples
     ///
-    /// let module = wasmi::Module::from_buffer(&wasm_binary).expect("Parsing failed");
-    /// assert!(module.deny_floating_point().is_err());
+    /// ```rust
+    /// # extern crate wasmi;
+    /// # extern crate wabt;
+    /// use wasmi::CompileLimits;
     ///
-    /// let wasm_binary: Vec<u8> =
-    ///     wabt::wat2wasm(
-    ///         r#"
-    ///         (module
-    ///          (func $add (param $lhs f32) (param $rhs f32) (result f32)
-    ///                get_local $lhs))
-    ///         "#,
-    ///     )
-    ///     .expect("failed to parse wat");
+    /// let wasm_binary: Vec<u8> = wabt::wat2wasm(
+    ///     r#"
+    ///     (module
+    ///      (func
+    ///            (block (block (block)))))
+    ///     "#,
+    /// )
+    /// .expect("failed to parse wat");
+    /// let parity_module: parity_wasm::elements::Module =
+    ///     parity_wasm::deserialize_buffer(&wasm_binary).unwrap();
     ///
-    /// let module = wasmi::Module::from_buffer(&wasm_binary).expect("Parsing failed");
-    /// assert!(module.deny_floating_point().is_err());
+    /// let limits = CompileLimits {
+    ///     max_nesting_depth: 2,
+    ///     ..CompileLimits::default()
+    /// };
+    /// assert!(wasmi::Module::from_parity_wasm_module_with_limits(parity_module, &limits).is_err());
     /// ```
-    pub fn deny_floating_point(&self) -> Result<(), Error> {
-        prepare::deny_floating_point(&self.module).map_err(Into::into)
+    pub fn from_parity_wasm_module_with_limits(
+        module: parity_wasm::elements::Module,
+        limits: &CompileLimits,
+    ) -> Result<Module, Error> {
+        Ok(Module::from_compiled(prepare::compile_module_with_limits(
+            module, limits,
+        )?))
     }
 
-    /// Create `Module` from a given buffer.
+    /// Create `Module` from `parity_wasm::elements::Module`, inlining the calls of small
+    /// functions.
     ///
-    /// This function will deserialize wasm module from a given module,
-    /// validate and prepare it for instantiation.
+    /// A function is inlined at its call sites if it doesn't call any function itself,
+    /// doesn't execute `unreachable` and compiles to at most `max_callee_size`
+    /// instructions, counting one per local. Calls of small helpers, such as accessors,
+    /// then don't pay for a call frame, at the cost of duplicating their code.
+    ///
+    /// Inlined calls produce the same results and traps, but they don't count towards
+    /// the call stack limit or the `max_call_depth` of [`CallStats`]. They also execute,
+    /// and are charged fuel for, a slightly different number of instructions.
     ///
     /// # Errors
     ///
-    /// Returns `Err` if wasm binary in provided `buffer` is not valid wasm binary.
+    /// Returns `Err` if provided `Module` is not valid.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # extern crate wasmi;
+    /// # extern crate wabt;
+    /// use wasmi::{ImportsBuilder, ModuleInstance, NopExternals, RuntimeValue};
+    ///
+    /// let wasm_binary: Vec<u8> = wabt::wat2wasm(
+    ///     r#"
+    ///     (module
+    ///      (func $double (param i32) (result i32)
+    ///            (i32.add (get_local 0) (get_local 0)))
+    ///      (func (export "quadruple") (param i32) (result i32)
+    ///            (call $double (call $double (get_local 0)))))
+    ///     "#,
+    /// )
+    /// .expect("failed to parse wat");
+    /// let parity_module = parity_wasm::deserialize_buffer(&wasm_binary).unwrap();
+    ///
+    /// let module = wasmi::Module::from_parity_wasm_module_with_inlining(parity_module, 16)
+    ///     .expect("module should be valid");
+    /// assert!(module.direct_callees(1).unwrap().functions.is_empty());
+    ///
+    /// let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+    ///     .unwrap()
+    ///     .assert_no_start();
+    /// assert_eq!(
+    ///     instance
+    ///         .invoke_export("quadruple", &[RuntimeValue::I32(5)], &mut NopExternals)
+    ///         .unwrap(),
+    ///     Some(RuntimeValue::I32(20)),
+    /// );
+    /// ```
+    ///
+    /// [`CallStats`]: struct.CallStats.html
+    pub fn from_parity_wasm_module_with_inlining(
+        module: parity_wasm::elements::Module,
+        max_callee_size: u32,
+    ) -> Result<Module, Error> {
+        Ok(Module::from_compiled(
+            prepare::compile_module_with_inlining(module, max_callee_size)?,
+        ))
+    }
+
+    /// Create `Module` from `parity_wasm::elements::Module`, collecting advisory
+    /// diagnostics about it.
+    ///
+    /// This is the same as [`from_parity_wasm_module`], except that it also reports
+    /// patterns that are valid but likely to be mistakes, such as unreachable code.
+    /// See [`Diagnostic`] for the full list. Diagnostics never cause a module to be
+    /// rejected.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if provided `Module` is not valid.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # extern crate wasmi;
+    /// # extern crate wabt;
+    /// use wasmi::Diagnostic;
+    ///
+    /// let wasm_binary: Vec<u8> = wabt::wat2wasm(
+    ///     r#"
+    ///     (module
+    ///      (memory 1)
+    ///      (func
+    ///            return
+    ///            unreachable))
+    ///     "#,
+    /// )
+    /// .expect("failed to parse wat");
+    ///
+    /// let parity_module = parity_wasm::deserialize_buffer(&wasm_binary).unwrap();
+    /// let (_module, diagnostics) =
+    ///     wasmi::Module::from_parity_wasm_module_with_diagnostics(parity_module)
+    ///         .expect("module should be valid");
+    /// assert_eq!(
+    ///     diagnostics,
+    ///     vec![
+    ///         Diagnostic::UnboundedMemory,
+    ///         Diagnostic::UnreachableCode { func: 0, instruction: 1 },
+    ///     ],
+    /// );
+    /// ```
+    ///
+    /// [`from_parity_wasm_module`]: #method.from_parity_wasm_module
+    /// [`Diagnostic`]: enum.Diagnostic.html
+    pub fn from_parity_wasm_module_with_diagnostics(
+        module: parity_wasm::elements::Module,
+    ) -> Result<(Module, Vec<Diagnostic>), Error> {
+        let (compiled, diagnostics) = prepare::compile_module_with_diagnostics(module)?;
+        Ok((Module::from_compiled(compiled), diagnostics))
+    }
+
+    /// Validate and compile a parity-wasm module, and report what the compilation observed.
+    ///
+    /// This is the same as [`from_parity_wasm_module_with_diagnostics`], except that the
+    /// report also contains the [`stats`], the [`features_used`] and the maximum stack height
+    /// of each function, all gathered in the same pass instead of by analyzing the module
+    /// again. Use [`from_parity_wasm_module`] if none of it is needed.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if provided `Module` is not valid.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # extern crate wasmi;
+    /// # extern crate wabt;
+    /// let wasm_binary: Vec<u8> = wabt::wat2wasm(
+    ///     r#"
+    ///     (module
+    ///      (func (param i32) (result i32)
+    ///            (i32.add (get_local 0) (i32.const 1)))
+    ///      (func (result f64)
+    ///            (f64.const 1)))
+    ///     "#,
+    /// )
+    /// .expect("failed to parse wat");
+    ///
+    /// let parity_module = parity_wasm::deserialize_buffer(&wasm_binary).unwrap();
+    /// let (_module, report) = wasmi::Module::from_parity_wasm_module_with_report(parity_module)
+    ///     .expect("module should be valid");
+    /// assert_eq!(report.stats.functions, 2);
+    /// assert_eq!(report.max_stack_heights, vec![3, 1]);
+    /// assert!(report.features.floats);
+    /// assert!(report.diagnostics.is_empty());
+    /// ```
+    ///
+    /// [`from_parity_wasm_module_with_diagnostics`]: #method.from_parity_wasm_module_with_diagnostics
+    /// [`from_parity_wasm_module`]: #method.from_parity_wasm_module
+    /// [`stats`]: #method.stats
+    /// [`features_used`]: #method.features_used
+    pub fn from_parity_wasm_module_with_report(
+        module: parity_wasm::elements::Module,
+    ) -> Result<(Module, CompileReport), Error> {
+        let (compiled, report) = prepare::compile_module_with_report(module)?;
+        Ok((Module::from_compiled(compiled), report))
+    }
+
+    fn from_compiled(compiled: prepare::CompiledModule) -> Module {
+        Module { compiled }
+    }
+
+    /// Returns the byte offset in the original wasm binary of the instruction from
+    /// which the compiled instruction at `isa_idx` of the function body `func` originates.
+    ///
+    /// `func` is the index of the body in the code section, i.e. imported functions
+    /// are not counted. The offset is relative to the start of the code section payload,
+    /// which is how DWARF for wasm addresses code.
+    ///
+    /// Returns `None` if the module wasn't created with
+    /// [`from_parity_wasm_module_with_source_offsets`] or if there is no such instruction.
+    ///
+    /// [`from_parity_wasm_module_with_source_offsets`]: #method.from_parity_wasm_module_with_source_offsets
+    pub fn source_offset(&self, func: usize, isa_idx: u32) -> Option<u32> {
+        self.compiled
+            .source_offsets
+            .as_ref()?
+            .get(func)?
+            .get(isa_idx as usize)
+            .cloned()
+    }
+
+    /// Returns the wasm encoding of the body of the function with index `func_idx`,
+    /// as it appears in the code section without the leading body size.
+    ///
+    /// `func_idx` is an index in the function index space, i.e. imported functions
+    /// are counted. Returns `None` for imported functions, which have no body, and
+    /// for out of bounds indices.
+    ///
+    /// The original binary isn't retained, so the body is encoded again. For binaries
+    /// that use the canonical (shortest) LEB128 encodings, which is what toolchains
+    /// emit, the result is the same as the original bytes.
+    pub fn function_body_bytes(&self, func_idx: u32) -> Option<Vec<u8>> {
+        let imported_funcs = self
+            .compiled
+            .module
+            .import_count(parity_wasm::elements::ImportCountType::Function);
+        let body_idx = (func_idx as usize).checked_sub(imported_funcs)?;
+        let body = self
+            .compiled
+            .module
+            .code_section()?
+            .bodies()
+            .get(body_idx)?;
+        Some(prepare::encode_func_body(body).expect("a decoded function body can be encoded again"))
+    }
+
+    /// Returns a fingerprint of the interface of this module, i.e. of the names, kinds
+    /// and types of its imports and exports.
+    ///
+    /// Two modules with the same imports and exports have the same fingerprint, no
+    /// matter their code, internal definitions or the order of their entries. This can
+    /// be used to check that a new version of a module honors the contract of the old
+    /// one before swapping it in. The limits of imported and exported memories and
+    /// tables are part of the interface.
+    ///
+    /// The fingerprint is 64-bit FNV-1a, which is stable but not collision resistant.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # extern crate wasmi;
+    /// # extern crate wabt;
+    /// let load = |wat: &str| {
+    ///     wasmi::Module::from_buffer(wabt::wat2wasm(wat).unwrap()).unwrap()
+    /// };
+    /// let old = load(r#"(module (func (export "answer") (result i32) (i32.const 41)))"#);
+    /// let new = load(r#"(module (func (export "answer") (result i32) (i32.const 42)))"#);
+    /// let incompatible = load(r#"(module (func (export "answer") (result i64) (i64.const 42)))"#);
+    ///
+    /// assert_eq!(old.interface_fingerprint(), new.interface_fingerprint());
+    /// assert_ne!(old.interface_fingerprint(), incompatible.interface_fingerprint());
+    /// ```
+    pub fn interface_fingerprint(&self) -> u64 {
+        self.compiled.interface_fingerprint()
+    }
+
+    /// Returns the functions that the function with index `func_idx` calls.
+    ///
+    /// Calls are read from the compiled code of the function. This can be used to build
+    /// a call graph of the module, e.g. to find unreachable functions or recursion before
+    /// running any code.
+    ///
+    /// `func_idx` is an index in the function index space, i.e. imported functions
+    /// are counted. Returns `None` for imported functions and for out of bounds indices.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # extern crate wasmi;
+    /// # extern crate wabt;
+    /// use wasmi::Callees;
+    ///
+    /// let wasm_binary: Vec<u8> =
+    ///     wabt::wat2wasm(
+    ///         r#"
+    ///         (module
+    ///          (import "env" "log" (func $log))
+    ///          (type $t (func))
+    ///          (table 1 anyfunc)
+    ///          (func $f (call $log) (call $f) (call $log))
+    ///          (func (call $f) (call_indirect (type $t) (i32.const 0))))
+    ///         "#,
+    ///     )
+    ///     .expect("failed to parse wat");
+    ///
+    /// let module = wasmi::Module::from_buffer(&wasm_binary).expect("Parsing failed");
+    /// assert_eq!(module.direct_callees(0), None);
+    /// assert_eq!(
+    ///     module.direct_callees(1),
+    ///     Some(Callees { functions: vec![0, 1], calls_indirect: false }),
+    /// );
+    /// assert_eq!(
+    ///     module.direct_callees(2),
+    ///     Some(Callees { functions: vec![1], calls_indirect: true }),
+    /// );
+    /// ```
+    pub fn direct_callees(&self, func_idx: u32) -> Option<Callees> {
+        self.compiled.direct_callees(func_idx)
+    }
+
+    /// Returns an upper bound of the number of bytes the function with index `func_idx`
+    /// takes up on the value stack, i.e. its arguments, locals and operands.
+    ///
+    /// The bound is computed during validation, which tracks the height of the operand
+    /// stack at every instruction. Functions it calls take up their own space on top of
+    /// this, so the bound is per frame, not for a whole invocation. It can be used to
+    /// size the value stack of a [`StackRecycler`] or to reject modules with functions
+    /// that could use too much of it.
+    ///
+    /// `func_idx` is an index in the function index space, i.e. imported functions
+    /// are counted. Returns `None` for imported functions and for out of bounds indices.
+    ///
+    /// [`StackRecycler`]: struct.StackRecycler.html
+    pub fn max_stack_bytes(&self, func_idx: u32) -> Option<usize> {
+        let imported_funcs = self
+            .compiled
+            .module
+            .import_count(parity_wasm::elements::ImportCountType::Function);
+        let body_idx = (func_idx as usize).checked_sub(imported_funcs)?;
+        let code = self.compiled.code_map.get(body_idx)?;
+        Some(code.max_stack_height() as usize * runner::VALUE_STACK_CELL_SIZE)
+    }
+
+    /// Returns a breakdown of the size of the module, e.g. to give feedback on what
+    /// takes up space.
+    ///
+    /// The function bodies are encoded again to measure them, see
+    /// [`function_body_bytes`].
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # extern crate wasmi;
+    /// # extern crate wabt;
+    /// use wasmi::memory_units::Pages;
+    ///
+    /// let wasm_binary: Vec<u8> =
+    ///     wabt::wat2wasm(
+    ///         r#"
+    ///         (module
+    ///          (memory 2)
+    ///          (data (i32.const 0) "hello")
+    ///          (func (export "main")))
+    ///         "#,
+    ///     )
+    ///     .expect("failed to parse wat");
+    ///
+    /// let module = wasmi::Module::from_buffer(&wasm_binary).expect("Parsing failed");
+    /// let stats = module.stats();
+    /// assert_eq!(stats.functions, 1);
+    /// assert_eq!(stats.data_bytes, 5);
+    /// assert_eq!(stats.memory_pages, Pages(2));
+    /// ```
+    ///
+    /// [`function_body_bytes`]: #method.function_body_bytes
+    pub fn stats(&self) -> ModuleStats {
+        prepare::module_stats(&self.compiled.module, &self.compiled.code_map)
+    }
+
+    /// Returns the WebAssembly proposals the module relies on.
+    ///
+    /// This allows to pick an execution mode for the module, or to reject it with a
+    /// precise message, before it is instantiated. Proposals that wasmi doesn't support
+    /// are never reported, because modules using them fail to load in the first place.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// extern crate wabt;
+    /// extern crate wasmi;
+    ///
+    /// use wasmi::{FeatureSet, Module};
+    ///
+    /// fn main() {
+    ///     let wasm = wabt::wat2wasm(
+    ///         r#"
+    ///         (module
+    ///             (global (export "g") (mut i32) (i32.const 0))
+    ///             (func (param f64)))
+    ///         "#,
+    ///     )
+    ///     .unwrap();
+    ///
+    ///     let features = Module::from_buffer(&wasm).unwrap().features_used();
+    ///     assert_eq!(
+    ///         features,
+    ///         FeatureSet {
+    ///             floats: true,
+    ///             mutable_globals: true,
+    ///             ..FeatureSet::default()
+    ///         }
+    ///     );
+    /// }
+    /// ```
+    pub fn features_used(&self) -> FeatureSet {
+        self.compiled.features_used()
+    }
+
+    /// Returns the number of compiled instructions of each [`OpcodeKind`] across all
+    /// function bodies.
+    ///
+    /// The instructions are counted as compiled, not as written in wasm: structured
+    /// control flow is lowered to branches, and some sequences are fused into
+    /// superinstructions. Kinds that don't occur are missing from the histogram.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// extern crate wabt;
+    /// extern crate wasmi;
+    ///
+    /// use wasmi::{Module, OpcodeKind};
+    ///
+    /// fn main() {
+    ///     let wasm = wabt::wat2wasm(
+    ///         r#"
+    ///         (module
+    ///             (memory 1)
+    ///             (func (param i32) (result i32)
+    ///                 (i32.load (get_local 0))))
+    ///         "#,
+    ///     )
+    ///     .unwrap();
+    ///
+    ///     let histogram = Module::from_buffer(&wasm).unwrap().opcode_histogram();
+    ///     assert_eq!(histogram[&OpcodeKind::Load], 1);
+    ///     assert_eq!(histogram.get(&OpcodeKind::Store), None);
+    /// }
+    /// ```
+    ///
+    /// [`OpcodeKind`]: enum.OpcodeKind.html
+    pub fn opcode_histogram(&self) -> BTreeMap<OpcodeKind, u64> {
+        self.compiled.opcode_histogram()
+    }
+
+    /// Fail if the module contains any floating-point operations
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if provided `Module` is not valid.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # extern crate wasmi;
+    /// # extern crate wabt;
+    ///
+    /// let wasm_binary: Vec<u8> =
+    ///     wabt::wat2wasm(
+    ///         r#"
+    ///         (module
+    ///          (func $add (param $lhs i32) (param $rhs i32) (result i32)
+    ///                get_local $lhs
+    ///                get_local $rhs
+    ///                i32.add))
+    ///         "#,
+    ///     )
+    ///     .expect("failed to parse wat");
+    ///
+    /// // Load wasm binary and prepare it for instantiation.
+    /// let module = wasmi::Module::from_buffer(&wasm_binary).expect("Parsing failed");
+    /// assert!(module.deny_floating_point().is_ok());
+    ///
+    /// let wasm_binary: Vec<u8> =
+    ///     wabt::wat2wasm(
+    ///         r#"
+    ///         (module
+    ///          (func $add (param $lhs f32) (param $rhs f32) (result f32)
+    ///                get_local $lhs
+    ///                get_local $rhs
+    ///                f32.add))
+    ///         "#,
+    ///     )
+    ///     .expect("failed to parse wat");
+    ///
+    /// let module = wasmi::Module::from_buffer(&wasm_binary).expect("Parsing failed");
+    /// assert!(module.deny_floating_point().is_err());
+    ///
+    /// let wasm_binary: Vec<u8> =
+    ///     wabt::wat2wasm(
+    ///         r#"
+    ///         (module
+    ///          (func $add (param $lhs f32) (param $rhs f32) (result f32)
+    ///                get_local $lhs))
+    ///         "#,
+    ///     )
+    ///     .expect("failed to parse wat");
+    ///
+    /// let module = wasmi::Module::from_buffer(&wasm_binary).expect("Parsing failed");
+    /// assert!(module.deny_floating_point().is_err());
+    /// ```
+    pub fn deny_floating_point(&self) -> Result<(), Error> {
+        prepare::deny_floating_point(&self.compiled.module).map_err(Into::into)
+    }
+
+    /// Fail if any function of the module uses the `memory.grow` instruction.
+    ///
+    /// A module that passes this check can only ever use the initial size of its
+    /// memory, at least unless the memory is shared with other modules or the host.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` naming the index of the first function that uses `memory.grow`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # extern crate wasmi;
+    /// # extern crate wabt;
+    ///
+    /// let wasm_binary: Vec<u8> =
+    ///     wabt::wat2wasm(
+    ///         r#"
+    ///         (module
+    ///          (memory 1)
+    ///          (func (result i32)
+    ///                current_memory))
+    ///         "#,
+    ///     )
+    ///     .expect("failed to parse wat");
+    ///
+    /// let module = wasmi::Module::from_buffer(&wasm_binary).expect("Parsing failed");
+    /// assert!(module.deny_memory_grow().is_ok());
+    ///
+    /// let wasm_binary: Vec<u8> =
+    ///     wabt::wat2wasm(
+    ///         r#"
+    ///         (module
+    ///          (memory 1)
+    ///          (func (result i32)
+    ///                i32.const 1
+    ///                grow_memory))
+    ///         "#,
+    ///     )
+    ///     .expect("failed to parse wat");
+    ///
+    /// let module = wasmi::Module::from_buffer(&wasm_binary).expect("Parsing failed");
+    /// let err = module.deny_memory_grow().unwrap_err();
+    /// assert!(err.to_string().contains("function 0"));
+    /// ```
+    pub fn deny_memory_grow(&self) -> Result<(), Error> {
+        prepare::deny_memory_grow(&self.compiled.module).map_err(Into::into)
+    }
+
+    /// Fail if the module uses the `call_indirect` instruction.
+    ///
+    /// Direct calls are still allowed, so all the calls a module can make are known
+    /// from its code. Together with other checks such as [`deny_memory_grow`], this
+    /// allows to lock down untrusted modules.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` naming the index of the first function that uses `call_indirect`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # extern crate wasmi;
+    /// # extern crate wabt;
+    ///
+    /// let wasm_binary: Vec<u8> =
+    ///     wabt::wat2wasm(
+    ///         r#"
+    ///         (module
+    ///          (func $f)
+    ///          (func (call $f)))
+    ///         "#,
+    ///     )
+    ///     .expect("failed to parse wat");
+    ///
+    /// let module = wasmi::Module::from_buffer(&wasm_binary).expect("Parsing failed");
+    /// assert!(module.deny_indirect_calls().is_ok());
+    ///
+    /// let wasm_binary: Vec<u8> =
+    ///     wabt::wat2wasm(
+    ///         r#"
+    ///         (module
+    ///          (type $t (func))
+    ///          (table 1 anyfunc)
+    ///          (func (call_indirect (type $t) (i32.const 0))))
+    ///         "#,
+    ///     )
+    ///     .expect("failed to parse wat");
+    ///
+    /// let module = wasmi::Module::from_buffer(&wasm_binary).expect("Parsing failed");
+    /// let err = module.deny_indirect_calls().unwrap_err();
+    /// assert!(err.to_string().contains("function 0"));
+    /// ```
+    ///
+    /// [`deny_memory_grow`]: #method.deny_memory_grow
+    pub fn deny_indirect_calls(&self) -> Result<(), Error> {
+        prepare::deny_indirect_calls(&self.compiled.module).map_err(Into::into)
+    }
+
+    /// Fail if the module doesn't export an entity of the given kind under each of
+    /// the `required` names.
+    ///
+    /// This lets an embedder check the contract of a module, such as exporting its
+    /// memory, before instantiating it. Other exports are allowed.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` naming the first required export that is missing or is of
+    /// another kind.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # extern crate wasmi;
+    /// # extern crate wabt;
+    /// use wasmi::ExportKind;
+    ///
+    /// let wasm_binary: Vec<u8> =
+    ///     wabt::wat2wasm(
+    ///         r#"
+    ///         (module
+    ///          (memory (export "memory") 1)
+    ///          (func (export "main")))
+    ///         "#,
+    ///     )
+    ///     .expect("failed to parse wat");
+    ///
+    /// let module = wasmi::Module::from_buffer(&wasm_binary).expect("Parsing failed");
+    /// assert!(module.require_exports(&[(ExportKind::Memory, "memory")]).is_ok());
+    ///
+    /// let err = module
+    ///     .require_exports(&[(ExportKind::Memory, "memory"), (ExportKind::Memory, "main")])
+    ///     .unwrap_err();
+    /// assert!(err.to_string().contains("memory `main`"));
+    /// ```
+    pub fn require_exports(&self, required: &[(ExportKind, &str)]) -> Result<(), Error> {
+        prepare::require_exports(&self.compiled.module, required).map_err(Into::into)
+    }
+
+    /// Fail if the module exports any entity of the `denied` kinds.
+    ///
+    /// This lets an embedder keep the memory and tables of an instance private, so
+    /// they can't be aliased by other instances that import them, while still
+    /// allowing exports of other kinds.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` naming the first export of a denied kind.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # extern crate wasmi;
+    /// # extern crate wabt;
+    /// use wasmi::ExportKind;
+    ///
+    /// let wasm_binary: Vec<u8> =
+    ///     wabt::wat2wasm(
+    ///         r#"
+    ///         (module
+    ///          (memory (export "memory") 1)
+    ///          (func (export "main")))
+    ///         "#,
+    ///     )
+    ///     .expect("failed to parse wat");
+    ///
+    /// let module = wasmi::Module::from_buffer(&wasm_binary).expect("Parsing failed");
+    /// assert!(module.deny_exports(&[ExportKind::Table]).is_ok());
+    ///
+    /// let err = module
+    ///     .deny_exports(&[ExportKind::Memory, ExportKind::Table])
+    ///     .unwrap_err();
+    /// assert!(err.to_string().contains("memory `memory`"));
+    /// ```
+    pub fn deny_exports(&self, denied: &[ExportKind]) -> Result<(), Error> {
+        prepare::deny_exports(&self.compiled.module, denied).map_err(Into::into)
+    }
+
+    /// Fail if the module defines a function that can never be called.
+    ///
+    /// A function is reachable if it is exported, is the start function or is placed
+    /// in a table by an element segment, or if it is called with `call` by a reachable
+    /// function, see [`direct_callees`]. Unused functions are common in debug builds,
+    /// but in a release build they can be a sign of a bloated or tampered module.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` naming the first function that isn't reachable.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # extern crate wasmi;
+    /// # extern crate wabt;
+    /// let wasm_binary: Vec<u8> =
+    ///     wabt::wat2wasm(
+    ///         r#"
+    ///         (module
+    ///          (func $helper)
+    ///          (func $unused)
+    ///          (func (export "main") (call $helper)))
+    ///         "#,
+    ///     )
+    ///     .expect("failed to parse wat");
+    ///
+    /// let module = wasmi::Module::from_buffer(&wasm_binary).expect("Parsing failed");
+    /// let err = module.deny_unreachable_functions().unwrap_err();
+    /// assert!(err.to_string().contains("Function 1 "));
+    /// ```
+    ///
+    /// [`direct_callees`]: #method.direct_callees
+    pub fn deny_unreachable_functions(&self) -> Result<(), Error> {
+        self.compiled
+            .deny_unreachable_functions()
+            .map_err(Into::into)
+    }
+
+    /// Fail if any active data segment with a constant offset doesn't fit into
+    /// the initial size of the memory defined by the module.
+    ///
+    /// Segments with offsets given by an imported global and segments that target
+    /// an imported memory are left to be checked at instantiation time.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if a data segment is out of bounds of the declared memory.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # extern crate wasmi;
+    /// # extern crate wabt;
+    ///
+    /// let wasm_binary: Vec<u8> =
+    ///     wabt::wat2wasm(
+    ///         r#"
+    ///         (module
+    ///          (memory 1)
+    ///          (data (i32.const 65532) "\01\02\03\04"))
+    ///         "#,
+    ///     )
+    ///     .expect("failed to parse wat");
+    ///
+    /// let module = wasmi::Module::from_buffer(&wasm_binary).expect("Parsing failed");
+    /// assert!(module.validate_data_segment_bounds().is_ok());
+    ///
+    /// let wasm_binary: Vec<u8> =
+    ///     wabt::wat2wasm(
+    ///         r#"
+    ///         (module
+    ///          (memory 1)
+    ///          (data (i32.const 65533) "\01\02\03\04"))
+    ///         "#,
+    ///     )
+    ///     .expect("failed to parse wat");
+    ///
+    /// let module = wasmi::Module::from_buffer(&wasm_binary).expect("Parsing failed");
+    /// assert!(module.validate_data_segment_bounds().is_err());
+    /// ```
+    pub fn validate_data_segment_bounds(&self) -> Result<(), Error> {
+        prepare::validate_data_segment_bounds(&self.compiled.module).map_err(Into::into)
+    }
+
+    /// Returns the smallest size of the default memory the module can be instantiated
+    /// with.
+    ///
+    /// This is the declared minimum of the memory, imported or defined, or more if the
+    /// module has data segments that reach further. Only segments with offsets given by
+    /// an `i32.const` are considered, others are left to be checked at instantiation
+    /// time. Modules without a memory require no pages.
+    ///
+    /// It can be used to size a memory shared with the module before instantiating it,
+    /// or, compared with the declared minimum, to find data segments that don't fit.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # extern crate wasmi;
+    /// # extern crate wabt;
+    /// use wasmi::memory_units::Pages;
+    ///
+    /// let wasm_binary: Vec<u8> =
+    ///     wabt::wat2wasm(
+    ///         r#"
+    ///         (module
+    ///          (import "env" "memory" (memory 1))
+    ///          (data (i32.const 65535) "\01\02"))
+    ///         "#,
+    ///     )
+    ///     .expect("failed to parse wat");
+    ///
+    /// let module = wasmi::Module::from_buffer(&wasm_binary).expect("Parsing failed");
+    /// assert_eq!(module.min_required_pages(), Pages(2));
+    /// ```
+    pub fn min_required_pages(&self) -> memory_units::Pages {
+        prepare::min_required_pages(&self.compiled.module)
+    }
+
+    /// Returns the contents of the `producers` custom section, which records the
+    /// toolchain that produced the module.
+    ///
+    /// Returns `None` if the module has no such section, or if it is malformed. Custom
+    /// sections are not validated, so a malformed one doesn't make the module invalid.
+    pub fn producers(&self) -> Option<Producers> {
+        producers::producers(&self.compiled.module).ok().flatten()
+    }
+
+    /// Fail unless the `producers` section lists `field`, e.g. `processed-by`, with
+    /// only values named in `allowed`, regardless of their versions.
+    ///
+    /// This lets an embedder only accept modules built by an approved toolchain. Keep
+    /// in mind that the section is written by the toolchain itself, so it can't be
+    /// trusted to be accurate for modules from untrusted sources.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if the section or the field is missing, if the section is
+    /// malformed, or naming the first value that isn't allowed.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # extern crate parity_wasm;
+    /// # extern crate wasmi;
+    /// use parity_wasm::elements::{CustomSection, Section};
+    ///
+    /// // processed-by: rustc 1.70.0
+    /// let payload = b"\x01\x0cprocessed-by\x01\x05rustc\x061.70.0".to_vec();
+    /// let mut parity_module = parity_wasm::builder::module().build();
+    /// parity_module
+    ///     .sections_mut()
+    ///     .push(Section::Custom(CustomSection::new("producers".into(), payload)));
+    ///
+    /// let module = wasmi::Module::from_parity_wasm_module(parity_module).unwrap();
+    /// assert_eq!(module.producers().unwrap().field("processed-by")[0].version, "1.70.0");
+    /// assert!(module.require_producer("processed-by", &["rustc", "clang"]).is_ok());
+    /// assert!(module.require_producer("processed-by", &["clang"]).is_err());
+    /// assert!(module.require_producer("language", &["Rust"]).is_err());
+    /// ```
+    pub fn require_producer(&self, field: &str, allowed: &[&str]) -> Result<(), Error> {
+        producers::require_producer(&self.compiled.module, field, allowed)
+    }
+
+    /// Returns the payload of the DWARF custom section `name`, e.g. `.debug_info` or
+    /// `.debug_line`, to feed a debugger.
+    ///
+    /// Together with [`source_offset`], which gives the code offsets DWARF addresses
+    /// code with, this maps a trap back to a source location.
+    ///
+    /// Returns `None` if `name` isn't a `.debug_*` name or if the module has no such
+    /// section.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # extern crate parity_wasm;
+    /// # extern crate wasmi;
+    /// use parity_wasm::elements::{CustomSection, Section};
+    ///
+    /// let mut parity_module = parity_wasm::builder::module().build();
+    /// parity_module
+    ///     .sections_mut()
+    ///     .push(Section::Custom(CustomSection::new(".debug_line".into(), vec![1, 2, 3])));
+    ///
+    /// let module = wasmi::Module::from_parity_wasm_module(parity_module).unwrap();
+    /// assert_eq!(module.debug_section(".debug_line"), Some(&[1, 2, 3][..]));
+    /// assert_eq!(module.debug_section(".debug_info"), None);
+    /// ```
+    ///
+    /// [`source_offset`]: #method.source_offset
+    pub fn debug_section(&self, name: &str) -> Option<&[u8]> {
+        self.compiled.debug_section(name)
+    }
+
+    /// Returns the name the [`name` section] gives to the module, e.g. the `$name` of
+    /// `(module $name ...)` if the binary was built with debug names.
+    ///
+    /// This tells apart the functions of linked modules in diagnostics, which refer to
+    /// functions by their index otherwise. Returns `None` if the module has no name,
+    /// or if the `name` section is malformed, since it doesn't affect validity.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # extern crate parity_wasm;
+    /// # extern crate wasmi;
+    /// use parity_wasm::elements::{CustomSection, Section};
+    ///
+    /// // The module name subsection, of id 0, holding the name `app`.
+    /// let payload = vec![0, 4, 3, b'a', b'p', b'p'];
+    /// let mut parity_module = parity_wasm::builder::module().build();
+    /// parity_module
+    ///     .sections_mut()
+    ///     .push(Section::Custom(CustomSection::new("name".into(), payload)));
+    ///
+    /// let module = wasmi::Module::from_parity_wasm_module(parity_module).unwrap();
+    /// assert_eq!(module.module_name(), Some("app"));
+    /// ```
+    ///
+    /// [`name` section]: https://webassembly.github.io/spec/core/appendix/custom.html#name-section
+    pub fn module_name(&self) -> Option<&str> {
+        self.compiled.module_name()
+    }
+
+    /// Create `Module` from a given buffer.
+    ///
+    /// This function will deserialize wasm module from a given module,
+    /// validate and prepare it for instantiation.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if wasm binary in provided `buffer` is not valid wasm binary.
     ///
     /// # Examples
     ///
@@ -585,15 +2239,182 @@ impl Module {
     /// ```
     pub fn from_buffer<B: AsRef<[u8]>>(buffer: B) -> Result<Module, Error> {
         let module = parity_wasm::elements::deserialize_buffer(buffer.as_ref())
-            .map_err(|e: parity_wasm::elements::Error| Error::Validation(e.to_string()))?;
+            .map_err(deserialization_error)?;
         Module::from_parity_wasm_module(module)
     }
 
+    /// Validate the wasm binary in `buffer`, without compiling it.
+    ///
+    /// Unlike [`from_buffer`], this returns the [`ValidationError`] itself, which
+    /// tells the category of the violated rule and, for errors in function bodies,
+    /// the index of the function body and of the instruction. See [`validate_only`]
+    /// for a summary of the module.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// extern crate wabt;
+    /// extern crate wasmi;
+    ///
+    /// use wasmi::{Module, ValidationErrorKind};
+    ///
+    /// fn main() {
+    ///     let wasm = wabt::Wat2Wasm::new()
+    ///         .validate(false)
+    ///         .convert(
+    ///             r#"
+    ///             (module
+    ///                 (func (result i32)
+    ///                     i64.const 1))
+    ///             "#,
+    ///         )
+    ///         .unwrap();
+    ///
+    ///     let error = Module::validate_buffer(&wasm).unwrap_err();
+    ///     assert_eq!(error.kind(), ValidationErrorKind::TypeMismatch);
+    ///     assert_eq!(error.func_body(), Some(0));
+    ///     assert_eq!(error.instruction(), Some(1));
+    /// }
+    /// ```
+    ///
+    /// [`from_buffer`]: #method.from_buffer
+    /// [`ValidationError`]: struct.ValidationError.html
+    /// [`validate_only`]: #method.validate_only
+    pub fn validate_buffer<B: AsRef<[u8]>>(buffer: B) -> Result<(), ValidationError> {
+        let module = parity_wasm::elements::deserialize_buffer(buffer.as_ref())
+            .map_err(deserialization_error)?;
+        prepare::validate_only(&module).map(|_| ())
+    }
+
+    /// Validate `module` without compiling it, and return a [`ValidationSummary`] of it.
+    ///
+    /// This performs the same validation as [`from_parity_wasm_module`], plus its limit
+    /// on the number of instructions, but not the checks that are part of compiling, so
+    /// compiling can still fail for a module this accepts. Compiling the function bodies
+    /// takes most of the time of loading a module, so this is the cheaper check if the
+    /// module is compiled in a separate step, if at all.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if the module isn't valid, see [`validate_buffer`].
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// extern crate parity_wasm;
+    /// extern crate wabt;
+    /// extern crate wasmi;
+    ///
+    /// use wasmi::Module;
+    ///
+    /// fn main() {
+    ///     let wasm = wabt::wat2wasm(
+    ///         r#"
+    ///         (module
+    ///             (import "env" "log" (func (param i32)))
+    ///             (func (export "run") (call 0 (i32.const 42))))
+    ///         "#,
+    ///     )
+    ///     .unwrap();
+    ///     let module = parity_wasm::deserialize_buffer(&wasm).unwrap();
+    ///
+    ///     let summary = Module::validate_only(&module).unwrap();
+    ///     assert_eq!(summary.functions, 1);
+    ///     assert_eq!(summary.imported_functions, 1);
+    ///     assert_eq!(summary.exports, 1);
+    ///     // `i32.const`, `call` and `end`.
+    ///     assert_eq!(summary.instructions, 3);
+    /// }
+    /// ```
+    ///
+    /// [`ValidationSummary`]: struct.ValidationSummary.html
+    /// [`from_parity_wasm_module`]: #method.from_parity_wasm_module
+    /// [`validate_buffer`]: #method.validate_buffer
+    pub fn validate_only(
+        module: &parity_wasm::elements::Module,
+    ) -> Result<ValidationSummary, ValidationError> {
+        prepare::validate_only(module)
+    }
+
+    /// Returns the exports of this module.
+    ///
+    /// The exports are yielded in the order they are defined in the export section.
+    /// This order is stable: it only depends on the module, which makes it suitable
+    /// for reproducibly hashing the interface of a module.
+    pub fn exports(&self) -> impl Iterator<Item = &parity_wasm::elements::ExportEntry> {
+        self.compiled
+            .module
+            .export_section()
+            .map_or(&[][..], |section| section.entries())
+            .iter()
+    }
+
+    /// Returns the imports of this module.
+    ///
+    /// The imports are yielded in the order they are defined in the import section,
+    /// with the same stability guarantee as [`exports`].
+    ///
+    /// [`exports`]: #method.exports
+    pub fn imports(&self) -> impl Iterator<Item = &parity_wasm::elements::ImportEntry> {
+        self.compiled
+            .module
+            .import_section()
+            .map_or(&[][..], |section| section.entries())
+            .iter()
+    }
+
+    /// Load a module from a given buffer through a [`CompilationCache`].
+    ///
+    /// If `cache` has a module compiled from the same bytes it is returned without
+    /// compiling `buffer` again. Otherwise the module is created with [`from_buffer`]
+    /// and put into `cache`.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`from_buffer`].
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use std::rc::Rc;
+    /// use wasmi::{LruCompilationCache, Module};
+    ///
+    /// let cache = LruCompilationCache::new(16);
+    /// let wasm_binary = [0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00];
+    ///
+    /// let first = Module::from_buffer_cached(&wasm_binary, &cache).unwrap();
+    /// let second = Module::from_buffer_cached(&wasm_binary, &cache).unwrap();
+    /// assert!(Rc::ptr_eq(&first, &second));
+    /// ```
+    ///
+    /// [`CompilationCache`]: trait.CompilationCache.html
+    /// [`from_buffer`]: #method.from_buffer
+    pub fn from_buffer_cached<B: AsRef<[u8]>, C: CompilationCache + ?Sized>(
+        buffer: B,
+        cache: &C,
+    ) -> Result<Rc<Module>, Error> {
+        cache::from_buffer_cached(buffer.as_ref(), cache)
+    }
+
+    /// Returns the content hash of the wasm binary `buffer`, by which modules are
+    /// keyed in a [`CompilationCache`].
+    ///
+    /// The hash is 64-bit FNV-1a, which is stable but not collision resistant.
+    ///
+    /// [`CompilationCache`]: trait.CompilationCache.html
+    pub fn content_hash<B: AsRef<[u8]>>(buffer: B) -> u64 {
+        cache::content_hash(buffer.as_ref())
+    }
+
     pub(crate) fn module(&self) -> &parity_wasm::elements::Module {
-        &self.module
+        &self.compiled.module
     }
 
     pub(crate) fn code(&self) -> &Vec<isa::Instructions> {
-        &self.code_map
+        &self.compiled.code_map
+    }
+
+    pub(crate) fn compiled(&self) -> &prepare::CompiledModule {
+        &self.compiled
     }
 }
@@ -0,0 +1,234 @@
+use crate::func::FuncRef;
+use crate::global::GlobalRef;
+use crate::imports::ImportResolver;
+use crate::memory::MemoryRef;
+use crate::module::ExternVal;
+use crate::table::TableRef;
+use crate::types::{GlobalDescriptor, MemoryDescriptor, Signature, TableDescriptor};
+use crate::Error;
+use alloc::{collections::BTreeMap, string::String};
+
+/// The imports a module is allowed to have, each mapped to the id of the capability
+/// that backs it.
+///
+/// The manifest only states the policy. The capabilities themselves are registered
+/// with a [`ManifestResolver`], so the same host can serve modules with different
+/// manifests. With the `json` feature, a manifest can be parsed with [`from_json`].
+///
+/// [`ManifestResolver`]: struct.ManifestResolver.html
+/// [`from_json`]: #method.from_json
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ImportManifest {
+    imports: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl ImportManifest {
+    /// Create a manifest that doesn't allow any imports.
+    pub fn new() -> ImportManifest {
+        ImportManifest::default()
+    }
+
+    /// Allow the import of `field_name` from `module_name`, backed by `capability`.
+    pub fn with_import<M: Into<String>, F: Into<String>, C: Into<String>>(
+        mut self,
+        module_name: M,
+        field_name: F,
+        capability: C,
+    ) -> Self {
+        self.push_import(module_name, field_name, capability);
+        self
+    }
+
+    /// Allow the import of `field_name` from `module_name`, backed by `capability`.
+    ///
+    /// Mutable borrowed version.
+    pub fn push_import<M: Into<String>, F: Into<String>, C: Into<String>>(
+        &mut self,
+        module_name: M,
+        field_name: F,
+        capability: C,
+    ) {
+        self.imports
+            .entry(module_name.into())
+            .or_default()
+            .insert(field_name.into(), capability.into());
+    }
+
+    /// Returns the id of the capability backing the import of `field_name` from
+    /// `module_name`, or `None` if the import isn't allowed.
+    pub fn capability(&self, module_name: &str, field_name: &str) -> Option<&str> {
+        self.imports
+            .get(module_name)?
+            .get(field_name)
+            .map(|capability| capability.as_str())
+    }
+}
+
+/// An [`ImportResolver`] that only resolves the imports allowed by an [`ImportManifest`].
+///
+/// Each allowed import is resolved to the entity registered for its capability id.
+/// Imports missing from the manifest fail the instantiation, as do imports whose
+/// capability isn't registered or is of another kind. This keeps the policy of
+/// which module gets which host capabilities out of the code that implements them.
+///
+/// Host functions are registered as [`FuncRef`]s allocated with
+/// [`FuncInstance::alloc_host`], so they are dispatched by index to [`Externals`]
+/// as usual.
+///
+/// # Examples
+///
+/// ```rust
+/// extern crate wabt;
+/// extern crate wasmi;
+///
+/// use wasmi::{
+///     ExternVal, FuncInstance, ImportManifest, ManifestResolver, Module, ModuleInstance,
+///     Signature, ValueType,
+/// };
+///
+/// fn main() {
+///     let load = |wat: &str| Module::from_buffer(wabt::wat2wasm(wat).unwrap()).unwrap();
+///     let log = FuncInstance::alloc_host(Signature::new(&[ValueType::I32][..], None), 0);
+///
+///     let manifest = ImportManifest::new().with_import("env", "print", "console.log");
+///     let resolver = ManifestResolver::new(manifest)
+///         .with_capability("console.log", ExternVal::Func(log));
+///
+///     let allowed = load(r#"(module (import "env" "print" (func (param i32))))"#);
+///     assert!(ModuleInstance::new(&allowed, &resolver).is_ok());
+///
+///     let denied = load(r#"(module (import "env" "exit" (func (param i32))))"#);
+///     assert!(ModuleInstance::new(&denied, &resolver).is_err());
+/// }
+/// ```
+///
+/// [`ImportResolver`]: trait.ImportResolver.html
+/// [`ImportManifest`]: struct.ImportManifest.html
+/// [`FuncRef`]: struct.FuncRef.html
+/// [`FuncInstance::alloc_host`]: struct.FuncInstance.html#method.alloc_host
+/// [`Externals`]: trait.Externals.html
+pub struct ManifestResolver {
+    manifest: ImportManifest,
+    capabilities: BTreeMap<String, ExternVal>,
+}
+
+impl ManifestResolver {
+    /// Create a resolver for the imports allowed by `manifest`, without any capabilities.
+    pub fn new(manifest: ImportManifest) -> ManifestResolver {
+        ManifestResolver {
+            manifest,
+            capabilities: BTreeMap::new(),
+        }
+    }
+
+    /// Register the entity implementing `capability`.
+    pub fn with_capability<C: Into<String>>(
+        mut self,
+        capability: C,
+        extern_val: ExternVal,
+    ) -> Self {
+        self.push_capability(capability, extern_val);
+        self
+    }
+
+    /// Register the entity implementing `capability`.
+    ///
+    /// Mutable borrowed version.
+    pub fn push_capability<C: Into<String>>(&mut self, capability: C, extern_val: ExternVal) {
+        self.capabilities.insert(capability.into(), extern_val);
+    }
+
+    /// Returns the manifest of this resolver.
+    pub fn manifest(&self) -> &ImportManifest {
+        &self.manifest
+    }
+
+    /// Returns the id and the entity of the capability backing an import.
+    fn capability(&self, module_name: &str, field_name: &str) -> Result<(&str, &ExternVal), Error> {
+        let capability = self
+            .manifest
+            .capability(module_name, field_name)
+            .ok_or_else(|| {
+                Error::Instantiation(format!(
+                    "Import {}.{} is not allowed by the manifest",
+                    module_name, field_name
+                ))
+            })?;
+        let extern_val = self.capabilities.get(capability).ok_or_else(|| {
+            Error::Instantiation(format!(
+                "Capability {} of import {}.{} is not registered",
+                capability, module_name, field_name
+            ))
+        })?;
+        Ok((capability, extern_val))
+    }
+}
+
+/// Returns the error for a capability that isn't of the kind `kind` of its import.
+fn wrong_kind(capability: &str, kind: &str) -> Error {
+    Error::Instantiation(format!("Capability {} is not a {}", capability, kind))
+}
+
+impl ImportResolver for ManifestResolver {
+    fn resolve_func(
+        &self,
+        module_name: &str,
+        field_name: &str,
+        signature: &Signature,
+    ) -> Result<FuncRef, Error> {
+        let (capability, extern_val) = self.capability(module_name, field_name)?;
+        let func = extern_val
+            .as_func()
+            .ok_or_else(|| wrong_kind(capability, "function"))?;
+        if func.signature() != signature {
+            return Err(Error::Instantiation(format!(
+                "Capability {} has signature {:?}, but import {}.{} expects {:?}",
+                capability,
+                func.signature(),
+                module_name,
+                field_name,
+                signature
+            )));
+        }
+        Ok(func.clone())
+    }
+
+    fn resolve_global(
+        &self,
+        module_name: &str,
+        field_name: &str,
+        _global_type: &GlobalDescriptor,
+    ) -> Result<GlobalRef, Error> {
+        let (capability, extern_val) = self.capability(module_name, field_name)?;
+        extern_val
+            .as_global()
+            .cloned()
+            .ok_or_else(|| wrong_kind(capability, "global"))
+    }
+
+    fn resolve_memory(
+        &self,
+        module_name: &str,
+        field_name: &str,
+        _memory_type: &MemoryDescriptor,
+    ) -> Result<MemoryRef, Error> {
+        let (capability, extern_val) = self.capability(module_name, field_name)?;
+        extern_val
+            .as_memory()
+            .cloned()
+            .ok_or_else(|| wrong_kind(capability, "memory"))
+    }
+
+    fn resolve_table(
+        &self,
+        module_name: &str,
+        field_name: &str,
+        _table_type: &TableDescriptor,
+    ) -> Result<TableRef, Error> {
+        let (capability, extern_val) = self.capability(module_name, field_name)?;
+        extern_val
+            .as_table()
+            .cloned()
+            .ok_or_else(|| wrong_kind(capability, "table"))
+    }
+}
@@ -0,0 +1,163 @@
+//! A `ByteBuf` whose bytes are provided by a [`MemoryAllocator`] of the embedder.
+
+use alloc::{
+    boxed::Box,
+    string::{String, ToString},
+};
+use core::{
+    ptr::{self, NonNull},
+    slice,
+};
+
+/// An allocator of the buffers backing linear memories.
+///
+/// By default, a memory is backed by a buffer of the global allocator (or, on unix, by a
+/// `mmap`ed region unless the `vec_memory` feature is enabled). An implementation of this
+/// trait given to [`MemoryInstance::alloc_with_allocator`] provides the bytes instead, e.g.
+/// from a pool in a specific region of physical memory.
+///
+/// Buffers are never requested with a length of zero, an empty memory doesn't have one.
+///
+/// # Safety
+///
+/// The memory accesses the buffers through slices, so implementations must uphold:
+///
+/// - A returned buffer holds the requested number of bytes, all set to zero, and isn't
+///   used for anything else until it is passed to [`free`] or moved by [`grow`].
+/// - [`grow`] keeps the contents of the buffer, and zeroes the bytes it adds. It may move
+///   the buffer. If it fails, the buffer is left untouched and stays valid.
+///
+/// Buffers don't need to be aligned.
+///
+/// [`MemoryInstance::alloc_with_allocator`]: struct.MemoryInstance.html#method.alloc_with_allocator
+/// [`free`]: #tymethod.free
+/// [`grow`]: #tymethod.grow
+pub unsafe trait MemoryAllocator {
+    /// Allocate a zeroed buffer of `len` bytes.
+    ///
+    /// Returns `None` if the buffer can't be allocated.
+    fn alloc(&self, len: usize) -> Option<NonNull<u8>>;
+
+    /// Grow the buffer at `ptr` from `old_len` to `new_len` bytes, zeroing the new ones.
+    ///
+    /// Returns the start of the grown buffer, or `None` if it can't be grown.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be a buffer of `old_len` bytes that was returned by this allocator, and
+    /// `new_len` must be greater than `old_len`.
+    unsafe fn grow(&self, ptr: NonNull<u8>, old_len: usize, new_len: usize) -> Option<NonNull<u8>>;
+
+    /// Free the buffer at `ptr`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be a buffer of `len` bytes that was returned by this allocator. It isn't
+    /// used afterwards.
+    unsafe fn free(&self, ptr: NonNull<u8>, len: usize);
+}
+
+pub struct AllocatedByteBuf {
+    allocator: Box<dyn MemoryAllocator>,
+    /// The start of the buffer, or dangling if it is empty.
+    ptr: NonNull<u8>,
+    len: usize,
+}
+
+impl AllocatedByteBuf {
+    /// Create a buffer of `len` bytes provided by `allocator`.
+    pub fn new(allocator: Box<dyn MemoryAllocator>, len: usize) -> Result<Self, String> {
+        let ptr = Self::alloc(&*allocator, len)?;
+        Ok(Self {
+            allocator,
+            ptr,
+            len,
+        })
+    }
+
+    fn alloc(allocator: &dyn MemoryAllocator, len: usize) -> Result<NonNull<u8>, String> {
+        if len == 0 {
+            return Ok(NonNull::dangling());
+        }
+        allocator
+            .alloc(len)
+            .ok_or_else(|| "the allocator failed to allocate the buffer".to_string())
+    }
+
+    pub fn realloc(&mut self, new_len: usize) -> Result<(), String> {
+        if new_len == self.len {
+            return Ok(());
+        }
+        let ptr = if self.len == 0 {
+            Self::alloc(&*self.allocator, new_len)?
+        } else if new_len > self.len {
+            unsafe {
+                // Safety Proof:
+                // The buffer is non-empty, so it was returned by the allocator with `self.len`
+                // bytes, and `new_len` is greater.
+                self.allocator.grow(self.ptr, self.len, new_len)
+            }
+            .ok_or_else(|| "the allocator failed to grow the buffer".to_string())?
+        } else {
+            // The allocator only grows buffers, so a shrunk buffer is a copy of the head.
+            let ptr = Self::alloc(&*self.allocator, new_len)?;
+            unsafe {
+                // Safety Proof:
+                // Both buffers are valid for `new_len` bytes, which is less than `self.len`.
+                // They don't overlap, since the old one is still in use.
+                ptr::copy_nonoverlapping(self.ptr.as_ptr(), ptr.as_ptr(), new_len);
+            }
+            self.free();
+            ptr
+        };
+        self.ptr = ptr;
+        self.len = new_len;
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe {
+            // Safety Proof:
+            // The allocator provides `self.len` bytes at `self.ptr`, which aren't used elsewhere,
+            // and a dangling pointer is fine for an empty slice.
+            slice::from_raw_parts(self.ptr.as_ptr(), self.len)
+        }
+    }
+
+    pub fn as_slice_mut(&mut self) -> &mut [u8] {
+        unsafe {
+            // Safety Proof:
+            // See `as_slice`. The buffer is borrowed mutably along with `self`.
+            slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len)
+        }
+    }
+
+    pub fn erase(&mut self) -> Result<(), String> {
+        for v in self.as_slice_mut() {
+            *v = 0;
+        }
+        Ok(())
+    }
+
+    /// Free the buffer, which must not be used afterwards.
+    fn free(&mut self) {
+        if self.len > 0 {
+            unsafe {
+                // Safety Proof:
+                // The buffer is non-empty, so it was returned by the allocator with `self.len`
+                // bytes.
+                self.allocator.free(self.ptr, self.len);
+            }
+        }
+    }
+}
+
+impl Drop for AllocatedByteBuf {
+    fn drop(&mut self) {
+        self.free();
+    }
+}
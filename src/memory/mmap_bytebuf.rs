@@ -1,10 +1,23 @@
 //! An implementation of a `ByteBuf` based on virtual memory.
 //!
 //! This implementation uses `mmap` on POSIX systems (and should use `VirtualAlloc` on windows).
-//! There are possibilities to improve the performance for the reallocating case by reserving
-//! memory up to maximum. This might be a problem for systems that don't have a lot of virtual
-//! memory (i.e. 32-bit platforms).
+//! Reallocation first tries to resize the mapping in place, by mapping the pages right after it
+//! when growing or by unmapping its tail when shrinking, so the start of the buffer doesn't move.
+//! Only if that fails (e.g. the following pages are already in use) the contents are copied to a
+//! new mapping.
+//!
+//! Growing in place can be guaranteed up to a capacity given on creation. The mapping then
+//! reserves the address space up to the capacity, but the part beyond the buffer is
+//! inaccessible, so it isn't committed until the buffer grows into it. Reserving up to the
+//! maximum of every memory might be a problem for systems that don't have a lot of virtual
+//! memory (i.e. 32-bit platforms), so this is opt-in.
+//!
+//! With the `guard_pages` feature, each mapping is followed by an inaccessible page, so an
+//! access just past the end of the buffer faults instead of hitting whatever is mapped next.
+//! This is meant for debugging. The guard page takes the place of the pages that growing in
+//! place would map, so mappings are only resized in place within their capacity then.
 
+use std::cmp;
 use std::ptr::{self, NonNull};
 use std::slice;
 
@@ -13,27 +26,42 @@ struct Mmap {
     ///
     /// This value doesn't change after creation.
     ptr: NonNull<u8>,
-    /// The length of this mapping.
+    /// The length of the buffer, i.e. of the accessible start of this mapping.
     ///
-    /// Cannot be more than `isize::max_value()`. This value doesn't change after creation.
+    /// Cannot be more than `isize::max_value()`. This value only changes when the mapping is
+    /// resized in place.
     len: usize,
+    /// The length of the whole mapping.
+    ///
+    /// Everything past `len`, rounded up to whole pages, is inaccessible. Cannot be more than
+    /// `isize::max_value()`. This value only changes when the mapping is resized in place,
+    /// beyond its capacity or by shrinking.
+    mapped_len: usize,
 }
 
 impl Mmap {
-    /// Create a new mmap mapping
+    /// Create a new mmap mapping for a buffer of `len` bytes, which can grow in place up to
+    /// `capacity` bytes.
     ///
     /// Returns `Err` if:
-    /// - `len` should not exceed `isize::max_value()`
-    /// - `len` should be greater than 0.
+    /// - `len` and `capacity` should not exceed `isize::max_value()`
+    /// - either `len` or `capacity` should be greater than 0.
     /// - `mmap` returns an error (almost certainly means out of memory).
-    fn new(len: usize) -> Result<Self, String> {
-        if len > isize::max_value() as usize {
+    fn new(len: usize, capacity: usize) -> Result<Self, String> {
+        let capacity = cmp::max(len, capacity);
+        if capacity > isize::max_value() as usize {
             return Err("`len` should not exceed `isize::max_value()`".into());
         }
-        if len == 0 {
+        if capacity == 0 {
             return Err("`len` should be greater than 0".into());
         }
 
+        let mapped_len = mapped_len(capacity).ok_or("`len` is too large for a guard page")?;
+        // Without a tail, the whole mapping is the buffer and can be mapped accessible right
+        // away. Otherwise everything is mapped inaccessible first, so the tail is never
+        // committed, and the buffer is made accessible afterwards.
+        let has_tail = mapped_len > accessible_len(len);
+
         let ptr_or_err = unsafe {
             // Safety Proof:
             // There are not specific safety proofs are required for this call, since the call
@@ -42,9 +70,13 @@ impl Mmap {
                 // `addr` - let the system to choose the address at which to create the mapping.
                 ptr::null_mut(),
                 // the length of the mapping in bytes.
-                len,
-                // `prot` - protection flags: READ WRITE !EXECUTE
-                libc::PROT_READ | libc::PROT_WRITE,
+                mapped_len,
+                // `prot` - protection flags: READ WRITE !EXECUTE, or none for a tail.
+                if has_tail {
+                    libc::PROT_NONE
+                } else {
+                    libc::PROT_READ | libc::PROT_WRITE
+                },
                 // `flags`
                 // `MAP_ANON` - mapping is not backed by any file and initial contents are
                 // initialized to zero.
@@ -74,17 +106,151 @@ impl Mmap {
             _ => {
                 let ptr = NonNull::new(ptr_or_err as *mut u8)
                     .ok_or_else(|| "mmap returned 0".to_string())?;
-                Ok(Self { ptr, len })
+                let mmap = Self {
+                    ptr,
+                    len,
+                    mapped_len,
+                };
+                if has_tail && len > 0 {
+                    // `mmap` is dropped and thus unmapped on return.
+                    mmap.make_accessible(0, accessible_len(len))?;
+                }
+                Ok(mmap)
             }
         }
     }
 
+    /// Returns the number of bytes the buffer can grow to in place.
+    ///
+    /// This excludes the guard page, which always stays inaccessible.
+    fn capacity(&self) -> usize {
+        if cfg!(feature = "guard_pages") {
+            self.mapped_len - page_size()
+        } else {
+            self.mapped_len
+        }
+    }
+
+    /// Make the bytes from `start` up to `end` of the mapping readable and writable.
+    ///
+    /// `start` must be page aligned.
+    fn make_accessible(&self, start: usize, end: usize) -> Result<(), String> {
+        debug_assert!(start % page_size() == 0 && start < end);
+        debug_assert!(end <= accessible_len(self.capacity()));
+        let ret_val = unsafe {
+            // Safety Proof:
+            // - The range lies within the mapping, since its end doesn't exceed the capacity
+            //   rounded up to whole pages, which are all mapped.
+            // - Making memory accessible can't invalidate any reference into the mapping.
+            libc::mprotect(
+                self.ptr.as_ptr().add(start) as *mut libc::c_void,
+                end - start,
+                libc::PROT_READ | libc::PROT_WRITE,
+            )
+        };
+        if ret_val != 0 {
+            return Err("mprotect of the buffer failed".into());
+        }
+        Ok(())
+    }
+
+    /// Try to grow the mapping to `new_len` bytes without moving it.
+    ///
+    /// Within the capacity, this only makes the reserved pages accessible. Beyond it, returns
+    /// `false`, leaving the mapping untouched, if the pages right after the mapping can't be
+    /// mapped, e.g. because they are already in use.
+    fn try_grow_in_place(&mut self, new_len: usize) -> bool {
+        debug_assert!(new_len > self.len);
+        if new_len <= self.capacity() {
+            let (start, end) = (accessible_len(self.len), accessible_len(new_len));
+            if end > start && self.make_accessible(start, end).is_err() {
+                return false;
+            }
+            self.len = new_len;
+            return true;
+        }
+        if cfg!(feature = "guard_pages")
+            || new_len > isize::MAX as usize
+            || self.mapped_len != self.len
+            || self.len % page_size() != 0
+        {
+            return false;
+        }
+        let end = match (self.ptr.as_ptr() as usize).checked_add(self.len) {
+            Some(end) => end as *mut libc::c_void,
+            None => return false,
+        };
+        let extra = new_len - self.len;
+
+        let ptr_or_err = unsafe {
+            // Safety Proof:
+            // Without `MAP_FIXED`, `end` is only a hint and the system never replaces existing
+            // mappings. Thus, this call can't affect any memory but the new mapping.
+            libc::mmap(
+                end,
+                extra,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_ANON | libc::MAP_PRIVATE,
+                -1,
+                0,
+            )
+        };
+        if ptr_or_err == libc::MAP_FAILED {
+            return false;
+        }
+        if ptr_or_err != end {
+            // The system chose another address, so the new pages aren't adjacent.
+            let ret_val = unsafe {
+                // Safety proof:
+                // - `ptr_or_err` was just allocated by a call to `mmap` for `extra` bytes.
+                // - Nothing refers to this mapping yet.
+                libc::munmap(ptr_or_err, extra)
+            };
+            assert_eq!(ret_val, 0, "munmap failed");
+            return false;
+        }
+
+        // The mapping and the new pages are adjacent, so they form a single range that can be
+        // unmapped with a single call to `munmap`.
+        self.len = new_len;
+        self.mapped_len = new_len;
+        true
+    }
+
+    /// Try to shrink the mapping to `new_len` bytes without moving it.
+    ///
+    /// This unmaps the reserved tail as well, so the capacity shrinks to `new_len`. Returns
+    /// `false`, leaving the mapping untouched, if `new_len` is not a multiple of the page size
+    /// of the system.
+    fn try_shrink_in_place(&mut self, new_len: usize) -> bool {
+        debug_assert!(new_len > 0 && new_len < self.len);
+        if cfg!(feature = "guard_pages") || new_len % page_size() != 0 {
+            return false;
+        }
+
+        let ret_val = unsafe {
+            // Safety proof:
+            // - The range is page aligned and lies within the mapping of `self`.
+            // - Taking `&mut self` guarantees that no slice of the mapping is alive.
+            libc::munmap(
+                self.ptr.as_ptr().add(new_len) as *mut libc::c_void,
+                self.mapped_len - new_len,
+            )
+        };
+        if ret_val != 0 {
+            return false;
+        }
+        self.len = new_len;
+        self.mapped_len = new_len;
+        true
+    }
+
     fn as_slice(&self) -> &[u8] {
         unsafe {
             // Safety Proof:
             // - Aliasing guarantees of `self.ptr` are not violated since `self` is the only owner.
-            // - This pointer was allocated for `self.len` bytes and thus is a valid slice.
-            // - `self.len` doesn't change throughout the lifetime of `self`.
+            // - The first `self.len` bytes of this mapping are accessible and thus a valid slice.
+            // - `self.len` only changes when the mapping is resized through `&mut self`.
             // - The value is returned valid for the duration of lifetime of `self`.
             //   `self` cannot be destroyed while the returned slice is alive.
             // - `self.ptr` is of `NonNull` type and thus `.as_ptr()` can never return NULL.
@@ -108,9 +274,9 @@ impl Drop for Mmap {
         let ret_val = unsafe {
             // Safety proof:
             // - `self.ptr` was allocated by a call to `mmap`.
-            // - `self.len` was saved at the same time and it doesn't change throughout the lifetime
-            //   of `self`.
-            libc::munmap(self.ptr.as_ptr() as *mut libc::c_void, self.len)
+            // - `self.mapped_len` was saved at the same time and only changes when the mapping is
+            //   resized in place, which keeps it covering exactly the mapped range.
+            libc::munmap(self.ptr.as_ptr() as *mut libc::c_void, self.mapped_len)
         };
 
         // There is no reason for `munmap` to fail to deallocate a private annonymous mapping
@@ -121,25 +287,78 @@ impl Drop for Mmap {
     }
 }
 
+/// Returns the page size of the system, which is the granularity of mappings.
+fn page_size() -> usize {
+    let page_size = unsafe {
+        // Safety Proof:
+        // `sysconf` has no preconditions.
+        libc::sysconf(libc::_SC_PAGESIZE)
+    };
+    // Fall back to the size of a wasm page, which is a multiple of the page size of all
+    // systems wasmi supports.
+    if page_size > 0 {
+        page_size as usize
+    } else {
+        65536
+    }
+}
+
+/// Returns `len` rounded up to whole pages, which is the part of a mapping that is accessible
+/// for a buffer of `len` bytes.
+///
+/// `len` must not exceed `isize::MAX`, so this can't overflow.
+fn accessible_len(len: usize) -> usize {
+    let page_size = page_size();
+    (len + page_size - 1) / page_size * page_size
+}
+
+/// Returns the length of the mapping for a buffer of up to `len` bytes.
+///
+/// With the `guard_pages` feature this is `len` rounded up to whole pages, plus the guard page.
+/// Returns `None` if that exceeds `isize::MAX`.
+fn mapped_len(len: usize) -> Option<usize> {
+    if !cfg!(feature = "guard_pages") {
+        return Some(len);
+    }
+    accessible_len(len)
+        .checked_add(page_size())
+        .filter(|&mapped_len| mapped_len <= isize::MAX as usize)
+}
+
 pub struct ByteBuf {
     mmap: Option<Mmap>,
+    /// The number of bytes every mapping of this buffer reserves, so it can grow in place.
+    capacity: usize,
 }
 
 impl ByteBuf {
-    pub fn new(len: usize) -> Result<Self, String> {
-        let mmap = if len == 0 {
+    /// Create a buffer of `len` bytes that can grow up to `capacity` bytes without moving.
+    pub fn with_capacity(len: usize, capacity: usize) -> Result<Self, String> {
+        let mmap = if len == 0 && capacity == 0 {
             None
         } else {
-            Some(Mmap::new(len)?)
+            Some(Mmap::new(len, capacity)?)
         };
-        Ok(Self { mmap })
+        Ok(Self { mmap, capacity })
     }
 
     pub fn realloc(&mut self, new_len: usize) -> Result<(), String> {
-        let new_mmap = if new_len == 0 {
+        if let Some(cur_mmap) = self.mmap.as_mut() {
+            let resized_in_place = match new_len {
+                0 => false,
+                _ if new_len > cur_mmap.len => cur_mmap.try_grow_in_place(new_len),
+                _ if new_len < cur_mmap.len => cur_mmap.try_shrink_in_place(new_len),
+                _ => true,
+            };
+            if resized_in_place {
+                return Ok(());
+            }
+        }
+
+        let new_mmap = if new_len == 0 && self.capacity == 0 {
             None
         } else {
-            let mut new_mmap = Mmap::new(new_len)?;
+            let mut new_mmap = Mmap::new(new_len, self.capacity)?;
             if let Some(cur_mmap) = self.mmap.take() {
                 let src = cur_mmap.as_slice();
                 let dst = new_mmap.as_slice_mut();
@@ -178,7 +397,7 @@ impl ByteBuf {
             //
             // Otherwise we double the peak memory consumption.
             self.mmap = None;
-            self.mmap = Some(Mmap::new(len)?);
+            self.mmap = Some(Mmap::new(len, self.capacity)?);
         }
         Ok(())
     }
@@ -193,7 +412,68 @@ mod tests {
     // This is not required since wasm memories can only grow but nice to have.
     #[test]
     fn byte_buf_shrink() {
-        let mut byte_buf = ByteBuf::new(PAGE_SIZE * 3).unwrap();
+        let mut byte_buf = ByteBuf::with_capacity(PAGE_SIZE * 3, 0).unwrap();
         byte_buf.realloc(PAGE_SIZE * 2).unwrap();
     }
+
+    #[test]
+    fn byte_buf_realloc_keeps_contents() {
+        let mut byte_buf = ByteBuf::with_capacity(PAGE_SIZE, 0).unwrap();
+        byte_buf.as_slice_mut()[PAGE_SIZE - 1] = 42;
+
+        byte_buf.realloc(PAGE_SIZE * 3).unwrap();
+        assert_eq!(byte_buf.len(), PAGE_SIZE * 3);
+        assert_eq!(byte_buf.as_slice()[PAGE_SIZE - 1], 42);
+        assert!(byte_buf.as_slice()[PAGE_SIZE..].iter().all(|&b| b == 0));
+        byte_buf.as_slice_mut()[PAGE_SIZE * 3 - 1] = 1;
+
+        // Pages removed by shrinking are zeroed when they are added back.
+        byte_buf.realloc(PAGE_SIZE).unwrap();
+        assert_eq!(byte_buf.as_slice()[PAGE_SIZE - 1], 42);
+        byte_buf.realloc(PAGE_SIZE * 3).unwrap();
+        assert_eq!(byte_buf.as_slice()[PAGE_SIZE * 3 - 1], 0);
+    }
+
+    #[test]
+    fn byte_buf_grows_within_capacity_in_place() {
+        let mut byte_buf = ByteBuf::with_capacity(PAGE_SIZE, PAGE_SIZE * 4).unwrap();
+        assert_eq!(byte_buf.len(), PAGE_SIZE);
+        byte_buf.as_slice_mut()[PAGE_SIZE - 1] = 42;
+        let ptr = byte_buf.as_slice().as_ptr();
+
+        byte_buf.realloc(PAGE_SIZE * 4).unwrap();
+        assert_eq!(byte_buf.as_slice().as_ptr(), ptr);
+        assert_eq!(byte_buf.as_slice()[PAGE_SIZE - 1], 42);
+        assert!(byte_buf.as_slice()[PAGE_SIZE..].iter().all(|&b| b == 0));
+        byte_buf.as_slice_mut()[PAGE_SIZE * 4 - 1] = 1;
+
+        // Growing beyond the capacity keeps the contents, wherever the buffer ends up.
+        byte_buf.realloc(PAGE_SIZE * 5).unwrap();
+        assert_eq!(byte_buf.as_slice()[PAGE_SIZE * 4 - 1], 1);
+    }
+
+    #[cfg(feature = "guard_pages")]
+    #[test]
+    fn byte_buf_guard_page() {
+        fn is_accessible(ptr: *const u8) -> bool {
+            let mut fds = [0; 2];
+            unsafe {
+                assert_eq!(libc::pipe(fds.as_mut_ptr()), 0);
+                // `write` reports an inaccessible buffer with `EFAULT` instead of faulting.
+                let written = libc::write(fds[1], ptr as *const libc::c_void, 1);
+                libc::close(fds[0]);
+                libc::close(fds[1]);
+                written == 1
+            }
+        }
+
+        let mut byte_buf = ByteBuf::with_capacity(PAGE_SIZE * 2, 0).unwrap();
+        let end = byte_buf.as_slice().as_ptr_range().end;
+        assert!(is_accessible(unsafe { end.sub(1) }));
+        assert!(!is_accessible(end));
+
+        byte_buf.realloc(PAGE_SIZE * 3).unwrap();
+        assert_eq!(byte_buf.len(), PAGE_SIZE * 3);
+        assert!(!is_accessible(byte_buf.as_slice().as_ptr_range().end));
+    }
 }
@@ -1,7 +1,12 @@
 use crate::memory_units::{Bytes, Pages, RoundUpTo};
 use crate::value::LittleEndianConvert;
-use crate::Error;
-use alloc::{rc::Rc, string::ToString, vec::Vec};
+use crate::{fnv, Error};
+use alloc::{
+    boxed::Box,
+    rc::Rc,
+    string::{String, ToString},
+    vec::Vec,
+};
 use core::{
     cell::{Cell, Ref, RefCell, RefMut},
     cmp, fmt,
@@ -18,7 +23,53 @@ mod bytebuf;
 #[path = "vec_bytebuf.rs"]
 mod bytebuf;
 
-use self::bytebuf::ByteBuf;
+mod allocator;
+
+use self::allocator::AllocatedByteBuf;
+pub use self::allocator::MemoryAllocator;
+
+/// The buffer of a memory, either the default one or one provided by a [`MemoryAllocator`].
+enum ByteBuf {
+    Default(bytebuf::ByteBuf),
+    Allocated(AllocatedByteBuf),
+}
+
+impl ByteBuf {
+    fn realloc(&mut self, new_len: usize) -> Result<(), String> {
+        match self {
+            ByteBuf::Default(buf) => buf.realloc(new_len),
+            ByteBuf::Allocated(buf) => buf.realloc(new_len),
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            ByteBuf::Default(buf) => buf.len(),
+            ByteBuf::Allocated(buf) => buf.len(),
+        }
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        match self {
+            ByteBuf::Default(buf) => buf.as_slice(),
+            ByteBuf::Allocated(buf) => buf.as_slice(),
+        }
+    }
+
+    fn as_slice_mut(&mut self) -> &mut [u8] {
+        match self {
+            ByteBuf::Default(buf) => buf.as_slice_mut(),
+            ByteBuf::Allocated(buf) => buf.as_slice_mut(),
+        }
+    }
+
+    fn erase(&mut self) -> Result<(), String> {
+        match self {
+            ByteBuf::Default(buf) => buf.erase(),
+            ByteBuf::Allocated(buf) => buf.erase(),
+        }
+    }
+}
 
 /// Size of a page of [linear memory][`MemoryInstance`] - 64KiB.
 ///
@@ -64,6 +115,26 @@ pub struct MemoryInstance {
     initial: Pages,
     current_size: Cell<usize>,
     maximum: Option<Pages>,
+    trap_on_grow_failure: Cell<bool>,
+    /// Whether the memory is shared, see [`alloc_shared`](#method.alloc_shared).
+    shared: bool,
+    /// Whether writes are recorded in `write_journal`.
+    journaling: Cell<bool>,
+    write_journal: RefCell<Vec<MemoryWrite>>,
+}
+
+/// A write to a [`MemoryInstance`], as recorded in its write journal.
+///
+/// See [`MemoryInstance::set_write_journaling`].
+///
+/// [`MemoryInstance`]: struct.MemoryInstance.html
+/// [`MemoryInstance::set_write_journaling`]: struct.MemoryInstance.html#method.set_write_journaling
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MemoryWrite {
+    /// Offset of the written region.
+    pub offset: usize,
+    /// Length of the written region in bytes.
+    pub len: usize,
 }
 
 impl fmt::Debug for MemoryInstance {
@@ -95,6 +166,44 @@ impl CheckedRegion {
     }
 }
 
+/// A region of a [`MemoryInstance`] that is known to be in bounds.
+///
+/// Created by [`MemoryInstance::checked_region`]. The region can be read from and written to
+/// any number of times without checking its bounds again.
+///
+/// The region borrows the memory buffer for its whole lifetime, so the memory can't be
+/// grown (and thus reallocated) while the region is alive.
+///
+/// [`MemoryInstance`]: struct.MemoryInstance.html
+/// [`MemoryInstance::checked_region`]: struct.MemoryInstance.html#method.checked_region
+pub struct Region<'a> {
+    buffer: RefMut<'a, ByteBuf>,
+    region: CheckedRegion,
+}
+
+impl<'a> Region<'a> {
+    /// Returns the offset of this region in the memory.
+    pub fn offset(&self) -> usize {
+        self.region.offset
+    }
+
+    /// Returns the size of this region in bytes.
+    pub fn len(&self) -> usize {
+        self.region.size
+    }
+
+    /// Returns the contents of this region.
+    pub fn as_slice(&self) -> &[u8] {
+        &self.buffer.as_slice()[self.region.range()]
+    }
+
+    /// Returns the contents of this region for modification.
+    pub fn as_slice_mut(&mut self) -> &mut [u8] {
+        let range = self.region.range();
+        &mut self.buffer.as_slice_mut()[range]
+    }
+}
+
 impl MemoryInstance {
     /// Allocate a memory instance.
     ///
@@ -114,42 +223,202 @@ impl MemoryInstance {
     /// - `initial` is greater than `maximum`
     /// - either `initial` or `maximum` is greater than `65536`.
     ///
+    /// Returns [`Error::MemoryAllocationFailed`] if the memory can't be allocated.
+    ///
+    /// [`Error::MemoryAllocationFailed`]: enum.Error.html#variant.MemoryAllocationFailed
     /// [`LINEAR_MEMORY_PAGE_SIZE`]: constant.LINEAR_MEMORY_PAGE_SIZE.html
     pub fn alloc(initial: Pages, maximum: Option<Pages>) -> Result<MemoryRef, Error> {
-        {
-            use core::convert::TryInto;
-            let initial_u32: u32 = initial.0.try_into().map_err(|_| {
-                Error::Memory(format!("initial ({}) can't be coerced to u32", initial.0))
-            })?;
-            let maximum_u32: Option<u32> = maximum
-                .map(|maximum_pages| {
-                    maximum_pages.0.try_into().map_err(|_| {
-                        Error::Memory(format!(
-                            "maximum ({}) can't be coerced to u32",
-                            maximum_pages.0
-                        ))
-                    })
-                })
-                .transpose()?;
-            validation::validate_memory(initial_u32, maximum_u32).map_err(Error::Memory)?;
-        }
+        validation::validate_memory(initial, maximum).map_err(Error::Memory)?;
 
         let memory = MemoryInstance::new(initial, maximum)?;
         Ok(MemoryRef(Rc::new(memory)))
     }
 
+    /// Allocate a shared memory instance, otherwise as with [`alloc`].
+    ///
+    /// wasmi executes code on a single thread, so a shared memory behaves like any other
+    /// one, except that `i32.atomic.wait` and `i64.atomic.wait`, available with the
+    /// `atomics` feature, can only wait on a shared memory and trap with
+    /// [`TrapKind::AtomicWaitOnUnsharedMemory`] on any other. Memories a module declares
+    /// as `shared` are allocated with this, and modules that import a shared memory
+    /// should be given one too.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if [`alloc`] would.
+    ///
+    /// [`alloc`]: #method.alloc
+    /// [`TrapKind::AtomicWaitOnUnsharedMemory`]: enum.TrapKind.html#variant.AtomicWaitOnUnsharedMemory
+    pub fn alloc_shared(initial: Pages, maximum: Option<Pages>) -> Result<MemoryRef, Error> {
+        validation::validate_memory(initial, maximum).map_err(Error::Memory)?;
+
+        let mut memory = MemoryInstance::new(initial, maximum)?;
+        memory.shared = true;
+        Ok(MemoryRef(Rc::new(memory)))
+    }
+
+    /// Allocate a memory instance, as with [`alloc`], and copy each of `segments` into
+    /// it at the offset given with it.
+    ///
+    /// Like any other memory created by the host, it isn't tied to a module. It can be
+    /// provided as an import to any number of module instances, which then all share
+    /// the same contents.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if [`alloc`] fails or if a segment doesn't fit into the initial
+    /// size of the memory.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use wasmi::MemoryInstance;
+    /// use wasmi::memory_units::Pages;
+    ///
+    /// let memory =
+    ///     MemoryInstance::alloc_with_data(Pages(1), None, &[(0, b"hello"), (16, b"world")])
+    ///         .unwrap();
+    /// assert_eq!(memory.get(16, 5).unwrap(), b"world");
+    /// ```
+    ///
+    /// [`alloc`]: #method.alloc
+    pub fn alloc_with_data(
+        initial: Pages,
+        maximum: Option<Pages>,
+        segments: &[(u32, &[u8])],
+    ) -> Result<MemoryRef, Error> {
+        let memory = MemoryInstance::alloc(initial, maximum)?;
+        for &(offset, data) in segments {
+            memory.set(offset, data)?;
+        }
+        Ok(memory)
+    }
+
+    /// Allocate a memory instance, as with [`alloc`], that can grow up to `capacity_hint`
+    /// pages without moving its buffer.
+    ///
+    /// The size of the memory, as seen by wasm, is still `initial`. Only the buffer backing
+    /// it is reserved up front, so a program that is known to grow its memory to a certain
+    /// size doesn't pay for the reallocations and copies on the way. A hint less than
+    /// `initial` has no effect.
+    ///
+    /// On unix, unless the `vec_memory` feature is enabled, only the address space is
+    /// reserved and the pages beyond the current size are inaccessible, so they don't count
+    /// towards the committed memory until the memory grows into them. The vector based
+    /// buffer used elsewhere allocates the whole capacity right away.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if [`alloc`] would, if `capacity_hint` is greater than `maximum`, or
+    /// greater than `65536` without a maximum, or if the capacity can't be reserved.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use wasmi::MemoryInstance;
+    /// use wasmi::memory_units::Pages;
+    ///
+    /// let memory = MemoryInstance::with_capacity_hint(Pages(1), Some(Pages(16)), Pages(8)).unwrap();
+    /// assert_eq!(memory.current_size(), Pages(1));
+    /// let start = memory.direct_access().as_ref().as_ptr();
+    ///
+    /// memory.grow(Pages(7)).unwrap();
+    /// assert_eq!(memory.direct_access().as_ref().as_ptr(), start);
+    ///
+    /// assert!(MemoryInstance::with_capacity_hint(Pages(1), Some(Pages(16)), Pages(17)).is_err());
+    /// ```
+    ///
+    /// [`alloc`]: #method.alloc
+    pub fn with_capacity_hint(
+        initial: Pages,
+        maximum: Option<Pages>,
+        capacity_hint: Pages,
+    ) -> Result<MemoryRef, Error> {
+        validation::validate_memory(initial, maximum).map_err(Error::Memory)?;
+        let limit = maximum.unwrap_or(Pages(validation::LINEAR_MEMORY_MAX_PAGES as usize));
+        if capacity_hint > limit {
+            return Err(Error::Memory(format!(
+                "Capacity hint of {} pages exceeds the maximum of {} pages",
+                capacity_hint.0, limit.0,
+            )));
+        }
+
+        let memory = MemoryInstance::with_capacity(initial, maximum, capacity_hint)?;
+        Ok(MemoryRef(Rc::new(memory)))
+    }
+
+    /// Allocate a memory instance, as with [`alloc`], whose buffer is provided by `allocator`
+    /// instead of the default one.
+    ///
+    /// The allocator provides the buffer on creation, whenever the memory is grown or resized,
+    /// and takes it back when the memory is dropped. See [`MemoryAllocator`] for details.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if [`alloc`] would, and [`Error::MemoryAllocationFailed`] if the
+    /// allocator can't provide the buffer.
+    ///
+    /// [`alloc`]: #method.alloc
+    /// [`MemoryAllocator`]: trait.MemoryAllocator.html
+    /// [`Error::MemoryAllocationFailed`]: enum.Error.html#variant.MemoryAllocationFailed
+    pub fn alloc_with_allocator<A: MemoryAllocator + 'static>(
+        initial: Pages,
+        maximum: Option<Pages>,
+        allocator: A,
+    ) -> Result<MemoryRef, Error> {
+        validation::validate_memory(initial, maximum).map_err(Error::Memory)?;
+
+        let initial_size = pages_to_bytes(initial)?;
+        let buffer = AllocatedByteBuf::new(Box::new(allocator), initial_size.0).map_err(|_| {
+            Error::MemoryAllocationFailed {
+                requested_pages: initial,
+            }
+        })?;
+        let memory = MemoryInstance::with_buffer(initial, maximum, ByteBuf::Allocated(buffer));
+        Ok(MemoryRef(Rc::new(memory)))
+    }
+
     /// Create new linear memory instance.
     fn new(initial: Pages, maximum: Option<Pages>) -> Result<Self, Error> {
-        let limits = ResizableLimits::new(initial.0 as u32, maximum.map(|p| p.0 as u32));
+        MemoryInstance::with_capacity(initial, maximum, initial)
+    }
 
-        let initial_size: Bytes = initial.into();
-        Ok(MemoryInstance {
+    /// Create new linear memory instance, reserving its buffer up to `capacity` pages.
+    fn with_capacity(
+        initial: Pages,
+        maximum: Option<Pages>,
+        capacity: Pages,
+    ) -> Result<Self, Error> {
+        let initial_size = pages_to_bytes(initial)?;
+        let capacity_size = pages_to_bytes(capacity)?;
+        let buffer =
+            bytebuf::ByteBuf::with_capacity(initial_size.0, capacity_size.0).map_err(|_| {
+                Error::MemoryAllocationFailed {
+                    requested_pages: cmp::max(initial, capacity),
+                }
+            })?;
+        Ok(MemoryInstance::with_buffer(
+            initial,
+            maximum,
+            ByteBuf::Default(buffer),
+        ))
+    }
+
+    /// Create new linear memory instance backed by `buffer` of the size of `initial`.
+    fn with_buffer(initial: Pages, maximum: Option<Pages>, buffer: ByteBuf) -> Self {
+        let limits = ResizableLimits::new(initial.0 as u32, maximum.map(|p| p.0 as u32));
+        let initial_size = buffer.len();
+        MemoryInstance {
             limits,
-            buffer: RefCell::new(ByteBuf::new(initial_size.0).map_err(Error::Memory)?),
+            buffer: RefCell::new(buffer),
             initial,
-            current_size: Cell::new(initial_size.0),
+            current_size: Cell::new(initial_size),
             maximum,
-        })
+            trap_on_grow_failure: Cell::new(false),
+            shared: false,
+            journaling: Cell::new(false),
+            write_journal: RefCell::new(Vec::new()),
+        }
     }
 
     /// Return linear memory limits.
@@ -170,6 +439,94 @@ impl MemoryInstance {
         self.maximum
     }
 
+    /// Set whether a failed `grow_memory` instruction on this memory traps.
+    ///
+    /// By default, as the spec requires, a failed `grow_memory` pushes `-1` and the
+    /// execution continues. Programs that ignore the result can then silently proceed
+    /// with a too small memory. If enabled, the failure traps with
+    /// [`TrapKind::MemoryGrowFailed`] instead.
+    ///
+    /// This only affects the instruction, [`grow`] keeps returning `Err`.
+    ///
+    /// [`TrapKind::MemoryGrowFailed`]: enum.TrapKind.html#variant.MemoryGrowFailed
+    /// [`grow`]: #method.grow
+    pub fn set_trap_on_grow_failure(&self, trap_on_grow_failure: bool) {
+        self.trap_on_grow_failure.set(trap_on_grow_failure);
+    }
+
+    /// Returns whether this memory is shared, i.e. was allocated with [`alloc_shared`].
+    ///
+    /// [`alloc_shared`]: #method.alloc_shared
+    pub fn is_shared(&self) -> bool {
+        self.shared
+    }
+
+    /// Returns whether a failed `grow_memory` instruction on this memory traps.
+    ///
+    /// See [`set_trap_on_grow_failure`] for details.
+    ///
+    /// [`set_trap_on_grow_failure`]: #method.set_trap_on_grow_failure
+    pub fn trap_on_grow_failure(&self) -> bool {
+        self.trap_on_grow_failure.get()
+    }
+
+    /// Set whether writes to this memory are recorded in its write journal.
+    ///
+    /// While enabled, every write through the methods of this memory, including the
+    /// stores of wasm code, appends a [`MemoryWrite`] to the journal. Comparing the
+    /// journals of two runs of the same input then shows where they started to diverge.
+    /// Writes through [`checked_region`] and the direct access methods aren't recorded.
+    ///
+    /// Journaling is disabled by default. Disabling it keeps the records written so far,
+    /// they are only removed by [`take_write_journal`].
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use wasmi::memory_units::Pages;
+    /// use wasmi::{MemoryInstance, MemoryWrite};
+    ///
+    /// let memory = MemoryInstance::alloc(Pages(1), None).unwrap();
+    /// memory.set(0, &[1]).unwrap();
+    ///
+    /// memory.set_write_journaling(true);
+    /// memory.set(8, &[1, 2, 3]).unwrap();
+    /// memory.set_value(16, 42u32).unwrap();
+    /// assert_eq!(
+    ///     memory.take_write_journal(),
+    ///     [
+    ///         MemoryWrite { offset: 8, len: 3 },
+    ///         MemoryWrite { offset: 16, len: 4 },
+    ///     ]
+    /// );
+    /// ```
+    ///
+    /// [`MemoryWrite`]: struct.MemoryWrite.html
+    /// [`checked_region`]: #method.checked_region
+    /// [`take_write_journal`]: #method.take_write_journal
+    pub fn set_write_journaling(&self, journaling: bool) {
+        self.journaling.set(journaling);
+    }
+
+    /// Returns the writes recorded since the last call, oldest first, and clears the
+    /// journal.
+    ///
+    /// See [`set_write_journaling`] for details.
+    ///
+    /// [`set_write_journaling`]: #method.set_write_journaling
+    pub fn take_write_journal(&self) -> Vec<MemoryWrite> {
+        core::mem::take(&mut *self.write_journal.borrow_mut())
+    }
+
+    #[inline]
+    fn record_write(&self, offset: usize, len: usize) {
+        if self.journaling.get() {
+            self.write_journal
+                .borrow_mut()
+                .push(MemoryWrite { offset, len });
+        }
+    }
+
     /// Returns current linear memory size.
     ///
     /// Maximum memory size cannot exceed `65536` pages or 4GiB.
@@ -194,10 +551,12 @@ impl MemoryInstance {
     }
 
     /// Get value from memory at given offset.
+    ///
+    /// The value is copied out byte by byte, so `offset` doesn't need to be aligned.
     pub fn get_value<T: LittleEndianConvert>(&self, offset: u32) -> Result<T, Error> {
         let mut buffer = self.buffer.borrow_mut();
         let region =
-            self.checked_region(&mut buffer, offset as usize, ::core::mem::size_of::<T>())?;
+            self.check_region(&mut buffer, offset as usize, ::core::mem::size_of::<T>())?;
         Ok(
             T::from_little_endian(&buffer.as_slice_mut()[region.range()])
                 .expect("Slice size is checked"),
@@ -212,7 +571,7 @@ impl MemoryInstance {
     /// [`get_into`]: #method.get_into
     pub fn get(&self, offset: u32, size: usize) -> Result<Vec<u8>, Error> {
         let mut buffer = self.buffer.borrow_mut();
-        let region = self.checked_region(&mut buffer, offset as usize, size)?;
+        let region = self.check_region(&mut buffer, offset as usize, size)?;
 
         Ok(buffer.as_slice_mut()[region.range()].to_vec())
     }
@@ -221,44 +580,156 @@ impl MemoryInstance {
     ///
     /// # Errors
     ///
-    /// Returns `Err` if the specified region is out of bounds.
+    /// Returns [`Error::MemoryOutOfBounds`] if the specified region is out of bounds.
+    ///
+    /// [`Error::MemoryOutOfBounds`]: enum.Error.html#variant.MemoryOutOfBounds
     pub fn get_into(&self, offset: u32, target: &mut [u8]) -> Result<(), Error> {
         let mut buffer = self.buffer.borrow_mut();
-        let region = self.checked_region(&mut buffer, offset as usize, target.len())?;
+        let region = self.check_region(&mut buffer, offset as usize, target.len())?;
 
         target.copy_from_slice(&buffer.as_slice_mut()[region.range()]);
 
         Ok(())
     }
 
+    /// Copy a null-terminated string starting at the given offset.
+    ///
+    /// The returned bytes don't include the terminating zero byte.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if `offset` is out of bounds or if there is no zero byte
+    /// between `offset` and the end of the memory.
+    pub fn read_cstr(&self, offset: u32) -> Result<Vec<u8>, Error> {
+        let mut buffer = self.buffer.borrow_mut();
+        let offset = offset as usize;
+        let len = buffer.len();
+        self.check_region(&mut buffer, offset, 0)?;
+
+        let tail = &buffer.as_slice()[offset..];
+        let size = tail.iter().position(|&byte| byte == 0).ok_or_else(|| {
+            Error::Memory(format!(
+                "string at offset {} isn't null-terminated before the end of memory [0..{}]",
+                offset, len
+            ))
+        })?;
+
+        Ok(tail[..size].to_vec())
+    }
+
+    /// Copy a null-terminated UTF-8 string starting at the given offset.
+    ///
+    /// See [`read_cstr`] for details.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if [`read_cstr`] fails or if the string isn't valid UTF-8.
+    ///
+    /// [`read_cstr`]: #method.read_cstr
+    pub fn read_utf8(&self, offset: u32) -> Result<String, Error> {
+        let bytes = self.read_cstr(offset)?;
+        String::from_utf8(bytes).map_err(|e| {
+            Error::Memory(format!(
+                "string at offset {} isn't valid UTF-8: {}",
+                offset,
+                e.utf8_error()
+            ))
+        })
+    }
+
+    /// Hash `len` bytes starting at the given offset, without copying them out.
+    ///
+    /// The hash is 64-bit FNV-1a. It is not cryptographic, but it is stable: the same
+    /// bytes always give the same hash, across runs, platforms and wasmi versions.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if the specified region is out of bounds.
+    pub fn hash_range(&self, offset: u32, len: usize) -> Result<u64, Error> {
+        let mut buffer = self.buffer.borrow_mut();
+        let region = self.check_region(&mut buffer, offset as usize, len)?;
+
+        Ok(fnv::fnv1a(&buffer.as_slice()[region.range()]))
+    }
+
+    /// Check that the region of the given `size` starting at `offset` is in bounds
+    /// and return a [`Region`] for accessing it.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if the specified region is out of bounds.
+    ///
+    /// # Panics
+    ///
+    /// Any call that requires either read or write access to memory (such as [`get`], [`set`],
+    /// [`grow`], etc) made while the returned region is alive will panic.
+    ///
+    /// [`Region`]: struct.Region.html
+    /// [`get`]: #method.get
+    /// [`set`]: #method.set
+    /// [`grow`]: #method.grow
+    pub fn checked_region(&self, offset: u32, size: usize) -> Result<Region<'_>, Error> {
+        let mut buffer = self.buffer.borrow_mut();
+        let region = self.check_region(&mut buffer, offset as usize, size)?;
+        Ok(Region { buffer, region })
+    }
+
     /// Copy data in the memory at given offset.
     pub fn set(&self, offset: u32, value: &[u8]) -> Result<(), Error> {
         let mut buffer = self.buffer.borrow_mut();
         let range = self
-            .checked_region(&mut buffer, offset as usize, value.len())?
+            .check_region(&mut buffer, offset as usize, value.len())?
             .range();
 
         buffer.as_slice_mut()[range].copy_from_slice(value);
+        self.record_write(offset as usize, value.len());
 
         Ok(())
     }
 
     /// Copy value in the memory at given offset.
+    ///
+    /// The value is copied in byte by byte, so `offset` doesn't need to be aligned.
     pub fn set_value<T: LittleEndianConvert>(&self, offset: u32, value: T) -> Result<(), Error> {
         let mut buffer = self.buffer.borrow_mut();
         let range = self
-            .checked_region(&mut buffer, offset as usize, ::core::mem::size_of::<T>())?
+            .check_region(&mut buffer, offset as usize, ::core::mem::size_of::<T>())?
             .range();
         value.into_little_endian(&mut buffer.as_slice_mut()[range]);
+        self.record_write(offset as usize, ::core::mem::size_of::<T>());
         Ok(())
     }
 
     /// Increases the size of the linear memory by given number of pages.
     /// Returns previous memory size if succeeds.
     ///
+    /// # Validity of pointers
+    ///
+    /// Growing can't happen while the buffer is borrowed through [`direct_access`] and
+    /// friends. It panics instead, so slices into the memory are never invalidated.
+    ///
+    /// Raw pointers derived from such a slice stay valid across a grow if, and only if,
+    /// the start of the buffer doesn't move, i.e. [`as_ptr`] of a later direct access
+    /// returns the same address. On unix, unless the `vec_memory` feature is enabled,
+    /// the memory is grown in place whenever the pages following it are free, and moved
+    /// otherwise (always, with the `guard_pages` feature). The vector based buffer used
+    /// elsewhere moves whenever the allocator decides so. Either way, a memory allocated
+    /// with [`with_capacity_hint`] is grown in place up to the hint. A failed grow never
+    /// moves the buffer. [`resize_to`] follows the same rules, shrinking in place, while
+    /// [`erase`] always invalidates raw pointers.
+    ///
+    /// [`direct_access`]: #method.direct_access
+    /// [`as_ptr`]: https://doc.rust-lang.org/std/primitive.slice.html#method.as_ptr
+    /// [`resize_to`]: #method.resize_to
+    /// [`erase`]: #method.erase
+    /// [`with_capacity_hint`]: #method.with_capacity_hint
+    ///
     /// # Errors
     ///
-    /// Returns `Err` if attempted to allocate more memory than permited by the limit.
+    /// Returns `Err` if attempted to allocate more memory than permited by the limit, and
+    /// [`Error::MemoryAllocationFailed`] if the grown buffer can't be allocated.
+    ///
+    /// [`Error::MemoryAllocationFailed`]: enum.Error.html#variant.MemoryAllocationFailed
     pub fn grow(&self, additional: Pages) -> Result<Pages, Error> {
         let size_before_grow: Pages = self.current_size();
 
@@ -271,7 +742,16 @@ impl MemoryInstance {
             ));
         }
 
-        let new_size: Pages = size_before_grow + additional;
+        let new_size = size_before_grow
+            .0
+            .checked_add(additional.0)
+            .map(Pages)
+            .ok_or_else(|| {
+                Error::Memory(format!(
+                    "Trying to grow memory by {} pages when already have {}",
+                    additional.0, size_before_grow.0,
+                ))
+            })?;
         let maximum = self
             .maximum
             .unwrap_or(Pages(validation::LINEAR_MEMORY_MAX_PAGES as usize));
@@ -282,40 +762,80 @@ impl MemoryInstance {
             )));
         }
 
-        let new_buffer_length: Bytes = new_size.into();
+        let new_buffer_length = pages_to_bytes(new_size)?;
         self.buffer
             .borrow_mut()
             .realloc(new_buffer_length.0)
-            .map_err(Error::Memory)?;
+            .map_err(|_| Error::MemoryAllocationFailed {
+                requested_pages: new_size,
+            })?;
 
         self.current_size.set(new_buffer_length.0);
 
         Ok(size_before_grow)
     }
 
-    fn checked_region(
+    /// Set the size of the linear memory to `pages`, growing or shrinking it.
+    ///
+    /// This is meant for the host to set up a known state, e.g. when restoring a
+    /// snapshot. Newly added pages are zeroed, including pages that were removed by
+    /// shrinking and are added back.
+    ///
+    /// Shrinking is a host-only operation: wasm code can only ever grow a memory and
+    /// may rely on it never getting smaller, e.g. with pointers it keeps in globals.
+    /// Only shrink a memory when all such state is reset as well.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if `pages` exceeds the maximum of this memory, or is less than
+    /// its initial size, and [`Error::MemoryAllocationFailed`] if the resized buffer
+    /// can't be allocated.
+    ///
+    /// [`Error::MemoryAllocationFailed`]: enum.Error.html#variant.MemoryAllocationFailed
+    pub fn resize_to(&self, pages: Pages) -> Result<(), Error> {
+        let maximum = self
+            .maximum
+            .unwrap_or(Pages(validation::LINEAR_MEMORY_MAX_PAGES as usize));
+        if pages > maximum {
+            return Err(Error::Memory(format!(
+                "Trying to resize memory to {} pages when the maximum is {}",
+                pages.0, maximum.0,
+            )));
+        }
+        if pages < self.initial {
+            return Err(Error::Memory(format!(
+                "Trying to resize memory to {} pages when the initial size is {}",
+                pages.0, self.initial.0,
+            )));
+        }
+
+        let new_buffer_length = pages_to_bytes(pages)?;
+        self.buffer
+            .borrow_mut()
+            .realloc(new_buffer_length.0)
+            .map_err(|_| Error::MemoryAllocationFailed {
+                requested_pages: pages,
+            })?;
+
+        self.current_size.set(new_buffer_length.0);
+
+        Ok(())
+    }
+
+    fn check_region(
         &self,
         buffer: &mut ByteBuf,
         offset: usize,
         size: usize,
     ) -> Result<CheckedRegion, Error> {
-        let end = offset.checked_add(size).ok_or_else(|| {
-            Error::Memory(format!(
-                "trying to access memory block of size {} from offset {}",
-                size, offset
-            ))
-        })?;
-
-        if end > buffer.len() {
-            return Err(Error::Memory(format!(
-                "trying to access region [{}..{}] in memory [0..{}]",
+        match offset.checked_add(size) {
+            Some(end) if end <= buffer.len() => Ok(CheckedRegion { offset, size }),
+            _ => Err(Error::MemoryOutOfBounds {
                 offset,
-                end,
-                buffer.len()
-            )));
+                len: size,
+                memory_size: buffer.len(),
+            }),
         }
-
-        Ok(CheckedRegion { offset, size })
     }
 
     fn checked_region_pair(
@@ -326,48 +846,9 @@ impl MemoryInstance {
         offset2: usize,
         size2: usize,
     ) -> Result<(CheckedRegion, CheckedRegion), Error> {
-        let end1 = offset1.checked_add(size1).ok_or_else(|| {
-            Error::Memory(format!(
-                "trying to access memory block of size {} from offset {}",
-                size1, offset1
-            ))
-        })?;
-
-        let end2 = offset2.checked_add(size2).ok_or_else(|| {
-            Error::Memory(format!(
-                "trying to access memory block of size {} from offset {}",
-                size2, offset2
-            ))
-        })?;
-
-        if end1 > buffer.len() {
-            return Err(Error::Memory(format!(
-                "trying to access region [{}..{}] in memory [0..{}]",
-                offset1,
-                end1,
-                buffer.len()
-            )));
-        }
-
-        if end2 > buffer.len() {
-            return Err(Error::Memory(format!(
-                "trying to access region [{}..{}] in memory [0..{}]",
-                offset2,
-                end2,
-                buffer.len()
-            )));
-        }
-
-        Ok((
-            CheckedRegion {
-                offset: offset1,
-                size: size1,
-            },
-            CheckedRegion {
-                offset: offset2,
-                size: size2,
-            },
-        ))
+        let region1 = self.check_region(buffer, offset1, size1)?;
+        let region2 = self.check_region(buffer, offset2, size2)?;
+        Ok((region1, region2))
     }
 
     /// Copy contents of one memory region to another.
@@ -390,6 +871,7 @@ impl MemoryInstance {
                 len,
             )
         }
+        self.record_write(dst_offset, len);
 
         Ok(())
     }
@@ -429,6 +911,7 @@ impl MemoryInstance {
                 len,
             )
         }
+        self.record_write(dst_offset, len);
 
         Ok(())
     }
@@ -454,14 +937,11 @@ impl MemoryInstance {
         let mut src_buffer = src.buffer.borrow_mut();
         let mut dst_buffer = dst.buffer.borrow_mut();
 
-        let src_range = src
-            .checked_region(&mut src_buffer, src_offset, len)?
-            .range();
-        let dst_range = dst
-            .checked_region(&mut dst_buffer, dst_offset, len)?
-            .range();
+        let src_range = src.check_region(&mut src_buffer, src_offset, len)?.range();
+        let dst_range = dst.check_region(&mut dst_buffer, dst_offset, len)?.range();
 
         dst_buffer.as_slice_mut()[dst_range].copy_from_slice(&src_buffer.as_slice()[src_range]);
+        dst.record_write(dst_offset, len);
 
         Ok(())
     }
@@ -476,11 +956,12 @@ impl MemoryInstance {
     pub fn clear(&self, offset: usize, new_val: u8, len: usize) -> Result<(), Error> {
         let mut buffer = self.buffer.borrow_mut();
 
-        let range = self.checked_region(&mut buffer, offset, len)?.range();
+        let range = self.check_region(&mut buffer, offset, len)?.range();
 
         for val in &mut buffer.as_slice_mut()[range] {
             *val = new_val
         }
+        self.record_write(offset, len);
         Ok(())
     }
 
@@ -497,7 +978,10 @@ impl MemoryInstance {
     ///
     /// Might be useful for some optimization shenanigans.
     pub fn erase(&self) -> Result<(), Error> {
-        self.buffer.borrow_mut().erase().map_err(Error::Memory)
+        let mut buffer = self.buffer.borrow_mut();
+        buffer.erase().map_err(Error::Memory)?;
+        self.record_write(0, buffer.len());
+        Ok(())
     }
 
     /// Provides direct access to the underlying memory buffer.
@@ -571,10 +1055,22 @@ impl MemoryInstance {
     }
 }
 
+/// Convert a number of pages into a number of bytes.
+///
+/// Unlike the `Pages` to `Bytes` conversion this doesn't wrap or panic on overflow,
+/// which can happen for huge page counts, in particular when `usize` is 32 bits wide.
+fn pages_to_bytes(pages: Pages) -> Result<Bytes, Error> {
+    pages
+        .0
+        .checked_mul(LINEAR_MEMORY_PAGE_SIZE.0)
+        .map(Bytes)
+        .ok_or_else(|| Error::Memory(format!("{} pages don't fit in the address space", pages.0)))
+}
+
 #[cfg(test)]
 mod tests {
 
-    use super::{MemoryInstance, MemoryRef, LINEAR_MEMORY_PAGE_SIZE};
+    use super::{pages_to_bytes, MemoryInstance, MemoryRef, MemoryWrite, LINEAR_MEMORY_PAGE_SIZE};
     use crate::memory_units::Pages;
     use crate::Error;
     use alloc::rc::Rc;
@@ -610,6 +1106,120 @@ mod tests {
         }
     }
 
+    #[test]
+    fn hash_range() {
+        let mem = MemoryInstance::alloc(Pages(1), None).unwrap();
+        mem.set(0, b"foobar").unwrap();
+
+        // Reference values of 64-bit FNV-1a.
+        assert_eq!(mem.hash_range(0, 0).unwrap(), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(mem.hash_range(0, 6).unwrap(), 0x8594_4171_f739_67e8);
+
+        let before = mem.hash_range(0, 3).unwrap();
+        mem.set(1, b"x").unwrap();
+        assert_ne!(mem.hash_range(0, 3).unwrap(), before);
+
+        assert!(mem.hash_range(65535, 1).is_ok());
+        assert!(mem.hash_range(65535, 2).is_err());
+        assert!(mem.hash_range(0, usize::MAX).is_err());
+    }
+
+    #[test]
+    fn u128_value_is_little_endian() {
+        let mem = MemoryInstance::alloc(Pages(1), None).unwrap();
+        let value: u128 = 0x0f0e_0d0c_0b0a_0908_0706_0504_0302_0100;
+        mem.set_value(8, value).unwrap();
+        let expected: alloc::vec::Vec<u8> = (0..16).collect();
+        assert_eq!(mem.get(8, 16).unwrap(), expected);
+        assert_eq!(mem.get_value::<u128>(8).unwrap(), value);
+        assert_eq!(mem.get_value::<i128>(8).unwrap(), value as i128);
+        assert!(mem.get_value::<u128>(65521).is_err());
+    }
+
+    #[test]
+    fn alloc_rejects_huge_page_counts() {
+        for &pages in &[65537, u32::MAX as usize, usize::MAX] {
+            assert!(MemoryInstance::alloc(Pages(pages), None).is_err());
+            assert!(MemoryInstance::alloc(Pages(0), Some(Pages(pages))).is_err());
+        }
+    }
+
+    #[test]
+    fn grow_rejects_huge_page_counts() {
+        let mem = MemoryInstance::alloc(Pages(1), None).unwrap();
+        for &additional in &[65536, u32::MAX as usize, usize::MAX - 1, usize::MAX] {
+            assert!(mem.grow(Pages(additional)).is_err());
+            assert_eq!(mem.current_size(), Pages(1));
+        }
+    }
+
+    #[test]
+    fn resize_to() {
+        let mem = MemoryInstance::alloc(Pages(1), Some(Pages(3))).unwrap();
+        mem.set(LINEAR_MEMORY_PAGE_SIZE.0 as u32 - 1, &[1]).unwrap();
+
+        mem.resize_to(Pages(3)).unwrap();
+        assert_eq!(mem.current_size(), Pages(3));
+        assert_eq!(
+            mem.get(LINEAR_MEMORY_PAGE_SIZE.0 as u32 - 1, 1).unwrap(),
+            [1]
+        );
+        mem.set(LINEAR_MEMORY_PAGE_SIZE.0 as u32, &[2]).unwrap();
+
+        // Removed pages come back zeroed.
+        mem.resize_to(Pages(1)).unwrap();
+        assert_eq!(mem.current_size(), Pages(1));
+        assert!(mem.get(LINEAR_MEMORY_PAGE_SIZE.0 as u32, 1).is_err());
+        mem.resize_to(Pages(2)).unwrap();
+        assert_eq!(mem.get(LINEAR_MEMORY_PAGE_SIZE.0 as u32, 1).unwrap(), [0]);
+        assert_eq!(
+            mem.get(LINEAR_MEMORY_PAGE_SIZE.0 as u32 - 1, 1).unwrap(),
+            [1]
+        );
+
+        assert!(mem.resize_to(Pages(4)).is_err());
+        assert!(mem.resize_to(Pages(0)).is_err());
+        assert_eq!(mem.current_size(), Pages(2));
+    }
+
+    #[test]
+    fn with_capacity_hint() {
+        let mem = MemoryInstance::with_capacity_hint(Pages(1), Some(Pages(8)), Pages(4)).unwrap();
+        assert_eq!(mem.current_size(), Pages(1));
+        assert!(mem.get(LINEAR_MEMORY_PAGE_SIZE.0 as u32, 1).is_err());
+        mem.set(0, &[42]).unwrap();
+        let start = mem.direct_access().as_ref().as_ptr();
+
+        // Growing within the hint doesn't move the buffer.
+        mem.grow(Pages(1)).unwrap();
+        mem.grow(Pages(2)).unwrap();
+        assert_eq!(mem.current_size(), Pages(4));
+        assert_eq!(mem.direct_access().as_ref().as_ptr(), start);
+        assert_eq!(mem.get(0, 1).unwrap(), [42]);
+        assert_eq!(
+            mem.get(LINEAR_MEMORY_PAGE_SIZE.0 as u32 * 4 - 1, 1)
+                .unwrap(),
+            [0]
+        );
+
+        // Beyond the hint, the memory still grows up to its maximum.
+        mem.grow(Pages(4)).unwrap();
+        assert_eq!(mem.get(0, 1).unwrap(), [42]);
+        assert!(mem.grow(Pages(1)).is_err());
+
+        assert!(MemoryInstance::with_capacity_hint(Pages(1), Some(Pages(8)), Pages(9)).is_err());
+        assert!(MemoryInstance::with_capacity_hint(Pages(0), None, Pages(65537)).is_err());
+        assert!(MemoryInstance::with_capacity_hint(Pages(2), None, Pages(1)).is_ok());
+        assert!(MemoryInstance::with_capacity_hint(Pages(0), None, Pages(1)).is_ok());
+    }
+
+    #[test]
+    fn pages_to_bytes_doesnt_overflow() {
+        assert_eq!(pages_to_bytes(Pages(1)).unwrap(), LINEAR_MEMORY_PAGE_SIZE);
+        assert!(pages_to_bytes(Pages(usize::MAX)).is_err());
+        assert!(pages_to_bytes(Pages(usize::MAX / LINEAR_MEMORY_PAGE_SIZE.0 + 1)).is_err());
+    }
+
     #[test]
     fn ensure_page_size() {
         use memory_units::ByteSize;
@@ -623,6 +1233,23 @@ mod tests {
         mem
     }
 
+    #[test]
+    fn read_cstr() {
+        let mem = create_memory(b"hello\0\xff\0");
+        assert_eq!(mem.read_cstr(0).unwrap(), b"hello");
+        assert_eq!(mem.read_cstr(3).unwrap(), b"lo");
+        assert_eq!(mem.read_cstr(5).unwrap(), b"");
+        assert_eq!(mem.read_utf8(0).unwrap(), "hello");
+        assert!(mem.read_utf8(6).is_err());
+
+        // The rest of the memory is zeroed, so write a non-zero byte at the very end.
+        let last = LINEAR_MEMORY_PAGE_SIZE.0 as u32 - 1;
+        mem.set(last, &[1]).unwrap();
+        assert!(mem.read_cstr(last).is_err());
+        assert!(mem.read_cstr(last + 1).is_err());
+        assert!(mem.read_cstr(u32::MAX).is_err());
+    }
+
     #[test]
     fn copy_overlaps_1() {
         let mem = create_memory(&[0, 1, 2, 3, 4, 5, 6, 7, 8, 9]);
@@ -741,6 +1368,34 @@ mod tests {
         assert_eq!(data, [17, 129]);
     }
 
+    #[test]
+    fn out_of_bounds_error() {
+        let mem = MemoryInstance::new(Pages(1), None).unwrap();
+        let mut data = [0u8; 64];
+        let err = mem.get_into(65500, &mut data[..]).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::MemoryOutOfBounds {
+                offset: 65500,
+                len: 64,
+                memory_size: 65536,
+            }
+        ));
+        assert_eq!(
+            err.to_string(),
+            "Memory: tried offset 65500 len 64, memory is 65536 bytes"
+        );
+
+        assert!(matches!(
+            mem.clear(usize::MAX, 0, 2),
+            Err(Error::MemoryOutOfBounds {
+                offset: usize::MAX,
+                len: 2,
+                ..
+            })
+        ));
+    }
+
     #[test]
     fn zero_copy() {
         let mem = MemoryInstance::alloc(Pages(1), None).unwrap();
@@ -772,4 +1427,186 @@ mod tests {
             let _ = mem_inner.set(0, &[11, 12, 13]);
         });
     }
+
+    #[test]
+    fn checked_region() {
+        let mem = create_memory(&[0, 1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        {
+            let mut region = mem
+                .checked_region(2, 4)
+                .expect("checked_region should not fail");
+            assert_eq!(region.offset(), 2);
+            assert_eq!(region.len(), 4);
+            assert_eq!(region.as_slice(), &[2, 3, 4, 5]);
+            region.as_slice_mut().copy_from_slice(&[42, 43, 44, 45]);
+        }
+        let result = mem.get(0, 10).expect("Successfully retrieve the result");
+        assert_eq!(result, &[0, 1, 42, 43, 44, 45, 6, 7, 8, 9]);
+
+        assert!(mem.checked_region(65535, 1).is_ok());
+        assert!(mem.checked_region(65535, 2).is_err());
+        assert!(mem.checked_region(u32::MAX, 2).is_err());
+    }
+
+    #[should_panic]
+    #[test]
+    fn checked_region_prevents_grow() {
+        let mem = MemoryInstance::alloc(Pages(1), None).unwrap();
+        let _region = mem.checked_region(0, 1).unwrap();
+        let _ = mem.grow(Pages(1));
+    }
+
+    #[test]
+    fn write_journal() {
+        let write = |offset, len| MemoryWrite { offset, len };
+        let src = MemoryInstance::alloc(Pages(1), None).unwrap();
+        let dst = MemoryInstance::alloc(Pages(1), None).unwrap();
+        dst.set(0, &[1, 2, 3]).unwrap();
+        assert_eq!(dst.take_write_journal(), []);
+
+        dst.set_write_journaling(true);
+        dst.copy(0, 10, 3).unwrap();
+        dst.copy_nonoverlapping(0, 20, 2).unwrap();
+        dst.clear(30, 7, 5).unwrap();
+        MemoryInstance::transfer(&src, 0, &dst, 40, 6).unwrap();
+        // Failed writes aren't recorded.
+        assert!(dst.set(65535, &[1, 2]).is_err());
+        dst.erase().unwrap();
+        assert_eq!(
+            dst.take_write_journal(),
+            [
+                write(10, 3),
+                write(20, 2),
+                write(30, 5),
+                write(40, 6),
+                write(0, 65536)
+            ]
+        );
+        assert_eq!(src.take_write_journal(), []);
+
+        dst.set_value(0, 1u16).unwrap();
+        dst.set_write_journaling(false);
+        dst.set_value(0, 1u16).unwrap();
+        assert_eq!(dst.take_write_journal(), [write(0, 2)]);
+        assert_eq!(dst.take_write_journal(), []);
+    }
+
+    #[test]
+    fn alloc_with_allocator() {
+        use super::MemoryAllocator;
+        use core::{cell::Cell, ptr::NonNull};
+
+        const PAGE: usize = LINEAR_MEMORY_PAGE_SIZE.0;
+
+        /// A pool that hands out its bytes in order, and only takes back the last buffer.
+        struct Pool {
+            bytes: Box<[Cell<u8>]>,
+            next: Cell<usize>,
+        }
+
+        impl Pool {
+            fn base(&self) -> *mut u8 {
+                self.bytes.as_ptr() as *mut u8
+            }
+        }
+
+        struct BumpAllocator(Rc<Pool>);
+
+        unsafe impl MemoryAllocator for BumpAllocator {
+            fn alloc(&self, len: usize) -> Option<NonNull<u8>> {
+                let start = self.0.next.get();
+                let end = start
+                    .checked_add(len)
+                    .filter(|&end| end <= self.0.bytes.len())?;
+                for byte in &self.0.bytes[start..end] {
+                    byte.set(0);
+                }
+                self.0.next.set(end);
+                NonNull::new(self.0.bytes[start..].as_ptr() as *mut u8)
+            }
+
+            unsafe fn grow(
+                &self,
+                ptr: NonNull<u8>,
+                old_len: usize,
+                new_len: usize,
+            ) -> Option<NonNull<u8>> {
+                let start = ptr.as_ptr() as usize - self.0.base() as usize;
+                if start + old_len != self.0.next.get() {
+                    let new = self.alloc(new_len)?;
+                    core::ptr::copy_nonoverlapping(ptr.as_ptr(), new.as_ptr(), old_len);
+                    return Some(new);
+                }
+                let end = start
+                    .checked_add(new_len)
+                    .filter(|&end| end <= self.0.bytes.len())?;
+                for byte in &self.0.bytes[start + old_len..end] {
+                    byte.set(0);
+                }
+                self.0.next.set(end);
+                Some(ptr)
+            }
+
+            unsafe fn free(&self, ptr: NonNull<u8>, len: usize) {
+                let start = ptr.as_ptr() as usize - self.0.base() as usize;
+                if start + len == self.0.next.get() {
+                    self.0.next.set(start);
+                }
+            }
+        }
+
+        let pool = Rc::new(Pool {
+            bytes: (0..4 * PAGE).map(|_| Cell::new(0xff)).collect(),
+            next: Cell::new(0),
+        });
+        let allocator = || BumpAllocator(Rc::clone(&pool));
+
+        let mem =
+            MemoryInstance::alloc_with_allocator(Pages(1), Some(Pages(8)), allocator()).unwrap();
+        assert_eq!(
+            mem.direct_access().as_ref().as_ptr(),
+            pool.base() as *const u8
+        );
+        assert_eq!(pool.next.get(), PAGE);
+        assert_eq!(mem.get(0, PAGE).unwrap(), vec![0; PAGE]);
+        mem.set(0, &[42]).unwrap();
+        assert_eq!(pool.bytes[0].get(), 42);
+
+        // The pool grows the last buffer in place.
+        mem.grow(Pages(1)).unwrap();
+        assert_eq!(
+            mem.direct_access().as_ref().as_ptr(),
+            pool.base() as *const u8
+        );
+        assert_eq!(mem.get(2 * PAGE as u32 - 1, 1).unwrap(), [0]);
+        assert_eq!(pool.next.get(), 2 * PAGE);
+
+        // Growing beyond the pool fails, without losing the contents.
+        assert!(mem.grow(Pages(3)).is_err());
+        assert_eq!(mem.current_size(), Pages(2));
+        assert_eq!(mem.get(0, 1).unwrap(), [42]);
+
+        // Shrinking moves the head of the buffer to a new one.
+        mem.resize_to(Pages(1)).unwrap();
+        let moved = mem.direct_access().as_ref().as_ptr();
+        assert_eq!(moved, pool.bytes[2 * PAGE..].as_ptr() as *const u8);
+        assert_eq!(mem.get(0, 1).unwrap(), [42]);
+
+        // Dropping the memory gives the buffer back.
+        drop(mem);
+        assert_eq!(pool.next.get(), 2 * PAGE);
+
+        match MemoryInstance::alloc_with_allocator(Pages(3), None, allocator()) {
+            Err(Error::MemoryAllocationFailed { requested_pages }) => {
+                assert_eq!(requested_pages, Pages(3))
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+        let empty = MemoryInstance::alloc_with_allocator(Pages(0), None, allocator()).unwrap();
+        assert_eq!(empty.current_size(), Pages(0));
+        assert_eq!(pool.next.get(), 2 * PAGE);
+        assert!(
+            MemoryInstance::alloc_with_allocator(Pages(2), Some(Pages(1)), allocator()).is_err()
+        );
+    }
 }
@@ -1,14 +1,22 @@
 //! An implementation of `ByteBuf` based on a plain `Vec`.
 
-use alloc::{string::String, vec::Vec};
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::cmp;
 
 pub struct ByteBuf {
     buf: Vec<u8>,
 }
 
 impl ByteBuf {
-    pub fn new(len: usize) -> Result<Self, String> {
-        let buf = vec![0; len];
+    /// Create a buffer of `len` bytes that can grow up to `capacity` bytes without moving.
+    pub fn with_capacity(len: usize, capacity: usize) -> Result<Self, String> {
+        let mut buf = Vec::new();
+        buf.try_reserve_exact(cmp::max(len, capacity))
+            .map_err(|_| "failed to reserve the capacity of the buffer".to_string())?;
+        buf.resize(len, 0u8);
         Ok(Self { buf })
     }
 
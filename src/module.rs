@@ -1,24 +1,24 @@
 use crate::func::{FuncBody, FuncInstance, FuncRef};
 use crate::global::{GlobalInstance, GlobalRef};
 use crate::host::Externals;
-use crate::imports::ImportResolver;
-use crate::memory::MemoryRef;
-use crate::memory_units::Pages;
-use crate::runner::StackRecycler;
+use crate::imports::{ImportResolver, ModuleImportResolver};
+use crate::memory::{MemoryRef, Region, LINEAR_MEMORY_PAGE_SIZE};
+use crate::memory_units::{Bytes, Pages};
+use crate::runner::{CallStats, StackRecycler};
 use crate::table::TableRef;
-use crate::types::{GlobalDescriptor, MemoryDescriptor, TableDescriptor};
-use crate::{Error, MemoryInstance, Module, RuntimeValue, Signature, TableInstance, Trap};
-use alloc::collections::BTreeMap;
-use alloc::{
-    borrow::ToOwned,
-    rc::Rc,
-    string::{String, ToString},
-    vec::Vec,
+use crate::types::{GlobalDescriptor, MemoryDescriptor, SegmentKind, TableDescriptor};
+use crate::{
+    Coercion, Error, FromRuntimeValue, MemoryInstance, Module, RuntimeValue, Signature,
+    TableInstance, Trap, ValueType,
 };
+use alloc::collections::BTreeMap;
+use alloc::{borrow::ToOwned, rc::Rc, string::String, vec::Vec};
 use core::cell::{Ref, RefCell};
-use core::fmt;
-use parity_wasm::elements::{External, InitExpr, Instruction, Internal, ResizableLimits, Type};
-use validation::{DEFAULT_MEMORY_INDEX, DEFAULT_TABLE_INDEX};
+use core::{fmt, mem};
+use parity_wasm::elements::{
+    External, ImportCountType, InitExpr, Instruction, Internal, ResizableLimits,
+};
+use validation::DEFAULT_MEMORY_INDEX;
 
 /// Reference to a [`ModuleInstance`].
 ///
@@ -43,6 +43,75 @@ impl ::core::ops::Deref for ModuleRef {
     }
 }
 
+impl ModuleRef {
+    /// Returns a resolver of imports backed by the exports of this instance.
+    ///
+    /// This links modules together: registering the resolver under some name with
+    /// [`ImportsBuilder::with_resolver`] lets other modules import the exports of this
+    /// instance from that module name. The imported entities are shared, not copied,
+    /// e.g. a memory exported by this instance and imported by another one is the
+    /// same memory.
+    ///
+    /// Imports are resolved by field name only. Whether an export matches the
+    /// imported type is checked when the importing module is instantiated.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// extern crate wabt;
+    /// extern crate wasmi;
+    ///
+    /// use wasmi::{ImportsBuilder, Module, ModuleInstance, NopExternals, RuntimeValue};
+    ///
+    /// fn main() {
+    ///     let a = Module::from_buffer(
+    ///         wabt::wat2wasm(
+    ///             r#"
+    ///             (module
+    ///               (memory (export "memory") 1)
+    ///               (func (export "double") (param i32) (result i32)
+    ///                 (i32.mul (get_local 0) (i32.const 2))))
+    ///             "#,
+    ///         )
+    ///         .unwrap(),
+    ///     )
+    ///     .unwrap();
+    ///     let b = Module::from_buffer(
+    ///         wabt::wat2wasm(
+    ///             r#"
+    ///             (module
+    ///               (import "a" "memory" (memory 1))
+    ///               (import "a" "double" (func $double (param i32) (result i32)))
+    ///               (func (export "run") (result i32)
+    ///                 (i32.store (i32.const 0) (call $double (i32.const 21)))
+    ///                 (i32.load (i32.const 0))))
+    ///             "#,
+    ///         )
+    ///         .unwrap(),
+    ///     )
+    ///     .unwrap();
+    ///
+    ///     let a = ModuleInstance::new(&a, &ImportsBuilder::default())
+    ///         .unwrap()
+    ///         .assert_no_start();
+    ///     let imports = ImportsBuilder::new().with_resolver("a", a.as_import_resolver());
+    ///     let b = ModuleInstance::new(&b, &imports).unwrap().assert_no_start();
+    ///
+    ///     assert_eq!(
+    ///         b.invoke_export("run", &[], &mut NopExternals).unwrap(),
+    ///         Some(RuntimeValue::I32(42)),
+    ///     );
+    ///     let memory = a.export_by_name("memory").unwrap();
+    ///     assert_eq!(memory.as_memory().unwrap().get_value::<i32>(0).unwrap(), 42);
+    /// }
+    /// ```
+    ///
+    /// [`ImportsBuilder::with_resolver`]: struct.ImportsBuilder.html#method.with_resolver
+    pub fn as_import_resolver(&self) -> &dyn ModuleImportResolver {
+        self
+    }
+}
+
 /// An external value is the runtime representation of an entity
 /// that can be imported or exported.
 pub enum ExternVal {
@@ -158,7 +227,12 @@ pub struct ModuleInstance {
     funcs: RefCell<Vec<FuncRef>>,
     memories: RefCell<Vec<MemoryRef>>,
     globals: RefCell<Vec<GlobalRef>>,
+    /// Mutable globals defined by the module, along with their initial values.
+    initial_globals: RefCell<Vec<(GlobalRef, RuntimeValue)>>,
     exports: RefCell<BTreeMap<String, ExternVal>>,
+    start: RefCell<Option<FuncRef>>,
+    /// The name the `name` section of the module gives to it.
+    module_name: Option<String>,
 }
 
 impl ModuleInstance {
@@ -169,7 +243,10 @@ impl ModuleInstance {
             tables: RefCell::new(Vec::new()),
             memories: RefCell::new(Vec::new()),
             globals: RefCell::new(Vec::new()),
+            initial_globals: RefCell::new(Vec::new()),
             exports: RefCell::new(BTreeMap::new()),
+            start: RefCell::new(None),
+            module_name: None,
         }
     }
 
@@ -219,6 +296,45 @@ impl ModuleInstance {
         self.globals.borrow()
     }
 
+    /// Restore every mutable global defined by this module to the value of its
+    /// initializer expression, as it was evaluated at instantiation.
+    ///
+    /// Together with restoring the contents of the memories this allows to reuse an
+    /// instance without instantiating the module again. Immutable globals and imported
+    /// globals are left untouched.
+    pub fn reset_globals(&self) {
+        for (global, initial) in self.initial_globals.borrow().iter() {
+            global
+                .set(*initial)
+                .expect("Only mutable globals are recorded, with a value of their type");
+        }
+    }
+
+    /// Returns the `start` function of the module, if it has one.
+    ///
+    /// Instantiation runs the `start` function once, e.g. with [`run_start`]. This gives
+    /// access to it to initialize the instance again, e.g. after [`reset_globals`] and
+    /// restoring the memories. Keep in mind that running it more than once isn't
+    /// necessarily idempotent: it runs against the current state of the instance and of
+    /// its imports, which it might not expect to be already initialized.
+    ///
+    /// [`run_start`]: struct.NotStartedModuleRef.html#method.run_start
+    /// [`reset_globals`]: #method.reset_globals
+    pub fn start_func(&self) -> Option<FuncRef> {
+        self.start.borrow().clone()
+    }
+
+    /// Returns the name the `name` section of the module gives to it, see
+    /// [`Module::module_name`].
+    ///
+    /// Use it to qualify the functions of this instance in diagnostics, e.g. as
+    /// `name::12`, when several instances are linked together.
+    ///
+    /// [`Module::module_name`]: struct.Module.html#method.module_name
+    pub fn module_name(&self) -> Option<&str> {
+        self.module_name.as_deref()
+    }
+
     fn insert_export<N: Into<String>>(&self, name: N, extern_val: ExternVal) {
         self.exports.borrow_mut().insert(name.into(), extern_val);
     }
@@ -226,13 +342,24 @@ impl ModuleInstance {
     fn alloc_module<'i, I: Iterator<Item = &'i ExternVal>>(
         loaded_module: &Module,
         extern_vals: I,
+        budget: &mut AllocationBudget,
     ) -> Result<ModuleRef, Error> {
         let module = loaded_module.module();
-        let instance = ModuleRef(Rc::new(ModuleInstance::default()));
-
-        for &Type::Function(ref ty) in module.type_section().map(|ts| ts.types()).unwrap_or(&[]) {
-            let signature = Rc::new(Signature::from_elements(ty));
-            instance.push_signature(signature);
+        budget.charge(
+            format_args!("the module instance"),
+            mem::size_of::<ModuleInstance>()
+                + loaded_module.compiled().signatures.len() * mem::size_of::<Rc<Signature>>()
+                + module.import_section().map_or(0, |is| is.entries().len())
+                    * mem::size_of::<ExternVal>()
+                + loaded_module.module_name().map_or(0, str::len),
+        )?;
+        let instance = ModuleRef(Rc::new(ModuleInstance {
+            module_name: loaded_module.module_name().map(String::from),
+            ..ModuleInstance::default()
+        }));
+
+        for signature in &loaded_module.compiled().signatures {
+            instance.push_signature(Rc::clone(signature));
         }
 
         {
@@ -312,49 +439,105 @@ impl ModuleInstance {
                 "Due to validation func and body counts must match"
             );
 
+            let funcs_base = module.import_count(ImportCountType::Function);
             for (index, (ty, body)) in Iterator::zip(funcs.iter(), bodies.iter()).enumerate() {
                 let signature = instance
                     .signature_by_index(ty.type_ref())
                     .expect("Due to validation type should exists");
                 let code = code.get(index).expect(
 					"At func validation time labels are collected; Collected labels are added by index; qed",
-				).clone();
+				);
+                budget.charge(
+                    format_args!("function {}", funcs_base + index),
+                    mem::size_of::<FuncInstance>()
+                        + mem::size_of::<FuncRef>()
+                        + mem::size_of_val(body.locals())
+                        + code.heap_size(),
+                )?;
+                let code = code.clone();
                 let func_body = FuncBody {
                     locals: body.locals().to_vec(),
                     code,
                 };
-                let func_instance =
-                    FuncInstance::alloc_internal(Rc::downgrade(&instance.0), signature, func_body);
+                let func_instance = FuncInstance::alloc_internal(
+                    Rc::downgrade(&instance.0),
+                    (funcs_base + index) as u32,
+                    signature,
+                    func_body,
+                );
                 instance.push_func(func_instance);
             }
         }
 
-        for table_type in module.table_section().map(|ts| ts.entries()).unwrap_or(&[]) {
+        let tables_base = module.import_count(ImportCountType::Table);
+        for (index, table_type) in module
+            .table_section()
+            .map(|ts| ts.entries())
+            .unwrap_or(&[])
+            .iter()
+            .enumerate()
+        {
+            budget.charge(
+                format_args!("table {}", tables_base + index),
+                mem::size_of::<TableInstance>()
+                    + table_type.limits().initial() as usize * mem::size_of::<Option<FuncRef>>(),
+            )?;
             let table =
                 TableInstance::alloc(table_type.limits().initial(), table_type.limits().maximum())?;
             instance.push_table(table);
         }
 
-        for memory_type in module
+        let memories_base = module.import_count(ImportCountType::Memory);
+        for (index, memory_type) in module
             .memory_section()
             .map(|ms| ms.entries())
             .unwrap_or(&[])
+            .iter()
+            .enumerate()
         {
             let initial: Pages = Pages(memory_type.limits().initial() as usize);
+            budget.charge(
+                format_args!("memory {}", memories_base + index),
+                mem::size_of::<MemoryInstance>()
+                    + initial.0.saturating_mul(LINEAR_MEMORY_PAGE_SIZE.0),
+            )?;
             let maximum: Option<Pages> = memory_type.limits().maximum().map(|m| Pages(m as usize));
 
-            let memory = MemoryInstance::alloc(initial, maximum)
-                .expect("Due to validation `initial` and `maximum` should be valid");
+            #[cfg(feature = "atomics")]
+            let shared = memory_type.limits().shared();
+            #[cfg(not(feature = "atomics"))]
+            let shared = false;
+
+            // Due to validation `initial` and `maximum` are valid, so this can only fail to
+            // allocate.
+            let memory = if shared {
+                MemoryInstance::alloc_shared(initial, maximum)?
+            } else {
+                MemoryInstance::alloc(initial, maximum)?
+            };
             instance.push_memory(memory);
         }
 
-        for global_entry in module
+        let globals_base = module.import_count(ImportCountType::Global);
+        for (index, global_entry) in module
             .global_section()
             .map(|gs| gs.entries())
             .unwrap_or(&[])
+            .iter()
+            .enumerate()
         {
+            budget.charge(
+                format_args!("global {}", globals_base + index),
+                mem::size_of::<GlobalInstance>() + mem::size_of::<GlobalRef>(),
+            )?;
             let init_val = eval_init_expr(global_entry.init_expr(), &*instance);
             let global = GlobalInstance::alloc(init_val, global_entry.global_type().is_mutable());
+            if global.is_mutable() {
+                instance
+                    .initial_globals
+                    .borrow_mut()
+                    .push((global.clone(), init_val));
+            }
             instance.push_global(global);
         }
 
@@ -364,6 +547,10 @@ impl ModuleInstance {
             .unwrap_or(&[])
         {
             let field = export.field();
+            budget.charge(
+                format_args!("export `{}`", field),
+                field.len() + mem::size_of::<ExternVal>(),
+            )?;
             let extern_val: ExternVal = match *export.internal() {
                 Internal::Function(idx) => {
                     let func = instance
@@ -393,6 +580,13 @@ impl ModuleInstance {
             instance.insert_export(field, extern_val);
         }
 
+        if let Some(start_fn_idx) = module.start_section() {
+            let start_func = instance
+                .func_by_index(start_fn_idx)
+                .expect("Due to validation start function should exists");
+            *instance.start.borrow_mut() = Some(start_func);
+        }
+
         Ok(instance)
     }
 
@@ -405,15 +599,32 @@ impl ModuleInstance {
     pub fn with_externvals<'a, 'i, I: Iterator<Item = &'i ExternVal>>(
         loaded_module: &'a Module,
         extern_vals: I,
+    ) -> Result<NotStartedModuleRef<'a>, Error> {
+        Self::instantiate(
+            loaded_module,
+            extern_vals,
+            &mut AllocationBudget::unlimited(),
+        )
+    }
+
+    fn instantiate<'a, 'i, I: Iterator<Item = &'i ExternVal>>(
+        loaded_module: &'a Module,
+        extern_vals: I,
+        budget: &mut AllocationBudget,
     ) -> Result<NotStartedModuleRef<'a>, Error> {
         let module = loaded_module.module();
 
-        let module_ref = ModuleInstance::alloc_module(loaded_module, extern_vals)?;
+        let module_ref = ModuleInstance::alloc_module(loaded_module, extern_vals, budget)?;
 
-        for element_segment in module
+        // All segments are checked before any of them is written, so that a failed
+        // instantiation has no effect on imported tables and memories.
+        let mut element_writes = Vec::new();
+        for (index, element_segment) in module
             .elements_section()
             .map(|es| es.entries())
             .unwrap_or(&[])
+            .iter()
+            .enumerate()
         {
             let offset = element_segment
                 .offset()
@@ -425,29 +636,48 @@ impl ModuleInstance {
             };
 
             let table_inst = module_ref
-                .table_by_index(DEFAULT_TABLE_INDEX)
-                .expect("Due to validation default table should exists");
-
-            // This check is not only for bailing out early, but also to check the case when
-            // segment consist of 0 members.
-            if offset_val as u64 + element_segment.members().len() as u64
-                > table_inst.current_size() as u64
-            {
-                return Err(Error::Instantiation(
-                    "elements segment does not fit".to_string(),
-                ));
+                .table_by_index(element_segment.index())
+                .expect("Due to validation table should exists");
+
+            // This check also covers segments that consist of 0 members.
+            let len = element_segment.members().len();
+            if offset_val as u64 + len as u64 > table_inst.current_size() as u64 {
+                return Err(Error::SegmentDoesNotFit {
+                    kind: SegmentKind::Element,
+                    index,
+                    offset: offset_val,
+                    len,
+                    size: table_inst.current_size() as usize,
+                });
             }
 
-            for (j, func_idx) in element_segment.members().iter().enumerate() {
-                let func = module_ref
-                    .func_by_index(*func_idx)
-                    .expect("Due to validation funcs from element segments should exists");
-
-                table_inst.set(offset_val + j as u32, Some(func))?;
-            }
+            budget.charge(
+                format_args!("element segment {}", index),
+                len * mem::size_of::<FuncRef>(),
+            )?;
+            let funcs = element_segment
+                .members()
+                .iter()
+                .map(|func_idx| {
+                    module_ref.func_by_index(*func_idx).ok_or_else(|| {
+                        Error::Instantiation(format!(
+                            "elements segment refers to function {}, which doesn't exist",
+                            func_idx
+                        ))
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            element_writes.push((table_inst, offset_val, funcs));
         }
 
-        for data_segment in module.data_section().map(|ds| ds.entries()).unwrap_or(&[]) {
+        let mut data_writes = Vec::new();
+        for (index, data_segment) in module
+            .data_section()
+            .map(|ds| ds.entries())
+            .unwrap_or(&[])
+            .iter()
+            .enumerate()
+        {
             let offset = data_segment
                 .offset()
                 .as_ref()
@@ -460,7 +690,28 @@ impl ModuleInstance {
             let memory_inst = module_ref
                 .memory_by_index(DEFAULT_MEMORY_INDEX)
                 .expect("Due to validation default memory should exists");
-            memory_inst.set(offset_val, data_segment.value())?;
+
+            let len = data_segment.value().len();
+            let size = Bytes::from(memory_inst.current_size()).0;
+            if offset_val as u64 + len as u64 > size as u64 {
+                return Err(Error::SegmentDoesNotFit {
+                    kind: SegmentKind::Data,
+                    index,
+                    offset: offset_val,
+                    len,
+                    size,
+                });
+            }
+            data_writes.push((memory_inst, offset_val, data_segment.value()));
+        }
+
+        for (table_inst, offset, funcs) in element_writes {
+            for (j, func) in funcs.into_iter().enumerate() {
+                table_inst.set(offset + j as u32, Some(func))?;
+            }
+        }
+        for (memory_inst, offset, value) in data_writes {
+            memory_inst.set(offset, value)?;
         }
 
         Ok(NotStartedModuleRef {
@@ -488,6 +739,9 @@ impl ModuleInstance {
     /// there is a mismatch between requested import and provided (e.g. module requested memory with no
     /// maximum size limit, however, was provided memory with the maximum size limit).
     ///
+    /// If the memory the module declares can't be allocated, returns
+    /// [`Error::MemoryAllocationFailed`] instead of aborting.
+    ///
     /// # Examples
     ///
     /// ```rust
@@ -529,9 +783,78 @@ impl ModuleInstance {
     /// [`NotStartedModuleRef`]: struct.NotStartedModuleRef.html
     /// [`ImportResolver`]: trait.ImportResolver.html
     /// [`assert_no_start`]: struct.NotStartedModuleRef.html#method.assert_no_start
+    /// [`Error::MemoryAllocationFailed`]: enum.Error.html#variant.MemoryAllocationFailed
     pub fn new<'m, I: ImportResolver>(
         loaded_module: &'m Module,
         imports: &I,
+    ) -> Result<NotStartedModuleRef<'m>, Error> {
+        Self::new_with_budget(loaded_module, imports, &mut AllocationBudget::unlimited())
+    }
+
+    /// Instantiate a [module][`Module`], failing if that would allocate more than
+    /// `max_bytes`.
+    ///
+    /// This is the same as [`new`], except that the major allocations of the
+    /// instantiation are tallied: the instance itself, the functions with their
+    /// compiled code, the tables, memories and globals defined by the module, its
+    /// exports and the functions of its element segments. Each allocation is checked
+    /// against the limit before it is made, so a module exceeding the limit is rejected
+    /// before e.g. a huge memory is allocated. Imported entities aren't counted, they
+    /// were allocated by the embedder. The sizes are estimates of the heap usage, they
+    /// don't include the overhead of the allocator.
+    ///
+    /// This bounds the cost of instantiating untrusted modules with a single number,
+    /// in addition to the limits of their memories and tables.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`new`]. Additionally, returns [`Error::Instantiation`] if `max_bytes`
+    /// would be exceeded.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # extern crate wasmi;
+    /// # extern crate wabt;
+    /// use wasmi::{ImportsBuilder, ModuleInstance};
+    ///
+    /// let wasm_binary: Vec<u8> = wabt::wat2wasm("(module (memory 16))").unwrap();
+    /// let module = wasmi::Module::from_buffer(&wasm_binary).unwrap();
+    ///
+    /// // 16 pages are 1 MiB.
+    /// assert!(ModuleInstance::new_with_allocation_limit(
+    ///     &module,
+    ///     &ImportsBuilder::default(),
+    ///     512 * 1024,
+    /// )
+    /// .is_err());
+    /// assert!(ModuleInstance::new_with_allocation_limit(
+    ///     &module,
+    ///     &ImportsBuilder::default(),
+    ///     2 * 1024 * 1024,
+    /// )
+    /// .is_ok());
+    /// ```
+    ///
+    /// [`Module`]: struct.Module.html
+    /// [`new`]: #method.new
+    /// [`Error::Instantiation`]: enum.Error.html#variant.Instantiation
+    pub fn new_with_allocation_limit<'m, I: ImportResolver>(
+        loaded_module: &'m Module,
+        imports: &I,
+        max_bytes: usize,
+    ) -> Result<NotStartedModuleRef<'m>, Error> {
+        Self::new_with_budget(
+            loaded_module,
+            imports,
+            &mut AllocationBudget::new(max_bytes),
+        )
+    }
+
+    fn new_with_budget<'m, I: ImportResolver>(
+        loaded_module: &'m Module,
+        imports: &I,
+        budget: &mut AllocationBudget,
     ) -> Result<NotStartedModuleRef<'m>, Error> {
         let module = loaded_module.module();
 
@@ -541,12 +864,8 @@ impl ModuleInstance {
             let field_name = import_entry.field();
             let extern_val = match *import_entry.external() {
                 External::Function(fn_ty_idx) => {
-                    let types = module.type_section().map(|s| s.types()).unwrap_or(&[]);
-                    let &Type::Function(ref func_type) = types
-                        .get(fn_ty_idx as usize)
-                        .expect("Due to validation functions should have valid types");
-                    let signature = Signature::from_elements(func_type);
-                    let func = imports.resolve_func(module_name, field_name, &signature)?;
+                    let signature = loaded_module.compiled().signature(fn_ty_idx);
+                    let func = imports.resolve_func(module_name, field_name, signature)?;
                     ExternVal::Func(func)
                 }
                 External::Table(ref table_type) => {
@@ -571,7 +890,7 @@ impl ModuleInstance {
             extern_vals.push(extern_val);
         }
 
-        Self::with_externvals(loaded_module, extern_vals.iter())
+        Self::instantiate(loaded_module, extern_vals.iter(), budget)
     }
 
     /// Invoke exported function by a name.
@@ -579,6 +898,10 @@ impl ModuleInstance {
     /// This function finds exported function by a name, and calls it with provided arguments and
     /// external state.
     ///
+    /// The arguments are borrowed, so they can be passed in an array on the stack, and the
+    /// result is returned by value. The stacks of the interpreter are allocated anew on each
+    /// call though; use [`invoke_export_with_stack`] or [`prepare_call`] to reuse them instead.
+    ///
     /// # Errors
     ///
     /// Returns `Err` if:
@@ -622,6 +945,9 @@ impl ModuleInstance {
     /// );
     /// # }
     /// ```
+    ///
+    /// [`invoke_export_with_stack`]: #method.invoke_export_with_stack
+    /// [`prepare_call`]: #method.prepare_call
     pub fn invoke_export<E: Externals>(
         &self,
         func_name: &str,
@@ -633,8 +959,170 @@ impl ModuleInstance {
         FuncInstance::invoke(&func_instance, args, externals).map_err(Error::Trap)
     }
 
+    /// Invoke exported function by a name, writing its results to the start of `results`.
+    ///
+    /// Returns the number of results written. As function results are returned by
+    /// value, [`invoke_export`] doesn't allocate for them either, but a loop calling
+    /// functions with different signatures can keep the results in one buffer this way.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`invoke_export`], and returns `Err` if `results` can't hold the results
+    /// of the function, in which case the function isn't called.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # extern crate wasmi;
+    /// # extern crate wabt;
+    /// # use wasmi::{ModuleInstance, ImportsBuilder, NopExternals, RuntimeValue};
+    /// # fn main() {
+    /// # let wasm_binary: Vec<u8> = wabt::wat2wasm(
+    /// #   r#"
+    /// #   (module
+    /// #       (func (export "answer") (result i32) (i32.const 42))
+    /// #       (func (export "nothing"))
+    /// #   )
+    /// #   "#,
+    /// # ).expect("failed to parse wat");
+    /// # let module = wasmi::Module::from_buffer(&wasm_binary).expect("failed to load wasm");
+    /// # let instance = ModuleInstance::new(
+    /// # &module,
+    /// # &ImportsBuilder::default()
+    /// # ).expect("failed to instantiate wasm module").assert_no_start();
+    /// let mut results = [RuntimeValue::I32(0); 1];
+    /// let written = instance
+    ///     .invoke_export_into("answer", &[], &mut results, &mut NopExternals)
+    ///     .expect("failed to execute export");
+    /// assert_eq!(&results[..written], [RuntimeValue::I32(42)]);
+    ///
+    /// let written = instance
+    ///     .invoke_export_into("nothing", &[], &mut results, &mut NopExternals)
+    ///     .expect("failed to execute export");
+    /// assert_eq!(written, 0);
+    /// assert!(instance.invoke_export_into("answer", &[], &mut [], &mut NopExternals).is_err());
+    /// # }
+    /// ```
+    ///
+    /// [`invoke_export`]: #method.invoke_export
+    pub fn invoke_export_into<E: Externals>(
+        &self,
+        func_name: &str,
+        args: &[RuntimeValue],
+        results: &mut [RuntimeValue],
+        externals: &mut E,
+    ) -> Result<usize, Error> {
+        let func_instance = self.func_by_name(func_name)?;
+        let num_results = func_instance.signature().return_type().map_or(0, |_| 1);
+        if results.len() < num_results {
+            return Err(Error::Function(format!(
+                "Export {} has {} results, but the buffer only holds {} values",
+                func_name,
+                num_results,
+                results.len()
+            )));
+        }
+
+        let result = FuncInstance::invoke(&func_instance, args, externals).map_err(Error::Trap)?;
+        if let Some(result) = result {
+            results[0] = result;
+        }
+        Ok(num_results)
+    }
+
+    /// Invoke exported function by a name, converting the arguments to the types of its
+    /// parameters first.
+    ///
+    /// This is meant for host code that doesn't know the exact signature of the export
+    /// statically, e.g. to pass an `I64` to an `i32` parameter. Each argument is
+    /// converted with [`RuntimeValue::coerce_to`] according to `coercion`, so lossy
+    /// conversions only happen if [`Coercion::Truncate`] is requested explicitly.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`invoke_export`], and returns `Err` if the number of arguments doesn't
+    /// match the signature, or naming the first argument that can't be converted. The
+    /// function isn't called then.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # extern crate wasmi;
+    /// # extern crate wabt;
+    /// # use wasmi::{Coercion, ModuleInstance, ImportsBuilder, NopExternals, RuntimeValue};
+    /// # fn main() {
+    /// # let wasm_binary: Vec<u8> = wabt::wat2wasm(
+    /// #   r#"
+    /// #   (module
+    /// #       (func (export "add") (param i32 i64) (result i64)
+    /// #           (i64.add (i64.extend_u/i32 (get_local 0)) (get_local 1)))
+    /// #   )
+    /// #   "#,
+    /// # ).expect("failed to parse wat");
+    /// # let module = wasmi::Module::from_buffer(&wasm_binary).expect("failed to load wasm");
+    /// # let instance = ModuleInstance::new(
+    /// # &module,
+    /// # &ImportsBuilder::default()
+    /// # ).expect("failed to instantiate wasm module").assert_no_start();
+    /// let args = [RuntimeValue::I64(3_000_000_000), RuntimeValue::I32(1)];
+    /// assert_eq!(
+    ///     instance
+    ///         .invoke_export_coerced("add", &args, Coercion::Lossless, &mut NopExternals)
+    ///         .expect("failed to execute export"),
+    ///     Some(RuntimeValue::I64(3_000_000_001)),
+    /// );
+    ///
+    /// let args = [RuntimeValue::I64(1 << 32), RuntimeValue::I32(1)];
+    /// assert!(instance
+    ///     .invoke_export_coerced("add", &args, Coercion::Lossless, &mut NopExternals)
+    ///     .is_err());
+    /// # }
+    /// ```
+    ///
+    /// [`RuntimeValue::coerce_to`]: enum.RuntimeValue.html#method.coerce_to
+    /// [`Coercion::Truncate`]: enum.Coercion.html#variant.Truncate
+    /// [`invoke_export`]: #method.invoke_export
+    pub fn invoke_export_coerced<E: Externals>(
+        &self,
+        func_name: &str,
+        args: &[RuntimeValue],
+        coercion: Coercion,
+        externals: &mut E,
+    ) -> Result<Option<RuntimeValue>, Error> {
+        let func_instance = self.func_by_name(func_name)?;
+        let params = func_instance.signature().params();
+        if params.len() != args.len() {
+            return Err(Error::Function(format!(
+                "Export {} takes {} arguments, but {} were given",
+                func_name,
+                params.len(),
+                args.len()
+            )));
+        }
+        let args = args
+            .iter()
+            .zip(params)
+            .enumerate()
+            .map(|(index, (&arg, &param))| {
+                arg.coerce_to(param, coercion).ok_or_else(|| {
+                    Error::Function(format!(
+                        "Argument {} of export {} is {}, which can't be coerced to {:?}",
+                        index, func_name, arg, param
+                    ))
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        FuncInstance::invoke(&func_instance, &args, externals).map_err(Error::Trap)
+    }
+
     /// Invoke exported function by a name using recycled stacks.
     ///
+    /// Once `stack_recycler` holds the stacks of a previous call, the stacks aren't
+    /// allocated again, unless the call traps or nests deeper than the previous ones.
+    /// This doesn't make the call allocation-free: every call of a host function with
+    /// parameters still allocates the arguments passed to it.
+    ///
     /// # Errors
     ///
     /// Same as [`invoke_export`].
@@ -653,6 +1141,197 @@ impl ModuleInstance {
             .map_err(Error::Trap)
     }
 
+    /// Invoke a function by its index, whether it is exported or not.
+    ///
+    /// `func_index` refers to the function index space of the module, i.e. imported
+    /// functions come first, followed by the functions defined by the module. Unlike
+    /// [`invoke_export`], this doesn't look up a name on each call, and it can call
+    /// functions that were found by analysing the module rather than by their export.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if:
+    ///
+    /// - there is no function with the given index,
+    /// - given arguments doesn't match to function signature,
+    /// - trap occurred at the execution time,
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # extern crate wasmi;
+    /// # extern crate wabt;
+    /// # use wasmi::{ModuleInstance, ImportsBuilder, NopExternals, RuntimeValue};
+    /// # fn main() {
+    /// # let wasm_binary: Vec<u8> = wabt::wat2wasm(
+    /// #   r#"
+    /// #   (module
+    /// #       (func $double (param i32) (result i32)
+    /// #           (i32.shl (get_local 0) (i32.const 1)))
+    /// #   )
+    /// #   "#,
+    /// # ).expect("failed to parse wat");
+    /// # let module = wasmi::Module::from_buffer(&wasm_binary).expect("failed to load wasm");
+    /// # let instance = ModuleInstance::new(
+    /// # &module,
+    /// # &ImportsBuilder::default()
+    /// # ).expect("failed to instantiate wasm module").assert_no_start();
+    /// assert_eq!(
+    ///     instance.invoke_index(0, &[RuntimeValue::I32(21)], &mut NopExternals)
+    ///         .expect("failed to execute function"),
+    ///     Some(RuntimeValue::I32(42)),
+    /// );
+    /// assert!(instance.invoke_index(1, &[], &mut NopExternals).is_err());
+    /// # }
+    /// ```
+    ///
+    /// [`invoke_export`]: #method.invoke_export
+    pub fn invoke_index<E: Externals>(
+        &self,
+        func_index: u32,
+        args: &[RuntimeValue],
+        externals: &mut E,
+    ) -> Result<Option<RuntimeValue>, Error> {
+        let func_instance = self.func_by_index(func_index).ok_or_else(|| {
+            Error::Function(format!(
+                "Module doesn't have function at index {}, it has {} functions",
+                func_index,
+                self.funcs.borrow().len()
+            ))
+        })?;
+
+        FuncInstance::invoke(&func_instance, args, externals).map_err(Error::Trap)
+    }
+
+    /// Invoke exported function by a name, collecting [`CallStats`] of the execution.
+    ///
+    /// The statistics are returned even if the call fails. They are all zero if the
+    /// function couldn't be called at all. The number of executed instructions is the
+    /// same on every platform, under the conditions listed for [`CallStats::instructions`].
+    ///
+    /// # Errors
+    ///
+    /// Same as [`invoke_export`].
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # extern crate wasmi;
+    /// # extern crate wabt;
+    /// # use wasmi::{ModuleInstance, ImportsBuilder, NopExternals, RuntimeValue};
+    /// # fn main() {
+    /// # let wasm_binary: Vec<u8> = wabt::wat2wasm(
+    /// #   r#"
+    /// #   (module
+    /// #       (func $double (param i32) (result i32)
+    /// #           (i32.shl (get_local 0) (i32.const 1)))
+    /// #       (func (export "quadruple") (param i32) (result i32)
+    /// #           (call $double (call $double (get_local 0))))
+    /// #   )
+    /// #   "#,
+    /// # ).expect("failed to parse wat");
+    /// # let module = wasmi::Module::from_buffer(&wasm_binary).expect("failed to load wasm");
+    /// # let instance = ModuleInstance::new(
+    /// # &module,
+    /// # &ImportsBuilder::default()
+    /// # ).expect("failed to instantiate wasm module").assert_no_start();
+    /// let (result, stats) =
+    ///     instance.invoke_export_with_stats("quadruple", &[RuntimeValue::I32(3)], &mut NopExternals);
+    /// assert_eq!(result.expect("failed to execute export"), Some(RuntimeValue::I32(12)));
+    /// assert_eq!(stats.host_calls, 0);
+    /// assert_eq!(stats.max_call_depth, 2);
+    /// # }
+    /// ```
+    ///
+    /// [`CallStats`]: struct.CallStats.html
+    /// [`CallStats::instructions`]: struct.CallStats.html#structfield.instructions
+    /// [`invoke_export`]: #method.invoke_export
+    pub fn invoke_export_with_stats<E: Externals>(
+        &self,
+        func_name: &str,
+        args: &[RuntimeValue],
+        externals: &mut E,
+    ) -> (Result<Option<RuntimeValue>, Error>, CallStats) {
+        let func_instance = match self.func_by_name(func_name) {
+            Ok(func_instance) => func_instance,
+            Err(err) => return (Err(err), CallStats::default()),
+        };
+
+        let (result, stats) = FuncInstance::invoke_with_stats(&func_instance, args, externals);
+        (result.map_err(Error::Trap), stats)
+    }
+
+    /// Resolve an exported function by a name once, for invoking it many times.
+    ///
+    /// The returned [`PreparedCall`] skips the export lookup on each invocation
+    /// and reuses its stacks between invocations.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if:
+    ///
+    /// - there are no export with a given name or this export is not a function,
+    /// - the signature of the function doesn't match `signature`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # extern crate wasmi;
+    /// # extern crate wabt;
+    /// # use wasmi::{ModuleInstance, ImportsBuilder, NopExternals, RuntimeValue, Signature, ValueType};
+    /// # fn main() {
+    /// # let wasm_binary: Vec<u8> = wabt::wat2wasm(
+    /// #   r#"
+    /// #   (module
+    /// #       (func (export "add") (param i32 i32) (result i32)
+    /// #           get_local 0
+    /// #           get_local 1
+    /// #           i32.add
+    /// #       )
+    /// #   )
+    /// #   "#,
+    /// # ).expect("failed to parse wat");
+    /// # let module = wasmi::Module::from_buffer(&wasm_binary).expect("failed to load wasm");
+    /// # let instance = ModuleInstance::new(
+    /// # &module,
+    /// # &ImportsBuilder::default()
+    /// # ).expect("failed to instantiate wasm module").assert_no_start();
+    /// let signature = Signature::new(&[ValueType::I32, ValueType::I32][..], Some(ValueType::I32));
+    /// let mut add = instance
+    ///     .prepare_call("add", &signature)
+    ///     .expect("failed to prepare call");
+    /// for i in 0..10 {
+    ///     assert_eq!(
+    ///         add.invoke(&[RuntimeValue::I32(i), RuntimeValue::I32(1)], &mut NopExternals)
+    ///             .expect("failed to execute export"),
+    ///         Some(RuntimeValue::I32(i + 1)),
+    ///     );
+    /// }
+    /// # }
+    /// ```
+    ///
+    /// [`PreparedCall`]: struct.PreparedCall.html
+    pub fn prepare_call(
+        &self,
+        func_name: &str,
+        signature: &Signature,
+    ) -> Result<PreparedCall, Error> {
+        let func = self.func_by_name(func_name)?;
+        if func.signature() != signature {
+            return Err(Error::Function(format!(
+                "Export {} has signature {:?}, but {:?} was expected",
+                func_name,
+                func.signature(),
+                signature
+            )));
+        }
+
+        Ok(PreparedCall {
+            func,
+            stack_recycler: StackRecycler::default(),
+        })
+    }
+
     fn func_by_name(&self, func_name: &str) -> Result<FuncRef, Error> {
         let extern_val = self
             .export_by_name(func_name)
@@ -675,6 +1354,126 @@ impl ModuleInstance {
     }
 }
 
+/// An exported function resolved once for invoking it many times.
+///
+/// Created by [`ModuleInstance::prepare_call`].
+///
+/// [`ModuleInstance::prepare_call`]: struct.ModuleInstance.html#method.prepare_call
+pub struct PreparedCall {
+    func: FuncRef,
+    stack_recycler: StackRecycler,
+}
+
+impl PreparedCall {
+    /// Returns the resolved function.
+    pub fn func(&self) -> &FuncRef {
+        &self.func
+    }
+
+    /// Invoke the function with the given `args`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if `args` types don't match the signature of the function or
+    /// if a [`Trap`] occurred at the execution time.
+    ///
+    /// [`Trap`]: struct.Trap.html
+    pub fn invoke<E: Externals>(
+        &mut self,
+        args: &[RuntimeValue],
+        externals: &mut E,
+    ) -> Result<Option<RuntimeValue>, Error> {
+        FuncInstance::invoke_with_stack(&self.func, args, externals, &mut self.stack_recycler)
+            .map_err(Error::Trap)
+    }
+
+    /// Invoke a function that returns a buffer in `memory`, and return a view of it.
+    ///
+    /// The function returns the buffer as a `(ptr, len)` pair packed into an `i64`:
+    /// the offset of the buffer in the low 32 bits and its length in the high 32 bits.
+    ///
+    /// The returned [`Region`] borrows `memory`, so it can't outlive it. While the
+    /// region is alive the memory can't be grown, which would move the buffer, and
+    /// any other access to the memory, including invoking functions that use it,
+    /// panics. Drop the region before the next call.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if:
+    ///
+    /// - the function doesn't return an `i64`,
+    /// - `args` types don't match the signature of the function or a [`Trap`] occurred
+    ///   at the execution time,
+    /// - the returned buffer is out of bounds of `memory`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # extern crate wasmi;
+    /// # extern crate wabt;
+    /// # use wasmi::{ImportsBuilder, ModuleInstance, NopExternals, Signature, ValueType};
+    /// # fn main() {
+    /// # let wasm_binary: Vec<u8> = wabt::wat2wasm(
+    /// #   r#"
+    /// #   (module
+    /// #       (memory (export "memory") 1)
+    /// #       (data (i32.const 16) "hello")
+    /// #       ;; ptr = 16, len = 5
+    /// #       (func (export "greeting") (result i64)
+    /// #           i64.const 0x5_0000_0010
+    /// #       )
+    /// #   )
+    /// #   "#,
+    /// # ).expect("failed to parse wat");
+    /// # let module = wasmi::Module::from_buffer(&wasm_binary).expect("failed to load wasm");
+    /// # let instance = ModuleInstance::new(
+    /// # &module,
+    /// # &ImportsBuilder::default()
+    /// # ).expect("failed to instantiate wasm module").assert_no_start();
+    /// let memory = instance
+    ///     .export_by_name("memory")
+    ///     .and_then(|export| export.as_memory().cloned())
+    ///     .expect("no memory export");
+    /// let mut greeting = instance
+    ///     .prepare_call("greeting", &Signature::new(&[][..], Some(ValueType::I64)))
+    ///     .expect("failed to prepare call");
+    /// let region = greeting
+    ///     .invoke_region(&[], &mut NopExternals, &memory)
+    ///     .expect("failed to execute export");
+    /// assert_eq!(region.as_slice(), b"hello");
+    /// # }
+    /// ```
+    ///
+    /// [`Region`]: struct.Region.html
+    /// [`Trap`]: struct.Trap.html
+    pub fn invoke_region<'m, E: Externals>(
+        &mut self,
+        args: &[RuntimeValue],
+        externals: &mut E,
+        memory: &'m MemoryInstance,
+    ) -> Result<Region<'m>, Error> {
+        if self.func.signature().return_type() != Some(ValueType::I64) {
+            return Err(Error::Function(format!(
+                "Function with signature {:?} doesn't return a (ptr, len) pair",
+                self.func.signature()
+            )));
+        }
+
+        let packed = match self.invoke(args, externals)? {
+            Some(RuntimeValue::I64(packed)) => packed as u64,
+            unexpected => {
+                return Err(Error::Function(format!(
+                    "Expected an i64 (ptr, len) pair, got {:?}",
+                    unexpected
+                )))
+            }
+        };
+        let ptr = packed as u32;
+        let len = (packed >> 32) as u32;
+        memory.checked_region(ptr, len as usize)
+    }
+}
+
 /// Mostly instantiated [`ModuleRef`].
 ///
 /// At this point memory segments and tables are copied. However, `start` function (if any) is not called.
@@ -718,11 +1517,7 @@ impl<'a> NotStartedModuleRef<'a> {
     ///
     /// Returns `Err` if start function traps.
     pub fn run_start<E: Externals>(self, state: &mut E) -> Result<ModuleRef, Trap> {
-        if let Some(start_fn_idx) = self.loaded_module.module().start_section() {
-            let start_func = self
-                .instance
-                .func_by_index(start_fn_idx)
-                .expect("Due to validation start function should exists");
+        if let Some(start_func) = self.instance.start_func() {
             FuncInstance::invoke(&start_func, &[], state)?;
         }
         Ok(self.instance)
@@ -738,16 +1533,36 @@ impl<'a> NotStartedModuleRef<'a> {
         state: &mut E,
         stack_recycler: &mut StackRecycler,
     ) -> Result<ModuleRef, Trap> {
-        if let Some(start_fn_idx) = self.loaded_module.module().start_section() {
-            let start_func = self
-                .instance
-                .func_by_index(start_fn_idx)
-                .expect("Due to validation start function should exists");
+        if let Some(start_func) = self.instance.start_func() {
             FuncInstance::invoke_with_stack(&start_func, &[], state, stack_recycler)?;
         }
         Ok(self.instance)
     }
 
+    /// Executes `start` function (if any) with metered execution and returns fully
+    /// instantiated module.
+    ///
+    /// This bounds the work an untrusted `start` function can do during instantiation.
+    /// See [`FuncInstance::invoke_with_fuel`] for how `fuel` is consumed.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if start function traps, in particular with [`TrapKind::OutOfFuel`]
+    /// if it runs out of `fuel`.
+    ///
+    /// [`FuncInstance::invoke_with_fuel`]: struct.FuncInstance.html#method.invoke_with_fuel
+    /// [`TrapKind::OutOfFuel`]: enum.TrapKind.html#variant.OutOfFuel
+    pub fn run_start_with_fuel<E: Externals>(
+        self,
+        state: &mut E,
+        fuel: &mut u64,
+    ) -> Result<ModuleRef, Trap> {
+        if let Some(start_func) = self.instance.start_func() {
+            FuncInstance::invoke_with_fuel(&start_func, &[], state, fuel)?;
+        }
+        Ok(self.instance)
+    }
+
     /// Returns fully instantiated module without running `start` function.
     ///
     /// # Panics
@@ -769,23 +1584,87 @@ impl<'a> NotStartedModuleRef<'a> {
 }
 
 fn eval_init_expr(init_expr: &InitExpr, module: &ModuleInstance) -> RuntimeValue {
-    let code = init_expr.code();
+    let mut stack: Vec<RuntimeValue> = Vec::new();
+    for instruction in init_expr.code() {
+        let value = match *instruction {
+            Instruction::I32Const(v) => v.into(),
+            Instruction::I64Const(v) => v.into(),
+            Instruction::F32Const(v) => RuntimeValue::decode_f32(v),
+            Instruction::F64Const(v) => RuntimeValue::decode_f64(v),
+            Instruction::GetGlobal(idx) => {
+                let global = module
+                    .global_by_index(idx)
+                    .expect("Due to validation global should exists in module");
+                global.get()
+            }
+            Instruction::I32Add | Instruction::I32Sub | Instruction::I32Mul => {
+                let rhs: i32 = pop_init_operand(&mut stack);
+                let lhs: i32 = pop_init_operand(&mut stack);
+                match *instruction {
+                    Instruction::I32Add => lhs.wrapping_add(rhs),
+                    Instruction::I32Sub => lhs.wrapping_sub(rhs),
+                    _ => lhs.wrapping_mul(rhs),
+                }
+                .into()
+            }
+            Instruction::I64Add | Instruction::I64Sub | Instruction::I64Mul => {
+                let rhs: i64 = pop_init_operand(&mut stack);
+                let lhs: i64 = pop_init_operand(&mut stack);
+                match *instruction {
+                    Instruction::I64Add => lhs.wrapping_add(rhs),
+                    Instruction::I64Sub => lhs.wrapping_sub(rhs),
+                    _ => lhs.wrapping_mul(rhs),
+                }
+                .into()
+            }
+            Instruction::End => break,
+            _ => panic!("Due to validation init should be a const expr"),
+        };
+        stack.push(value);
+    }
     debug_assert!(
-        code.len() == 2,
-        "Due to validation `code`.len() should be 2"
+        stack.len() == 1,
+        "Due to validation init expr should produce exactly one value"
     );
-    match code[0] {
-        Instruction::I32Const(v) => v.into(),
-        Instruction::I64Const(v) => v.into(),
-        Instruction::F32Const(v) => RuntimeValue::decode_f32(v),
-        Instruction::F64Const(v) => RuntimeValue::decode_f64(v),
-        Instruction::GetGlobal(idx) => {
-            let global = module
-                .global_by_index(idx)
-                .expect("Due to validation global should exists in module");
-            global.get()
-        }
-        _ => panic!("Due to validation init should be a const expr"),
+    stack
+        .pop()
+        .expect("Due to validation init expr should produce a value")
+}
+
+fn pop_init_operand<T: FromRuntimeValue>(stack: &mut Vec<RuntimeValue>) -> T {
+    stack
+        .pop()
+        .and_then(|value| value.try_into())
+        .expect("Due to validation operands should have the right type")
+}
+
+/// Tally of the bytes allocated by an instantiation, bounded by a limit.
+struct AllocationBudget {
+    limit: usize,
+    used: usize,
+}
+
+impl AllocationBudget {
+    fn new(limit: usize) -> AllocationBudget {
+        AllocationBudget { limit, used: 0 }
+    }
+
+    fn unlimited() -> AllocationBudget {
+        AllocationBudget::new(usize::MAX)
+    }
+
+    /// Account for the allocation of `bytes` for `what`, before it is made.
+    ///
+    /// `what` is only formatted if the limit is exceeded, so charging doesn't allocate.
+    fn charge(&mut self, what: fmt::Arguments, bytes: usize) -> Result<(), Error> {
+        self.used = self.used.saturating_add(bytes);
+        if self.used > self.limit {
+            return Err(Error::Instantiation(format!(
+                "Allocation limit of {} bytes exceeded by {}",
+                self.limit, what
+            )));
+        }
+        Ok(())
     }
 }
 
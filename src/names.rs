@@ -0,0 +1,34 @@
+use crate::producers::split_var_u32;
+use alloc::string::{String, ToString};
+use parity_wasm::elements::{deserialize_buffer, Module, ModuleNameSubsection};
+
+/// Name of the custom section that holds the debug names of a module.
+const NAME_SECTION: &str = "name";
+
+/// Id of the subsection of the `name` section that names the module itself.
+const MODULE_NAME_SUBSECTION: u8 = 0;
+
+/// Returns the name the `name` section of `module` gives to the module.
+///
+/// The `name` section is only used for diagnostics, so a malformed one doesn't make
+/// the module invalid: `None` is returned if the module has no `name` section, if the
+/// section doesn't name the module, or if it is malformed.
+pub fn module_name(module: &Module) -> Option<String> {
+    // The section is only parsed to `Section::Name` if `Module::parse_names` was called.
+    if let Some(names) = module.names_section() {
+        return names.module().map(|module| module.name().to_string());
+    }
+    let section = module
+        .custom_sections()
+        .find(|section| section.name() == NAME_SECTION)?;
+    // Subsections are ordered by their id, so the module name comes first, if at all.
+    // `NameSection::deserialize` can't be called on a payload outside of parity-wasm,
+    // so only the subsection is located here, and decoded by parity-wasm.
+    let (&id, rest) = section.payload().split_first()?;
+    if id != MODULE_NAME_SUBSECTION {
+        return None;
+    }
+    let (len, rest) = split_var_u32(rest)?;
+    let subsection: ModuleNameSubsection = deserialize_buffer(rest.get(..len as usize)?).ok()?;
+    Some(subsection.name().to_string())
+}
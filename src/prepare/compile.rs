@@ -1,4 +1,4 @@
-use alloc::{string::String, vec::Vec};
+use alloc::{collections::TryReserveError, vec::Vec};
 
 use parity_wasm::elements::{BlockType, FuncBody, Instruction};
 
@@ -8,7 +8,7 @@ use validation::func::{
 };
 use validation::stack::StackWithLimit;
 use validation::util::Locals;
-use validation::{Error, FuncValidator};
+use validation::{Error, ErrorKind, FuncValidator};
 
 /// Type of block frame.
 #[derive(Debug, Clone, Copy)]
@@ -59,19 +59,59 @@ impl BlockFrameType {
     }
 }
 
+/// Reject code of `count` instructions if they can't all be addressed, see
+/// [`isa::MAX_INSTRUCTIONS`].
+pub(crate) fn check_instruction_count(count: usize) -> Result<(), Error> {
+    if count > isa::MAX_INSTRUCTIONS as usize {
+        return Err(Error::new(
+            ErrorKind::Other,
+            format!(
+                "Function compiles to {} instructions, above the limit of {}",
+                count,
+                isa::MAX_INSTRUCTIONS
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Returns the most isa instructions `body` can compile to.
+pub(crate) fn instruction_capacity(body: &FuncBody) -> usize {
+    // Each wasm instruction is compiled into at most one isa instruction,
+    // except for `br_table` which is followed by all of its targets.
+    body.code()
+        .elements()
+        .iter()
+        .map(|instruction| match *instruction {
+            Instruction::BrTable(ref br_table_data) => br_table_data.table.len() + 2,
+            _ => 1,
+        })
+        .fold(0usize, |acc, len| acc.saturating_add(len))
+}
+
 pub struct Compiler {
     /// A sink used to emit optimized code.
     sink: Sink,
     label_stack: Vec<BlockFrameType>,
+    /// The most values on the value stack so far, including locals.
+    max_stack_height: usize,
 }
 
 impl FuncValidator for Compiler {
     type Output = isa::Instructions;
-    fn new(_ctx: &FunctionValidationContext, body: &FuncBody) -> Self {
-        let code_len = body.code().elements().len();
+    fn new(ctx: &FunctionValidationContext, body: &FuncBody) -> Result<Self, Error> {
+        let capacity = instruction_capacity(body);
+        check_instruction_count(capacity)?;
+        let sink = Sink::try_with_capacity(capacity).map_err(|_| {
+            Error::new(
+                ErrorKind::Other,
+                format!("Failed to allocate memory for {} instructions", capacity),
+            )
+        })?;
         let mut compiler = Compiler {
-            sink: Sink::with_capacity(code_len),
+            sink,
             label_stack: Vec::new(),
+            max_stack_height: ctx.locals.count() as usize,
         };
 
         // Push implicit frame for the outer function block.
@@ -80,17 +120,107 @@ impl FuncValidator for Compiler {
             .label_stack
             .push(BlockFrameType::Block { end_label });
 
-        compiler
+        Ok(compiler)
     }
     fn next_instruction(
         &mut self,
         ctx: &mut FunctionValidationContext,
         instruction: &Instruction,
     ) -> Result<(), Error> {
-        self.compile_instruction(ctx, instruction)
+        self.compile_instruction(ctx, instruction)?;
+        // Each instruction pushes at most one value, so its result is the highest
+        // the stack gets while it's executed.
+        let stack_height = ctx.locals.count() as usize + ctx.value_stack.len();
+        self.max_stack_height = self.max_stack_height.max(stack_height);
+        Ok(())
     }
     fn finish(self) -> Self::Output {
-        self.sink.into_inner()
+        let mut code = self.sink.into_inner();
+        // Bounded by the validation limits of locals and values.
+        code.set_max_stack_height(self.max_stack_height as u32);
+        code
+    }
+}
+
+/// A [`Compiler`] that also records the wasm instruction each emitted
+/// isa instruction originates from.
+///
+/// Its output is the compiled code along with, for every isa instruction,
+/// the index of the originating instruction in the function body.
+pub struct SourceMappingCompiler {
+    compiler: Compiler,
+    positions: Vec<u32>,
+    position: u32,
+}
+
+impl FuncValidator for SourceMappingCompiler {
+    type Output = (isa::Instructions, Vec<u32>);
+    fn new(ctx: &FunctionValidationContext, body: &FuncBody) -> Result<Self, Error> {
+        Ok(SourceMappingCompiler {
+            compiler: Compiler::new(ctx, body)?,
+            positions: Vec::new(),
+            position: 0,
+        })
+    }
+    fn next_instruction(
+        &mut self,
+        ctx: &mut FunctionValidationContext,
+        instruction: &Instruction,
+    ) -> Result<(), Error> {
+        self.compiler.next_instruction(ctx, instruction)?;
+        // Instructions are appended to the sink, or fused with the last few ones,
+        // so everything past the already recorded ones was emitted for this
+        // instruction and fused ones map to the first instruction of the sequence.
+        let emitted_up_to = self.compiler.sink.cur_pc() as usize;
+        self.positions.resize(emitted_up_to, self.position);
+        self.position += 1;
+        Ok(())
+    }
+    fn finish(self) -> Self::Output {
+        (self.compiler.finish(), self.positions)
+    }
+}
+
+/// A [`Compiler`] that also looks for code that can never be executed.
+///
+/// Its output is the compiled code along with the index of the first instruction
+/// in the function body that can't be reached, if any.
+pub struct LintingCompiler {
+    compiler: Compiler,
+    unreachable_code: Option<u32>,
+    position: u32,
+}
+
+impl FuncValidator for LintingCompiler {
+    type Output = (isa::Instructions, Option<u32>);
+    fn new(ctx: &FunctionValidationContext, body: &FuncBody) -> Result<Self, Error> {
+        Ok(LintingCompiler {
+            compiler: Compiler::new(ctx, body)?,
+            unreachable_code: None,
+            position: 0,
+        })
+    }
+    fn next_instruction(
+        &mut self,
+        ctx: &mut FunctionValidationContext,
+        instruction: &Instruction,
+    ) -> Result<(), Error> {
+        // After an instruction that never passes control further the stack of the
+        // enclosing frame is polymorphic, and only `end` or `else` can make
+        // the following instructions reachable again.
+        let is_block_boundary = matches!(*instruction, Instruction::End | Instruction::Else);
+        if self.unreachable_code.is_none()
+            && !is_block_boundary
+            && top_label(&ctx.frame_stack).polymorphic_stack
+        {
+            self.unreachable_code = Some(self.position);
+        }
+        self.compiler.next_instruction(ctx, instruction)?;
+        self.position += 1;
+        Ok(())
+    }
+    fn finish(self) -> Self::Output {
+        (self.compiler.finish(), self.unreachable_code)
     }
 }
 
@@ -305,15 +435,15 @@ impl Compiler {
                 context.step(instruction)?;
                 self.sink.emit(isa::InstructionInternal::Call(index));
             }
-            CallIndirect(index, _reserved) => {
+            CallIndirect(index, table) => {
                 context.step(instruction)?;
                 self.sink
-                    .emit(isa::InstructionInternal::CallIndirect(index));
+                    .emit(isa::InstructionInternal::CallIndirect(index, table.into()));
             }
 
             Drop => {
                 context.step(instruction)?;
-                self.sink.emit(isa::InstructionInternal::Drop);
+                self.sink.emit_drop();
             }
             Select => {
                 context.step(instruction)?;
@@ -330,7 +460,7 @@ impl Compiler {
             SetLocal(index) => {
                 context.step(instruction)?;
                 let depth = relative_local_depth(index, &context.locals, &context.value_stack)?;
-                self.sink.emit(isa::InstructionInternal::SetLocal(depth));
+                self.sink.emit_set_local(depth);
             }
             TeeLocal(index) => {
                 context.step(instruction)?;
@@ -449,6 +579,25 @@ impl Compiler {
                 self.sink.emit(isa::InstructionInternal::GrowMemory);
             }
 
+            #[cfg(feature = "atomics")]
+            Atomics(ref atomic) => {
+                use parity_wasm::elements::AtomicsInstruction;
+
+                context.step(instruction)?;
+                self.sink.emit(match *atomic {
+                    AtomicsInstruction::AtomicWake(ref memarg) => {
+                        isa::InstructionInternal::AtomicNotify(memarg.offset)
+                    }
+                    AtomicsInstruction::I32AtomicWait(ref memarg) => {
+                        isa::InstructionInternal::I32AtomicWait(memarg.offset)
+                    }
+                    AtomicsInstruction::I64AtomicWait(ref memarg) => {
+                        isa::InstructionInternal::I64AtomicWait(memarg.offset)
+                    }
+                    _ => unreachable!("Due to validation only wait and notify are allowed"),
+                });
+            }
+
             I32Const(v) => {
                 context.step(instruction)?;
                 self.sink.emit(isa::InstructionInternal::I32Const(v));
@@ -620,7 +769,7 @@ impl Compiler {
             }
             I32Add => {
                 context.step(instruction)?;
-                self.sink.emit(isa::InstructionInternal::I32Add);
+                self.sink.emit_i32_add();
             }
             I32Sub => {
                 context.step(instruction)?;
@@ -628,7 +777,7 @@ impl Compiler {
             }
             I32Mul => {
                 context.step(instruction)?;
-                self.sink.emit(isa::InstructionInternal::I32Mul);
+                self.sink.emit_i32_mul();
             }
             I32DivS => {
                 context.step(instruction)?;
@@ -1005,18 +1154,21 @@ fn compute_drop_keep(
         0
     } else {
         if actual_value_stack_height < start_value_stack_height {
-            return Err(Error(format!(
+            return Err(Error::new(ErrorKind::StackHeightMismatch, format!(
                 "Stack underflow detected: value stack height ({}) is lower than minimum stack len ({})",
                 actual_value_stack_height,
                 start_value_stack_height,
             )));
         }
         if (actual_value_stack_height as u32 - start_value_stack_height as u32) < keep.count() {
-            return Err(Error(format!(
-                "Stack underflow detected: asked to keep {:?} values, but there are only {}",
-                keep,
-                actual_value_stack_height as u32 - start_value_stack_height as u32,
-            )));
+            return Err(Error::new(
+                ErrorKind::StackHeightMismatch,
+                format!(
+                    "Stack underflow detected: asked to keep {:?} values, but there are only {}",
+                    keep,
+                    actual_value_stack_height as u32 - start_value_stack_height as u32,
+                ),
+            ));
         }
         (actual_value_stack_height as u32 - start_value_stack_height as u32) - keep.count()
     };
@@ -1074,8 +1226,9 @@ fn drop_keep_return(
     frame_stack: &StackWithLimit<BlockFrame>,
 ) -> Result<isa::DropKeep, Error> {
     if frame_stack.is_empty() {
-        return Err(Error(
-            "drop_keep_return can't be called with the frame stack empty".into(),
+        return Err(Error::new(
+            ErrorKind::Other,
+            "drop_keep_return can't be called with the frame stack empty",
         ));
     }
 
@@ -1114,7 +1267,7 @@ fn relative_local_depth(
     let depth = value_stack_height
         .checked_add(locals_and_params_count)
         .and_then(|x| x.checked_sub(idx))
-        .ok_or_else(|| Error(String::from("Locals range not in 32-bit range")))?;
+        .ok_or_else(|| Error::new(ErrorKind::Other, "Locals range not in 32-bit range"))?;
     Ok(depth)
 }
 
@@ -1141,14 +1294,17 @@ enum Label {
 struct Sink {
     ins: isa::Instructions,
     labels: Vec<(Label, Vec<isa::Reloc>)>,
+    /// The pc at which a label was most recently resolved, i.e. the last point a branch can land.
+    last_label_pc: Option<u32>,
 }
 
 impl Sink {
-    fn with_capacity(capacity: usize) -> Sink {
-        Sink {
-            ins: isa::Instructions::with_capacity(capacity),
+    fn try_with_capacity(capacity: usize) -> Result<Sink, TryReserveError> {
+        Ok(Sink {
+            ins: isa::Instructions::try_with_capacity(capacity)?,
             labels: Vec::new(),
-        }
+            last_label_pc: None,
+        })
     }
 
     fn cur_pc(&self) -> u32 {
@@ -1173,6 +1329,75 @@ impl Sink {
         self.ins.push(instruction);
     }
 
+    /// Returns the last `count` emitted instructions if they can be fused with the
+    /// one being emitted, i.e. if no branch can land after the first of them.
+    fn fusable_tail(&self, count: u32) -> Option<&[isa::InstructionInternal]> {
+        let start = self.cur_pc().checked_sub(count)?;
+        match self.last_label_pc {
+            Some(label_pc) if label_pc > start => None,
+            _ => Some(self.ins.tail(start)),
+        }
+    }
+
+    /// Replace the last `count` emitted instructions with `instruction`.
+    fn replace_tail(&mut self, count: u32, instruction: isa::InstructionInternal) {
+        self.ins.truncate(self.cur_pc() - count);
+        self.ins.push(instruction);
+    }
+
+    /// Emit `i32.add`, fusing `get_local; get_local; i32.add` and
+    /// `get_local; i32.const; i32.add`.
+    fn emit_i32_add(&mut self) {
+        use crate::isa::InstructionInternal::*;
+
+        // The depth of the second `get_local` was computed with the first local
+        // already pushed, so it's one less for the fused instruction.
+        let fused = match *self.fusable_tail(2).unwrap_or(&[]) {
+            [GetLocal(left), GetLocal(right)] => I32AddLocals(left, right - 1),
+            [GetLocal(local), I32Const(value)] => I32AddLocalConst(local, value),
+            _ => return self.emit(I32Add),
+        };
+        self.replace_tail(2, fused);
+    }
+
+    /// Emit `i32.mul`, fusing `get_local; get_local; i32.mul`.
+    fn emit_i32_mul(&mut self) {
+        use crate::isa::InstructionInternal::*;
+
+        let fused = match *self.fusable_tail(2).unwrap_or(&[]) {
+            [GetLocal(left), GetLocal(right)] => I32MulLocals(left, right - 1),
+            _ => return self.emit(I32Mul),
+        };
+        self.replace_tail(2, fused);
+    }
+
+    /// Emit `drop`, eliding it together with a preceding instruction that only
+    /// pushes a value without side effects.
+    fn emit_drop(&mut self) {
+        use crate::isa::InstructionInternal::*;
+
+        match *self.fusable_tail(1).unwrap_or(&[]) {
+            [GetLocal(_) | GetGlobal(_) | I32Const(_) | I64Const(_) | F32Const(_) | F64Const(_)] => {
+                self.ins.truncate(self.cur_pc() - 1)
+            }
+            _ => self.emit(Drop),
+        }
+    }
+
+    /// Emit `set_local`, fusing it with a preceding fused `i32.add`.
+    fn emit_set_local(&mut self, depth: u32) {
+        use crate::isa::InstructionInternal::*;
+
+        // The sum is popped before the local is set, so all depths are relative to
+        // the same stack pointer.
+        let fused = match *self.fusable_tail(1).unwrap_or(&[]) {
+            [I32AddLocals(left, right)] => I32AddLocalsToLocal(left, right, depth),
+            [I32AddLocalConst(local, value)] => I32AddLocalConstToLocal(local, value, depth),
+            _ => return self.emit(SetLocal(depth)),
+        };
+        self.replace_tail(1, fused);
+    }
+
     fn emit_br(&mut self, target: Target) {
         let Target { label, drop_keep } = target;
         let pc = self.cur_pc();
@@ -1253,6 +1478,7 @@ impl Sink {
 
         // Mark this label as resolved.
         self.labels[label.0] = (Label::Resolved(dst_pc), Vec::new());
+        self.last_label_pc = Some(dst_pc);
     }
 
     /// Consume this Sink and returns isa::Instructions.
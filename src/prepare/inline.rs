@@ -0,0 +1,169 @@
+//! Inlining of small functions at their call sites.
+//!
+//! Only leaf functions are inlined, i.e. functions that don't call any function
+//! themselves, so inlining never recurses. Their code is copied in place of the `call`
+//! instruction, which works without remapping any local, because the stack layout of
+//! the inlined code is the same as the one of a call:
+//!
+//! - the arguments are already on the stack, where the callee expects its parameters,
+//! - the locals of the callee are pushed as zeros, as on the entry of a call,
+//! - the `return`s of the callee become branches to the end of the inlined code, which
+//!   drop the values of the callee and keep the result just like returning does.
+//!
+//! Branch targets of both the caller and the inlined code are relocated to the new
+//! positions of the instructions.
+
+use crate::isa::{InstructionInternal, Instructions, Target, MAX_INSTRUCTIONS};
+use alloc::vec::Vec;
+use parity_wasm::elements::{ImportCountType, Module};
+
+/// A function that can be inlined.
+struct Inlinee<'a> {
+    code: &'a [InstructionInternal],
+    num_locals: u32,
+    max_stack_height: u32,
+}
+
+impl<'a> Inlinee<'a> {
+    /// Number of instructions the inlined code takes up.
+    fn len(&self) -> u32 {
+        self.num_locals + self.code.len() as u32
+    }
+}
+
+/// Inline the calls of leaf functions of at most `max_callee_size` instructions,
+/// counting the pushes of their locals, in all function bodies of `code_map`.
+///
+/// Functions that execute `unreachable` are never inlined, so that the trap still
+/// names the function that raised it.
+pub fn inline_small_functions(
+    code_map: &mut [Instructions],
+    module: &Module,
+    max_callee_size: u32,
+) {
+    let imported_funcs = module.import_count(ImportCountType::Function) as u32;
+    let bodies = module.code_section().map_or(&[][..], |cs| cs.bodies());
+
+    let inlinees: Vec<Option<Inlinee>> = code_map
+        .iter()
+        .zip(bodies)
+        .map(|(code, body)| {
+            let num_locals = body
+                .locals()
+                .iter()
+                .try_fold(0u32, |sum, local| sum.checked_add(local.count()))?;
+            let inlinee = Inlinee {
+                code: code.tail(0),
+                num_locals,
+                max_stack_height: code.max_stack_height(),
+            };
+            let is_leaf = inlinee.code.iter().all(|instruction| {
+                !matches!(
+                    instruction,
+                    InstructionInternal::Call(_)
+                        | InstructionInternal::CallIndirect(..)
+                        | InstructionInternal::Unreachable
+                )
+            });
+            let fits =
+                (inlinee.code.len() as u64 + u64::from(num_locals)) <= u64::from(max_callee_size);
+            if is_leaf && fits {
+                Some(inlinee)
+            } else {
+                None
+            }
+        })
+        .collect();
+    let inlinee = |func_idx: u32| {
+        func_idx
+            .checked_sub(imported_funcs)
+            .and_then(|body_idx| inlinees.get(body_idx as usize))
+            .and_then(Option::as_ref)
+    };
+
+    let inlined: Vec<Option<Instructions>> = code_map
+        .iter()
+        .map(|code| inline_calls(code, &inlinee))
+        .collect();
+    for (code, inlined) in code_map.iter_mut().zip(inlined) {
+        if let Some(inlined) = inlined {
+            *code = inlined;
+        }
+    }
+}
+
+/// Returns `code` with the calls of inlinable functions inlined, or `None` if it
+/// doesn't call any, or if the inlined code would exceed [`isa::MAX_INSTRUCTIONS`].
+fn inline_calls<'a, F>(code: &Instructions, inlinee: &F) -> Option<Instructions>
+where
+    F: Fn(u32) -> Option<&'a Inlinee<'a>>,
+{
+    let instructions = code.tail(0);
+
+    // The new position of each instruction, and of the end of the code.
+    let mut new_pcs = Vec::with_capacity(instructions.len() + 1);
+    let mut pc = 0u32;
+    let mut max_inlinee_height = None;
+    for instruction in instructions {
+        new_pcs.push(pc);
+        let len = match *instruction {
+            InstructionInternal::Call(func_idx) => match inlinee(func_idx) {
+                Some(inlinee) => {
+                    let height = max_inlinee_height.get_or_insert(0);
+                    *height = (*height).max(inlinee.max_stack_height);
+                    inlinee.len()
+                }
+                None => 1,
+            },
+            _ => 1,
+        };
+        pc = pc.checked_add(len).filter(|&pc| pc <= MAX_INSTRUCTIONS)?;
+    }
+    new_pcs.push(pc);
+    let max_inlinee_height = max_inlinee_height?;
+
+    let mut inlined = Instructions::try_with_capacity(pc as usize).ok()?;
+    // The code of a caller runs with at most its own height on the stack when it calls.
+    inlined.set_max_stack_height(code.max_stack_height().saturating_add(max_inlinee_height));
+    for &instruction in instructions {
+        match instruction {
+            InstructionInternal::Call(func_idx) if inlinee(func_idx).is_some() => {
+                let inlinee = inlinee(func_idx).expect("checked by the guard");
+                for _ in 0..inlinee.num_locals {
+                    inlined.push(InstructionInternal::I32Const(0));
+                }
+                let base = inlined.current_pc();
+                let end = base + inlinee.code.len() as u32;
+                for &instruction in inlinee.code {
+                    inlined.push(match instruction {
+                        InstructionInternal::Return(drop_keep) => InstructionInternal::Br(Target {
+                            dst_pc: end,
+                            drop_keep,
+                        }),
+                        other => map_target(other, |dst_pc| base + dst_pc),
+                    });
+                }
+            }
+            other => inlined.push(map_target(other, |dst_pc| new_pcs[dst_pc as usize])),
+        }
+    }
+    debug_assert_eq!(inlined.current_pc(), pc);
+    Some(inlined)
+}
+
+/// Returns `instruction` with the destination of its branch target, if any, mapped by `f`.
+fn map_target<F: Fn(u32) -> u32>(instruction: InstructionInternal, f: F) -> InstructionInternal {
+    let map = |target: Target| Target {
+        dst_pc: f(target.dst_pc),
+        ..target
+    };
+    match instruction {
+        InstructionInternal::Br(target) => InstructionInternal::Br(map(target)),
+        InstructionInternal::BrIfEqz(target) => InstructionInternal::BrIfEqz(map(target)),
+        InstructionInternal::BrIfNez(target) => InstructionInternal::BrIfNez(map(target)),
+        InstructionInternal::BrTableTarget(target) => {
+            InstructionInternal::BrTableTarget(map(target))
+        }
+        other => other,
+    }
+}
@@ -1,12 +1,17 @@
-use crate::isa;
-use alloc::vec::Vec;
-use parity_wasm::elements::Module;
-use validation::{validate_module, Error, Validator};
-
-#[cfg(feature = "core")]
-use crate::alloc::string::ToString;
+use crate::isa::{self, OpcodeKind};
+use crate::memory::LINEAR_MEMORY_PAGE_SIZE;
+use crate::memory_units::Pages;
+use crate::names;
+use crate::types::{ExportKind, Signature};
+use alloc::{collections::BTreeMap, rc::Rc, string::String, vec::Vec};
+use core::{cmp, fmt, mem};
+use parity_wasm::elements::{self, FuncBody, Module, Serialize, Type, VarUint32};
+use validation::{
+    validate_module, Error, ErrorKind, FuncValidator, Validator, DEFAULT_MEMORY_INDEX,
+};
 
 mod compile;
+mod inline;
 
 #[cfg(test)]
 mod tests;
@@ -15,35 +20,1095 @@ mod tests;
 pub struct CompiledModule {
     pub code_map: Vec<isa::Instructions>,
     pub module: Module,
+    /// For each function body, the byte offset of the wasm instruction each isa
+    /// instruction was compiled from.
+    ///
+    /// The offsets are relative to the start of the code section payload, which is
+    /// how DWARF for wasm addresses code.
+    ///
+    /// Only present if the module was compiled with [`compile_module_with_source_offsets`].
+    pub source_offsets: Option<Vec<Vec<u32>>>,
+    /// The signatures of the type section, resolved once so that every instance of
+    /// the module shares them.
+    pub signatures: Vec<Rc<Signature>>,
+    /// The name the `name` section gives to the module, parsed once so that diagnostics
+    /// of every instance can refer to it.
+    module_name: Option<String>,
 }
 
-pub struct WasmiValidation {
-    code_map: Vec<isa::Instructions>,
+impl CompiledModule {
+    fn new(
+        code_map: Vec<isa::Instructions>,
+        module: Module,
+        source_offsets: Option<Vec<Vec<u32>>>,
+    ) -> CompiledModule {
+        let signatures = module
+            .type_section()
+            .map_or(&[][..], |ts| ts.types())
+            .iter()
+            .map(|Type::Function(ty)| Rc::new(Signature::from_elements(ty)))
+            .collect();
+        let module_name = names::module_name(&module);
+        CompiledModule {
+            code_map,
+            module,
+            source_offsets,
+            signatures,
+            module_name,
+        }
+    }
+
+    /// Returns the name the `name` section gives to the module, if any.
+    pub fn module_name(&self) -> Option<&str> {
+        self.module_name.as_deref()
+    }
+
+    /// Returns the signature of the type `type_index` of the type section.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no such type.
+    pub fn signature(&self, type_index: u32) -> &Signature {
+        &self.signatures[type_index as usize]
+    }
+
+    /// Returns the payload of the DWARF custom section `name`, e.g. `.debug_info`.
+    ///
+    /// Custom sections are kept along with the rest of the module, so they don't need
+    /// to be parsed from the original binary again. Returns `None` if `name` isn't a
+    /// `.debug_*` name or if the module has no such section.
+    pub fn debug_section(&self, name: &str) -> Option<&[u8]> {
+        if !name.starts_with(".debug_") {
+            return None;
+        }
+        self.module
+            .custom_sections()
+            .find(|section| section.name() == name)
+            .map(|section| section.payload())
+    }
+
+    /// Returns the functions called by the function `func_idx`, see [`Callees`].
+    ///
+    /// `func_idx` is an index in the function index space, i.e. imported functions
+    /// are counted. Returns `None` for imported functions and for out of bounds indices.
+    pub fn direct_callees(&self, func_idx: u32) -> Option<Callees> {
+        let imported_funcs = self
+            .module
+            .import_count(elements::ImportCountType::Function);
+        let body_idx = (func_idx as usize).checked_sub(imported_funcs)?;
+        let code = self.code_map.get(body_idx)?;
+
+        let mut callees = Callees::default();
+        for instruction in code.iterate_from(0) {
+            match instruction {
+                isa::Instruction::Call(callee) => callees.functions.push(callee),
+                isa::Instruction::CallIndirect(..) => callees.calls_indirect = true,
+                _ => {}
+            }
+        }
+        callees.functions.sort_unstable();
+        callees.functions.dedup();
+        Some(callees)
+    }
+
+    /// Verify that every function defined by the module is reachable over the static
+    /// call graph, see [`direct_callees`].
+    ///
+    /// The roots are the exported functions, the start function and the functions in
+    /// element segments, since these can be called through a table.
+    ///
+    /// Returns `Err` naming the first function that isn't reachable.
+    ///
+    /// [`direct_callees`]: #method.direct_callees
+    pub fn deny_unreachable_functions(&self) -> Result<(), Error> {
+        use parity_wasm::elements::Internal;
+
+        let imported_funcs = self
+            .module
+            .import_count(elements::ImportCountType::Function);
+        let total_funcs = imported_funcs + self.code_map.len();
+
+        let mut pending: Vec<u32> = Vec::new();
+        if let Some(es) = self.module.export_section() {
+            pending.extend(
+                es.entries()
+                    .iter()
+                    .filter_map(|export| match *export.internal() {
+                        Internal::Function(func_idx) => Some(func_idx),
+                        _ => None,
+                    }),
+            );
+        }
+        pending.extend(self.module.start_section());
+        if let Some(es) = self.module.elements_section() {
+            for segment in es.entries() {
+                pending.extend_from_slice(segment.members());
+            }
+        }
+
+        let mut reachable = vec![false; total_funcs];
+        while let Some(func_idx) = pending.pop() {
+            if mem::replace(&mut reachable[func_idx as usize], true) {
+                continue;
+            }
+            if let Some(callees) = self.direct_callees(func_idx) {
+                pending.extend(callees.functions);
+            }
+        }
+
+        match reachable[imported_funcs..]
+            .iter()
+            .position(|&reached| !reached)
+        {
+            Some(body_idx) => Err(Error::new(
+                ErrorKind::Other,
+                format!(
+                "Function {} is not reachable from the exports, the start function or the tables",
+                imported_funcs + body_idx
+            ),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the [`FeatureSet`] of the module, found by scanning its types and
+    /// instructions.
+    ///
+    /// [`FeatureSet`]: struct.FeatureSet.html
+    pub fn features_used(&self) -> FeatureSet {
+        use parity_wasm::elements::{External, Internal, ValueType};
+
+        let module = &self.module;
+        let is_float = |ty: &ValueType| matches!(*ty, ValueType::F32 | ValueType::F64);
+        let imports = module.import_section().map_or(&[][..], |is| is.entries());
+        let globals = module.global_section().map_or(&[][..], |gs| gs.entries());
+        let bodies = module.code_section().map_or(&[][..], |cs| cs.bodies());
+        let instructions = || bodies.iter().flat_map(|body| body.code().elements());
+
+        let floats = module
+            .type_section()
+            .map_or(&[][..], |ts| ts.types())
+            .iter()
+            .any(|Type::Function(func)| func.params().iter().chain(func.results()).any(is_float))
+            || imports.iter().any(|import| match *import.external() {
+                External::Global(ref global) => is_float(&global.content_type()),
+                _ => false,
+            })
+            || globals
+                .iter()
+                .any(|global| is_float(&global.global_type().content_type()))
+            || bodies
+                .iter()
+                .flat_map(|body| body.locals())
+                .any(|local| is_float(&local.value_type()))
+            || instructions().any(is_float_instruction);
+
+        // Memories can only be declared shared if atomics are decoded.
+        #[cfg(feature = "atomics")]
+        let threads = instructions().any(|op| matches!(*op, elements::Instruction::Atomics(_)))
+            || imports.iter().any(|import| match *import.external() {
+                External::Memory(ref memory) => memory.limits().shared(),
+                _ => false,
+            })
+            || module
+                .memory_section()
+                .map_or(&[][..], |ms| ms.entries())
+                .iter()
+                .any(|memory| memory.limits().shared());
+        #[cfg(not(feature = "atomics"))]
+        let threads = false;
+
+        let imported_mutable_global = imports.iter().any(|import| match *import.external() {
+            External::Global(ref global) => global.is_mutable(),
+            _ => false,
+        });
+        let imported_globals = module.import_count(elements::ImportCountType::Global);
+        let exported_mutable_global = module
+            .export_section()
+            .map_or(&[][..], |es| es.entries())
+            .iter()
+            .any(|export| match *export.internal() {
+                Internal::Global(index) => (index as usize)
+                    .checked_sub(imported_globals)
+                    .and_then(|index| globals.get(index))
+                    .map_or(imported_mutable_global, |global| {
+                        global.global_type().is_mutable()
+                    }),
+                _ => false,
+            });
+
+        FeatureSet {
+            floats,
+            threads,
+            multiple_tables: module.import_count(elements::ImportCountType::Table)
+                + module.table_section().map_or(0, |ts| ts.entries().len())
+                > 1,
+            mutable_globals: imported_mutable_global || exported_mutable_global,
+        }
+    }
+
+    /// Count the compiled instructions of all function bodies by their [`OpcodeKind`].
+    ///
+    /// Kinds that don't occur are missing from the histogram.
+    ///
+    /// [`OpcodeKind`]: enum.OpcodeKind.html
+    pub fn opcode_histogram(&self) -> BTreeMap<OpcodeKind, u64> {
+        let mut histogram = BTreeMap::new();
+        for instruction in self.code_map.iter().flat_map(|code| code.tail(0)) {
+            if let Some(kind) = instruction.opcode_kind() {
+                *histogram.entry(kind).or_insert(0) += 1;
+            }
+        }
+        histogram
+    }
+
+    /// Returns a hash of the imports and exports of the module: their names, kinds
+    /// and types.
+    ///
+    /// Code, internal definitions and the order of the entries don't affect the
+    /// fingerprint, so a module can be checked to be a drop-in replacement for
+    /// another one by comparing their fingerprints. Like [`Module::content_hash`] it
+    /// is 64-bit FNV-1a, which is stable but not collision resistant.
+    ///
+    /// [`Module::content_hash`]: ../struct.Module.html#method.content_hash
+    pub fn interface_fingerprint(&self) -> u64 {
+        use parity_wasm::elements::{External, Internal};
+
+        let module = &self.module;
+        let types = module.type_section().map_or(&[][..], |ts| ts.types());
+        let func_type = |type_idx: u32| {
+            let Type::Function(ty) = &types[type_idx as usize];
+            ty.clone()
+        };
+        let imports = module.import_section().map_or(&[][..], |is| is.entries());
+
+        // The index spaces of the exportable entities, imports first.
+        let mut funcs = Vec::new();
+        let mut tables = Vec::new();
+        let mut memories = Vec::new();
+        let mut globals = Vec::new();
+        for import in imports {
+            match *import.external() {
+                External::Function(type_idx) => funcs.push(type_idx),
+                External::Table(table_type) => tables.push(table_type),
+                External::Memory(memory_type) => memories.push(memory_type),
+                External::Global(global_type) => globals.push(global_type),
+            }
+        }
+        if let Some(fs) = module.function_section() {
+            funcs.extend(fs.entries().iter().map(|func| func.type_ref()));
+        }
+        if let Some(ts) = module.table_section() {
+            tables.extend(ts.entries());
+        }
+        if let Some(ms) = module.memory_section() {
+            memories.extend(ms.entries());
+        }
+        if let Some(gs) = module.global_section() {
+            globals.extend(gs.entries().iter().map(|global| *global.global_type()));
+        }
+
+        fn push<T: Serialize<Error = elements::Error>>(buf: &mut Vec<u8>, value: T) {
+            value
+                .serialize(buf)
+                .expect("serializing into a vector doesn't fail");
+        }
+        fn push_name(buf: &mut Vec<u8>, name: &str) {
+            push(buf, VarUint32::from(name.len()));
+            buf.extend_from_slice(name.as_bytes());
+        }
+
+        let mut entries = Vec::new();
+        for import in imports {
+            let mut entry = vec![0];
+            push_name(&mut entry, import.module());
+            push_name(&mut entry, import.field());
+            match *import.external() {
+                External::Function(type_idx) => {
+                    entry.push(0);
+                    push(&mut entry, func_type(type_idx));
+                }
+                External::Table(table_type) => {
+                    entry.push(1);
+                    push(&mut entry, table_type);
+                }
+                External::Memory(memory_type) => {
+                    entry.push(2);
+                    push(&mut entry, memory_type);
+                }
+                External::Global(global_type) => {
+                    entry.push(3);
+                    push(&mut entry, global_type);
+                }
+            }
+            entries.push(entry);
+        }
+        for export in module.export_section().map_or(&[][..], |es| es.entries()) {
+            let mut entry = vec![1];
+            push_name(&mut entry, export.field());
+            match *export.internal() {
+                Internal::Function(idx) => {
+                    entry.push(0);
+                    push(&mut entry, func_type(funcs[idx as usize]));
+                }
+                Internal::Table(idx) => {
+                    entry.push(1);
+                    push(&mut entry, tables[idx as usize]);
+                }
+                Internal::Memory(idx) => {
+                    entry.push(2);
+                    push(&mut entry, memories[idx as usize]);
+                }
+                Internal::Global(idx) => {
+                    entry.push(3);
+                    push(&mut entry, globals[idx as usize]);
+                }
+            }
+            entries.push(entry);
+        }
+        entries.sort_unstable();
+
+        let mut buf = Vec::new();
+        for entry in entries {
+            push(&mut buf, VarUint32::from(entry.len()));
+            buf.extend_from_slice(&entry);
+        }
+        crate::cache::content_hash(&buf)
+    }
+}
+
+/// Functions called by a function, returned by [`Module::direct_callees`].
+///
+/// [`Module::direct_callees`]: struct.Module.html#method.direct_callees
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Callees {
+    /// Indices of the functions called with `call`, in ascending order and without
+    /// duplicates. Imported functions are counted.
+    pub functions: Vec<u32>,
+    /// Whether the function performs any `call_indirect`, which can call any function
+    /// with a matching signature in the table.
+    pub calls_indirect: bool,
+}
+
+pub struct WasmiValidation<F: FuncValidator = compile::Compiler> {
+    code_map: Vec<F::Output>,
 }
 
 // This implementation of `Validation` is compiling wasm code at the
 // validation time.
-impl Validator for WasmiValidation {
-    type Output = Vec<isa::Instructions>;
-    type FuncValidator = compile::Compiler;
-    fn new(_module: &Module) -> Self {
-        WasmiValidation {
-            // TODO: with capacity?
-            code_map: Vec::new(),
-        }
+impl<F: FuncValidator> Validator for WasmiValidation<F> {
+    type Output = Vec<F::Output>;
+    type FuncValidator = F;
+    fn new(module: &Module) -> Result<Self, Error> {
+        let funcs_count = module
+            .function_section()
+            .map(|fs| fs.entries().len())
+            .unwrap_or(0);
+        let mut code_map = Vec::new();
+        code_map.try_reserve_exact(funcs_count).map_err(|_| {
+            Error::new(
+                ErrorKind::Other,
+                format!("Failed to allocate memory for {} functions", funcs_count),
+            )
+        })?;
+        Ok(WasmiValidation { code_map })
     }
-    fn on_function_validated(&mut self, _index: u32, output: isa::Instructions) {
+    fn on_function_validated(&mut self, _index: u32, output: F::Output) {
         self.code_map.push(output);
     }
-    fn finish(self) -> Vec<isa::Instructions> {
+    fn finish(self) -> Vec<F::Output> {
         self.code_map
     }
 }
 
+/// Basic figures of a module that was validated without compiling it, see
+/// [`validate_only`].
+///
+/// [`validate_only`]: fn.validate_only.html
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ValidationSummary {
+    /// Number of functions with a body in the code section.
+    pub functions: usize,
+    /// Number of imported functions.
+    pub imported_functions: usize,
+    /// Number of global variables defined by the module.
+    pub globals: usize,
+    /// Number of exports of any kind.
+    pub exports: usize,
+    /// Number of wasm instructions across all function bodies, including their `end`s.
+    pub instructions: usize,
+    /// Number of data segments.
+    pub data_segments: usize,
+    /// Total size in bytes of the data segment payloads.
+    pub data_bytes: usize,
+}
+
+/// Validates function bodies like [`compile::Compiler`] does, without compiling them.
+struct CountingFuncValidator {
+    instructions: usize,
+}
+
+impl FuncValidator for CountingFuncValidator {
+    type Output = usize;
+
+    fn new(
+        _ctx: &validation::func::FunctionValidationContext,
+        body: &FuncBody,
+    ) -> Result<Self, Error> {
+        compile::check_instruction_count(compile::instruction_capacity(body))?;
+        Ok(CountingFuncValidator { instructions: 0 })
+    }
+
+    fn next_instruction(
+        &mut self,
+        ctx: &mut validation::func::FunctionValidationContext,
+        instruction: &elements::Instruction,
+    ) -> Result<(), Error> {
+        self.instructions += 1;
+        ctx.step(instruction)
+    }
+
+    fn finish(self) -> usize {
+        self.instructions
+    }
+}
+
+#[derive(Default)]
+struct CountingValidator {
+    instructions: usize,
+}
+
+impl Validator for CountingValidator {
+    type Output = usize;
+    type FuncValidator = CountingFuncValidator;
+
+    fn new(_module: &Module) -> Result<Self, Error> {
+        Ok(CountingValidator::default())
+    }
+
+    fn on_function_validated(&mut self, _index: u32, instructions: usize) {
+        self.instructions += instructions;
+    }
+
+    fn finish(self) -> usize {
+        self.instructions
+    }
+}
+
+/// Validate a module without compiling it, and summarize it.
+///
+/// This performs the same validation as [`compile_module`], plus its limit on the
+/// number of instructions, but skips producing the internal representation, so it is
+/// cheaper if the module isn't compiled right away, e.g. to check a module on upload.
+/// The checks that are part of compiling, e.g. that the locals fit the range the
+/// compiled code can address, aren't run, so `compile_module` can still reject a
+/// module this accepts.
+pub fn validate_only(module: &Module) -> Result<ValidationSummary, Error> {
+    let instructions = validate_module::<CountingValidator>(module)?;
+    let data_segments = module.data_section().map_or(&[][..], |ds| ds.entries());
+    Ok(ValidationSummary {
+        functions: module.code_section().map_or(0, |cs| cs.bodies().len()),
+        imported_functions: module.import_count(elements::ImportCountType::Function),
+        globals: module.global_section().map_or(0, |gs| gs.entries().len()),
+        exports: module.export_section().map_or(0, |es| es.entries().len()),
+        instructions,
+        data_segments: data_segments.len(),
+        data_bytes: data_segments
+            .iter()
+            .map(|segment| segment.value().len())
+            .sum(),
+    })
+}
+
 /// Validate a module and compile it to the internal representation.
 pub fn compile_module(module: Module) -> Result<CompiledModule, Error> {
     let code_map = validate_module::<WasmiValidation>(&module)?;
-    Ok(CompiledModule { code_map, module })
+    Ok(CompiledModule::new(code_map, module, None))
+}
+
+/// Validate a module and compile it to the internal representation, inlining the
+/// calls of leaf functions of at most `max_callee_size` instructions.
+///
+/// Inlined calls are executed without creating a call frame, which makes calls of
+/// small helpers cheaper at the cost of a larger `code_map`.
+pub fn compile_module_with_inlining(
+    module: Module,
+    max_callee_size: u32,
+) -> Result<CompiledModule, Error> {
+    let mut compiled = compile_module(module)?;
+    inline::inline_small_functions(&mut compiled.code_map, &compiled.module, max_callee_size);
+    Ok(compiled)
+}
+
+/// Limits on the control flow of function bodies, checked before compilation.
+///
+/// The default limits don't restrict anything beyond what the validator already does,
+/// so they accept all valid modules.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CompileLimits {
+    /// Maximum number of `block`, `loop` and `if` constructs enclosing an instruction.
+    pub max_nesting_depth: u32,
+    /// Maximum number of targets of a `br_table`, not counting the default one.
+    pub max_br_table_size: u32,
+}
+
+impl Default for CompileLimits {
+    fn default() -> Self {
+        CompileLimits {
+            max_nesting_depth: u32::MAX,
+            max_br_table_size: u32::MAX,
+        }
+    }
+}
+
+/// Verify that no function body exceeds `limits`.
+///
+/// Returns `Err` naming the first offending function, imported functions included.
+pub fn check_compile_limits(module: &Module, limits: &CompileLimits) -> Result<(), Error> {
+    use parity_wasm::elements::Instruction;
+
+    let bodies = match module.code_section() {
+        Some(code) => code.bodies(),
+        None => return Ok(()),
+    };
+    let imported_funcs = module.import_count(elements::ImportCountType::Function);
+    for (body_idx, body) in bodies.iter().enumerate() {
+        let func_idx = imported_funcs + body_idx;
+        let mut depth = 0u32;
+        for op in body.code().elements() {
+            match *op {
+                Instruction::Block(_) | Instruction::Loop(_) | Instruction::If(_) => {
+                    depth += 1;
+                    if depth > limits.max_nesting_depth {
+                        return Err(Error::new(
+                            ErrorKind::Other,
+                            format!(
+                                "Function {} nests blocks deeper than the limit of {}",
+                                func_idx, limits.max_nesting_depth
+                            ),
+                        ));
+                    }
+                }
+                Instruction::End => depth = depth.saturating_sub(1),
+                Instruction::BrTable(ref br_table)
+                    if br_table.table.len() as u64 > u64::from(limits.max_br_table_size) =>
+                {
+                    return Err(Error::new(
+                        ErrorKind::Other,
+                        format!(
+                            "Function {} has a br_table with {} targets, above the limit of {}",
+                            func_idx,
+                            br_table.table.len(),
+                            limits.max_br_table_size
+                        ),
+                    ));
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Validate a module and compile it to the internal representation, rejecting
+/// functions that exceed `limits` before compiling any of them.
+pub fn compile_module_with_limits(
+    module: Module,
+    limits: &CompileLimits,
+) -> Result<CompiledModule, Error> {
+    check_compile_limits(&module, limits)?;
+    compile_module(module)
+}
+
+/// Validate a module and compile it to the internal representation, recording
+/// the source offset of each compiled instruction.
+pub fn compile_module_with_source_offsets(module: Module) -> Result<CompiledModule, Error> {
+    let compiled = validate_module::<WasmiValidation<compile::SourceMappingCompiler>>(&module)?;
+    let wasm_offsets = wasm_instruction_offsets(&module)?;
+
+    let (code_map, source_offsets) = compiled
+        .into_iter()
+        .zip(wasm_offsets)
+        .map(|((code, positions), offsets)| {
+            let source_offsets = positions
+                .into_iter()
+                .map(|position| offsets[position as usize])
+                .collect();
+            (code, source_offsets)
+        })
+        .unzip();
+
+    Ok(CompiledModule::new(code_map, module, Some(source_offsets)))
+}
+
+/// Number of locals above which a function is reported with [`Diagnostic::LargeLocals`].
+pub const LARGE_LOCALS_THRESHOLD: u32 = 1024;
+
+/// Advisory diagnostic about a valid module.
+///
+/// Diagnostics point at patterns that are legal, but are likely to be a mistake or to cause
+/// problems at runtime. They are collected by
+/// [`Module::from_parity_wasm_module_with_diagnostics`] and never cause a module to be rejected.
+///
+/// Functions are identified by the index of their body in the code section, i.e. imported
+/// functions are not counted.
+///
+/// [`Module::from_parity_wasm_module_with_diagnostics`]: struct.Module.html#method.from_parity_wasm_module_with_diagnostics
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Diagnostic {
+    /// The function body contains code that can never be executed, starting
+    /// with the instruction with the given index.
+    UnreachableCode {
+        /// Index of the function body.
+        func: u32,
+        /// Index of the first unreachable instruction in the function body.
+        instruction: u32,
+    },
+    /// The module defines a memory without a maximum size, so it can grow up to 4GiB.
+    UnboundedMemory,
+    /// The function declares more than [`LARGE_LOCALS_THRESHOLD`] locals, all of which
+    /// are allocated and zeroed on the value stack on every call.
+    ///
+    /// [`LARGE_LOCALS_THRESHOLD`]: constant.LARGE_LOCALS_THRESHOLD.html
+    LargeLocals {
+        /// Index of the function body.
+        func: u32,
+        /// Number of declared locals, not counting parameters.
+        count: u32,
+    },
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Diagnostic::UnreachableCode { func, instruction } => write!(
+                f,
+                "Function {} contains unreachable code starting at instruction {}",
+                func, instruction
+            ),
+            Diagnostic::UnboundedMemory => write!(f, "Memory doesn't have a maximum size"),
+            Diagnostic::LargeLocals { func, count } => {
+                write!(f, "Function {} declares {} locals", func, count)
+            }
+        }
+    }
+}
+
+/// Size figures of a module, see [`Module::stats`].
+///
+/// Only entities defined by the module are counted, unless stated otherwise.
+///
+/// [`Module::stats`]: struct.Module.html#method.stats
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModuleStats {
+    /// Number of functions with a body in the code section.
+    pub functions: usize,
+    /// Number of imported functions.
+    pub imported_functions: usize,
+    /// Number of global variables.
+    pub globals: usize,
+    /// Number of exports of any kind.
+    pub exports: usize,
+    /// Total size in bytes of the encoded function bodies, not counting their size prefixes.
+    pub code_bytes: usize,
+    /// Number of compiled instructions across all function bodies.
+    pub instructions: usize,
+    /// Number of data segments.
+    pub data_segments: usize,
+    /// Total size in bytes of the data segment payloads.
+    pub data_bytes: usize,
+    /// Initial size of the memory, or zero pages if the module doesn't define one.
+    pub memory_pages: Pages,
+    /// Total initial number of elements of the tables.
+    pub table_elements: u32,
+}
+
+/// Gather the [`ModuleStats`] of a compiled module.
+///
+/// [`ModuleStats`]: struct.ModuleStats.html
+pub fn module_stats(module: &Module, code_map: &[isa::Instructions]) -> ModuleStats {
+    let bodies = module.code_section().map(|cs| cs.bodies()).unwrap_or(&[]);
+    let data_segments = module.data_section().map(|ds| ds.entries()).unwrap_or(&[]);
+    ModuleStats {
+        functions: bodies.len(),
+        imported_functions: module.import_count(elements::ImportCountType::Function),
+        globals: module.global_section().map_or(0, |gs| gs.entries().len()),
+        exports: module.export_section().map_or(0, |es| es.entries().len()),
+        code_bytes: bodies
+            .iter()
+            .map(|body| {
+                encode_func_body(body)
+                    .expect("a decoded function body can be encoded again")
+                    .len()
+            })
+            .sum(),
+        instructions: code_map.iter().map(|code| code.current_pc() as usize).sum(),
+        data_segments: data_segments.len(),
+        data_bytes: data_segments
+            .iter()
+            .map(|segment| segment.value().len())
+            .sum(),
+        memory_pages: module
+            .memory_section()
+            .and_then(|ms| ms.entries().first())
+            .map_or(Pages(0), |memory_type| {
+                Pages(memory_type.limits().initial() as usize)
+            }),
+        table_elements: module
+            .table_section()
+            .map(|ts| ts.entries())
+            .unwrap_or(&[])
+            .iter()
+            .map(|table_type| table_type.limits().initial())
+            .sum(),
+    }
+}
+
+/// The WebAssembly proposals a module relies on, see [`Module::features_used`].
+///
+/// Only proposals that wasmi supports are listed. Modules using any other, e.g.
+/// SIMD, bulk memory, sign-extension operators, multi-value or tail calls, are
+/// rejected when they are loaded, with an error naming the proposal.
+///
+/// [`Module::features_used`]: struct.Module.html#method.features_used
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FeatureSet {
+    /// Float types or instructions. These are part of the MVP, but environments that
+    /// need deterministic execution often deny them.
+    pub floats: bool,
+    /// Atomic instructions or shared memories of the threads proposal. They can only
+    /// be decoded with the `atomics` feature.
+    pub threads: bool,
+    /// More than one table, counting imported ones, as in the reference types
+    /// proposal. Only valid with the `multi_table` feature.
+    pub multiple_tables: bool,
+    /// Imports or exports of mutable globals, from the mutable-global proposal.
+    pub mutable_globals: bool,
+}
+
+/// Validate a module and compile it to the internal representation, collecting
+/// advisory [`Diagnostic`]s about it.
+pub fn compile_module_with_diagnostics(
+    module: Module,
+) -> Result<(CompiledModule, Vec<Diagnostic>), Error> {
+    let compiled = validate_module::<WasmiValidation<compile::LintingCompiler>>(&module)?;
+
+    let mut diagnostics = Vec::new();
+    if let Some(memory_section) = module.memory_section() {
+        if memory_section
+            .entries()
+            .iter()
+            .any(|memory| memory.limits().maximum().is_none())
+        {
+            diagnostics.push(Diagnostic::UnboundedMemory);
+        }
+    }
+
+    let bodies = module.code_section().map_or(&[][..], |cs| cs.bodies());
+    let mut code_map = Vec::with_capacity(compiled.len());
+    for (func, ((code, unreachable_code), body)) in compiled.into_iter().zip(bodies).enumerate() {
+        let func = func as u32;
+        let count = body
+            .locals()
+            .iter()
+            .fold(0u32, |acc, local| acc.saturating_add(local.count()));
+        if count > LARGE_LOCALS_THRESHOLD {
+            diagnostics.push(Diagnostic::LargeLocals { func, count });
+        }
+        if let Some(instruction) = unreachable_code {
+            diagnostics.push(Diagnostic::UnreachableCode { func, instruction });
+        }
+        code_map.push(code);
+    }
+
+    Ok((CompiledModule::new(code_map, module, None), diagnostics))
+}
+
+/// Everything the compilation of a module observed, see
+/// [`Module::from_parity_wasm_module_with_report`].
+///
+/// [`Module::from_parity_wasm_module_with_report`]: struct.Module.html#method.from_parity_wasm_module_with_report
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompileReport {
+    /// Size figures of the module, as returned by [`Module::stats`].
+    ///
+    /// [`Module::stats`]: struct.Module.html#method.stats
+    pub stats: ModuleStats,
+    /// For each function body, the upper bound of the number of values it keeps on the
+    /// value stack, including its locals.
+    pub max_stack_heights: Vec<u32>,
+    /// The proposals the module relies on, as returned by [`Module::features_used`].
+    ///
+    /// [`Module::features_used`]: struct.Module.html#method.features_used
+    pub features: FeatureSet,
+    /// Advisory diagnostics about the module, as collected by
+    /// [`Module::from_parity_wasm_module_with_diagnostics`].
+    ///
+    /// [`Module::from_parity_wasm_module_with_diagnostics`]: struct.Module.html#method.from_parity_wasm_module_with_diagnostics
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Validate a module and compile it to the internal representation, reporting what
+/// the compilation observed.
+///
+/// The module is validated only once, like with [`compile_module_with_diagnostics`],
+/// and the rest of the report is gathered from the compiled module.
+pub fn compile_module_with_report(
+    module: Module,
+) -> Result<(CompiledModule, CompileReport), Error> {
+    let (compiled, diagnostics) = compile_module_with_diagnostics(module)?;
+    let report = CompileReport {
+        stats: module_stats(&compiled.module, &compiled.code_map),
+        max_stack_heights: compiled
+            .code_map
+            .iter()
+            .map(|code| code.max_stack_height())
+            .collect(),
+        features: compiled.features_used(),
+        diagnostics,
+    };
+    Ok((compiled, report))
+}
+
+/// Compute the byte offset of each instruction of each function body, relative
+/// to the start of the code section payload.
+///
+/// `parity_wasm` doesn't retain the offsets of the binary it was deserialized
+/// from, so they are recomputed by encoding the code section again. For binaries
+/// that use the canonical (shortest) LEB128 encodings, which is what toolchains
+/// emit, these are the same as the original offsets.
+fn wasm_instruction_offsets(module: &Module) -> Result<Vec<Vec<u32>>, Error> {
+    fn encoded_len<T: Serialize<Error = elements::Error>>(value: T) -> Result<usize, Error> {
+        let mut buf = Vec::new();
+        value.serialize(&mut buf).map_err(|e| {
+            Error::new(
+                ErrorKind::Other,
+                format!("Failed to encode code section: {}", e),
+            )
+        })?;
+        Ok(buf.len())
+    }
+
+    let bodies = match module.code_section() {
+        Some(code_section) => code_section.bodies(),
+        None => return Ok(Vec::new()),
+    };
+
+    let mut offset = encoded_len(VarUint32::from(bodies.len()))?;
+    let mut result = Vec::with_capacity(bodies.len());
+    for body in bodies {
+        let mut body_len = encoded_len(VarUint32::from(body.locals().len()))?;
+        for local in body.locals() {
+            body_len += encoded_len(*local)?;
+        }
+        let mut offsets = Vec::with_capacity(body.code().elements().len());
+        let mut instruction_offsets = Vec::with_capacity(body.code().elements().len());
+        for instruction in body.code().elements() {
+            instruction_offsets.push(body_len);
+            body_len += encoded_len(instruction.clone())?;
+        }
+        let body_start = offset + encoded_len(VarUint32::from(body_len))?;
+        for instruction_offset in instruction_offsets {
+            offsets.push((body_start + instruction_offset) as u32);
+        }
+        result.push(offsets);
+        offset = body_start + body_len;
+    }
+    Ok(result)
+}
+
+/// Encode a function body the way it appears in the code section, without
+/// the leading body size.
+///
+/// Like [`wasm_instruction_offsets`] this encodes the body again, so the bytes
+/// only match the original binary if it used the canonical LEB128 encodings.
+pub fn encode_func_body(body: &FuncBody) -> Result<Vec<u8>, Error> {
+    let to_error = |e: elements::Error| {
+        Error::new(
+            ErrorKind::Other,
+            format!("Failed to encode function body: {}", e),
+        )
+    };
+
+    let mut buf = Vec::new();
+    VarUint32::from(body.locals().len())
+        .serialize(&mut buf)
+        .map_err(to_error)?;
+    for local in body.locals() {
+        local.serialize(&mut buf).map_err(to_error)?;
+    }
+    body.code().clone().serialize(&mut buf).map_err(to_error)?;
+    Ok(buf)
+}
+
+/// Verify that no function body contains an instruction matching `is_denied`.
+///
+/// Returns `Err` naming the index of the offending function (imported functions
+/// included) and the instruction.
+fn deny_instructions<F>(module: &Module, what: &str, is_denied: F) -> Result<(), Error>
+where
+    F: Fn(&elements::Instruction) -> bool,
+{
+    let bodies = match module.code_section() {
+        Some(code) => code.bodies(),
+        None => return Ok(()),
+    };
+    let imported_funcs = module.import_count(elements::ImportCountType::Function);
+    for (body_idx, body) in bodies.iter().enumerate() {
+        if let Some(op) = body.code().elements().iter().find(|op| is_denied(op)) {
+            return Err(Error::new(
+                ErrorKind::Other,
+                format!(
+                    "{} denied in function {}: {:?}",
+                    what,
+                    imported_funcs + body_idx,
+                    op
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Verify that the module doesn't use the `memory.grow` instruction.
+///
+/// Returns `Err` naming the first function that uses it.
+pub fn deny_memory_grow(module: &Module) -> Result<(), Error> {
+    deny_instructions(module, "Memory growth", |op| {
+        matches!(*op, elements::Instruction::GrowMemory(_))
+    })
+}
+
+/// Verify that the module doesn't use the `call_indirect` instruction.
+///
+/// Returns `Err` naming the first function that uses it.
+pub fn deny_indirect_calls(module: &Module) -> Result<(), Error> {
+    deny_instructions(module, "Indirect call", |op| {
+        matches!(*op, elements::Instruction::CallIndirect(_, _))
+    })
+}
+
+/// Verify that the module exports an entity of the given kind under each of the `required` names.
+///
+/// Returns `Err` naming the first required export that is missing or has another kind.
+pub fn require_exports(module: &Module, required: &[(ExportKind, &str)]) -> Result<(), Error> {
+    let exports = module
+        .export_section()
+        .map(|es| es.entries())
+        .unwrap_or(&[]);
+    for &(kind, field) in required {
+        let found = exports
+            .iter()
+            .any(|export| export.field() == field && ExportKind::of(export.internal()) == kind);
+        if !found {
+            return Err(Error::new(
+                ErrorKind::Other,
+                format!("Required export {} `{}` is missing", kind.name(), field),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Verify that the module doesn't export any entity of the `denied` kinds.
+///
+/// Returns `Err` naming the first export of a denied kind.
+pub fn deny_exports(module: &Module, denied: &[ExportKind]) -> Result<(), Error> {
+    let exports = module
+        .export_section()
+        .map(|es| es.entries())
+        .unwrap_or(&[]);
+    for export in exports {
+        let kind = ExportKind::of(export.internal());
+        if denied.contains(&kind) {
+            return Err(Error::new(
+                ErrorKind::Other,
+                format!(
+                    "Export of {} `{}` is not allowed",
+                    kind.name(),
+                    export.field()
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Whether `op` operates on floats, or converts from or to them.
+fn is_float_instruction(op: &elements::Instruction) -> bool {
+    use parity_wasm::elements::Instruction::*;
+
+    matches!(
+        *op,
+        F32Load(_, _)
+            | F64Load(_, _)
+            | F32Store(_, _)
+            | F64Store(_, _)
+            | F32Const(_)
+            | F64Const(_)
+            | F32Eq
+            | F32Ne
+            | F32Lt
+            | F32Gt
+            | F32Le
+            | F32Ge
+            | F64Eq
+            | F64Ne
+            | F64Lt
+            | F64Gt
+            | F64Le
+            | F64Ge
+            | F32Abs
+            | F32Neg
+            | F32Ceil
+            | F32Floor
+            | F32Trunc
+            | F32Nearest
+            | F32Sqrt
+            | F32Add
+            | F32Sub
+            | F32Mul
+            | F32Div
+            | F32Min
+            | F32Max
+            | F32Copysign
+            | F64Abs
+            | F64Neg
+            | F64Ceil
+            | F64Floor
+            | F64Trunc
+            | F64Nearest
+            | F64Sqrt
+            | F64Add
+            | F64Sub
+            | F64Mul
+            | F64Div
+            | F64Min
+            | F64Max
+            | F64Copysign
+            | F32ConvertSI32
+            | F32ConvertUI32
+            | F32ConvertSI64
+            | F32ConvertUI64
+            | F32DemoteF64
+            | F64ConvertSI32
+            | F64ConvertUI32
+            | F64ConvertSI64
+            | F64ConvertUI64
+            | F64PromoteF32
+            | F32ReinterpretI32
+            | F64ReinterpretI64
+            | I32TruncSF32
+            | I32TruncUF32
+            | I32TruncSF64
+            | I32TruncUF64
+            | I64TruncSF32
+            | I64TruncUF32
+            | I64TruncSF64
+            | I64TruncUF64
+            | I32ReinterpretF32
+            | I64ReinterpretF64
+    )
 }
 
 /// Verify that the module doesn't use floating point instructions or types.
@@ -54,92 +1119,15 @@ pub fn compile_module(module: Module) -> Result<CompiledModule, Error> {
 ///   consumes or produces a value of a floating point type)
 /// - If a floating point type used in a definition of a function.
 pub fn deny_floating_point(module: &Module) -> Result<(), Error> {
-    use parity_wasm::elements::{
-        Instruction::{self, *},
-        Type, ValueType,
-    };
+    use parity_wasm::elements::{Type, ValueType};
 
     if let Some(code) = module.code_section() {
         for op in code.bodies().iter().flat_map(|body| body.code().elements()) {
-            macro_rules! match_eq {
-                ($pattern:pat) => {
-                    |val| if let $pattern = *val { true } else { false }
-                };
-            }
-
-            const DENIED: &[fn(&Instruction) -> bool] = &[
-                match_eq!(F32Load(_, _)),
-                match_eq!(F64Load(_, _)),
-                match_eq!(F32Store(_, _)),
-                match_eq!(F64Store(_, _)),
-                match_eq!(F32Const(_)),
-                match_eq!(F64Const(_)),
-                match_eq!(F32Eq),
-                match_eq!(F32Ne),
-                match_eq!(F32Lt),
-                match_eq!(F32Gt),
-                match_eq!(F32Le),
-                match_eq!(F32Ge),
-                match_eq!(F64Eq),
-                match_eq!(F64Ne),
-                match_eq!(F64Lt),
-                match_eq!(F64Gt),
-                match_eq!(F64Le),
-                match_eq!(F64Ge),
-                match_eq!(F32Abs),
-                match_eq!(F32Neg),
-                match_eq!(F32Ceil),
-                match_eq!(F32Floor),
-                match_eq!(F32Trunc),
-                match_eq!(F32Nearest),
-                match_eq!(F32Sqrt),
-                match_eq!(F32Add),
-                match_eq!(F32Sub),
-                match_eq!(F32Mul),
-                match_eq!(F32Div),
-                match_eq!(F32Min),
-                match_eq!(F32Max),
-                match_eq!(F32Copysign),
-                match_eq!(F64Abs),
-                match_eq!(F64Neg),
-                match_eq!(F64Ceil),
-                match_eq!(F64Floor),
-                match_eq!(F64Trunc),
-                match_eq!(F64Nearest),
-                match_eq!(F64Sqrt),
-                match_eq!(F64Add),
-                match_eq!(F64Sub),
-                match_eq!(F64Mul),
-                match_eq!(F64Div),
-                match_eq!(F64Min),
-                match_eq!(F64Max),
-                match_eq!(F64Copysign),
-                match_eq!(F32ConvertSI32),
-                match_eq!(F32ConvertUI32),
-                match_eq!(F32ConvertSI64),
-                match_eq!(F32ConvertUI64),
-                match_eq!(F32DemoteF64),
-                match_eq!(F64ConvertSI32),
-                match_eq!(F64ConvertUI32),
-                match_eq!(F64ConvertSI64),
-                match_eq!(F64ConvertUI64),
-                match_eq!(F64PromoteF32),
-                match_eq!(F32ReinterpretI32),
-                match_eq!(F64ReinterpretI64),
-                match_eq!(I32TruncSF32),
-                match_eq!(I32TruncUF32),
-                match_eq!(I32TruncSF64),
-                match_eq!(I32TruncUF64),
-                match_eq!(I64TruncSF32),
-                match_eq!(I64TruncUF32),
-                match_eq!(I64TruncSF64),
-                match_eq!(I64TruncUF64),
-                match_eq!(I32ReinterpretF32),
-                match_eq!(I64ReinterpretF64),
-            ];
-
-            if DENIED.iter().any(|is_denied| is_denied(op)) {
-                return Err(Error(format!("Floating point operation denied: {:?}", op)));
+            if is_float_instruction(op) {
+                return Err(Error::new(
+                    ErrorKind::Other,
+                    format!("Floating point operation denied: {:?}", op),
+                ));
             }
         }
     }
@@ -157,7 +1145,10 @@ pub fn deny_floating_point(module: &Module) -> Result<(), Error> {
                             .chain(func.results())
                             .any(|&typ| typ == ValueType::F32 || typ == ValueType::F64)
                         {
-                            return Err(Error("Use of floating point types denied".to_string()));
+                            return Err(Error::new(
+                                ErrorKind::Other,
+                                "Use of floating point types denied",
+                            ));
                         }
                     }
                 }
@@ -167,3 +1158,106 @@ pub fn deny_floating_point(module: &Module) -> Result<(), Error> {
 
     Ok(())
 }
+
+/// Verify that active data segments with constant offsets fit into the memory defined by the module.
+///
+/// Returns `Err` if
+///
+/// - The offset of a data segment is given by an `i32.const` and the end of the segment
+///   lies beyond the initial size of the memory defined in the module.
+///
+/// Segments with offsets given by `get_global` and segments that target an imported memory
+/// are not checked since their bounds can only be known at instantiation time.
+pub fn validate_data_segment_bounds(module: &Module) -> Result<(), Error> {
+    use parity_wasm::elements::{External, Instruction};
+
+    let imports_memory = module
+        .import_section()
+        .map(|is| {
+            is.entries()
+                .iter()
+                .any(|entry| matches!(entry.external(), External::Memory(_)))
+        })
+        .unwrap_or(false);
+    if imports_memory {
+        return Ok(());
+    }
+
+    let initial_pages = match module
+        .memory_section()
+        .and_then(|ms| ms.entries().get(DEFAULT_MEMORY_INDEX as usize))
+    {
+        Some(memory_type) => memory_type.limits().initial(),
+        // Data segments without a memory are rejected by the validation.
+        None => return Ok(()),
+    };
+    let initial_size = u64::from(initial_pages) * LINEAR_MEMORY_PAGE_SIZE.0 as u64;
+
+    for (index, data_segment) in module
+        .data_section()
+        .map(|ds| ds.entries())
+        .unwrap_or(&[])
+        .iter()
+        .enumerate()
+    {
+        let offset = match data_segment.offset().as_ref().map(|init| init.code()) {
+            Some(&[Instruction::I32Const(offset), Instruction::End]) => offset as u32,
+            _ => continue,
+        };
+        let end = u64::from(offset) + data_segment.value().len() as u64;
+        if end > initial_size {
+            return Err(Error::new(ErrorKind::Other, format!(
+                "Data segment {} (offset {}, length {}) doesn't fit into memory of initial size {} bytes",
+                index,
+                offset,
+                data_segment.value().len(),
+                initial_size
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// Compute the smallest size of the default memory the module can be instantiated with.
+///
+/// This is the larger of the declared minimum of the memory, imported or defined, and the
+/// end of the furthest data segment with an offset given by an `i32.const`, rounded up
+/// to whole pages. Segments with other offsets are only known at instantiation time and
+/// are not considered. Modules without a memory require `Pages(0)`.
+pub fn min_required_pages(module: &Module) -> Pages {
+    use parity_wasm::elements::{External, Instruction};
+
+    let imported = module
+        .import_section()
+        .map_or(&[][..], |is| is.entries())
+        .iter()
+        .filter_map(|entry| match entry.external() {
+            External::Memory(memory_type) => Some(memory_type),
+            _ => None,
+        });
+    let defined = module.memory_section().map_or(&[][..], |ms| ms.entries());
+    let declared = imported
+        .chain(defined)
+        .nth(DEFAULT_MEMORY_INDEX as usize)
+        .map_or(0, |memory_type| memory_type.limits().initial());
+
+    let page_size = LINEAR_MEMORY_PAGE_SIZE.0 as u64;
+    let reach = module
+        .data_section()
+        .map_or(&[][..], |ds| ds.entries())
+        .iter()
+        .filter_map(
+            |data_segment| match data_segment.offset().as_ref().map(|init| init.code()) {
+                Some(&[Instruction::I32Const(offset), Instruction::End]) => {
+                    let end = u64::from(offset as u32) + data_segment.value().len() as u64;
+                    Some((end + page_size - 1) / page_size)
+                }
+                _ => None,
+            },
+        )
+        .max()
+        .unwrap_or(0);
+
+    Pages(cmp::max(u64::from(declared), reach) as usize)
+}
@@ -4,8 +4,17 @@ extern crate std;
 use alloc::vec::Vec;
 use std::println;
 
-use super::{compile_module, CompiledModule};
-use crate::isa;
+use super::{
+    compile_module, compile_module_with_diagnostics, compile_module_with_inlining,
+    compile_module_with_limits, compile_module_with_report, compile_module_with_source_offsets,
+    deny_exports, deny_indirect_calls, deny_memory_grow, min_required_pages, module_stats,
+    require_exports, validate_only, Callees, CompileLimits, CompiledModule, Diagnostic, FeatureSet,
+    ModuleStats, ValidationSummary, LARGE_LOCALS_THRESHOLD,
+};
+use crate::isa::{self, OpcodeKind};
+use crate::memory_units::Pages;
+use crate::{ExportKind, ModuleBuilder, Signature, ValueType};
+use parity_wasm::elements::Instruction;
 use parity_wasm::{deserialize_buffer, elements::Module};
 
 fn validate(wat: &str) -> CompiledModule {
@@ -177,14 +186,10 @@ fn add_params() {
     assert_eq!(
         code,
         vec![
-            // This is tricky. Locals are now loaded from the stack. The load
-            // happens from address relative of the current stack pointer. The first load
-            // takes the value below the previous one (i.e the second argument) and then, it increments
-            // the stack pointer. And then the same thing hapens with the value below the previous one
-            // (which happens to be the value loaded by the first get_local).
-            isa::Instruction::GetLocal(2),
-            isa::Instruction::GetLocal(2),
-            isa::Instruction::I32Add,
+            // Both loads and the addition are fused into one instruction. Its depths
+            // are relative to the stack pointer before it's executed: the first argument
+            // is two values below it and the second argument is right below it.
+            isa::Instruction::I32AddLocals(2, 1),
             isa::Instruction::Return(isa::DropKeep {
                 drop: 2,
                 keep: isa::Keep::Single,
@@ -509,8 +514,7 @@ fn loop_() {
                     keep: isa::Keep::None,
                 },
             }),
-            isa::Instruction::I32Const(2),
-            isa::Instruction::Drop,
+            // `i32.const 2` and its `drop` are elided, no branch lands in between.
             isa::Instruction::Return(isa::DropKeep {
                 drop: 0,
                 keep: isa::Keep::None,
@@ -747,3 +751,1042 @@ fn wabt_example() {
         ]
     )
 }
+
+#[test]
+fn module_builder() {
+    let module = ModuleBuilder::new()
+        .with_memory(1, None)
+        .with_func(
+            &Signature::new(&[ValueType::I32][..], Some(ValueType::I32)),
+            &[ValueType::I32],
+            vec![
+                Instruction::GetLocal(0),
+                Instruction::SetLocal(1),
+                Instruction::GetLocal(1),
+            ],
+        )
+        .export_func("call", 0)
+        .export_memory("mem")
+        .build();
+    let module = compile_module(module).unwrap();
+    let (code, _) = compile(&module);
+    assert_eq!(
+        code,
+        vec![
+            isa::Instruction::GetLocal(2),
+            isa::Instruction::SetLocal(1),
+            isa::Instruction::GetLocal(1),
+            isa::Instruction::Return(isa::DropKeep {
+                drop: 2,
+                keep: isa::Keep::Single,
+            }),
+        ]
+    )
+}
+
+#[test]
+fn source_offsets() {
+    let wasm = wabt::wat2wasm(
+        r#"
+		(module
+			(func (result i32)
+				i32.const 1
+				i32.const 2
+				i32.add
+			)
+		)
+	"#,
+    )
+    .unwrap();
+    let module = deserialize_buffer::<Module>(&wasm).unwrap();
+
+    assert!(compile_module(module.clone())
+        .unwrap()
+        .source_offsets
+        .is_none());
+
+    let module = compile_module_with_source_offsets(module).unwrap();
+    let offsets = &module.source_offsets.as_ref().unwrap()[0];
+    assert_eq!(offsets, &[3, 5, 7, 8]);
+
+    // The code section is the last one, so its payload is at the end of the binary.
+    let code_section_payload = &wasm[wasm.len() - 9..];
+    let opcodes: Vec<u8> = offsets
+        .iter()
+        .map(|&offset| code_section_payload[offset as usize])
+        .collect();
+    // i32.const, i32.const, i32.add and end which is compiled into return.
+    assert_eq!(opcodes, vec![0x41, 0x41, 0x6a, 0x0b]);
+}
+
+#[test]
+fn diagnostics() {
+    let diagnostics = |wat: &str| {
+        let wasm = wabt::wat2wasm(wat).unwrap();
+        let module = deserialize_buffer::<Module>(&wasm).unwrap();
+        compile_module_with_diagnostics(module).unwrap().1
+    };
+
+    // Code after a branch out of a block is reachable through its `end`.
+    assert_eq!(
+        diagnostics(
+            r#"
+		(module
+			(memory 1 1)
+			(func (result i32)
+				block
+					br 0
+				end
+				i32.const 0
+			)
+		)
+	"#,
+        ),
+        vec![]
+    );
+
+    assert_eq!(
+        diagnostics(
+            r#"
+		(module
+			(func)
+			(func (result i32)
+				(local i64)
+				block
+					br 0
+					nop
+				end
+				i32.const 0
+				return
+				drop
+				i32.const 1
+			)
+		)
+	"#,
+        ),
+        vec![Diagnostic::UnreachableCode {
+            func: 1,
+            instruction: 2
+        }]
+    );
+
+    let mut builder = ModuleBuilder::new();
+    builder.push_func(
+        &Signature::new(&[][..], None),
+        &[ValueType::I32; LARGE_LOCALS_THRESHOLD as usize + 1],
+        vec![],
+    );
+    let module = builder.with_memory(1, None).build();
+    let (_, diagnostics) = compile_module_with_diagnostics(module).unwrap();
+    assert_eq!(
+        diagnostics,
+        vec![
+            Diagnostic::UnboundedMemory,
+            Diagnostic::LargeLocals {
+                func: 0,
+                count: LARGE_LOCALS_THRESHOLD + 1
+            },
+        ]
+    );
+}
+
+#[test]
+fn deny_memory_grow_names_function() {
+    let wasm = wabt::wat2wasm(
+        r#"
+(module
+  (import "env" "f" (func))
+  (memory 1)
+  (func (drop (current_memory)))
+  (func (drop (grow_memory (i32.const 1))))
+)
+"#,
+    )
+    .unwrap();
+    let module = deserialize_buffer::<Module>(&wasm).unwrap();
+    let err = deny_memory_grow(&module).unwrap_err();
+    // Imported functions are counted.
+    assert_eq!(
+        err.to_string(),
+        "Memory growth denied in function 2: GrowMemory(0)"
+    );
+}
+
+#[test]
+fn deny_indirect_calls_names_function() {
+    let wasm = wabt::wat2wasm(
+        r#"
+(module
+  (import "env" "f" (func $f))
+  (type $t (func))
+  (table 1 anyfunc)
+  (func (call $f))
+  (func (call_indirect (type $t) (i32.const 0)))
+)
+"#,
+    )
+    .unwrap();
+    let module = deserialize_buffer::<Module>(&wasm).unwrap();
+    let err = deny_indirect_calls(&module).unwrap_err();
+    // Imported functions are counted.
+    assert_eq!(
+        err.to_string(),
+        "Indirect call denied in function 2: CallIndirect(0, 0)"
+    );
+}
+
+#[test]
+fn require_exports_names_missing_export() {
+    let wasm = wabt::wat2wasm(
+        r#"
+(module
+  (memory (export "memory") 1)
+  (global (export "memory_size") i32 (i32.const 0))
+)
+"#,
+    )
+    .unwrap();
+    let module = deserialize_buffer::<Module>(&wasm).unwrap();
+    assert!(require_exports(&module, &[]).is_ok());
+    assert!(require_exports(
+        &module,
+        &[
+            (ExportKind::Memory, "memory"),
+            (ExportKind::Global, "memory_size")
+        ]
+    )
+    .is_ok());
+
+    let err = require_exports(&module, &[(ExportKind::Func, "main")]).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Required export function `main` is missing"
+    );
+    // An export of another kind doesn't count.
+    let err = require_exports(&module, &[(ExportKind::Table, "memory")]).unwrap_err();
+    assert_eq!(err.to_string(), "Required export table `memory` is missing");
+}
+
+#[test]
+fn deny_exports_names_denied_export() {
+    let wasm = wabt::wat2wasm(
+        r#"
+(module
+  (func (export "main"))
+  (global (export "answer") i32 (i32.const 42))
+  (table (export "table") 1 anyfunc)
+  (memory (export "memory") 1)
+)
+"#,
+    )
+    .unwrap();
+    let module = deserialize_buffer::<Module>(&wasm).unwrap();
+    assert!(deny_exports(&module, &[]).is_ok());
+    assert!(deny_exports(&module, &[ExportKind::Func, ExportKind::Global]).is_err());
+
+    let err = deny_exports(&module, &[ExportKind::Memory, ExportKind::Table]).unwrap_err();
+    assert_eq!(err.to_string(), "Export of table `table` is not allowed");
+    let err = deny_exports(&module, &[ExportKind::Memory]).unwrap_err();
+    assert_eq!(err.to_string(), "Export of memory `memory` is not allowed");
+}
+
+#[test]
+fn deny_unreachable_functions_names_unreachable_function() {
+    let module = validate(
+        r#"
+(module
+  (import "env" "f" (func $imported))
+  (type $t (func))
+  (table 1 anyfunc)
+  (elem (i32.const 0) $in_table)
+  (func $start (call $from_start))
+  (func $from_start)
+  (func $in_table (call $imported))
+  (func $loop (call $loop))
+  (func $main (export "main")
+    (call $from_main)
+    (call_indirect (type $t) (i32.const 0)))
+  (func $from_main (call $main))
+  (start $start)
+)
+"#,
+    );
+    let err = module.deny_unreachable_functions().unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Function 4 is not reachable from the exports, the start function or the tables"
+    );
+
+    let module = validate(
+        r#"
+(module
+  (import "env" "f" (func $imported))
+  (func (export "main") (call $helper))
+  (func $helper (call $helper))
+)
+"#,
+    );
+    assert!(module.deny_unreachable_functions().is_ok());
+}
+
+#[test]
+fn inline_leaf_function() {
+    let wat = r#"
+(module
+  (func (param i32) (result i32)
+    (i32.add (call $f (get_local 0)) (i32.const 1)))
+  (func $f (param i32) (result i32)
+    (local i32)
+    (if (get_local 0)
+      (then (return (i32.const 7))))
+    (get_local 1))
+)
+"#;
+    let module = deserialize_buffer::<Module>(&wabt::wat2wasm(wat).unwrap()).unwrap();
+    // The callee takes up 7 instructions, counting the push of its local.
+    let inlined = compile_module_with_inlining(module.clone(), 7).unwrap();
+    let (code, _) = compile(&inlined);
+    let ret = isa::Target {
+        dst_pc: 8,
+        drop_keep: isa::DropKeep {
+            drop: 2,
+            keep: isa::Keep::Single,
+        },
+    };
+    assert_eq!(
+        code,
+        vec![
+            isa::Instruction::GetLocal(1),
+            // The inlined code of the callee.
+            isa::Instruction::I32Const(0),
+            isa::Instruction::GetLocal(2),
+            isa::Instruction::BrIfEqz(isa::Target {
+                dst_pc: 6,
+                drop_keep: isa::DropKeep {
+                    drop: 0,
+                    keep: isa::Keep::None,
+                },
+            }),
+            isa::Instruction::I32Const(7),
+            isa::Instruction::Br(ret),
+            isa::Instruction::GetLocal(1),
+            isa::Instruction::Br(ret),
+            // The rest of the caller.
+            isa::Instruction::I32Const(1),
+            isa::Instruction::I32Add,
+            isa::Instruction::Return(isa::DropKeep {
+                drop: 1,
+                keep: isa::Keep::Single,
+            }),
+        ]
+    );
+
+    let not_inlined = compile_module_with_inlining(module, 6).unwrap();
+    let (code, _) = compile(&not_inlined);
+    assert!(code.contains(&isa::Instruction::Call(1)));
+}
+
+#[test]
+fn stats() {
+    let module = validate(
+        r#"
+(module
+  (import "env" "f" (func))
+  (memory 3)
+  (table 2 anyfunc)
+  (global i32 (i32.const 0))
+  (data (i32.const 0) "abc")
+  (data (i32.const 8) "de")
+  (func (export "one") (result i32) (i32.const 1))
+  (func)
+)
+"#,
+    );
+    let stats = module_stats(&module.module, &module.code_map);
+    assert_eq!(
+        stats,
+        ModuleStats {
+            functions: 2,
+            imported_functions: 1,
+            globals: 1,
+            exports: 1,
+            // 0x00 (no locals) 0x41 0x01 (i32.const 1) 0x0b (end), then 0x00 0x0b.
+            code_bytes: 6,
+            // i32.const and return, then return.
+            instructions: 3,
+            data_segments: 2,
+            data_bytes: 5,
+            memory_pages: Pages(3),
+            table_elements: 2,
+        }
+    );
+}
+
+#[test]
+fn opcode_histogram() {
+    let module = validate(
+        r#"
+(module
+  (import "env" "f" (func))
+  (memory 1)
+  (table 1 anyfunc)
+  (global $g i32 (i32.const 0))
+  (func (param i32) (result i32)
+    call 0
+    get_local 0
+    i32.load
+    get_global $g
+    i32.eq
+    if
+      get_local 0
+      i64.const 1
+      i64.store
+    end
+    i32.const 0
+    call_indirect
+    get_local 0
+    f32.convert_s/i32
+    f32.neg
+    i32.trunc_s/f32
+    get_local 0
+    get_local 0
+    i32.add
+    i32.mul
+  )
+)
+"#,
+    );
+    let histogram: Vec<(OpcodeKind, u64)> = module.opcode_histogram().into_iter().collect();
+    assert_eq!(
+        histogram,
+        vec![
+            // The `get_local`s of the `load`, the `store` and the conversion.
+            (OpcodeKind::Local, 3),
+            (OpcodeKind::Global, 1),
+            (OpcodeKind::Const, 2),
+            (OpcodeKind::Load, 1),
+            (OpcodeKind::Store, 1),
+            (OpcodeKind::Compare, 1),
+            (OpcodeKind::Arithmetic, 2),
+            (OpcodeKind::Conversion, 2),
+            // The `if` and the final `return`.
+            (OpcodeKind::Branch, 2),
+            (OpcodeKind::Call, 1),
+            (OpcodeKind::CallIndirect, 1),
+            // `get_local 0 get_local 0 i32.add`.
+            (OpcodeKind::Fused, 1),
+        ]
+    );
+}
+
+#[test]
+fn features_used() {
+    let features = |wat: &str| validate(wat).features_used();
+
+    assert_eq!(
+        features(
+            r#"
+(module
+  (global (export "g") i32 (i32.const 0))
+  (func (param i32) (result i32) (i32.add (get_local 0) (i32.const 1)))
+)
+"#
+        ),
+        FeatureSet::default()
+    );
+
+    // Floats are found in any type, or in instructions on their own.
+    for wat in &[
+        r#"(module (type (func (result f32))))"#,
+        r#"(module (global f64 (f64.const 0)))"#,
+        r#"(module (import "env" "g" (global f32)))"#,
+        r#"(module (func (local f64)))"#,
+        r#"(module (func (drop (i32.reinterpret/f32 (f32.const 0)))))"#,
+    ] {
+        assert_eq!(
+            features(wat),
+            FeatureSet {
+                floats: true,
+                ..FeatureSet::default()
+            },
+            "{}",
+            wat
+        );
+    }
+
+    for wat in &[
+        r#"(module (import "env" "g" (global (mut i32))))"#,
+        r#"(module (global (export "g") (mut i32) (i32.const 0)))"#,
+        r#"(module (import "env" "g" (global $g (mut i32))) (export "g" (global $g)))"#,
+    ] {
+        assert_eq!(
+            features(wat),
+            FeatureSet {
+                mutable_globals: true,
+                ..FeatureSet::default()
+            },
+            "{}",
+            wat
+        );
+    }
+
+    #[cfg(feature = "atomics")]
+    {
+        use parity_wasm::elements::{AtomicsInstruction, MemArg};
+
+        let module = ModuleBuilder::new()
+            .with_memory(1, None)
+            .with_func(
+                &Signature::new(&[][..], Some(ValueType::I32)),
+                &[],
+                vec![
+                    Instruction::I32Const(0),
+                    Instruction::I32Const(1),
+                    Instruction::Atomics(AtomicsInstruction::AtomicWake(MemArg {
+                        align: 2,
+                        offset: 0,
+                    })),
+                ],
+            )
+            .build();
+        assert!(compile_module(module).unwrap().features_used().threads);
+    }
+
+    #[cfg(feature = "multi_table")]
+    {
+        use parity_wasm::elements::{Section, TableSection, TableType};
+
+        let mut module = ModuleBuilder::new().build();
+        module
+            .sections_mut()
+            .push(Section::Table(TableSection::with_entries(vec![
+                TableType::new(1, None),
+                TableType::new(1, None),
+            ])));
+        assert!(
+            compile_module(module)
+                .unwrap()
+                .features_used()
+                .multiple_tables
+        );
+    }
+}
+
+#[test]
+fn fuse_arithmetic() {
+    let module = validate(
+        r#"
+		(module
+			(func (export "call") (param i32) (param i32)
+				get_local 0
+				i32.const 1
+				i32.add
+				set_local 1
+
+				get_local 0
+				get_local 1
+				i32.mul
+				drop
+
+				get_local 1
+				get_local 0
+				i32.add
+				set_local 0
+			)
+		)
+	"#,
+    );
+    let (code, _) = compile(&module);
+    assert_eq!(
+        code,
+        vec![
+            isa::Instruction::I32AddLocalConstToLocal(2, 1, 1),
+            isa::Instruction::I32MulLocals(2, 1),
+            isa::Instruction::Drop,
+            isa::Instruction::I32AddLocalsToLocal(1, 2, 2),
+            isa::Instruction::Return(isa::DropKeep {
+                drop: 2,
+                keep: isa::Keep::None,
+            }),
+        ]
+    )
+}
+
+#[test]
+fn no_fusion_across_labels() {
+    let module = validate(
+        r#"
+		(module
+			(func (export "call") (param i32) (param i32) (result i32)
+				get_local 0
+				block
+				end
+				get_local 1
+				i32.add
+			)
+		)
+	"#,
+    );
+    let (code, _) = compile(&module);
+    assert_eq!(
+        code,
+        vec![
+            // This is tricky. Locals are now loaded from the stack. The load
+            // happens from address relative of the current stack pointer. The first load
+            // takes the value below the previous one (i.e the second argument) and then, it increments
+            // the stack pointer. And then the same thing hapens with the value below the previous one
+            // (which happens to be the value loaded by the first get_local).
+            //
+            // The end of the block is a branch target, so the loads aren't fused.
+            isa::Instruction::GetLocal(2),
+            isa::Instruction::GetLocal(2),
+            isa::Instruction::I32Add,
+            isa::Instruction::Return(isa::DropKeep {
+                drop: 2,
+                keep: isa::Keep::Single,
+            }),
+        ]
+    )
+}
+
+#[test]
+fn signatures() {
+    let module = validate(
+        r#"
+		(module
+			(type (func (param i32 i64) (result f32)))
+			(type (func))
+			(func (type 1))
+		)
+	"#,
+    );
+    assert_eq!(module.signatures.len(), 2);
+    assert_eq!(
+        module.signature(0),
+        &Signature::new(&[ValueType::I32, ValueType::I64][..], Some(ValueType::F32))
+    );
+    assert_eq!(module.signature(1), &Signature::new(&[][..], None));
+}
+
+#[test]
+fn direct_callees() {
+    let module = validate(
+        r#"
+		(module
+			(import "env" "f" (func $imported))
+			(type $t (func))
+			(table 2 anyfunc)
+			(func $even (param i32) (result i32)
+				(if (result i32) (i32.eqz (get_local 0))
+					(then (i32.const 1))
+					(else (call $odd (i32.sub (get_local 0) (i32.const 1))))
+				)
+			)
+			(func $odd (param i32) (result i32)
+				(block
+					(loop
+						(call $imported)
+						(br_if 1 (get_local 0))
+					)
+				)
+				(call $even (i32.sub (get_local 0) (i32.const 1)))
+			)
+			(func $leaf)
+			(func $dispatch (param i32)
+				(call_indirect (type $t) (get_local 0))
+				(call $leaf)
+				(call $leaf)
+			)
+		)
+	"#,
+    );
+    // Imported functions are counted.
+    assert_eq!(module.direct_callees(0), None);
+    assert_eq!(
+        module.direct_callees(1),
+        Some(Callees {
+            functions: vec![2],
+            calls_indirect: false,
+        })
+    );
+    assert_eq!(
+        module.direct_callees(2),
+        Some(Callees {
+            functions: vec![0, 1],
+            calls_indirect: false,
+        })
+    );
+    assert_eq!(module.direct_callees(3), Some(Callees::default()));
+    assert_eq!(
+        module.direct_callees(4),
+        Some(Callees {
+            functions: vec![3],
+            calls_indirect: true,
+        })
+    );
+    assert_eq!(module.direct_callees(5), None);
+}
+
+#[test]
+fn min_required_pages_of_data() {
+    let parse = |wat: &str| {
+        let wasm = wabt::wat2wasm(wat).unwrap();
+        deserialize_buffer::<Module>(&wasm).unwrap()
+    };
+
+    assert_eq!(min_required_pages(&parse("(module)")), Pages(0));
+    assert_eq!(min_required_pages(&parse("(module (memory 3))")), Pages(3));
+    assert_eq!(
+        min_required_pages(&parse(
+            r#"
+		(module
+			(memory 1)
+			(data (i32.const 10) "\01")
+			(data (i32.const 131072) "\01")
+		)
+	"#
+        )),
+        Pages(3)
+    );
+    assert_eq!(
+        min_required_pages(&parse(
+            r#"
+		(module
+			(import "env" "offset" (global i32))
+			(memory 1)
+			(data (get_global 0) "\01")
+		)
+	"#
+        )),
+        Pages(1)
+    );
+    assert_eq!(
+        min_required_pages(&parse(
+            r#"
+		(module
+			(import "env" "memory" (memory 2))
+			(data (i32.const 65536) "")
+		)
+	"#
+        )),
+        Pages(2)
+    );
+}
+
+#[test]
+fn compile_limits() {
+    let parse = |wat: &str| {
+        let wasm = wabt::wat2wasm(wat).unwrap();
+        deserialize_buffer::<Module>(&wasm).unwrap()
+    };
+    let limits = CompileLimits {
+        max_nesting_depth: 3,
+        max_br_table_size: 2,
+    };
+
+    // Blocks that end before others start don't add up.
+    let module = parse(
+        r#"
+		(module
+			(func
+				(block (loop (if (i32.const 0) (then))))
+				(block (block (block)))
+			)
+		)
+	"#,
+    );
+    assert!(compile_module_with_limits(module, &limits).is_ok());
+
+    let module = parse(
+        r#"
+		(module
+			(import "env" "f" (func))
+			(func (block (block (block (block)))))
+		)
+	"#,
+    );
+    assert_eq!(
+        compile_module_with_limits(module, &limits)
+            .err()
+            .map(|e| e.to_string()),
+        Some("Function 1 nests blocks deeper than the limit of 3".into())
+    );
+
+    let module = parse(
+        r#"
+		(module
+			(func (param i32)
+				(block (block (block (br_table 0 1 2 0 (get_local 0)))))
+			)
+		)
+	"#,
+    );
+    assert_eq!(
+        compile_module_with_limits(module, &limits)
+            .err()
+            .map(|e| e.to_string()),
+        Some("Function 0 has a br_table with 3 targets, above the limit of 2".into())
+    );
+}
+
+#[test]
+fn interface_fingerprint() {
+    let fingerprint = |wat: &str| validate(wat).interface_fingerprint();
+
+    let base = fingerprint(
+        r#"
+		(module
+			(import "env" "log" (func (param i32)))
+			(import "env" "memory" (memory 1))
+			(global $g (mut i64) (i64.const 0))
+			(func (export "run") (param i32) (result i32) (get_local 0))
+			(export "counter" (global $g))
+		)
+	"#,
+    );
+
+    // Other code, other internal definitions and another order of the entries and
+    // of the type section.
+    assert_eq!(
+        fingerprint(
+            r#"
+		(module
+			(type (func (param i32) (result i32)))
+			(import "env" "memory" (memory 1))
+			(import "env" "log" (func (param i32)))
+			(global $g (mut i64) (i64.const 5))
+			(func $helper)
+			(export "counter" (global $g))
+			(func (export "run") (param i32) (result i32)
+				(call $helper)
+				(i32.add (get_local 0) (i32.const 1))
+			)
+		)
+	"#,
+        ),
+        base
+    );
+
+    let changes = [
+        // Import renamed.
+        r#"
+		(module
+			(import "env" "trace" (func (param i32)))
+			(import "env" "memory" (memory 1))
+			(global $g (mut i64) (i64.const 0))
+			(func (export "run") (param i32) (result i32) (get_local 0))
+			(export "counter" (global $g))
+		)
+	"#,
+        // Import with another signature.
+        r#"
+		(module
+			(import "env" "log" (func (param i64)))
+			(import "env" "memory" (memory 1))
+			(global $g (mut i64) (i64.const 0))
+			(func (export "run") (param i32) (result i32) (get_local 0))
+			(export "counter" (global $g))
+		)
+	"#,
+        // Imported memory with other limits.
+        r#"
+		(module
+			(import "env" "log" (func (param i32)))
+			(import "env" "memory" (memory 1 2))
+			(global $g (mut i64) (i64.const 0))
+			(func (export "run") (param i32) (result i32) (get_local 0))
+			(export "counter" (global $g))
+		)
+	"#,
+        // Exported global that is immutable.
+        r#"
+		(module
+			(import "env" "log" (func (param i32)))
+			(import "env" "memory" (memory 1))
+			(global $g i64 (i64.const 0))
+			(func (export "run") (param i32) (result i32) (get_local 0))
+			(export "counter" (global $g))
+		)
+	"#,
+        // Export of another kind under the same name.
+        r#"
+		(module
+			(import "env" "log" (func (param i32)))
+			(import "env" "memory" (memory 1))
+			(global $g (mut i64) (i64.const 0))
+			(func (export "run") (param i32) (result i32) (get_local 0))
+			(func (export "counter"))
+		)
+	"#,
+        // An additional export.
+        r#"
+		(module
+			(import "env" "log" (func (param i32)))
+			(import "env" "memory" (memory 1))
+			(global $g (mut i64) (i64.const 0))
+			(func (export "run") (param i32) (result i32) (get_local 0))
+			(export "counter" (global $g))
+			(export "memory" (memory 0))
+		)
+	"#,
+    ];
+    for wat in &changes {
+        assert_ne!(fingerprint(wat), base, "{}", wat);
+    }
+}
+
+#[test]
+fn nop_and_dropped_constants_elided() {
+    let module = validate(
+        r#"
+		(module
+			(global $g i32 (i32.const 0))
+			(func (param i32) (result i32)
+				nop
+				nop
+				(drop (i32.const 1))
+				(drop (get_local 0))
+				(drop (get_global $g))
+				(block
+					nop
+					(drop (f64.const 1))
+				)
+				nop
+				get_local 0
+			)
+		)
+	"#,
+    );
+    let (code, _) = compile(&module);
+    assert_eq!(
+        code,
+        vec![
+            isa::Instruction::GetLocal(1),
+            isa::Instruction::Return(isa::DropKeep {
+                drop: 1,
+                keep: isa::Keep::Single,
+            }),
+        ]
+    );
+}
+
+#[test]
+fn drop_kept_after_side_effects() {
+    let module = validate(
+        r#"
+		(module
+			(memory 1)
+			(func (param i32)
+				(drop (i32.load (get_local 0)))
+				(drop (tee_local 0 (i32.const 1)))
+				(drop (i32.add (get_local 0) (i32.const 1)))
+			)
+		)
+	"#,
+    );
+    let (code, _) = compile(&module);
+    let drops = code
+        .iter()
+        .filter(|instruction| matches!(instruction, isa::Instruction::Drop))
+        .count();
+    assert_eq!(drops, 3);
+}
+
+#[test]
+fn compile_report_matches_introspection() {
+    let wasm = wabt::wat2wasm(
+        r#"
+(module
+  (memory 1)
+  (func (param i32) (result i32)
+    (local i32 i32)
+    (i32.add (i32.mul (get_local 0) (get_local 1)) (get_local 2)))
+  (func
+    return
+    unreachable)
+)
+"#,
+    )
+    .unwrap();
+    let module = deserialize_buffer::<Module>(&wasm).unwrap();
+    let (compiled, report) = compile_module_with_report(module.clone()).unwrap();
+
+    // The report is the same as what the separate analyses find.
+    let (_, diagnostics) = compile_module_with_diagnostics(module).unwrap();
+    assert_eq!(report.diagnostics, diagnostics);
+    assert_eq!(
+        report.stats,
+        module_stats(&compiled.module, &compiled.code_map)
+    );
+    assert_eq!(report.features, compiled.features_used());
+    assert_eq!(report.max_stack_heights, vec![5, 0]);
+}
+
+#[test]
+fn instruction_count_is_limited() {
+    use super::compile::check_instruction_count;
+
+    // Functions this large can't be compiled in a test, so only the check is exercised.
+    assert!(check_instruction_count(isa::MAX_INSTRUCTIONS as usize).is_ok());
+    let error = check_instruction_count(isa::MAX_INSTRUCTIONS as usize + 1).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Function compiles to 4294967295 instructions, above the limit of 4294967294"
+    );
+}
+
+#[test]
+fn validate_only_agrees_with_compile_module() {
+    let parse = |wat: &str| {
+        let wasm = wabt::Wat2Wasm::new().validate(false).convert(wat).unwrap();
+        deserialize_buffer::<Module>(wasm.as_ref()).unwrap()
+    };
+
+    let module = parse(
+        r#"
+        (module
+            (import "env" "f" (func $f (param i32)))
+            (memory 1)
+            (global (mut i32) (i32.const 0))
+            (func (export "run") (param i32)
+                (block
+                    (br_table 0 0 (get_local 0)))
+                (call $f (get_local 0)))
+            (func)
+            (data (i32.const 0) "abc")
+            (data (i32.const 8) "de"))
+        "#,
+    );
+    assert_eq!(
+        validate_only(&module).unwrap(),
+        ValidationSummary {
+            functions: 2,
+            imported_functions: 1,
+            globals: 1,
+            exports: 1,
+            // `block`, `get_local`, `br_table`, `end`, `get_local`, `call`, `end`, and
+            // the `end` of the empty function.
+            instructions: 8,
+            data_segments: 2,
+            data_bytes: 5,
+        }
+    );
+    assert!(compile_module(module).is_ok());
+
+    for wat in &[
+        "(module (func (result i32) (i64.const 1)))",
+        "(module (func (drop)))",
+        "(module (func (call 1)))",
+        "(module (start 0) (func (param i32)))",
+    ] {
+        let module = parse(wat);
+        let error = validate_only(&module).unwrap_err();
+        let compile_error = compile_module(module)
+            .err()
+            .expect("invalid module compiled");
+        assert_eq!(error.to_string(), compile_error.to_string(), "{}", wat);
+        assert_eq!(error.kind(), compile_error.kind());
+        assert_eq!(error.func_body(), compile_error.func_body());
+        assert_eq!(error.instruction(), compile_error.instruction());
+    }
+}
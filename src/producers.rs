@@ -0,0 +1,155 @@
+use crate::Error;
+use alloc::{string::String, vec::Vec};
+use parity_wasm::elements::{deserialize_buffer, Module, VarUint32};
+
+/// Name of the custom section that records the toolchain that produced a module.
+const PRODUCERS_SECTION: &str = "producers";
+
+/// A tool or language recorded in the `producers` section, with its version.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProducerValue {
+    /// Name of the tool or language, e.g. `rustc`.
+    pub name: String,
+    /// Version of the tool or language, which can be empty.
+    pub version: String,
+}
+
+/// Contents of the [`producers` section] of a module.
+///
+/// The section lists the values of a few fields, e.g. the `language` the module was
+/// written in, the tools it was `processed-by` and the `sdk` used to build it.
+///
+/// [`producers` section]: https://github.com/WebAssembly/tool-conventions/blob/main/ProducersSection.md
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Producers {
+    fields: Vec<(String, Vec<ProducerValue>)>,
+}
+
+impl Producers {
+    /// Returns the fields of the section in their order, each with its values.
+    pub fn fields(&self) -> impl Iterator<Item = (&str, &[ProducerValue])> {
+        self.fields
+            .iter()
+            .map(|(name, values)| (name.as_str(), values.as_slice()))
+    }
+
+    /// Returns the values of the field `name`, which are empty if there is no such field.
+    pub fn field(&self, name: &str) -> &[ProducerValue] {
+        self.fields
+            .iter()
+            .find(|(field, _)| field == name)
+            .map_or(&[][..], |(_, values)| values.as_slice())
+    }
+}
+
+/// Split a `varuint32` off the start of `bytes`, returning its value and the rest.
+///
+/// The encoding ends with the first byte without the continuation bit, and is decoded by
+/// parity-wasm.
+pub(crate) fn split_var_u32(bytes: &[u8]) -> Option<(u32, &[u8])> {
+    let len = bytes.iter().position(|byte| byte & 0x80 == 0)? + 1;
+    let (encoding, rest) = bytes.split_at(len);
+    let value = deserialize_buffer::<VarUint32>(encoding).ok()?;
+    Some((value.into(), rest))
+}
+
+/// Reader of the primitives the `producers` section is encoded with.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn read_u32(&mut self) -> Option<u32> {
+        let (value, rest) = split_var_u32(self.bytes)?;
+        self.bytes = rest;
+        Some(value)
+    }
+
+    /// Reads `len` bytes.
+    fn read_bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        if len > self.bytes.len() {
+            return None;
+        }
+        let (bytes, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Some(bytes)
+    }
+
+    fn read_string(&mut self) -> Option<String> {
+        // The string is decoded along with its length, which is read ahead to know
+        // where it ends.
+        let start = self.bytes;
+        let len = self.read_u32()? as usize;
+        let encoded_len = (start.len() - self.bytes.len()).checked_add(len)?;
+        self.bytes = start;
+        deserialize_buffer(self.read_bytes(encoded_len)?).ok()
+    }
+
+    /// Reads a vector, without reserving space for a count that the remaining bytes
+    /// can't possibly hold.
+    fn read_vec<T, F: FnMut(&mut Self) -> Option<T>>(&mut self, mut read: F) -> Option<Vec<T>> {
+        let count = self.read_u32()? as usize;
+        let mut items = Vec::with_capacity(count.min(self.bytes.len()));
+        for _ in 0..count {
+            items.push(read(self)?);
+        }
+        Some(items)
+    }
+}
+
+/// Parse the `producers` section of `module`.
+///
+/// Returns `None` if the module has no such section, and `Err` if it is malformed.
+pub fn producers(module: &Module) -> Result<Option<Producers>, Error> {
+    let section = match module
+        .custom_sections()
+        .find(|section| section.name() == PRODUCERS_SECTION)
+    {
+        Some(section) => section,
+        None => return Ok(None),
+    };
+    let mut reader = Reader {
+        bytes: section.payload(),
+    };
+    let fields = reader
+        .read_vec(|reader| {
+            let name = reader.read_string()?;
+            let values = reader.read_vec(|reader| {
+                Some(ProducerValue {
+                    name: reader.read_string()?,
+                    version: reader.read_string()?,
+                })
+            })?;
+            Some((name, values))
+        })
+        .filter(|_| reader.bytes.is_empty())
+        .ok_or_else(|| Error::Validation("Malformed producers section".into()))?;
+    Ok(Some(Producers { fields }))
+}
+
+/// Verify that the `producers` section of `module` lists `field`, and only `allowed`
+/// values for it.
+///
+/// Returns `Err` if the section or the field is missing or malformed, or naming the
+/// first value that isn't allowed.
+pub fn require_producer(module: &Module, field: &str, allowed: &[&str]) -> Result<(), Error> {
+    let producers = producers(module)?
+        .ok_or_else(|| Error::Validation("Module has no producers section".into()))?;
+    let values = producers.field(field);
+    if values.is_empty() {
+        return Err(Error::Validation(format!(
+            "Producers section has no `{}` field",
+            field
+        )));
+    }
+    if let Some(value) = values
+        .iter()
+        .find(|value| !allowed.contains(&value.name.as_str()))
+    {
+        return Err(Error::Validation(format!(
+            "Producer `{}` {} of field `{}` is not allowed",
+            value.name, value.version, field
+        )));
+    }
+    Ok(())
+}
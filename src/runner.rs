@@ -1,6 +1,7 @@
 #![allow(clippy::unnecessary_wraps)]
 
-use crate::func::{FuncInstance, FuncInstanceInternal, FuncRef};
+use crate::debug::{BreakpointHandler, Breakpoints, CallHook, DebugFrame};
+use crate::func::{FuncInstanceInternal, FuncRef};
 use crate::host::Externals;
 use crate::isa;
 use crate::memory::MemoryRef;
@@ -11,13 +12,14 @@ use crate::value::{
     ArithmeticOps, ExtendInto, Float, Integer, LittleEndianConvert, RuntimeValue, TransmuteInto,
     TryTruncateInto, WrapInto,
 };
-use crate::{Signature, Trap, TrapKind, ValueType};
-use alloc::{boxed::Box, vec::Vec};
+use crate::{Error, Signature, Trap, TrapKind, ValueType};
+use alloc::{boxed::Box, rc::Rc, vec::Vec};
+use core::cmp;
 use core::fmt;
 use core::ops;
 use core::{u32, usize};
 use parity_wasm::elements::Local;
-use validation::{DEFAULT_MEMORY_INDEX, DEFAULT_TABLE_INDEX};
+use validation::DEFAULT_MEMORY_INDEX;
 
 /// Maximum number of bytes on the value stack.
 pub const DEFAULT_VALUE_STACK_LIMIT: usize = 1024 * 1024;
@@ -25,6 +27,9 @@ pub const DEFAULT_VALUE_STACK_LIMIT: usize = 1024 * 1024;
 /// Maximum number of levels on the call stack.
 pub const DEFAULT_CALL_STACK_LIMIT: usize = 64 * 1024;
 
+/// Number of bytes each value takes up on the value stack.
+pub(crate) const VALUE_STACK_CELL_SIZE: usize = ::core::mem::size_of::<RuntimeValueInternal>();
+
 /// This is a wrapper around u64 to allow us to treat runtime values as a tag-free `u64`
 /// (where if the runtime value is <64 bits the upper bits are 0). This is safe, since
 /// all of the possible runtime values are valid to create from 64 defined bits, so if
@@ -37,7 +42,7 @@ pub const DEFAULT_CALL_STACK_LIMIT: usize = 64 * 1024;
 /// at these boundaries.
 #[derive(Copy, Clone, Debug, PartialEq, Default)]
 #[repr(transparent)]
-struct RuntimeValueInternal(pub u64);
+pub(crate) struct RuntimeValueInternal(pub u64);
 
 impl RuntimeValueInternal {
     pub fn with_type(self, ty: ValueType) -> RuntimeValue {
@@ -145,11 +150,17 @@ pub enum InterpreterState {
     /// The interpreter has been executed, and returned a Host trap. It can resume execution by providing back a return
     /// value.
     Resumable(Option<ValueType>),
+    /// The interpreter has executed the number of instructions given to `set_yield_interval` and paused. It can
+    /// resume execution without providing a value.
+    Yielded,
 }
 
 impl InterpreterState {
     pub fn is_resumable(&self) -> bool {
-        matches!(self, InterpreterState::Resumable(_))
+        matches!(
+            self,
+            InterpreterState::Resumable(_) | InterpreterState::Yielded
+        )
     }
 }
 
@@ -159,6 +170,118 @@ enum RunResult {
     Return,
     /// Function is calling other function.
     NestedCall(FuncRef),
+    /// Function hit a breakpoint.
+    Breakpoint,
+    /// Function has to yield to the embedder.
+    Yield,
+}
+
+/// Breakpoints of a debugged execution.
+struct Debugger {
+    breakpoints: Breakpoints,
+    /// The module the breakpoints refer to.
+    module: ModuleRef,
+    /// Whether execution resumes at a breakpoint that was just hit, which mustn't be
+    /// hit again.
+    resuming: bool,
+}
+
+/// Handler of an execution without breakpoints.
+struct NoBreakpoints;
+
+impl BreakpointHandler for NoBreakpoints {
+    fn on_breakpoint(&mut self, _frame: &mut DebugFrame) -> Result<(), Trap> {
+        unreachable!("Breakpoints can't be hit without a debugger")
+    }
+}
+
+/// Observer of the calls of wasm functions, which is either nothing or a [`CallHook`].
+///
+/// [`CallHook`]: ../trait.CallHook.html
+trait CallEvents {
+    fn enter(&mut self, function: &FuncRef);
+    fn exit(&mut self);
+}
+
+/// Observer of an execution without a [`CallHook`], which costs nothing.
+///
+/// [`CallHook`]: ../trait.CallHook.html
+struct NoCallHook;
+
+impl CallEvents for NoCallHook {
+    #[inline(always)]
+    fn enter(&mut self, _function: &FuncRef) {}
+
+    #[inline(always)]
+    fn exit(&mut self) {}
+}
+
+/// Reports the calls of an execution to a [`CallHook`], keeping track of the functions
+/// that were entered, so that they can be exited if the execution traps.
+///
+/// [`CallHook`]: ../trait.CallHook.html
+struct CallTracker<'a, C> {
+    hook: &'a mut C,
+    entered: Vec<u32>,
+}
+
+impl<'a, C: CallHook> CallEvents for CallTracker<'a, C> {
+    fn enter(&mut self, function: &FuncRef) {
+        let func_index = function
+            .index()
+            .expect("Only functions defined by modules are executed by the interpreter; qed");
+        self.entered.push(func_index);
+        self.hook.on_enter(func_index);
+    }
+
+    fn exit(&mut self) {
+        let func_index = self
+            .entered
+            .pop()
+            .expect("Functions exit after they entered; qed");
+        self.hook.on_exit(func_index, false);
+    }
+}
+
+/// Statistics of a call, see [`FuncInstance::invoke_with_stats`].
+///
+/// [`FuncInstance::invoke_with_stats`]: struct.FuncInstance.html#method.invoke_with_stats
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct CallStats {
+    /// Number of executed instructions, including the instructions of nested calls.
+    ///
+    /// The instructions are the compiled ones that fuel is consumed by, not the wasm
+    /// instructions they were compiled from. Calling the same function of the same
+    /// module with the same arguments and host results executes the same number of
+    /// instructions on every platform, so the count can serve as a receipt of the
+    /// execution, e.g. to be committed to in a consensus log. This holds as long as:
+    ///
+    /// - The module is compiled in the same way. The compilation is the same for the
+    ///   same [`ISA_VERSION`], but compiling with inlining changes the count.
+    /// - The variable inputs of the execution are the same: the results of host
+    ///   functions, the stack limits and whether growing memory succeeds, which depends
+    ///   on the memory available to the host.
+    /// - Float results don't differ. The results of float instructions are the same on
+    ///   every platform, except for the bits of NaNs, which depend on the host FPU.
+    ///   These only change the count if the code inspects them, e.g. by reinterpreting
+    ///   a NaN as an integer and branching on it. The `soft_float` feature makes NaNs
+    ///   the same on every platform, too.
+    ///
+    /// [`ISA_VERSION`]: constant.ISA_VERSION.html
+    pub instructions: u64,
+    /// Number of calls of host functions.
+    pub host_calls: u64,
+    /// Maximum number of wasm functions that were on the call stack at once.
+    pub max_call_depth: usize,
+}
+
+/// Counters of an execution that collects [`CallStats`].
+struct StatsCounters {
+    /// Fuel at the start of the execution. Executed instructions are counted by the
+    /// fuel they consumed.
+    initial_fuel: u64,
+    host_calls: u64,
+    max_call_depth: usize,
 }
 
 /// Function interpreter.
@@ -167,6 +290,16 @@ pub struct Interpreter {
     call_stack: CallStack,
     return_type: Option<ValueType>,
     state: InterpreterState,
+    /// Remaining fuel, if execution is metered. Each executed compiled instruction
+    /// consumes one unit, see `CallStats::instructions`.
+    fuel: Option<u64>,
+    /// Number of host calls left, if they are limited.
+    host_calls_left: Option<u64>,
+    debugger: Option<Debugger>,
+    stats: Option<StatsCounters>,
+    /// Number of instructions to execute between yields, and the number left until
+    /// the next one, if execution yields.
+    yield_interval: Option<(u64, u64)>,
 }
 
 impl Interpreter {
@@ -175,6 +308,9 @@ impl Interpreter {
         args: &[RuntimeValue],
         mut stack_recycler: Option<&mut StackRecycler>,
     ) -> Result<Interpreter, Trap> {
+        #[cfg(feature = "std")]
+        crate::bounded_stack::check_native_stack()?;
+
         let mut value_stack = StackRecycler::recreate_value_stack(&mut stack_recycler);
         for &arg in args {
             let arg = arg.into();
@@ -196,6 +332,11 @@ impl Interpreter {
             call_stack,
             return_type,
             state: InterpreterState::Initialized,
+            fuel: None,
+            host_calls_left: None,
+            debugger: None,
+            stats: None,
+            yield_interval: None,
         })
     }
 
@@ -203,15 +344,140 @@ impl Interpreter {
         &self.state
     }
 
+    /// Meter the execution with the given amount of `fuel`, or don't meter it if `None`.
+    ///
+    /// Execution traps with `TrapKind::OutOfFuel` if it runs out of fuel.
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.fuel = fuel;
+    }
+
+    /// Remaining fuel, or `None` if the execution isn't metered.
+    pub fn fuel(&self) -> Option<u64> {
+        self.fuel
+    }
+
+    /// Allow the execution to call host functions at most `limit` times, or any number of
+    /// times if `None`.
+    ///
+    /// Execution traps with `TrapKind::HostCallLimitExceeded` instead of making one
+    /// more call.
+    pub fn set_host_call_limit(&mut self, limit: Option<u64>) {
+        self.host_calls_left = limit;
+    }
+
+    /// Pause the execution every `instructions` executed instructions, leaving the
+    /// interpreter in the `Yielded` state, or never pause it if `None`.
+    ///
+    /// The execution pauses between instructions, so it can be resumed with
+    /// `resume_execution` as if it hadn't been paused.
+    pub fn set_yield_interval(&mut self, instructions: Option<u64>) {
+        assert!(
+            instructions != Some(0),
+            "The yield interval must be at least one instruction"
+        );
+        self.yield_interval = instructions.map(|instructions| (instructions, instructions));
+    }
+
+    /// Collect [`CallStats`] of the execution, see [`stats`].
+    ///
+    /// Instructions are counted by metering the execution. If it isn't metered yet,
+    /// it is metered with fuel that can't run out in practice. Must be called after
+    /// [`set_fuel`].
+    ///
+    /// [`CallStats`]: struct.CallStats.html
+    /// [`stats`]: #method.stats
+    /// [`set_fuel`]: #method.set_fuel
+    pub fn collect_stats(&mut self) {
+        let initial_fuel = *self.fuel.get_or_insert(u64::MAX);
+        self.stats = Some(StatsCounters {
+            initial_fuel,
+            host_calls: 0,
+            max_call_depth: self.call_stack.len(),
+        });
+    }
+
+    /// Returns the statistics of the execution so far, or `None` if they aren't
+    /// collected.
+    pub fn stats(&self) -> Option<CallStats> {
+        let counters = self.stats.as_ref()?;
+        let fuel = self
+            .fuel
+            .expect("execution is metered while collecting stats; qed");
+        Some(CallStats {
+            instructions: counters.initial_fuel - fuel,
+            host_calls: counters.host_calls,
+            max_call_depth: counters.max_call_depth,
+        })
+    }
+
+    /// Pause the execution at `breakpoints` in functions of `module`, see
+    /// [`start_execution_with_breakpoints`].
+    ///
+    /// [`start_execution_with_breakpoints`]: #method.start_execution_with_breakpoints
+    pub fn set_breakpoints(&mut self, module: ModuleRef, breakpoints: Breakpoints) {
+        self.debugger = Some(Debugger {
+            breakpoints,
+            module,
+            resuming: false,
+        });
+    }
+
     pub fn start_execution<'a, E: Externals + 'a>(
         &mut self,
         externals: &'a mut E,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        self.start_execution_with_breakpoints(externals, &mut NoBreakpoints)
+    }
+
+    /// Start the execution, calling `handler` whenever a breakpoint set with
+    /// [`set_breakpoints`] is hit.
+    ///
+    /// [`set_breakpoints`]: #method.set_breakpoints
+    pub fn start_execution_with_breakpoints<'a, E: Externals + 'a, H: BreakpointHandler>(
+        &mut self,
+        externals: &'a mut E,
+        handler: &mut H,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        self.start_execution_with(externals, handler, &mut NoCallHook)
+    }
+
+    /// Start the execution, calling `hook` whenever a wasm function is entered or exited.
+    ///
+    /// If the execution traps, the functions on the call stack are exited, innermost
+    /// first, so `hook` observes balanced calls either way.
+    pub fn start_execution_with_call_hook<'a, E: Externals + 'a, C: CallHook>(
+        &mut self,
+        externals: &'a mut E,
+        hook: &mut C,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        let mut tracker = CallTracker {
+            hook,
+            entered: Vec::new(),
+        };
+        let result = self.start_execution_with(externals, &mut NoBreakpoints, &mut tracker);
+        if result.is_err() {
+            while let Some(func_index) = tracker.entered.pop() {
+                tracker.hook.on_exit(func_index, true);
+            }
+        }
+        result
+    }
+
+    fn start_execution_with<'a, E: Externals + 'a, H: BreakpointHandler, C: CallEvents>(
+        &mut self,
+        externals: &'a mut E,
+        handler: &mut H,
+        calls: &mut C,
     ) -> Result<Option<RuntimeValue>, Trap> {
         // Ensure that the VM has not been executed. This is checked in `FuncInvocation::start_execution`.
         assert!(self.state == InterpreterState::Initialized);
 
         self.state = InterpreterState::Started;
-        self.run_interpreter_loop(externals)?;
+        self.run_interpreter_loop(externals, handler, calls)?;
+        if self.state == InterpreterState::Yielded {
+            // There is no return value yet, the caller checks the state.
+            return Ok(None);
+        }
 
         let opt_return_value = self
             .return_type
@@ -242,7 +508,11 @@ impl Interpreter {
                 .map_err(Trap::new)?;
         }
 
-        self.run_interpreter_loop(externals)?;
+        self.run_interpreter_loop(externals, &mut NoBreakpoints, &mut NoCallHook)?;
+        if self.state == InterpreterState::Yielded {
+            // There is no return value yet, the caller checks the state.
+            return Ok(None);
+        }
 
         let opt_return_value = self
             .return_type
@@ -254,9 +524,11 @@ impl Interpreter {
         Ok(opt_return_value)
     }
 
-    fn run_interpreter_loop<'a, E: Externals + 'a>(
+    fn run_interpreter_loop<'a, E: Externals + 'a, H: BreakpointHandler, C: CallEvents>(
         &mut self,
         externals: &'a mut E,
+        handler: &mut H,
+        calls: &mut C,
     ) -> Result<(), Trap> {
         loop {
             let mut function_context = self.call_stack.pop().expect(
@@ -272,6 +544,7 @@ impl Interpreter {
             if !function_context.is_initialized() {
                 // Initialize stack frame for the function call.
                 function_context.initialize(&function_body.locals, &mut self.value_stack)?;
+                calls.enter(&function_ref);
             }
 
             let function_return = self
@@ -279,7 +552,17 @@ impl Interpreter {
                 .map_err(Trap::new)?;
 
             match function_return {
+                RunResult::Breakpoint => {
+                    self.hit_breakpoint(&function_context, &function_body.locals, handler)?;
+                    self.call_stack.push(function_context);
+                }
+                RunResult::Yield => {
+                    self.call_stack.push(function_context);
+                    self.state = InterpreterState::Yielded;
+                    return Ok(());
+                }
                 RunResult::Return => {
+                    calls.exit();
                     if self.call_stack.is_empty() {
                         // This was the last frame in the call stack. This means we
                         // are done executing.
@@ -288,7 +571,7 @@ impl Interpreter {
                 }
                 RunResult::NestedCall(nested_func) => {
                     if self.call_stack.is_full() {
-                        return Err(TrapKind::StackOverflow.into());
+                        return Err(TrapKind::CallDepthExceeded.into());
                     }
 
                     match *nested_func.as_internal() {
@@ -296,31 +579,49 @@ impl Interpreter {
                             let nested_context = FunctionContext::new(nested_func.clone());
                             self.call_stack.push(function_context);
                             self.call_stack.push(nested_context);
+                            if let Some(ref mut stats) = self.stats {
+                                stats.max_call_depth =
+                                    cmp::max(stats.max_call_depth, self.call_stack.len());
+                            }
                         }
-                        FuncInstanceInternal::Host { ref signature, .. } => {
+                        FuncInstanceInternal::Host {
+                            ref signature,
+                            host_func_index,
+                        } => {
+                            if let Some(ref mut left) = self.host_calls_left {
+                                if *left == 0 {
+                                    return Err(TrapKind::HostCallLimitExceeded.into());
+                                }
+                                *left -= 1;
+                            }
+                            if let Some(ref mut stats) = self.stats {
+                                stats.host_calls += 1;
+                            }
                             let args = prepare_function_args(signature, &mut self.value_stack);
-                            // We push the function context first. If the VM is not resumable, it does no harm. If it is, we then save the context here.
+                            let return_val = externals.invoke_index_with_memory(
+                                host_func_index,
+                                args.as_slice().into(),
+                                function_context.memory(),
+                            );
+                            // We push the function context regardless of the outcome. If the VM is not resumable, it does no harm. If it is, we then save the context here.
                             self.call_stack.push(function_context);
 
-                            let return_val =
-                                match FuncInstance::invoke(&nested_func, &args, externals) {
-                                    Ok(val) => val,
-                                    Err(trap) => {
-                                        if trap.kind().is_host() {
-                                            self.state = InterpreterState::Resumable(
-                                                nested_func.signature().return_type(),
-                                            );
-                                        }
-                                        return Err(trap);
+                            let return_val = match return_val {
+                                Ok(val) => val,
+                                Err(trap) => {
+                                    if trap.kind().is_host() {
+                                        self.state = InterpreterState::Resumable(
+                                            nested_func.signature().return_type(),
+                                        );
                                     }
-                                };
+                                    return Err(trap);
+                                }
+                            };
 
-                            // Check if `return_val` matches the signature.
-                            let value_ty = return_val.as_ref().map(|val| val.value_type());
-                            let expected_ty = nested_func.signature().return_type();
-                            if value_ty != expected_ty {
-                                return Err(TrapKind::UnexpectedSignature.into());
-                            }
+                            let return_val = check_host_result(
+                                nested_func.signature().return_type(),
+                                return_val,
+                            )?;
 
                             if let Some(return_val) = return_val {
                                 self.value_stack
@@ -328,26 +629,111 @@ impl Interpreter {
                                     .map_err(Trap::new)?;
                             }
                         }
+                        FuncInstanceInternal::Late { .. } => {
+                            return Err(TrapKind::UnboundFunction.into());
+                        }
                     }
                 }
             }
         }
     }
 
+    /// Returns the instructions of the function of `function_context` with a
+    /// breakpoint, in ascending order.
+    fn breakpoint_pcs(&self, function_context: &FunctionContext) -> Vec<u32> {
+        let debugger = match self.debugger {
+            Some(ref debugger) => debugger,
+            None => return Vec::new(),
+        };
+        if !Rc::ptr_eq(&debugger.module.0, &function_context.module.0) {
+            return Vec::new();
+        }
+        function_context
+            .function
+            .index()
+            .map(|func_index| debugger.breakpoints.pcs(func_index))
+            .unwrap_or_default()
+    }
+
+    fn hit_breakpoint<H: BreakpointHandler>(
+        &mut self,
+        function_context: &FunctionContext,
+        locals: &[Local],
+        handler: &mut H,
+    ) -> Result<(), Trap> {
+        let func_index = function_context
+            .function
+            .index()
+            .expect("Breakpoints are only hit in functions of the debugged module");
+        let mut local_types = function_context.function.signature().params().to_vec();
+        for local in locals {
+            let value_type = ValueType::from_elements(local.value_type());
+            local_types.extend((0..local.count()).map(|_| value_type));
+        }
+        let values = &mut self.value_stack.buf[function_context.locals_base..self.value_stack.sp];
+        let mut frame = DebugFrame::new(
+            func_index,
+            function_context.position,
+            local_types,
+            values,
+            function_context.memory(),
+        );
+        handler.on_breakpoint(&mut frame)?;
+        if let Some(ref mut debugger) = self.debugger {
+            debugger.resuming = true;
+        }
+        Ok(())
+    }
+
     fn do_run_function(
         &mut self,
         function_context: &mut FunctionContext,
         instructions: &isa::Instructions,
     ) -> Result<RunResult, TrapKind> {
         let mut iter = instructions.iterate_from(function_context.position);
+        let breakpoint_pcs = self.breakpoint_pcs(function_context);
+        let mut resuming = match self.debugger {
+            Some(ref mut debugger) => core::mem::replace(&mut debugger.resuming, false),
+            None => false,
+        };
 
         loop {
+            // Yield before checking for breakpoints, so that a breakpoint at the
+            // instruction is hit once execution resumes.
+            if let Some((interval, ref mut left)) = self.yield_interval {
+                if *left == 0 {
+                    *left = interval;
+                    function_context.position = iter.position();
+                    return Ok(RunResult::Yield);
+                }
+            }
+
+            if !breakpoint_pcs.is_empty() {
+                let pc = iter.position();
+                if !core::mem::replace(&mut resuming, false)
+                    && breakpoint_pcs.binary_search(&pc).is_ok()
+                {
+                    function_context.position = pc;
+                    return Ok(RunResult::Breakpoint);
+                }
+            }
+
             let instruction = iter.next().expect(
                 "Ran out of instructions, this should be impossible \
                  since validation ensures that we either have an explicit \
                  return or an implicit block `end`.",
             );
 
+            if let Some(ref mut fuel) = self.fuel {
+                if *fuel == 0 {
+                    return Err(TrapKind::OutOfFuel);
+                }
+                *fuel -= 1;
+            }
+            if let Some((_, ref mut left)) = self.yield_interval {
+                *left -= 1;
+            }
+
             match self.run_instruction(function_context, &instruction)? {
                 InstructionOutcome::RunNextInstruction => {}
                 InstructionOutcome::Branch(target) => {
@@ -384,7 +770,9 @@ impl Interpreter {
             isa::Instruction::Return(drop_keep) => self.run_return(*drop_keep),
 
             isa::Instruction::Call(index) => self.run_call(context, *index),
-            isa::Instruction::CallIndirect(index) => self.run_call_indirect(context, *index),
+            isa::Instruction::CallIndirect(index, table) => {
+                self.run_call_indirect(context, *index, *table)
+            }
 
             isa::Instruction::Drop => self.run_drop(),
             isa::Instruction::Select => self.run_select(),
@@ -449,6 +837,17 @@ impl Interpreter {
             isa::Instruction::CurrentMemory => self.run_current_memory(context),
             isa::Instruction::GrowMemory => self.run_grow_memory(context),
 
+            #[cfg(feature = "atomics")]
+            isa::Instruction::AtomicNotify(offset) => self.run_atomic_notify(context, *offset),
+            #[cfg(feature = "atomics")]
+            isa::Instruction::I32AtomicWait(offset) => {
+                self.run_atomic_wait::<i32>(context, *offset)
+            }
+            #[cfg(feature = "atomics")]
+            isa::Instruction::I64AtomicWait(offset) => {
+                self.run_atomic_wait::<i64>(context, *offset)
+            }
+
             isa::Instruction::I32Const(val) => self.run_const((*val).into()),
             isa::Instruction::I64Const(val) => self.run_const((*val).into()),
             isa::Instruction::F32Const(val) => self.run_const((*val).into()),
@@ -586,14 +985,28 @@ impl Interpreter {
             isa::Instruction::I64ReinterpretF64 => self.run_reinterpret::<F64, i64>(),
             isa::Instruction::F32ReinterpretI32 => self.run_reinterpret::<i32, F32>(),
             isa::Instruction::F64ReinterpretI64 => self.run_reinterpret::<i64, F64>(),
+
+            isa::Instruction::I32AddLocals(left, right) => self.run_i32_add_locals(*left, *right),
+            isa::Instruction::I32MulLocals(left, right) => self.run_i32_mul_locals(*left, *right),
+            isa::Instruction::I32AddLocalConst(local, value) => {
+                self.run_i32_add_local_const(*local, *value)
+            }
+            isa::Instruction::I32AddLocalsToLocal(left, right, dst) => {
+                self.run_i32_add_locals_to_local(*left, *right, *dst)
+            }
+            isa::Instruction::I32AddLocalConstToLocal(local, value, dst) => {
+                self.run_i32_add_local_const_to_local(*local, *value, *dst)
+            }
         }
     }
 
     fn run_unreachable(
         &mut self,
-        _context: &mut FunctionContext,
+        context: &mut FunctionContext,
     ) -> Result<InstructionOutcome, TrapKind> {
-        Err(TrapKind::Unreachable)
+        Err(TrapKind::Unreachable {
+            func_index: context.function.index(),
+        })
     }
 
     fn run_br(
@@ -650,11 +1063,12 @@ impl Interpreter {
         &mut self,
         context: &mut FunctionContext,
         signature_idx: u32,
+        table_idx: u32,
     ) -> Result<InstructionOutcome, TrapKind> {
         let table_func_idx: u32 = self.value_stack.pop_as();
         let table = context
             .module()
-            .table_by_index(DEFAULT_TABLE_INDEX)
+            .table_by_index(table_idx)
             .expect("Due to validation table should exists");
         let func_ref = table
             .get(table_func_idx)
@@ -668,8 +1082,12 @@ impl Interpreter {
                 .signature_by_index(signature_idx)
                 .expect("Due to validation type should exists");
 
-            if &*required_function_type != actual_function_type {
-                return Err(TrapKind::UnexpectedSignature);
+            // Functions of the module share its signatures, so most checks are
+            // settled by comparing pointers.
+            if !core::ptr::eq(&*required_function_type, actual_function_type)
+                && &*required_function_type != actual_function_type
+            {
+                return Err(TrapKind::IndirectCallTypeMismatch);
             }
         }
 
@@ -708,6 +1126,62 @@ impl Interpreter {
         Ok(InstructionOutcome::RunNextInstruction)
     }
 
+    fn i32_local(&self, depth: u32) -> i32 {
+        i32::from_runtime_value_internal(*self.value_stack.pick(depth as usize))
+    }
+
+    fn run_i32_add_locals(
+        &mut self,
+        left: u32,
+        right: u32,
+    ) -> Result<InstructionOutcome, TrapKind> {
+        let v = self.i32_local(left).wrapping_add(self.i32_local(right));
+        self.value_stack.push(v.into())?;
+        Ok(InstructionOutcome::RunNextInstruction)
+    }
+
+    fn run_i32_mul_locals(
+        &mut self,
+        left: u32,
+        right: u32,
+    ) -> Result<InstructionOutcome, TrapKind> {
+        let v = self.i32_local(left).wrapping_mul(self.i32_local(right));
+        self.value_stack.push(v.into())?;
+        Ok(InstructionOutcome::RunNextInstruction)
+    }
+
+    fn run_i32_add_local_const(
+        &mut self,
+        local: u32,
+        value: i32,
+    ) -> Result<InstructionOutcome, TrapKind> {
+        let v = self.i32_local(local).wrapping_add(value);
+        self.value_stack.push(v.into())?;
+        Ok(InstructionOutcome::RunNextInstruction)
+    }
+
+    fn run_i32_add_locals_to_local(
+        &mut self,
+        left: u32,
+        right: u32,
+        dst: u32,
+    ) -> Result<InstructionOutcome, TrapKind> {
+        let v = self.i32_local(left).wrapping_add(self.i32_local(right));
+        *self.value_stack.pick_mut(dst as usize) = v.into();
+        Ok(InstructionOutcome::RunNextInstruction)
+    }
+
+    fn run_i32_add_local_const_to_local(
+        &mut self,
+        local: u32,
+        value: i32,
+        dst: u32,
+    ) -> Result<InstructionOutcome, TrapKind> {
+        let v = self.i32_local(local).wrapping_add(value);
+        *self.value_stack.pick_mut(dst as usize) = v.into();
+        Ok(InstructionOutcome::RunNextInstruction)
+    }
+
     fn run_get_global(
         &mut self,
         context: &mut FunctionContext,
@@ -748,13 +1222,13 @@ impl Interpreter {
         T: LittleEndianConvert,
     {
         let raw_address = self.value_stack.pop_as();
-        let address = effective_address(offset, raw_address)?;
+        let address = effective_address::<T>(offset, raw_address)?;
         let m = context
             .memory()
             .expect("Due to validation memory should exists");
         let n: T = m
             .get_value(address)
-            .map_err(|_| TrapKind::MemoryAccessOutOfBounds)?;
+            .map_err(|_| out_of_bounds::<T>(address.into()))?;
         self.value_stack.push(n.into())?;
         Ok(InstructionOutcome::RunNextInstruction)
     }
@@ -770,13 +1244,13 @@ impl Interpreter {
         T: LittleEndianConvert,
     {
         let raw_address = self.value_stack.pop_as();
-        let address = effective_address(offset, raw_address)?;
+        let address = effective_address::<T>(offset, raw_address)?;
         let m = context
             .memory()
             .expect("Due to validation memory should exists");
         let v: T = m
             .get_value(address)
-            .map_err(|_| TrapKind::MemoryAccessOutOfBounds)?;
+            .map_err(|_| out_of_bounds::<T>(address.into()))?;
         let stack_value: U = v.extend_into();
         self.value_stack
             .push(stack_value.into())
@@ -795,13 +1269,13 @@ impl Interpreter {
     {
         let stack_value = self.value_stack.pop_as::<T>();
         let raw_address = self.value_stack.pop_as::<u32>();
-        let address = effective_address(offset, raw_address)?;
+        let address = effective_address::<T>(offset, raw_address)?;
 
         let m = context
             .memory()
             .expect("Due to validation memory should exists");
         m.set_value(address, stack_value)
-            .map_err(|_| TrapKind::MemoryAccessOutOfBounds)?;
+            .map_err(|_| out_of_bounds::<T>(address.into()))?;
         Ok(InstructionOutcome::RunNextInstruction)
     }
 
@@ -818,12 +1292,12 @@ impl Interpreter {
         let stack_value: T = <_>::from_runtime_value_internal(self.value_stack.pop());
         let stack_value = stack_value.wrap_into();
         let raw_address = self.value_stack.pop_as::<u32>();
-        let address = effective_address(offset, raw_address)?;
+        let address = effective_address::<U>(offset, raw_address)?;
         let m = context
             .memory()
             .expect("Due to validation memory should exists");
         m.set_value(address, stack_value)
-            .map_err(|_| TrapKind::MemoryAccessOutOfBounds)?;
+            .map_err(|_| out_of_bounds::<U>(address.into()))?;
         Ok(InstructionOutcome::RunNextInstruction)
     }
 
@@ -839,6 +1313,66 @@ impl Interpreter {
         Ok(InstructionOutcome::RunNextInstruction)
     }
 
+    /// Execute `atomic.notify`.
+    ///
+    /// There are no other threads in wasmi, hence there are never any waiters to wake up.
+    #[cfg(feature = "atomics")]
+    fn run_atomic_notify(
+        &mut self,
+        context: &mut FunctionContext,
+        offset: u32,
+    ) -> Result<InstructionOutcome, TrapKind> {
+        let _count: u32 = self.value_stack.pop_as();
+        let raw_address = self.value_stack.pop_as();
+        let address = effective_address::<i32>(offset, raw_address)?;
+        let m = check_atomic_access::<i32>(context, address)?;
+        // Only the bounds check is of interest here.
+        m.get_value::<i32>(address)
+            .map_err(|_| out_of_bounds::<i32>(address.into()))?;
+        self.value_stack.push(RuntimeValueInternal(0))?;
+        Ok(InstructionOutcome::RunNextInstruction)
+    }
+
+    /// Execute `i32.atomic.wait` or `i64.atomic.wait`.
+    ///
+    /// There are no other threads in wasmi that could notify the waiter. So if the
+    /// loaded value matches the expected one, the wait can only end by timing out.
+    /// That result is returned right away, without sleeping. Waiting without a
+    /// timeout would block forever and traps instead.
+    #[cfg(feature = "atomics")]
+    fn run_atomic_wait<T>(
+        &mut self,
+        context: &mut FunctionContext,
+        offset: u32,
+    ) -> Result<InstructionOutcome, TrapKind>
+    where
+        T: FromRuntimeValueInternal + LittleEndianConvert + PartialEq,
+    {
+        let timeout: i64 = self.value_stack.pop_as();
+        let expected: T = self.value_stack.pop_as();
+        let raw_address = self.value_stack.pop_as();
+        let address = effective_address::<T>(offset, raw_address)?;
+        let m = check_atomic_access::<T>(context, address)?;
+        let loaded: T = m
+            .get_value(address)
+            .map_err(|_| out_of_bounds::<T>(address.into()))?;
+        if !m.is_shared() {
+            return Err(TrapKind::AtomicWaitOnUnsharedMemory);
+        }
+
+        let result = if loaded != expected {
+            // "not-equal"
+            1
+        } else if timeout >= 0 {
+            // "timed-out"
+            2
+        } else {
+            return Err(TrapKind::AtomicWaitDeadlock);
+        };
+        self.value_stack.push(RuntimeValueInternal(result))?;
+        Ok(InstructionOutcome::RunNextInstruction)
+    }
+
     fn run_grow_memory(
         &mut self,
         context: &mut FunctionContext,
@@ -849,6 +1383,10 @@ impl Interpreter {
             .expect("Due to validation memory should exists");
         let m = match m.grow(Pages(pages as usize)) {
             Ok(Pages(new_size)) => new_size as u32,
+            Err(Error::MemoryAllocationFailed { .. }) if m.trap_on_grow_failure() => {
+                return Err(TrapKind::MemoryAllocationFailed)
+            }
+            Err(_) if m.trap_on_grow_failure() => return Err(TrapKind::MemoryGrowFailed),
             Err(_) => u32::MAX, // Returns -1 (or 0xFFFFFFFF) in case of error.
         };
         self.value_stack.push(RuntimeValueInternal(m as _))?;
@@ -1258,13 +1796,15 @@ struct FunctionContext {
     pub memory: Option<MemoryRef>,
     /// Current instruction position.
     pub position: u32,
+    /// Index in the value stack of the first local, i.e. the first parameter.
+    pub locals_base: usize,
 }
 
 impl FunctionContext {
     pub fn new(function: FuncRef) -> Self {
         let module = match function.as_internal() {
 			FuncInstanceInternal::Internal { module, .. } => module.upgrade().expect("module deallocated"),
-			FuncInstanceInternal::Host { .. } => panic!("Host functions can't be called as internally defined functions; Thus FunctionContext can be created only with internally defined functions; qed"),
+			FuncInstanceInternal::Host { .. } | FuncInstanceInternal::Late { .. } => panic!("Host functions can't be called as internally defined functions; Thus FunctionContext can be created only with internally defined functions; qed"),
 		};
         let memory = module.memory_by_index(DEFAULT_MEMORY_INDEX);
         FunctionContext {
@@ -1273,6 +1813,7 @@ impl FunctionContext {
             module: ModuleRef(module),
             memory,
             position: 0,
+            locals_base: 0,
         }
     }
 
@@ -1289,6 +1830,7 @@ impl FunctionContext {
 
         let num_locals = locals.iter().map(|l| l.count() as usize).sum();
 
+        self.locals_base = value_stack.len() - self.function.signature().params().len();
         value_stack.extend(num_locals)?;
 
         self.is_initialized = true;
@@ -1310,10 +1852,33 @@ impl fmt::Debug for FunctionContext {
     }
 }
 
-fn effective_address(address: u32, offset: u32) -> Result<u32, TrapKind> {
-    match offset.checked_add(address) {
-        None => Err(TrapKind::MemoryAccessOutOfBounds),
-        Some(address) => Ok(address),
+/// Check that an atomic access of a `T` at `address` is naturally aligned,
+/// returning the accessed memory.
+#[cfg(feature = "atomics")]
+fn check_atomic_access<T>(context: &FunctionContext, address: u32) -> Result<MemoryRef, TrapKind> {
+    if address as usize & (::core::mem::size_of::<T>() - 1) != 0 {
+        return Err(TrapKind::UnalignedAtomic);
+    }
+    Ok(context
+        .memory()
+        .expect("Due to validation memory should exists")
+        .clone())
+}
+
+/// Returns the address accessed by a load or store of a `T`, i.e. `raw_address + offset`.
+///
+/// Traps if the sum doesn't fit into the 32-bit address space.
+fn effective_address<T>(offset: u32, raw_address: u32) -> Result<u32, TrapKind> {
+    offset
+        .checked_add(raw_address)
+        .ok_or_else(|| out_of_bounds::<T>(u64::from(raw_address) + u64::from(offset)))
+}
+
+/// Returns the trap of an out of bounds access of a `T` at `address`.
+fn out_of_bounds<T>(address: u64) -> TrapKind {
+    TrapKind::MemoryAccessOutOfBounds {
+        address,
+        len: ::core::mem::size_of::<T>(),
     }
 }
 
@@ -1348,6 +1913,18 @@ pub fn check_function_args(signature: &Signature, args: &[RuntimeValue]) -> Resu
     Ok(())
 }
 
+/// Checks that the value returned by a host function matches its declared result type.
+pub fn check_host_result(
+    expected: Option<ValueType>,
+    return_val: Option<RuntimeValue>,
+) -> Result<Option<RuntimeValue>, Trap> {
+    let actual = return_val.as_ref().map(|val| val.value_type());
+    if actual != expected {
+        return Err(TrapKind::UnexpectedHostResult { expected, actual }.into());
+    }
+    Ok(return_val)
+}
+
 #[derive(Debug)]
 struct ValueStack {
     buf: Box<[RuntimeValueInternal]>,
@@ -1465,6 +2042,10 @@ impl CallStack {
         self.buf.is_empty()
     }
 
+    fn len(&self) -> usize {
+        self.buf.len()
+    }
+
     fn is_full(&self) -> bool {
         self.buf.len() + 1 >= self.limit
     }
@@ -1482,6 +2063,12 @@ impl StackRecycler {
     /// Limit stacks created by this recycler to
     /// - `value_stack_limit` bytes for values and
     /// - `call_stack_limit` levels for calls.
+    ///
+    /// Exceeding the limit of the value stack traps with [`TrapKind::StackOverflow`], and
+    /// exceeding the limit of the call stack with [`TrapKind::CallDepthExceeded`].
+    ///
+    /// [`TrapKind::StackOverflow`]: enum.TrapKind.html#variant.StackOverflow
+    /// [`TrapKind::CallDepthExceeded`]: enum.TrapKind.html#variant.CallDepthExceeded
     pub fn with_limits(value_stack_limit: usize, call_stack_limit: usize) -> Self {
         Self {
             value_stack_buf: None,
@@ -1511,7 +2098,7 @@ impl StackRecycler {
         let limit = this
             .as_ref()
             .map_or(DEFAULT_VALUE_STACK_LIMIT, |this| this.value_stack_limit)
-            / ::core::mem::size_of::<RuntimeValueInternal>();
+            / VALUE_STACK_CELL_SIZE;
 
         let buf = this
             .as_mut()
@@ -0,0 +1,775 @@
+//! Software implementation of the float operations of wasm, used instead of the
+//! host FPU when the `soft_float` feature is enabled.
+//!
+//! The operations are computed on the bit patterns with integer arithmetic only,
+//! so their results are bit-identical on every platform, NaNs included: a NaN
+//! result is the first NaN operand made quiet, or the positive canonical NaN if no
+//! operand is a NaN.
+//!
+//! All operations are correctly rounded to nearest, ties to even, as wasm requires.
+
+use crate::nan_preserving_float::{F32, F64};
+use crate::value::{ArithmeticOps, ExtendInto, Float, WrapInto};
+use crate::TrapKind;
+use core::cmp;
+
+/// Parameters of an IEEE 754 binary interchange format. Values of the format are
+/// handled as the low bits of a `u64`.
+#[derive(Copy, Clone)]
+struct Format {
+    mant_bits: u32,
+    exp_bits: u32,
+}
+
+const BINARY32: Format = Format {
+    mant_bits: 23,
+    exp_bits: 8,
+};
+
+const BINARY64: Format = Format {
+    mant_bits: 52,
+    exp_bits: 11,
+};
+
+/// The class of a value, with finite nonzero values decoded to `sig * 2^exp`.
+///
+/// `sig` is normalized to have its highest bit at `mant_bits`, subnormal values
+/// included.
+enum Class {
+    Nan,
+    Inf,
+    Zero,
+    Finite { sig: u64, exp: i32 },
+}
+
+impl Format {
+    fn bias(self) -> i32 {
+        (1 << (self.exp_bits - 1)) - 1
+    }
+
+    /// The biased exponent of infinities and NaNs.
+    fn max_exp(self) -> u64 {
+        (1 << self.exp_bits) - 1
+    }
+
+    fn sign_bit(self) -> u64 {
+        1 << (self.mant_bits + self.exp_bits)
+    }
+
+    fn mant_mask(self) -> u64 {
+        (1 << self.mant_bits) - 1
+    }
+
+    fn quiet_bit(self) -> u64 {
+        1 << (self.mant_bits - 1)
+    }
+
+    fn one(self) -> u64 {
+        (self.bias() as u64) << self.mant_bits
+    }
+
+    fn half(self) -> u64 {
+        (self.bias() as u64 - 1) << self.mant_bits
+    }
+
+    fn zero(self, negative: bool) -> u64 {
+        if negative {
+            self.sign_bit()
+        } else {
+            0
+        }
+    }
+
+    fn inf(self, negative: bool) -> u64 {
+        self.zero(negative) | self.max_exp() << self.mant_bits
+    }
+
+    fn canonical_nan(self) -> u64 {
+        self.inf(false) | self.quiet_bit()
+    }
+
+    fn is_negative(self, bits: u64) -> bool {
+        bits & self.sign_bit() != 0
+    }
+
+    fn biased_exp(self, bits: u64) -> u64 {
+        (bits >> self.mant_bits) & self.max_exp()
+    }
+
+    fn is_nan(self, bits: u64) -> bool {
+        self.biased_exp(bits) == self.max_exp() && bits & self.mant_mask() != 0
+    }
+
+    fn classify(self, bits: u64) -> Class {
+        let biased_exp = self.biased_exp(bits);
+        let mant = bits & self.mant_mask();
+        if biased_exp == self.max_exp() {
+            return if mant == 0 { Class::Inf } else { Class::Nan };
+        }
+        if biased_exp == 0 {
+            if mant == 0 {
+                return Class::Zero;
+            }
+            let shift = mant.leading_zeros() - (63 - self.mant_bits);
+            return Class::Finite {
+                sig: mant << shift,
+                exp: 1 - self.bias() - self.mant_bits as i32 - shift as i32,
+            };
+        }
+        Class::Finite {
+            sig: mant | 1 << self.mant_bits,
+            exp: biased_exp as i32 - self.bias() - self.mant_bits as i32,
+        }
+    }
+
+    /// Returns the NaN result of an operation on `a` and `b`.
+    fn propagate_nan(self, a: u64, b: u64) -> u64 {
+        if self.is_nan(a) {
+            a | self.quiet_bit()
+        } else if self.is_nan(b) {
+            b | self.quiet_bit()
+        } else {
+            self.canonical_nan()
+        }
+    }
+
+    /// Round the exact value `sig * 2^exp` to the format.
+    fn round_pack(self, negative: bool, exp: i32, sig: u128) -> u64 {
+        if sig == 0 {
+            return self.zero(negative);
+        }
+        let mant_bits = self.mant_bits as i32;
+        let msb_exp = exp + 127 - sig.leading_zeros() as i32;
+        let mut quantum_exp = cmp::max(msb_exp, 1 - self.bias()) - mant_bits;
+        let shift = quantum_exp - exp;
+        let mut sig = if shift <= 0 {
+            sig << -shift
+        } else {
+            round_shift(sig, shift as u32)
+        };
+        if sig == 1 << (mant_bits + 1) {
+            sig >>= 1;
+            quantum_exp += 1;
+        }
+
+        let sig = sig as u64;
+        let (biased_exp, mant) = if sig >> mant_bits != 0 {
+            (
+                (quantum_exp + mant_bits + self.bias()) as u64,
+                sig & self.mant_mask(),
+            )
+        } else {
+            (0, sig)
+        };
+        if biased_exp >= self.max_exp() {
+            return self.inf(negative);
+        }
+        self.zero(negative) | biased_exp << self.mant_bits | mant
+    }
+
+    fn add(self, a: u64, b: u64) -> u64 {
+        let (a_negative, b_negative) = (self.is_negative(a), self.is_negative(b));
+        match (self.classify(a), self.classify(b)) {
+            (Class::Nan, _) | (_, Class::Nan) => self.propagate_nan(a, b),
+            (Class::Inf, Class::Inf) if a_negative != b_negative => self.canonical_nan(),
+            (Class::Inf, _) => a,
+            (_, Class::Inf) => b,
+            (Class::Zero, Class::Zero) => self.zero(a_negative && b_negative),
+            (Class::Zero, _) => b,
+            (_, Class::Zero) => a,
+            (
+                Class::Finite {
+                    sig: a_sig,
+                    exp: a_exp,
+                },
+                Class::Finite {
+                    sig: b_sig,
+                    exp: b_exp,
+                },
+            ) => {
+                // `x` is the operand with the larger exponent.
+                let ((x_negative, x_sig, x_exp), (y_negative, y_sig, y_exp)) = if a_exp >= b_exp {
+                    ((a_negative, a_sig, a_exp), (b_negative, b_sig, b_exp))
+                } else {
+                    ((b_negative, b_sig, b_exp), (a_negative, a_sig, a_exp))
+                };
+                let distance = (x_exp - y_exp) as u32;
+                let (x, y, exp) = if distance <= 64 {
+                    ((x_sig as u128) << distance, y_sig as u128, y_exp)
+                } else {
+                    // `y` is far below the rounding position of the result, so any value
+                    // strictly between zero and the last bit of the shifted `x` rounds
+                    // the same way.
+                    ((x_sig as u128) << 65, 1, x_exp - 65)
+                };
+                if x_negative == y_negative {
+                    self.round_pack(x_negative, exp, x + y)
+                } else if x > y {
+                    self.round_pack(x_negative, exp, x - y)
+                } else if x < y {
+                    self.round_pack(y_negative, exp, y - x)
+                } else {
+                    self.zero(false)
+                }
+            }
+        }
+    }
+
+    fn sub(self, a: u64, b: u64) -> u64 {
+        if self.is_nan(a) || self.is_nan(b) {
+            return self.propagate_nan(a, b);
+        }
+        self.add(a, b ^ self.sign_bit())
+    }
+
+    fn mul(self, a: u64, b: u64) -> u64 {
+        let negative = self.is_negative(a) != self.is_negative(b);
+        match (self.classify(a), self.classify(b)) {
+            (Class::Nan, _) | (_, Class::Nan) => self.propagate_nan(a, b),
+            (Class::Inf, Class::Zero) | (Class::Zero, Class::Inf) => self.canonical_nan(),
+            (Class::Inf, _) | (_, Class::Inf) => self.inf(negative),
+            (Class::Zero, _) | (_, Class::Zero) => self.zero(negative),
+            (
+                Class::Finite {
+                    sig: a_sig,
+                    exp: a_exp,
+                },
+                Class::Finite {
+                    sig: b_sig,
+                    exp: b_exp,
+                },
+            ) => self.round_pack(negative, a_exp + b_exp, a_sig as u128 * b_sig as u128),
+        }
+    }
+
+    fn div(self, a: u64, b: u64) -> u64 {
+        let negative = self.is_negative(a) != self.is_negative(b);
+        match (self.classify(a), self.classify(b)) {
+            (Class::Nan, _) | (_, Class::Nan) => self.propagate_nan(a, b),
+            (Class::Inf, Class::Inf) | (Class::Zero, Class::Zero) => self.canonical_nan(),
+            (Class::Inf, _) | (_, Class::Zero) => self.inf(negative),
+            (_, Class::Inf) | (Class::Zero, _) => self.zero(negative),
+            (
+                Class::Finite {
+                    sig: a_sig,
+                    exp: a_exp,
+                },
+                Class::Finite {
+                    sig: b_sig,
+                    exp: b_exp,
+                },
+            ) => {
+                let dividend = (a_sig as u128) << 64;
+                let quotient = dividend / b_sig as u128;
+                let sticky = (quotient * b_sig as u128 != dividend) as u128;
+                self.round_pack(negative, a_exp - b_exp - 65, quotient << 1 | sticky)
+            }
+        }
+    }
+
+    fn sqrt(self, a: u64) -> u64 {
+        match self.classify(a) {
+            Class::Nan => self.propagate_nan(a, a),
+            Class::Zero => a,
+            _ if self.is_negative(a) => self.canonical_nan(),
+            Class::Inf => a,
+            Class::Finite { sig, exp } => {
+                // Make the exponent even, so that it can be halved.
+                let (sig, exp) = if exp & 1 != 0 {
+                    ((sig as u128) << 1, exp - 1)
+                } else {
+                    (sig as u128, exp)
+                };
+                let scaled = sig << 72;
+                let root = isqrt(scaled);
+                let sticky = (root * root != scaled) as u128;
+                self.round_pack(false, (exp - 72) / 2 - 1, root << 1 | sticky)
+            }
+        }
+    }
+
+    /// Returns `true` if `bits` is an odd integer. `bits` must be an integer below
+    /// `2^mant_bits` in magnitude.
+    fn is_odd(self, bits: u64) -> bool {
+        match self.classify(bits) {
+            Class::Finite { sig, exp } => exp > -64 && exp <= 0 && (sig >> -exp) & 1 != 0,
+            _ => false,
+        }
+    }
+
+    fn trunc(self, a: u64) -> u64 {
+        let biased_exp = self.biased_exp(a);
+        if biased_exp == self.max_exp() {
+            return if self.is_nan(a) {
+                a | self.quiet_bit()
+            } else {
+                a
+            };
+        }
+        let exp = biased_exp as i32 - self.bias();
+        if exp < 0 {
+            self.zero(self.is_negative(a))
+        } else if exp >= self.mant_bits as i32 {
+            a
+        } else {
+            a & !(self.mant_mask() >> exp)
+        }
+    }
+
+    fn floor(self, a: u64) -> u64 {
+        let truncated = self.trunc(a);
+        if truncated != a && !self.is_nan(a) && self.is_negative(a) {
+            self.sub(truncated, self.one())
+        } else {
+            truncated
+        }
+    }
+
+    fn ceil(self, a: u64) -> u64 {
+        let truncated = self.trunc(a);
+        if truncated != a && !self.is_nan(a) && !self.is_negative(a) {
+            self.add(truncated, self.one())
+        } else {
+            truncated
+        }
+    }
+
+    /// Round `a` to an integer, with ties rounded to even if `ties_to_even` is set and
+    /// away from zero otherwise.
+    fn round_to_integer(self, a: u64, ties_to_even: bool) -> u64 {
+        let truncated = self.trunc(a);
+        if truncated == a || self.is_nan(a) {
+            return truncated;
+        }
+        // Exact, as `truncated` has the same sign and a larger quantum than `a`.
+        let fraction = self.sub(a, truncated) & !self.sign_bit();
+        let away = fraction > self.half()
+            || (fraction == self.half() && (!ties_to_even || self.is_odd(truncated)));
+        if !away {
+            truncated
+        } else if self.is_negative(a) {
+            self.sub(truncated, self.one())
+        } else {
+            self.add(truncated, self.one())
+        }
+    }
+
+    /// Map non-NaN values to integers of the same order, with `-0` below `+0`.
+    fn order_key(self, bits: u64) -> u64 {
+        if self.is_negative(bits) {
+            !bits & (self.sign_bit() << 1).wrapping_sub(1)
+        } else {
+            bits | self.sign_bit()
+        }
+    }
+
+    fn min(self, a: u64, b: u64) -> u64 {
+        if self.is_nan(a) {
+            a
+        } else if self.is_nan(b) || self.order_key(b) < self.order_key(a) {
+            b
+        } else {
+            a
+        }
+    }
+
+    fn max(self, a: u64, b: u64) -> u64 {
+        if self.is_nan(a) {
+            a
+        } else if self.is_nan(b) || self.order_key(b) > self.order_key(a) {
+            b
+        } else {
+            a
+        }
+    }
+
+    /// Round the integer `-magnitude` or `magnitude` to the format.
+    fn convert_int(self, negative: bool, magnitude: u64) -> u64 {
+        self.round_pack(negative, 0, magnitude as u128)
+    }
+
+    /// Convert `bits` of the format `from` to this format.
+    fn convert(self, from: Format, bits: u64) -> u64 {
+        let negative = from.is_negative(bits);
+        match from.classify(bits) {
+            Class::Nan => {
+                // Keep the highest bits of the payload.
+                let mant = bits & from.mant_mask();
+                let payload = if self.mant_bits >= from.mant_bits {
+                    mant << (self.mant_bits - from.mant_bits)
+                } else {
+                    mant >> (from.mant_bits - self.mant_bits)
+                };
+                self.inf(negative) | self.quiet_bit() | payload
+            }
+            Class::Inf => self.inf(negative),
+            Class::Zero => self.zero(negative),
+            Class::Finite { sig, exp } => self.round_pack(negative, exp, sig as u128),
+        }
+    }
+}
+
+/// Shift `sig` right by `shift`, rounding to nearest, ties to even.
+fn round_shift(sig: u128, shift: u32) -> u128 {
+    if shift > 128 {
+        // `sig` is below half of the last kept bit.
+        return 0;
+    }
+    let (kept, rest) = if shift == 128 {
+        (0, sig)
+    } else {
+        (sig >> shift, sig & ((1 << shift) - 1))
+    };
+    let half = 1 << (shift - 1);
+    if rest > half || (rest == half && kept & 1 != 0) {
+        kept + 1
+    } else {
+        kept
+    }
+}
+
+/// Integer square root, rounded down.
+fn isqrt(n: u128) -> u128 {
+    let mut rest = n;
+    let mut root = 0;
+    let mut bit = 1 << 126;
+    while bit > rest {
+        bit >>= 2;
+    }
+    while bit != 0 {
+        if rest >= root + bit {
+            rest -= root + bit;
+            root = (root >> 1) + bit;
+        } else {
+            root >>= 1;
+        }
+        bit >>= 2;
+    }
+    root
+}
+
+macro_rules! impl_soft_float {
+    ($type:ident, $format:expr) => {
+        impl ArithmeticOps<$type> for $type {
+            fn add(self, other: $type) -> $type {
+                soft_op2($format, self, other, Format::add)
+            }
+            fn sub(self, other: $type) -> $type {
+                soft_op2($format, self, other, Format::sub)
+            }
+            fn mul(self, other: $type) -> $type {
+                soft_op2($format, self, other, Format::mul)
+            }
+            fn div(self, other: $type) -> Result<$type, TrapKind> {
+                Ok(soft_op2($format, self, other, Format::div))
+            }
+        }
+
+        impl Float<$type> for $type {
+            fn abs(self) -> $type {
+                $type::abs(self)
+            }
+            fn floor(self) -> $type {
+                soft_op1($format, self, Format::floor)
+            }
+            fn ceil(self) -> $type {
+                soft_op1($format, self, Format::ceil)
+            }
+            fn trunc(self) -> $type {
+                soft_op1($format, self, Format::trunc)
+            }
+            fn round(self) -> $type {
+                soft_op1($format, self, |format, a| format.round_to_integer(a, false))
+            }
+            fn nearest(self) -> $type {
+                soft_op1($format, self, |format, a| format.round_to_integer(a, true))
+            }
+            fn sqrt(self) -> $type {
+                soft_op1($format, self, Format::sqrt)
+            }
+            fn min(self, other: $type) -> $type {
+                soft_op2($format, self, other, Format::min)
+            }
+            fn max(self, other: $type) -> $type {
+                soft_op2($format, self, other, Format::max)
+            }
+            fn copysign(self, other: $type) -> $type {
+                soft_op2($format, self, other, |format, a, b| {
+                    a & !format.sign_bit() | b & format.sign_bit()
+                })
+            }
+        }
+    };
+}
+
+fn soft_op1<T: SoftFloat>(format: Format, a: T, op: impl FnOnce(Format, u64) -> u64) -> T {
+    T::from_soft_bits(op(format, a.soft_bits()))
+}
+
+fn soft_op2<T: SoftFloat>(
+    format: Format,
+    a: T,
+    b: T,
+    op: impl FnOnce(Format, u64, u64) -> u64,
+) -> T {
+    T::from_soft_bits(op(format, a.soft_bits(), b.soft_bits()))
+}
+
+/// Access to the bits of [`F32`] and [`F64`] as `u64`.
+trait SoftFloat: Copy {
+    fn soft_bits(self) -> u64;
+    fn from_soft_bits(bits: u64) -> Self;
+}
+
+impl SoftFloat for F32 {
+    fn soft_bits(self) -> u64 {
+        u64::from(self.to_bits())
+    }
+    fn from_soft_bits(bits: u64) -> F32 {
+        F32::from_bits(bits as u32)
+    }
+}
+
+impl SoftFloat for F64 {
+    fn soft_bits(self) -> u64 {
+        self.to_bits()
+    }
+    fn from_soft_bits(bits: u64) -> F64 {
+        F64::from_bits(bits)
+    }
+}
+
+impl_soft_float!(F32, BINARY32);
+impl_soft_float!(F64, BINARY64);
+
+macro_rules! impl_from_int {
+    ($trait:ident, $method:ident, $from:ident, $into:ident, $format:expr) => {
+        impl $trait<$into> for $from {
+            fn $method(self) -> $into {
+                // Widening to `i128` makes the magnitude of the minimum value representable.
+                let value = self as i128;
+                $into::from_soft_bits($format.convert_int(value < 0, value.unsigned_abs() as u64))
+            }
+        }
+    };
+}
+
+impl_from_int!(ExtendInto, extend_into, i32, F32, BINARY32);
+impl_from_int!(ExtendInto, extend_into, u32, F32, BINARY32);
+impl_from_int!(WrapInto, wrap_into, i64, F32, BINARY32);
+impl_from_int!(WrapInto, wrap_into, u64, F32, BINARY32);
+impl_from_int!(ExtendInto, extend_into, i32, F64, BINARY64);
+impl_from_int!(ExtendInto, extend_into, u32, F64, BINARY64);
+impl_from_int!(ExtendInto, extend_into, i64, F64, BINARY64);
+impl_from_int!(ExtendInto, extend_into, u64, F64, BINARY64);
+
+impl WrapInto<F32> for F64 {
+    fn wrap_into(self) -> F32 {
+        F32::from_soft_bits(BINARY32.convert(BINARY64, self.to_bits()))
+    }
+}
+
+impl ExtendInto<F64> for F32 {
+    fn extend_into(self) -> F64 {
+        F64::from_soft_bits(BINARY64.convert(BINARY32, u64::from(self.to_bits())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate rand;
+
+    use self::rand::Rng;
+    use super::*;
+
+    fn special_f64() -> Vec<f64> {
+        vec![
+            0.0,
+            -0.0,
+            0.5,
+            -0.5,
+            1.0,
+            -1.5,
+            2.5,
+            3.5,
+            -2.5,
+            1e-310,
+            f64::from_bits(1),
+            f64::MIN_POSITIVE,
+            f64::MAX,
+            -f64::MAX,
+            4503599627370495.5,
+            9007199254740993.0,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+        ]
+    }
+
+    fn random_f64(rng: &mut rand::ThreadRng) -> f64 {
+        match rng.gen_range(0, 3) {
+            0 => f64::from_bits(rng.gen()),
+            1 => (rng.gen::<f64>() - 0.5) * 2000.0,
+            _ => f64::from_bits(rng.gen_range(0, 1 << 53)),
+        }
+    }
+
+    /// Round to the nearest integer, ties to even, with the host FPU.
+    fn nearest(x: f64) -> f64 {
+        if (x - x.trunc()).abs() == 0.5 {
+            (x / 2.0).round() * 2.0
+        } else {
+            x.round()
+        }
+    }
+
+    fn assert_same(soft: u64, native: f64, what: &str) {
+        if native.is_nan() {
+            assert!(BINARY64.is_nan(soft), "{}: {:#x} isn't NaN", what, soft);
+        } else {
+            assert_eq!(soft, native.to_bits(), "{} = {:?}", what, native);
+        }
+    }
+
+    fn assert_same_f32(soft: u64, native: f32, what: &str) {
+        if native.is_nan() {
+            assert!(BINARY32.is_nan(soft), "{}: {:#x} isn't NaN", what, soft);
+        } else {
+            assert_eq!(soft, u64::from(native.to_bits()), "{} = {:?}", what, native);
+        }
+    }
+
+    fn check_pair(a: f64, b: f64) {
+        let (x, y) = (a.to_bits(), b.to_bits());
+        let f = BINARY64;
+        assert_same(f.add(x, y), a + b, &format!("{:?} + {:?}", a, b));
+        assert_same(f.sub(x, y), a - b, &format!("{:?} - {:?}", a, b));
+        assert_same(f.mul(x, y), a * b, &format!("{:?} * {:?}", a, b));
+        assert_same(f.div(x, y), a / b, &format!("{:?} / {:?}", a, b));
+        // The host `min` and `max` ignore NaNs and are unspecified for zeros of both signs.
+        if a.is_nan() || b.is_nan() {
+            assert!(f.is_nan(f.min(x, y)) && f.is_nan(f.max(x, y)));
+        } else if a != 0.0 || b != 0.0 {
+            assert_same(f.min(x, y), a.min(b), &format!("min({:?}, {:?})", a, b));
+            assert_same(f.max(x, y), a.max(b), &format!("max({:?}, {:?})", a, b));
+        }
+
+        let (a, b) = (a as f32, b as f32);
+        let (x, y) = (u64::from(a.to_bits()), u64::from(b.to_bits()));
+        let f = BINARY32;
+        assert_same_f32(f.add(x, y), a + b, &format!("{:?} + {:?}", a, b));
+        assert_same_f32(f.sub(x, y), a - b, &format!("{:?} - {:?}", a, b));
+        assert_same_f32(f.mul(x, y), a * b, &format!("{:?} * {:?}", a, b));
+        assert_same_f32(f.div(x, y), a / b, &format!("{:?} / {:?}", a, b));
+    }
+
+    fn check_single(a: f64) {
+        let x = a.to_bits();
+        let f = BINARY64;
+        assert_same(f.sqrt(x), a.sqrt(), &format!("sqrt({:?})", a));
+        assert_same(f.floor(x), a.floor(), &format!("floor({:?})", a));
+        assert_same(f.ceil(x), a.ceil(), &format!("ceil({:?})", a));
+        assert_same(f.trunc(x), a.trunc(), &format!("trunc({:?})", a));
+        assert_same(
+            f.round_to_integer(x, true),
+            nearest(a),
+            &format!("nearest({:?})", a),
+        );
+        assert_same_f32(
+            BINARY32.convert(f, x),
+            a as f32,
+            &format!("demote({:?})", a),
+        );
+
+        let a = a as f32;
+        let x = u64::from(a.to_bits());
+        let f = BINARY32;
+        assert_same_f32(f.sqrt(x), a.sqrt(), &format!("sqrt({:?})", a));
+        assert_same_f32(
+            f.round_to_integer(x, true),
+            nearest(f64::from(a)) as f32,
+            &format!("nearest({:?})", a),
+        );
+        assert_same(
+            BINARY64.convert(f, x),
+            f64::from(a),
+            &format!("promote({:?})", a),
+        );
+    }
+
+    #[test]
+    fn matches_host_fpu() {
+        let specials = special_f64();
+        for &a in &specials {
+            check_single(a);
+            for &b in &specials {
+                check_pair(a, b);
+            }
+        }
+
+        let mut rng = rand::thread_rng();
+        for _ in 0..10000 {
+            let (a, b) = (random_f64(&mut rng), random_f64(&mut rng));
+            check_single(a);
+            check_pair(a, b);
+        }
+    }
+
+    #[test]
+    fn int_conversions_match_host_fpu() {
+        let mut rng = rand::thread_rng();
+        let values = [0, 1, u64::MAX, i64::MIN as u64, 0x0020_0000_2000_0001];
+        let random = (0..1000).map(|_| rng.gen::<u64>() >> rng.gen_range(0, 64));
+        for value in values.iter().cloned().chain(random) {
+            let bits = WrapInto::<F32>::wrap_into(value).to_bits();
+            assert_eq!(bits, (value as f32).to_bits(), "{}", value);
+            let bits = WrapInto::<F32>::wrap_into(value as i64).to_bits();
+            assert_eq!(bits, (value as i64 as f32).to_bits(), "{}", value as i64);
+            let bits = ExtendInto::<F64>::extend_into(value).to_bits();
+            assert_eq!(bits, (value as f64).to_bits(), "{}", value);
+            let bits = ExtendInto::<F64>::extend_into(value as i64).to_bits();
+            assert_eq!(bits, (value as i64 as f64).to_bits(), "{}", value as i64);
+            let bits = ExtendInto::<F32>::extend_into(value as i32).to_bits();
+            assert_eq!(bits, (value as i32 as f32).to_bits(), "{}", value as i32);
+        }
+    }
+
+    #[test]
+    fn spec_vectors() {
+        let f = BINARY64;
+        // Ties are rounded to even.
+        assert_eq!(f.add(1f64.to_bits(), 0x3ca0_0000_0000_0000), 1f64.to_bits());
+        assert_eq!(
+            f.add(0x3ff0_0000_0000_0001, 0x3ca0_0000_0000_0000),
+            0x3ff0_0000_0000_0002
+        );
+        assert_eq!(f.sqrt(2f64.to_bits()), 0x3ff6_a09e_667f_3bcd);
+        assert_eq!(
+            f.round_to_integer((-0.5f64).to_bits(), true),
+            0x8000_0000_0000_0000
+        );
+        assert_eq!(f.round_to_integer(4.5f64.to_bits(), true), 4f64.to_bits());
+
+        let f = BINARY32;
+        assert_eq!(
+            f.div(1f32.to_bits().into(), 3f32.to_bits().into()),
+            0x3eaa_aaab
+        );
+        // The smallest subnormal, and half of it, which rounds to zero.
+        assert_eq!(f.convert(BINARY64, 0x36a0_0000_0000_0000), 0x0000_0001);
+        assert_eq!(f.convert(BINARY64, 0x3690_0000_0000_0000), 0x0000_0000);
+        assert_eq!(f.convert(BINARY64, 0x3690_0000_0000_0001), 0x0000_0001);
+        assert_eq!(f.convert_int(false, 0x0020_0000_2000_0001), 0x5a00_0001);
+    }
+
+    #[test]
+    fn nans_are_deterministic() {
+        let f = BINARY32;
+        let (inf, zero, one) = (0x7f80_0000, 0, 1f32.to_bits().into());
+        assert_eq!(f.mul(inf, zero), 0x7fc0_0000);
+        assert_eq!(f.sub(inf, inf), 0x7fc0_0000);
+        assert_eq!(f.sqrt(0xbf80_0000), 0x7fc0_0000);
+        assert_eq!(f.add(one, 0xff80_0001), 0xffc0_0001);
+        assert_eq!(f.div(0x7fa0_0000, 0x7f80_0001), 0x7fe0_0000);
+        assert_eq!(BINARY64.convert(f, 0x7fc0_0001), 0x7ff8_0000_2000_0000);
+    }
+}
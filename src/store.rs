@@ -0,0 +1,238 @@
+use crate::func::FuncRef;
+use crate::global::GlobalRef;
+use crate::host::Externals;
+use crate::imports::ImportsBuilder;
+use crate::memory::MemoryRef;
+use crate::module::{ExternVal, ModuleInstance, ModuleRef};
+use crate::table::TableRef;
+use crate::{Error, Module};
+use alloc::{collections::BTreeMap, string::String, vec::Vec};
+
+/// Handle of a module instance added to a [`Store`].
+///
+/// [`Store`]: struct.Store.html
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct InstanceHandle(u32);
+
+/// Handle of a function added to a [`Store`].
+///
+/// [`Store`]: struct.Store.html
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FuncHandle(u32);
+
+/// Handle of a linear memory added to a [`Store`].
+///
+/// [`Store`]: struct.Store.html
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MemoryHandle(u32);
+
+/// Handle of a table added to a [`Store`].
+///
+/// [`Store`]: struct.Store.html
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TableHandle(u32);
+
+/// Handle of a global variable added to a [`Store`].
+///
+/// [`Store`]: struct.Store.html
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct GlobalHandle(u32);
+
+/// `Copy` handles over the references of module instances and the entities shared
+/// between them.
+///
+/// A store keeps a reference to everything added to it, so that it stays alive for as
+/// long as the store does, and hands out a plain index-based handle for it, which can be
+/// stored anywhere without keeping the entity alive on its own. The entities themselves
+/// are still the reference counted [`FuncRef`], [`MemoryRef`] etc., so a store doesn't
+/// change how they are owned, nor the cost of using them.
+///
+/// Modules instantiated with [`instantiate`] import from the store: from entities
+/// [defined][`define`] by a module and field name and from the exports of instances
+/// [registered][`register`] under a module name. This makes linking many modules
+/// together a matter of instantiating them in dependency order.
+///
+/// Handles are only meaningful for the store that created them. Using a handle
+/// with another store either refers to a different entity or to none at all.
+///
+/// Using a store is optional, the references behind the handles can be used without
+/// one.
+///
+/// # Examples
+///
+/// ```rust
+/// extern crate wabt;
+/// extern crate wasmi;
+///
+/// use wasmi::{Module, NopExternals, RuntimeValue, Store};
+///
+/// fn main() {
+///     let lib = Module::from_buffer(
+///         wabt::wat2wasm(r#"(module (func (export "one") (result i32) (i32.const 1)))"#)
+///             .unwrap(),
+///     )
+///     .unwrap();
+///     let app = Module::from_buffer(
+///         wabt::wat2wasm(
+///             r#"
+///             (module
+///               (import "lib" "one" (func $one (result i32)))
+///               (func (export "two") (result i32) (i32.add (call $one) (call $one)))
+///             )
+///             "#,
+///         )
+///         .unwrap(),
+///     )
+///     .unwrap();
+///
+///     let mut store = Store::new();
+///     let lib = store.instantiate(&lib, &mut NopExternals).unwrap();
+///     store.register("lib", lib);
+///     let app = store.instantiate(&app, &mut NopExternals).unwrap();
+///
+///     let result = store
+///         .instance(app)
+///         .unwrap()
+///         .invoke_export("two", &[], &mut NopExternals)
+///         .unwrap();
+///     assert_eq!(result, Some(RuntimeValue::I32(2)));
+/// }
+/// ```
+///
+/// [`instantiate`]: #method.instantiate
+/// [`define`]: #method.define
+/// [`register`]: #method.register
+/// [`FuncRef`]: struct.FuncRef.html
+/// [`MemoryRef`]: struct.MemoryRef.html
+#[derive(Default)]
+pub struct Store {
+    instances: Vec<ModuleRef>,
+    funcs: Vec<FuncRef>,
+    memories: Vec<MemoryRef>,
+    tables: Vec<TableRef>,
+    globals: Vec<GlobalRef>,
+    registered: BTreeMap<String, InstanceHandle>,
+    defined: Vec<(String, String, ExternVal)>,
+}
+
+impl Store {
+    /// Create an empty `Store`.
+    pub fn new() -> Store {
+        Store::default()
+    }
+
+    /// Instantiate `module`, resolving its imports from this store, and run its
+    /// `start` function (if any) with `externals`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if an import can't be resolved, if instantiation fails or if
+    /// the `start` function traps. The store is left unchanged in that case.
+    pub fn instantiate<E: Externals>(
+        &mut self,
+        module: &Module,
+        externals: &mut E,
+    ) -> Result<InstanceHandle, Error> {
+        let instance = {
+            let mut imports = ImportsBuilder::new();
+            for (name, handle) in &self.registered {
+                imports.push_resolver(name.as_str(), &self.instances[handle.0 as usize]);
+            }
+            for (module_name, field_name, extern_val) in &self.defined {
+                imports.push_extern(
+                    module_name.as_str(),
+                    field_name.as_str(),
+                    extern_val.clone(),
+                );
+            }
+            ModuleInstance::new(module, &imports)?.run_start(externals)?
+        };
+        Ok(InstanceHandle(push(&mut self.instances, instance)))
+    }
+
+    /// Make the exports of `instance` importable under `name` by modules
+    /// instantiated afterwards.
+    ///
+    /// Registering another instance under the same `name` replaces the previous one
+    /// for subsequent instantiations.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no instance referred to by `instance` in this store.
+    pub fn register<N: Into<String>>(&mut self, name: N, instance: InstanceHandle) {
+        assert!(
+            self.instance(instance).is_some(),
+            "instance {:?} doesn't exist in this store",
+            instance
+        );
+        self.registered.insert(name.into(), instance);
+    }
+
+    /// Make `extern_val` importable by `module_name` and `field_name` by modules
+    /// instantiated afterwards.
+    ///
+    /// Defined entities take precedence over the exports of an instance registered
+    /// under the same module name.
+    pub fn define<M: Into<String>, F: Into<String>>(
+        &mut self,
+        module_name: M,
+        field_name: F,
+        extern_val: ExternVal,
+    ) {
+        self.defined
+            .push((module_name.into(), field_name.into(), extern_val));
+    }
+
+    /// Returns the instance referred to by `handle`, or `None` if there is none.
+    pub fn instance(&self, handle: InstanceHandle) -> Option<&ModuleRef> {
+        self.instances.get(handle.0 as usize)
+    }
+
+    /// Move `func` into the store.
+    pub fn add_func(&mut self, func: FuncRef) -> FuncHandle {
+        FuncHandle(push(&mut self.funcs, func))
+    }
+
+    /// Returns the function referred to by `handle`, or `None` if there is none.
+    pub fn func(&self, handle: FuncHandle) -> Option<&FuncRef> {
+        self.funcs.get(handle.0 as usize)
+    }
+
+    /// Move `memory` into the store.
+    pub fn add_memory(&mut self, memory: MemoryRef) -> MemoryHandle {
+        MemoryHandle(push(&mut self.memories, memory))
+    }
+
+    /// Returns the memory referred to by `handle`, or `None` if there is none.
+    pub fn memory(&self, handle: MemoryHandle) -> Option<&MemoryRef> {
+        self.memories.get(handle.0 as usize)
+    }
+
+    /// Move `table` into the store.
+    pub fn add_table(&mut self, table: TableRef) -> TableHandle {
+        TableHandle(push(&mut self.tables, table))
+    }
+
+    /// Returns the table referred to by `handle`, or `None` if there is none.
+    pub fn table(&self, handle: TableHandle) -> Option<&TableRef> {
+        self.tables.get(handle.0 as usize)
+    }
+
+    /// Move `global` into the store.
+    pub fn add_global(&mut self, global: GlobalRef) -> GlobalHandle {
+        GlobalHandle(push(&mut self.globals, global))
+    }
+
+    /// Returns the global referred to by `handle`, or `None` if there is none.
+    pub fn global(&self, handle: GlobalHandle) -> Option<&GlobalRef> {
+        self.globals.get(handle.0 as usize)
+    }
+}
+
+/// Push `item` into `arena`, returning its index.
+fn push<T>(arena: &mut Vec<T>, item: T) -> u32 {
+    let index = arena.len();
+    assert!(index < u32::MAX as usize, "too many entities in the store");
+    arena.push(item);
+    index as u32
+}
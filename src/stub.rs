@@ -0,0 +1,248 @@
+use crate::func::{FuncInstance, FuncRef};
+use crate::global::GlobalRef;
+use crate::host::{Externals, RuntimeArgs};
+use crate::imports::ModuleImportResolver;
+use crate::memory::MemoryRef;
+use crate::table::TableRef;
+use crate::types::{GlobalDescriptor, MemoryDescriptor, Signature, TableDescriptor};
+use crate::{Error, RuntimeValue, Trap, TrapKind};
+use alloc::{string::String, vec::Vec};
+use core::cell::RefCell;
+
+/// What a stub synthesized by a [`StubResolver`] does when called.
+///
+/// [`StubResolver`]: struct.StubResolver.html
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StubBehavior {
+    /// Trap with [`TrapKind::UnresolvedImport`].
+    ///
+    /// [`TrapKind::UnresolvedImport`]: enum.TrapKind.html#variant.UnresolvedImport
+    Trap,
+    /// Return the default value of the return type (zero), if any.
+    ReturnDefault,
+}
+
+struct Stub {
+    field_name: String,
+    signature: Signature,
+    called: bool,
+}
+
+/// A [`ModuleImportResolver`] that delegates to an inner one and stubs the functions
+/// it can't resolve, instead of failing the instantiation.
+///
+/// This lets a module whose host is only partially implemented be instantiated, to
+/// find out which of its imports are actually used. A stub behaves as configured by
+/// [`StubBehavior`].
+///
+/// Stubs are host functions like any other, so they are called through [`Externals`].
+/// They are allocated with indices counting down from `usize::MAX`, and the
+/// [`Externals`] returned by [`externals`] serve them, passing all other calls on
+/// to the wrapped ones.
+///
+/// Only functions are stubbed. Globals, memories and tables the inner resolver can't
+/// resolve still fail the instantiation.
+///
+/// # Examples
+///
+/// ```rust
+/// extern crate wabt;
+/// extern crate wasmi;
+///
+/// use wasmi::{
+///     Error, ImportsBuilder, Module, ModuleImportResolver, ModuleInstance, NopExternals,
+///     StubBehavior, StubResolver, TrapKind,
+/// };
+///
+/// struct Env;
+///
+/// impl ModuleImportResolver for Env {}
+///
+/// fn main() {
+///     let wasm = wabt::wat2wasm(
+///         r#"
+///         (module
+///           (import "env" "used" (func $used))
+///           (import "env" "unused" (func $unused))
+///           (func (export "run") (call $used))
+///         )
+///         "#,
+///     )
+///     .unwrap();
+///     let module = Module::from_buffer(&wasm).unwrap();
+///
+///     let resolver = StubResolver::new(Env, StubBehavior::Trap);
+///     let imports = ImportsBuilder::new().with_resolver("env", &resolver);
+///     let instance = ModuleInstance::new(&module, &imports)
+///         .unwrap()
+///         .assert_no_start();
+///     assert_eq!(resolver.stubbed_imports(), ["used", "unused"]);
+///
+///     let result = instance.invoke_export("run", &[], &mut resolver.externals(&mut NopExternals));
+///     match result {
+///         Err(Error::Trap(trap)) => match trap.kind() {
+///             TrapKind::UnresolvedImport { field_name } => assert_eq!(field_name, "used"),
+///             kind => panic!("unexpected trap {:?}", kind),
+///         },
+///         result => panic!("unexpected result {:?}", result),
+///     }
+///     assert_eq!(resolver.called_stubs(), ["used"]);
+/// }
+/// ```
+///
+/// [`ModuleImportResolver`]: trait.ModuleImportResolver.html
+/// [`StubBehavior`]: enum.StubBehavior.html
+/// [`Externals`]: trait.Externals.html
+/// [`externals`]: #method.externals
+pub struct StubResolver<R> {
+    inner: R,
+    behavior: StubBehavior,
+    stubs: RefCell<Vec<Stub>>,
+}
+
+impl<R> StubResolver<R> {
+    /// Create a `StubResolver` delegating to `inner`, with stubs behaving as `behavior`.
+    pub fn new(inner: R, behavior: StubBehavior) -> StubResolver<R> {
+        StubResolver {
+            inner,
+            behavior,
+            stubs: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Wrap `externals` so that calls of the stubs are served and all other calls are
+    /// passed on to `externals`.
+    pub fn externals<'a, E: Externals>(&'a self, externals: &'a mut E) -> StubExternals<'a, R, E> {
+        StubExternals {
+            resolver: self,
+            externals,
+        }
+    }
+
+    /// Returns the field names of the functions that were stubbed, in resolution order.
+    pub fn stubbed_imports(&self) -> Vec<String> {
+        self.stubs
+            .borrow()
+            .iter()
+            .map(|stub| stub.field_name.clone())
+            .collect()
+    }
+
+    /// Returns the field names of the stubbed functions that were called at least once
+    /// through [`StubExternals`] of this resolver, in resolution order.
+    ///
+    /// [`StubExternals`]: struct.StubExternals.html
+    pub fn called_stubs(&self) -> Vec<String> {
+        self.stubs
+            .borrow()
+            .iter()
+            .filter(|stub| stub.called)
+            .map(|stub| stub.field_name.clone())
+            .collect()
+    }
+
+    /// Returns the position of the stub with host function `index` in `stubs`.
+    fn stub_position(&self, index: usize) -> Option<usize> {
+        let position = usize::MAX - index;
+        if position < self.stubs.borrow().len() {
+            Some(position)
+        } else {
+            None
+        }
+    }
+}
+
+impl<R: ModuleImportResolver> ModuleImportResolver for StubResolver<R> {
+    fn resolve_func(&self, field_name: &str, signature: &Signature) -> Result<FuncRef, Error> {
+        if let Ok(func) = self.inner.resolve_func(field_name, signature) {
+            return Ok(func);
+        }
+        let mut stubs = self.stubs.borrow_mut();
+        let index = usize::MAX - stubs.len();
+        stubs.push(Stub {
+            field_name: field_name.into(),
+            signature: signature.clone(),
+            called: false,
+        });
+        Ok(FuncInstance::alloc_host(signature.clone(), index))
+    }
+
+    fn resolve_global(
+        &self,
+        field_name: &str,
+        global_type: &GlobalDescriptor,
+    ) -> Result<GlobalRef, Error> {
+        self.inner.resolve_global(field_name, global_type)
+    }
+
+    fn resolve_memory(
+        &self,
+        field_name: &str,
+        memory_type: &MemoryDescriptor,
+    ) -> Result<MemoryRef, Error> {
+        self.inner.resolve_memory(field_name, memory_type)
+    }
+
+    fn resolve_table(
+        &self,
+        field_name: &str,
+        table_type: &TableDescriptor,
+    ) -> Result<TableRef, Error> {
+        self.inner.resolve_table(field_name, table_type)
+    }
+}
+
+/// [`Externals`] that serve the stubs of a [`StubResolver`] and pass all other calls
+/// on to the wrapped ones.
+///
+/// Created with [`StubResolver::externals`].
+///
+/// [`Externals`]: trait.Externals.html
+/// [`StubResolver`]: struct.StubResolver.html
+/// [`StubResolver::externals`]: struct.StubResolver.html#method.externals
+pub struct StubExternals<'a, R, E> {
+    resolver: &'a StubResolver<R>,
+    externals: &'a mut E,
+}
+
+impl<'a, R, E> StubExternals<'a, R, E> {
+    fn call_stub(&self, position: usize) -> Result<Option<RuntimeValue>, Trap> {
+        let mut stubs = self.resolver.stubs.borrow_mut();
+        let stub = &mut stubs[position];
+        stub.called = true;
+        match self.resolver.behavior {
+            StubBehavior::Trap => Err(TrapKind::UnresolvedImport {
+                field_name: stub.field_name.clone(),
+            }
+            .into()),
+            StubBehavior::ReturnDefault => {
+                Ok(stub.signature.return_type().map(RuntimeValue::default))
+            }
+        }
+    }
+}
+
+impl<'a, R, E: Externals> Externals for StubExternals<'a, R, E> {
+    fn invoke_index(
+        &mut self,
+        index: usize,
+        args: RuntimeArgs,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        match self.resolver.stub_position(index) {
+            Some(position) => self.call_stub(position),
+            None => self.externals.invoke_index(index, args),
+        }
+    }
+
+    fn invoke_index_with_memory(
+        &mut self,
+        index: usize,
+        args: RuntimeArgs,
+        memory: Option<&MemoryRef>,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        match self.resolver.stub_position(index) {
+            Some(position) => self.call_stub(position),
+            None => self.externals.invoke_index_with_memory(index, args, memory),
+        }
+    }
+}
@@ -33,6 +33,8 @@ impl ::core::ops::Deref for TableRef {
 /// Growth can be limited by an optional maximum size.
 ///
 /// In future, a table might be extended to be able to hold not only functions but different types.
+/// Until then, tables of other element types, such as `externref`, are rejected when the module
+/// is loaded, so `call_indirect` always finds either a function or an uninitialized element.
 ///
 /// [`grow`]: #method.grow
 ///
@@ -151,4 +153,26 @@ impl TableInstance {
         *table_elem = value;
         Ok(())
     }
+
+    /// Set the table elements starting at `offset` to `funcs`.
+    ///
+    /// Either all of the elements are set or, if `funcs` doesn't fit in the table at
+    /// `offset`, none of them.
+    pub fn init_from(&self, offset: u32, funcs: &[Option<FuncRef>]) -> Result<(), Error> {
+        let mut buffer = self.buffer.borrow_mut();
+        let buffer_len = buffer.len();
+        let elems = (offset as usize)
+            .checked_add(funcs.len())
+            .and_then(|end| buffer.get_mut(offset as usize..end))
+            .ok_or_else(|| {
+                Error::Table(format!(
+                    "trying to update {} table items at index {} when there are only {} items",
+                    funcs.len(),
+                    offset,
+                    buffer_len
+                ))
+            })?;
+        elems.clone_from_slice(funcs);
+        Ok(())
+    }
 }
@@ -5,9 +5,10 @@ use super::parse_wat;
 use crate::memory_units::Pages;
 use crate::types::ValueType;
 use crate::{
-    Error, Externals, FuncInstance, FuncRef, HostError, ImportsBuilder, MemoryDescriptor,
-    MemoryInstance, MemoryRef, ModuleImportResolver, ModuleInstance, ModuleRef, ResumableError,
-    RuntimeArgs, RuntimeValue, Signature, TableDescriptor, TableInstance, TableRef, Trap, TrapKind,
+    Error, ExternVal, Externals, FuncInstance, FuncRef, GlobalInstance, HostError, ImportManifest,
+    ImportsBuilder, ManifestResolver, MemoryDescriptor, MemoryInstance, MemoryRef,
+    ModuleImportResolver, ModuleInstance, ModuleRef, NopExternals, ResumableError, RuntimeArgs,
+    RuntimeValue, Signature, TableDescriptor, TableInstance, TableRef, Trap, TrapCode, TrapKind,
 };
 use alloc::boxed::Box;
 use std::println;
@@ -89,6 +90,26 @@ const RECURSE_FUNC_INDEX: usize = 4;
 /// This function is the same as sub(a, b), but it will send a Host trap which pauses the interpreter execution.
 const TRAP_SUB_FUNC_INDEX: usize = 5;
 
+/// exit(exit_code: i32) -> !
+///
+/// This function requests termination of the execution with the given exit code.
+const EXIT_FUNC_INDEX: usize = 6;
+
+/// checked_add(a: i32, b: i32) -> i32
+///
+/// This function adds two integers, trapping if the addition overflows.
+const CHECKED_ADD_FUNC_INDEX: usize = 7;
+
+/// coded_err(code: i32) -> !
+///
+/// This function traps upon a call, with `code` attached to a HostErrorWithCode.
+const CODED_ERR_FUNC_INDEX: usize = 8;
+
+/// wrong_result() -> i32
+///
+/// This function is buggy and returns an i64 instead of the declared i32.
+const WRONG_RESULT_FUNC_INDEX: usize = 9;
+
 impl Externals for TestHost {
     fn invoke_index(
         &mut self,
@@ -165,6 +186,26 @@ impl Externals for TestHost {
                 self.trap_sub_result = Some(result);
                 Err(TrapKind::Host(Box::new(HostErrorWithCode { error_code: 301 })).into())
             }
+            EXIT_FUNC_INDEX => {
+                let exit_code: i32 = args.nth(0);
+                Err(Trap::exit(exit_code))
+            }
+            CHECKED_ADD_FUNC_INDEX => {
+                let a: i32 = args.nth(0);
+                let b: i32 = args.nth(1);
+
+                let result = Trap::check_overflow(a.checked_add(b))?;
+
+                Ok(Some(result.into()))
+            }
+            CODED_ERR_FUNC_INDEX => {
+                let code: u32 = args.nth(0);
+                Err(Trap::host_with_code(
+                    code,
+                    HostErrorWithCode { error_code: code },
+                ))
+            }
+            WRONG_RESULT_FUNC_INDEX => Ok(Some(RuntimeValue::I64(1))),
             _ => panic!("env doesn't provide function at index {}", index),
         }
     }
@@ -187,6 +228,10 @@ impl TestHost {
             INC_MEM_FUNC_INDEX => (&[ValueType::I32], None),
             GET_MEM_FUNC_INDEX => (&[ValueType::I32], Some(ValueType::I32)),
             TRAP_SUB_FUNC_INDEX => (&[ValueType::I32, ValueType::I32], Some(ValueType::I32)),
+            EXIT_FUNC_INDEX => (&[ValueType::I32], None),
+            CHECKED_ADD_FUNC_INDEX => (&[ValueType::I32, ValueType::I32], Some(ValueType::I32)),
+            CODED_ERR_FUNC_INDEX => (&[ValueType::I32], None),
+            WRONG_RESULT_FUNC_INDEX => (&[], Some(ValueType::I32)),
             _ => return false,
         };
 
@@ -203,6 +248,10 @@ impl ModuleImportResolver for TestHost {
             "get_mem" => GET_MEM_FUNC_INDEX,
             "recurse" => RECURSE_FUNC_INDEX,
             "trap_sub" => TRAP_SUB_FUNC_INDEX,
+            "exit" => EXIT_FUNC_INDEX,
+            "checked_add" => CHECKED_ADD_FUNC_INDEX,
+            "coded_err" => CODED_ERR_FUNC_INDEX,
+            "wrong_result" => WRONG_RESULT_FUNC_INDEX,
             _ => {
                 return Err(Error::Instantiation(format!(
                     "Export {} not found",
@@ -307,6 +356,80 @@ fn resume_call_host_func() {
     );
 }
 
+#[test]
+fn resume_yielded_execution() {
+    let module = parse_wat(
+        r#"
+(module
+	(import "env" "trap_sub" (func $trap_sub (param i32 i32) (result i32)))
+
+	(func $countdown (param i32) (result i32)
+		(loop $continue
+			(br_if $continue (tee_local 0 (i32.sub (get_local 0) (i32.const 1))))
+		)
+		(get_local 0)
+	)
+
+	(func (export "test") (param i32) (result i32)
+		(call $trap_sub
+			(call $countdown (get_local 0))
+			(i32.const 7)
+		)
+	)
+)
+"#,
+    );
+
+    let mut env = TestHost::new();
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::new().with_resolver("env", &env))
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+    let export = instance.export_by_name("test").unwrap();
+    let func = export.as_func().unwrap();
+
+    // The host trap stops a metered execution right after the call of `trap_sub`,
+    // which is followed by the final `return` of `test`.
+    let mut fuel = u64::MAX;
+    let result =
+        FuncInstance::invoke_with_fuel(func, &[RuntimeValue::I32(10)], &mut env, &mut fuel);
+    assert!(result.is_err());
+    let before_host_call = u64::MAX - fuel;
+
+    for &interval in &[1, 2, 7, before_host_call, before_host_call + 1] {
+        let mut invocation = FuncInstance::invoke_resumable(func, vec![RuntimeValue::I32(10)])
+            .expect("Failed to create invocation");
+        invocation.set_yield_interval(Some(interval));
+
+        let mut yields = 0;
+        let mut host_traps = 0;
+        let mut outcome = invocation.start_execution(&mut env);
+        let result = loop {
+            let return_val = match outcome {
+                Ok(result) => break result,
+                Err(ResumableError::Yielded) => {
+                    yields += 1;
+                    assert!(invocation.is_resumable());
+                    assert_eq!(invocation.resumable_value_type(), None);
+                    None
+                }
+                Err(ResumableError::Trap(_)) => {
+                    host_traps += 1;
+                    env.trap_sub_result.take()
+                }
+                Err(err) => panic!("Unexpected error {:?}", err),
+            };
+            outcome = invocation.resume_execution(return_val, &mut env);
+        };
+
+        assert_eq!(result, Some(RuntimeValue::I32(-7)));
+        assert_eq!(host_traps, 1);
+        // Execution yields before an instruction once `interval` instructions were
+        // executed since the last yield, so never before the first or after the
+        // last one.
+        assert_eq!(yields, before_host_call / interval, "interval {}", interval);
+    }
+}
+
 #[test]
 fn resume_call_host_func_type_mismatch() {
     fn resume_with_val(val: Option<RuntimeValue>) {
@@ -398,6 +521,318 @@ fn host_err() {
     assert_eq!(error_with_code.error_code, 228);
 }
 
+#[test]
+fn host_err_with_code() {
+    let module = parse_wat(
+        r#"
+(module
+	(import "env" "coded_err" (func $coded_err (param i32)))
+
+	(func (export "test")
+		(call $coded_err
+			(i32.const 42)
+		)
+	)
+)
+"#,
+    );
+
+    let mut env = TestHost::new();
+
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::new().with_resolver("env", &env))
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+
+    let trap = match instance.invoke_export("test", &[], &mut env) {
+        Err(Error::Trap(trap)) => trap,
+        result => panic!("Expected a trap, got {:?}", result),
+    };
+    assert_eq!(trap.host_code(), Some(42));
+    assert_eq!(trap.code(), TrapCode::Host);
+    assert!(trap.kind().is_host());
+    assert_eq!(
+        trap.as_host_error()
+            .expect("Expected host error")
+            .downcast_ref::<HostErrorWithCode>(),
+        Some(&HostErrorWithCode { error_code: 42 })
+    );
+
+    // Traps without a code don't have one.
+    assert_eq!(Trap::exit(0).host_code(), None);
+    assert_eq!(
+        Trap::from(HostErrorWithCode { error_code: 42 }).host_code(),
+        None
+    );
+}
+
+#[test]
+fn host_result_type_checked() {
+    let module = parse_wat(
+        r#"
+(module
+	(import "env" "wrong_result" (func $wrong_result (result i32)))
+
+	(func (export "test") (result i32)
+		(call $wrong_result)
+	)
+)
+"#,
+    );
+
+    let mut env = TestHost::new();
+
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::new().with_resolver("env", &env))
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+
+    fn assert_wrong_result(trap: &Trap) {
+        match trap.kind() {
+            TrapKind::UnexpectedHostResult {
+                expected: Some(ValueType::I32),
+                actual: Some(ValueType::I64),
+            } => {}
+            kind => panic!("Expected UnexpectedHostResult, got {:?}", kind),
+        }
+        assert_eq!(trap.code(), TrapCode::UnexpectedHostResult);
+    }
+
+    // Called from wasm code.
+    match instance.invoke_export("test", &[], &mut env) {
+        Err(Error::Trap(trap)) => assert_wrong_result(&trap),
+        result => panic!("Expected a trap, got {:?}", result),
+    }
+
+    // Invoked directly.
+    let func = env
+        .resolve_func(
+            "wrong_result",
+            &Signature::new(&[][..], Some(ValueType::I32)),
+        )
+        .unwrap();
+    assert_wrong_result(&FuncInstance::invoke(&func, &[], &mut env).unwrap_err());
+    let mut invocation = FuncInstance::invoke_resumable(&func, &[][..]).unwrap();
+    match invocation.start_execution(&mut env) {
+        Err(ResumableError::Trap(trap)) => assert_wrong_result(&trap),
+        result => panic!("Expected a trap, got {:?}", result),
+    }
+}
+
+#[test]
+fn invoke_by_index() {
+    let module = parse_wat(
+        r#"
+(module
+	(import "env" "sub" (func $sub (param i32 i32) (result i32)))
+
+	(func $neg (param i32) (result i32)
+		(call $sub
+			(i32.const 0)
+			(get_local 0)
+		)
+	)
+)
+"#,
+    );
+
+    let mut env = TestHost::new();
+
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::new().with_resolver("env", &env))
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+
+    // Imported functions come first in the index space.
+    assert_eq!(
+        instance
+            .invoke_index(0, &[RuntimeValue::I32(7), RuntimeValue::I32(2)], &mut env)
+            .unwrap(),
+        Some(RuntimeValue::I32(5))
+    );
+    // The function isn't exported.
+    assert_eq!(
+        instance
+            .invoke_index(1, &[RuntimeValue::I32(3)], &mut env)
+            .unwrap(),
+        Some(RuntimeValue::I32(-3))
+    );
+
+    match instance.invoke_index(1, &[RuntimeValue::I64(3)], &mut env) {
+        Err(Error::Trap(trap)) => assert_eq!(trap.code(), TrapCode::UnexpectedSignature),
+        result => panic!("Expected a trap, got {:?}", result),
+    }
+    match instance.invoke_index(2, &[], &mut env) {
+        Err(Error::Function(_)) => {}
+        result => panic!("Expected an error, got {:?}", result),
+    }
+}
+
+#[test]
+fn manifest_resolver() {
+    let sub_signature = Signature::new(&[ValueType::I32, ValueType::I32][..], Some(ValueType::I32));
+    let manifest = ImportManifest::new()
+        .with_import("env", "sub", "math.sub")
+        .with_import("env", "wrong", "math.sub")
+        .with_import("env", "memory", "math.sub")
+        .with_import("env", "unregistered", "math.mul");
+    let resolver = ManifestResolver::new(manifest).with_capability(
+        "math.sub",
+        ExternVal::Func(FuncInstance::alloc_host(sub_signature, SUB_FUNC_INDEX)),
+    );
+
+    let module = parse_wat(
+        r#"
+(module
+	(import "env" "sub" (func $sub (param i32 i32) (result i32)))
+	(func (export "test") (result i32)
+		(call $sub (i32.const 5) (i32.const 7))
+	)
+)
+"#,
+    );
+    let instance = ModuleInstance::new(&module, &resolver)
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+    assert_eq!(
+        instance
+            .invoke_export("test", &[], &mut TestHost::new())
+            .unwrap(),
+        Some(RuntimeValue::I32(-2))
+    );
+
+    let instantiation_error = |wat: &str| match ModuleInstance::new(&parse_wat(wat), &resolver) {
+        Err(Error::Instantiation(message)) => message,
+        Err(err) => panic!("Expected an instantiation error, got {:?}", err),
+        Ok(_) => panic!("Expected an instantiation error"),
+    };
+    assert_eq!(
+        instantiation_error(r#"(module (import "env" "err" (func (param i32))))"#),
+        "Import env.err is not allowed by the manifest"
+    );
+    assert_eq!(
+        instantiation_error(r#"(module (import "env" "unregistered" (func)))"#),
+        "Capability math.mul of import env.unregistered is not registered"
+    );
+    assert!(
+        instantiation_error(r#"(module (import "env" "wrong" (func)))"#)
+            .starts_with("Capability math.sub has signature")
+    );
+    assert_eq!(
+        instantiation_error(r#"(module (import "env" "memory" (memory 1)))"#),
+        "Capability math.sub is not a memory"
+    );
+}
+
+#[test]
+fn host_exit() {
+    let module = parse_wat(
+        r#"
+(module
+	(import "env" "exit" (func $exit (param i32)))
+
+	(func (export "test")
+		(call $exit
+			(i32.const 3)
+		)
+		unreachable
+	)
+)
+"#,
+    );
+
+    let mut env = TestHost::new();
+
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::new().with_resolver("env", &env))
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+
+    match instance.invoke_export("test", &[], &mut env) {
+        Err(Error::Trap(trap)) => assert_eq!(trap.exit_code(), Some(3)),
+        other => panic!("expected exit trap, got {:?}", other),
+    }
+
+    let export = instance.export_by_name("test").unwrap();
+    let func_instance = export.as_func().unwrap();
+
+    let mut invocation = FuncInstance::invoke_resumable(func_instance, &[][..]).unwrap();
+    match invocation.start_execution(&mut env) {
+        Err(ResumableError::Trap(trap)) => assert_eq!(trap.exit_code(), Some(3)),
+        other => panic!("expected exit trap, got {:?}", other),
+    }
+    assert!(!invocation.is_resumable());
+}
+
+#[test]
+fn host_integer_overflow() {
+    let module = parse_wat(
+        r#"
+(module
+	(import "env" "checked_add" (func $checked_add (param i32 i32) (result i32)))
+
+	(func (export "test") (param i32 i32) (result i32)
+		(call $checked_add
+			(get_local 0)
+			(get_local 1)
+		)
+	)
+)
+"#,
+    );
+
+    let mut env = TestHost::new();
+
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::new().with_resolver("env", &env))
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+
+    assert_eq!(
+        instance
+            .invoke_export("test", &[1.into(), 2.into()], &mut env)
+            .expect("Failed to invoke 'test' function"),
+        Some(RuntimeValue::I32(3))
+    );
+
+    match instance.invoke_export("test", &[i32::MAX.into(), 1.into()], &mut env) {
+        Err(Error::Trap(trap)) => assert!(matches!(trap.kind(), TrapKind::IntegerOverflow)),
+        other => panic!("expected overflow trap, got {:?}", other),
+    }
+}
+
+#[test]
+fn func_ref_introspection() {
+    let module = parse_wat(
+        r#"
+(module
+	(import "env" "sub" (func $sub (param i32 i32) (result i32)))
+	(export "sub" (func $sub))
+	(func (export "test") (result i32)
+		(call $sub (i32.const 3) (i32.const 1))
+	)
+)
+"#,
+    );
+
+    let env = TestHost::new();
+
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::new().with_resolver("env", &env))
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+
+    let sub = instance.export_by_name("sub").unwrap();
+    let sub = sub.as_func().unwrap();
+    assert!(sub.is_host());
+    assert_eq!(
+        sub.signature(),
+        &Signature::new(&[ValueType::I32, ValueType::I32][..], Some(ValueType::I32))
+    );
+
+    let test = instance.export_by_name("test").unwrap();
+    let test = test.as_func().unwrap();
+    assert!(!test.is_host());
+    assert_eq!(
+        test.signature(),
+        &Signature::new(&[][..], Some(ValueType::I32))
+    );
+}
+
 #[test]
 fn modify_mem_with_host_funcs() {
     let module = parse_wat(
@@ -865,3 +1300,729 @@ fn dynamically_add_host_func() {
         Some(RuntimeValue::I32(2))
     );
 }
+
+#[test]
+fn imports_registered_by_name() {
+    let memory = MemoryInstance::alloc(Pages(1), None).unwrap();
+    memory.set_value(0, 40i32).unwrap();
+    let global = GlobalInstance::alloc(RuntimeValue::I32(2), false);
+
+    let module = parse_wat(
+        r#"
+(module
+	(import "env" "memory" (memory 1))
+	(import "env" "two" (global $two i32))
+	(func (export "test") (result i32)
+		(i32.add
+			(i32.load (i32.const 0))
+			(get_global $two)
+		)
+	)
+)
+"#,
+    );
+
+    let imports = ImportsBuilder::new()
+        .with_extern("env", "memory", ExternVal::Memory(memory))
+        .with_extern("env", "two", ExternVal::Global(global.clone()));
+    let instance = ModuleInstance::new(&module, &imports)
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+    assert_eq!(
+        instance
+            .invoke_export("test", &[], &mut NopExternals)
+            .expect("Failed to invoke 'test' function"),
+        Some(RuntimeValue::I32(42))
+    );
+
+    // The kind of a registered entity is checked.
+    let imports = ImportsBuilder::new()
+        .with_extern("env", "memory", ExternVal::Global(global.clone()))
+        .with_extern("env", "two", ExternVal::Global(global));
+    match ModuleInstance::new(&module, &imports) {
+        Err(Error::Instantiation(msg)) => assert_eq!(msg, "Export memory is not a memory"),
+        _ => panic!("Instantiation should fail"),
+    }
+}
+
+#[test]
+fn host_func_gets_caller_memory() {
+    const STORE_FUNC_INDEX: usize = 0;
+
+    /// Stores a byte into the caller's memory, or returns `0` if there is none.
+    struct HostExternals;
+
+    impl Externals for HostExternals {
+        fn invoke_index(
+            &mut self,
+            index: usize,
+            args: RuntimeArgs,
+        ) -> Result<Option<RuntimeValue>, Trap> {
+            self.invoke_index_with_memory(index, args, None)
+        }
+
+        fn invoke_index_with_memory(
+            &mut self,
+            index: usize,
+            args: RuntimeArgs,
+            memory: Option<&MemoryRef>,
+        ) -> Result<Option<RuntimeValue>, Trap> {
+            assert_eq!(index, STORE_FUNC_INDEX);
+            let memory = match memory {
+                Some(memory) => memory,
+                None => return Ok(Some(RuntimeValue::I32(0))),
+            };
+            let address: u32 = args.nth_checked(0)?;
+            let value: u32 = args.nth_checked(1)?;
+            memory
+                .set(address, &[value as u8])
+                .map_err(|_| TrapKind::MemoryAccessOutOfBounds {
+                    address: address.into(),
+                    len: 1,
+                })?;
+            Ok(Some(RuntimeValue::I32(1)))
+        }
+    }
+
+    struct Resolver;
+
+    impl ModuleImportResolver for Resolver {
+        fn resolve_func(&self, _field_name: &str, signature: &Signature) -> Result<FuncRef, Error> {
+            Ok(FuncInstance::alloc_host(
+                signature.clone(),
+                STORE_FUNC_INDEX,
+            ))
+        }
+    }
+
+    let instantiate = |wat: &str| {
+        ModuleInstance::new(
+            &parse_wat(wat),
+            &ImportsBuilder::new().with_resolver("env", &Resolver),
+        )
+        .expect("Failed to instantiate module")
+        .assert_no_start()
+    };
+    let with_memory = instantiate(
+        r#"
+(module
+  (import "env" "store" (func $store (param i32 i32) (result i32)))
+  (memory (export "mem") 1)
+  (func (export "test") (result i32)
+    (call $store (i32.const 10) (i32.const 42))
+  )
+)
+"#,
+    );
+    let without_memory = instantiate(
+        r#"
+(module
+  (import "env" "store" (func $store (param i32 i32) (result i32)))
+  (func (export "test") (result i32)
+    (call $store (i32.const 10) (i32.const 42))
+  )
+)
+"#,
+    );
+
+    assert_eq!(
+        with_memory
+            .invoke_export("test", &[], &mut HostExternals)
+            .expect("Failed to invoke 'test' function"),
+        Some(RuntimeValue::I32(1))
+    );
+    let memory = with_memory
+        .export_by_name("mem")
+        .and_then(|export| export.as_memory().cloned())
+        .expect("Module should export memory");
+    assert_eq!(memory.get(10, 1).unwrap(), [42]);
+
+    assert_eq!(
+        without_memory
+            .invoke_export("test", &[], &mut HostExternals)
+            .expect("Failed to invoke 'test' function"),
+        Some(RuntimeValue::I32(0))
+    );
+}
+
+#[test]
+fn trap_host_error_accessors() {
+    let trap = Trap::from(HostErrorWithCode { error_code: 7 });
+    assert_eq!(
+        trap.as_host_error()
+            .and_then(|err| err.downcast_ref::<HostErrorWithCode>())
+            .map(|err| err.error_code),
+        Some(7)
+    );
+    let host_err = trap
+        .try_into_host_error()
+        .expect("Trap should contain a host error");
+    assert_eq!(
+        host_err.downcast_ref::<HostErrorWithCode>(),
+        Some(&HostErrorWithCode { error_code: 7 })
+    );
+
+    let trap = Trap::new(TrapKind::Unreachable { func_index: None });
+    assert!(trap.as_host_error().is_none());
+    let trap = trap
+        .try_into_host_error()
+        .expect_err("Trap shouldn't contain a host error");
+    assert!(matches!(
+        TrapKind::from(trap),
+        TrapKind::Unreachable { func_index: None }
+    ));
+}
+
+#[test]
+fn unreachable_is_classified() {
+    let module = parse_wat(
+        r#"
+(module
+	(import "env" "exit" (func $exit (param i32)))
+	(memory 1)
+
+	(func $abort
+		unreachable
+	)
+
+	(func (export "abort")
+		call $abort
+	)
+
+	(func (export "out_of_bounds")
+		(drop (i32.load (i32.const 65536)))
+	)
+)
+"#,
+    );
+
+    let mut env = TestHost::new();
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::new().with_resolver("env", &env))
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+    let trap = |name, env: &mut TestHost| match instance.invoke_export(name, &[], env) {
+        Err(Error::Trap(trap)) => trap,
+        result => panic!("Expected trap, got {:?}", result),
+    };
+
+    // Imported functions are counted.
+    assert_eq!(trap("abort", &mut env).unreachable_func_index(), Some(1));
+    assert_eq!(
+        trap("out_of_bounds", &mut env).unreachable_func_index(),
+        None
+    );
+
+    // Raised by the host, not by executing `unreachable`.
+    let trap = NopExternals
+        .invoke_index(0, [][..].into())
+        .expect_err("NopExternals should trap");
+    assert!(matches!(
+        trap.kind(),
+        TrapKind::Unreachable { func_index: None }
+    ));
+    assert_eq!(trap.unreachable_func_index(), None);
+}
+
+#[test]
+fn tracing_resolver_logs_host_calls() {
+    use crate::{HostCall, HostCallInterceptor, TracingResolver};
+    use alloc::string::String;
+    use alloc::vec::Vec;
+
+    struct Log(Vec<String>);
+
+    impl HostCallInterceptor for Log {
+        fn before_call(&mut self, call: &HostCall) {
+            self.0
+                .push(format!("-> {} {:?} {:?}", call.index, call.name, call.args));
+        }
+
+        fn after_call(&mut self, call: &HostCall, result: &Result<Option<RuntimeValue>, Trap>) {
+            let result = match result {
+                Ok(value) => format!("{:?}", value),
+                Err(trap) => format!("{}", trap),
+            };
+            self.0.push(format!("<- {} {}", call.index, result));
+        }
+    }
+
+    let module = parse_wat(
+        r#"
+(module
+	(import "env" "sub" (func $sub (param i32 i32) (result i32)))
+	(import "env" "err" (func $err (param i32)))
+
+	(func (export "test") (result i32)
+		(call $sub (i32.const 5) (i32.const 7))
+		(call $sub (i32.const 1) (i32.const 1))
+		i32.add
+	)
+
+	(func (export "trap")
+		(call $err (i32.const 228))
+	)
+)
+"#,
+    );
+
+    let mut env = TestHost::new();
+    let resolver = TracingResolver::new(TestHost::new());
+    let instance = ModuleInstance::new(
+        &module,
+        &ImportsBuilder::new().with_resolver("env", &resolver),
+    )
+    .expect("Failed to instantiate module")
+    .assert_no_start();
+
+    let mut log = Log(Vec::new());
+    assert_eq!(
+        instance
+            .invoke_export("test", &[], &mut resolver.intercept(&mut env, &mut log))
+            .expect("Failed to invoke 'test' function"),
+        Some(RuntimeValue::I32(-2))
+    );
+    instance
+        .invoke_export("trap", &[], &mut resolver.intercept(&mut env, &mut log))
+        .expect_err("`trap` expected to return error");
+    // Calls with the plain externals aren't traced.
+    instance
+        .invoke_export("test", &[], &mut env)
+        .expect("Failed to invoke 'test' function");
+
+    assert_eq!(
+        log.0,
+        [
+            "-> 0 Some(\"sub\") [I32(5), I32(7)]",
+            "<- 0 Some(I32(-2))",
+            "-> 0 Some(\"sub\") [I32(1), I32(1)]",
+            "<- 0 Some(I32(0))",
+            "-> 1 Some(\"err\") [I32(228)]",
+            "<- 1 Trap: Host(HostErrorWithCode { error_code: 228 })",
+        ]
+    );
+    assert_eq!(resolver.call_count(SUB_FUNC_INDEX), 2);
+    assert_eq!(resolver.call_count(ERR_FUNC_INDEX), 1);
+    assert_eq!(resolver.call_count(INC_MEM_FUNC_INDEX), 0);
+
+    // Host functions that weren't resolved through the tracer have no name.
+    let mut log = Log(Vec::new());
+    resolver
+        .intercept(&mut env, &mut log)
+        .invoke_index(EXIT_FUNC_INDEX, [RuntimeValue::I32(3)][..].into())
+        .expect_err("`exit` expected to trap");
+    assert_eq!(log.0[0], "-> 6 None [I32(3)]");
+}
+
+#[test]
+fn stub_resolver_fills_missing_imports() {
+    use crate::{StubBehavior, StubResolver};
+
+    let module = parse_wat(
+        r#"
+(module
+	(import "env" "sub" (func $sub (param i32 i32) (result i32)))
+	(import "env" "get_i64" (func $get_i64 (result i64)))
+	(import "env" "log" (func $log (param i32)))
+
+	(func (export "test") (result i64)
+		(call $log (call $sub (i32.const 5) (i32.const 7)))
+		(call $get_i64)
+	)
+)
+"#,
+    );
+
+    let mut env = TestHost::new();
+    let resolver = StubResolver::new(TestHost::new(), StubBehavior::ReturnDefault);
+    let instance = ModuleInstance::new(
+        &module,
+        &ImportsBuilder::new().with_resolver("env", &resolver),
+    )
+    .expect("Failed to instantiate module")
+    .assert_no_start();
+    assert_eq!(resolver.stubbed_imports(), ["get_i64", "log"]);
+    assert!(resolver.called_stubs().is_empty());
+
+    // `sub` is still served by the host, the stubs return zero or nothing.
+    assert_eq!(
+        instance
+            .invoke_export("test", &[], &mut resolver.externals(&mut env))
+            .expect("Failed to invoke 'test' function"),
+        Some(RuntimeValue::I64(0))
+    );
+    assert_eq!(resolver.called_stubs(), ["get_i64", "log"]);
+
+    let resolver = StubResolver::new(TestHost::new(), StubBehavior::Trap);
+    let instance = ModuleInstance::new(
+        &module,
+        &ImportsBuilder::new().with_resolver("env", &resolver),
+    )
+    .expect("Failed to instantiate module")
+    .assert_no_start();
+    let err = instance
+        .invoke_export("test", &[], &mut resolver.externals(&mut env))
+        .expect_err("Stub expected to trap");
+    match err {
+        Error::Trap(trap) => assert!(matches!(
+            trap.kind(),
+            TrapKind::UnresolvedImport { field_name } if field_name == "log"
+        )),
+        err => panic!("Expected a trap, got {:?}", err),
+    }
+    assert_eq!(resolver.called_stubs(), ["log"]);
+}
+
+#[test]
+fn call_stats() {
+    use crate::CallStats;
+
+    let module = parse_wat(
+        r#"
+(module
+	(import "env" "sub" (func $sub (param i32 i32) (result i32)))
+	(import "env" "err" (func $err (param i32)))
+
+	(func $dec (param i32) (result i32)
+		(call $sub (get_local 0) (i32.const 1))
+	)
+
+	(func $countdown (param i32)
+		(loop $continue
+			(br_if $continue (call $dec (get_local 0)) (tee_local 0))
+		)
+	)
+
+	(func (export "test") (param i32)
+		(call $countdown (get_local 0))
+	)
+
+	(func (export "fail")
+		(call $countdown (i32.const 2))
+		(call $err (i32.const 1))
+	)
+)
+"#,
+    );
+
+    let mut env = TestHost::new();
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::new().with_resolver("env", &env))
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+
+    let (result, stats) =
+        instance.invoke_export_with_stats("test", &[RuntimeValue::I32(3)], &mut env);
+    assert_eq!(result.expect("Failed to invoke 'test' function"), None);
+    assert_eq!(stats.host_calls, 3);
+    assert_eq!(stats.max_call_depth, 3);
+
+    // Instructions are counted in the same way fuel is consumed.
+    let test = instance.export_by_name("test").unwrap();
+    let mut fuel = 1000;
+    FuncInstance::invoke_with_fuel(
+        test.as_func().unwrap(),
+        &[RuntimeValue::I32(3)],
+        &mut env,
+        &mut fuel,
+    )
+    .unwrap();
+    assert_eq!(stats.instructions, 1000 - fuel);
+
+    // Statistics are returned for failed calls too.
+    let (result, stats) = instance.invoke_export_with_stats("fail", &[], &mut env);
+    assert!(matches!(result, Err(Error::Trap(_))));
+    assert_eq!(stats.host_calls, 3);
+    assert!(stats.instructions > 0);
+
+    let (result, stats) = instance.invoke_export_with_stats("missing", &[], &mut env);
+    assert!(result.is_err());
+    assert_eq!(stats, CallStats::default());
+}
+
+#[test]
+fn host_call_limit() {
+    let module = parse_wat(
+        r#"
+(module
+	(import "env" "sub" (func $sub (param i32 i32) (result i32)))
+
+	(func (export "countdown") (param i32)
+		(loop $continue
+			(br_if $continue (tee_local 0 (call $sub (get_local 0) (i32.const 1))))
+		)
+	)
+)
+"#,
+    );
+
+    let mut env = TestHost::new();
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::new().with_resolver("env", &env))
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+    let countdown = instance.export_by_name("countdown").unwrap();
+    let countdown = countdown.as_func().unwrap();
+    let mut run = |n: i32, limit: u64| {
+        FuncInstance::invoke_with_host_call_limit(
+            countdown,
+            &[RuntimeValue::I32(n)],
+            &mut env,
+            limit,
+        )
+    };
+
+    // Counting down from `n` calls `sub` `n` times.
+    assert_eq!(run(3, 3).expect("Failed to invoke 'countdown'"), None);
+    // The count starts anew for each invocation.
+    assert_eq!(run(3, 3).expect("Failed to invoke 'countdown'"), None);
+
+    let trap = run(1000, 3).expect_err("Limit wasn't enforced");
+    assert!(matches!(trap.kind(), TrapKind::HostCallLimitExceeded));
+    assert_eq!(trap.code(), TrapCode::HostCallLimitExceeded);
+
+    // Invoking a host function directly counts as a call.
+    let sub = env
+        .resolve_func(
+            "sub",
+            &Signature::new(&[ValueType::I32, ValueType::I32][..], Some(ValueType::I32)),
+        )
+        .unwrap();
+    let args = [RuntimeValue::I32(3), RuntimeValue::I32(1)];
+    assert_eq!(
+        FuncInstance::invoke_with_host_call_limit(&sub, &args, &mut env, 1).unwrap(),
+        Some(RuntimeValue::I32(2))
+    );
+    let trap = FuncInstance::invoke_with_host_call_limit(&sub, &args, &mut env, 0).unwrap_err();
+    assert!(matches!(trap.kind(), TrapKind::HostCallLimitExceeded));
+}
+
+#[test]
+fn preloaded_memory_shared_between_instances() {
+    let module = parse_wat(
+        r#"
+(module
+	(import "env" "memory" (memory 1))
+
+	(func (export "load") (param i32) (result i32)
+		(i32.load8_u (get_local 0))
+	)
+
+	(func (export "store") (param i32 i32)
+		(i32.store8 (get_local 0) (get_local 1))
+	)
+)
+"#,
+    );
+
+    let memory = MemoryInstance::alloc_with_data(Pages(1), None, &[(0, b"\x2a"), (100, b"\x07")])
+        .expect("Failed to allocate memory");
+    let imports =
+        ImportsBuilder::new().with_extern("env", "memory", ExternVal::Memory(memory.clone()));
+    let writer = ModuleInstance::new(&module, &imports)
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+    let reader = ModuleInstance::new(&module, &imports)
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+
+    assert_eq!(
+        reader
+            .invoke_export("load", &[RuntimeValue::I32(100)], &mut NopExternals)
+            .unwrap(),
+        Some(RuntimeValue::I32(7))
+    );
+    writer
+        .invoke_export(
+            "store",
+            &[RuntimeValue::I32(1), RuntimeValue::I32(5)],
+            &mut NopExternals,
+        )
+        .unwrap();
+    assert_eq!(
+        reader
+            .invoke_export("load", &[RuntimeValue::I32(1)], &mut NopExternals)
+            .unwrap(),
+        Some(RuntimeValue::I32(5))
+    );
+    assert_eq!(memory.get(0, 2).unwrap(), [42, 5]);
+
+    // Segments must fit into the initial size.
+    assert!(matches!(
+        MemoryInstance::alloc_with_data(Pages(1), None, &[(65535, b"\x01\x02")]),
+        Err(Error::MemoryOutOfBounds { .. })
+    ));
+}
+
+#[test]
+fn host_module_macro() {
+    use crate::nan_preserving_float::F64;
+
+    struct Env {
+        calls: u32,
+    }
+
+    crate::host_module! {
+        impl Env {
+            fn count(&mut self) -> u32 {
+                self.calls += 1;
+                self.calls
+            }
+
+            fn scale(&mut self, value: F64, factor: i64) -> F64 {
+                F64::from(value.to_float() * factor as f64)
+            }
+
+            fn fail(&mut self, code: u32) -> Result<(), Trap> {
+                Err(TrapKind::Host(Box::new(HostErrorWithCode { error_code: code })).into())
+            }
+        }
+    }
+
+    let module = parse_wat(
+        r#"
+(module
+    (import "env" "count" (func $count (result i32)))
+    (import "env" "scale" (func $scale (param f64 i64) (result f64)))
+    (import "env" "fail" (func $fail (param i32)))
+    (func (export "count_twice") (result i32)
+        (drop (call $count))
+        (call $count))
+    (func (export "scale") (param f64) (result f64)
+        (call $scale (local.get 0) (i64.const 3)))
+    (func (export "fail")
+        (call $fail (i32.const 7))))
+"#,
+    );
+    let mut env = Env { calls: 0 };
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::new().with_resolver("env", &env))
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+
+    assert_eq!(
+        instance
+            .invoke_export("count_twice", &[], &mut env)
+            .unwrap(),
+        Some(RuntimeValue::I32(2))
+    );
+    assert_eq!(
+        instance
+            .invoke_export("scale", &[RuntimeValue::F64(1.5.into())], &mut env)
+            .unwrap(),
+        Some(RuntimeValue::F64(4.5.into()))
+    );
+    let error = instance.invoke_export("fail", &[], &mut env).unwrap_err();
+    assert_eq!(
+        error
+            .as_host_error()
+            .unwrap()
+            .downcast_ref::<HostErrorWithCode>(),
+        Some(&HostErrorWithCode { error_code: 7 })
+    );
+
+    // Imports missing from the host module, or with another signature, are rejected.
+    let resolve = |wat: &str| {
+        ModuleInstance::new(
+            &parse_wat(wat),
+            &ImportsBuilder::new().with_resolver("env", &env),
+        )
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+    };
+    assert_eq!(
+        resolve(r#"(module (import "env" "missing" (func)))"#),
+        Err("Instantiation: Export missing not found".into())
+    );
+    assert_eq!(
+        resolve(r#"(module (import "env" "count" (func (result i64))))"#),
+        Err(
+            "Instantiation: Export count has signature Signature { params: [], return_type: Some(I32) }, \
+             but the import expects Signature { params: [], return_type: Some(I64) }"
+                .into()
+        )
+    );
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn bounded_stack_traps_on_deep_reentrance() {
+    use crate::{run_on_bounded_stack, NATIVE_STACK_RESERVE};
+
+    /// Invokes `recurse` of the instance again on every call of `reenter`.
+    struct Reentrant {
+        instance: Option<ModuleRef>,
+        depth: u32,
+    }
+
+    impl Externals for Reentrant {
+        fn invoke_index(
+            &mut self,
+            _index: usize,
+            _args: RuntimeArgs,
+        ) -> Result<Option<RuntimeValue>, Trap> {
+            self.depth += 1;
+            let instance = self
+                .instance
+                .clone()
+                .expect("instance is set before invoking");
+            match instance.invoke_export("recurse", &[], self) {
+                Ok(result) => Ok(result),
+                Err(Error::Trap(trap)) => Err(trap),
+                Err(error) => panic!("Unexpected error {:?}", error),
+            }
+        }
+    }
+
+    struct Env;
+    impl ModuleImportResolver for Env {
+        fn resolve_func(&self, _field_name: &str, signature: &Signature) -> Result<FuncRef, Error> {
+            Ok(FuncInstance::alloc_host(signature.clone(), 0))
+        }
+    }
+
+    // Instances can't be sent to the thread, so it gets the binary.
+    let wasm = wabt::wat2wasm(
+        r#"
+        (module
+            (import "env" "reenter" (func $reenter))
+            (func (export "recurse") (call $reenter)))
+        "#,
+    )
+    .unwrap();
+    let run = |stack_size: usize| {
+        run_on_bounded_stack(stack_size, || {
+            let mut externals = Reentrant {
+                instance: None,
+                depth: 0,
+            };
+            let module = crate::Module::from_buffer(&wasm).expect("Failed to load module");
+            let instance =
+                ModuleInstance::new(&module, &ImportsBuilder::new().with_resolver("env", &Env))
+                    .expect("Failed to instantiate module")
+                    .assert_no_start();
+            externals.instance = Some(instance.clone());
+            let code = match instance.invoke_export("recurse", &[], &mut externals) {
+                Err(Error::Trap(trap)) => trap.code(),
+                other => panic!("Unexpected result {:?}", other),
+            };
+            (code, externals.depth)
+        })
+        .expect("Failed to spawn the thread")
+    };
+
+    // The recursion is unbounded, so it only ends by exhausting the stack, and a
+    // larger stack allows deeper recursion.
+    let (code, small_depth) = run(NATIVE_STACK_RESERVE + 512 * 1024);
+    assert_eq!(code, TrapCode::NativeStackExhausted);
+    let (code, large_depth) = run(NATIVE_STACK_RESERVE + 2 * 1024 * 1024);
+    assert_eq!(code, TrapCode::NativeStackExhausted);
+    assert!(small_depth > 0 && large_depth > small_depth);
+
+    // A stack below the reserve traps on the first invocation.
+    assert_eq!(
+        run(NATIVE_STACK_RESERVE / 2),
+        (TrapCode::NativeStackExhausted, 0)
+    );
+
+    // Panics are resumed on the calling thread.
+    let panicked =
+        std::panic::catch_unwind(|| run_on_bounded_stack(1024 * 1024, || panic!("boom")));
+    assert!(panicked.is_err());
+}
@@ -38,7 +38,440 @@ fn unsigned_to_runtime_value() {
     );
 }
 
+#[test]
+fn runtime_value_display() {
+    use super::nan_preserving_float::{F32, F64};
+    use super::RuntimeValue;
+    use alloc::string::ToString;
+
+    let cases = [
+        (RuntimeValue::I32(42), "i32:42"),
+        (RuntimeValue::I32(-1), "i32:-1"),
+        (RuntimeValue::I64(i64::MIN), "i64:-9223372036854775808"),
+        (RuntimeValue::F32(1.5f32.into()), "f32:1.5"),
+        (RuntimeValue::F32((-0.0f32).into()), "f32:-0"),
+        (RuntimeValue::F32(f32::INFINITY.into()), "f32:inf"),
+        (RuntimeValue::F64(f64::NEG_INFINITY.into()), "f64:-inf"),
+        (RuntimeValue::F32(F32::from_bits(0x7fc0_0000)), "f32:nan"),
+        (RuntimeValue::F32(F32::from_bits(0xffc0_0000)), "f32:-nan"),
+        (
+            RuntimeValue::F32(F32::from_bits(0x7fa0_0001)),
+            "f32:nan:0x200001",
+        ),
+        (
+            RuntimeValue::F64(F64::from_bits(0x7ff8_0000_0000_0000)),
+            "f64:nan",
+        ),
+        (
+            RuntimeValue::F64(F64::from_bits(0x7ff4_0000_0000_0001)),
+            "f64:nan:0x4000000000001",
+        ),
+    ];
+    for (value, expected) in cases.iter() {
+        assert_eq!(value.to_string(), *expected);
+    }
+}
+
+#[test]
+fn runtime_value_checked_casts() {
+    use super::{RuntimeValue, TrapKind};
+
+    fn overflow<T: ::core::fmt::Debug>(result: Result<T, super::Trap>) -> bool {
+        matches!(result.unwrap_err().kind(), TrapKind::IntegerOverflow)
+    }
+    fn mismatch<T: ::core::fmt::Debug>(result: Result<T, super::Trap>) -> bool {
+        matches!(result.unwrap_err().kind(), TrapKind::UnexpectedSignature)
+    }
+
+    assert_eq!(RuntimeValue::I32(-1).as_u32_checked().unwrap(), u32::MAX);
+    assert_eq!(
+        RuntimeValue::I64(u32::MAX as i64).as_u32_checked().unwrap(),
+        u32::MAX
+    );
+    assert!(overflow(
+        RuntimeValue::I64(u32::MAX as i64 + 1).as_u32_checked()
+    ));
+    assert!(overflow(RuntimeValue::I64(-1).as_u32_checked()));
+    assert!(mismatch(RuntimeValue::F32(0.0.into()).as_u32_checked()));
+
+    assert_eq!(
+        RuntimeValue::I64(i32::MIN as i64)
+            .as_i32_from_i64_checked()
+            .unwrap(),
+        i32::MIN
+    );
+    assert!(overflow(
+        RuntimeValue::I64(i32::MAX as i64 + 1).as_i32_from_i64_checked()
+    ));
+    assert!(mismatch(RuntimeValue::I32(0).as_i32_from_i64_checked()));
+
+    assert_eq!(RuntimeValue::I32(-1).as_i64_sign_extended().unwrap(), -1);
+    assert_eq!(
+        RuntimeValue::I32(-1).as_u64_zero_extended().unwrap(),
+        u32::MAX as u64
+    );
+    assert_eq!(
+        RuntimeValue::I64(-1).as_u64_zero_extended().unwrap(),
+        u64::MAX
+    );
+    assert!(mismatch(
+        RuntimeValue::F64(0.0.into()).as_i64_sign_extended()
+    ));
+}
+
+#[test]
+fn runtime_value_enums_and_flags() {
+    use super::{I32Enum, RuntimeValue, TrapKind};
+
+    #[derive(Debug, PartialEq)]
+    enum Sign {
+        Negative = -1,
+        Positive = 1,
+    }
+
+    impl I32Enum for Sign {
+        fn from_i32(value: i32) -> Option<Self> {
+            match value {
+                -1 => Some(Sign::Negative),
+                1 => Some(Sign::Positive),
+                _ => None,
+            }
+        }
+    }
+
+    fn invalid<T: ::core::fmt::Debug>(result: Result<T, super::Trap>) -> Option<i32> {
+        match *result.unwrap_err().kind() {
+            TrapKind::InvalidArgument { value } => Some(value),
+            _ => None,
+        }
+    }
+
+    assert_eq!(
+        RuntimeValue::I32(-1).as_enum_checked::<Sign>().unwrap(),
+        Sign::Negative
+    );
+    assert_eq!(
+        invalid(RuntimeValue::I32(0).as_enum_checked::<Sign>()),
+        Some(0)
+    );
+    assert!(matches!(
+        RuntimeValue::I64(1)
+            .as_enum_checked::<Sign>()
+            .unwrap_err()
+            .kind(),
+        TrapKind::UnexpectedSignature
+    ));
+
+    let known = 0x8000_0001;
+    assert_eq!(RuntimeValue::I32(0).as_flags_checked(known).unwrap(), 0);
+    assert_eq!(
+        RuntimeValue::I32(i32::MIN | 1)
+            .as_flags_checked(known)
+            .unwrap(),
+        0x8000_0001
+    );
+    assert_eq!(
+        invalid(RuntimeValue::I32(2).as_flags_checked(known)),
+        Some(2)
+    );
+    assert_eq!(
+        invalid(RuntimeValue::I32(-1).as_flags_checked(known)),
+        Some(-1)
+    );
+    assert!(matches!(
+        RuntimeValue::F32(0.0.into())
+            .as_flags_checked(known)
+            .unwrap_err()
+            .kind(),
+        TrapKind::UnexpectedSignature
+    ));
+}
+
+#[test]
+fn runtime_value_coercion() {
+    use super::{Coercion, RuntimeValue, ValueType};
+    use RuntimeValue::*;
+
+    // Compare bits, so the sign of zeros and NaN payloads count.
+    let bits = |value: Option<RuntimeValue>| value.map(|v| (v.value_type(), v.to_abi_slot()));
+    let check = |value: RuntimeValue, ty: ValueType, lossless: Option<RuntimeValue>, truncated| {
+        assert_eq!(
+            bits(value.coerce_to(ty, Coercion::Lossless)),
+            bits(lossless),
+            "{} to {:?}",
+            value,
+            ty
+        );
+        assert_eq!(
+            bits(value.coerce_to(ty, Coercion::Truncate)),
+            bits(truncated),
+            "{} to {:?}, truncated",
+            value,
+            ty
+        );
+    };
+    let exact = |value, ty, expected| check(value, ty, Some(expected), Some(expected));
+    let lossy = |value, ty, truncated| check(value, ty, None, Some(truncated));
+    let never = |value, ty| check(value, ty, None, None);
+
+    exact(I32(-7), ValueType::I32, I32(-7));
+    exact(F64((-0.0).into()), ValueType::F64, F64((-0.0).into()));
+    exact(I32(-1), ValueType::I64, I64(-1));
+    exact(I64(-1), ValueType::I32, I32(-1));
+    exact(I64(i64::from(i32::MIN)), ValueType::I32, I32(i32::MIN));
+    exact(I64(i64::from(u32::MAX)), ValueType::I32, I32(-1));
+    lossy(I64(i64::from(u32::MAX) + 1), ValueType::I32, I32(0));
+    lossy(I64(i64::from(i32::MIN) - 1), ValueType::I32, I32(i32::MAX));
+
+    exact(I32(1 << 24), ValueType::F32, F32(16_777_216.0.into()));
+    lossy(I32((1 << 24) + 1), ValueType::F32, F32(16_777_216.0.into()));
+    exact(
+        I32(i32::MIN),
+        ValueType::F64,
+        F64(f64::from(i32::MIN).into()),
+    );
+    exact(
+        I64(-(1 << 53)),
+        ValueType::F64,
+        F64((-9_007_199_254_740_992.0).into()),
+    );
+    lossy(
+        I64((1 << 53) + 1),
+        ValueType::F64,
+        F64(9_007_199_254_740_992.0.into()),
+    );
+    // `i64::MAX` rounds up to 2^63, which must not be mistaken for an exact conversion.
+    lossy(
+        I64(i64::MAX),
+        ValueType::F64,
+        F64(9_223_372_036_854_775_808.0.into()),
+    );
+    lossy(
+        I64(i64::MAX),
+        ValueType::F32,
+        F32(9_223_372_036_854_775_808.0.into()),
+    );
+
+    exact(F32((-0.0).into()), ValueType::F64, F64((-0.0).into()));
+    exact(
+        F32(0.1.into()),
+        ValueType::F64,
+        F64(f64::from(0.1f32).into()),
+    );
+    exact(F64(0.5.into()), ValueType::F32, F32(0.5.into()));
+    exact(
+        F64(f64::INFINITY.into()),
+        ValueType::F32,
+        F32(f32::INFINITY.into()),
+    );
+    lossy(F64(0.1.into()), ValueType::F32, F32(0.1.into()));
+    lossy(F64(1e300.into()), ValueType::F32, F32(f32::INFINITY.into()));
+    assert!(F64(f64::NAN.into())
+        .coerce_to(ValueType::F32, Coercion::Lossless)
+        .and_then(|value| value.try_into::<crate::nan_preserving_float::F32>())
+        .is_some_and(|value| value.is_nan()));
+
+    never(F32(1.0.into()), ValueType::I32);
+    never(F64(1.0.into()), ValueType::I64);
+    never(F32(1.0.into()), ValueType::I64);
+}
+
+#[test]
+fn runtime_value_abi_slots() {
+    use super::nan_preserving_float::{F32, F64};
+    use super::{RuntimeValue, ValueType};
+
+    let cases = [
+        (RuntimeValue::I32(-1), 0x0000_0000_ffff_ffff),
+        (RuntimeValue::I64(-1), u64::MAX),
+        (RuntimeValue::F32(1.0f32.into()), 0x3f80_0000),
+        (RuntimeValue::F64(1.0f64.into()), 0x3ff0_0000_0000_0000),
+        (RuntimeValue::F32(F32::from_bits(0xffa0_0001)), 0xffa0_0001),
+        (
+            RuntimeValue::F64(F64::from_bits(0xfff4_0000_0000_0001)),
+            0xfff4_0000_0000_0001,
+        ),
+    ];
+    for &(value, slot) in cases.iter() {
+        assert_eq!(value.to_abi_slot(), slot);
+        let decoded = RuntimeValue::from_abi_slot(value.value_type(), slot);
+        // Compare the slots, since NaNs never compare equal.
+        assert_eq!(decoded.to_abi_slot(), slot);
+        assert_eq!(decoded.value_type(), value.value_type());
+    }
+
+    // The high bits of 32-bit slots are ignored.
+    assert_eq!(
+        RuntimeValue::from_abi_slot(ValueType::I32, u64::MAX),
+        RuntimeValue::I32(-1)
+    );
+    assert_eq!(
+        RuntimeValue::from_abi_slot(ValueType::F32, 0xffff_ffff_3f80_0000),
+        RuntimeValue::F32(1.0f32.into())
+    );
+}
+
+#[test]
+fn runtime_value_checksum() {
+    use super::nan_preserving_float::F32;
+    use super::RuntimeValue;
+
+    // Reference values of the specified hash, so it can't change between versions.
+    assert_eq!(RuntimeValue::checksum(&[]), 0xcbf2_9ce4_8422_2325);
+    assert_eq!(
+        RuntimeValue::checksum(&[RuntimeValue::I32(0)]),
+        0xe604_823a_2490_29bf
+    );
+
+    let values = [
+        RuntimeValue::I32(-1),
+        RuntimeValue::I64(-1),
+        RuntimeValue::F32(F32::from_bits(0x7fc0_0000)),
+        RuntimeValue::F32(F32::from_bits(0xffc0_0000)),
+    ];
+    let checksum = RuntimeValue::checksum(&values);
+    assert_eq!(checksum, RuntimeValue::checksum(values.to_vec().iter()));
+    // Changing the order, a type or the sign of a NaN changes the checksum.
+    let mut swapped = values;
+    swapped.swap(0, 1);
+    let mut retyped = values;
+    retyped[0] = RuntimeValue::I64(0xffff_ffff);
+    let mut flipped = values;
+    flipped[3] = RuntimeValue::F32(F32::from_bits(0x7fc0_0000));
+    for other in &[swapped, retyped, flipped] {
+        assert_ne!(RuntimeValue::checksum(other), checksum);
+    }
+}
+
 pub fn parse_wat(source: &str) -> Module {
     let wasm_binary = wabt::wat2wasm(source).expect("Failed to parse wat source");
     Module::from_buffer(wasm_binary).expect("Failed to load parsed module")
 }
+
+#[cfg(feature = "json")]
+#[test]
+fn runtime_value_json() {
+    use super::{RuntimeValue, ValueType};
+    use serde_json::json;
+
+    let from = |json, value_type| RuntimeValue::from_json(&json, value_type);
+
+    assert_eq!(
+        from(json!(4294967295u32), ValueType::I32).unwrap(),
+        RuntimeValue::I32(-1)
+    );
+    assert_eq!(
+        from(json!(-2147483648), ValueType::I32).unwrap(),
+        RuntimeValue::I32(i32::MIN)
+    );
+    assert!(from(json!(4294967296u64), ValueType::I32).is_err());
+    assert!(from(json!(-2147483649i64), ValueType::I32).is_err());
+    assert_eq!(
+        from(json!(u64::MAX), ValueType::I64).unwrap(),
+        RuntimeValue::I64(-1)
+    );
+    assert!(from(json!(1.0), ValueType::I32).is_err());
+    assert!(from(json!(null), ValueType::F64).is_err());
+
+    assert_eq!(
+        from(json!(0.1), ValueType::F32).unwrap(),
+        RuntimeValue::F32(0.1f32.into())
+    );
+    assert_eq!(
+        from(json!(3), ValueType::F64).unwrap(),
+        RuntimeValue::F64(3f64.into())
+    );
+    assert!(from(json!(1e300), ValueType::F32).is_err());
+
+    // Values convert back to the same JSON numbers, except for non-finite floats.
+    for &(ref json, value_type) in &[
+        (json!(-7), ValueType::I32),
+        (json!(i64::MIN), ValueType::I64),
+        (json!(-0.5), ValueType::F32),
+        (json!(1e300), ValueType::F64),
+    ] {
+        assert_eq!(&from(json.clone(), value_type).unwrap().to_json(), json);
+    }
+    assert_eq!(
+        RuntimeValue::F64(f64::INFINITY.into()).to_json(),
+        json!(null)
+    );
+}
+
+#[cfg(feature = "wat")]
+#[test]
+fn module_to_wat_round_trip() {
+    let wasm = wabt::wat2wasm(
+        r#"
+(module
+  (type $binop (func (param i32 i32) (result i32)))
+  (import "env" "log" (func $log (param i32)))
+  (import "env" "table" (table 2 funcref))
+  (import "env" "base" (global $base i32))
+  (memory 1 2)
+  (global $counter (mut i64) (i64.const -1))
+  (func $add (type $binop)
+    (i32.add (get_local 0) (get_local 1)))
+  (func $body (export "body") (param i32) (result f64)
+    (local i32 i64 i64)
+    (block $out
+      (loop $again
+        (br_table $again $out $out (get_local 0))
+      )
+    )
+    (if (result i32) (i32.eqz (get_local 0))
+      (then (i32.const 1))
+      (else (call_indirect (type $binop) (i32.const 2) (i32.const 3) (i32.const 0)))
+    )
+    (call $log)
+    (i32.store16 offset=4 align=1 (get_global $base) (i32.load8_u offset=1 (i32.const 0)))
+    (drop (f32.const -0x1.8p-130))
+    (drop (f32.const nan:0x200000))
+    (drop (f64.const -inf))
+    (f64.const 0.1)
+  )
+  (func $init)
+  (start $init)
+  (elem (get_global $base) $add $body)
+  (data (i32.const 16) "a\"b\\c\00\ffd")
+)
+"#,
+    )
+    .unwrap();
+
+    let wat = Module::from_buffer(&wasm).unwrap().to_wat();
+    assert_eq!(
+        wabt::wat2wasm(&wat).unwrap_or_else(|e| panic!("{:?} in:\n{}", e, wat)),
+        wasm,
+        "{}",
+        wat
+    );
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn import_manifest_json() {
+    use super::ImportManifest;
+
+    let manifest = ImportManifest::from_json(
+        r#"{ "env": { "print": "console.log" }, "wasi": { "proc_exit": "process.exit" } }"#,
+    )
+    .unwrap();
+    assert_eq!(
+        manifest,
+        ImportManifest::new()
+            .with_import("env", "print", "console.log")
+            .with_import("wasi", "proc_exit", "process.exit")
+    );
+    assert_eq!(
+        ImportManifest::from_json("{}").unwrap(),
+        ImportManifest::new()
+    );
+
+    for invalid in &["", "[]", r#"{ "env": [] }"#, r#"{ "env": { "print": 1 } }"#] {
+        match ImportManifest::from_json(invalid) {
+            Err(Error::Value(message)) => assert!(message.starts_with("Invalid import manifest")),
+            result => panic!("Expected an error for {}, got {:?}", invalid, result),
+        }
+    }
+}
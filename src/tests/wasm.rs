@@ -1,11 +1,14 @@
 // Test-only code importing std for no-std testing
 extern crate std;
 
+use super::parse_wat;
 use crate::memory_units::Pages;
 use crate::{
-    Error, FuncRef, GlobalDescriptor, GlobalInstance, GlobalRef, ImportsBuilder, MemoryDescriptor,
-    MemoryInstance, MemoryRef, Module, ModuleImportResolver, ModuleInstance, NopExternals,
-    RuntimeValue, Signature, TableDescriptor, TableInstance, TableRef,
+    Error, ExternVal, FuncInstance, FuncRef, GlobalDescriptor, GlobalInstance, GlobalRef,
+    ImportsBuilder, MemoryDescriptor, MemoryInstance, MemoryRef, MemoryWrite, Module,
+    ModuleImportResolver, ModuleInstance, ModuleRef, NopExternals, RuntimeValue, SegmentKind,
+    Signature, StackRecycler, TableDescriptor, TableInstance, TableRef, TrapCode, TrapKind,
+    ValueType,
 };
 use alloc::vec::Vec;
 use std::fs::File;
@@ -153,3 +156,4029 @@ fn interpreter_accumulate_u8() {
     // Verify calculation from WebAssembly runtime is identical to expected result
     assert_eq!(exp_retval, retval);
 }
+
+#[test]
+fn recycled_stacks_unwind_on_trap() {
+    let module = parse_wat(
+        r#"
+(module
+  (func $fib (export "fib") (param i32) (result i32)
+    (if (result i32) (i32.lt_u (get_local 0) (i32.const 2))
+      (then (get_local 0))
+      (else
+        (i32.add
+          (call $fib (i32.sub (get_local 0) (i32.const 1)))
+          (call $fib (i32.sub (get_local 0) (i32.const 2)))
+        )
+      )
+    )
+  )
+  (func $trap (export "trap") (param i32) (result i32)
+    (if (result i32) (i32.eqz (get_local 0))
+      (then unreachable)
+      (else (call $trap (i32.sub (get_local 0) (i32.const 1))))
+    )
+  )
+)
+"#,
+    );
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+
+    let mut stack_recycler = StackRecycler::default();
+    for _ in 0..3 {
+        assert_eq!(
+            instance
+                .invoke_export_with_stack(
+                    "fib",
+                    &[RuntimeValue::I32(15)],
+                    &mut NopExternals,
+                    &mut stack_recycler,
+                )
+                .expect("Failed to execute function"),
+            Some(RuntimeValue::I32(610))
+        );
+        assert!(instance
+            .invoke_export_with_stack(
+                "trap",
+                &[RuntimeValue::I32(1000)],
+                &mut NopExternals,
+                &mut stack_recycler,
+            )
+            .is_err());
+    }
+}
+
+#[test]
+fn trap_on_grow_failure() {
+    let module = parse_wat(
+        r#"
+(module
+  (memory (export "mem") 1 2)
+  (func (export "grow") (result i32)
+    (grow_memory (i32.const 1))
+  )
+)
+"#,
+    );
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+    let memory = instance
+        .export_by_name("mem")
+        .and_then(|export| export.as_memory().cloned())
+        .expect("Module should export memory");
+    assert!(!memory.trap_on_grow_failure());
+
+    assert_eq!(
+        instance
+            .invoke_export("grow", &[], &mut NopExternals)
+            .expect("Failed to execute function"),
+        Some(RuntimeValue::I32(1))
+    );
+    // Growing past the maximum fails, which is signalled with -1 by default.
+    assert_eq!(
+        instance
+            .invoke_export("grow", &[], &mut NopExternals)
+            .expect("Failed to execute function"),
+        Some(RuntimeValue::I32(-1))
+    );
+
+    memory.set_trap_on_grow_failure(true);
+    match instance.invoke_export("grow", &[], &mut NopExternals) {
+        Err(Error::Trap(trap)) => assert!(matches!(trap.kind(), TrapKind::MemoryGrowFailed)),
+        other => panic!("expected grow failure trap, got {:?}", other),
+    }
+    assert_eq!(memory.current_size(), Pages(2));
+}
+
+#[test]
+fn prepared_call() {
+    let module = parse_wat(
+        r#"
+(module
+  (memory (export "mem") 1)
+  (func (export "inc") (param i32) (result i32)
+    (i32.add (get_local 0) (i32.const 1))
+  )
+)
+"#,
+    );
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+
+    let signature = Signature::new(&[ValueType::I32][..], Some(ValueType::I32));
+    let mut inc = instance
+        .prepare_call("inc", &signature)
+        .expect("Failed to prepare call");
+    let mut value = 0;
+    for _ in 0..100 {
+        value = inc
+            .invoke(&[RuntimeValue::I32(value)], &mut NopExternals)
+            .expect("Failed to execute function")
+            .and_then(|value| value.try_into())
+            .expect("Function should return i32");
+    }
+    assert_eq!(value, 100);
+    // Arguments are still checked on each invocation.
+    assert!(inc
+        .invoke(&[RuntimeValue::I64(0)], &mut NopExternals)
+        .is_err());
+
+    assert!(instance
+        .prepare_call(
+            "inc",
+            &Signature::new(&[ValueType::I64][..], Some(ValueType::I64))
+        )
+        .is_err());
+    assert!(instance.prepare_call("mem", &signature).is_err());
+    assert!(instance.prepare_call("missing", &signature).is_err());
+}
+
+#[test]
+fn extended_const_data_segment_offset() {
+    use parity_wasm::builder;
+    use parity_wasm::elements::{
+        DataSegment, External, GlobalType, ImportEntry, InitExpr, Instruction,
+        ValueType as EValueType,
+    };
+
+    // (data (i32.add (i32.const 8) (global.get 0)) "hi")
+    let parity_module = builder::module()
+        .with_import(ImportEntry::new(
+            "env".into(),
+            "base".into(),
+            External::Global(GlobalType::new(EValueType::I32, false)),
+        ))
+        .memory()
+        .build()
+        .with_data_segment(DataSegment::new(
+            0,
+            Some(InitExpr::new(vec![
+                Instruction::I32Const(8),
+                Instruction::GetGlobal(0),
+                Instruction::I32Add,
+                Instruction::End,
+            ])),
+            b"hi".to_vec(),
+        ))
+        .export()
+        .field("mem")
+        .internal()
+        .memory(0)
+        .build()
+        .build();
+    let module = Module::from_parity_wasm_module(parity_module).expect("Failed to load module");
+
+    let base = GlobalInstance::alloc(RuntimeValue::I32(16), false);
+    let imports = ImportsBuilder::new().with_extern("env", "base", ExternVal::Global(base));
+    let instance = ModuleInstance::new(&module, &imports)
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+    let memory = instance
+        .export_by_name("mem")
+        .and_then(|export| export.as_memory().cloned())
+        .expect("Module should export memory");
+    assert_eq!(memory.get(24, 2).unwrap(), b"hi");
+}
+
+#[test]
+fn recycled_stacks_dont_leak_values() {
+    let module = parse_wat(
+        r#"
+(module
+  (func (export "dirty") (param i32) (result i32)
+    (local i32 i32 i32 i32)
+    (set_local 1 (get_local 0))
+    (set_local 2 (get_local 0))
+    (set_local 3 (get_local 0))
+    (set_local 4 (get_local 0))
+    (if (i32.eqz (get_local 0))
+      (then unreachable)
+    )
+    (get_local 4)
+  )
+  (func (export "read") (result i32)
+    (local i32 i32 i32 i32 i32)
+    (i32.or
+      (i32.or (get_local 0) (get_local 1))
+      (i32.or (get_local 2) (i32.or (get_local 3) (get_local 4)))
+    )
+  )
+)
+"#,
+    );
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+
+    let mut stack_recycler = StackRecycler::default();
+    // Leave values on the recycled stack both on return and on trap.
+    for &arg in &[42, 0] {
+        let _ = instance.invoke_export_with_stack(
+            "dirty",
+            &[RuntimeValue::I32(arg)],
+            &mut NopExternals,
+            &mut stack_recycler,
+        );
+        assert_eq!(
+            instance
+                .invoke_export_with_stack("read", &[], &mut NopExternals, &mut stack_recycler)
+                .expect("Failed to execute function"),
+            Some(RuntimeValue::I32(0))
+        );
+    }
+}
+
+#[cfg(feature = "atomics")]
+#[test]
+fn atomic_wait_and_notify() {
+    use crate::ModuleBuilder;
+    use parity_wasm::elements::{
+        deserialize_buffer, AtomicsInstruction, Instruction, MemArg, MemoryType,
+    };
+
+    fn memarg(align: u8) -> MemArg {
+        MemArg { align, offset: 0 }
+    }
+    let atomic = |instruction| {
+        vec![
+            Instruction::GetLocal(0),
+            Instruction::GetLocal(1),
+            Instruction::GetLocal(2),
+            Instruction::Atomics(instruction),
+        ]
+    };
+
+    let mut parity_module = ModuleBuilder::new()
+        .with_memory(1, None)
+        .with_func(
+            &Signature::new(&[ValueType::I32, ValueType::I32][..], Some(ValueType::I32)),
+            &[],
+            vec![
+                Instruction::GetLocal(0),
+                Instruction::GetLocal(1),
+                Instruction::Atomics(AtomicsInstruction::AtomicWake(memarg(2))),
+            ],
+        )
+        .with_func(
+            &Signature::new(
+                &[ValueType::I32, ValueType::I32, ValueType::I64][..],
+                Some(ValueType::I32),
+            ),
+            &[],
+            atomic(AtomicsInstruction::I32AtomicWait(memarg(2))),
+        )
+        .with_func(
+            &Signature::new(
+                &[ValueType::I32, ValueType::I64, ValueType::I64][..],
+                Some(ValueType::I32),
+            ),
+            &[],
+            atomic(AtomicsInstruction::I64AtomicWait(memarg(3))),
+        )
+        .export_func("notify", 0)
+        .export_func("wait32", 1)
+        .export_func("wait64", 2)
+        .build();
+    let unshared = parity_module.clone();
+    // The builder can't declare shared memories: limits with a maximum of 1, shared.
+    let shared: MemoryType = deserialize_buffer(&[0x03, 0x01, 0x01]).unwrap();
+    parity_module.memory_section_mut().unwrap().entries_mut()[0] = shared;
+
+    let instantiate = |parity_module| {
+        let module = Module::from_parity_wasm_module(parity_module).expect("Failed to load module");
+        ModuleInstance::new(&module, &ImportsBuilder::default())
+            .expect("Failed to instantiate module")
+            .assert_no_start()
+    };
+    let instance = instantiate(parity_module);
+    assert!(instance.memory_by_index(0).unwrap().is_shared());
+
+    let invoke_on = |instance: &ModuleRef, name: &str, args: &[RuntimeValue]| {
+        instance
+            .invoke_export(name, args, &mut NopExternals)
+            .map_err(|err| match err {
+                Error::Trap(trap) => trap,
+                err => panic!("unexpected error: {:?}", err),
+            })
+    };
+    let invoke = |name: &str, args: &[RuntimeValue]| invoke_on(&instance, name, args);
+    let wait32_args = |address: i32, expected: i32, timeout: i64| {
+        [
+            RuntimeValue::I32(address),
+            RuntimeValue::I32(expected),
+            RuntimeValue::I64(timeout),
+        ]
+    };
+    let wait32 = |address: i32, expected: i32, timeout: i64| {
+        invoke("wait32", &wait32_args(address, expected, timeout))
+    };
+
+    // Nobody is ever waiting, so no waiters are woken up.
+    assert_eq!(
+        invoke("notify", &[RuntimeValue::I32(0), RuntimeValue::I32(1)]).unwrap(),
+        Some(RuntimeValue::I32(0))
+    );
+    // "not-equal"
+    assert_eq!(wait32(0, 1, -1).unwrap(), Some(RuntimeValue::I32(1)));
+    // "timed-out", returned without actually sleeping.
+    assert_eq!(
+        wait32(0, 0, 1_000_000_000).unwrap(),
+        Some(RuntimeValue::I32(2))
+    );
+    assert_eq!(
+        invoke(
+            "wait64",
+            &[
+                RuntimeValue::I32(8),
+                RuntimeValue::I64(0),
+                RuntimeValue::I64(0)
+            ]
+        )
+        .unwrap(),
+        Some(RuntimeValue::I32(2))
+    );
+
+    assert!(matches!(
+        wait32(0, 0, -1).unwrap_err().kind(),
+        TrapKind::AtomicWaitDeadlock
+    ));
+    assert!(matches!(
+        wait32(2, 0, 0).unwrap_err().kind(),
+        TrapKind::UnalignedAtomic
+    ));
+    assert!(matches!(
+        invoke(
+            "wait64",
+            &[
+                RuntimeValue::I32(4),
+                RuntimeValue::I64(0),
+                RuntimeValue::I64(0)
+            ]
+        )
+        .unwrap_err()
+        .kind(),
+        TrapKind::UnalignedAtomic
+    ));
+    assert!(matches!(
+        wait32(65536, 0, 0).unwrap_err().kind(),
+        TrapKind::MemoryAccessOutOfBounds { .. }
+    ));
+    assert!(matches!(
+        invoke("notify", &[RuntimeValue::I32(65536), RuntimeValue::I32(1)])
+            .unwrap_err()
+            .kind(),
+        TrapKind::MemoryAccessOutOfBounds { .. }
+    ));
+
+    // Waiting on a memory that isn't shared traps, notifying doesn't.
+    let unshared = instantiate(unshared);
+    assert!(!unshared.memory_by_index(0).unwrap().is_shared());
+    assert!(matches!(
+        invoke_on(&unshared, "wait32", &wait32_args(0, 1, 0))
+            .unwrap_err()
+            .kind(),
+        TrapKind::AtomicWaitOnUnsharedMemory
+    ));
+    assert_eq!(
+        invoke_on(
+            &unshared,
+            "notify",
+            &[RuntimeValue::I32(0), RuntimeValue::I32(1)]
+        )
+        .unwrap(),
+        Some(RuntimeValue::I32(0))
+    );
+
+    // Atomic accesses must specify the natural alignment.
+    let misaligned = ModuleBuilder::new()
+        .with_memory(1, None)
+        .with_func(
+            &Signature::new(&[ValueType::I32, ValueType::I32][..], Some(ValueType::I32)),
+            &[],
+            vec![
+                Instruction::GetLocal(0),
+                Instruction::GetLocal(1),
+                Instruction::Atomics(AtomicsInstruction::AtomicWake(memarg(0))),
+            ],
+        )
+        .build();
+    assert!(Module::from_parity_wasm_module(misaligned).is_err());
+}
+
+#[test]
+fn value_stack_limit() {
+    let module = parse_wat(
+        r#"
+(module
+  (func (export "deep") (param i32) (result i32)
+    get_local 0
+    get_local 0
+    get_local 0
+    get_local 0
+    get_local 0
+    get_local 0
+    get_local 0
+    get_local 0
+    i32.add
+    i32.add
+    i32.add
+    i32.add
+    i32.add
+    i32.add
+    i32.add
+  )
+)
+"#,
+    );
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+
+    let value_size = ::core::mem::size_of::<u64>();
+    let invoke = |values| {
+        let mut stack_recycler =
+            StackRecycler::with_limits(values * value_size, crate::DEFAULT_CALL_STACK_LIMIT);
+        instance.invoke_export_with_stack(
+            "deep",
+            &[RuntimeValue::I32(1)],
+            &mut NopExternals,
+            &mut stack_recycler,
+        )
+    };
+
+    // The argument and up to 7 operands at once, since the last two loads are
+    // fused with the addition.
+    assert_eq!(
+        invoke(16).expect("Failed to execute function"),
+        Some(RuntimeValue::I32(8))
+    );
+    match invoke(4) {
+        Err(Error::Trap(trap)) => assert!(matches!(trap.kind(), TrapKind::StackOverflow)),
+        result => panic!("Expected stack overflow, got {:?}", result),
+    }
+}
+
+#[test]
+fn resource_exhaustion_trap_kinds() {
+    use crate::MemoryAllocator;
+    use core::ptr::NonNull;
+    use std::alloc::{alloc_zeroed, dealloc, Layout};
+
+    /// Allocates buffers from the global allocator, but can't grow them.
+    struct NoGrow;
+
+    unsafe impl MemoryAllocator for NoGrow {
+        fn alloc(&self, len: usize) -> Option<NonNull<u8>> {
+            let layout = Layout::from_size_align(len, 1).ok()?;
+            NonNull::new(unsafe { alloc_zeroed(layout) })
+        }
+
+        unsafe fn grow(&self, _: NonNull<u8>, _: usize, _: usize) -> Option<NonNull<u8>> {
+            None
+        }
+
+        unsafe fn free(&self, ptr: NonNull<u8>, len: usize) {
+            dealloc(ptr.as_ptr(), Layout::from_size_align_unchecked(len, 1));
+        }
+    }
+
+    let module = parse_wat(
+        r#"
+        (module
+            (import "env" "f" (func $f))
+            (import "env" "mem" (memory 1 4))
+            (func $recurse (export "recurse") (param i32) (result i32)
+                (call $recurse (i32.add (get_local 0) (i32.const 1))))
+            (func (export "spin") (loop (br 0)))
+            (func (export "host") (call $f))
+            (func (export "grow") (param i32) (result i32)
+                (grow_memory (get_local 0))))
+        "#,
+    );
+    let memory = MemoryInstance::alloc_with_allocator(Pages(1), Some(Pages(4)), NoGrow).unwrap();
+    memory.set_trap_on_grow_failure(true);
+    let mut imports = ImportsBuilder::new();
+    imports.push_extern(
+        "env",
+        "f",
+        ExternVal::Func(FuncInstance::alloc_host(Signature::new(&[][..], None), 0)),
+    );
+    imports.push_extern("env", "mem", ExternVal::Memory(memory.clone()));
+    let instance = ModuleInstance::new(&module, &imports)
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+    let func = |name: &str| {
+        instance
+            .export_by_name(name)
+            .and_then(|export| export.as_func().cloned())
+            .expect("function should be exported")
+    };
+    let with_stack_limits = |value_stack_limit, call_stack_limit| {
+        let mut stack_recycler = StackRecycler::with_limits(value_stack_limit, call_stack_limit);
+        FuncInstance::invoke_with_stack(
+            &func("recurse"),
+            &[RuntimeValue::I32(0)],
+            &mut NopExternals,
+            &mut stack_recycler,
+        )
+    };
+    let grow = |pages| FuncInstance::invoke(&func("grow"), &[pages], &mut NopExternals);
+
+    let cases = vec![
+        (
+            "value stack",
+            with_stack_limits(1024, crate::DEFAULT_CALL_STACK_LIMIT),
+            TrapCode::StackOverflow,
+        ),
+        (
+            "call depth",
+            with_stack_limits(crate::DEFAULT_VALUE_STACK_LIMIT, 16),
+            TrapCode::CallDepthExceeded,
+        ),
+        (
+            "fuel",
+            FuncInstance::invoke_with_fuel(&func("spin"), &[], &mut NopExternals, &mut 1000),
+            TrapCode::OutOfFuel,
+        ),
+        (
+            "host calls",
+            FuncInstance::invoke_with_host_call_limit(&func("host"), &[], &mut NopExternals, 0),
+            TrapCode::HostCallLimitExceeded,
+        ),
+        (
+            "memory maximum",
+            grow(RuntimeValue::I32(4)),
+            TrapCode::MemoryGrowFailed,
+        ),
+        (
+            "memory allocation",
+            grow(RuntimeValue::I32(1)),
+            TrapCode::MemoryAllocationFailed,
+        ),
+    ];
+    for (condition, result, expected) in cases {
+        match result {
+            Err(trap) => assert_eq!(trap.kind().code(), expected, "{}", condition),
+            Ok(result) => panic!("{}: expected a trap, got {:?}", condition, result),
+        }
+    }
+
+    // The host sees the allocation failure as well.
+    match memory.grow(Pages(1)) {
+        Err(Error::MemoryAllocationFailed { requested_pages }) => {
+            assert_eq!(requested_pages, Pages(2))
+        }
+        result => panic!("unexpected result: {:?}", result),
+    }
+    assert_eq!(memory.current_size(), Pages(1));
+}
+
+#[test]
+fn function_body_bytes() {
+    let wasm_binary = wabt::wat2wasm(
+        r#"
+(module
+  (import "env" "f" (func))
+  (func (param i32) (result i32)
+    (local i64)
+    get_local 0
+  )
+)
+"#,
+    )
+    .expect("Failed to parse wat source");
+    let module = Module::from_buffer(&wasm_binary).expect("Failed to load module");
+
+    // The imported function doesn't have a body.
+    assert_eq!(module.function_body_bytes(0), None);
+    // One i64 local, then `get_local 0` and `end`.
+    let body = module
+        .function_body_bytes(1)
+        .expect("Function should have a body");
+    assert_eq!(body, [0x01, 0x01, 0x7e, 0x20, 0x00, 0x0b]);
+    assert!(wasm_binary
+        .windows(body.len())
+        .any(|window| window == &body[..]));
+    assert_eq!(module.function_body_bytes(2), None);
+}
+
+#[test]
+fn exports_and_imports_in_section_order() {
+    let module = parse_wat(
+        r#"
+(module
+  (import "env" "z" (func))
+  (import "env" "a" (global i32))
+  (import "b" "m" (memory 1))
+  (func (export "zz"))
+  (global (export "aa") i32 (i32.const 0))
+  (export "mm" (memory 0))
+  (export "b" (func 1))
+)
+"#,
+    );
+
+    let exports: Vec<_> = module.exports().map(|export| export.field()).collect();
+    assert_eq!(exports, ["zz", "aa", "mm", "b"]);
+    let imports: Vec<_> = module
+        .imports()
+        .map(|import| (import.module(), import.field()))
+        .collect();
+    assert_eq!(imports, [("env", "z"), ("env", "a"), ("b", "m")]);
+}
+
+#[test]
+fn store_links_instances() {
+    use crate::Store;
+
+    let mut store = Store::new();
+    let memory = store.add_memory(MemoryInstance::alloc(Pages(1), None).unwrap());
+    store.define(
+        "env",
+        "memory",
+        ExternVal::Memory(store.memory(memory).unwrap().clone()),
+    );
+
+    let writer = store
+        .instantiate(
+            &parse_wat(
+                r#"
+(module
+  (import "env" "memory" (memory 1))
+  (func (export "write") (param i32 i32)
+    (i32.store (get_local 0) (get_local 1))
+  )
+)
+"#,
+            ),
+            &mut NopExternals,
+        )
+        .expect("Failed to instantiate module");
+    store.register("writer", writer);
+
+    let app = parse_wat(
+        r#"
+(module
+  (import "writer" "write" (func $write (param i32 i32)))
+  (import "env" "memory" (memory 1))
+  (func (export "run") (result i32)
+    (call $write (i32.const 8) (i32.const 42))
+    (i32.load (i32.const 8))
+  )
+)
+"#,
+    );
+    let app = store
+        .instantiate(&app, &mut NopExternals)
+        .expect("Failed to instantiate module");
+
+    assert_eq!(
+        store
+            .instance(app)
+            .unwrap()
+            .invoke_export("run", &[], &mut NopExternals)
+            .expect("Failed to invoke 'run' function"),
+        Some(RuntimeValue::I32(42))
+    );
+    assert_eq!(
+        store.memory(memory).unwrap().get_value::<i32>(8).unwrap(),
+        42
+    );
+
+    // Unresolved imports fail the instantiation.
+    let unresolved = parse_wat(r#"(module (import "missing" "f" (func)))"#);
+    assert!(store.instantiate(&unresolved, &mut NopExternals).is_err());
+}
+
+#[test]
+fn instance_as_import_resolver() {
+    let provider = ModuleInstance::new(
+        &parse_wat(
+            r#"
+(module
+  (global (export "counter") (mut i32) (i32.const 0))
+  (table (export "table") 1 anyfunc)
+  (func $inc (export "inc")
+    (set_global 0 (i32.add (get_global 0) (i32.const 1)))
+  )
+  (elem (i32.const 0) $inc)
+)
+"#,
+        ),
+        &ImportsBuilder::default(),
+    )
+    .expect("Failed to instantiate module")
+    .assert_no_start();
+    let imports = ImportsBuilder::new().with_resolver("provider", provider.as_import_resolver());
+
+    let consumer = parse_wat(
+        r#"
+(module
+  (import "provider" "counter" (global (mut i32)))
+  (import "provider" "table" (table 1 anyfunc))
+  (import "provider" "inc" (func $inc))
+  (type $void (func))
+  (func (export "run") (result i32)
+    (call $inc)
+    (call_indirect (type $void) (i32.const 0))
+    (get_global 0)
+  )
+)
+"#,
+    );
+    let consumer = ModuleInstance::new(&consumer, &imports)
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+    assert_eq!(
+        consumer
+            .invoke_export("run", &[], &mut NopExternals)
+            .expect("Failed to invoke 'run' function"),
+        Some(RuntimeValue::I32(2))
+    );
+    // The global is shared with the provider.
+    assert_eq!(
+        provider
+            .export_by_name("counter")
+            .unwrap()
+            .as_global()
+            .unwrap()
+            .get(),
+        RuntimeValue::I32(2)
+    );
+
+    // Exports are matched against the imported types at instantiation.
+    for wat in &[
+        r#"(module (import "provider" "inc" (func (param i32))))"#,
+        r#"(module (import "provider" "counter" (func)))"#,
+        r#"(module (import "provider" "missing" (func)))"#,
+    ] {
+        assert!(matches!(
+            ModuleInstance::new(&parse_wat(wat), &imports),
+            Err(Error::Instantiation(_))
+        ));
+    }
+}
+
+#[test]
+fn mutually_recursive_instances() {
+    // `even` and `odd` call each other across the instances, and the start
+    // function of `a` counts the calls made while linking.
+    let a = parse_wat(
+        r#"
+(module
+  (import "b" "odd" (func $odd (param i32) (result i32)))
+  (global $calls (export "calls") (mut i32) (i32.const 0))
+  (func $even (export "even") (param i32) (result i32)
+    (set_global $calls (i32.add (get_global $calls) (i32.const 1)))
+    (if (result i32) (i32.eqz (get_local 0))
+      (then (i32.const 1))
+      (else (call $odd (i32.sub (get_local 0) (i32.const 1))))
+    )
+  )
+  (func $start
+    (drop (call $even (i32.const 3)))
+  )
+  (start $start)
+)
+"#,
+    );
+    let b = parse_wat(
+        r#"
+(module
+  (import "a" "even" (func $even (param i32) (result i32)))
+  (func (export "odd") (param i32) (result i32)
+    (if (result i32) (i32.eqz (get_local 0))
+      (then (i32.const 0))
+      (else (call $even (i32.sub (get_local 0) (i32.const 1))))
+    )
+  )
+)
+"#,
+    );
+    let signature = Signature::new(&[ValueType::I32][..], Some(ValueType::I32));
+
+    let odd = FuncInstance::alloc_late(signature.clone());
+    let imports = ImportsBuilder::new().with_extern("b", "odd", ExternVal::Func(odd.clone()));
+    let not_started = ModuleInstance::new(&a, &imports).expect("Failed to instantiate module");
+    let a = not_started.not_started_instance().clone();
+
+    // Calls of the late function before it is bound trap.
+    let trap = FuncInstance::invoke(&odd, &[RuntimeValue::I32(1)], &mut NopExternals)
+        .expect_err("unbound function was called");
+    assert_eq!(trap.kind().code(), TrapCode::UnboundFunction);
+    assert!(matches!(
+        a.invoke_export("even", &[RuntimeValue::I32(1)], &mut NopExternals),
+        Err(Error::Trap(ref trap)) if trap.kind().code() == TrapCode::UnboundFunction
+    ));
+
+    let b = ModuleInstance::new(&b, &ImportsBuilder::new().with_resolver("a", &a))
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+    let b_odd = b.export_by_name("odd").unwrap().as_func().unwrap().clone();
+
+    // The target must have the same signature, and binding must not form a cycle.
+    let other = FuncInstance::alloc_late(Signature::new(&[][..], None));
+    assert!(odd.bind(&other).is_err());
+    assert!(odd.bind(&odd).is_err());
+    let alias = FuncInstance::alloc_late(signature);
+    alias.bind(&odd).unwrap();
+    assert!(odd.bind(&alias).is_err());
+    assert!(b_odd.bind(&odd).is_err());
+
+    odd.bind(&b_odd).unwrap();
+    assert!(odd.bind(&b_odd).is_err());
+
+    let a = not_started
+        .run_start(&mut NopExternals)
+        .expect("Failed to run the start function");
+    let calls = a
+        .export_by_name("calls")
+        .unwrap()
+        .as_global()
+        .unwrap()
+        .clone();
+    // The start function called `even` with 3 and 1, the earlier trapping call with 1.
+    assert_eq!(calls.get(), RuntimeValue::I32(3));
+
+    for (n, even) in [(0, 1), (7, 0), (10, 1)].iter() {
+        assert_eq!(
+            a.invoke_export("even", &[RuntimeValue::I32(*n)], &mut NopExternals)
+                .expect("Failed to invoke 'even' function"),
+            Some(RuntimeValue::I32(*even))
+        );
+    }
+    // Calls through a late function bound to another late function also work.
+    assert_eq!(
+        FuncInstance::invoke(&alias, &[RuntimeValue::I32(3)], &mut NopExternals).unwrap(),
+        Some(RuntimeValue::I32(1))
+    );
+}
+
+#[test]
+fn late_bound_functions_report_their_index() {
+    use crate::{Breakpoints, DebugFrame, Trap};
+
+    let a = parse_wat(
+        r#"
+(module
+  (import "b" "abort" (func $abort))
+  (func (export "run") (call $abort))
+)
+"#,
+    );
+    let b = parse_wat(
+        r#"
+(module
+  (func $pad)
+  (func (export "abort") (unreachable))
+)
+"#,
+    );
+    let abort = FuncInstance::alloc_late(Signature::new(&[][..], None));
+    let imports = ImportsBuilder::new().with_extern("b", "abort", ExternVal::Func(abort.clone()));
+    let a = ModuleInstance::new(&a, &imports)
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+    let b = ModuleInstance::new(&b, &ImportsBuilder::default())
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+    abort
+        .bind(b.export_by_name("abort").unwrap().as_func().unwrap())
+        .unwrap();
+
+    // The trap names the function of `b` that was bound, not the late function.
+    match a.invoke_export("run", &[], &mut NopExternals) {
+        Err(Error::Trap(trap)) => assert_eq!(trap.unreachable_func_index(), Some(1)),
+        result => panic!("Expected a trap, got {:?}", result),
+    }
+
+    // Breakpoints in the bound function are hit.
+    let mut breakpoints = Breakpoints::new();
+    breakpoints.add(1, 0);
+    let mut hits = 0;
+    let mut handler = |frame: &mut DebugFrame| -> Result<(), Trap> {
+        assert_eq!(frame.func_index(), 1);
+        hits += 1;
+        Ok(())
+    };
+    let trap = FuncInstance::invoke_with_breakpoints(
+        &abort,
+        &[],
+        &mut NopExternals,
+        &breakpoints,
+        &mut handler,
+    )
+    .expect_err("unreachable was executed");
+    assert_eq!(trap.unreachable_func_index(), Some(1));
+    assert_eq!(hits, 1);
+}
+
+#[test]
+fn segments_are_checked_before_writing() {
+    let memory = MemoryInstance::alloc(Pages(1), None).unwrap();
+    let table = TableInstance::alloc(2, None).unwrap();
+    let imports = ImportsBuilder::new()
+        .with_extern("env", "memory", ExternVal::Memory(memory.clone()))
+        .with_extern("env", "table", ExternVal::Table(table.clone()));
+    let instantiate = |wat: &str| ModuleInstance::new(&parse_wat(wat), &imports).map(|_| ());
+
+    // The second data segment ends one byte past the end of the memory.
+    let result = instantiate(
+        r#"
+(module
+  (import "env" "memory" (memory 1))
+  (import "env" "table" (table 2 anyfunc))
+  (func $f)
+  (elem (i32.const 0) $f $f)
+  (data (i32.const 0) "\01\02\03\04")
+  (data (i32.const 65534) "\05\06\07")
+)
+"#,
+    );
+    assert!(matches!(
+        result,
+        Err(Error::SegmentDoesNotFit {
+            kind: SegmentKind::Data,
+            index: 1,
+            offset: 65534,
+            len: 3,
+            size: 65536,
+        })
+    ));
+    // Neither the element segment nor the data segments were written.
+    assert_eq!(memory.get(0, 4).unwrap(), [0; 4]);
+    assert_eq!(memory.get(65534, 2).unwrap(), [0; 2]);
+    assert!(table.get(0).unwrap().is_none());
+    assert!(table.get(1).unwrap().is_none());
+
+    let result = instantiate(
+        r#"
+(module
+  (import "env" "memory" (memory 1))
+  (import "env" "table" (table 2 anyfunc))
+  (func $f)
+  (elem (i32.const 0) $f)
+  (elem (i32.const 1) $f $f)
+  (data (i32.const 0) "\01")
+)
+"#,
+    );
+    assert!(matches!(
+        result,
+        Err(Error::SegmentDoesNotFit {
+            kind: SegmentKind::Element,
+            index: 1,
+            offset: 1,
+            len: 2,
+            size: 2,
+        })
+    ));
+    assert_eq!(memory.get(0, 1).unwrap(), [0]);
+    assert!(table.get(0).unwrap().is_none());
+
+    // Segments that fit exactly are written.
+    instantiate(
+        r#"
+(module
+  (import "env" "memory" (memory 1))
+  (data (i32.const 0) "\01\02\03\04")
+  (data (i32.const 65534) "\05\06")
+)
+"#,
+    )
+    .expect("Failed to instantiate module");
+    assert_eq!(memory.get(0, 4).unwrap(), [1, 2, 3, 4]);
+    assert_eq!(memory.get(65534, 2).unwrap(), [5, 6]);
+}
+
+#[test]
+fn start_function_runs_out_of_fuel() {
+    let malicious = parse_wat(
+        r#"
+(module
+  (func $start
+    (loop (br 0))
+  )
+  (start $start)
+)
+"#,
+    );
+    let mut fuel = 1000;
+    let result = ModuleInstance::new(&malicious, &ImportsBuilder::default())
+        .expect("Failed to instantiate module")
+        .run_start_with_fuel(&mut NopExternals, &mut fuel);
+    match result {
+        Err(trap) => assert!(matches!(trap.kind(), TrapKind::OutOfFuel)),
+        Ok(_) => panic!("Start function should run out of fuel"),
+    }
+    assert_eq!(fuel, 0);
+
+    let benign = parse_wat(
+        r#"
+(module
+  (global $g (mut i32) (i32.const 0))
+  (func $start
+    (set_global $g (i32.const 1))
+  )
+  (func (export "get") (result i32) (get_global $g))
+  (start $start)
+)
+"#,
+    );
+    let mut fuel = 1000;
+    let instance = ModuleInstance::new(&benign, &ImportsBuilder::default())
+        .expect("Failed to instantiate module")
+        .run_start_with_fuel(&mut NopExternals, &mut fuel)
+        .expect("Start function should succeed");
+    assert!(fuel < 1000);
+    assert_eq!(
+        instance
+            .invoke_export("get", &[], &mut NopExternals)
+            .expect("Failed to invoke 'get' function"),
+        Some(RuntimeValue::I32(1))
+    );
+}
+
+#[test]
+fn reference_types_rejected() {
+    const HEADER: &[u8] = &[0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00];
+    let cases: &[(&[u8], &str)] = &[
+        // (type (func (param externref)))
+        (&[0x01, 0x05, 0x01, 0x60, 0x01, 0x6f, 0x00], "externref"),
+        // (table 0 externref)
+        (&[0x04, 0x04, 0x01, 0x6f, 0x00, 0x00], "externref"),
+        // (import "env" "t" (table 0 externref))
+        (
+            &[
+                0x02, 0x0b, 0x01, 0x03, 0x65, 0x6e, 0x76, 0x01, 0x74, 0x01, 0x6f, 0x00, 0x00,
+            ],
+            "externref",
+        ),
+        // (func (drop (ref.null func)))
+        (
+            &[
+                0x01, 0x04, 0x01, 0x60, 0x00, 0x00, 0x03, 0x02, 0x01, 0x00, 0x0a, 0x07, 0x01, 0x05,
+                0x00, 0xd0, 0x70, 0x1a, 0x0b,
+            ],
+            "ref.null",
+        ),
+    ];
+    for &(sections, what) in cases {
+        let mut wasm = HEADER.to_vec();
+        wasm.extend_from_slice(sections);
+        match Module::from_buffer(&wasm) {
+            Err(Error::Validation(msg)) => assert_eq!(
+                msg,
+                std::format!("reference types not supported: module uses `{}`", what)
+            ),
+            result => panic!("Expected a validation error, got {:?}", result.err()),
+        }
+    }
+}
+
+#[test]
+fn instruction_count_is_pinned() {
+    // The number of executed instructions is part of the interface: receipts of
+    // executions are compared across platforms and versions of the same `ISA_VERSION`.
+    let module = parse_wat(
+        r#"
+(module
+  (func $square (param i32) (result i32)
+    (i32.mul (get_local 0) (get_local 0))
+  )
+  (func (export "sum_of_squares") (param $n i32) (result i32)
+    (local $sum i32)
+    (block $done
+      (loop $continue
+        (br_if $done (i32.eqz (get_local $n)))
+        (set_local $sum (i32.add (get_local $sum) (call $square (get_local $n))))
+        (set_local $n (i32.sub (get_local $n) (i32.const 1)))
+        (br $continue)
+      )
+    )
+    (get_local $sum)
+  )
+
+  (func (export "float_branch") (param f32) (result i32)
+    (if (result i32) (f32.lt (get_local 0) (f32.const 0.5))
+      (then (i32.const 1))
+      (else (i32.const 2))
+    )
+  )
+)
+"#,
+    );
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+    let instructions = |name: &str, arg: RuntimeValue| {
+        let (result, stats) = instance.invoke_export_with_stats(name, &[arg], &mut NopExternals);
+        result.expect("Failed to invoke function");
+        stats.instructions
+    };
+
+    let counts: Vec<u64> = [0, 1, 10]
+        .iter()
+        .map(|&n| instructions("sum_of_squares", RuntimeValue::I32(n)))
+        .collect();
+    assert_eq!(counts, [5, 20, 155]);
+    // Repeated executions don't differ.
+    assert_eq!(instructions("sum_of_squares", RuntimeValue::I32(10)), 155);
+
+    // Float results decide the count when the code branches on them: the `then` branch
+    // has to jump over the `else` branch.
+    assert_eq!(
+        instructions("float_branch", RuntimeValue::F32(0.25.into())),
+        7
+    );
+    assert_eq!(
+        instructions("float_branch", RuntimeValue::F32(1.0.into())),
+        6
+    );
+}
+
+#[test]
+fn unsupported_proposals_rejected() {
+    const HEADER: &[u8] = &[0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00];
+    // (type (func)) (func (type 0) ...)
+    const FUNC: &[u8] = &[0x01, 0x04, 0x01, 0x60, 0x00, 0x00, 0x03, 0x02, 0x01, 0x00];
+    let cases: &[(&[u8], &[u8], &str)] = &[
+        // (type (func (param v128)))
+        (
+            &[],
+            &[0x01, 0x05, 0x01, 0x60, 0x01, 0x7b, 0x00],
+            "SIMD not supported: module uses `v128`",
+        ),
+        // (type (func (result i32 i32)))
+        (
+            &[],
+            &[0x01, 0x06, 0x01, 0x60, 0x00, 0x02, 0x7f, 0x7f],
+            "multi-value not supported: module uses `functions with more than one result`",
+        ),
+        // (drop (i32.extend8_s (i32.const 0)))
+        (
+            FUNC,
+            &[0x0a, 0x08, 0x01, 0x06, 0x00, 0x41, 0x00, 0xc0, 0x1a, 0x0b],
+            "sign-extension operators not supported: module uses `i32.extend8_s`",
+        ),
+        // (drop (i32.trunc_sat_f32_s (f32.const 0)))
+        (
+            FUNC,
+            &[
+                0x0a, 0x0c, 0x01, 0x0a, 0x00, 0x43, 0x00, 0x00, 0x00, 0x00, 0xfc, 0x00, 0x1a, 0x0b,
+            ],
+            "bulk memory and non-trapping float-to-int conversions not supported: \
+             module uses `0xfc prefixed instructions`",
+        ),
+        // (return_call 0)
+        (
+            FUNC,
+            &[0x0a, 0x06, 0x01, 0x04, 0x00, 0x12, 0x00, 0x0b],
+            "tail calls not supported: module uses `return_call`",
+        ),
+        // (func (param externref externref i32)
+        //   (drop (select (local.get 0) (local.get 1) (local.get 2))))
+        //
+        // Untyped `select` is invalid for reference operands, but such a module doesn't get
+        // that far, as reference values aren't supported at all.
+        (
+            &[
+                0x01, 0x07, 0x01, 0x60, 0x03, 0x6f, 0x6f, 0x7f, 0x00, 0x03, 0x02, 0x01, 0x00,
+            ],
+            &[
+                0x0a, 0x0c, 0x01, 0x0a, 0x00, 0x20, 0x00, 0x20, 0x01, 0x20, 0x02, 0x1b, 0x1a, 0x0b,
+            ],
+            "reference types not supported: module uses `externref`",
+        ),
+        // (func (param externref) (drop (local.get 0)))
+        (
+            &[
+                0x01, 0x05, 0x01, 0x60, 0x01, 0x6f, 0x00, 0x03, 0x02, 0x01, 0x00,
+            ],
+            &[0x0a, 0x07, 0x01, 0x05, 0x00, 0x20, 0x00, 0x1a, 0x0b],
+            "reference types not supported: module uses `externref`",
+        ),
+        // (table 0 externref) (func (call_indirect (type 0) (i32.const 0)))
+        //
+        // `call_indirect` needs a `funcref` table, but the table itself is rejected first.
+        // As every table holds functions, an element of the wrong type can't be called.
+        (
+            &[
+                0x01, 0x04, 0x01, 0x60, 0x00, 0x00, 0x03, 0x02, 0x01, 0x00, 0x04, 0x04, 0x01, 0x6f,
+                0x00, 0x00,
+            ],
+            &[
+                0x0a, 0x09, 0x01, 0x07, 0x00, 0x41, 0x00, 0x11, 0x00, 0x00, 0x0b,
+            ],
+            "reference types not supported: module uses `externref`",
+        ),
+    ];
+    for &(prefix, sections, expected) in cases {
+        let mut wasm = HEADER.to_vec();
+        wasm.extend_from_slice(prefix);
+        wasm.extend_from_slice(sections);
+        let error = Module::validate_buffer(&wasm).expect_err("module was accepted");
+        assert_eq!(error.kind(), crate::ValidationErrorKind::UnsupportedFeature);
+        assert_eq!(error.to_string(), expected);
+    }
+}
+
+#[test]
+fn out_of_range_type_indices_rejected() {
+    const HEADER: &[u8] = &[0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00];
+    // (type (func))
+    const TYPES: &[u8] = &[0x01, 0x04, 0x01, 0x60, 0x00, 0x00];
+    let cases: &[(&[u8], &str)] = &[
+        // (func (type 5))
+        (
+            &[0x03, 0x02, 0x01, 0x05, 0x0a, 0x04, 0x01, 0x02, 0x00, 0x0b],
+            "Type at index 5 of function #0 doesn't exists, the module declares 1 types",
+        ),
+        // (import "env" "f" (func (type 5)))
+        (
+            &[0x02, 0x09, 0x01, 0x03, 0x65, 0x6e, 0x76, 0x01, 0x66, 0x00, 0x05],
+            "Type at index 5 of imported function env.f doesn't exists, the module declares 1 types",
+        ),
+    ];
+    for &(sections, expected) in cases {
+        let mut wasm = HEADER.to_vec();
+        wasm.extend_from_slice(TYPES);
+        wasm.extend_from_slice(sections);
+        let error = Module::validate_buffer(&wasm).expect_err("module was accepted");
+        assert_eq!(error.kind(), crate::ValidationErrorKind::UndeclaredIndex);
+        assert_eq!(error.to_string(), expected);
+        assert!(Module::from_buffer(&wasm).is_err());
+    }
+}
+
+#[test]
+fn malformed_start_sections_rejected() {
+    use crate::ValidationErrorKind;
+
+    const HEADER: &[u8] = &[0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00];
+    // (type (func)) (type (func (param i32))) (func (type 0)) (func (type 1))
+    const FUNCS: &[u8] = &[
+        0x01, 0x08, 0x02, 0x60, 0x00, 0x00, 0x60, 0x01, 0x7f, 0x00, 0x03, 0x03, 0x02, 0x00, 0x01,
+    ];
+    const CODE: &[u8] = &[0x0a, 0x07, 0x02, 0x02, 0x00, 0x0b, 0x02, 0x00, 0x0b];
+    let cases: &[(&[u8], ValidationErrorKind, &str)] = &[
+        // (start 5)
+        (
+            &[0x08, 0x01, 0x05],
+            ValidationErrorKind::UndeclaredIndex,
+            "Start function at index 5 doesn't exists, the module has 2 functions",
+        ),
+        // (start 4294967295)
+        (
+            &[0x08, 0x05, 0xff, 0xff, 0xff, 0xff, 0x0f],
+            ValidationErrorKind::UndeclaredIndex,
+            "Start function at index 4294967295 doesn't exists, the module has 2 functions",
+        ),
+        // (start 1), which takes a parameter.
+        (
+            &[0x08, 0x01, 0x01],
+            ValidationErrorKind::TypeMismatch,
+            "Start function #1 has type [I32] -> [], but start functions must have type [] -> []",
+        ),
+        // (start 0) (start 0)
+        (
+            &[0x08, 0x01, 0x00, 0x08, 0x01, 0x00],
+            ValidationErrorKind::MalformedSection,
+            "Duplicated sections (8)",
+        ),
+    ];
+    for &(start, kind, expected) in cases {
+        let mut wasm = HEADER.to_vec();
+        wasm.extend_from_slice(FUNCS);
+        wasm.extend_from_slice(start);
+        wasm.extend_from_slice(CODE);
+        let error = Module::validate_buffer(&wasm).expect_err("module was accepted");
+        assert_eq!(error.kind(), kind);
+        assert_eq!(error.to_string(), expected);
+        assert!(Module::from_buffer(&wasm).is_err());
+    }
+}
+
+#[test]
+fn producers_section() {
+    use crate::ProducerValue;
+    use parity_wasm::elements::{CustomSection, Section};
+
+    let module = |payload: &[u8]| {
+        let mut module = parity_wasm::builder::module().build();
+        module
+            .sections_mut()
+            .push(Section::Custom(CustomSection::new(
+                "producers".into(),
+                payload.to_vec(),
+            )));
+        Module::from_parity_wasm_module(module).expect("custom sections aren't validated")
+    };
+
+    // language: Rust; processed-by: rustc 1.70.0, wasm-opt 110
+    let payload = b"\x02\x08language\x01\x04Rust\x00\
+                    \x0cprocessed-by\x02\x05rustc\x061.70.0\x08wasm-opt\x03110";
+    let with_producers = module(payload);
+    let producers = with_producers.producers().unwrap();
+    let value = |name: &str, version: &str| ProducerValue {
+        name: name.into(),
+        version: version.into(),
+    };
+    assert_eq!(producers.field("language"), &[value("Rust", "")][..]);
+    assert_eq!(
+        producers.fields().map(|(name, _)| name).collect::<Vec<_>>(),
+        ["language", "processed-by"]
+    );
+    assert!(producers.field("sdk").is_empty());
+
+    assert!(with_producers
+        .require_producer("processed-by", &["rustc", "wasm-opt"])
+        .is_ok());
+    assert_eq!(
+        with_producers
+            .require_producer("processed-by", &["rustc"])
+            .unwrap_err()
+            .to_string(),
+        "Validation: Producer `wasm-opt` 110 of field `processed-by` is not allowed"
+    );
+    assert!(with_producers
+        .require_producer("sdk", &["emscripten"])
+        .is_err());
+
+    let without_producers =
+        Module::from_parity_wasm_module(parity_wasm::builder::module().build()).unwrap();
+    assert_eq!(without_producers.producers(), None);
+    assert!(without_producers
+        .require_producer("processed-by", &["rustc"])
+        .is_err());
+
+    // truncated, with trailing bytes, with an overlong count.
+    for payload in &[&payload[..10], b"\x00\x00", b"\xff\xff\xff\xff\x0f"] {
+        let malformed = module(payload);
+        assert_eq!(malformed.producers(), None);
+        assert_eq!(
+            malformed
+                .require_producer("processed-by", &["rustc"])
+                .unwrap_err()
+                .to_string(),
+            "Validation: Malformed producers section"
+        );
+    }
+}
+
+#[test]
+fn invoke_export_into_buffer() {
+    let module = parse_wat(
+        r#"
+        (module
+            (global $calls (export "calls") (mut i32) (i32.const 0))
+            (func (export "count") (result i32)
+                (set_global $calls (i32.add (get_global $calls) (i32.const 1)))
+                (get_global $calls))
+            (func (export "nothing")))
+        "#,
+    );
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .unwrap()
+        .assert_no_start();
+    let calls = || {
+        instance
+            .export_by_name("calls")
+            .unwrap()
+            .as_global()
+            .unwrap()
+            .get()
+    };
+
+    let mut results = [RuntimeValue::I64(7); 2];
+    let invoke = |name: &str, results: &mut [RuntimeValue]| {
+        instance.invoke_export_into(name, &[], results, &mut NopExternals)
+    };
+    assert_eq!(invoke("count", &mut results).unwrap(), 1);
+    assert_eq!(invoke("count", &mut results).unwrap(), 1);
+    assert_eq!(results, [RuntimeValue::I32(2), RuntimeValue::I64(7)]);
+    assert_eq!(invoke("nothing", &mut results).unwrap(), 0);
+    assert_eq!(invoke("nothing", &mut []).unwrap(), 0);
+
+    // A buffer that is too small fails the call before the function runs.
+    match invoke("count", &mut []) {
+        Err(Error::Function(msg)) => assert_eq!(
+            msg,
+            "Export count has 1 results, but the buffer only holds 0 values"
+        ),
+        result => panic!("unexpected result: {:?}", result),
+    }
+    assert_eq!(calls(), RuntimeValue::I32(2));
+    assert!(invoke("missing", &mut results).is_err());
+}
+
+#[test]
+fn invoke_export_coerced() {
+    use crate::Coercion;
+
+    let module = parse_wat(
+        r#"
+        (module
+            (func (export "mix") (param i32 i64 f32) (result f64)
+                (f64.add
+                    (f64.add
+                        (f64.convert_s/i32 (get_local 0))
+                        (f64.convert_s/i64 (get_local 1)))
+                    (f64.promote/f32 (get_local 2)))))
+        "#,
+    );
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .unwrap()
+        .assert_no_start();
+    let invoke = |args: &[RuntimeValue], coercion| {
+        instance.invoke_export_coerced("mix", args, coercion, &mut NopExternals)
+    };
+    let error = |args: &[RuntimeValue], coercion| match invoke(args, coercion) {
+        Err(Error::Function(msg)) => msg,
+        result => panic!("unexpected result: {:?}", result),
+    };
+
+    let exact = [
+        RuntimeValue::I64(-2),
+        RuntimeValue::I32(3),
+        RuntimeValue::F64(0.5.into()),
+    ];
+    for &coercion in &[Coercion::Lossless, Coercion::Truncate] {
+        assert_eq!(
+            invoke(&exact, coercion).unwrap(),
+            Some(RuntimeValue::F64(1.5.into()))
+        );
+    }
+
+    let lossy = [
+        RuntimeValue::I64(1 << 32),
+        RuntimeValue::I32(3),
+        RuntimeValue::F64(0.5.into()),
+    ];
+    assert_eq!(
+        error(&lossy, Coercion::Lossless),
+        "Argument 0 of export mix is i64:4294967296, which can't be coerced to I32"
+    );
+    assert_eq!(
+        invoke(&lossy, Coercion::Truncate).unwrap(),
+        Some(RuntimeValue::F64(3.5.into()))
+    );
+
+    let float_for_int = [
+        RuntimeValue::I32(1),
+        RuntimeValue::F64(2.0.into()),
+        RuntimeValue::F32(0.5.into()),
+    ];
+    assert_eq!(
+        error(&float_for_int, Coercion::Truncate),
+        "Argument 1 of export mix is f64:2, which can't be coerced to I64"
+    );
+    assert_eq!(
+        error(&exact[..2], Coercion::Lossless),
+        "Export mix takes 3 arguments, but 2 were given"
+    );
+}
+
+#[test]
+fn debug_sections() {
+    // (func), followed by custom sections.
+    let mut wasm = vec![
+        0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x01, 0x04, 0x01, 0x60, 0x00, 0x00, 0x03,
+        0x02, 0x01, 0x00, 0x0a, 0x04, 0x01, 0x02, 0x00, 0x0b,
+    ];
+    for &(name, payload) in &[
+        (".debug_info", &[1, 2, 3][..]),
+        (".debug_line", &[][..]),
+        ("producers", &[0][..]),
+    ] {
+        wasm.push(0x00);
+        wasm.push((1 + name.len() + payload.len()) as u8);
+        wasm.push(name.len() as u8);
+        wasm.extend_from_slice(name.as_bytes());
+        wasm.extend_from_slice(payload);
+    }
+
+    let parity_module = || parity_wasm::elements::deserialize_buffer(&wasm).unwrap();
+    for module in &[
+        Module::from_buffer(&wasm).unwrap(),
+        Module::from_parity_wasm_module_with_source_offsets(parity_module()).unwrap(),
+    ] {
+        assert_eq!(module.debug_section(".debug_info"), Some(&[1, 2, 3][..]));
+        assert_eq!(module.debug_section(".debug_line"), Some(&[][..]));
+        assert_eq!(module.debug_section(".debug_str"), None);
+        // Other custom sections aren't debug sections.
+        assert_eq!(module.debug_section("producers"), None);
+    }
+}
+
+#[test]
+fn module_names() {
+    // (func), followed by a `name` section with the given payload.
+    let wasm = |names: &[u8]| {
+        let mut wasm = vec![
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x01, 0x04, 0x01, 0x60, 0x00, 0x00,
+            0x03, 0x02, 0x01, 0x00, 0x0a, 0x04, 0x01, 0x02, 0x00, 0x0b,
+        ];
+        wasm.extend_from_slice(&[0x00, (5 + names.len()) as u8, 0x04]);
+        wasm.extend_from_slice(b"name");
+        wasm.extend_from_slice(names);
+        wasm
+    };
+    // The module name `app`, and the function name `fn` of function 0.
+    let module_name: &[u8] = &[0x00, 0x04, 0x03, b'a', b'p', b'p'];
+    let func_names: &[u8] = &[0x01, 0x05, 0x01, 0x00, 0x02, b'f', b'n'];
+
+    let named = wasm(&[module_name, func_names].concat());
+    let parsed_names =
+        parity_wasm::elements::deserialize_buffer::<parity_wasm::elements::Module>(&named)
+            .unwrap()
+            .parse_names()
+            .unwrap();
+    for module in &[
+        Module::from_buffer(&named).unwrap(),
+        Module::from_parity_wasm_module(parsed_names).unwrap(),
+    ] {
+        assert_eq!(module.module_name(), Some("app"));
+        let instance = ModuleInstance::new(module, &ImportsBuilder::default())
+            .expect("Failed to instantiate module")
+            .assert_no_start();
+        assert_eq!(instance.module_name(), Some("app"));
+    }
+
+    // Without a name, a malformed `name` section doesn't affect the module.
+    for names in &[
+        func_names,
+        &[][..],
+        &[0x00, 0x05, 0x03, b'a', b'p', b'p'][..],
+        &[0x00, 0x04, 0x04, b'a', b'p', b'p'][..],
+        &[0x00, 0x04, 0x03, b'a', 0xff, b'p'][..],
+    ] {
+        let module = Module::from_buffer(wasm(names)).unwrap();
+        assert_eq!(module.module_name(), None, "{:x?}", names);
+    }
+    let module = parse_wat("(module)");
+    assert_eq!(module.module_name(), None);
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+    assert_eq!(instance.module_name(), None);
+}
+
+#[test]
+fn canonicalized_modules_are_equal() {
+    use crate::canonicalize;
+    use parity_wasm::elements::{self, CustomSection, Section};
+
+    let parse = |wat: &str| -> elements::Module {
+        elements::deserialize_buffer(&wabt::wat2wasm(wat).unwrap()).unwrap()
+    };
+    let deduplicated = parse(
+        r#"
+        (module
+            (type $i (func (param i32) (result i32)))
+            (type $v (func))
+            (import "env" "f" (func (type $v)))
+            (table anyfunc (elem $double))
+            (func $double (type $i) (i32.mul (get_local 0) (i32.const 2)))
+            (func (export "call") (type $i)
+                (call_indirect (type $i) (get_local 0) (i32.const 0))))
+        "#,
+    );
+    let redundant = parse(
+        r#"
+        (module
+            (type $i (func (param i32) (result i32)))
+            (type $v (func))
+            (type $i2 (func (param i32) (result i32)))
+            (type $v2 (func))
+            (import "env" "f" (func (type $v2)))
+            (table anyfunc (elem $double))
+            (func $double (type $i) (i32.mul (get_local 0) (i32.const 2)))
+            (func (export "call") (type $i2)
+                (call_indirect (type $i2) (get_local 0) (i32.const 0))))
+        "#,
+    );
+    let serialize = |module: elements::Module| elements::serialize(module).unwrap();
+    let canonical = serialize(canonicalize(deduplicated.clone()));
+    // Nothing changes in a canonical module.
+    assert_eq!(canonical, serialize(deduplicated));
+    assert_eq!(serialize(canonicalize(redundant.clone())), canonical);
+
+    // Both versions behave the same, call_indirect compares types structurally.
+    struct HostFuncs;
+    impl ModuleImportResolver for HostFuncs {
+        fn resolve_func(&self, _field_name: &str, signature: &Signature) -> Result<FuncRef, Error> {
+            Ok(FuncInstance::alloc_host(signature.clone(), 0))
+        }
+    }
+    for module in &[redundant.clone(), canonicalize(redundant.clone())] {
+        let module = Module::from_parity_wasm_module(module.clone()).unwrap();
+        let instance = ModuleInstance::new(
+            &module,
+            &ImportsBuilder::new().with_resolver("env", &HostFuncs),
+        )
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+        assert_eq!(
+            instance
+                .invoke_export("call", &[RuntimeValue::I32(21)], &mut NopExternals)
+                .expect("Failed to invoke call"),
+            Some(RuntimeValue::I32(42))
+        );
+    }
+
+    // Custom sections move after the known sections, in their order.
+    let custom = |name: &str| Section::Custom(CustomSection::new(name.into(), vec![]));
+    let mut interleaved = redundant.clone();
+    interleaved.sections_mut().insert(0, custom("a"));
+    interleaved.sections_mut().insert(3, custom("b"));
+    let mut appended = redundant;
+    appended.sections_mut().push(custom("a"));
+    appended.sections_mut().push(custom("b"));
+    assert_eq!(
+        serialize(canonicalize(interleaved)),
+        serialize(canonicalize(appended))
+    );
+
+    // Types of invalid modules are left as is, so validation reports the original index.
+    let mut invalid = parse("(module (type (func)) (type (func)) (func (type 0)) (func (type 1)))");
+    *invalid.function_section_mut().unwrap().entries_mut()[1].type_ref_mut() = 7;
+    assert_eq!(serialize(canonicalize(invalid.clone())), serialize(invalid));
+}
+
+#[test]
+fn validator_applies_its_limits_to_every_module() {
+    use crate::{CompileLimits, Validator};
+
+    let validator = Validator::new(CompileLimits {
+        max_br_table_size: 2,
+        ..CompileLimits::default()
+    });
+    assert_eq!(validator.limits().max_br_table_size, 2);
+
+    let br_table = |targets: usize| {
+        wabt::wat2wasm(std::format!(
+            "(module (func (param i32) (block (br_table {} 0 (get_local 0)))))",
+            "0 ".repeat(targets)
+        ))
+        .expect("Failed to parse wat source")
+    };
+    for targets in 0..=2 {
+        let module = validator
+            .validate_and_compile(br_table(targets))
+            .expect("module within the limits was rejected");
+        assert!(ModuleInstance::new(&module, &ImportsBuilder::default()).is_ok());
+    }
+    let error = validator
+        .validate_and_compile(br_table(3))
+        .err()
+        .expect("module above the limits was accepted");
+    assert_eq!(
+        error.to_string(),
+        "Function 0 has a br_table with 3 targets, above the limit of 2"
+    );
+
+    // Invalid modules are rejected as by `Module::validate_buffer`, e.g. one with a
+    // function of type 0, but no type section.
+    let invalid = [
+        0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x03, 0x02, 0x01, 0x00,
+    ];
+    assert_eq!(
+        validator
+            .validate_and_compile(&invalid[..])
+            .err()
+            .expect("invalid module was accepted")
+            .kind(),
+        Module::validate_buffer(&invalid[..]).unwrap_err().kind()
+    );
+    assert!(Validator::default()
+        .validate_and_compile(br_table(3))
+        .is_ok());
+}
+
+#[test]
+fn compilation_cache() {
+    use crate::{CompilationCache, LruCompilationCache};
+    use alloc::rc::Rc;
+
+    let wasm = |export: &str| {
+        wabt::wat2wasm(std::format!(r#"(module (func (export "{}")))"#, export))
+            .expect("Failed to parse wat source")
+    };
+    let (a, b, c) = (wasm("a"), wasm("b"), wasm("c"));
+
+    let cache = LruCompilationCache::new(2);
+    let module_a = Module::from_buffer_cached(&a, &cache).unwrap();
+    assert!(Rc::ptr_eq(
+        &module_a,
+        &Module::from_buffer_cached(&a, &cache).unwrap()
+    ));
+    Module::from_buffer_cached(&b, &cache).unwrap();
+    assert_eq!(cache.len(), 2);
+
+    // `a` was used more recently than `b`, so `b` is evicted.
+    Module::from_buffer_cached(&a, &cache).unwrap();
+    Module::from_buffer_cached(&c, &cache).unwrap();
+    assert_eq!(cache.len(), 2);
+    assert!(cache.get(Module::content_hash(&a), &a).is_some());
+    assert!(cache.get(Module::content_hash(&b), &b).is_none());
+
+    // A colliding hash doesn't return a module compiled from different bytes.
+    assert!(cache.get(Module::content_hash(&a), &c).is_none());
+
+    // Invalid modules aren't cached.
+    assert!(Module::from_buffer_cached([0, 1, 2, 3], &cache).is_err());
+    assert_eq!(cache.len(), 2);
+}
+
+#[test]
+fn fused_instructions_match_unfused() {
+    // A matrix-multiply-style loop made of instruction sequences that get fused.
+    let body = [
+        "loop",
+        "get_local $i",
+        "get_local $seed",
+        "i32.mul",
+        "set_local $x",
+        "get_local $acc",
+        "get_local $x",
+        "i32.add",
+        "set_local $acc",
+        "get_local $x",
+        "i32.const -7",
+        "i32.add",
+        "get_local $acc",
+        "i32.mul",
+        "get_local $acc",
+        "i32.add",
+        "set_local $acc",
+        "get_local $i",
+        "i32.const 1",
+        "i32.add",
+        "tee_local $i",
+        "get_local $n",
+        "i32.lt_s",
+        "br_if 0",
+        "end",
+        "get_local $acc",
+        "get_local $seed",
+        "i32.add",
+    ];
+    let kernel = |separator: &str| {
+        let source = format!(
+            r#"
+(module
+  (func (export "kernel") (param $n i32) (param $seed i32) (result i32)
+    (local $i i32) (local $acc i32) (local $x i32)
+    {}
+  )
+)
+"#,
+            body.join(separator)
+        );
+        ModuleInstance::new(&parse_wat(&source), &ImportsBuilder::default())
+            .expect("Failed to instantiate module")
+            .assert_no_start()
+    };
+    let fused = kernel("\n");
+    // Ends of blocks are branch targets, which prevents fusing anything.
+    let unfused = kernel("\nblock\nend\n");
+
+    for n in 0..20 {
+        for &seed in &[0, 1, -1, 3, 0x7fff_ffff, i32::MIN, 0x1234_5678] {
+            let args = [RuntimeValue::I32(n), RuntimeValue::I32(seed)];
+            let invoke = |instance: &ModuleRef| {
+                instance
+                    .invoke_export("kernel", &args, &mut NopExternals)
+                    .expect("Failed to execute function")
+            };
+            assert_eq!(
+                invoke(&fused),
+                invoke(&unfused),
+                "n = {}, seed = {}",
+                n,
+                seed
+            );
+        }
+    }
+}
+
+#[test]
+fn nearest_rounds_ties_to_even() {
+    let module = parse_wat(
+        r#"
+(module
+  (func (export "f32") (param f32) (result f32) (f32.nearest (get_local 0)))
+  (func (export "f64") (param f64) (result f64) (f64.nearest (get_local 0)))
+)
+"#,
+    );
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+    // Results are compared by their bits, so that the sign of zeros is checked too.
+    let bits = |value: RuntimeValue| match value {
+        RuntimeValue::F32(value) => u64::from(value.to_bits()),
+        RuntimeValue::F64(value) => value.to_bits(),
+        other => panic!("Unexpected value {:?}", other),
+    };
+    let nearest = |value: RuntimeValue| {
+        let name = match value {
+            RuntimeValue::F32(_) => "f32",
+            _ => "f64",
+        };
+        instance
+            .invoke_export(name, &[value], &mut NopExternals)
+            .expect("Failed to execute function")
+            .expect("nearest returns a value")
+    };
+
+    let cases: &[(f64, f64)] = &[
+        (0.5, 0.0),
+        (1.5, 2.0),
+        (2.5, 2.0),
+        (3.5, 4.0),
+        (-0.5, -0.0),
+        (-1.5, -2.0),
+        (-2.5, -2.0),
+        (-3.5, -4.0),
+        (0.0, 0.0),
+        (-0.0, -0.0),
+        (-0.4, -0.0),
+        (2.4, 2.0),
+        (2.6, 3.0),
+        (f64::INFINITY, f64::INFINITY),
+        (f64::NEG_INFINITY, f64::NEG_INFINITY),
+    ];
+    for &(input, expected) in cases {
+        assert_eq!(
+            bits(nearest(RuntimeValue::F64(input.into()))),
+            bits(RuntimeValue::decode_f64(expected.to_bits())),
+            "f64.nearest({})",
+            input
+        );
+        assert_eq!(
+            bits(nearest(RuntimeValue::F32((input as f32).into()))),
+            bits(RuntimeValue::decode_f32((expected as f32).to_bits())),
+            "f32.nearest({})",
+            input
+        );
+    }
+
+    // Ties at the largest values that still have a fraction, and the closest value
+    // below one half, which rounding by adding one half gets wrong.
+    for &(input, expected) in &[
+        (4503599627370495.5, 4503599627370496.0),
+        (-4503599627370494.5, -4503599627370494.0),
+        (0.49999999999999994, 0.0),
+    ] {
+        assert_eq!(
+            bits(nearest(RuntimeValue::F64(f64::into(input)))),
+            bits(RuntimeValue::decode_f64(f64::to_bits(expected))),
+            "f64.nearest({})",
+            input
+        );
+    }
+    for &(input, expected) in &[
+        (8388607.5f32, 8388608.0f32),
+        (-8388606.5, -8388606.0),
+        (0.49999997, 0.0),
+    ] {
+        assert_eq!(
+            bits(nearest(RuntimeValue::F32(input.into()))),
+            bits(RuntimeValue::decode_f32(expected.to_bits())),
+            "f32.nearest({})",
+            input
+        );
+    }
+
+    match nearest(RuntimeValue::F64(f64::NAN.into())) {
+        RuntimeValue::F64(result) => assert!(result.is_nan()),
+        other => panic!("Unexpected result {:?}", other),
+    }
+    match nearest(RuntimeValue::F32(f32::NAN.into())) {
+        RuntimeValue::F32(result) => assert!(result.is_nan()),
+        other => panic!("Unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn misaligned_memory_access() {
+    // Alignment immediates are only hints, so accesses of every width must work at
+    // any address, whatever alignment they declare.
+    let loads: &[(&str, &str)] = &[
+        ("i32.load", "i32"),
+        ("i64.load", "i64"),
+        ("f32.load", "f32"),
+        ("f64.load", "f64"),
+        ("i32.load16_s", "i32"),
+        ("i32.load16_u", "i32"),
+        ("i64.load16_s", "i64"),
+        ("i64.load16_u", "i64"),
+        ("i64.load32_s", "i64"),
+        ("i64.load32_u", "i64"),
+    ];
+    let stores: &[(&str, &str)] = &[
+        ("i32.store", "i32"),
+        ("i64.store", "i64"),
+        ("f32.store", "f32"),
+        ("f64.store", "f64"),
+        ("i32.store16", "i32"),
+        ("i64.store16", "i64"),
+        ("i64.store32", "i64"),
+    ];
+    let mut wat = String::from("(module (memory (export \"mem\") 1)");
+    for &(op, ty) in loads {
+        wat.push_str(&std::format!(
+            "(func (export \"{op}\") (param i32) (result {ty}) \
+             ({op} offset=3 align=1 (get_local 0)))",
+            op = op,
+            ty = ty
+        ));
+    }
+    for &(op, ty) in stores {
+        wat.push_str(&std::format!(
+            "(func (export \"{op}\") (param i32 {ty}) \
+             ({op} offset=3 align=1 (get_local 0) (get_local 1)))",
+            op = op,
+            ty = ty
+        ));
+    }
+    wat.push(')');
+
+    let module = parse_wat(&wat);
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+    let memory = instance
+        .export_by_name("mem")
+        .and_then(|export| export.as_memory().cloned())
+        .unwrap();
+    let pattern: Vec<u8> = (0..32u8).map(|i| i.wrapping_mul(0x9d) ^ 0x5a).collect();
+    let bits = |value: RuntimeValue| match value {
+        RuntimeValue::I32(v) => u64::from(v as u32),
+        RuntimeValue::I64(v) => v as u64,
+        RuntimeValue::F32(v) => u64::from(v.to_bits()),
+        RuntimeValue::F64(v) => v.to_bits(),
+    };
+    let bytes = |start: usize| -> [u8; 8] {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(&pattern[start..start + 8]);
+        bytes
+    };
+
+    for address in 0..8u32 {
+        memory.set(0, &pattern).unwrap();
+        let start = address as usize + 3;
+        let b = bytes(start);
+        let [b0, b1, b2, b3, ..] = b;
+        let expected = |op: &str| match op {
+            "i32.load" => RuntimeValue::I32(i32::from_le_bytes([b0, b1, b2, b3])),
+            "i64.load" => RuntimeValue::I64(i64::from_le_bytes(b)),
+            "f32.load" => RuntimeValue::decode_f32(u32::from_le_bytes([b0, b1, b2, b3])),
+            "f64.load" => RuntimeValue::decode_f64(u64::from_le_bytes(b)),
+            "i32.load16_s" => RuntimeValue::I32(i16::from_le_bytes([b0, b1]).into()),
+            "i32.load16_u" => RuntimeValue::I32(u16::from_le_bytes([b0, b1]).into()),
+            "i64.load16_s" => RuntimeValue::I64(i16::from_le_bytes([b0, b1]).into()),
+            "i64.load16_u" => RuntimeValue::I64(u16::from_le_bytes([b0, b1]).into()),
+            "i64.load32_s" => RuntimeValue::I64(i32::from_le_bytes([b0, b1, b2, b3]).into()),
+            "i64.load32_u" => RuntimeValue::I64(u32::from_le_bytes([b0, b1, b2, b3]).into()),
+            _ => unreachable!(),
+        };
+        for &(op, _) in loads {
+            let result = instance
+                .invoke_export(op, &[RuntimeValue::I32(address as i32)], &mut NopExternals)
+                .expect("Failed to execute load");
+            // Compare floats by their bits, so that NaNs are compared too.
+            assert_eq!(
+                result.map(|value| (value.value_type(), bits(value))),
+                Some((expected(op).value_type(), bits(expected(op)))),
+                "{} at {}",
+                op,
+                start
+            );
+        }
+
+        for &(op, ty) in stores {
+            memory.set(0, &pattern).unwrap();
+            let (value, len) = match (op, ty) {
+                ("f32.store", _) => (RuntimeValue::decode_f32(0x7fc0_1234), 4),
+                ("f64.store", _) => (RuntimeValue::decode_f64(0x7ff8_0000_1234_5678), 8),
+                (_, "i32") => (
+                    RuntimeValue::I32(0x1122_3344),
+                    if op == "i32.store" { 4 } else { 2 },
+                ),
+                _ => (
+                    RuntimeValue::I64(0x1122_3344_5566_7788),
+                    match op {
+                        "i64.store" => 8,
+                        "i64.store32" => 4,
+                        _ => 2,
+                    },
+                ),
+            };
+            instance
+                .invoke_export(
+                    op,
+                    &[RuntimeValue::I32(address as i32), value],
+                    &mut NopExternals,
+                )
+                .expect("Failed to execute store");
+            let value_bytes = bits(value).to_le_bytes();
+            let mut expected = pattern.clone();
+            expected[start..start + len].copy_from_slice(&value_bytes[..len]);
+            assert_eq!(
+                memory.get(0, pattern.len()).unwrap(),
+                expected,
+                "{} at {}",
+                op,
+                start
+            );
+        }
+    }
+
+    // A misaligned access that straddles the end of the memory traps without writing.
+    memory.set(65532, &[0; 4]).unwrap();
+    let trap = instance
+        .invoke_export(
+            "i64.store",
+            &[RuntimeValue::I32(65530), RuntimeValue::I64(-1)],
+            &mut NopExternals,
+        )
+        .unwrap_err();
+    assert!(matches!(
+        trap,
+        Error::Trap(ref trap) if matches!(trap.kind(), TrapKind::MemoryAccessOutOfBounds { .. })
+    ));
+    assert_eq!(memory.get(65532, 4).unwrap(), [0; 4]);
+    assert!(instance
+        .invoke_export("i32.load", &[RuntimeValue::I32(65530)], &mut NopExternals)
+        .is_err());
+}
+
+#[test]
+fn reset_globals() {
+    let module = parse_wat(
+        r#"
+(module
+  (global $counter (export "counter") (mut i32) (i32.const 10))
+  (global $step (export "step") i32 (i32.const 3))
+  (func (export "bump") (result i32)
+    (set_global $counter (i32.add (get_global $counter) (get_global $step)))
+    (get_global $counter)
+  )
+)
+"#,
+    );
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+    let global = |name| {
+        instance
+            .export_by_name(name)
+            .and_then(|export| export.as_global().cloned())
+            .expect("global should be exported")
+    };
+    let bump = || {
+        instance
+            .invoke_export("bump", &[], &mut NopExternals)
+            .expect("Failed to execute function")
+    };
+
+    assert_eq!(bump(), Some(RuntimeValue::I32(13)));
+    assert_eq!(bump(), Some(RuntimeValue::I32(16)));
+
+    instance.reset_globals();
+    assert_eq!(global("counter").get(), RuntimeValue::I32(10));
+    assert_eq!(global("step").get(), RuntimeValue::I32(3));
+    assert_eq!(bump(), Some(RuntimeValue::I32(13)));
+}
+
+#[test]
+fn start_func() {
+    let module = parse_wat(
+        r#"
+(module
+  (global $counter (export "counter") (mut i32) (i32.const 0))
+  (func $init
+    (set_global $counter (i32.add (get_global $counter) (i32.const 1)))
+  )
+  (start $init)
+)
+"#,
+    );
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .expect("Failed to instantiate module")
+        .run_start(&mut NopExternals)
+        .expect("Failed to run start function");
+    let counter = instance
+        .export_by_name("counter")
+        .and_then(|export| export.as_global().cloned())
+        .expect("global should be exported");
+    assert_eq!(counter.get(), RuntimeValue::I32(1));
+
+    let start = instance.start_func().expect("module has a start function");
+    instance.reset_globals();
+    FuncInstance::invoke(&start, &[], &mut NopExternals).expect("Failed to run start function");
+    assert_eq!(counter.get(), RuntimeValue::I32(1));
+
+    // Running it again without a reset doesn't start from the initial state.
+    FuncInstance::invoke(&start, &[], &mut NopExternals).expect("Failed to run start function");
+    assert_eq!(counter.get(), RuntimeValue::I32(2));
+
+    let no_start = ModuleInstance::new(&parse_wat("(module)"), &ImportsBuilder::default())
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+    assert!(no_start.start_func().is_none());
+}
+
+#[test]
+fn max_stack_bytes() {
+    let module = parse_wat(
+        r#"
+(module
+  (import "env" "f" (func))
+  (func (export "deep") (param i32) (local i64)
+    get_local 0
+    get_local 0
+    get_local 0
+    i32.sub
+    i32.sub
+    drop
+  )
+)
+"#,
+    );
+    assert_eq!(module.max_stack_bytes(0), None);
+    assert_eq!(module.max_stack_bytes(2), None);
+    // The argument, the local and 3 operands.
+    let max_stack_bytes = module
+        .max_stack_bytes(1)
+        .expect("function should be defined");
+    assert_eq!(max_stack_bytes, 5 * ::core::mem::size_of::<u64>());
+
+    let mut imports = ImportsBuilder::new();
+    imports.push_extern(
+        "env",
+        "f",
+        ExternVal::Func(FuncInstance::alloc_host(Signature::new(&[][..], None), 0)),
+    );
+    let instance = ModuleInstance::new(&module, &imports)
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+    let invoke = |value_stack_bytes| {
+        let mut stack_recycler =
+            StackRecycler::with_limits(value_stack_bytes, crate::DEFAULT_CALL_STACK_LIMIT);
+        instance.invoke_export_with_stack(
+            "deep",
+            &[RuntimeValue::I32(1)],
+            &mut NopExternals,
+            &mut stack_recycler,
+        )
+    };
+    invoke(max_stack_bytes).expect("Failed to execute function");
+    match invoke(max_stack_bytes - 1) {
+        Err(Error::Trap(trap)) => assert!(matches!(trap.kind(), TrapKind::StackOverflow)),
+        result => panic!("Expected stack overflow, got {:?}", result),
+    }
+}
+
+#[test]
+fn integer_division_traps() {
+    let module = parse_wat(
+        r#"
+(module
+  (func (export "i32.div_s") (param i32 i32) (result i32) (i32.div_s (get_local 0) (get_local 1)))
+  (func (export "i32.div_u") (param i32 i32) (result i32) (i32.div_u (get_local 0) (get_local 1)))
+  (func (export "i32.rem_s") (param i32 i32) (result i32) (i32.rem_s (get_local 0) (get_local 1)))
+  (func (export "i32.rem_u") (param i32 i32) (result i32) (i32.rem_u (get_local 0) (get_local 1)))
+  (func (export "i64.div_s") (param i64 i64) (result i64) (i64.div_s (get_local 0) (get_local 1)))
+  (func (export "i64.div_u") (param i64 i64) (result i64) (i64.div_u (get_local 0) (get_local 1)))
+  (func (export "i64.rem_s") (param i64 i64) (result i64) (i64.rem_s (get_local 0) (get_local 1)))
+  (func (export "i64.rem_u") (param i64 i64) (result i64) (i64.rem_u (get_local 0) (get_local 1)))
+)
+"#,
+    );
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+    let invoke = |name: &str, left: RuntimeValue, right: RuntimeValue| {
+        instance
+            .invoke_export(name, &[left, right], &mut NopExternals)
+            .map(|result| result.expect("Function should return a value"))
+            .map_err(|err| match err {
+                Error::Trap(trap) => trap.into_kind(),
+                err => panic!("Expected trap, got {:?}", err),
+            })
+    };
+    let i32_op = |name, left: i32, right: i32| invoke(name, left.into(), right.into());
+    let i64_op = |name, left: i64, right: i64| invoke(name, left.into(), right.into());
+
+    for name in &["i32.div_s", "i32.div_u", "i32.rem_s", "i32.rem_u"] {
+        for &left in &[0, 1, -1, i32::MIN, i32::MAX] {
+            assert!(matches!(
+                i32_op(name, left, 0),
+                Err(TrapKind::DivisionByZero)
+            ));
+        }
+    }
+    for name in &["i64.div_s", "i64.div_u", "i64.rem_s", "i64.rem_u"] {
+        for &left in &[0, 1, -1, i64::MIN, i64::MAX] {
+            assert!(matches!(
+                i64_op(name, left, 0),
+                Err(TrapKind::DivisionByZero)
+            ));
+        }
+    }
+
+    // The quotient 2^(N-1) isn't representable, but the remainder 0 is.
+    assert!(matches!(
+        i32_op("i32.div_s", i32::MIN, -1),
+        Err(TrapKind::InvalidConversionToInt)
+    ));
+    assert!(matches!(
+        i64_op("i64.div_s", i64::MIN, -1),
+        Err(TrapKind::InvalidConversionToInt)
+    ));
+    assert!(matches!(
+        i32_op("i32.rem_s", i32::MIN, -1),
+        Ok(RuntimeValue::I32(0))
+    ));
+    assert!(matches!(
+        i64_op("i64.rem_s", i64::MIN, -1),
+        Ok(RuntimeValue::I64(0))
+    ));
+
+    // Unsigned operations see 2^(N-1) / (2^N - 1).
+    assert!(matches!(
+        i32_op("i32.div_u", i32::MIN, -1),
+        Ok(RuntimeValue::I32(0))
+    ));
+    assert!(matches!(
+        i32_op("i32.rem_u", i32::MIN, -1),
+        Ok(RuntimeValue::I32(i32::MIN))
+    ));
+    assert!(matches!(
+        i64_op("i64.div_u", i64::MIN, -1),
+        Ok(RuntimeValue::I64(0))
+    ));
+    assert!(matches!(
+        i64_op("i64.rem_u", i64::MIN, -1),
+        Ok(RuntimeValue::I64(i64::MIN))
+    ));
+
+    // Neighbours of the overflowing case don't trap.
+    assert!(matches!(
+        i32_op("i32.div_s", i32::MIN, 1),
+        Ok(RuntimeValue::I32(i32::MIN))
+    ));
+    assert!(matches!(
+        i32_op("i32.div_s", i32::MIN + 1, -1),
+        Ok(RuntimeValue::I32(i32::MAX))
+    ));
+    assert!(matches!(
+        i64_op("i64.div_s", i64::MIN, 1),
+        Ok(RuntimeValue::I64(i64::MIN))
+    ));
+    assert!(matches!(
+        i64_op("i64.div_s", i64::MIN + 1, -1),
+        Ok(RuntimeValue::I64(i64::MAX))
+    ));
+    assert!(matches!(
+        i32_op("i32.rem_s", -7, 2),
+        Ok(RuntimeValue::I32(-1))
+    ));
+    assert!(matches!(
+        i64_op("i64.rem_s", -7, 2),
+        Ok(RuntimeValue::I64(-1))
+    ));
+}
+
+#[test]
+fn integer_arithmetic_wraps() {
+    let module = parse_wat(
+        r#"
+(module
+  (func (export "i32.add") (param i32 i32) (result i32) (i32.add (get_local 0) (get_local 1)))
+  (func (export "i32.sub") (param i32 i32) (result i32) (i32.sub (get_local 0) (get_local 1)))
+  (func (export "i32.mul") (param i32 i32) (result i32) (i32.mul (get_local 0) (get_local 1)))
+  (func (export "i32.shl") (param i32 i32) (result i32) (i32.shl (get_local 0) (get_local 1)))
+  (func (export "i32.shr_s") (param i32 i32) (result i32) (i32.shr_s (get_local 0) (get_local 1)))
+  (func (export "i32.shr_u") (param i32 i32) (result i32) (i32.shr_u (get_local 0) (get_local 1)))
+  (func (export "i32.neg") (param i32 i32) (result i32) (i32.sub (i32.const 0) (get_local 0)))
+  (func (export "i64.add") (param i64 i64) (result i64) (i64.add (get_local 0) (get_local 1)))
+  (func (export "i64.sub") (param i64 i64) (result i64) (i64.sub (get_local 0) (get_local 1)))
+  (func (export "i64.mul") (param i64 i64) (result i64) (i64.mul (get_local 0) (get_local 1)))
+  (func (export "i64.shl") (param i64 i64) (result i64) (i64.shl (get_local 0) (get_local 1)))
+  (func (export "i64.shr_s") (param i64 i64) (result i64) (i64.shr_s (get_local 0) (get_local 1)))
+  (func (export "i64.shr_u") (param i64 i64) (result i64) (i64.shr_u (get_local 0) (get_local 1)))
+  (func (export "i64.neg") (param i64 i64) (result i64) (i64.sub (i64.const 0) (get_local 0)))
+
+  ;; The end of the block is a branch target, so these aren't fused into a
+  ;; superinstruction.
+  (func (export "i32.add_unfused") (param i32 i32) (result i32)
+    (i32.add (get_local 0) (block (result i32) (get_local 1))))
+  (func (export "i32.mul_unfused") (param i32 i32) (result i32)
+    (i32.mul (get_local 0) (block (result i32) (get_local 1))))
+)
+"#,
+    );
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+    let invoke = |name: &str, left: RuntimeValue, right: RuntimeValue| {
+        instance
+            .invoke_export(name, &[left, right], &mut NopExternals)
+            .expect("Arithmetic never traps")
+            .expect("Function should return a value")
+    };
+
+    type BinOp<T> = fn(T, T) -> T;
+
+    let i32_ops: &[(&str, BinOp<i32>)] = &[
+        ("i32.add", i32::wrapping_add),
+        ("i32.add_unfused", i32::wrapping_add),
+        ("i32.sub", i32::wrapping_sub),
+        ("i32.mul", i32::wrapping_mul),
+        ("i32.mul_unfused", i32::wrapping_mul),
+        ("i32.shl", |x, n| x.wrapping_shl(n as u32)),
+        ("i32.shr_s", |x, n| x.wrapping_shr(n as u32)),
+        ("i32.shr_u", |x, n| (x as u32).wrapping_shr(n as u32) as i32),
+        ("i32.neg", |x, _| x.wrapping_neg()),
+    ];
+    let values32 = [i32::MIN, i32::MIN + 1, -1, 0, 1, 31, 32, 33, i32::MAX];
+    for &(name, expected) in i32_ops {
+        for &left in &values32 {
+            for &right in &values32 {
+                assert_eq!(
+                    invoke(name, left.into(), right.into()),
+                    RuntimeValue::I32(expected(left, right)),
+                    "{} {} {}",
+                    name,
+                    left,
+                    right
+                );
+            }
+        }
+    }
+
+    let i64_ops: &[(&str, BinOp<i64>)] = &[
+        ("i64.add", i64::wrapping_add),
+        ("i64.sub", i64::wrapping_sub),
+        ("i64.mul", i64::wrapping_mul),
+        ("i64.shl", |x, n| x.wrapping_shl(n as u32)),
+        ("i64.shr_s", |x, n| x.wrapping_shr(n as u32)),
+        ("i64.shr_u", |x, n| (x as u64).wrapping_shr(n as u32) as i64),
+        ("i64.neg", |x, _| x.wrapping_neg()),
+    ];
+    let values64 = [
+        i64::MIN,
+        i64::MIN + 1,
+        -1,
+        0,
+        1,
+        63,
+        64,
+        65,
+        1 << 32,
+        i64::MAX,
+    ];
+    for &(name, expected) in i64_ops {
+        for &left in &values64 {
+            for &right in &values64 {
+                assert_eq!(
+                    invoke(name, left.into(), right.into()),
+                    RuntimeValue::I64(expected(left, right)),
+                    "{} {} {}",
+                    name,
+                    left,
+                    right
+                );
+            }
+        }
+    }
+}
+
+#[cfg(feature = "multi_table")]
+#[test]
+fn call_indirect_non_default_table() {
+    use crate::ModuleBuilder;
+    use parity_wasm::elements::{
+        ElementSection, ElementSegment, InitExpr, Instruction, Section, TableSection, TableType,
+    };
+
+    let offset = || {
+        Some(InitExpr::new(vec![
+            Instruction::I32Const(0),
+            Instruction::End,
+        ]))
+    };
+    let returns_i32 = Signature::new(&[][..], Some(ValueType::I32));
+    let dispatch = Signature::new(&[ValueType::I32][..], Some(ValueType::I32));
+    let call_indirect = |table| {
+        vec![
+            Instruction::GetLocal(0),
+            Instruction::CallIndirect(0, table),
+        ]
+    };
+    let mut parity_module = ModuleBuilder::new()
+        .with_func(&returns_i32, &[], vec![Instruction::I32Const(1)])
+        .with_func(&returns_i32, &[], vec![Instruction::I32Const(2)])
+        .with_func(&dispatch, &[], call_indirect(0))
+        .with_func(&dispatch, &[], call_indirect(1))
+        .export_func("call0", 2)
+        .export_func("call1", 3)
+        .build();
+    parity_module
+        .insert_section(Section::Table(TableSection::with_entries(vec![
+            TableType::new(1, None),
+            TableType::new(2, None),
+        ])))
+        .unwrap();
+    parity_module
+        .insert_section(Section::Element(ElementSection::with_entries(vec![
+            ElementSegment::new(0, offset(), vec![0]),
+            ElementSegment::new(1, offset(), vec![1, 0]),
+        ])))
+        .unwrap();
+    let module = Module::from_parity_wasm_module(parity_module).expect("Failed to load module");
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+
+    let call =
+        |name, index| instance.invoke_export(name, &[RuntimeValue::I32(index)], &mut NopExternals);
+    assert_eq!(call("call0", 0).unwrap(), Some(RuntimeValue::I32(1)));
+    assert_eq!(call("call1", 0).unwrap(), Some(RuntimeValue::I32(2)));
+    assert_eq!(call("call1", 1).unwrap(), Some(RuntimeValue::I32(1)));
+    match call("call1", 2) {
+        Err(Error::Trap(trap)) => assert!(matches!(trap.kind(), TrapKind::TableAccessOutOfBounds)),
+        result => panic!("Expected a trap, got {:?}", result),
+    }
+}
+
+#[cfg(feature = "multi_table")]
+#[test]
+fn imported_and_defined_tables() {
+    use crate::ModuleBuilder;
+    use parity_wasm::elements::{
+        ElementSection, ElementSegment, ExportEntry, ExportSection, External, ImportEntry,
+        ImportSection, InitExpr, Instruction, Internal, Section, TableSection, TableType,
+    };
+
+    let offset = |offset| {
+        Some(InitExpr::new(vec![
+            Instruction::I32Const(offset),
+            Instruction::End,
+        ]))
+    };
+    let returns_i32 = Signature::new(&[][..], Some(ValueType::I32));
+    let mut parity_module = ModuleBuilder::new()
+        .with_func(&returns_i32, &[], vec![Instruction::I32Const(1)])
+        .with_func(&returns_i32, &[], vec![Instruction::I32Const(2)])
+        .build();
+    parity_module
+        .insert_section(Section::Import(ImportSection::with_entries(vec![
+            ImportEntry::new(
+                "env".into(),
+                "table".into(),
+                External::Table(TableType::new(2, None)),
+            ),
+        ])))
+        .unwrap();
+    parity_module
+        .insert_section(Section::Table(TableSection::with_entries(vec![
+            TableType::new(1, None),
+        ])))
+        .unwrap();
+    parity_module
+        .insert_section(Section::Export(ExportSection::with_entries(vec![
+            ExportEntry::new("table".into(), Internal::Table(1)),
+        ])))
+        .unwrap();
+    parity_module
+        .insert_section(Section::Element(ElementSection::with_entries(vec![
+            ElementSegment::new(0, offset(1), vec![0]),
+            ElementSegment::new(1, offset(0), vec![1]),
+        ])))
+        .unwrap();
+    let module = Module::from_parity_wasm_module(parity_module).expect("Failed to load module");
+
+    let imported = TableInstance::alloc(2, None).unwrap();
+    let imports =
+        ImportsBuilder::new().with_extern("env", "table", ExternVal::Table(imported.clone()));
+    let instance = ModuleInstance::new(&module, &imports)
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+    let defined = instance
+        .export_by_name("table")
+        .and_then(|export| export.as_table().cloned())
+        .expect("Module should export its own table");
+
+    let call = |table: &TableRef, index| {
+        let func = table
+            .get(index)
+            .unwrap()
+            .expect("Element should be initialized");
+        FuncInstance::invoke(&func, &[], &mut NopExternals).unwrap()
+    };
+    assert!(imported.get(0).unwrap().is_none());
+    assert_eq!(call(&imported, 1), Some(RuntimeValue::I32(1)));
+    assert_eq!(call(&defined, 0), Some(RuntimeValue::I32(2)));
+}
+
+#[test]
+fn integer_rotations() {
+    let module = parse_wat(
+        r#"
+(module
+  (func (export "i32.rotl") (param i32 i32) (result i32) (i32.rotl (get_local 0) (get_local 1)))
+  (func (export "i32.rotr") (param i32 i32) (result i32) (i32.rotr (get_local 0) (get_local 1)))
+  (func (export "i64.rotl") (param i64 i64) (result i64) (i64.rotl (get_local 0) (get_local 1)))
+  (func (export "i64.rotr") (param i64 i64) (result i64) (i64.rotr (get_local 0) (get_local 1)))
+)
+"#,
+    );
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+    let invoke = |name: &str, value: RuntimeValue, amount: RuntimeValue| {
+        instance
+            .invoke_export(name, &[value, amount], &mut NopExternals)
+            .expect("Rotations never trap")
+            .expect("Function should return a value")
+    };
+
+    // Reference implementation that moves bit `i` to bit `(i + n) % width`.
+    fn rotl(x: u64, n: u64, width: u64) -> u64 {
+        (0..width)
+            .filter(|i| x & (1 << i) != 0)
+            .fold(0, |acc, i| acc | 1 << ((i + n % width) % width))
+    }
+    let rotl32 = |x: u32, n: u32| rotl(u64::from(x), u64::from(n), 32) as u32;
+    let rotl64 = |x: u64, n: u64| rotl(x, n, 64);
+
+    let values32: &[i32] = &[0, 1, -1, i32::MIN, i32::MAX, 0x1234_5678];
+    let mut amounts32: Vec<i32> = (0..=65).collect();
+    amounts32.extend_from_slice(&[-1, -31, -32, -33, i32::MIN, i32::MAX]);
+    for &value in values32 {
+        for &amount in &amounts32 {
+            let n = amount as u32;
+            let left = rotl32(value as u32, n) as i32;
+            let right = rotl32(value as u32, 32 - n % 32) as i32;
+            assert_eq!(
+                invoke("i32.rotl", value.into(), amount.into()),
+                RuntimeValue::I32(left),
+                "i32.rotl {:#x} {}",
+                value,
+                amount
+            );
+            assert_eq!(
+                invoke("i32.rotr", value.into(), amount.into()),
+                RuntimeValue::I32(right),
+                "i32.rotr {:#x} {}",
+                value,
+                amount
+            );
+        }
+    }
+
+    let values64: &[i64] = &[0, 1, -1, i64::MIN, i64::MAX, 0x0123_4567_89ab_cdef];
+    let mut amounts64: Vec<i64> = (0..=129).collect();
+    // Amounts with high bits set, which must not be lost when reducing them.
+    amounts64.extend_from_slice(&[
+        -1,
+        -63,
+        -64,
+        -65,
+        1 << 32,
+        (1 << 32) + 1,
+        (1 << 32) + 63,
+        i64::MIN,
+        i64::MAX,
+    ]);
+    for &value in values64 {
+        for &amount in &amounts64 {
+            let n = amount as u64;
+            let left = rotl64(value as u64, n) as i64;
+            let right = rotl64(value as u64, 64 - n % 64) as i64;
+            assert_eq!(
+                invoke("i64.rotl", value.into(), amount.into()),
+                RuntimeValue::I64(left),
+                "i64.rotl {:#x} {}",
+                value,
+                amount
+            );
+            assert_eq!(
+                invoke("i64.rotr", value.into(), amount.into()),
+                RuntimeValue::I64(right),
+                "i64.rotr {:#x} {}",
+                value,
+                amount
+            );
+        }
+    }
+}
+
+#[test]
+fn breakpoints() {
+    use crate::{Breakpoints, DebugFrame, Trap};
+
+    let module = parse_wat(
+        r#"
+(module
+  (memory 1)
+  (func $xor (param i32 i32) (result i32) (i32.xor (get_local 0) (get_local 1)))
+  (func (export "fold") (param $n i32) (result i32) (local $acc i32)
+    (block
+      (loop
+        (br_if 1 (i32.eqz (get_local $n)))
+        (set_local $acc (call $xor (get_local $acc) (get_local $n)))
+        (set_local $n (i32.sub (get_local $n) (i32.const 1)))
+        (br 0)))
+    (get_local $acc))
+)
+"#,
+    );
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+    let fold = instance
+        .export_by_name("fold")
+        .and_then(|export| export.as_func().cloned())
+        .expect("Module should export fold");
+
+    // Break in `$xor` before `i32.xor`, with both operands on the stack.
+    let mut breakpoints = Breakpoints::new();
+    breakpoints.add(0, 2);
+    let mut hits = Vec::new();
+    let mut handler = |frame: &mut DebugFrame| -> Result<(), Trap> {
+        assert_eq!(frame.func_index(), 0);
+        assert_eq!(frame.pc(), 2);
+        assert_eq!(frame.local_count(), 2);
+        assert!(frame.memory().is_some());
+        hits.push((frame.local(0), frame.local(1), frame.operands()));
+        Ok(())
+    };
+    let result = FuncInstance::invoke_with_breakpoints(
+        &fold,
+        &[RuntimeValue::I32(3)],
+        &mut NopExternals,
+        &breakpoints,
+        &mut handler,
+    )
+    .unwrap();
+    assert_eq!(result, Some(RuntimeValue::I32(0)));
+    assert_eq!(
+        hits,
+        [
+            (
+                Some(RuntimeValue::I32(0)),
+                Some(RuntimeValue::I32(3)),
+                vec![0, 3]
+            ),
+            (
+                Some(RuntimeValue::I32(3)),
+                Some(RuntimeValue::I32(2)),
+                vec![3, 2]
+            ),
+            (
+                Some(RuntimeValue::I32(1)),
+                Some(RuntimeValue::I32(1)),
+                vec![1, 1]
+            ),
+        ]
+    );
+
+    // Locals can be modified. The first instruction of `fold` is also the head of
+    // the loop, so the breakpoint is hit once per iteration and once more on exit.
+    let mut breakpoints = Breakpoints::new();
+    breakpoints.add(1, 0);
+    let mut hits = 0;
+    let mut handler = |frame: &mut DebugFrame| -> Result<(), Trap> {
+        if hits == 0 {
+            assert!(frame.set_local(1, RuntimeValue::I64(0)).is_err());
+            assert!(frame.set_local(2, RuntimeValue::I32(0)).is_err());
+            frame.set_local(0, RuntimeValue::I32(4)).unwrap();
+            frame.set_local(1, RuntimeValue::I32(100)).unwrap();
+        }
+        hits += 1;
+        Ok(())
+    };
+    let result = FuncInstance::invoke_with_breakpoints(
+        &fold,
+        &[RuntimeValue::I32(1)],
+        &mut NopExternals,
+        &breakpoints,
+        &mut handler,
+    )
+    .unwrap();
+    assert_eq!(hits, 5);
+    assert_eq!(result, Some(RuntimeValue::I32(100 ^ 4 ^ 3 ^ 2 ^ 1)));
+
+    // A handler can abort the execution.
+    let mut breakpoints = Breakpoints::new();
+    breakpoints.add(0, 0);
+    let mut handler = |_: &mut DebugFrame| -> Result<(), Trap> {
+        Err(Trap::new(TrapKind::Unreachable { func_index: None }))
+    };
+    let err = FuncInstance::invoke_with_breakpoints(
+        &fold,
+        &[RuntimeValue::I32(1)],
+        &mut NopExternals,
+        &breakpoints,
+        &mut handler,
+    )
+    .unwrap_err();
+    assert!(matches!(
+        err.kind(),
+        TrapKind::Unreachable { func_index: None }
+    ));
+
+    // Invocations without breakpoints run through.
+    assert_eq!(
+        FuncInstance::invoke(&fold, &[RuntimeValue::I32(3)], &mut NopExternals).unwrap(),
+        Some(RuntimeValue::I32(0))
+    );
+}
+
+#[test]
+fn call_hook() {
+    use crate::CallHook;
+
+    #[derive(Default)]
+    struct Recorder(Vec<(&'static str, u32, bool)>);
+
+    impl CallHook for Recorder {
+        fn on_enter(&mut self, func_index: u32) {
+            self.0.push(("enter", func_index, false));
+        }
+
+        fn on_exit(&mut self, func_index: u32, trapped: bool) {
+            self.0.push(("exit", func_index, trapped));
+        }
+    }
+
+    let module = parse_wat(
+        r#"
+(module
+  (import "env" "host" (func $host))
+  (type $t (func (param i32) (result i32)))
+  (table anyfunc (elem $leaf))
+  (func $leaf (param i32) (result i32)
+    (if (i32.eqz (get_local 0)) (then unreachable))
+    (get_local 0)
+  )
+  (func $middle (param i32) (result i32)
+    (call_indirect (type $t) (get_local 0) (i32.const 0))
+  )
+  (func (export "main") (param i32) (result i32)
+    (drop (call $leaf (i32.const 1)))
+    (call $middle (get_local 0))
+  )
+)
+"#,
+    );
+    let host = FuncInstance::alloc_host(Signature::new(&[][..], None), 0);
+    let instance = ModuleInstance::with_externvals(&module, [ExternVal::Func(host)].iter())
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+    let main = instance
+        .export_by_name("main")
+        .and_then(|export| export.as_func().cloned())
+        .unwrap();
+    // The indices count the imported function.
+    let (leaf, middle, main_idx) = (1, 2, 3);
+
+    let mut recorder = Recorder::default();
+    let result = FuncInstance::invoke_with_call_hook(
+        &main,
+        &[RuntimeValue::I32(2)],
+        &mut NopExternals,
+        &mut recorder,
+    );
+    assert_eq!(result.unwrap(), Some(RuntimeValue::I32(2)));
+    assert_eq!(
+        recorder.0,
+        [
+            ("enter", main_idx, false),
+            ("enter", leaf, false),
+            ("exit", leaf, false),
+            ("enter", middle, false),
+            ("enter", leaf, false),
+            ("exit", leaf, false),
+            ("exit", middle, false),
+            ("exit", main_idx, false),
+        ]
+    );
+
+    // All functions on the call stack exit if the execution traps.
+    let mut recorder = Recorder::default();
+    let result = FuncInstance::invoke_with_call_hook(
+        &main,
+        &[RuntimeValue::I32(0)],
+        &mut NopExternals,
+        &mut recorder,
+    );
+    assert!(matches!(
+        result.unwrap_err().kind(),
+        TrapKind::Unreachable { .. }
+    ));
+    assert_eq!(
+        &recorder.0[recorder.0.len() - 3..],
+        [
+            ("exit", leaf, true),
+            ("exit", middle, true),
+            ("exit", main_idx, true),
+        ]
+    );
+}
+
+#[test]
+fn table_init_from() {
+    let table = TableInstance::alloc(4, None).unwrap();
+    let func = FuncInstance::alloc_host(Signature::new(&[][..], None), 0);
+
+    table
+        .init_from(1, &[Some(func.clone()), None, Some(func)])
+        .unwrap();
+    assert!(table.get(0).unwrap().is_none());
+    assert!(table.get(1).unwrap().is_some());
+    assert!(table.get(2).unwrap().is_none());
+    assert!(table.get(3).unwrap().is_some());
+
+    // A range reaching past the end leaves the table untouched.
+    match table.init_from(3, &[None, None]) {
+        Err(Error::Table(msg)) => assert_eq!(
+            msg,
+            "trying to update 2 table items at index 3 when there are only 4 items"
+        ),
+        result => panic!("unexpected result {:?}", result),
+    }
+    assert!(table.get(3).unwrap().is_some());
+    assert!(table.init_from(u32::MAX, &[None]).is_err());
+    table.init_from(4, &[]).unwrap();
+}
+
+#[test]
+fn trap_codes() {
+    let module = parse_wat(
+        r#"
+(module
+	(memory 1)
+	(func (export "unreachable") unreachable)
+	(func (export "div") (result i32) (i32.div_u (i32.const 1) (i32.const 0)))
+	(func (export "load") (result i32) (i32.load (i32.const 65536)))
+)
+"#,
+    );
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .unwrap()
+        .assert_no_start();
+
+    let code = |name: &str| match instance.invoke_export(name, &[], &mut NopExternals) {
+        Err(Error::Trap(trap)) => trap.code(),
+        result => panic!("unexpected result {:?}", result),
+    };
+    assert_eq!(code("unreachable"), TrapCode::Unreachable);
+    assert_eq!(code("div"), TrapCode::DivisionByZero);
+    assert_eq!(code("load"), TrapCode::MemoryAccessOutOfBounds);
+}
+
+#[test]
+fn segment_offsets_from_imported_globals() {
+    let module = parse_wat(
+        r#"
+(module
+	(import "env" "memoryBase" (global $memory_base i32))
+	(import "env" "tableBase" (global $table_base i32))
+	(import "env" "memory" (memory 1))
+	(import "env" "table" (table 1 anyfunc))
+	(func $f)
+	(data (get_global $memory_base) "\01\02")
+	(elem (get_global $table_base) $f)
+)
+"#,
+    );
+
+    let mut env = Env::new();
+    env.memory_base = GlobalInstance::alloc(RuntimeValue::I32(1000), false);
+    env.table_base = GlobalInstance::alloc(RuntimeValue::I32(7), false);
+    ModuleInstance::new(&module, &ImportsBuilder::new().with_resolver("env", &env))
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+    assert_eq!(env.memory.get(999, 4).unwrap(), [0, 1, 2, 0]);
+    assert!(env.table.get(6).unwrap().is_none());
+    assert!(env.table.get(7).unwrap().is_some());
+
+    // An offset placing the segment out of bounds fails the instantiation.
+    let mut env = Env::new();
+    env.memory_base = GlobalInstance::alloc(RuntimeValue::I32(256 * 65536 - 1), false);
+    match ModuleInstance::new(&module, &ImportsBuilder::new().with_resolver("env", &env)) {
+        Err(Error::SegmentDoesNotFit {
+            kind: SegmentKind::Data,
+            index: 0,
+            offset,
+            len: 2,
+            ..
+        }) => assert_eq!(offset, 256 * 65536 - 1),
+        result => panic!("unexpected result {:?}", result.map(|_| ())),
+    }
+}
+
+#[test]
+fn memory_write_journal() {
+    let module = parse_wat(
+        r#"
+(module
+	(memory (export "memory") 1)
+	(data (i32.const 0) "\01")
+	(func (export "run")
+		(i32.store (i32.const 100) (i32.const 1))
+		(i64.store8 (i32.const 200) (i64.const 2))
+		(drop (i32.load (i32.const 300)))
+	)
+)
+"#,
+    );
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .unwrap()
+        .assert_no_start();
+    let memory = instance
+        .export_by_name("memory")
+        .unwrap()
+        .as_memory()
+        .unwrap()
+        .clone();
+
+    memory.set_write_journaling(true);
+    instance
+        .invoke_export("run", &[], &mut NopExternals)
+        .unwrap();
+    assert_eq!(
+        memory.take_write_journal(),
+        [
+            MemoryWrite {
+                offset: 100,
+                len: 4
+            },
+            MemoryWrite {
+                offset: 200,
+                len: 1
+            },
+        ]
+    );
+}
+
+#[test]
+fn exported_mutable_global() {
+    let module = parse_wat(
+        r#"
+(module
+	(global $counter (export "counter") (mut i32) (i32.const 0))
+	(func (export "get") (result i32) (get_global $counter))
+	(func (export "double") (set_global $counter (i32.mul (get_global $counter) (i32.const 2))))
+)
+"#,
+    );
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .unwrap()
+        .assert_no_start();
+    let counter = instance
+        .export_by_name("counter")
+        .unwrap()
+        .as_global()
+        .unwrap()
+        .clone();
+    assert!(counter.is_mutable());
+
+    for _ in 0..3 {
+        let value: i32 = counter.get().try_into().unwrap();
+        counter.set(RuntimeValue::I32(value + 1)).unwrap();
+    }
+    assert_eq!(
+        instance
+            .invoke_export("get", &[], &mut NopExternals)
+            .unwrap(),
+        Some(RuntimeValue::I32(3))
+    );
+
+    instance
+        .invoke_export("double", &[], &mut NopExternals)
+        .unwrap();
+    assert_eq!(counter.get(), RuntimeValue::I32(6));
+}
+
+#[test]
+fn i64_division_wide_operands() {
+    let module = parse_wat(
+        r#"
+(module
+  (func (export "i64.div_s") (param i64 i64) (result i64) (i64.div_s (get_local 0) (get_local 1)))
+  (func (export "i64.div_u") (param i64 i64) (result i64) (i64.div_u (get_local 0) (get_local 1)))
+  (func (export "i64.rem_s") (param i64 i64) (result i64) (i64.rem_s (get_local 0) (get_local 1)))
+  (func (export "i64.rem_u") (param i64 i64) (result i64) (i64.rem_u (get_local 0) (get_local 1)))
+)
+"#,
+    );
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+    let invoke = |name: &str, left: i64, right: i64| {
+        instance
+            .invoke_export(name, &[left.into(), right.into()], &mut NopExternals)
+            .expect("Failed to execute function")
+            .expect("Function should return a value")
+    };
+
+    // Operands and results with both 32-bit halves set, which 32-bit hosts divide in
+    // software. The expected results are precomputed rather than derived on the host.
+    // Columns: left, right, div_s, rem_s, div_u, rem_u.
+    let cases: &[(i64, i64, i64, i64, i64, i64)] = &[
+        (
+            0x7fff_ffff_ffff_fff0,
+            0x1_0000_0001,
+            2147483647,
+            2147483633,
+            2147483647,
+            2147483633,
+        ),
+        (
+            -0x1234_5678_9abc_def0,
+            0x8765_4321,
+            -577474594,
+            -951870606,
+            7543261889,
+            1359952687,
+        ),
+        (
+            0x0123_4567_89ab_cdef,
+            -0xfedc_ba98,
+            -19173961,
+            619702423,
+            0,
+            81985529216486895,
+        ),
+        (
+            -0x4000_0000_0000_0007,
+            -0x2_0000_0003,
+            536870911,
+            -6979321866,
+            0,
+            -4611686018427387911,
+        ),
+    ];
+    for &(left, right, div_s, rem_s, div_u, rem_u) in cases {
+        assert_eq!(invoke("i64.div_s", left, right), RuntimeValue::I64(div_s));
+        assert_eq!(invoke("i64.rem_s", left, right), RuntimeValue::I64(rem_s));
+        assert_eq!(invoke("i64.div_u", left, right), RuntimeValue::I64(div_u));
+        assert_eq!(invoke("i64.rem_u", left, right), RuntimeValue::I64(rem_u));
+    }
+}
+
+#[test]
+fn instantiation_allocation_limit() {
+    let instantiate = |wat: &str, max_bytes: usize| {
+        let module = parse_wat(wat);
+        let env = Env::new();
+        let imports = ImportsBuilder::new().with_resolver("env", &env);
+        ModuleInstance::new_with_allocation_limit(&module, &imports, max_bytes)
+            .map(|_| ())
+            .map_err(|err| match err {
+                Error::Instantiation(msg) => msg,
+                err => panic!("unexpected error {:?}", err),
+            })
+    };
+
+    let defined = r#"
+(module
+	(memory 20)
+	(table 100000 anyfunc)
+)
+"#;
+    assert_eq!(
+        instantiate(defined, 64 * 1024),
+        Err("Allocation limit of 65536 bytes exceeded by table 0".into())
+    );
+    assert_eq!(
+        instantiate(defined, 1024 * 1024),
+        Err("Allocation limit of 1048576 bytes exceeded by memory 0".into())
+    );
+    assert_eq!(instantiate(defined, 3 * 1024 * 1024), Ok(()));
+
+    // Imported entities were allocated by the embedder and aren't counted.
+    let imported = r#"
+(module
+	(import "env" "memory" (memory 256))
+	(import "env" "table" (table 64 anyfunc))
+	(func $f)
+	(elem (i32.const 0) $f $f $f $f)
+)
+"#;
+    assert_eq!(instantiate(imported, 4 * 1024), Ok(()));
+    match instantiate(imported, 64) {
+        Err(msg) => assert_eq!(
+            msg,
+            "Allocation limit of 64 bytes exceeded by the module instance"
+        ),
+        Ok(()) => panic!("instantiation should exceed the limit"),
+    }
+}
+
+#[test]
+fn empty_module() {
+    // Just the magic and the version, without any sections.
+    let wasm = [0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00];
+    let module = Module::from_buffer(&wasm[..]).expect("Failed to load empty module");
+    assert_eq!(module.min_required_pages(), Pages(0));
+    assert_eq!(
+        module.interface_fingerprint(),
+        Module::from_buffer(&wasm[..])
+            .unwrap()
+            .interface_fingerprint()
+    );
+
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .expect("Failed to instantiate empty module");
+    assert!(!instance.has_start());
+    let instance = instance.run_start(&mut NopExternals).unwrap();
+
+    assert!(instance.export_by_name("anything").is_none());
+    assert!(instance.globals().is_empty());
+    assert!(instance.invoke_index(0, &[], &mut NopExternals).is_err());
+}
+
+#[test]
+fn memory_access_out_of_bounds_address() {
+    let module = parse_wat(
+        r#"
+(module
+  (memory 1)
+  (func (export "load") (param i32) (result i32)
+    (i32.load offset=4 (get_local 0)))
+  (func (export "load_far") (param i32) (result i64)
+    (i64.load16_u offset=4294967295 (get_local 0)))
+  (func (export "store") (param i32)
+    (i64.store8 (get_local 0) (i64.const 1)))
+)
+"#,
+    );
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+
+    let access = |name: &str, address: i32| {
+        let trap =
+            match instance.invoke_export(name, &[RuntimeValue::I32(address)], &mut NopExternals) {
+                Err(Error::Trap(trap)) => trap,
+                result => panic!("Expected a trap, got {:?}", result),
+            };
+        match *trap.kind() {
+            TrapKind::MemoryAccessOutOfBounds { address, len } => (address, len),
+            ref kind => panic!("Expected an out of bounds access, got {:?}", kind),
+        }
+    };
+
+    assert_eq!(access("load", 65534), (65538, 4));
+    assert_eq!(access("load", -1), (0x1_0000_0003, 4));
+    assert_eq!(access("load_far", 2), (0x1_0000_0001, 2));
+    assert_eq!(access("store", 65536), (65536, 1));
+}
+
+#[test]
+fn float_ops_match_spec_vectors() {
+    use crate::nan_preserving_float::{F32, F64};
+
+    /// The result of an operation: exact bits, or any NaN.
+    #[derive(Debug, Clone, Copy)]
+    enum Expected {
+        Bits(u64),
+        Nan,
+    }
+    use Expected::*;
+
+    const BINARY: &[&str] = &["add", "sub", "mul", "div", "min", "max", "copysign"];
+    const UNARY: &[&str] = &["sqrt", "nearest"];
+
+    let mut wat = String::from("(module\n");
+    for ty in &["f32", "f64"] {
+        for op in BINARY {
+            wat.push_str(&format!(
+                "(func (export \"{ty}.{op}\") (param {ty} {ty}) (result {ty}) \
+                 ({ty}.{op} (get_local 0) (get_local 1)))\n",
+                ty = ty,
+                op = op
+            ));
+        }
+        for op in UNARY {
+            wat.push_str(&format!(
+                "(func (export \"{ty}.{op}\") (param {ty}) (result {ty}) \
+                 ({ty}.{op} (get_local 0)))\n",
+                ty = ty,
+                op = op
+            ));
+        }
+    }
+    wat.push(')');
+    let instance = ModuleInstance::new(&parse_wat(&wat), &ImportsBuilder::default())
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+
+    // Expected results as specified, with ties rounding to even, subnormals, signed
+    // zeros and overflow to infinity. The second operand of unary operations is unused.
+    let vectors: &[(ValueType, &str, u64, u64, Expected)] = &[
+        (
+            ValueType::F32,
+            "add",
+            0x00000001,
+            0x00000001,
+            Bits(0x00000002),
+        ),
+        (
+            ValueType::F32,
+            "add",
+            0x3f800000,
+            0x33800000,
+            Bits(0x3f800000),
+        ),
+        (
+            ValueType::F32,
+            "add",
+            0x3f800000,
+            0x33800001,
+            Bits(0x3f800001),
+        ),
+        (
+            ValueType::F32,
+            "add",
+            0x7f7fffff,
+            0x7f7fffff,
+            Bits(0x7f800000),
+        ),
+        (
+            ValueType::F32,
+            "add",
+            0x80000000,
+            0x00000000,
+            Bits(0x00000000),
+        ),
+        (
+            ValueType::F32,
+            "add",
+            0x3f800000,
+            0xbf800000,
+            Bits(0x00000000),
+        ),
+        (
+            ValueType::F32,
+            "sub",
+            0x3f800000,
+            0x3f7fffff,
+            Bits(0x33800000),
+        ),
+        (
+            ValueType::F32,
+            "sub",
+            0x00800000,
+            0x00000001,
+            Bits(0x007fffff),
+        ),
+        (
+            ValueType::F32,
+            "sub",
+            0x80000000,
+            0x80000000,
+            Bits(0x00000000),
+        ),
+        (
+            ValueType::F32,
+            "mul",
+            0x00800000,
+            0x3f000000,
+            Bits(0x00400000),
+        ),
+        (
+            ValueType::F32,
+            "mul",
+            0x3f800001,
+            0x3f800001,
+            Bits(0x3f800002),
+        ),
+        (
+            ValueType::F32,
+            "mul",
+            0x80000000,
+            0x3f800000,
+            Bits(0x80000000),
+        ),
+        (
+            ValueType::F32,
+            "mul",
+            0x7f000000,
+            0x40000000,
+            Bits(0x7f800000),
+        ),
+        (
+            ValueType::F32,
+            "div",
+            0x3f800000,
+            0x40400000,
+            Bits(0x3eaaaaab),
+        ),
+        (
+            ValueType::F32,
+            "div",
+            0x00000001,
+            0x40000000,
+            Bits(0x00000000),
+        ),
+        (
+            ValueType::F32,
+            "div",
+            0x00000003,
+            0x40000000,
+            Bits(0x00000002),
+        ),
+        (
+            ValueType::F32,
+            "div",
+            0xbf800000,
+            0x7f800000,
+            Bits(0x80000000),
+        ),
+        (ValueType::F32, "sqrt", 0x40000000, 0, Bits(0x3fb504f3)),
+        (ValueType::F32, "sqrt", 0x00000001, 0, Bits(0x1a3504f3)),
+        (ValueType::F32, "sqrt", 0x80000000, 0, Bits(0x80000000)),
+        (ValueType::F32, "sqrt", 0x7f7fffff, 0, Bits(0x5f7fffff)),
+        (
+            ValueType::F32,
+            "min",
+            0x80000000,
+            0x00000000,
+            Bits(0x80000000),
+        ),
+        (
+            ValueType::F32,
+            "min",
+            0x00000000,
+            0x80000000,
+            Bits(0x80000000),
+        ),
+        (
+            ValueType::F32,
+            "min",
+            0x3f800000,
+            0xff800000,
+            Bits(0xff800000),
+        ),
+        (
+            ValueType::F32,
+            "max",
+            0x80000000,
+            0x00000000,
+            Bits(0x00000000),
+        ),
+        (
+            ValueType::F32,
+            "max",
+            0x00000000,
+            0x80000000,
+            Bits(0x00000000),
+        ),
+        (
+            ValueType::F32,
+            "max",
+            0x00000001,
+            0x80000001,
+            Bits(0x00000001),
+        ),
+        (
+            ValueType::F32,
+            "max",
+            0x3f800000,
+            0xff800000,
+            Bits(0x3f800000),
+        ),
+        (ValueType::F32, "nearest", 0x3f000000, 0, Bits(0x00000000)),
+        (ValueType::F32, "nearest", 0x3fc00000, 0, Bits(0x40000000)),
+        (ValueType::F32, "nearest", 0x40200000, 0, Bits(0x40000000)),
+        (ValueType::F32, "nearest", 0xbf000000, 0, Bits(0x80000000)),
+        (ValueType::F32, "nearest", 0x4b000001, 0, Bits(0x4b000001)),
+        (ValueType::F32, "nearest", 0x3f7fffff, 0, Bits(0x3f800000)),
+        (
+            ValueType::F32,
+            "copysign",
+            0x3f800000,
+            0xffc00000,
+            Bits(0xbf800000),
+        ),
+        (
+            ValueType::F32,
+            "copysign",
+            0xff800000,
+            0x00000000,
+            Bits(0x7f800000),
+        ),
+        (
+            ValueType::F32,
+            "copysign",
+            0x7fa00001,
+            0x80000000,
+            Bits(0xffa00001),
+        ),
+        (
+            ValueType::F64,
+            "add",
+            0x0000000000000001,
+            0x0000000000000001,
+            Bits(0x0000000000000002),
+        ),
+        (
+            ValueType::F64,
+            "add",
+            0x3ff0000000000000,
+            0x3ca0000000000000,
+            Bits(0x3ff0000000000000),
+        ),
+        (
+            ValueType::F64,
+            "add",
+            0x3ff0000000000000,
+            0x3ca0000000000001,
+            Bits(0x3ff0000000000001),
+        ),
+        (
+            ValueType::F64,
+            "add",
+            0x7fefffffffffffff,
+            0x7fefffffffffffff,
+            Bits(0x7ff0000000000000),
+        ),
+        (
+            ValueType::F64,
+            "add",
+            0x8000000000000000,
+            0x0000000000000000,
+            Bits(0x0000000000000000),
+        ),
+        (
+            ValueType::F64,
+            "sub",
+            0x3ff0000000000000,
+            0x3fefffffffffffff,
+            Bits(0x3ca0000000000000),
+        ),
+        (
+            ValueType::F64,
+            "sub",
+            0x0010000000000000,
+            0x0000000000000001,
+            Bits(0x000fffffffffffff),
+        ),
+        (
+            ValueType::F64,
+            "mul",
+            0x0010000000000000,
+            0x3fe0000000000000,
+            Bits(0x0008000000000000),
+        ),
+        (
+            ValueType::F64,
+            "mul",
+            0x3ff0000000000001,
+            0x3ff0000000000001,
+            Bits(0x3ff0000000000002),
+        ),
+        (
+            ValueType::F64,
+            "mul",
+            0x8000000000000000,
+            0x3ff0000000000000,
+            Bits(0x8000000000000000),
+        ),
+        (
+            ValueType::F64,
+            "div",
+            0x3ff0000000000000,
+            0x4008000000000000,
+            Bits(0x3fd5555555555555),
+        ),
+        (
+            ValueType::F64,
+            "div",
+            0x0000000000000003,
+            0x4000000000000000,
+            Bits(0x0000000000000002),
+        ),
+        (
+            ValueType::F64,
+            "div",
+            0xbff0000000000000,
+            0x7ff0000000000000,
+            Bits(0x8000000000000000),
+        ),
+        (
+            ValueType::F64,
+            "sqrt",
+            0x4000000000000000,
+            0,
+            Bits(0x3ff6a09e667f3bcd),
+        ),
+        (
+            ValueType::F64,
+            "sqrt",
+            0x0000000000000001,
+            0,
+            Bits(0x1e60000000000000),
+        ),
+        (
+            ValueType::F64,
+            "sqrt",
+            0x8000000000000000,
+            0,
+            Bits(0x8000000000000000),
+        ),
+        (
+            ValueType::F64,
+            "min",
+            0x8000000000000000,
+            0x0000000000000000,
+            Bits(0x8000000000000000),
+        ),
+        (
+            ValueType::F64,
+            "min",
+            0x0000000000000000,
+            0x8000000000000000,
+            Bits(0x8000000000000000),
+        ),
+        (
+            ValueType::F64,
+            "max",
+            0x8000000000000000,
+            0x0000000000000000,
+            Bits(0x0000000000000000),
+        ),
+        (
+            ValueType::F64,
+            "max",
+            0x0000000000000000,
+            0x8000000000000000,
+            Bits(0x0000000000000000),
+        ),
+        (
+            ValueType::F64,
+            "min",
+            0x3ff0000000000000,
+            0xfff0000000000000,
+            Bits(0xfff0000000000000),
+        ),
+        (
+            ValueType::F64,
+            "max",
+            0x3ff0000000000000,
+            0xfff0000000000000,
+            Bits(0x3ff0000000000000),
+        ),
+        (
+            ValueType::F64,
+            "nearest",
+            0x3fe0000000000000,
+            0,
+            Bits(0x0000000000000000),
+        ),
+        (
+            ValueType::F64,
+            "nearest",
+            0x3ff8000000000000,
+            0,
+            Bits(0x4000000000000000),
+        ),
+        (
+            ValueType::F64,
+            "nearest",
+            0x4004000000000000,
+            0,
+            Bits(0x4000000000000000),
+        ),
+        (
+            ValueType::F64,
+            "nearest",
+            0xbfe0000000000000,
+            0,
+            Bits(0x8000000000000000),
+        ),
+        (
+            ValueType::F64,
+            "nearest",
+            0x4330000000000001,
+            0,
+            Bits(0x4330000000000001),
+        ),
+        (
+            ValueType::F64,
+            "nearest",
+            0x3fdfffffffffffff,
+            0,
+            Bits(0x0000000000000000),
+        ),
+        (
+            ValueType::F64,
+            "copysign",
+            0x3ff0000000000000,
+            0xfff8000000000000,
+            Bits(0xbff0000000000000),
+        ),
+        (
+            ValueType::F64,
+            "copysign",
+            0x7ff4000000000001,
+            0x8000000000000000,
+            Bits(0xfff4000000000001),
+        ),
+        (ValueType::F32, "add", 0x7fc00000, 0x3f800000, Nan),
+        (ValueType::F32, "sub", 0x7f800000, 0x7f800000, Nan),
+        (ValueType::F32, "mul", 0x00000000, 0xff800000, Nan),
+        (ValueType::F32, "div", 0x00000000, 0x80000000, Nan),
+        (ValueType::F32, "sqrt", 0xbf800000, 0, Nan),
+        (ValueType::F32, "min", 0x7fa00000, 0x3f800000, Nan),
+        (ValueType::F32, "max", 0x3f800000, 0xffc00000, Nan),
+        (ValueType::F32, "min", 0x7fc00000, 0x3f800000, Nan),
+        (ValueType::F32, "max", 0x7fc00000, 0x3f800000, Nan),
+        (ValueType::F32, "min", 0x3f800000, 0xffc00001, Nan),
+        (ValueType::F32, "max", 0x00000000, 0x7fa00000, Nan),
+        (
+            ValueType::F64,
+            "add",
+            0x7ff8000000000000,
+            0x3ff0000000000000,
+            Nan,
+        ),
+        (
+            ValueType::F64,
+            "sub",
+            0x7ff0000000000000,
+            0x7ff0000000000000,
+            Nan,
+        ),
+        (
+            ValueType::F64,
+            "div",
+            0x7ff0000000000000,
+            0xfff0000000000000,
+            Nan,
+        ),
+        (ValueType::F64, "sqrt", 0xbff0000000000000, 0, Nan),
+        (
+            ValueType::F64,
+            "min",
+            0x3ff0000000000000,
+            0x7ff4000000000000,
+            Nan,
+        ),
+        (
+            ValueType::F64,
+            "max",
+            0xfff8000000000000,
+            0x3ff0000000000000,
+            Nan,
+        ),
+        (
+            ValueType::F64,
+            "min",
+            0x7ff8000000000000,
+            0x3ff0000000000000,
+            Nan,
+        ),
+        (
+            ValueType::F64,
+            "max",
+            0x3ff0000000000000,
+            0xfff8000000000001,
+            Nan,
+        ),
+        (
+            ValueType::F64,
+            "max",
+            0x8000000000000000,
+            0x7ff4000000000000,
+            Nan,
+        ),
+    ];
+
+    for (idx, &(ty, op, a, b, expected)) in vectors.iter().enumerate() {
+        let value = |bits: u64| match ty {
+            ValueType::F32 => RuntimeValue::F32(F32::from_bits(bits as u32)),
+            _ => RuntimeValue::F64(F64::from_bits(bits)),
+        };
+        let name = format!(
+            "{}.{}",
+            if ty == ValueType::F32 { "f32" } else { "f64" },
+            op
+        );
+        let args = if UNARY.contains(&op) {
+            vec![value(a)]
+        } else {
+            vec![value(a), value(b)]
+        };
+        let result = instance
+            .invoke_export(&name, &args, &mut NopExternals)
+            .expect("Failed to invoke float operation")
+            .expect("Float operations return a value");
+        let (bits, is_nan) = match result {
+            RuntimeValue::F32(result) => (u64::from(result.to_bits()), result.is_nan()),
+            RuntimeValue::F64(result) => (result.to_bits(), result.is_nan()),
+            _ => unreachable!(),
+        };
+        let matches = match expected {
+            Bits(expected) => bits == expected,
+            Nan => is_nan,
+        };
+        assert!(
+            matches,
+            "Vector {} diverges: {}({:#x}, {:#x}) returned {:#x}, expected {:x?}",
+            idx, name, a, b, bits, expected
+        );
+    }
+}
+
+#[test]
+fn prepared_call_returns_memory_region() {
+    let module = parse_wat(
+        r#"
+(module
+  (memory (export "memory") 1)
+  (data (i32.const 8) "hello, world")
+  (func (export "buffer") (param i64) (result i64)
+    (get_local 0))
+  (func (export "ptr") (result i32)
+    (i32.const 8))
+)
+"#,
+    );
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+    let memory = instance
+        .export_by_name("memory")
+        .and_then(|export| export.as_memory().cloned())
+        .unwrap();
+    let signature = Signature::new(&[ValueType::I64][..], Some(ValueType::I64));
+    let mut buffer = instance.prepare_call("buffer", &signature).unwrap();
+    let pair = |ptr: u32, len: u32| [RuntimeValue::I64(((len as i64) << 32) | ptr as i64)];
+
+    {
+        let mut region = buffer
+            .invoke_region(&pair(15, 5), &mut NopExternals, &memory)
+            .expect("Failed to return a region");
+        assert_eq!((region.offset(), region.len()), (15, 5));
+        assert_eq!(region.as_slice(), b"world");
+        region.as_slice_mut().copy_from_slice(b"wasmi");
+    }
+    assert_eq!(memory.get(8, 12).unwrap(), b"hello, wasmi");
+
+    let empty = buffer.invoke_region(&pair(65536, 0), &mut NopExternals, &memory);
+    assert_eq!(empty.map(|region| region.len()).ok(), Some(0));
+    let out_of_bounds = buffer.invoke_region(&pair(65532, 5), &mut NopExternals, &memory);
+    assert!(matches!(
+        out_of_bounds.err(),
+        Some(Error::MemoryOutOfBounds { .. })
+    ));
+
+    // The region is dropped, so the memory can grow again.
+    assert_eq!(memory.grow(Pages(1)).unwrap(), Pages(1));
+
+    let mut ptr = instance
+        .prepare_call("ptr", &Signature::new(&[][..], Some(ValueType::I32)))
+        .unwrap();
+    let not_a_pair = ptr.invoke_region(&[], &mut NopExternals, &memory);
+    assert!(matches!(not_a_pair.err(), Some(Error::Function(_))));
+}
+
+#[test]
+fn invoke_export_with_tuple_args() {
+    use crate::IntoRuntimeValues;
+
+    let module = parse_wat(
+        r#"
+(module
+  (func (export "mix") (param i32 i64 f32 f64) (result f64)
+    (f64.add
+      (f64.add (f64.convert_s/i32 (get_local 0)) (f64.convert_s/i64 (get_local 1)))
+      (f64.add (f64.promote/f32 (get_local 2)) (get_local 3))))
+  (func (export "sum12")
+    (param i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32) (result i32)
+    (i32.add (get_local 0) (get_local 11)))
+)
+"#,
+    );
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+
+    let args = (1i32, 2u64, 0.5f32, 0.25f64).into_runtime_values();
+    assert_eq!(
+        instance
+            .invoke_export("mix", &args, &mut NopExternals)
+            .unwrap(),
+        Some(RuntimeValue::from(3.75f64))
+    );
+
+    let args = (1u8, 0i8, 0i16, 0u16, 0i32, 0u32, 0, 0, 0, 0, 0, 41i32).into_runtime_values();
+    assert_eq!(
+        instance
+            .invoke_export("sum12", &args, &mut NopExternals)
+            .unwrap(),
+        Some(RuntimeValue::I32(42))
+    );
+}
+
+#[test]
+fn call_indirect_trap_kinds() {
+    let module = parse_wat(
+        r#"
+(module
+  (type $i32 (func (result i32)))
+  (type $i64 (func (result i64)))
+  (table 3 anyfunc)
+  (elem (i32.const 0) $one $wide)
+  (func $one (result i32) (i32.const 1))
+  (func $wide (result i64) (i64.const 1))
+  (func (export "call") (param i32) (result i32)
+    (call_indirect (type $i32) (get_local 0)))
+)
+"#,
+    );
+    let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+        .expect("Failed to instantiate module")
+        .assert_no_start();
+
+    let call =
+        |index: i32| instance.invoke_export("call", &[RuntimeValue::I32(index)], &mut NopExternals);
+    let trap_code = |index: i32| match call(index) {
+        Err(Error::Trap(trap)) => trap.code(),
+        result => panic!("Expected a trap, got {:?}", result),
+    };
+    assert_eq!(call(0).unwrap(), Some(RuntimeValue::I32(1)));
+    assert_eq!(trap_code(1), TrapCode::IndirectCallTypeMismatch);
+    assert_eq!(trap_code(2), TrapCode::ElemUninitialized);
+    assert_eq!(trap_code(3), TrapCode::TableAccessOutOfBounds);
+    assert_eq!(trap_code(-1), TrapCode::TableAccessOutOfBounds);
+}
+
+#[test]
+fn inlining_matches_calls() {
+    let wat = r#"
+(module
+  (memory 1)
+  (global $counter (mut i32) (i32.const 0))
+  (table 1 anyfunc)
+  (elem (i32.const 0) $square)
+
+  ;; Leaf helpers, which are inlined.
+  (func $square (param i32) (result i32)
+    (i32.mul (get_local 0) (get_local 0)))
+  (func $bump
+    (set_global $counter (i32.add (get_global $counter) (i32.const 1))))
+  (func $clamp (param i32 i32) (result i32)
+    (local i32)
+    (set_local 2 (get_local 0))
+    (if (i32.gt_s (get_local 2) (get_local 1))
+      (then (return (get_local 1))))
+    (get_local 2))
+  (func $classify (param i32) (result i32)
+    (block
+      (block
+        (block
+          (br_table 0 1 2 (get_local 0)))
+        (return (i32.const 10)))
+      (return (i32.const 20)))
+    (i32.const 30))
+  (func $sum_to (param i32) (result i64)
+    (local i64)
+    (block
+      (loop
+        (br_if 1 (i32.eqz (get_local 0)))
+        (set_local 1 (i64.add (get_local 1) (i64.extend_u/i32 (get_local 0))))
+        (set_local 0 (i32.sub (get_local 0) (i32.const 1)))
+        (br 0)))
+    (get_local 1))
+  (func $store (param i32 i32)
+    (i32.store (get_local 0) (get_local 1)))
+  (func $div (param i32 i32) (result i32)
+    (i32.div_s (get_local 0) (get_local 1)))
+  (func $half (param f64) (result f64)
+    (f64.mul (get_local 0) (f64.const 0.5)))
+
+  ;; Not inlined: calls another function.
+  (func $square_twice (param i32) (result i32)
+    (call $square (call $square (get_local 0))))
+  ;; Not inlined: executes `unreachable`.
+  (func $check (param i32)
+    (if (get_local 0) (then (unreachable))))
+
+  (func (export "main") (param i32) (result i32)
+    (local i32)
+    (loop
+      (call $bump)
+      (set_local 1
+        (i32.add (get_local 1)
+          (i32.add
+            (call $clamp (call $square (get_local 0)) (i32.const 50))
+            (call $classify (get_local 0)))))
+      (call $store (i32.mul (get_local 0) (i32.const 4)) (get_local 1))
+      (br_if 0
+        (i32.gt_s (tee_local 0 (i32.sub (get_local 0) (i32.const 1))) (i32.const -2))))
+    (i32.add (get_local 1) (call $square_twice (i32.const 3))))
+  (func (export "sum") (param i32) (result i64)
+    (i64.add (call $sum_to (get_local 0)) (call $sum_to (i32.const 3))))
+  (func (export "div") (param i32 i32) (result i32)
+    (call $div (get_local 0) (get_local 1)))
+  (func (export "half") (param f64) (result f64)
+    (call $half (call $half (get_local 0))))
+  (func (export "check") (param i32)
+    (call $check (get_local 0)))
+  (func (export "counter") (result i32)
+    (get_global $counter))
+  (func (export "indirect") (param i32) (result i32)
+    (call_indirect (param i32) (result i32) (get_local 0) (i32.const 0)))
+)
+"#;
+    let load = |max_callee_size: Option<u32>| {
+        let wasm = wabt::wat2wasm(wat).unwrap();
+        let module = parity_wasm::deserialize_buffer(&wasm).unwrap();
+        let module = match max_callee_size {
+            Some(size) => Module::from_parity_wasm_module_with_inlining(module, size),
+            None => Module::from_parity_wasm_module(module),
+        }
+        .expect("Failed to load module");
+        let instance = ModuleInstance::new(&module, &ImportsBuilder::default())
+            .expect("Failed to instantiate module")
+            .assert_no_start();
+        (module, instance)
+    };
+    let (plain_module, plain) = load(None);
+    let (inlined_module, inlined) = load(Some(64));
+
+    // Only the leaf helpers are inlined.
+    let main = 10;
+    assert_eq!(
+        plain_module.direct_callees(main).unwrap().functions.len(),
+        6
+    );
+    assert_eq!(
+        inlined_module.direct_callees(main).unwrap().functions,
+        vec![8]
+    );
+    assert_eq!(
+        inlined_module.direct_callees(14).unwrap().functions,
+        vec![9]
+    );
+
+    let calls: &[(&str, &[RuntimeValue])] = &[
+        ("main", &[RuntimeValue::I32(5)]),
+        ("main", &[RuntimeValue::I32(-1)]),
+        ("main", &[RuntimeValue::I32(100)]),
+        ("sum", &[RuntimeValue::I32(0)]),
+        ("sum", &[RuntimeValue::I32(1000)]),
+        ("div", &[RuntimeValue::I32(7), RuntimeValue::I32(-2)]),
+        ("div", &[RuntimeValue::I32(7), RuntimeValue::I32(0)]),
+        ("div", &[RuntimeValue::I32(i32::MIN), RuntimeValue::I32(-1)]),
+        ("half", &[RuntimeValue::F64(3.0.into())]),
+        ("check", &[RuntimeValue::I32(0)]),
+        ("check", &[RuntimeValue::I32(1)]),
+        ("indirect", &[RuntimeValue::I32(9)]),
+        // The last store of this one is out of bounds.
+        ("main", &[RuntimeValue::I32(16384)]),
+        ("counter", &[]),
+    ];
+    let outcome = |instance: &ModuleRef, name: &str, args: &[RuntimeValue]| match instance
+        .invoke_export(name, args, &mut NopExternals)
+    {
+        Ok(result) => Ok(result),
+        Err(Error::Trap(trap)) => Err(std::format!("{:?}", trap.kind())),
+        Err(err) => panic!("Unexpected error {:?}", err),
+    };
+    for &(name, args) in calls {
+        assert_eq!(
+            outcome(&inlined, name, args),
+            outcome(&plain, name, args),
+            "{}({:?})",
+            name,
+            args
+        );
+    }
+
+    let memory = |instance: &ModuleRef| {
+        instance
+            .memory_by_index(0)
+            .unwrap()
+            .get(0, crate::LINEAR_MEMORY_PAGE_SIZE.0)
+            .unwrap()
+    };
+    assert!(memory(&plain) == memory(&inlined));
+}
@@ -0,0 +1,293 @@
+use crate::func::{FuncInstanceInternal, FuncRef};
+use crate::global::GlobalRef;
+use crate::host::{Externals, RuntimeArgs};
+use crate::imports::ModuleImportResolver;
+use crate::memory::MemoryRef;
+use crate::table::TableRef;
+use crate::types::{GlobalDescriptor, MemoryDescriptor, Signature, TableDescriptor};
+use crate::{Error, RuntimeValue, Trap};
+use alloc::{collections::BTreeMap, rc::Rc};
+use core::cell::RefCell;
+
+/// A call of a host function, as seen by a [`HostCallInterceptor`].
+///
+/// [`HostCallInterceptor`]: trait.HostCallInterceptor.html
+#[derive(Debug)]
+pub struct HostCall<'a> {
+    /// The index the host function was allocated with, see [`FuncInstance::alloc_host`].
+    ///
+    /// [`FuncInstance::alloc_host`]: struct.FuncInstance.html#method.alloc_host
+    pub index: usize,
+    /// The field name the function was resolved by, or `None` if it wasn't resolved
+    /// through the [`TracingResolver`].
+    ///
+    /// Calls only carry the index, so if several fields resolve to functions with the
+    /// same index, this is the name of the first one that was resolved.
+    ///
+    /// [`TracingResolver`]: struct.TracingResolver.html
+    pub name: Option<&'a str>,
+    /// The arguments of the call.
+    pub args: &'a [RuntimeValue],
+}
+
+/// Callbacks invoked around every host function call made through [`TracingExternals`].
+///
+/// [`TracingExternals`]: struct.TracingExternals.html
+pub trait HostCallInterceptor {
+    /// Called before the host function is dispatched to.
+    fn before_call(&mut self, call: &HostCall);
+
+    /// Called after the host function returned with `result`.
+    ///
+    /// The default implementation does nothing.
+    fn after_call(&mut self, call: &HostCall, result: &Result<Option<RuntimeValue>, Trap>) {
+        let _ = (call, result);
+    }
+}
+
+/// A [`ModuleImportResolver`] that delegates to an inner one and remembers which
+/// host functions it resolved, for tracing host calls.
+///
+/// Host functions are dispatched by index to [`Externals`], so calls can't be
+/// intercepted at the [`FuncRef`] itself. Instead, the resolver records the field
+/// name of every host function it resolves, and [`intercept`] wraps the
+/// [`Externals`] used for the invocation into [`TracingExternals`], which looks the
+/// name up, counts the call and passes it to a [`HostCallInterceptor`].
+///
+/// Host calls only carry the index of the host function, so names and call counts are
+/// recorded per index. Fields that resolve to functions with the same index are all
+/// traced under the name of the first one that was resolved, so give every field a
+/// function with an index of its own to tell them apart.
+///
+/// Only the wrapped [`Externals`] do any work per call. Invoking wasm with the plain
+/// [`Externals`] has no overhead, even if the imports were resolved through this
+/// resolver.
+///
+/// # Examples
+///
+/// ```rust
+/// extern crate wabt;
+/// extern crate wasmi;
+///
+/// use wasmi::{
+///     Error, Externals, FuncInstance, FuncRef, HostCall, HostCallInterceptor, ImportsBuilder,
+///     Module, ModuleImportResolver, ModuleInstance, RuntimeArgs, RuntimeValue, Signature,
+///     TracingResolver, Trap,
+/// };
+///
+/// struct Env;
+///
+/// impl ModuleImportResolver for Env {
+///     fn resolve_func(&self, field_name: &str, signature: &Signature) -> Result<FuncRef, Error> {
+///         let index = match field_name {
+///             "double" => 0,
+///             _ => {
+///                 return Err(Error::Instantiation(format!(
+///                     "Export {} not found",
+///                     field_name
+///                 )))
+///             }
+///         };
+///         Ok(FuncInstance::alloc_host(signature.clone(), index))
+///     }
+/// }
+///
+/// impl Externals for Env {
+///     fn invoke_index(
+///         &mut self,
+///         _index: usize,
+///         args: RuntimeArgs,
+///     ) -> Result<Option<RuntimeValue>, Trap> {
+///         Ok(Some(RuntimeValue::I32(args.nth::<i32>(0) * 2)))
+///     }
+/// }
+///
+/// struct Log(Vec<String>);
+///
+/// impl HostCallInterceptor for Log {
+///     fn before_call(&mut self, call: &HostCall) {
+///         self.0.push(format!("{}{:?}", call.name.unwrap_or("?"), call.args));
+///     }
+/// }
+///
+/// fn main() {
+///     let wasm = wabt::wat2wasm(
+///         r#"
+///         (module
+///           (import "env" "double" (func $double (param i32) (result i32)))
+///           (func (export "run") (result i32) (call $double (i32.const 21)))
+///         )
+///         "#,
+///     )
+///     .unwrap();
+///     let module = Module::from_buffer(&wasm).unwrap();
+///
+///     let resolver = TracingResolver::new(Env);
+///     let imports = ImportsBuilder::new().with_resolver("env", &resolver);
+///     let instance = ModuleInstance::new(&module, &imports)
+///         .unwrap()
+///         .assert_no_start();
+///
+///     let mut log = Log(Vec::new());
+///     let result = instance
+///         .invoke_export("run", &[], &mut resolver.intercept(&mut Env, &mut log))
+///         .unwrap();
+///     assert_eq!(result, Some(RuntimeValue::I32(42)));
+///     assert_eq!(log.0, ["double[I32(21)]"]);
+///     assert_eq!(resolver.call_count(0), 1);
+/// }
+/// ```
+///
+/// [`ModuleImportResolver`]: trait.ModuleImportResolver.html
+/// [`Externals`]: trait.Externals.html
+/// [`FuncRef`]: struct.FuncRef.html
+/// [`intercept`]: #method.intercept
+/// [`TracingExternals`]: struct.TracingExternals.html
+/// [`HostCallInterceptor`]: trait.HostCallInterceptor.html
+pub struct TracingResolver<R> {
+    inner: R,
+    names: RefCell<BTreeMap<usize, Rc<str>>>,
+    call_counts: RefCell<BTreeMap<usize, u64>>,
+}
+
+impl<R> TracingResolver<R> {
+    /// Create a `TracingResolver` delegating to `inner`.
+    pub fn new(inner: R) -> TracingResolver<R> {
+        TracingResolver {
+            inner,
+            names: RefCell::new(BTreeMap::new()),
+            call_counts: RefCell::new(BTreeMap::new()),
+        }
+    }
+
+    /// Wrap `externals` so that every host call made through them is passed to
+    /// `interceptor` and counted.
+    pub fn intercept<'a, E: Externals, I: HostCallInterceptor>(
+        &'a self,
+        externals: &'a mut E,
+        interceptor: &'a mut I,
+    ) -> TracingExternals<'a, R, E, I> {
+        TracingExternals {
+            resolver: self,
+            externals,
+            interceptor,
+        }
+    }
+
+    /// Returns the number of calls of the host function with `index` that were made
+    /// through [`TracingExternals`] of this resolver.
+    ///
+    /// [`TracingExternals`]: struct.TracingExternals.html
+    pub fn call_count(&self, index: usize) -> u64 {
+        self.call_counts.borrow().get(&index).cloned().unwrap_or(0)
+    }
+}
+
+impl<R: ModuleImportResolver> ModuleImportResolver for TracingResolver<R> {
+    fn resolve_func(&self, field_name: &str, signature: &Signature) -> Result<FuncRef, Error> {
+        let func = self.inner.resolve_func(field_name, signature)?;
+        if let FuncInstanceInternal::Host {
+            host_func_index, ..
+        } = *func.as_internal()
+        {
+            self.names
+                .borrow_mut()
+                .entry(host_func_index)
+                .or_insert_with(|| Rc::from(field_name));
+        }
+        Ok(func)
+    }
+
+    fn resolve_global(
+        &self,
+        field_name: &str,
+        global_type: &GlobalDescriptor,
+    ) -> Result<GlobalRef, Error> {
+        self.inner.resolve_global(field_name, global_type)
+    }
+
+    fn resolve_memory(
+        &self,
+        field_name: &str,
+        memory_type: &MemoryDescriptor,
+    ) -> Result<MemoryRef, Error> {
+        self.inner.resolve_memory(field_name, memory_type)
+    }
+
+    fn resolve_table(
+        &self,
+        field_name: &str,
+        table_type: &TableDescriptor,
+    ) -> Result<TableRef, Error> {
+        self.inner.resolve_table(field_name, table_type)
+    }
+}
+
+/// [`Externals`] that pass every host call to a [`HostCallInterceptor`] before and
+/// after dispatching it to the wrapped ones.
+///
+/// Created with [`TracingResolver::intercept`].
+///
+/// [`Externals`]: trait.Externals.html
+/// [`HostCallInterceptor`]: trait.HostCallInterceptor.html
+/// [`TracingResolver::intercept`]: struct.TracingResolver.html#method.intercept
+pub struct TracingExternals<'a, R, E, I> {
+    resolver: &'a TracingResolver<R>,
+    externals: &'a mut E,
+    interceptor: &'a mut I,
+}
+
+impl<'a, R, E: Externals, I: HostCallInterceptor> TracingExternals<'a, R, E, I> {
+    fn trace<F>(
+        &mut self,
+        index: usize,
+        args: RuntimeArgs,
+        dispatch: F,
+    ) -> Result<Option<RuntimeValue>, Trap>
+    where
+        F: FnOnce(&mut E, RuntimeArgs) -> Result<Option<RuntimeValue>, Trap>,
+    {
+        *self
+            .resolver
+            .call_counts
+            .borrow_mut()
+            .entry(index)
+            .or_insert(0) += 1;
+        // Cloned so that the host function can resolve imports through the same resolver.
+        let name = self.resolver.names.borrow().get(&index).cloned();
+        let values = args.as_ref();
+        let call = HostCall {
+            index,
+            name: name.as_deref(),
+            args: values,
+        };
+
+        self.interceptor.before_call(&call);
+        let result = dispatch(self.externals, values.into());
+        self.interceptor.after_call(&call, &result);
+        result
+    }
+}
+
+impl<'a, R, E: Externals, I: HostCallInterceptor> Externals for TracingExternals<'a, R, E, I> {
+    fn invoke_index(
+        &mut self,
+        index: usize,
+        args: RuntimeArgs,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        self.trace(index, args, |externals, args| {
+            externals.invoke_index(index, args)
+        })
+    }
+
+    fn invoke_index_with_memory(
+        &mut self,
+        index: usize,
+        args: RuntimeArgs,
+        memory: Option<&MemoryRef>,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        self.trace(index, args, |externals, args| {
+            externals.invoke_index_with_memory(index, args, memory)
+        })
+    }
+}
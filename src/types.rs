@@ -1,7 +1,8 @@
+use crate::memory_units::Pages;
 use alloc::borrow::Cow;
 
 use parity_wasm::elements::{
-    FunctionType, GlobalType, MemoryType, TableType, ValueType as EValueType,
+    FunctionType, GlobalType, Internal, MemoryType, TableType, ValueType as EValueType,
 };
 
 /// Signature of a [function].
@@ -108,6 +109,83 @@ impl ValueType {
             ValueType::F64 => EValueType::F64,
         }
     }
+
+    /// Returns the size in bytes of a value of this type.
+    ///
+    /// This is the number of bytes the value occupies in linear memory,
+    /// e.g. when stored with the corresponding `*.store` instruction.
+    pub fn size_of(self) -> usize {
+        match self {
+            ValueType::I32 | ValueType::F32 => 4,
+            ValueType::I64 | ValueType::F64 => 8,
+        }
+    }
+
+    /// Returns the natural alignment in bytes of a value of this type.
+    ///
+    /// This is the alignment that `*.load` and `*.store` instructions use by default.
+    pub fn align_of(self) -> usize {
+        self.size_of()
+    }
+}
+
+/// Kind of an entity exported by a module.
+///
+/// See [`Module::require_exports`] for details.
+///
+/// [`Module::require_exports`]: struct.Module.html#method.require_exports
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ExportKind {
+    /// A function.
+    Func,
+    /// A table.
+    Table,
+    /// A linear memory.
+    Memory,
+    /// A global variable.
+    Global,
+}
+
+impl ExportKind {
+    pub(crate) fn of(internal: &Internal) -> ExportKind {
+        match *internal {
+            Internal::Function(_) => ExportKind::Func,
+            Internal::Table(_) => ExportKind::Table,
+            Internal::Memory(_) => ExportKind::Memory,
+            Internal::Global(_) => ExportKind::Global,
+        }
+    }
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            ExportKind::Func => "function",
+            ExportKind::Table => "table",
+            ExportKind::Memory => "memory",
+            ExportKind::Global => "global",
+        }
+    }
+}
+
+/// Kind of a segment initializing a table or a memory at instantiation.
+///
+/// See [`Error::SegmentDoesNotFit`] for details.
+///
+/// [`Error::SegmentDoesNotFit`]: enum.Error.html#variant.SegmentDoesNotFit
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SegmentKind {
+    /// An element segment, initializing a table.
+    Element,
+    /// A data segment, initializing a memory.
+    Data,
+}
+
+impl SegmentKind {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            SegmentKind::Element => "element",
+            SegmentKind::Data => "data",
+        }
+    }
 }
 
 /// Description of a global variable.
@@ -179,25 +257,46 @@ impl TableDescriptor {
 ///
 /// [`ImportResolver`]: trait.ImportResolver.html
 pub struct MemoryDescriptor {
-    initial: u32,
-    maximum: Option<u32>,
+    initial: Pages,
+    maximum: Option<Pages>,
 }
 
 impl MemoryDescriptor {
     pub(crate) fn from_elements(memory_type: &MemoryType) -> MemoryDescriptor {
         MemoryDescriptor {
-            initial: memory_type.limits().initial(),
-            maximum: memory_type.limits().maximum(),
+            initial: Pages(memory_type.limits().initial() as usize),
+            maximum: memory_type
+                .limits()
+                .maximum()
+                .map(|maximum| Pages(maximum as usize)),
         }
     }
 
-    /// Returns initial size (in pages) of the requested memory.
-    pub fn initial(&self) -> u32 {
+    /// Returns initial size of the requested memory.
+    pub fn initial(&self) -> Pages {
         self.initial
     }
 
-    /// Returns maximum size (in pages) of the requested memory.
-    pub fn maximum(&self) -> Option<u32> {
+    /// Returns maximum size of the requested memory.
+    pub fn maximum(&self) -> Option<Pages> {
         self.maximum
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::ValueType;
+
+    #[test]
+    fn value_type_layout() {
+        for &(ty, size) in &[
+            (ValueType::I32, 4),
+            (ValueType::I64, 8),
+            (ValueType::F32, 4),
+            (ValueType::F64, 8),
+        ] {
+            assert_eq!(ty.size_of(), size);
+            assert_eq!(ty.align_of(), size);
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use crate::{deserialization_error, prepare, CompileLimits, Module, ValidationError};
+
+/// Validates and compiles wasm binaries, all with the same [`CompileLimits`].
+///
+/// This is meant for pipelines that check many modules against one configuration,
+/// e.g. at upload, so the limits are specified once instead of on every call. The
+/// buffers used to validate function bodies are allocated per function, so no
+/// state is kept between modules, and a `Validator` can be shared freely.
+///
+/// # Examples
+///
+/// ```rust
+/// extern crate wabt;
+/// extern crate wasmi;
+///
+/// use wasmi::{CompileLimits, Validator};
+///
+/// fn main() {
+///     let validator = Validator::new(CompileLimits {
+///         max_nesting_depth: 1,
+///         ..CompileLimits::default()
+///     });
+///
+///     let flat = wabt::wat2wasm("(module (func (block)))").unwrap();
+///     let nested = wabt::wat2wasm("(module (func (block (block))))").unwrap();
+///     assert!(validator.validate_and_compile(&flat).is_ok());
+///     assert!(validator.validate_and_compile(&nested).is_err());
+/// }
+/// ```
+///
+/// [`CompileLimits`]: struct.CompileLimits.html
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Validator {
+    limits: CompileLimits,
+}
+
+impl Validator {
+    /// Create a validator that rejects functions exceeding `limits`.
+    pub fn new(limits: CompileLimits) -> Validator {
+        Validator { limits }
+    }
+
+    /// Returns the limits this validator enforces.
+    pub fn limits(&self) -> &CompileLimits {
+        &self.limits
+    }
+
+    /// Validate the wasm binary in `buffer` and compile it, if it doesn't exceed the
+    /// limits of this validator.
+    ///
+    /// This is the same as [`Module::from_parity_wasm_module_with_limits`] on the
+    /// deserialized binary, but returns the [`ValidationError`] itself, as
+    /// [`Module::validate_buffer`] does.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if the binary can't be deserialized, isn't valid, or has a
+    /// function that exceeds the limits.
+    ///
+    /// [`Module::from_parity_wasm_module_with_limits`]: struct.Module.html#method.from_parity_wasm_module_with_limits
+    /// [`ValidationError`]: struct.ValidationError.html
+    /// [`Module::validate_buffer`]: struct.Module.html#method.validate_buffer
+    pub fn validate_and_compile<B: AsRef<[u8]>>(
+        &self,
+        buffer: B,
+    ) -> Result<Module, ValidationError> {
+        let module = parity_wasm::elements::deserialize_buffer(buffer.as_ref())
+            .map_err(deserialization_error)?;
+        prepare::compile_module_with_limits(module, &self.limits).map(Module::from_compiled)
+    }
+}
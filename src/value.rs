@@ -1,7 +1,9 @@
 use crate::nan_preserving_float::{F32, F64};
 use crate::types::ValueType;
-use crate::TrapKind;
-use core::{f32, i32, i64, u32, u64};
+use crate::{fnv, Trap, TrapKind};
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::{f32, fmt, i32, i64, u32, u64};
 
 /// Error for `LittleEndianConvert`
 #[derive(Debug)]
@@ -17,6 +19,11 @@ pub enum Error {
 ///
 /// There is no distinction between signed and unsigned integer types. Instead, integers are
 /// interpreted by respective operations as either unsigned or signed in two’s complement representation.
+///
+/// The `Display` implementation renders values in the notation of the spec text format,
+/// prefixed by the type, e.g. `i32:42`, `f32:-inf` or `f64:nan:0x4000000000001`.
+/// Integers are printed as signed. A NaN is printed as `nan` if it is canonical and
+/// with its payload otherwise.
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum RuntimeValue {
     /// Value of 32-bit signed or unsigned integer.
@@ -29,6 +36,44 @@ pub enum RuntimeValue {
     F64(F64),
 }
 
+impl fmt::Display for RuntimeValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RuntimeValue::I32(val) => write!(f, "i32:{}", val),
+            RuntimeValue::I64(val) => write!(f, "i64:{}", val),
+            RuntimeValue::F32(val) => {
+                f.write_str("f32:")?;
+                if val.is_nan() {
+                    let bits = val.to_bits();
+                    fmt_nan(f, bits >> 31 != 0, (bits & 0x7f_ffff).into(), 1 << 22)
+                } else {
+                    write!(f, "{}", val.to_float())
+                }
+            }
+            RuntimeValue::F64(val) => {
+                f.write_str("f64:")?;
+                if val.is_nan() {
+                    let bits = val.to_bits();
+                    fmt_nan(f, bits >> 63 != 0, bits & 0xf_ffff_ffff_ffff, 1 << 51)
+                } else {
+                    write!(f, "{}", val.to_float())
+                }
+            }
+        }
+    }
+}
+
+fn fmt_nan(f: &mut fmt::Formatter, negative: bool, payload: u64, canonical: u64) -> fmt::Result {
+    if negative {
+        f.write_str("-")?;
+    }
+    if payload == canonical {
+        f.write_str("nan")
+    } else {
+        write!(f, "nan:{:#x}", payload)
+    }
+}
+
 /// Trait for creating value from a [`RuntimeValue`].
 ///
 /// Typically each implementation can create a value from the specific type.
@@ -51,6 +96,84 @@ where
     fn from_runtime_value(val: RuntimeValue) -> Option<Self>;
 }
 
+/// A C-style enum that is passed across the boundary as the `i32` of its discriminant.
+///
+/// Implementing it lets host functions decode such arguments with
+/// [`RuntimeValue::as_enum_checked`], which rejects unknown discriminants, so that wasm
+/// code can't pass values that the host logic doesn't expect.
+///
+/// # Examples
+///
+/// ```rust
+/// use wasmi::{I32Enum, RuntimeValue};
+///
+/// #[derive(Debug, PartialEq)]
+/// enum Whence {
+///     Set = 0,
+///     Cur = 1,
+///     End = 2,
+/// }
+///
+/// impl I32Enum for Whence {
+///     fn from_i32(value: i32) -> Option<Self> {
+///         match value {
+///             0 => Some(Whence::Set),
+///             1 => Some(Whence::Cur),
+///             2 => Some(Whence::End),
+///             _ => None,
+///         }
+///     }
+/// }
+///
+/// assert_eq!(RuntimeValue::I32(2).as_enum_checked::<Whence>().unwrap(), Whence::End);
+/// assert!(RuntimeValue::I32(3).as_enum_checked::<Whence>().is_err());
+/// ```
+///
+/// [`RuntimeValue::as_enum_checked`]: enum.RuntimeValue.html#method.as_enum_checked
+pub trait I32Enum: Sized {
+    /// Returns the variant with the discriminant `value`, or `None` if there is none.
+    fn from_i32(value: i32) -> Option<Self>;
+}
+
+/// Which conversions [`RuntimeValue::coerce_to`] may make.
+///
+/// [`RuntimeValue::coerce_to`]: enum.RuntimeValue.html#method.coerce_to
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Coercion {
+    /// Only convert values that are represented exactly by the target type.
+    Lossless,
+    /// Also convert values that aren't, wrapping integers and rounding to the nearest
+    /// floating point number, as the conversion instructions of wasm do.
+    Truncate,
+}
+
+/// Conversion of a tuple into arguments of a function call.
+///
+/// Implemented for the unit type and for tuples of up to 12 elements of types that
+/// convert into a [`RuntimeValue`], so arguments don't have to be built by hand:
+///
+/// ```rust
+/// use wasmi::{IntoRuntimeValues, RuntimeValue};
+///
+/// assert_eq!(
+///     (1i32, 2u64, 0.5f64).into_runtime_values(),
+///     vec![RuntimeValue::I32(1), RuntimeValue::I64(2), RuntimeValue::F64(0.5.into())],
+/// );
+/// assert!(().into_runtime_values().is_empty());
+/// ```
+///
+/// The result can be passed to [`ModuleInstance::invoke_export`] as
+/// `&(1i32, 2.0f64).into_runtime_values()`.
+///
+/// [`RuntimeValue`]: enum.RuntimeValue.html
+/// [`ModuleInstance::invoke_export`]: struct.ModuleInstance.html#method.invoke_export
+pub trait IntoRuntimeValues {
+    /// Convert `self` into a vector of [`RuntimeValue`]s, one per element.
+    ///
+    /// [`RuntimeValue`]: enum.RuntimeValue.html
+    fn into_runtime_values(self) -> Vec<RuntimeValue>;
+}
+
 /// Convert one type to another by wrapping.
 pub trait WrapInto<T> {
     /// Convert one type to another by wrapping.
@@ -86,6 +209,20 @@ where
     fn from_little_endian(buffer: &[u8]) -> Result<Self, Error>;
 }
 
+/// Order of the two `i64` halves of a 128-bit integer passed as a pair of values.
+///
+/// See [`RuntimeValue::split_u128`] and [`RuntimeValue::join_u128`].
+///
+/// [`RuntimeValue::split_u128`]: enum.RuntimeValue.html#method.split_u128
+/// [`RuntimeValue::join_u128`]: enum.RuntimeValue.html#method.join_u128
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum WordOrder {
+    /// The high 64 bits come first, then the low 64 bits.
+    HighFirst,
+    /// The low 64 bits come first, then the high 64 bits.
+    LowFirst,
+}
+
 /// Arithmetic operations.
 pub trait ArithmeticOps<T> {
     /// Add two values.
@@ -125,6 +262,7 @@ pub trait Float<T>: ArithmeticOps<T> {
     /// Returns the integer part of a number.
     fn trunc(self) -> T;
     /// Returns the nearest integer to a number. Round half-way cases away from 0.0.
+    #[cfg_attr(feature = "soft_float", allow(dead_code))]
     fn round(self) -> T;
     /// Returns the nearest integer to a number. Ties are round to even number.
     fn nearest(self) -> T;
@@ -159,6 +297,177 @@ impl RuntimeValue {
         RuntimeValue::F64(F64::from_bits(val))
     }
 
+    /// Encode this value into a single `u64` slot of a flat argument or return buffer.
+    ///
+    /// The slot layout is:
+    ///
+    /// - `I32`: the 32 bits of the value, zero-extended, e.g. `-1` is `0x0000_0000_ffff_ffff`.
+    /// - `I64`: the 64 bits of the value.
+    /// - `F32`: the IEEE 754 bits of the value, zero-extended.
+    /// - `F64`: the IEEE 754 bits of the value.
+    ///
+    /// Floats are encoded by their bits, so the sign and payload of a NaN are preserved
+    /// exactly and round-trip through [`from_abi_slot`].
+    ///
+    /// The slot doesn't record the type. Decoding requires the type to be known from
+    /// the signature of the function.
+    ///
+    /// [`from_abi_slot`]: #method.from_abi_slot
+    pub fn to_abi_slot(&self) -> u64 {
+        match *self {
+            RuntimeValue::I32(v) => u64::from(v as u32),
+            RuntimeValue::I64(v) => v as u64,
+            RuntimeValue::F32(v) => u64::from(v.to_bits()),
+            RuntimeValue::F64(v) => v.to_bits(),
+        }
+    }
+
+    /// Decode a value of type `value_type` from a slot produced by [`to_abi_slot`].
+    ///
+    /// For `I32` and `F32`, only the low 32 bits of `slot` are used and the high bits
+    /// are ignored, so slots written by a bridge that sign-extends 32-bit values are
+    /// accepted as well.
+    ///
+    /// [`to_abi_slot`]: #method.to_abi_slot
+    pub fn from_abi_slot(value_type: ValueType, slot: u64) -> Self {
+        match value_type {
+            ValueType::I32 => RuntimeValue::I32(slot as u32 as i32),
+            ValueType::I64 => RuntimeValue::I64(slot as i64),
+            ValueType::F32 => RuntimeValue::decode_f32(slot as u32),
+            ValueType::F64 => RuntimeValue::decode_f64(slot),
+        }
+    }
+
+    /// Fold `values` into a checksum, e.g. to compare large sets of results against
+    /// expectations without matching each value.
+    ///
+    /// The checksum is the 64-bit FNV-1a hash of each value's type followed by its
+    /// [`to_abi_slot`] in little endian, so it is the same on every platform. Values
+    /// of different types or order give different checksums, and floats are hashed by
+    /// their bits, which tells NaNs apart by sign and payload.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use wasmi::RuntimeValue;
+    ///
+    /// let results = vec![RuntimeValue::I32(1), RuntimeValue::F64(2.5.into())];
+    /// assert_eq!(
+    ///     RuntimeValue::checksum(&results),
+    ///     RuntimeValue::checksum(results.iter().filter(|_| true)),
+    /// );
+    /// assert_ne!(
+    ///     RuntimeValue::checksum(&[RuntimeValue::I32(1)]),
+    ///     RuntimeValue::checksum(&[RuntimeValue::I64(1)]),
+    /// );
+    /// ```
+    ///
+    /// [`to_abi_slot`]: #method.to_abi_slot
+    pub fn checksum<'a, I: IntoIterator<Item = &'a RuntimeValue>>(values: I) -> u64 {
+        values.into_iter().fold(fnv::fnv1a(&[]), |hash, value| {
+            let tag = match value.value_type() {
+                ValueType::I32 => 0,
+                ValueType::I64 => 1,
+                ValueType::F32 => 2,
+                ValueType::F64 => 3,
+            };
+            let hash = fnv::fnv1a_extend(hash, &[tag]);
+            fnv::fnv1a_extend(hash, &value.to_abi_slot().to_le_bytes())
+        })
+    }
+
+    /// Split `value` into two `I64` values, in the given `order`.
+    ///
+    /// Each half holds 64 bits of `value`, the high half bits 127..64 and the low
+    /// half bits 63..0, reinterpreted as `i64`. The halves are plain integers, so
+    /// there is no byte order involved. To pass a 128-bit integer through linear
+    /// memory instead, store it with [`MemoryInstance::set_value`], which uses little
+    /// endian like all wasm memory accesses.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use wasmi::{RuntimeValue, WordOrder};
+    ///
+    /// let value = 0x0123_4567_89ab_cdef_fedc_ba98_7654_3210;
+    /// assert_eq!(
+    ///     RuntimeValue::split_u128(value, WordOrder::HighFirst),
+    ///     [
+    ///         RuntimeValue::I64(0x0123_4567_89ab_cdef),
+    ///         RuntimeValue::I64(0xfedc_ba98_7654_3210_u64 as i64),
+    ///     ]
+    /// );
+    /// let [low, high] = RuntimeValue::split_u128(value, WordOrder::LowFirst);
+    /// assert_eq!(
+    ///     RuntimeValue::join_u128(low, high, WordOrder::LowFirst).unwrap(),
+    ///     value
+    /// );
+    /// ```
+    ///
+    /// [`MemoryInstance::set_value`]: struct.MemoryInstance.html#method.set_value
+    pub fn split_u128(value: u128, order: WordOrder) -> [RuntimeValue; 2] {
+        let high = RuntimeValue::I64((value >> 64) as u64 as i64);
+        let low = RuntimeValue::I64(value as u64 as i64);
+        match order {
+            WordOrder::HighFirst => [high, low],
+            WordOrder::LowFirst => [low, high],
+        }
+    }
+
+    /// Split `value` into two `I64` values, in the given `order`.
+    ///
+    /// The two's complement bits of `value` are split as by [`split_u128`].
+    ///
+    /// [`split_u128`]: #method.split_u128
+    pub fn split_i128(value: i128, order: WordOrder) -> [RuntimeValue; 2] {
+        RuntimeValue::split_u128(value as u128, order)
+    }
+
+    /// Join two `I64` values, given in `order`, into an `u128`.
+    ///
+    /// This is the inverse of [`split_u128`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TrapKind::UnexpectedSignature`] if either value isn't an `I64`.
+    ///
+    /// [`split_u128`]: #method.split_u128
+    /// [`TrapKind::UnexpectedSignature`]: enum.TrapKind.html#variant.UnexpectedSignature
+    pub fn join_u128(
+        first: RuntimeValue,
+        second: RuntimeValue,
+        order: WordOrder,
+    ) -> Result<u128, Trap> {
+        let (high, low) = match order {
+            WordOrder::HighFirst => (first, second),
+            WordOrder::LowFirst => (second, first),
+        };
+        match (high, low) {
+            (RuntimeValue::I64(high), RuntimeValue::I64(low)) => {
+                Ok(u128::from(high as u64) << 64 | u128::from(low as u64))
+            }
+            _ => Err(TrapKind::UnexpectedSignature.into()),
+        }
+    }
+
+    /// Join two `I64` values, given in `order`, into an `i128`.
+    ///
+    /// This is the inverse of [`split_i128`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TrapKind::UnexpectedSignature`] if either value isn't an `I64`.
+    ///
+    /// [`split_i128`]: #method.split_i128
+    /// [`TrapKind::UnexpectedSignature`]: enum.TrapKind.html#variant.UnexpectedSignature
+    pub fn join_i128(
+        first: RuntimeValue,
+        second: RuntimeValue,
+        order: WordOrder,
+    ) -> Result<i128, Trap> {
+        RuntimeValue::join_u128(first, second, order).map(|value| value as i128)
+    }
+
     /// Get variable type for this value.
     pub fn value_type(&self) -> ValueType {
         match *self {
@@ -169,6 +478,73 @@ impl RuntimeValue {
         }
     }
 
+    /// Convert this value to `value_type`, e.g. to pass it as an argument of that type.
+    ///
+    /// With [`Coercion::Lossless`], a value is only converted if it doesn't change:
+    ///
+    /// - an `I32` is sign-extended to `I64`,
+    /// - an `I64` is narrowed to `I32` if it fits into an `i32` or `u32`, the latter
+    ///   being reinterpreted as signed, so unsigned values can be passed as well,
+    /// - integers are converted to floating point numbers that represent them exactly,
+    /// - an `F32` is promoted to `F64`, and an `F64` is demoted to `F32` if it is
+    ///   represented exactly (a NaN stays a NaN, but might lose its payload).
+    ///
+    /// With [`Coercion::Truncate`], the same conversions are made regardless of the
+    /// value, like `i32.wrap_i64`, `f32.demote_f64` and the `convert` instructions do.
+    ///
+    /// Floating point numbers are never converted to integers.
+    ///
+    /// Returns `None` if the value can't be converted.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use wasmi::{Coercion, RuntimeValue, ValueType};
+    ///
+    /// let big = RuntimeValue::I64(3_000_000_000);
+    /// assert_eq!(
+    ///     big.coerce_to(ValueType::I32, Coercion::Lossless),
+    ///     Some(RuntimeValue::I32(3_000_000_000u32 as i32)),
+    /// );
+    /// let huge = RuntimeValue::I64(1 << 40);
+    /// assert_eq!(huge.coerce_to(ValueType::I32, Coercion::Lossless), None);
+    /// assert_eq!(huge.coerce_to(ValueType::I32, Coercion::Truncate), Some(RuntimeValue::I32(0)));
+    /// ```
+    ///
+    /// [`Coercion::Lossless`]: enum.Coercion.html#variant.Lossless
+    /// [`Coercion::Truncate`]: enum.Coercion.html#variant.Truncate
+    pub fn coerce_to(self, value_type: ValueType, coercion: Coercion) -> Option<RuntimeValue> {
+        use self::RuntimeValue::*;
+
+        // Converting back through `i128` doesn't saturate, unlike converting to `i64`.
+        let (value, exact) = match (self, value_type) {
+            (value, value_type) if value.value_type() == value_type => (value, true),
+            (I32(v), ValueType::I64) => (I64(i64::from(v)), true),
+            (I64(v), ValueType::I32) => (
+                I32(v as i32),
+                i64::from(i32::MIN) <= v && v <= i64::from(u32::MAX),
+            ),
+            (I32(v), ValueType::F32) => (F32((v as f32).into()), v as f32 as i128 == v as i128),
+            (I32(v), ValueType::F64) => (F64(f64::from(v).into()), true),
+            (I64(v), ValueType::F32) => (F32((v as f32).into()), v as f32 as i128 == v as i128),
+            (I64(v), ValueType::F64) => (F64((v as f64).into()), v as f64 as i128 == v as i128),
+            (F32(v), ValueType::F64) => (F64(f64::from(v.to_float()).into()), true),
+            (F64(v), ValueType::F32) => {
+                let v = v.to_float();
+                (
+                    F32((v as f32).into()),
+                    v.is_nan() || f64::from(v as f32) == v,
+                )
+            }
+            _ => return None,
+        };
+        if exact || coercion == Coercion::Truncate {
+            Some(value)
+        } else {
+            None
+        }
+    }
+
     /// Returns `T` if this particular [`RuntimeValue`] contains
     /// appropriate type.
     ///
@@ -179,6 +555,121 @@ impl RuntimeValue {
     pub fn try_into<T: FromRuntimeValue>(self) -> Option<T> {
         FromRuntimeValue::from_runtime_value(self)
     }
+
+    /// Returns the value as an `u32`, e.g. a pointer into linear memory.
+    ///
+    /// An `I32` is reinterpreted as unsigned, which never fails. An `I64` is
+    /// interpreted as unsigned and must fit into an `u32`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TrapKind::IntegerOverflow`] if an `I64` doesn't fit and
+    /// [`TrapKind::UnexpectedSignature`] for floating point values.
+    ///
+    /// [`TrapKind::IntegerOverflow`]: enum.TrapKind.html#variant.IntegerOverflow
+    /// [`TrapKind::UnexpectedSignature`]: enum.TrapKind.html#variant.UnexpectedSignature
+    pub fn as_u32_checked(&self) -> Result<u32, Trap> {
+        match *self {
+            RuntimeValue::I32(v) => Ok(v as u32),
+            RuntimeValue::I64(v) => Trap::check_overflow(u32::try_from(v as u64).ok()),
+            _ => Err(TrapKind::UnexpectedSignature.into()),
+        }
+    }
+
+    /// Narrows an `I64`, interpreted as signed, to an `i32`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TrapKind::IntegerOverflow`] if the value doesn't fit into an `i32`
+    /// and [`TrapKind::UnexpectedSignature`] if this isn't an `I64`.
+    ///
+    /// [`TrapKind::IntegerOverflow`]: enum.TrapKind.html#variant.IntegerOverflow
+    /// [`TrapKind::UnexpectedSignature`]: enum.TrapKind.html#variant.UnexpectedSignature
+    pub fn as_i32_from_i64_checked(&self) -> Result<i32, Trap> {
+        match *self {
+            RuntimeValue::I64(v) => Trap::check_overflow(i32::try_from(v).ok()),
+            _ => Err(TrapKind::UnexpectedSignature.into()),
+        }
+    }
+
+    /// Returns an integer value as `i64`, sign-extending an `I32`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TrapKind::UnexpectedSignature`] for floating point values.
+    ///
+    /// [`TrapKind::UnexpectedSignature`]: enum.TrapKind.html#variant.UnexpectedSignature
+    pub fn as_i64_sign_extended(&self) -> Result<i64, Trap> {
+        match *self {
+            RuntimeValue::I32(v) => Ok(i64::from(v)),
+            RuntimeValue::I64(v) => Ok(v),
+            _ => Err(TrapKind::UnexpectedSignature.into()),
+        }
+    }
+
+    /// Decodes an `I32` as the enum `T`, e.g. an argument of a host function.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TrapKind::InvalidArgument`] if the value isn't the discriminant of a
+    /// variant of `T` and [`TrapKind::UnexpectedSignature`] if this isn't an `I32`.
+    ///
+    /// [`TrapKind::InvalidArgument`]: enum.TrapKind.html#variant.InvalidArgument
+    /// [`TrapKind::UnexpectedSignature`]: enum.TrapKind.html#variant.UnexpectedSignature
+    pub fn as_enum_checked<T: I32Enum>(&self) -> Result<T, Trap> {
+        match *self {
+            RuntimeValue::I32(v) => {
+                T::from_i32(v).ok_or_else(|| TrapKind::InvalidArgument { value: v }.into())
+            }
+            _ => Err(TrapKind::UnexpectedSignature.into()),
+        }
+    }
+
+    /// Returns an `I32` as bitflags, which may only have bits set that are set in
+    /// `known_flags`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TrapKind::InvalidArgument`] if the value has any other bit set and
+    /// [`TrapKind::UnexpectedSignature`] if this isn't an `I32`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use wasmi::RuntimeValue;
+    ///
+    /// const READ: u32 = 1;
+    /// const WRITE: u32 = 2;
+    ///
+    /// let flags = RuntimeValue::I32(3).as_flags_checked(READ | WRITE).unwrap();
+    /// assert_eq!(flags & WRITE, WRITE);
+    /// assert!(RuntimeValue::I32(4).as_flags_checked(READ | WRITE).is_err());
+    /// ```
+    ///
+    /// [`TrapKind::InvalidArgument`]: enum.TrapKind.html#variant.InvalidArgument
+    /// [`TrapKind::UnexpectedSignature`]: enum.TrapKind.html#variant.UnexpectedSignature
+    pub fn as_flags_checked(&self, known_flags: u32) -> Result<u32, Trap> {
+        match *self {
+            RuntimeValue::I32(v) if v as u32 & !known_flags == 0 => Ok(v as u32),
+            RuntimeValue::I32(v) => Err(TrapKind::InvalidArgument { value: v }.into()),
+            _ => Err(TrapKind::UnexpectedSignature.into()),
+        }
+    }
+
+    /// Returns an integer value as `u64`, zero-extending an `I32`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TrapKind::UnexpectedSignature`] for floating point values.
+    ///
+    /// [`TrapKind::UnexpectedSignature`]: enum.TrapKind.html#variant.UnexpectedSignature
+    pub fn as_u64_zero_extended(&self) -> Result<u64, Trap> {
+        match *self {
+            RuntimeValue::I32(v) => Ok(u64::from(v as u32)),
+            RuntimeValue::I64(v) => Ok(v as u64),
+            _ => Err(TrapKind::UnexpectedSignature.into()),
+        }
+    }
 }
 
 impl From<i8> for RuntimeValue {
@@ -241,6 +732,49 @@ impl From<F64> for RuntimeValue {
     }
 }
 
+impl From<f32> for RuntimeValue {
+    fn from(val: f32) -> Self {
+        RuntimeValue::F32(val.into())
+    }
+}
+
+impl From<f64> for RuntimeValue {
+    fn from(val: f64) -> Self {
+        RuntimeValue::F64(val.into())
+    }
+}
+
+impl IntoRuntimeValues for () {
+    fn into_runtime_values(self) -> Vec<RuntimeValue> {
+        Vec::new()
+    }
+}
+
+macro_rules! impl_into_runtime_values {
+    ($($arg: ident),+) => {
+        impl<$($arg: Into<RuntimeValue>),+> IntoRuntimeValues for ($($arg,)+) {
+            #[allow(non_snake_case)]
+            fn into_runtime_values(self) -> Vec<RuntimeValue> {
+                let ($($arg,)+) = self;
+                vec![$($arg.into()),+]
+            }
+        }
+    };
+}
+
+impl_into_runtime_values!(A);
+impl_into_runtime_values!(A, B);
+impl_into_runtime_values!(A, B, C);
+impl_into_runtime_values!(A, B, C, D);
+impl_into_runtime_values!(A, B, C, D, E);
+impl_into_runtime_values!(A, B, C, D, E, F);
+impl_into_runtime_values!(A, B, C, D, E, F, G);
+impl_into_runtime_values!(A, B, C, D, E, F, G, H);
+impl_into_runtime_values!(A, B, C, D, E, F, G, H, I);
+impl_into_runtime_values!(A, B, C, D, E, F, G, H, I, J);
+impl_into_runtime_values!(A, B, C, D, E, F, G, H, I, J, K);
+impl_into_runtime_values!(A, B, C, D, E, F, G, H, I, J, K, L);
+
 macro_rules! impl_from_runtime_value {
     ($expected_rt_ty: ident, $into: ty) => {
         impl FromRuntimeValue for $into {
@@ -352,13 +886,16 @@ impl_wrap_into!(i32, i16);
 impl_wrap_into!(i64, i8);
 impl_wrap_into!(i64, i16);
 impl_wrap_into!(i64, i32);
+#[cfg(not(feature = "soft_float"))]
 impl_wrap_into!(i64, f32, F32);
+#[cfg(not(feature = "soft_float"))]
 impl_wrap_into!(u64, f32, F32);
 // Casting from an f64 to an f32 will produce the closest possible value (rounding strategy unspecified)
 // NOTE: currently this will cause Undefined Behavior if the value is finite but larger or smaller than the
 // largest or smallest finite value representable by f32. This is a bug and will be fixed.
 impl_wrap_into!(f64, f32);
 
+#[cfg(not(feature = "soft_float"))]
 impl WrapInto<F32> for F64 {
     fn wrap_into(self) -> F32 {
         (f64::from(self) as f32).into()
@@ -448,14 +985,21 @@ impl_extend_into!(i64, f64);
 impl_extend_into!(u64, f64);
 impl_extend_into!(f32, f64);
 
+#[cfg(not(feature = "soft_float"))]
 impl_extend_into!(i32, f32, F32);
+#[cfg(not(feature = "soft_float"))]
 impl_extend_into!(i32, f64, F64);
+#[cfg(not(feature = "soft_float"))]
 impl_extend_into!(u32, f32, F32);
+#[cfg(not(feature = "soft_float"))]
 impl_extend_into!(u32, f64, F64);
+#[cfg(not(feature = "soft_float"))]
 impl_extend_into!(i64, f64, F64);
+#[cfg(not(feature = "soft_float"))]
 impl_extend_into!(u64, f64, F64);
 impl_extend_into!(f32, f64, F64);
 
+#[cfg(not(feature = "soft_float"))]
 impl ExtendInto<F64> for F32 {
     fn extend_into(self) -> F64 {
         (f32::from(self) as f64).into()
@@ -683,6 +1227,40 @@ impl LittleEndianConvert for i64 {
     }
 }
 
+impl LittleEndianConvert for i128 {
+    fn into_little_endian(self, buffer: &mut [u8]) {
+        buffer.copy_from_slice(&self.to_le_bytes());
+    }
+
+    fn from_little_endian(buffer: &[u8]) -> Result<Self, Error> {
+        let mut res = [0u8; 16];
+        buffer
+            .get(0..16)
+            .map(|s| {
+                res.copy_from_slice(s);
+                Self::from_le_bytes(res)
+            })
+            .ok_or(Error::InvalidLittleEndianBuffer)
+    }
+}
+
+impl LittleEndianConvert for u128 {
+    fn into_little_endian(self, buffer: &mut [u8]) {
+        buffer.copy_from_slice(&self.to_le_bytes());
+    }
+
+    fn from_little_endian(buffer: &[u8]) -> Result<Self, Error> {
+        let mut res = [0u8; 16];
+        buffer
+            .get(0..16)
+            .map(|s| {
+                res.copy_from_slice(s);
+                Self::from_le_bytes(res)
+            })
+            .ok_or(Error::InvalidLittleEndianBuffer)
+    }
+}
+
 impl LittleEndianConvert for f32 {
     fn into_little_endian(self, buffer: &mut [u8]) {
         buffer.copy_from_slice(&self.to_bits().to_le_bytes());
@@ -791,7 +1369,9 @@ macro_rules! impl_float_arithmetic_ops {
 
 impl_float_arithmetic_ops!(f32);
 impl_float_arithmetic_ops!(f64);
+#[cfg(not(feature = "soft_float"))]
 impl_float_arithmetic_ops!(F32);
+#[cfg(not(feature = "soft_float"))]
 impl_float_arithmetic_ops!(F64);
 
 macro_rules! impl_integer {
@@ -806,6 +1386,8 @@ macro_rules! impl_integer {
             fn count_ones(self) -> $type {
                 self.count_ones() as $type
             }
+            // `rotate_*` reduce the amount modulo the width, as wasm requires. Truncating a
+            // 64-bit amount to `u32` keeps it intact modulo 64.
             fn rotl(self, other: $type) -> $type {
                 self.rotate_left(other as u32)
             }
@@ -863,6 +1445,9 @@ macro_rules! impl_float {
             fn round(self) -> $type {
                 fmath::$fXX::round($fXX::from(self)).into()
             }
+            // Rounds halfway cases to even. `round` rounds them away from zero, so ties it
+            // rounds to an odd integer are rounded towards zero instead, which keeps the
+            // sign of results that are zero.
             fn nearest(self) -> $type {
                 let round = self.round();
                 if fmath::$fXX::fract($fXX::from(self)).abs() != 0.5 {
@@ -882,6 +1467,8 @@ macro_rules! impl_float {
                 fmath::$fXX::sqrt($fXX::from(self)).into()
             }
             // This instruction corresponds to what is sometimes called "minNaN" in other languages.
+            // The host `min` isn't used, since it ignores NaNs and is unspecified for zeros of
+            // both signs.
             fn min(self, other: $type) -> $type {
                 if self.is_nan() {
                     return self;
@@ -889,8 +1476,13 @@ macro_rules! impl_float {
                 if other.is_nan() {
                     return other;
                 }
-
-                self.min(other)
+                let (a, b) = ($fXX::from(self), $fXX::from(other));
+                // Zeros compare equal, but -0 is less than +0 in wasm.
+                if b < a || (b == a && b.is_sign_negative()) {
+                    other
+                } else {
+                    self
+                }
             }
             // This instruction corresponds to what is sometimes called "maxNaN" in other languages.
             fn max(self, other: $type) -> $type {
@@ -900,16 +1492,17 @@ macro_rules! impl_float {
                 if other.is_nan() {
                     return other;
                 }
-
-                self.max(other)
+                let (a, b) = ($fXX::from(self), $fXX::from(other));
+                if b > a || (b == a && b.is_sign_positive()) {
+                    other
+                } else {
+                    self
+                }
             }
             fn copysign(self, other: $type) -> $type {
                 use core::mem::size_of;
 
-                if self.is_nan() {
-                    return self;
-                }
-
+                // Like `neg` and `abs`, this only touches the sign bit, NaNs included.
                 let sign_mask: $iXX = 1 << ((size_of::<$iXX>() << 3) - 1);
                 let self_int: $iXX = self.transmute_into();
                 let other_int: $iXX = other.transmute_into();
@@ -929,7 +1522,9 @@ macro_rules! impl_float {
 
 impl_float!(f32, f32, i32);
 impl_float!(f64, f64, i64);
+#[cfg(not(feature = "soft_float"))]
 impl_float!(F32, f32, i32);
+#[cfg(not(feature = "soft_float"))]
 impl_float!(F64, f64, i64);
 
 #[cfg(not(feature = "std"))]
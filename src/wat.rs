@@ -0,0 +1,395 @@
+use crate::prepare::CompiledModule;
+use crate::Module;
+use alloc::string::String;
+use core::fmt::{self, Write};
+use parity_wasm::elements::{
+    self, BlockType, External, FunctionType, InitExpr, Instruction, Internal, ResizableLimits,
+    TableElementType, Type,
+};
+
+impl CompiledModule {
+    /// Render the module this was compiled from in the WebAssembly text format.
+    pub fn to_wat(&self) -> String {
+        let mut wat = String::new();
+        write_module(&mut wat, &self.module).expect("writing to a String can't fail; qed");
+        wat
+    }
+}
+
+impl Module {
+    /// Render this module in the WebAssembly text format.
+    ///
+    /// This shows the wasm code as it was parsed, not the instructions it was
+    /// compiled to. Items are referred to by their index, written as a comment
+    /// next to each definition, e.g. `(func (;3;) ...)`. Custom sections, names
+    /// included, aren't rendered.
+    ///
+    /// Available with the `wat` feature.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # extern crate wasmi;
+    /// # extern crate wabt;
+    /// let wasm = wabt::wat2wasm(
+    ///     r#"(module (func (export "answer") (result i32) (i32.const 42)))"#,
+    /// ).unwrap();
+    /// let module = wasmi::Module::from_buffer(&wasm).unwrap();
+    ///
+    /// let wat = module.to_wat();
+    /// assert!(wat.contains("i32.const 42"));
+    /// assert_eq!(wabt::wat2wasm(&wat).unwrap(), wasm);
+    /// ```
+    pub fn to_wat(&self) -> String {
+        self.compiled.to_wat()
+    }
+}
+
+fn write_module(out: &mut String, module: &elements::Module) -> fmt::Result {
+    writeln!(out, "(module")?;
+
+    let types = module.type_section().map(|s| s.types()).unwrap_or(&[]);
+    for (idx, Type::Function(func_type)) in types.iter().enumerate() {
+        write!(out, "  (type (;{};) (func", idx)?;
+        write_func_type(out, func_type)?;
+        writeln!(out, "))")?;
+    }
+
+    let mut func_idx = 0;
+    let mut table_idx = 0;
+    let mut memory_idx = 0;
+    let mut global_idx = 0;
+    for entry in module.import_section().map(|s| s.entries()).unwrap_or(&[]) {
+        write!(out, "  (import \"")?;
+        write_string(out, entry.module().as_bytes())?;
+        write!(out, "\" \"")?;
+        write_string(out, entry.field().as_bytes())?;
+        write!(out, "\" ")?;
+        match *entry.external() {
+            External::Function(type_idx) => {
+                write!(out, "(func (;{};) (type {}))", func_idx, type_idx)?;
+                func_idx += 1;
+            }
+            External::Table(ref table_type) => {
+                write!(out, "(table (;{};)", table_idx)?;
+                write_limits(out, table_type.limits())?;
+                write_elem_type(out, table_type.elem_type())?;
+                write!(out, ")")?;
+                table_idx += 1;
+            }
+            External::Memory(ref memory_type) => {
+                write!(out, "(memory (;{};)", memory_idx)?;
+                write_limits(out, memory_type.limits())?;
+                write!(out, ")")?;
+                memory_idx += 1;
+            }
+            External::Global(ref global_type) => {
+                write!(out, "(global (;{};) ", global_idx)?;
+                write_global_type(out, global_type)?;
+                write!(out, ")")?;
+                global_idx += 1;
+            }
+        }
+        writeln!(out, ")")?;
+    }
+
+    let funcs = module
+        .function_section()
+        .map(|s| s.entries())
+        .unwrap_or(&[]);
+    let bodies = module.code_section().map(|s| s.bodies()).unwrap_or(&[]);
+    for (func, body) in funcs.iter().zip(bodies) {
+        write!(out, "  (func (;{};) (type {})", func_idx, func.type_ref())?;
+        if let Some(Type::Function(func_type)) = types.get(func.type_ref() as usize) {
+            write_func_type(out, func_type)?;
+        }
+        writeln!(out)?;
+        for local in body.locals() {
+            write!(out, "    (local")?;
+            for _ in 0..local.count() {
+                write!(out, " {}", local.value_type())?;
+            }
+            writeln!(out, ")")?;
+        }
+        write_body(out, body.code().elements())?;
+        writeln!(out, "  )")?;
+        func_idx += 1;
+    }
+
+    for table_type in module.table_section().map(|s| s.entries()).unwrap_or(&[]) {
+        write!(out, "  (table (;{};)", table_idx)?;
+        write_limits(out, table_type.limits())?;
+        write_elem_type(out, table_type.elem_type())?;
+        writeln!(out, ")")?;
+        table_idx += 1;
+    }
+
+    for memory_type in module.memory_section().map(|s| s.entries()).unwrap_or(&[]) {
+        write!(out, "  (memory (;{};)", memory_idx)?;
+        write_limits(out, memory_type.limits())?;
+        writeln!(out, ")")?;
+        memory_idx += 1;
+    }
+
+    for global in module.global_section().map(|s| s.entries()).unwrap_or(&[]) {
+        write!(out, "  (global (;{};) ", global_idx)?;
+        write_global_type(out, global.global_type())?;
+        write_init_expr(out, global.init_expr())?;
+        writeln!(out, ")")?;
+        global_idx += 1;
+    }
+
+    for entry in module.export_section().map(|s| s.entries()).unwrap_or(&[]) {
+        write!(out, "  (export \"")?;
+        write_string(out, entry.field().as_bytes())?;
+        let (kind, idx) = match *entry.internal() {
+            Internal::Function(idx) => ("func", idx),
+            Internal::Table(idx) => ("table", idx),
+            Internal::Memory(idx) => ("memory", idx),
+            Internal::Global(idx) => ("global", idx),
+        };
+        writeln!(out, "\" ({} {}))", kind, idx)?;
+    }
+
+    if let Some(start) = module.start_section() {
+        writeln!(out, "  (start {})", start)?;
+    }
+
+    let segments = module
+        .elements_section()
+        .map(|s| s.entries())
+        .unwrap_or(&[]);
+    for (idx, segment) in segments.iter().enumerate() {
+        write!(out, "  (elem (;{};)", idx)?;
+        if segment.index() != 0 {
+            write!(out, " {}", segment.index())?;
+        }
+        if let Some(offset) = segment.offset() {
+            write_init_expr(out, offset)?;
+        }
+        for member in segment.members() {
+            write!(out, " {}", member)?;
+        }
+        writeln!(out, ")")?;
+    }
+
+    let segments = module.data_section().map(|s| s.entries()).unwrap_or(&[]);
+    for (idx, segment) in segments.iter().enumerate() {
+        write!(out, "  (data (;{};)", idx)?;
+        if segment.index() != 0 {
+            write!(out, " {}", segment.index())?;
+        }
+        if let Some(offset) = segment.offset() {
+            write_init_expr(out, offset)?;
+        }
+        write!(out, " \"")?;
+        write_string(out, segment.value())?;
+        writeln!(out, "\")")?;
+    }
+
+    write!(out, ")")
+}
+
+fn write_func_type(out: &mut String, func_type: &FunctionType) -> fmt::Result {
+    if !func_type.params().is_empty() {
+        write!(out, " (param")?;
+        for param in func_type.params() {
+            write!(out, " {}", param)?;
+        }
+        write!(out, ")")?;
+    }
+    if !func_type.results().is_empty() {
+        write!(out, " (result")?;
+        for result in func_type.results() {
+            write!(out, " {}", result)?;
+        }
+        write!(out, ")")?;
+    }
+    Ok(())
+}
+
+fn write_limits(out: &mut String, limits: &ResizableLimits) -> fmt::Result {
+    write!(out, " {}", limits.initial())?;
+    if let Some(maximum) = limits.maximum() {
+        write!(out, " {}", maximum)?;
+    }
+    #[cfg(feature = "atomics")]
+    if limits.shared() {
+        write!(out, " shared")?;
+    }
+    Ok(())
+}
+
+fn write_elem_type(out: &mut String, elem_type: TableElementType) -> fmt::Result {
+    match elem_type {
+        TableElementType::AnyFunc => write!(out, " funcref"),
+    }
+}
+
+fn write_global_type(out: &mut String, global_type: &elements::GlobalType) -> fmt::Result {
+    if global_type.is_mutable() {
+        write!(out, "(mut {})", global_type.content_type())
+    } else {
+        write!(out, "{}", global_type.content_type())
+    }
+}
+
+/// Writes ` (instr)` for each instruction of `init_expr`, except the final `end`.
+fn write_init_expr(out: &mut String, init_expr: &InitExpr) -> fmt::Result {
+    for instruction in init_expr.code() {
+        if *instruction != Instruction::End {
+            write!(out, " (")?;
+            write_instruction(out, instruction)?;
+            write!(out, ")")?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes the instructions of a function body, one per line, indented by their nesting.
+fn write_body(out: &mut String, code: &[Instruction]) -> fmt::Result {
+    let mut depth = 0usize;
+    // The final `end` closes the function itself.
+    let code = match code.split_last() {
+        Some((Instruction::End, rest)) => rest,
+        _ => code,
+    };
+    for instruction in code {
+        if *instruction == Instruction::End {
+            depth = depth.saturating_sub(1);
+        }
+        let indent = match *instruction {
+            Instruction::Else => depth.saturating_sub(1),
+            _ => depth,
+        };
+        for _ in 0..indent + 2 {
+            write!(out, "  ")?;
+        }
+        write_instruction(out, instruction)?;
+        writeln!(out)?;
+        if let Instruction::Block(_) | Instruction::Loop(_) | Instruction::If(_) = *instruction {
+            depth += 1;
+        }
+    }
+    Ok(())
+}
+
+fn write_instruction(out: &mut String, instruction: &Instruction) -> fmt::Result {
+    match *instruction {
+        Instruction::Block(block_type) => write_block(out, "block", block_type),
+        Instruction::Loop(block_type) => write_block(out, "loop", block_type),
+        Instruction::If(block_type) => write_block(out, "if", block_type),
+        Instruction::BrTable(ref table) => {
+            write!(out, "br_table")?;
+            for target in table.table.iter() {
+                write!(out, " {}", target)?;
+            }
+            write!(out, " {}", table.default)
+        }
+        Instruction::CallIndirect(type_idx, table_idx) => {
+            write!(out, "call_indirect")?;
+            if table_idx != 0 {
+                write!(out, " {}", table_idx)?;
+            }
+            write!(out, " (type {})", type_idx)
+        }
+        Instruction::F32Const(bits) => write!(out, "f32.const {}", F32Bits(bits)),
+        Instruction::F64Const(bits) => write!(out, "f64.const {}", F64Bits(bits)),
+        _ => {
+            write!(out, "{}", instruction)?;
+            if let Some(align) = memarg_align(instruction) {
+                write!(out, " align={}", 1u64 << align)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+fn write_block(out: &mut String, mnemonic: &str, block_type: BlockType) -> fmt::Result {
+    match block_type {
+        BlockType::NoResult => write!(out, "{}", mnemonic),
+        BlockType::Value(value_type) => write!(out, "{} (result {})", mnemonic, value_type),
+    }
+}
+
+/// Returns the alignment exponent of a load or store.
+///
+/// The `Display` impl of parity-wasm writes the offset of these instructions, but not
+/// their alignment.
+fn memarg_align(instruction: &Instruction) -> Option<u32> {
+    use self::Instruction::*;
+
+    match *instruction {
+        I32Load(align, _)
+        | I64Load(align, _)
+        | F32Load(align, _)
+        | F64Load(align, _)
+        | I32Load8S(align, _)
+        | I32Load8U(align, _)
+        | I32Load16S(align, _)
+        | I32Load16U(align, _)
+        | I64Load8S(align, _)
+        | I64Load8U(align, _)
+        | I64Load16S(align, _)
+        | I64Load16U(align, _)
+        | I64Load32S(align, _)
+        | I64Load32U(align, _)
+        | I32Store(align, _)
+        | I64Store(align, _)
+        | F32Store(align, _)
+        | F64Store(align, _)
+        | I32Store8(align, _)
+        | I32Store16(align, _)
+        | I64Store8(align, _)
+        | I64Store16(align, _)
+        | I64Store32(align, _) => Some(align),
+        _ => None,
+    }
+}
+
+/// Writes `bytes` as the contents of a string literal, escaping everything but printable ASCII.
+fn write_string(out: &mut String, bytes: &[u8]) -> fmt::Result {
+    for &byte in bytes {
+        match byte {
+            b'"' | b'\\' => write!(out, "\\{}", byte as char)?,
+            0x20..=0x7e => out.push(byte as char),
+            _ => write!(out, "\\{:02x}", byte)?,
+        }
+    }
+    Ok(())
+}
+
+/// Formats the bits of an `f32` such that parsing the text gives the same bits back.
+struct F32Bits(u32);
+
+impl fmt::Display for F32Bits {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let value = f32::from_bits(self.0);
+        let sign = if self.0 >> 31 != 0 { "-" } else { "" };
+        if value.is_nan() {
+            write!(f, "{}nan:0x{:x}", sign, self.0 & 0x007f_ffff)
+        } else if value.is_infinite() {
+            write!(f, "{}inf", sign)
+        } else {
+            // Rust writes the shortest representation that rounds back to the same value.
+            write!(f, "{:?}", value)
+        }
+    }
+}
+
+/// Formats the bits of an `f64` such that parsing the text gives the same bits back.
+struct F64Bits(u64);
+
+impl fmt::Display for F64Bits {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let value = f64::from_bits(self.0);
+        let sign = if self.0 >> 63 != 0 { "-" } else { "" };
+        if value.is_nan() {
+            write!(f, "{}nan:0x{:x}", sign, self.0 & 0x000f_ffff_ffff_ffff)
+        } else if value.is_infinite() {
+            write!(f, "{}inf", sign)
+        } else {
+            write!(f, "{:?}", value)
+        }
+    }
+}
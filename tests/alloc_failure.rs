@@ -0,0 +1,101 @@
+//! Compilation and instantiation must report allocation failures instead of aborting.
+//!
+//! This lives in its own test binary since it installs a global allocator.
+
+extern crate parity_wasm;
+extern crate wasmi;
+
+use parity_wasm::elements::Instruction;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use wasmi::{Module, ModuleBuilder, Signature};
+
+/// Allocations of this size or larger fail while `FAIL_LARGE_ALLOCS` is set.
+const LARGE_ALLOC: usize = 1024 * 1024;
+
+static FAIL_LARGE_ALLOCS: AtomicBool = AtomicBool::new(false);
+
+/// Serializes the tests, since `FAIL_LARGE_ALLOCS` affects all of them.
+static SERIAL: Mutex<()> = Mutex::new(());
+
+struct FailingAlloc;
+
+unsafe impl GlobalAlloc for FailingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if layout.size() >= LARGE_ALLOC && FAIL_LARGE_ALLOCS.load(Ordering::SeqCst) {
+            return std::ptr::null_mut();
+        }
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        if new_size >= LARGE_ALLOC && FAIL_LARGE_ALLOCS.load(Ordering::SeqCst) {
+            return std::ptr::null_mut();
+        }
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: FailingAlloc = FailingAlloc;
+
+#[test]
+fn compilation_reports_allocation_failure() {
+    let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+
+    // Enough instructions for the compiled code to exceed `LARGE_ALLOC`.
+    let mut body = Vec::new();
+    for _ in 0..100_000 {
+        body.push(Instruction::I32Const(0));
+        body.push(Instruction::Drop);
+    }
+    let parity_module = ModuleBuilder::new()
+        .with_func(&Signature::new(&[][..], None), &[], body)
+        .build();
+
+    let cloned_module = parity_module.clone();
+    FAIL_LARGE_ALLOCS.store(true, Ordering::SeqCst);
+    let result = Module::from_parity_wasm_module(cloned_module);
+    FAIL_LARGE_ALLOCS.store(false, Ordering::SeqCst);
+
+    let err = result.err().expect("compilation should fail");
+    assert!(format!("{}", err).contains("Failed to allocate"));
+
+    // The same module compiles fine once large allocations are allowed again.
+    Module::from_parity_wasm_module(parity_module).expect("compilation should succeed");
+}
+
+// Memories are only allocated with the global allocator if they are based on a vector.
+#[cfg(any(not(unix), feature = "vec_memory"))]
+#[test]
+fn instantiation_reports_memory_allocation_failure() {
+    use wasmi::memory_units::Pages;
+    use wasmi::{Error, ImportsBuilder, ModuleInstance};
+
+    let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+
+    // 32 pages are 2MiB, which exceeds `LARGE_ALLOC`.
+    let parity_module = ModuleBuilder::new().with_memory(32, None).build();
+    let module =
+        Module::from_parity_wasm_module(parity_module).expect("compilation should succeed");
+
+    FAIL_LARGE_ALLOCS.store(true, Ordering::SeqCst);
+    let result = ModuleInstance::new(&module, &ImportsBuilder::default());
+    FAIL_LARGE_ALLOCS.store(false, Ordering::SeqCst);
+
+    match result {
+        Err(Error::MemoryAllocationFailed { requested_pages }) => {
+            assert_eq!(requested_pages, Pages(32))
+        }
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("instantiation should fail"),
+    }
+
+    // The same module instantiates fine once large allocations are allowed again.
+    ModuleInstance::new(&module, &ImportsBuilder::default()).expect("instantiation should succeed");
+}
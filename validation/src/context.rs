@@ -1,4 +1,4 @@
-use crate::Error;
+use crate::{Error, ErrorKind};
 use alloc::vec::Vec;
 use parity_wasm::elements::{
     BlockType, FunctionType, GlobalType, MemoryType, TableType, ValueType,
@@ -36,30 +36,40 @@ impl ModuleContext {
 
     pub fn require_memory(&self, idx: u32) -> Result<(), Error> {
         if self.memories().get(idx as usize).is_none() {
-            return Err(Error(format!("Memory at index {} doesn't exists", idx)));
+            return Err(Error::new(
+                ErrorKind::UndeclaredIndex,
+                format!("Memory at index {} doesn't exists", idx),
+            ));
         }
         Ok(())
     }
 
     pub fn require_table(&self, idx: u32) -> Result<&TableType, Error> {
-        self.tables()
-            .get(idx as usize)
-            .ok_or_else(|| Error(format!("Table at index {} doesn't exists", idx)))
+        self.tables().get(idx as usize).ok_or_else(|| {
+            Error::new(
+                ErrorKind::UndeclaredIndex,
+                format!("Table at index {} doesn't exists", idx),
+            )
+        })
     }
 
     pub fn require_function(&self, idx: u32) -> Result<(&[ValueType], BlockType), Error> {
-        let ty_idx = self
-            .func_type_indexes()
-            .get(idx as usize)
-            .ok_or_else(|| Error(format!("Function at index {} doesn't exists", idx)))?;
+        let ty_idx = self.func_type_indexes().get(idx as usize).ok_or_else(|| {
+            Error::new(
+                ErrorKind::UndeclaredIndex,
+                format!("Function at index {} doesn't exists", idx),
+            )
+        })?;
         self.require_function_type(*ty_idx)
     }
 
     pub fn require_function_type(&self, idx: u32) -> Result<(&[ValueType], BlockType), Error> {
-        let ty = self
-            .types()
-            .get(idx as usize)
-            .ok_or_else(|| Error(format!("Type at index {} doesn't exists", idx)))?;
+        let ty = self.types().get(idx as usize).ok_or_else(|| {
+            Error::new(
+                ErrorKind::UndeclaredIndex,
+                format!("Type at index {} doesn't exists", idx),
+            )
+        })?;
 
         let params = ty.params();
         let return_ty = ty
@@ -71,17 +81,25 @@ impl ModuleContext {
     }
 
     pub fn require_global(&self, idx: u32, mutability: Option<bool>) -> Result<&GlobalType, Error> {
-        let global = self
-            .globals()
-            .get(idx as usize)
-            .ok_or_else(|| Error(format!("Global at index {} doesn't exists", idx)))?;
+        let global = self.globals().get(idx as usize).ok_or_else(|| {
+            Error::new(
+                ErrorKind::UndeclaredIndex,
+                format!("Global at index {} doesn't exists", idx),
+            )
+        })?;
 
         if let Some(expected_mutable) = mutability {
             if expected_mutable && !global.is_mutable() {
-                return Err(Error(format!("Expected global {} to be mutable", idx)));
+                return Err(Error::new(
+                    ErrorKind::TypeMismatch,
+                    format!("Expected global {} to be mutable", idx),
+                ));
             }
             if !expected_mutable && global.is_mutable() {
-                return Err(Error(format!("Expected global {} to be immutable", idx)));
+                return Err(Error::new(
+                    ErrorKind::TypeMismatch,
+                    format!("Expected global {} to be immutable", idx),
+                ));
             }
         }
         Ok(global)
@@ -1,9 +1,11 @@
 use crate::{
-    context::ModuleContext, stack::StackWithLimit, util::Locals, Error, FuncValidator,
-    DEFAULT_MEMORY_INDEX, DEFAULT_TABLE_INDEX,
+    context::ModuleContext, stack::StackWithLimit, util::Locals, Error, ErrorKind, FuncValidator,
+    DEFAULT_MEMORY_INDEX,
 };
 
 use core::u32;
+#[cfg(feature = "atomics")]
+use parity_wasm::elements::AtomicsInstruction;
 use parity_wasm::elements::{BlockType, Func, FuncBody, Instruction, TableElementType, ValueType};
 
 /// Maximum number of entries in value stack per function.
@@ -92,7 +94,10 @@ pub fn drive<T: FuncValidator>(
     let code = body.code().elements();
     let code_len = code.len();
     if code_len == 0 {
-        return Err(Error("Non-empty function body expected".into()));
+        return Err(Error::new(
+            ErrorKind::MalformedSection,
+            "Non-empty function body expected",
+        ));
     }
 
     let mut context = FunctionValidationContext::new(
@@ -103,17 +108,12 @@ pub fn drive<T: FuncValidator>(
         result_ty,
     )?;
 
-    let mut validator = T::new(&context, body);
+    let mut validator = T::new(&context, body)?;
 
     for (position, instruction) in code.iter().enumerate() {
         validator
             .next_instruction(&mut context, instruction)
-            .map_err(|err| {
-                Error(format!(
-                    "At instruction {:?}(@{}): {}",
-                    instruction, position, err
-                ))
-            })?;
+            .map_err(|err| err.at_instruction(position, instruction))?;
     }
 
     // The last `end` opcode should pop last instruction.
@@ -206,7 +206,7 @@ impl<'a> FunctionValidationContext<'a> {
                 let block_type = {
                     let top = top_label(&self.frame_stack);
                     if top.started_with != StartedWith::If {
-                        return Err(Error("Misplaced else instruction".into()));
+                        return Err(Error::new(ErrorKind::Other, "Misplaced else instruction"));
                     }
                     top.block_type
                 };
@@ -228,7 +228,7 @@ impl<'a> FunctionValidationContext<'a> {
                     if top.started_with == StartedWith::If && top.block_type != BlockType::NoResult
                     {
                         // A `if` without an `else` can't return a result.
-                        return Err(Error(format!(
+                        return Err(Error::new(ErrorKind::TypeMismatch, format!(
                             "If block without else required to have NoResult block type. But it has {:?} type",
                             top.block_type
                         )));
@@ -282,8 +282,8 @@ impl<'a> FunctionValidationContext<'a> {
             Call(index) => {
                 self.validate_call(index)?;
             }
-            CallIndirect(index, _reserved) => {
-                self.validate_call_indirect(index)?;
+            CallIndirect(index, table) => {
+                self.validate_call_indirect(index, table.into())?;
             }
 
             Drop => {
@@ -778,11 +778,58 @@ impl<'a> FunctionValidationContext<'a> {
             F64ReinterpretI64 => {
                 self.validate_cvtop(ValueType::I64, ValueType::F64)?;
             }
+
+            #[cfg(feature = "atomics")]
+            Atomics(ref instruction) => {
+                self.validate_atomic(instruction)?;
+            }
         }
 
         Ok(())
     }
 
+    /// Validate an instruction of the threads proposal.
+    ///
+    /// Only `atomic.wait` and `atomic.notify` are supported.
+    #[cfg(feature = "atomics")]
+    fn validate_atomic(&mut self, instruction: &AtomicsInstruction) -> Result<(), Error> {
+        let (memarg, natural_align, operands): (_, _, &[ValueType]) = match *instruction {
+            AtomicsInstruction::AtomicWake(ref memarg) => {
+                (memarg, 2, &[ValueType::I32, ValueType::I32])
+            }
+            AtomicsInstruction::I32AtomicWait(ref memarg) => {
+                (memarg, 2, &[ValueType::I32, ValueType::I32, ValueType::I64])
+            }
+            AtomicsInstruction::I64AtomicWait(ref memarg) => {
+                (memarg, 3, &[ValueType::I32, ValueType::I64, ValueType::I64])
+            }
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::UnsupportedFeature,
+                    format!("Atomic instruction {:?} is not supported", instruction),
+                ));
+            }
+        };
+
+        // Unlike other memory accesses, atomic accesses must specify the natural alignment.
+        if memarg.align != natural_align {
+            return Err(Error::new(
+                ErrorKind::Other,
+                format!(
+                    "Atomic memory alignment must be 2^{}, got 2^{}",
+                    natural_align, memarg.align
+                ),
+            ));
+        }
+
+        self.module.require_memory(DEFAULT_MEMORY_INDEX)?;
+        for &operand in operands.iter().rev() {
+            pop_value(&mut self.value_stack, &self.frame_stack, operand.into())?;
+        }
+        push_value(&mut self.value_stack, ValueType::I32.into())?;
+        Ok(())
+    }
+
     fn validate_const(&mut self, value_type: ValueType) -> Result<(), Error> {
         push_value(&mut self.value_stack, value_type.into())?;
         Ok(())
@@ -833,6 +880,10 @@ impl<'a> FunctionValidationContext<'a> {
         Ok(())
     }
 
+    /// Validate an untyped `select`.
+    ///
+    /// Untyped `select` is only valid for numeric operands. The operands aren't checked
+    /// for that, since reference types aren't supported and thus every value is numeric.
     fn validate_select(&mut self) -> Result<(), Error> {
         pop_value(
             &mut self.value_stack,
@@ -863,10 +914,13 @@ impl<'a> FunctionValidationContext<'a> {
             StackValueType::Any,
         )?;
         if local_type != value_type {
-            return Err(Error(format!(
-                "Trying to update local {} of type {:?} with value of type {:?}",
-                index, local_type, value_type
-            )));
+            return Err(Error::new(
+                ErrorKind::TypeMismatch,
+                format!(
+                    "Trying to update local {} of type {:?} with value of type {:?}",
+                    index, local_type, value_type
+                ),
+            ));
         }
         Ok(())
     }
@@ -897,10 +951,13 @@ impl<'a> FunctionValidationContext<'a> {
             StackValueType::Any,
         )?;
         if global_type != value_type {
-            return Err(Error(format!(
-                "Trying to update global {} of type {:?} with value of type {:?}",
-                index, global_type, value_type
-            )));
+            return Err(Error::new(
+                ErrorKind::TypeMismatch,
+                format!(
+                    "Trying to update global {} of type {:?} with value of type {:?}",
+                    index, global_type, value_type
+                ),
+            ));
         }
         Ok(())
     }
@@ -912,10 +969,13 @@ impl<'a> FunctionValidationContext<'a> {
         value_type: ValueType,
     ) -> Result<(), Error> {
         if 1u32.checked_shl(align).unwrap_or(u32::MAX) > max_align {
-            return Err(Error(format!(
-                "Too large memory alignment 2^{} (expected at most {})",
-                align, max_align
-            )));
+            return Err(Error::new(
+                ErrorKind::Other,
+                format!(
+                    "Too large memory alignment 2^{} (expected at most {})",
+                    align, max_align
+                ),
+            ));
         }
 
         pop_value(
@@ -935,10 +995,13 @@ impl<'a> FunctionValidationContext<'a> {
         value_type: ValueType,
     ) -> Result<(), Error> {
         if 1u32.checked_shl(align).unwrap_or(u32::MAX) > max_align {
-            return Err(Error(format!(
-                "Too large memory alignment 2^{} (expected at most {})",
-                align, max_align
-            )));
+            return Err(Error::new(
+                ErrorKind::Other,
+                format!(
+                    "Too large memory alignment 2^{} (expected at most {})",
+                    align, max_align
+                ),
+            ));
         }
 
         self.module.require_memory(DEFAULT_MEMORY_INDEX)?;
@@ -1000,10 +1063,13 @@ impl<'a> FunctionValidationContext<'a> {
                     label_block.block_type
                 };
                 if required_block_type != label_block_type {
-                    return Err(Error(format!(
-                        "Labels in br_table points to block of different types: {:?} and {:?}",
-                        required_block_type, label_block.block_type
-                    )));
+                    return Err(Error::new(
+                        ErrorKind::TypeMismatch,
+                        format!(
+                            "Labels in br_table points to block of different types: {:?} and {:?}",
+                            required_block_type, label_block.block_type
+                        ),
+                    ));
                 }
             }
             required_block_type
@@ -1036,15 +1102,18 @@ impl<'a> FunctionValidationContext<'a> {
         Ok(())
     }
 
-    fn validate_call_indirect(&mut self, idx: u32) -> Result<(), Error> {
+    fn validate_call_indirect(&mut self, idx: u32, table_idx: u32) -> Result<(), Error> {
         {
-            let table = self.module.require_table(DEFAULT_TABLE_INDEX)?;
+            let table = self.module.require_table(table_idx)?;
             if table.elem_type() != TableElementType::AnyFunc {
-                return Err(Error(format!(
-                    "Table {} has element type {:?} while `anyfunc` expected",
-                    idx,
-                    table.elem_type()
-                )));
+                return Err(Error::new(
+                    ErrorKind::TypeMismatch,
+                    format!(
+                        "Table {} has element type {:?} while `anyfunc` expected",
+                        table_idx,
+                        table.elem_type()
+                    ),
+                ));
             }
         }
 
@@ -1121,7 +1190,10 @@ fn pop_value(
             .expect("at least 1 topmost block")
             .value_stack_len;
         if value_stack.len() <= value_stack_min {
-            return Err(Error("Trying to access parent frame stack values.".into()));
+            return Err(Error::new(
+                ErrorKind::StackHeightMismatch,
+                "Trying to access parent frame stack values.",
+            ));
         }
         value_stack.pop()?
     };
@@ -1130,10 +1202,13 @@ fn pop_value(
             Ok(actual_value)
         }
         StackValueType::Any => Ok(actual_value),
-        stack_value_type => Err(Error(format!(
-            "Expected value of type {:?} on top of stack. Got {:?}",
-            expected_value_ty, stack_value_type
-        ))),
+        stack_value_type => Err(Error::new(
+            ErrorKind::TypeMismatch,
+            format!(
+                "Expected value of type {:?} on top of stack. Got {:?}",
+                expected_value_ty, stack_value_type
+            ),
+        )),
     }
 }
 
@@ -1183,11 +1258,14 @@ fn pop_label(
 
     let frame = frame_stack.pop()?;
     if value_stack.len() != frame.value_stack_len {
-        return Err(Error(format!(
-            "Unexpected stack height {}, expected {}",
-            value_stack.len(),
-            frame.value_stack_len
-        )));
+        return Err(Error::new(
+            ErrorKind::StackHeightMismatch,
+            format!(
+                "Unexpected stack height {}, expected {}",
+                value_stack.len(),
+                frame.value_stack_len
+            ),
+        ));
     }
 
     Ok(())
@@ -1210,7 +1288,9 @@ pub fn require_label(
     depth: u32,
     frame_stack: &StackWithLimit<BlockFrame>,
 ) -> Result<&BlockFrame, Error> {
-    Ok(frame_stack.get(depth as usize)?)
+    frame_stack
+        .get(depth as usize)
+        .map_err(|e| Error::new(ErrorKind::UndeclaredIndex, format!("Stack: {}", e)))
 }
 
 fn require_local(locals: &Locals, idx: u32) -> Result<ValueType, Error> {
@@ -25,9 +25,11 @@ use core::fmt;
 use std::error;
 
 use self::context::ModuleContextBuilder;
+use memory_units::wasm32::Pages;
 use parity_wasm::elements::{
     BlockType, ExportEntry, External, FuncBody, GlobalEntry, GlobalType, InitExpr, Instruction,
-    Internal, MemoryType, Module, ResizableLimits, TableType, Type, ValueType,
+    Internal, MemoryType, Module, ResizableLimits, Section, TableElementType, TableType, Type,
+    ValueType,
 };
 
 pub mod context;
@@ -37,34 +39,129 @@ pub mod util;
 #[cfg(test)]
 mod tests;
 
+/// Category of the rule a module violates, see [`Error::kind`].
+///
+/// [`Error::kind`]: struct.Error.html#method.kind
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// A value, an expression or a function has another type than required.
+    TypeMismatch,
+    /// An index refers to a function, type, local, global, table, memory or label
+    /// that isn't declared.
+    UndeclaredIndex,
+    /// The value stack doesn't have the height required, e.g. an instruction is
+    /// missing operands or a block leaves values behind.
+    StackHeightMismatch,
+    /// The sections of the module are malformed or inconsistent with each other.
+    MalformedSection,
+    /// The module uses a feature that isn't supported.
+    UnsupportedFeature,
+    /// Any other rule, e.g. a limit or a duplicate export.
+    Other,
+}
+
 // TODO: Consider using a type other than String, because
 // of formatting machinary is not welcomed in substrate runtimes.
+/// Error returned when a module is invalid.
+///
+/// Besides the message, it records the category of the rule that was violated
+/// and, for errors in function bodies, where the violation is.
 #[derive(Debug)]
-pub struct Error(pub String);
+pub struct Error {
+    kind: ErrorKind,
+    message: String,
+    func_body: Option<u32>,
+    instruction: Option<u32>,
+}
+
+impl Error {
+    /// Create an error of the given `kind`, not attributed to a function.
+    pub fn new<M: Into<String>>(kind: ErrorKind, message: M) -> Error {
+        Error {
+            kind,
+            message: message.into(),
+            func_body: None,
+            instruction: None,
+        }
+    }
+
+    /// Returns the category of the violated rule.
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// Returns the index of the function body the error is in, if any.
+    ///
+    /// This is the index in the code section, which doesn't count the imported
+    /// functions, unlike the function index space.
+    pub fn func_body(&self) -> Option<u32> {
+        self.func_body
+    }
+
+    /// Returns the index of the instruction the error is at, in the function body
+    /// returned by [`func_body`], if any.
+    ///
+    /// [`func_body`]: #method.func_body
+    pub fn instruction(&self) -> Option<u32> {
+        self.instruction
+    }
+
+    /// Attribute the error to the instruction `instruction` at the index `position`.
+    pub(crate) fn at_instruction(self, position: usize, instruction: &Instruction) -> Error {
+        Error {
+            message: format!(
+                "At instruction {:?}(@{}): {}",
+                instruction, position, self.message
+            ),
+            instruction: Some(position as u32),
+            ..self
+        }
+    }
+
+    /// Attribute the error to the function body with the index `func_body`.
+    pub(crate) fn in_function(self, func_body: u32) -> Error {
+        Error {
+            message: format!(
+                "Function #{} reading/validation error: {}",
+                func_body, self.message
+            ),
+            func_body: Some(func_body),
+            ..self
+        }
+    }
+}
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.0)
+        write!(f, "{}", self.message)
     }
 }
 
 #[cfg(feature = "std")]
 impl error::Error for Error {
     fn description(&self) -> &str {
-        &self.0
+        &self.message
     }
 }
 
 impl From<stack::Error> for Error {
     fn from(e: stack::Error) -> Error {
-        Error(format!("Stack: {}", e))
+        let kind = if e.is_limit_exceeded() {
+            ErrorKind::Other
+        } else {
+            ErrorKind::StackHeightMismatch
+        };
+        Error::new(kind, format!("Stack: {}", e))
     }
 }
 
-pub trait Validator {
+pub trait Validator: Sized {
     type Output;
     type FuncValidator: FuncValidator;
-    fn new(module: &Module) -> Self;
+    /// Create a validator for the `module`.
+    ///
+    /// Returns `Err` if the validator can't be created, e.g. if its buffers can't be allocated.
+    fn new(module: &Module) -> Result<Self, Error>;
     fn on_function_validated(
         &mut self,
         index: u32,
@@ -73,9 +170,12 @@ pub trait Validator {
     fn finish(self) -> Self::Output;
 }
 
-pub trait FuncValidator {
+pub trait FuncValidator: Sized {
     type Output;
-    fn new(ctx: &func::FunctionValidationContext, body: &FuncBody) -> Self;
+    /// Create a validator for the function `body`.
+    ///
+    /// Returns `Err` if the validator can't be created, e.g. if its buffers can't be allocated.
+    fn new(ctx: &func::FunctionValidationContext, body: &FuncBody) -> Result<Self, Error>;
     fn next_instruction(
         &mut self,
         ctx: &mut func::FunctionValidationContext,
@@ -90,8 +190,8 @@ pub struct PlainValidator;
 impl Validator for PlainValidator {
     type Output = ();
     type FuncValidator = PlainFuncValidator;
-    fn new(_module: &Module) -> PlainValidator {
-        PlainValidator
+    fn new(_module: &Module) -> Result<PlainValidator, Error> {
+        Ok(PlainValidator)
     }
     fn on_function_validated(
         &mut self,
@@ -108,8 +208,11 @@ pub struct PlainFuncValidator;
 impl FuncValidator for PlainFuncValidator {
     type Output = ();
 
-    fn new(_ctx: &func::FunctionValidationContext, _body: &FuncBody) -> PlainFuncValidator {
-        PlainFuncValidator
+    fn new(
+        _ctx: &func::FunctionValidationContext,
+        _body: &FuncBody,
+    ) -> Result<PlainFuncValidator, Error> {
+        Ok(PlainFuncValidator)
     }
 
     fn next_instruction(
@@ -126,7 +229,7 @@ impl FuncValidator for PlainFuncValidator {
 pub fn validate_module<V: Validator>(module: &Module) -> Result<V::Output, Error> {
     let mut context_builder = ModuleContextBuilder::new();
     let mut imported_globals = Vec::new();
-    let mut validation = V::new(module);
+    let mut validation = V::new(module)?;
 
     // Copy types from module as is.
     context_builder.set_types(
@@ -142,6 +245,8 @@ pub fn validate_module<V: Validator>(module: &Module) -> Result<V::Output, Error
             .unwrap_or_default(),
     );
 
+    let types_len = module.type_section().map_or(0, |ts| ts.types().len());
+
     // Fill elements with imported values.
     for import_entry in module
         .import_section()
@@ -149,7 +254,16 @@ pub fn validate_module<V: Validator>(module: &Module) -> Result<V::Output, Error
         .unwrap_or_default()
     {
         match *import_entry.external() {
-            External::Function(idx) => context_builder.push_func_type_index(idx),
+            External::Function(idx) => {
+                validate_type_index(idx, types_len, || {
+                    format!(
+                        "imported function {}.{}",
+                        import_entry.module(),
+                        import_entry.field()
+                    )
+                })?;
+                context_builder.push_func_type_index(idx)
+            }
             External::Table(ref table) => context_builder.push_table(*table),
             External::Memory(ref memory) => context_builder.push_memory(*memory),
             External::Global(ref global) => {
@@ -161,7 +275,10 @@ pub fn validate_module<V: Validator>(module: &Module) -> Result<V::Output, Error
 
     // Concatenate elements with defined in the module.
     if let Some(function_section) = module.function_section() {
-        for func_entry in function_section.entries() {
+        for (index, func_entry) in function_section.entries().iter().enumerate() {
+            validate_type_index(func_entry.type_ref(), types_len, || {
+                format!("function #{}", index)
+            })?;
             context_builder.push_func_type_index(func_entry.type_ref())
         }
     }
@@ -192,10 +309,13 @@ pub fn validate_module<V: Validator>(module: &Module) -> Result<V::Output, Error
         .unwrap_or(0);
     let code_section_len = module.code_section().map(|s| s.bodies().len()).unwrap_or(0);
     if function_section_len != code_section_len {
-        return Err(Error(format!(
-            "length of function section is {}, while len of code section is {}",
-            function_section_len, code_section_len
-        )));
+        return Err(Error::new(
+            ErrorKind::MalformedSection,
+            format!(
+                "length of function section is {}, while len of code section is {}",
+                function_section_len, code_section_len
+            ),
+        ));
     }
 
     // validate every function body in user modules
@@ -209,28 +329,43 @@ pub fn validate_module<V: Validator>(module: &Module) -> Result<V::Output, Error
             .expect("function_section_len != 0; function_section_len == code_section_len; qed");
         // check every function body
         for (index, function) in function_section.entries().iter().enumerate() {
-            let function_body = code_section
-                .bodies()
-                .get(index as usize)
-                .ok_or_else(|| Error(format!("Missing body for function {}", index)))?;
+            let function_body = code_section.bodies().get(index as usize).ok_or_else(|| {
+                Error::new(
+                    ErrorKind::MalformedSection,
+                    format!("Missing body for function {}", index),
+                )
+            })?;
 
             let output = func::drive::<V::FuncValidator>(&context, function, function_body)
-                .map_err(|Error(ref msg)| {
-                    Error(format!(
-                        "Function #{} reading/validation error: {}",
-                        index, msg
-                    ))
-                })?;
+                .map_err(|err| err.in_function(index as u32))?;
             validation.on_function_validated(index as u32, output);
         }
     }
 
     // validate start section
     if let Some(start_fn_idx) = module.start_section() {
+        let func_count = context.func_type_indexes().len();
+        if start_fn_idx as usize >= func_count {
+            return Err(Error::new(
+                ErrorKind::UndeclaredIndex,
+                format!(
+                    "Start function at index {} doesn't exists, the module has {} functions",
+                    start_fn_idx, func_count
+                ),
+            ));
+        }
         let (params, return_ty) = context.require_function(start_fn_idx)?;
         if return_ty != BlockType::NoResult || !params.is_empty() {
-            return Err(Error(
-                "start function expected to have type [] -> []".into(),
+            let results: &[ValueType] = match return_ty {
+                BlockType::Value(ref ty) => core::slice::from_ref(ty),
+                _ => &[],
+            };
+            return Err(Error::new(
+                ErrorKind::TypeMismatch,
+                format!(
+                    "Start function #{} has type {:?} -> {:?}, but start functions must have type [] -> []",
+                    start_fn_idx, params, results
+                ),
             ));
         }
     }
@@ -247,7 +382,10 @@ pub fn validate_module<V: Validator>(module: &Module) -> Result<V::Output, Error
 
         for (fst, snd) in export_names.iter().zip(export_names.iter().skip(1)) {
             if fst == snd {
-                return Err(Error(format!("duplicate export {}", fst)));
+                return Err(Error::new(
+                    ErrorKind::Other,
+                    format!("duplicate export {}", fst),
+                ));
             }
         }
 
@@ -287,52 +425,100 @@ pub fn validate_module<V: Validator>(module: &Module) -> Result<V::Output, Error
         }
     }
 
-    // there must be no greater than 1 table in tables index space
+    // there must be no greater than 1 table in tables index space, unless multiple
+    // tables are enabled
+    #[cfg(not(feature = "multi_table"))]
     if context.tables().len() > 1 {
-        return Err(Error(format!(
-            "too many tables in index space: {}",
-            context.tables().len()
-        )));
+        return Err(Error::new(
+            ErrorKind::UnsupportedFeature,
+            format!("too many tables in index space: {}", context.tables().len()),
+        ));
     }
 
     // there must be no greater than 1 linear memory in memory index space
     if context.memories().len() > 1 {
-        return Err(Error(format!(
-            "too many memory regions in index space: {}",
-            context.memories().len()
-        )));
+        return Err(Error::new(
+            ErrorKind::UnsupportedFeature,
+            format!(
+                "too many memory regions in index space: {}",
+                context.memories().len()
+            ),
+        ));
+    }
+
+    // the data count section, if any, must agree with the data section
+    let data_count = module.sections().iter().find_map(|section| match *section {
+        Section::DataCount(count) => Some(count),
+        _ => None,
+    });
+    if let Some(data_count) = data_count {
+        let data_segments = module.data_section().map_or(0, |ds| ds.entries().len());
+        if data_count as usize != data_segments {
+            return Err(Error::new(
+                ErrorKind::MalformedSection,
+                format!(
+                    "data count section declares {} data segments, but there are {}",
+                    data_count, data_segments
+                ),
+            ));
+        }
     }
 
     // use data section to initialize linear memory regions
     if let Some(data_section) = module.data_section() {
         for data_segment in data_section.entries() {
             context.require_memory(data_segment.index())?;
-            let offset = data_segment
-                .offset()
-                .as_ref()
-                .ok_or_else(|| Error("passive memory segments are not supported".into()))?;
+            let offset = data_segment.offset().as_ref().ok_or_else(|| {
+                Error::new(
+                    ErrorKind::UnsupportedFeature,
+                    "passive memory segments are not supported",
+                )
+            })?;
             let init_ty = expr_const_type(offset, context.globals())?;
             if init_ty != ValueType::I32 {
-                return Err(Error("segment offset should return I32".into()));
+                return Err(Error::new(
+                    ErrorKind::TypeMismatch,
+                    "segment offset should return I32",
+                ));
             }
         }
     }
 
     // use element section to fill tables
     if let Some(element_section) = module.elements_section() {
-        for element_segment in element_section.entries() {
-            context.require_table(element_segment.index())?;
-            let offset = element_segment
-                .offset()
-                .as_ref()
-                .ok_or_else(|| Error("passive element segments are not supported".into()))?;
+        for (segment_index, element_segment) in element_section.entries().iter().enumerate() {
+            let table = context.require_table(element_segment.index())?;
+            // Entries of element segments are functions.
+            if table.elem_type() != TableElementType::AnyFunc {
+                return Err(Error::new(ErrorKind::TypeMismatch, format!(
+                    "element segment {} initializes table {} of element type {:?}, expected AnyFunc",
+                    segment_index,
+                    element_segment.index(),
+                    table.elem_type()
+                )));
+            }
+            let offset = element_segment.offset().as_ref().ok_or_else(|| {
+                Error::new(
+                    ErrorKind::UnsupportedFeature,
+                    "passive element segments are not supported",
+                )
+            })?;
             let init_ty = expr_const_type(offset, context.globals())?;
             if init_ty != ValueType::I32 {
-                return Err(Error("segment offset should return I32".into()));
+                return Err(Error::new(
+                    ErrorKind::TypeMismatch,
+                    "segment offset should return I32",
+                ));
             }
 
-            for function_index in element_segment.members() {
-                context.require_function(*function_index)?;
+            let function_count = context.func_type_indexes().len();
+            for (entry_index, &function_index) in element_segment.members().iter().enumerate() {
+                if function_index as usize >= function_count {
+                    return Err(Error::new(ErrorKind::UndeclaredIndex, format!(
+                        "element segment {} entry {} refers to function {}, but there are only {} functions",
+                        segment_index, entry_index, function_index, function_count
+                    )));
+                }
             }
         }
     }
@@ -340,27 +526,59 @@ pub fn validate_module<V: Validator>(module: &Module) -> Result<V::Output, Error
     Ok(validation.finish())
 }
 
+/// Verify that the type `idx`, referred to by `referrer`, is in a type section of
+/// `types_len` types.
+///
+/// The type section only holds function types, which can't refer to other types, so
+/// the indices of functions are the only type references outside of function bodies.
+fn validate_type_index<F: FnOnce() -> String>(
+    idx: u32,
+    types_len: usize,
+    referrer: F,
+) -> Result<(), Error> {
+    if idx as usize >= types_len {
+        return Err(Error::new(
+            ErrorKind::UndeclaredIndex,
+            format!(
+                "Type at index {} of {} doesn't exists, the module declares {} types",
+                idx,
+                referrer(),
+                types_len
+            ),
+        ));
+    }
+    Ok(())
+}
+
 fn validate_limits(limits: &ResizableLimits) -> Result<(), Error> {
     if let Some(maximum) = limits.maximum() {
         if limits.initial() > maximum {
-            return Err(Error(format!(
-                "maximum limit {} is less than minimum {}",
-                maximum,
-                limits.initial()
-            )));
+            return Err(Error::new(
+                ErrorKind::Other,
+                format!(
+                    "maximum limit {} is less than minimum {}",
+                    maximum,
+                    limits.initial()
+                ),
+            ));
         }
     }
     Ok(())
 }
 
 fn validate_memory_type(memory_type: &MemoryType) -> Result<(), Error> {
-    let initial = memory_type.limits().initial();
-    let maximum: Option<u32> = memory_type.limits().maximum();
-    validate_memory(initial, maximum).map_err(Error)
+    let initial = Pages(memory_type.limits().initial() as usize);
+    let maximum = memory_type
+        .limits()
+        .maximum()
+        .map(|maximum| Pages(maximum as usize));
+    validate_memory(initial, maximum).map_err(|message| Error::new(ErrorKind::Other, message))
 }
 
-pub fn validate_memory(initial: u32, maximum: Option<u32>) -> Result<(), String> {
-    if initial > LINEAR_MEMORY_MAX_PAGES {
+/// Validate the limits of a linear memory.
+pub fn validate_memory(initial: Pages, maximum: Option<Pages>) -> Result<(), String> {
+    let max_pages = Pages(LINEAR_MEMORY_MAX_PAGES as usize);
+    if initial > max_pages {
         return Err(format!(
             "initial memory size must be at most {} pages",
             LINEAR_MEMORY_MAX_PAGES
@@ -370,11 +588,11 @@ pub fn validate_memory(initial: u32, maximum: Option<u32>) -> Result<(), String>
         if initial > maximum {
             return Err(format!(
                 "maximum limit {} is less than minimum {}",
-                maximum, initial,
+                maximum.0, initial.0,
             ));
         }
 
-        if maximum > LINEAR_MEMORY_MAX_PAGES {
+        if maximum > max_pages {
             return Err(format!(
                 "maximum memory size must be at most {} pages",
                 LINEAR_MEMORY_MAX_PAGES
@@ -392,46 +610,111 @@ fn validate_global_entry(global_entry: &GlobalEntry, globals: &[GlobalType]) ->
     let init = global_entry.init_expr();
     let init_expr_ty = expr_const_type(init, globals)?;
     if init_expr_ty != global_entry.global_type().content_type() {
-        return Err(Error(format!(
-            "Trying to initialize variable of type {:?} with value of type {:?}",
-            global_entry.global_type().content_type(),
-            init_expr_ty
-        )));
+        return Err(Error::new(
+            ErrorKind::TypeMismatch,
+            format!(
+                "Trying to initialize variable of type {:?} with value of type {:?}",
+                global_entry.global_type().content_type(),
+                init_expr_ty
+            ),
+        ));
     }
     Ok(())
 }
 
 /// Returns type of this constant expression.
+///
+/// Besides a single constant or `get_global`, integer `add`, `sub` and `mul` instructions
+/// are allowed, as specified by the extended-const proposal.
 fn expr_const_type(init_expr: &InitExpr, globals: &[GlobalType]) -> Result<ValueType, Error> {
     let code = init_expr.code();
-    if code.len() != 2 {
-        return Err(Error(
-            "Init expression should always be with length 2".into(),
+    let (last, body) = code.split_last().ok_or_else(|| {
+        Error::new(
+            ErrorKind::MalformedSection,
+            "Init expression shouldn't be empty",
+        )
+    })?;
+    if *last != Instruction::End {
+        return Err(Error::new(
+            ErrorKind::MalformedSection,
+            "Expression doesn't ends with `end` opcode",
         ));
     }
-    let expr_ty: ValueType = match code[0] {
-        Instruction::I32Const(_) => ValueType::I32,
-        Instruction::I64Const(_) => ValueType::I64,
-        Instruction::F32Const(_) => ValueType::F32,
-        Instruction::F64Const(_) => ValueType::F64,
-        Instruction::GetGlobal(idx) => match globals.get(idx as usize) {
-            Some(target_global) => {
-                if target_global.is_mutable() {
-                    return Err(Error(format!("Global {} is mutable", idx)));
+
+    fn pop_operands(stack: &mut Vec<ValueType>, operand_ty: ValueType) -> Result<(), Error> {
+        for _ in 0..2 {
+            match stack.pop() {
+                Some(ty) if ty == operand_ty => {}
+                Some(ty) => {
+                    return Err(Error::new(
+                        ErrorKind::TypeMismatch,
+                        format!(
+                            "Expected operand of type {:?} in init expr, got {:?}",
+                            operand_ty, ty
+                        ),
+                    ));
+                }
+                None => {
+                    return Err(Error::new(
+                        ErrorKind::StackHeightMismatch,
+                        "Stack underflow in init expr",
+                    ))
                 }
-                target_global.content_type()
             }
-            None => {
-                return Err(Error(format!(
-                    "Global {} doesn't exists or not yet defined",
-                    idx
-                )));
+        }
+        Ok(())
+    }
+
+    let mut stack: Vec<ValueType> = Vec::new();
+    for instruction in body {
+        let ty = match *instruction {
+            Instruction::I32Const(_) => ValueType::I32,
+            Instruction::I64Const(_) => ValueType::I64,
+            Instruction::F32Const(_) => ValueType::F32,
+            Instruction::F64Const(_) => ValueType::F64,
+            Instruction::GetGlobal(idx) => match globals.get(idx as usize) {
+                Some(target_global) => {
+                    if target_global.is_mutable() {
+                        return Err(Error::new(
+                            ErrorKind::Other,
+                            format!("Global {} is mutable", idx),
+                        ));
+                    }
+                    target_global.content_type()
+                }
+                None => {
+                    return Err(Error::new(
+                        ErrorKind::UndeclaredIndex,
+                        format!("Global {} doesn't exists or not yet defined", idx),
+                    ));
+                }
+            },
+            Instruction::I32Add | Instruction::I32Sub | Instruction::I32Mul => {
+                pop_operands(&mut stack, ValueType::I32)?;
+                ValueType::I32
+            }
+            Instruction::I64Add | Instruction::I64Sub | Instruction::I64Mul => {
+                pop_operands(&mut stack, ValueType::I64)?;
+                ValueType::I64
             }
-        },
-        _ => return Err(Error("Non constant opcode in init expr".into())),
-    };
-    if code[1] != Instruction::End {
-        return Err(Error("Expression doesn't ends with `end` opcode".into()));
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::Other,
+                    "Non constant opcode in init expr",
+                ))
+            }
+        };
+        stack.push(ty);
+    }
+
+    match stack[..] {
+        [expr_ty] => Ok(expr_ty),
+        _ => Err(Error::new(
+            ErrorKind::TypeMismatch,
+            format!(
+                "Init expression should produce exactly one value, got {}",
+                stack.len()
+            ),
+        )),
     }
-    Ok(expr_ty)
 }
@@ -5,18 +5,36 @@ use core::fmt;
 use std::error;
 
 #[derive(Debug)]
-pub struct Error(String);
+pub struct Error {
+    message: String,
+    limit_exceeded: bool,
+}
+
+impl Error {
+    fn new<M: Into<String>>(message: M) -> Error {
+        Error {
+            message: message.into(),
+            limit_exceeded: false,
+        }
+    }
+
+    /// Returns whether a push failed because the stack reached its limit, rather than
+    /// the stack having too few values.
+    pub fn is_limit_exceeded(&self) -> bool {
+        self.limit_exceeded
+    }
+}
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.0)
+        write!(f, "{}", self.message)
     }
 }
 
 #[cfg(feature = "std")]
 impl error::Error for Error {
     fn description(&self) -> &str {
-        &self.0
+        &self.message
     }
 }
 
@@ -54,18 +72,18 @@ where
     pub fn top(&self) -> Result<&T, Error> {
         self.values
             .last()
-            .ok_or_else(|| Error("non-empty stack expected".into()))
+            .ok_or_else(|| Error::new("non-empty stack expected"))
     }
 
     pub fn top_mut(&mut self) -> Result<&mut T, Error> {
         self.values
             .last_mut()
-            .ok_or_else(|| Error("non-empty stack expected".into()))
+            .ok_or_else(|| Error::new("non-empty stack expected"))
     }
 
     pub fn get(&self, index: usize) -> Result<&T, Error> {
         if index >= self.values.len() {
-            return Err(Error(format!(
+            return Err(Error::new(format!(
                 "trying to get value at position {} on stack of size {}",
                 index,
                 self.values.len()
@@ -80,7 +98,10 @@ where
 
     pub fn push(&mut self, value: T) -> Result<(), Error> {
         if self.values.len() >= self.limit {
-            return Err(Error(format!("exceeded stack limit {}", self.limit)));
+            return Err(Error {
+                message: format!("exceeded stack limit {}", self.limit),
+                limit_exceeded: true,
+            });
         }
 
         self.values.push(value);
@@ -90,7 +111,7 @@ where
     pub fn pop(&mut self) -> Result<T, Error> {
         self.values
             .pop()
-            .ok_or_else(|| Error("non-empty stack expected".into()))
+            .ok_or_else(|| Error::new("non-empty stack expected"))
     }
 
     pub fn resize(&mut self, new_size: usize, dummy: T) {
@@ -1,9 +1,10 @@
-use crate::{Error, PlainValidator};
+use crate::{Error, ErrorKind, PlainValidator};
 use parity_wasm::{
     builder::module,
     elements::{
-        BlockType, External, GlobalEntry, GlobalType, ImportEntry, InitExpr, Instruction,
-        Instructions, MemoryType, Module, TableType, ValueType,
+        BlockType, CodeSection, External, Func, FuncBody, FunctionSection, FunctionType,
+        GlobalEntry, GlobalType, ImportEntry, ImportSection, InitExpr, Instruction, Instructions,
+        MemoryType, Module, Section, TableSection, TableType, Type, TypeSection, ValueType,
     },
 };
 
@@ -166,6 +167,93 @@ fn global_init_misc() {
     assert!(validate_module(&m).is_err());
 }
 
+#[test]
+fn global_init_extended_const() {
+    let global_with_init = |ty, code| {
+        module()
+            .with_import(ImportEntry::new(
+                "env".into(),
+                "ext_global".into(),
+                External::Global(GlobalType::new(ValueType::I32, false)),
+            ))
+            .with_global(GlobalEntry::new(
+                GlobalType::new(ty, false),
+                InitExpr::new(code),
+            ))
+            .build()
+    };
+
+    // integer arithmetic is allowed.
+    let m = global_with_init(
+        ValueType::I32,
+        vec![
+            Instruction::I32Const(8),
+            Instruction::GetGlobal(0),
+            Instruction::I32Add,
+            Instruction::I32Const(2),
+            Instruction::I32Mul,
+            Instruction::End,
+        ],
+    );
+    assert!(validate_module(&m).is_ok());
+    let m = global_with_init(
+        ValueType::I64,
+        vec![
+            Instruction::I64Const(8),
+            Instruction::I64Const(1),
+            Instruction::I64Sub,
+            Instruction::End,
+        ],
+    );
+    assert!(validate_module(&m).is_ok());
+
+    // operand types must match.
+    let m = global_with_init(
+        ValueType::I64,
+        vec![
+            Instruction::I64Const(8),
+            Instruction::GetGlobal(0),
+            Instruction::I64Add,
+            Instruction::End,
+        ],
+    );
+    assert!(validate_module(&m).is_err());
+
+    // not enough operands.
+    let m = global_with_init(
+        ValueType::I32,
+        vec![
+            Instruction::I32Const(8),
+            Instruction::I32Add,
+            Instruction::End,
+        ],
+    );
+    assert!(validate_module(&m).is_err());
+
+    // exactly one value must be left.
+    let m = global_with_init(
+        ValueType::I32,
+        vec![
+            Instruction::I32Const(8),
+            Instruction::I32Const(8),
+            Instruction::End,
+        ],
+    );
+    assert!(validate_module(&m).is_err());
+
+    // other arithmetic isn't allowed.
+    let m = global_with_init(
+        ValueType::I32,
+        vec![
+            Instruction::I32Const(8),
+            Instruction::I32Const(8),
+            Instruction::I32DivU,
+            Instruction::End,
+        ],
+    );
+    assert!(validate_module(&m).is_err());
+}
+
 #[test]
 fn module_limits_validity() {
     // module cannot contain more than 1 memory atm.
@@ -275,3 +363,350 @@ fn if_else_with_return_type_validation() {
         .build();
     validate_module(&m).unwrap();
 }
+
+#[test]
+fn duplicate_exports() {
+    // exports with distinct names are legal.
+    let m = module()
+        .function()
+        .signature()
+        .build()
+        .body()
+        .build()
+        .build()
+        .export()
+        .field("a")
+        .internal()
+        .func(0)
+        .build()
+        .export()
+        .field("b")
+        .internal()
+        .func(0)
+        .build()
+        .build();
+    assert!(validate_module(&m).is_ok());
+
+    // exports with the same name are illegal, even if they are of different kinds.
+    let m = module()
+        .function()
+        .signature()
+        .build()
+        .body()
+        .build()
+        .build()
+        .memory()
+        .build()
+        .export()
+        .field("a")
+        .internal()
+        .func(0)
+        .build()
+        .export()
+        .field("a")
+        .internal()
+        .memory(0)
+        .build()
+        .build();
+    let message = validate_module(&m).unwrap_err().to_string();
+    assert_eq!(message, "duplicate export a");
+}
+
+#[test]
+fn element_segment_function_indices() {
+    // element segments referring to existing functions are legal.
+    let m = module()
+        .function()
+        .signature()
+        .build()
+        .body()
+        .build()
+        .build()
+        .table()
+        .with_min(2)
+        .with_element(0, vec![0, 0])
+        .build()
+        .build();
+    assert!(validate_module(&m).is_ok());
+
+    // a function index past the last function is illegal.
+    let m = module()
+        .function()
+        .signature()
+        .build()
+        .body()
+        .build()
+        .build()
+        .table()
+        .with_min(2)
+        .with_element(0, vec![0, 1])
+        .build()
+        .build();
+    let message = validate_module(&m).unwrap_err().to_string();
+    assert_eq!(
+        message,
+        "element segment 0 entry 1 refers to function 1, but there are only 1 functions"
+    );
+}
+
+#[test]
+fn data_count() {
+    let with_data_count = |count: Option<u32>, segments: usize| {
+        let mut builder = module().memory().with_min(1).build();
+        for _ in 0..segments {
+            builder = builder
+                .data()
+                .offset(Instruction::I32Const(0))
+                .value(vec![1])
+                .build();
+        }
+        let mut m = builder.build();
+        if let Some(count) = count {
+            m.sections_mut().push(Section::DataCount(count));
+        }
+        validate_module(&m)
+    };
+
+    // without a data count section, any number of segments is legal.
+    assert!(with_data_count(None, 2).is_ok());
+    assert!(with_data_count(Some(2), 2).is_ok());
+    assert!(with_data_count(Some(0), 0).is_ok());
+
+    let message = with_data_count(Some(3), 2).unwrap_err().to_string();
+    assert_eq!(
+        message,
+        "data count section declares 3 data segments, but there are 2"
+    );
+    let message = with_data_count(Some(1), 0).unwrap_err().to_string();
+    assert_eq!(
+        message,
+        "data count section declares 1 data segments, but there are 0"
+    );
+}
+
+#[test]
+fn error_kinds_and_locations() {
+    let with_body = |instructions: Vec<Instruction>| {
+        let m = module()
+            .function()
+            .signature()
+            .build()
+            .body()
+            .build()
+            .build()
+            .function()
+            .signature()
+            .result()
+            .i32()
+            .build()
+            .body()
+            .with_instructions(Instructions::new(instructions))
+            .build()
+            .build()
+            .build();
+        validate_module(&m).unwrap_err()
+    };
+
+    let error = with_body(vec![Instruction::I64Const(1), Instruction::End]);
+    assert_eq!(error.kind(), ErrorKind::TypeMismatch);
+    assert_eq!(error.func_body(), Some(1));
+    assert_eq!(error.instruction(), Some(1));
+    assert_eq!(
+        error.to_string(),
+        "Function #1 reading/validation error: At instruction End(@1): \
+         Expected value of type Specific(I32) on top of stack. Got Specific(I64)"
+    );
+
+    let error = with_body(vec![Instruction::I32Add, Instruction::End]);
+    assert_eq!(error.kind(), ErrorKind::StackHeightMismatch);
+    assert_eq!(error.instruction(), Some(0));
+
+    let error = with_body(vec![Instruction::Call(2), Instruction::End]);
+    assert_eq!(error.kind(), ErrorKind::UndeclaredIndex);
+    assert_eq!(error.instruction(), Some(0));
+
+    let error = with_body(vec![Instruction::Br(1), Instruction::End]);
+    assert_eq!(error.kind(), ErrorKind::UndeclaredIndex);
+
+    // running out of stack is a limit of the validator, not a mismatch of the stack height.
+    let mut instructions = vec![Instruction::I32Const(0); 16385];
+    instructions.push(Instruction::End);
+    let error = with_body(instructions);
+    assert_eq!(error.kind(), ErrorKind::Other);
+    assert_eq!(error.instruction(), Some(16384));
+
+    // errors outside of function bodies have no location.
+    let m = module()
+        .memory()
+        .with_min(2)
+        .with_max(Some(1))
+        .build()
+        .build();
+    let error = validate_module(&m).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::Other);
+    assert_eq!(error.func_body(), None);
+    assert_eq!(error.instruction(), None);
+
+    let mut m = module().build();
+    m.sections_mut().push(Section::DataCount(1));
+    assert_eq!(
+        validate_module(&m).unwrap_err().kind(),
+        ErrorKind::MalformedSection
+    );
+}
+
+#[test]
+fn out_of_range_type_indices() {
+    let types = || {
+        Section::Type(TypeSection::with_types(vec![
+            Type::Function(FunctionType::new(vec![], vec![])),
+            Type::Function(FunctionType::new(vec![ValueType::I32], vec![])),
+        ]))
+    };
+    let function = |type_ref: u32, instructions: Vec<Instruction>| {
+        vec![
+            Section::Function(FunctionSection::with_entries(vec![Func::new(type_ref)])),
+            Section::Code(CodeSection::with_bodies(vec![FuncBody::new(
+                vec![],
+                Instructions::new(instructions),
+            )])),
+        ]
+    };
+    let import = |type_ref: u32| {
+        Section::Import(ImportSection::with_entries(vec![ImportEntry::new(
+            "env".into(),
+            "f".into(),
+            External::Function(type_ref),
+        )]))
+    };
+    let call_indirect = |type_ref: u32| {
+        let mut sections = vec![
+            types(),
+            Section::Table(TableSection::with_entries(vec![TableType::new(1, None)])),
+        ];
+        sections.extend(function(
+            0,
+            vec![
+                Instruction::I32Const(0),
+                Instruction::CallIndirect(type_ref, 0),
+                Instruction::End,
+            ],
+        ));
+        Module::new(sections)
+    };
+
+    for &type_ref in &[0, 1, 2, 3, 0x7f, 0x80, 0xffff, u32::MAX - 1, u32::MAX] {
+        let is_valid = type_ref < 2;
+
+        let mut sections = vec![types()];
+        sections.extend(function(type_ref, vec![Instruction::End]));
+        let result = validate_module(&Module::new(sections));
+        assert_eq!(result.is_ok(), is_valid, "function of type {}", type_ref);
+        if let Err(error) = result {
+            assert_eq!(error.kind(), ErrorKind::UndeclaredIndex);
+            assert_eq!(
+                error.to_string(),
+                format!(
+                    "Type at index {} of function #0 doesn't exists, the module declares 2 types",
+                    type_ref
+                )
+            );
+        }
+
+        let result = validate_module(&Module::new(vec![types(), import(type_ref)]));
+        assert_eq!(result.is_ok(), is_valid, "import of type {}", type_ref);
+        if let Err(error) = result {
+            assert_eq!(error.kind(), ErrorKind::UndeclaredIndex);
+            assert_eq!(
+                error.to_string(),
+                format!(
+                    "Type at index {} of imported function env.f doesn't exists, \
+                     the module declares 2 types",
+                    type_ref
+                )
+            );
+        }
+
+        // without a type section, no index is in range.
+        let error = validate_module(&Module::new(vec![import(type_ref)])).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UndeclaredIndex);
+
+        // type 1 takes a parameter, which the `call_indirect` doesn't provide.
+        let result = validate_module(&call_indirect(type_ref));
+        assert_eq!(
+            result.is_ok(),
+            type_ref == 0,
+            "call_indirect of type {}",
+            type_ref
+        );
+        if !is_valid {
+            let error = result.unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::UndeclaredIndex);
+            assert_eq!((error.func_body(), error.instruction()), (Some(0), Some(1)));
+        }
+    }
+}
+
+#[test]
+fn start_function() {
+    let with_start = |start: u32| {
+        Module::new(vec![
+            Section::Type(TypeSection::with_types(vec![
+                Type::Function(FunctionType::new(vec![], vec![])),
+                Type::Function(FunctionType::new(vec![ValueType::I32], vec![])),
+                Type::Function(FunctionType::new(vec![], vec![ValueType::I64])),
+            ])),
+            Section::Import(ImportSection::with_entries(vec![ImportEntry::new(
+                "env".into(),
+                "f".into(),
+                External::Function(0),
+            )])),
+            Section::Function(FunctionSection::with_entries(vec![
+                Func::new(1),
+                Func::new(2),
+                Func::new(0),
+            ])),
+            Section::Start(start),
+            Section::Code(CodeSection::with_bodies(vec![
+                FuncBody::new(vec![], Instructions::new(vec![Instruction::End])),
+                FuncBody::new(
+                    vec![],
+                    Instructions::new(vec![Instruction::I64Const(0), Instruction::End]),
+                ),
+                FuncBody::new(vec![], Instructions::new(vec![Instruction::End])),
+            ])),
+        ])
+    };
+
+    // Imported functions can be start functions as well.
+    assert!(validate_module(&with_start(0)).is_ok());
+    assert!(validate_module(&with_start(3)).is_ok());
+
+    let cases = [
+        (
+            1,
+            ErrorKind::TypeMismatch,
+            "Start function #1 has type [I32] -> [], but start functions must have type [] -> []",
+        ),
+        (
+            2,
+            ErrorKind::TypeMismatch,
+            "Start function #2 has type [] -> [I64], but start functions must have type [] -> []",
+        ),
+        (
+            4,
+            ErrorKind::UndeclaredIndex,
+            "Start function at index 4 doesn't exists, the module has 4 functions",
+        ),
+        (
+            u32::MAX,
+            ErrorKind::UndeclaredIndex,
+            "Start function at index 4294967295 doesn't exists, the module has 4 functions",
+        ),
+    ];
+    for &(start, kind, message) in &cases {
+        let error = validate_module(&with_start(start)).unwrap_err();
+        assert_eq!(error.kind(), kind, "start function {}", start);
+        assert_eq!(error.to_string(), message);
+    }
+}
@@ -1,5 +1,4 @@
-use crate::Error;
-use alloc::string::String;
+use crate::{Error, ErrorKind};
 use parity_wasm::elements::{Local, ValueType};
 
 #[cfg(test)]
@@ -24,7 +23,7 @@ impl<'a> Locals<'a> {
         for locals_group in local_groups {
             acc = acc
                 .checked_add(locals_group.count())
-                .ok_or_else(|| Error(String::from("Locals range not in 32-bit range")))?;
+                .ok_or_else(|| Error::new(ErrorKind::Other, "Locals range not in 32-bit range"))?;
         }
 
         Ok(Locals {
@@ -57,7 +56,7 @@ impl<'a> Locals<'a> {
         for locals_group in self.local_groups {
             let end_idx = start_idx
                 .checked_add(locals_group.count())
-                .ok_or_else(|| Error(String::from("Locals range not in 32-bit range")))?;
+                .ok_or_else(|| Error::new(ErrorKind::Other, "Locals range not in 32-bit range"))?;
 
             if idx >= start_idx && idx < end_idx {
                 return Ok(locals_group.value_type());
@@ -71,10 +70,13 @@ impl<'a> Locals<'a> {
         // (since it's either set to the `end_idx` or equal to `params.len()`)
         let total_count = start_idx;
 
-        Err(Error(format!(
-            "Trying to access local with index {} when there are only {} locals",
-            idx, total_count
-        )))
+        Err(Error::new(
+            ErrorKind::UndeclaredIndex,
+            format!(
+                "Trying to access local with index {} when there are only {} locals",
+                idx, total_count
+            ),
+        ))
     }
 }
 